@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+
+use containerd_shim::protos::ttrpc;
+use libcgroups::common::AnyManagerError;
+use libcontainer::error::LibcontainerError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TaskError {
+    #[error("task {0} not found")]
+    NotFound(String),
+    #[error("exec process {exec_id} of task {id} not found")]
+    ExecNotFound { id: String, exec_id: String },
+    #[error("task {0} exists already")]
+    AlreadyExists(String),
+    #[error("{0}")]
+    FailedPrecondition(String),
+    #[error("{0}")]
+    InvalidArgument(String),
+    #[error("{0} is not supported by the youki shim")]
+    Unsupported(&'static str),
+    #[error("failed to open {path:?}")]
+    Stdio {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("process {0} has exited already")]
+    Exited(u32),
+    #[error("failed to write {0:?}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("failed to {0}")]
+    Shim(&'static str, #[source] containerd_shim::Error),
+    #[error("invalid {0}")]
+    Json(&'static str, #[source] serde_json::Error),
+    #[error(transparent)]
+    Libcontainer(#[from] LibcontainerError),
+    #[error(transparent)]
+    Cgroup(#[from] AnyManagerError),
+}
+
+impl From<TaskError> for ttrpc::Error {
+    fn from(err: TaskError) -> Self {
+        let code = match err {
+            // containerd ignores kills of processes which have exited
+            TaskError::NotFound(_) | TaskError::ExecNotFound { .. } | TaskError::Exited(_) => {
+                ttrpc::Code::NOT_FOUND
+            }
+            TaskError::AlreadyExists(_) => ttrpc::Code::ALREADY_EXISTS,
+            TaskError::FailedPrecondition(_) => ttrpc::Code::FAILED_PRECONDITION,
+            TaskError::InvalidArgument(_) | TaskError::Json(..) => ttrpc::Code::INVALID_ARGUMENT,
+            TaskError::Unsupported(_) => ttrpc::Code::UNIMPLEMENTED,
+            _ => ttrpc::Code::UNKNOWN,
+        };
+        // the sources are part of the message, since containerd only gets
+        // to see the status
+        let mut message = err.to_string();
+        let mut source = std::error::Error::source(&err);
+        while let Some(err) = source {
+            message.push_str(&format!(": {err}"));
+            source = err.source();
+        }
+        ttrpc::Error::RpcStatus(ttrpc::get_status(code, message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        for (err, code) in [
+            (TaskError::NotFound("c1".to_owned()), ttrpc::Code::NOT_FOUND),
+            (
+                TaskError::ExecNotFound {
+                    id: "c1".to_owned(),
+                    exec_id: "e1".to_owned(),
+                },
+                ttrpc::Code::NOT_FOUND,
+            ),
+            (
+                TaskError::AlreadyExists("c1".to_owned()),
+                ttrpc::Code::ALREADY_EXISTS,
+            ),
+            (
+                TaskError::Unsupported("terminal"),
+                ttrpc::Code::UNIMPLEMENTED,
+            ),
+            (
+                TaskError::Libcontainer(LibcontainerError::IncorrectStatus),
+                ttrpc::Code::UNKNOWN,
+            ),
+        ] {
+            match ttrpc::Error::from(err) {
+                ttrpc::Error::RpcStatus(status) => assert_eq!(status.code(), code),
+                err => panic!("unexpected error {err:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_message_with_sources() {
+        let err = TaskError::Stdio {
+            source: std::io::Error::from_raw_os_error(libc::ENOENT),
+            path: PathBuf::from("/run/stdout"),
+        };
+        match ttrpc::Error::from(err) {
+            ttrpc::Error::RpcStatus(status) => assert_eq!(
+                status.message(),
+                "failed to open \"/run/stdout\": No such file or directory (os error 2)"
+            ),
+            err => panic!("unexpected error {err:?}"),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Publishing of task events to containerd
+
+use containerd_shim::event::Event;
+use containerd_shim::protos::protobuf::MessageDyn;
+use containerd_shim::publisher::RemotePublisher;
+use containerd_shim::Context;
+
+/// Receiver of the events of the tasks of the shim
+pub trait Publisher: Send + Sync {
+    fn publish(&self, topic: String, event: Box<dyn MessageDyn>);
+}
+
+pub fn publish<E: Event + 'static>(publisher: &dyn Publisher, event: E) {
+    publisher.publish(event.topic(), Box::new(event));
+}
+
+/// Publishes the events to containerd through its ttrpc address
+pub struct Containerd {
+    publisher: RemotePublisher,
+    namespace: String,
+}
+
+impl Containerd {
+    pub fn new(publisher: RemotePublisher, namespace: &str) -> Self {
+        Self {
+            publisher,
+            namespace: namespace.to_owned(),
+        }
+    }
+}
+
+impl Publisher for Containerd {
+    fn publish(&self, topic: String, event: Box<dyn MessageDyn>) {
+        // containerd learns about the state of the task through the task API
+        // as well, so a lost event is not fatal
+        if let Err(err) = self
+            .publisher
+            .publish(Context::default(), &topic, &self.namespace, event)
+        {
+            tracing::warn!(%topic, %err, "failed to publish event");
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Records the topics of the published events
+    #[derive(Default)]
+    pub struct Recorder(pub Mutex<Vec<String>>);
+
+    impl Recorder {
+        pub fn topics(&self) -> Vec<String> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    impl Publisher for Recorder {
+        fn publish(&self, topic: String, _event: Box<dyn MessageDyn>) {
+            self.0.lock().unwrap().push(topic);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::sync::Mutex;
+
+use tracing::Level;
+
+/// Fifo in the working directory of the shim, which containerd copies into
+/// its own log
+const LOG_FIFO: &str = "log";
+
+/// Logs to the log fifo of containerd. The logs of the containerd-shim crate
+/// are forwarded from the log crate.
+pub fn init(debug: bool) -> io::Result<()> {
+    let fifo = OpenOptions::new().write(true).open(LOG_FIFO)?;
+    let level = if debug { Level::DEBUG } else { Level::INFO };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_ansi(false)
+        .with_writer(Mutex::new(fifo))
+        .try_init()
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+}
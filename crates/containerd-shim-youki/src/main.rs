@@ -0,0 +1,17 @@
+//! containerd shim v2 running the containers of containerd with libcontainer,
+//! instead of executing the youki binary for every operation as
+//! `containerd-shim-runc-v2` does.
+
+mod error;
+mod events;
+mod logger;
+mod process;
+mod shim;
+mod task;
+
+/// Runtime name containerd is configured with to use this shim
+const RUNTIME_ID: &str = "io.containerd.youki.v2";
+
+fn main() {
+    containerd_shim::run::<shim::Shim>(RUNTIME_ID, Some(shim::config()))
+}
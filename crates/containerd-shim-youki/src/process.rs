@@ -0,0 +1,274 @@
+//! Processes of the tasks, which are the init process of a container and the
+//! processes executed in it
+
+use std::fs::{File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::SystemTime;
+
+use libcontainer::container::builder::Stdio;
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::Pid;
+
+use crate::error::TaskError;
+
+/// Exit status of a process reaped by the shim
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exit {
+    pub status: u32,
+    pub at: SystemTime,
+}
+
+/// Holds the exit of a process once it has been reaped, which waiters block
+/// on until then
+#[derive(Debug, Default)]
+pub struct ExitCell {
+    exit: Mutex<Option<Exit>>,
+    exited: Condvar,
+}
+
+impl ExitCell {
+    pub fn set(&self, exit: Exit) {
+        *self.exit.lock().unwrap() = Some(exit);
+        self.exited.notify_all();
+    }
+
+    pub fn get(&self) -> Option<Exit> {
+        *self.exit.lock().unwrap()
+    }
+
+    pub fn wait(&self) -> Exit {
+        let mut exit = self.exit.lock().unwrap();
+        loop {
+            match *exit {
+                Some(exit) => return exit,
+                None => exit = self.exited.wait(exit).unwrap(),
+            }
+        }
+    }
+}
+
+/// Paths of the fifos containerd connects the stdio of a process with. Paths
+/// are empty for the streams containerd doesn't attach.
+#[derive(Debug, Clone, Default)]
+pub struct StdioPaths {
+    pub stdin: String,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl StdioPaths {
+    /// Opens the fifos for the process. containerd has opened the other end
+    /// of the output fifos, but doesn't necessarily write to stdin yet, so
+    /// stdin is opened without waiting for a writer.
+    pub fn open(&self) -> Result<Stdio, TaskError> {
+        let stdin = open_fifo(&self.stdin, |path| {
+            let file = OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(path)?;
+            fcntl(file.as_raw_fd(), FcntlArg::F_SETFL(OFlag::empty()))?;
+            Ok(file)
+        })?;
+        let stdout = open_fifo(&self.stdout, |path| {
+            OpenOptions::new().write(true).open(path)
+        })?;
+        let stderr = open_fifo(&self.stderr, |path| {
+            OpenOptions::new().write(true).open(path)
+        })?;
+
+        Ok(Stdio {
+            stdin: stdin.map(Into::into),
+            stdout: stdout.map(Into::into),
+            stderr: stderr.map(Into::into),
+        })
+    }
+}
+
+fn open_fifo(
+    path: &str,
+    open: impl FnOnce(&Path) -> std::io::Result<File>,
+) -> Result<Option<File>, TaskError> {
+    if path.is_empty() {
+        return Ok(None);
+    }
+
+    open(Path::new(path))
+        .map(Some)
+        .map_err(|err| TaskError::Stdio {
+            source: err,
+            path: path.into(),
+        })
+}
+
+/// Process of a task. Exec processes have no pid until they are started.
+#[derive(Debug, Default)]
+pub struct Process {
+    pub pid: u32,
+    pub stdio: StdioPaths,
+    pub exit: Arc<ExitCell>,
+}
+
+impl Process {
+    pub fn new(stdio: StdioPaths) -> Self {
+        Self {
+            stdio,
+            ..Default::default()
+        }
+    }
+
+    /// Reaps the process in a separate thread, which records the exit and
+    /// then reports it to `on_exit`. The process has to be a child of the
+    /// shim, which is the subreaper of the containers.
+    pub fn watch_exit(&self, on_exit: impl FnOnce(Exit) + Send + 'static) {
+        let pid = Pid::from_raw(self.pid as i32);
+        let cell = self.exit.clone();
+        thread::spawn(move || {
+            let status = loop {
+                match waitpid(pid, None) {
+                    Ok(status) => {
+                        if let Some(status) = exit_status(status) {
+                            break status;
+                        }
+                    }
+                    Err(Errno::EINTR) => continue,
+                    Err(err) => {
+                        // the exit is reported anyway, so that waiters don't
+                        // block forever
+                        tracing::error!(?pid, %err, "failed to wait for the process");
+                        break 255;
+                    }
+                }
+            };
+            let exit = Exit {
+                status,
+                at: SystemTime::now(),
+            };
+            tracing::debug!(?pid, status, "process exited");
+            cell.set(exit);
+            on_exit(exit);
+        });
+    }
+}
+
+/// Returns the exit status of a terminated process the way a shell reports
+/// it, i.e. 128 plus the signal for processes killed by a signal
+fn exit_status(status: WaitStatus) -> Option<u32> {
+    match status {
+        WaitStatus::Exited(_, code) => Some(code as u32),
+        WaitStatus::Signaled(_, signal, _) => Some(128 + signal as u32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::process::Command;
+    use std::time::Duration;
+
+    use nix::sys::signal::Signal;
+    use nix::sys::stat::Mode;
+    use nix::unistd::mkfifo;
+
+    use super::*;
+
+    #[test]
+    fn test_exit_status() {
+        let pid = Pid::from_raw(100);
+        assert_eq!(exit_status(WaitStatus::Exited(pid, 0)), Some(0));
+        assert_eq!(exit_status(WaitStatus::Exited(pid, 3)), Some(3));
+        assert_eq!(
+            exit_status(WaitStatus::Signaled(pid, Signal::SIGKILL, false)),
+            Some(137)
+        );
+        assert_eq!(exit_status(WaitStatus::Stopped(pid, Signal::SIGSTOP)), None);
+        assert_eq!(exit_status(WaitStatus::Continued(pid)), None);
+    }
+
+    #[test]
+    fn test_exit_cell() {
+        let cell = Arc::new(ExitCell::default());
+        assert_eq!(cell.get(), None);
+
+        let waiter = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.wait())
+        };
+        thread::sleep(Duration::from_millis(10));
+        let exit = Exit {
+            status: 1,
+            at: SystemTime::now(),
+        };
+        cell.set(exit);
+
+        assert_eq!(waiter.join().unwrap(), exit);
+        assert_eq!(cell.get(), Some(exit));
+        assert_eq!(cell.wait(), exit);
+    }
+
+    #[test]
+    fn test_watch_exit() {
+        let child = Command::new("sh").args(["-c", "exit 7"]).spawn().unwrap();
+        let process = Process {
+            pid: child.id(),
+            ..Default::default()
+        };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        process.watch_exit(move |exit| sender.send(exit).unwrap());
+
+        let exit = process.exit.wait();
+        assert_eq!(exit.status, 7);
+        assert_eq!(receiver.recv().unwrap(), exit);
+    }
+
+    #[test]
+    fn test_open_stdio() -> Result<(), TaskError> {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = |name: &str| tmp.path().join(name).to_string_lossy().into_owned();
+        for name in ["stdin", "stdout"] {
+            mkfifo(tmp.path().join(name).as_path(), Mode::S_IRWXU).unwrap();
+        }
+
+        // containerd holds the reading end of stdout
+        let stdout_path = path("stdout");
+        let reader = thread::spawn(move || {
+            let mut output = String::new();
+            File::open(stdout_path)
+                .unwrap()
+                .read_to_string(&mut output)
+                .unwrap();
+            output
+        });
+
+        let paths = StdioPaths {
+            stdin: path("stdin"),
+            stdout: path("stdout"),
+            stderr: String::new(),
+        };
+        let stdio = paths.open()?;
+        assert!(stdio.stdin.is_some());
+        assert!(stdio.stderr.is_none());
+
+        let mut stdout = File::from(stdio.stdout.unwrap());
+        stdout.write_all(b"hello").unwrap();
+        drop(stdout);
+        assert_eq!(reader.join().unwrap(), "hello");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_missing_fifo() {
+        let paths = StdioPaths {
+            stdout: "/nonexistent/stdout".to_owned(),
+            ..Default::default()
+        };
+        assert!(matches!(paths.open(), Err(TaskError::Stdio { .. })));
+    }
+}
@@ -0,0 +1,143 @@
+//! Shim process containerd starts for the tasks, which serves the task service
+//! and cleans up after the tasks once the shim is gone
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use containerd_shim::protos::protobuf::well_known_types::timestamp::Timestamp;
+use containerd_shim::protos::protobuf::MessageField;
+use containerd_shim::publisher::RemotePublisher;
+use containerd_shim::util::{read_options, write_address};
+use containerd_shim::{io_error, Config, DeleteResponse, Error, ExitSignal, Flags, StartOpts};
+use libcontainer::container::Container;
+use libcontainer::error::LibcontainerError;
+use libcontainer::oci_spec::runtime::Spec;
+
+use crate::events::Containerd;
+use crate::task::{self, TaskService, DEFAULT_ROOT};
+
+/// Annotation of the Kubernetes CRI, the containers of a pod are grouped into
+/// one shim by
+const SANDBOX_ID_ANNOTATION: &str = "io.kubernetes.cri.sandbox-id";
+
+/// The shim logs through tracing and reaps the processes of its tasks itself,
+/// since the reaper of the containerd-shim crate would race with the waits of
+/// the shim and libcontainer
+pub fn config() -> Config {
+    Config {
+        no_setup_logger: true,
+        no_reaper: true,
+        ..Default::default()
+    }
+}
+
+pub struct Shim {
+    id: String,
+    namespace: String,
+    debug: bool,
+    exit: Arc<ExitSignal>,
+}
+
+impl containerd_shim::Shim for Shim {
+    type T = TaskService;
+
+    fn new(_runtime_id: &str, args: &Flags, _config: &mut Config) -> Self {
+        Self {
+            id: args.id.clone(),
+            namespace: args.namespace.clone(),
+            debug: args.debug,
+            exit: Arc::default(),
+        }
+    }
+
+    fn start_shim(&mut self, opts: StartOpts) -> Result<String, Error> {
+        // the shim is started in the bundle of the task
+        let spec = Spec::load("config.json").ok();
+        let grouping = grouping(spec.as_ref(), &opts.id);
+        let (_, address) = containerd_shim::spawn(opts, &grouping, Vec::new())?;
+        write_address(&address)?;
+
+        Ok(address)
+    }
+
+    fn delete_shim(&mut self) -> Result<DeleteResponse, Error> {
+        let bundle = env::current_dir().map_err(io_error!(e, "get the bundle"))?;
+        let root = read_options(&bundle)
+            .ok()
+            .map(|options| options.root)
+            .filter(|root| !root.is_empty())
+            .map_or_else(|| PathBuf::from(DEFAULT_ROOT), PathBuf::from)
+            .join(&self.namespace);
+
+        // the shim has exited without deleting the container, which is
+        // killed and deleted regardless of its state
+        match Container::load_from_root(&root, &self.id) {
+            Ok(mut container) => {
+                if let Err(err) = container.delete(true) {
+                    tracing::warn!(id = %self.id, %err, "failed to delete the container");
+                }
+            }
+            Err(LibcontainerError::NoDirectory) => {}
+            Err(err) => tracing::warn!(id = %self.id, %err, "failed to load the container"),
+        }
+        task::unmount_rootfs(&bundle);
+
+        Ok(DeleteResponse {
+            exit_status: 128 + libc::SIGKILL as u32,
+            exited_at: MessageField::some(Timestamp::from(SystemTime::now())),
+            ..Default::default()
+        })
+    }
+
+    fn wait(&mut self) {
+        self.exit.wait();
+    }
+
+    fn create_task_service(&self, publisher: RemotePublisher) -> TaskService {
+        if let Err(err) = crate::logger::init(self.debug) {
+            eprintln!("failed to set up the logger: {err}");
+        }
+
+        TaskService::new(
+            Path::new(DEFAULT_ROOT).to_owned(),
+            &self.namespace,
+            Arc::new(Containerd::new(publisher, &self.namespace)),
+            self.exit.clone(),
+        )
+    }
+}
+
+/// Returns the id the shim is shared by, which is the sandbox of the
+/// Kubernetes pod for the containers of a pod
+fn grouping(spec: Option<&Spec>, id: &str) -> String {
+    spec.and_then(|spec| spec.annotations().as_ref())
+        .and_then(|annotations| annotations.get(SANDBOX_ID_ANNOTATION))
+        .cloned()
+        .unwrap_or_else(|| id.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use libcontainer::oci_spec::runtime::SpecBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_grouping() {
+        assert_eq!(grouping(None, "c1"), "c1");
+        assert_eq!(grouping(Some(&Spec::default()), "c1"), "c1");
+
+        let spec = SpecBuilder::default()
+            .annotations(HashMap::from([(
+                SANDBOX_ID_ANNOTATION.to_owned(),
+                "sandbox".to_owned(),
+            )]))
+            .build()
+            .unwrap();
+        assert_eq!(grouping(Some(&spec), "c1"), "sandbox");
+    }
+}
@@ -0,0 +1,1114 @@
+//! Task service containerd manages the containers of the shim with. The
+//! init process of a task is the init process of a container of
+//! libcontainer, and the exec processes are tenant processes of it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{fs, thread};
+
+use containerd_shim::api::{self, Empty};
+use containerd_shim::mount::mount_rootfs;
+use containerd_shim::protos::events::task::{
+    TaskCreate, TaskDelete, TaskExecAdded, TaskExecStarted, TaskExit, TaskIO, TaskOOM, TaskPaused,
+    TaskResumed, TaskStart,
+};
+use containerd_shim::protos::protobuf::well_known_types::any::Any;
+use containerd_shim::protos::protobuf::well_known_types::timestamp::Timestamp;
+use containerd_shim::protos::protobuf::{Message, MessageField};
+use containerd_shim::protos::types::mount::Mount;
+use containerd_shim::protos::types::task::{ProcessInfo, Status};
+use containerd_shim::util::write_options;
+use containerd_shim::{ExitSignal, TtrpcContext, TtrpcResult};
+use libcgroups::checkpoint::CgroupCheckpoint;
+use libcgroups::common::{CgroupManager, ControllerOpt};
+use libcontainer::container::builder::ContainerBuilder;
+use libcontainer::container::{Container, ContainerStatus};
+use libcontainer::error::LibcontainerError;
+use libcontainer::oci_spec::runtime::{LinuxResources, Process as ProcessSpec};
+use libcontainer::signal::Signal;
+use libcontainer::syscall::syscall::SyscallType;
+use nix::mount::{umount2, MntFlags};
+
+use crate::error::TaskError;
+use crate::events::{self, Publisher};
+use crate::process::{Exit, Process, StdioPaths};
+
+/// Directory the states of the containers are kept in, in a subdirectory per
+/// namespace of containerd
+pub const DEFAULT_ROOT: &str = "/run/containerd/youki";
+
+/// Container of the shim, which containerd calls a task
+struct Task {
+    bundle: PathBuf,
+    /// Root directory the state of the container is kept in
+    root: PathBuf,
+    /// Flag indicating if the rootfs has been mounted from the mounts
+    /// containerd passed, and has to be unmounted on delete
+    mounted_rootfs: bool,
+    init: Process,
+    execs: HashMap<String, Exec>,
+}
+
+impl Task {
+    fn container(&self, id: &str) -> Result<Container, TaskError> {
+        Ok(Container::load_from_root(&self.root, id)?)
+    }
+
+    fn exec(&self, id: &str, exec_id: &str) -> Result<&Exec, TaskError> {
+        self.execs
+            .get(exec_id)
+            .ok_or_else(|| TaskError::ExecNotFound {
+                id: id.to_owned(),
+                exec_id: exec_id.to_owned(),
+            })
+    }
+
+    /// Returns the init process for an empty exec id, and the exec process
+    /// otherwise
+    fn process(&self, id: &str, exec_id: &str) -> Result<&Process, TaskError> {
+        if exec_id.is_empty() {
+            Ok(&self.init)
+        } else {
+            Ok(&self.exec(id, exec_id)?.process)
+        }
+    }
+}
+
+/// Process executed in a container, which is started separately from
+/// being added
+struct Exec {
+    process: Process,
+    /// The process of the OCI runtime spec, as JSON
+    spec: Vec<u8>,
+}
+
+pub struct TaskService {
+    /// Root directory of the container states, unless the runtime options
+    /// of a task set another one
+    root: PathBuf,
+    /// Namespace of containerd, which the states are kept apart by
+    namespace: String,
+    tasks: Mutex<HashMap<String, Task>>,
+    publisher: Arc<dyn Publisher>,
+    exit: Arc<ExitSignal>,
+}
+
+impl TaskService {
+    pub fn new(
+        root: PathBuf,
+        namespace: &str,
+        publisher: Arc<dyn Publisher>,
+        exit: Arc<ExitSignal>,
+    ) -> Self {
+        Self {
+            root,
+            namespace: namespace.to_owned(),
+            tasks: Mutex::default(),
+            publisher,
+            exit,
+        }
+    }
+
+    fn create_task(&self, req: api::CreateTaskRequest) -> Result<u32, TaskError> {
+        let mut tasks = self.tasks.lock().unwrap();
+        if tasks.contains_key(&req.id) {
+            return Err(TaskError::AlreadyExists(req.id));
+        }
+        if req.terminal {
+            return Err(TaskError::Unsupported("a terminal"));
+        }
+        if !req.checkpoint.is_empty() {
+            return Err(TaskError::Unsupported("restoring a checkpoint"));
+        }
+        let options = match req.options.as_ref() {
+            Some(options) => api::Options::parse_from_bytes(&options.value)
+                .map_err(|err| TaskError::InvalidArgument(format!("invalid options: {err}")))?,
+            None => api::Options::default(),
+        };
+        if !options.criu_path.is_empty() {
+            return Err(TaskError::Unsupported("the criu_path option"));
+        }
+        let root = match options.root.as_str() {
+            "" => self.root.join(&self.namespace),
+            root => Path::new(root).join(&self.namespace),
+        };
+        // the shim is started anew to clean up after the task, which reads
+        // the options from the bundle
+        if req.options.is_some() {
+            write_options(&req.bundle, &options)
+                .map_err(|err| TaskError::Shim("write the runtime options", err))?;
+        }
+
+        let bundle = PathBuf::from(&req.bundle);
+        let stdio = StdioPaths {
+            stdin: req.stdin.clone(),
+            stdout: req.stdout.clone(),
+            stderr: req.stderr.clone(),
+        };
+        let mounted_rootfs = !req.rootfs.is_empty();
+        if mounted_rootfs {
+            mount(&bundle, &req.rootfs)?;
+        }
+
+        let result = (|| {
+            fs::create_dir_all(&root).map_err(|err| TaskError::Io(root.clone(), err))?;
+            let container = ContainerBuilder::new(req.id.clone(), SyscallType::default())
+                .with_root_path(&root)?
+                .with_stdio(stdio.open()?)
+                .validate_id()?
+                .as_init(&bundle)
+                .with_systemd(options.systemd_cgroup)
+                .with_no_pivot(options.no_pivot_root)
+                .with_no_new_keyring(options.no_new_keyring)
+                .with_detach(true)
+                .build()?;
+            let pid = container
+                .pid()
+                .ok_or(LibcontainerError::Other("container has no pid".to_owned()))?;
+            Ok((container, pid.as_raw() as u32))
+        })();
+        let (container, pid) = match result {
+            Ok(created) => created,
+            Err(err) => {
+                if mounted_rootfs {
+                    unmount_rootfs(&bundle);
+                }
+                return Err(err);
+            }
+        };
+
+        let init = Process {
+            pid,
+            stdio,
+            ..Default::default()
+        };
+        self.watch_exit(&req.id, &req.id, &init);
+        self.watch_oom(&container);
+        tasks.insert(
+            req.id.clone(),
+            Task {
+                bundle,
+                root,
+                mounted_rootfs,
+                init,
+                execs: HashMap::new(),
+            },
+        );
+
+        events::publish(
+            self.publisher.as_ref(),
+            TaskCreate {
+                container_id: req.id,
+                bundle: req.bundle,
+                rootfs: req.rootfs,
+                io: MessageField::some(TaskIO {
+                    stdin: req.stdin,
+                    stdout: req.stdout,
+                    stderr: req.stderr,
+                    terminal: req.terminal,
+                    ..Default::default()
+                }),
+                pid,
+                ..Default::default()
+            },
+        );
+
+        Ok(pid)
+    }
+
+    fn start_task(&self, req: api::StartRequest) -> Result<u32, TaskError> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let task = get_mut(&mut tasks, &req.id)?;
+
+        if req.exec_id.is_empty() {
+            task.container(&req.id)?.start()?;
+            events::publish(
+                self.publisher.as_ref(),
+                TaskStart {
+                    container_id: req.id,
+                    pid: task.init.pid,
+                    ..Default::default()
+                },
+            );
+            return Ok(task.init.pid);
+        }
+
+        let exec = task.exec(&req.id, &req.exec_id)?;
+        if exec.process.pid != 0 {
+            return Err(TaskError::FailedPrecondition(format!(
+                "exec process {} has been started already",
+                req.exec_id
+            )));
+        }
+        // the tenant builder reads the process from a file
+        let spec_path = task.bundle.join(format!("{}.process.json", req.exec_id));
+        fs::write(&spec_path, &exec.spec).map_err(|err| TaskError::Io(spec_path.clone(), err))?;
+        let result = (|| {
+            Ok::<_, TaskError>(
+                ContainerBuilder::new(req.id.clone(), SyscallType::default())
+                    .with_root_path(&task.root)?
+                    .with_stdio(exec.process.stdio.open()?)
+                    .as_tenant()
+                    .with_process(Some(&spec_path))
+                    .with_exec_id(Some(req.exec_id.clone()))
+                    .with_detach(true)
+                    .build()?,
+            )
+        })();
+        if let Err(err) = fs::remove_file(&spec_path) {
+            tracing::warn!(?spec_path, %err, "failed to remove the exec process spec");
+        }
+        let pid = result?.as_raw() as u32;
+
+        let exec = task
+            .execs
+            .get_mut(&req.exec_id)
+            .expect("exec process is known");
+        exec.process.pid = pid;
+        self.watch_exit(&req.id, &req.exec_id, &exec.process);
+        events::publish(
+            self.publisher.as_ref(),
+            TaskExecStarted {
+                container_id: req.id,
+                exec_id: req.exec_id,
+                pid,
+                ..Default::default()
+            },
+        );
+
+        Ok(pid)
+    }
+
+    fn delete_task(&self, req: api::DeleteRequest) -> Result<api::DeleteResponse, TaskError> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let task = get_mut(&mut tasks, &req.id)?;
+
+        if !req.exec_id.is_empty() {
+            let exec = task.exec(&req.id, &req.exec_id)?;
+            let exit = exec.process.exit.get();
+            if exec.process.pid != 0 && exit.is_none() {
+                return Err(TaskError::FailedPrecondition(format!(
+                    "exec process {} is still running",
+                    req.exec_id
+                )));
+            }
+            let response = delete_response(exec.process.pid, exit);
+            task.execs.remove(&req.exec_id);
+            return Ok(response);
+        }
+
+        // a container which has been created but not started is deleted
+        // as well, which kills its init process
+        task.container(&req.id)?.delete(false)?;
+        let exit = task.init.exit.wait();
+        if task.mounted_rootfs {
+            unmount_rootfs(&task.bundle);
+        }
+        let task = tasks.remove(&req.id).expect("task is known");
+
+        let response = delete_response(task.init.pid, Some(exit));
+        events::publish(
+            self.publisher.as_ref(),
+            TaskDelete {
+                container_id: req.id.clone(),
+                pid: response.pid,
+                exit_status: response.exit_status,
+                exited_at: response.exited_at.clone(),
+                id: req.id,
+                ..Default::default()
+            },
+        );
+        Ok(response)
+    }
+
+    fn exec_process(&self, req: api::ExecProcessRequest) -> Result<(), TaskError> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let task = get_mut(&mut tasks, &req.id)?;
+        if task.execs.contains_key(&req.exec_id) {
+            return Err(TaskError::AlreadyExists(format!(
+                "{}/{}",
+                req.id, req.exec_id
+            )));
+        }
+        if req.terminal {
+            return Err(TaskError::Unsupported("a terminal"));
+        }
+        let spec = req.spec.into_option().unwrap_or_default().value;
+        serde_json::from_slice::<ProcessSpec>(&spec)
+            .map_err(|err| TaskError::Json("process spec", err))?;
+
+        task.execs.insert(
+            req.exec_id.clone(),
+            Exec {
+                process: Process::new(StdioPaths {
+                    stdin: req.stdin,
+                    stdout: req.stdout,
+                    stderr: req.stderr,
+                }),
+                spec,
+            },
+        );
+        events::publish(
+            self.publisher.as_ref(),
+            TaskExecAdded {
+                container_id: req.id,
+                exec_id: req.exec_id,
+                ..Default::default()
+            },
+        );
+
+        Ok(())
+    }
+
+    fn kill_task(&self, req: api::KillRequest) -> Result<(), TaskError> {
+        let tasks = self.tasks.lock().unwrap();
+        let task = get(&tasks, &req.id)?;
+        let signal = Signal::try_from(req.signal as i32)
+            .map_err(|_| TaskError::InvalidArgument(format!("invalid signal {}", req.signal)))?;
+
+        let process = task.process(&req.id, &req.exec_id)?;
+        if process.pid == 0 {
+            return Err(TaskError::FailedPrecondition(format!(
+                "exec process {} hasn't been started",
+                req.exec_id
+            )));
+        }
+        if process.exit.get().is_some() && !req.all {
+            return Err(TaskError::Exited(process.pid));
+        }
+
+        let mut container = task.container(&req.id)?;
+        if req.exec_id.is_empty() {
+            container.kill(signal, req.all)?;
+        } else {
+            container.kill_exec(&req.exec_id, signal)?;
+        }
+
+        Ok(())
+    }
+
+    fn task_state(&self, req: api::StateRequest) -> Result<api::StateResponse, TaskError> {
+        let tasks = self.tasks.lock().unwrap();
+        let task = get(&tasks, &req.id)?;
+        let process = task.process(&req.id, &req.exec_id)?;
+
+        let exit = process.exit.get();
+        let container_status = match (exit, req.exec_id.is_empty()) {
+            (None, true) => Some(task.container(&req.id)?.status()),
+            _ => None,
+        };
+        let mut response = api::StateResponse {
+            id: req.id,
+            bundle: task.bundle.to_string_lossy().into_owned(),
+            pid: process.pid,
+            status: status(exit, process.pid, container_status).into(),
+            stdin: process.stdio.stdin.clone(),
+            stdout: process.stdio.stdout.clone(),
+            stderr: process.stdio.stderr.clone(),
+            exec_id: req.exec_id,
+            ..Default::default()
+        };
+        if let Some(exit) = exit {
+            response.exit_status = exit.status;
+            response.exited_at = MessageField::some(Timestamp::from(exit.at));
+        }
+
+        Ok(response)
+    }
+
+    fn wait_task(&self, req: api::WaitRequest) -> Result<api::WaitResponse, TaskError> {
+        // the exit is awaited without blocking the other requests
+        let exit = {
+            let tasks = self.tasks.lock().unwrap();
+            let task = get(&tasks, &req.id)?;
+            task.process(&req.id, &req.exec_id)?.exit.clone()
+        };
+        let exit = exit.wait();
+
+        Ok(api::WaitResponse {
+            exit_status: exit.status,
+            exited_at: MessageField::some(Timestamp::from(exit.at)),
+            ..Default::default()
+        })
+    }
+
+    fn task_pids(&self, req: api::PidsRequest) -> Result<api::PidsResponse, TaskError> {
+        let tasks = self.tasks.lock().unwrap();
+        let task = get(&tasks, &req.id)?;
+        let pids = task.container(&req.id)?.cgroup_manager()?.get_all_pids()?;
+
+        Ok(api::PidsResponse {
+            processes: pids
+                .into_iter()
+                .map(|pid| ProcessInfo {
+                    pid: pid.as_raw() as u32,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    fn pause_task(&self, id: &str, pause: bool) -> Result<(), TaskError> {
+        let tasks = self.tasks.lock().unwrap();
+        let mut container = get(&tasks, id)?.container(id)?;
+        let container_id = id.to_owned();
+        if pause {
+            container.pause()?;
+            events::publish(
+                self.publisher.as_ref(),
+                TaskPaused {
+                    container_id,
+                    ..Default::default()
+                },
+            );
+        } else {
+            container.resume()?;
+            events::publish(
+                self.publisher.as_ref(),
+                TaskResumed {
+                    container_id,
+                    ..Default::default()
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    fn update_task(&self, req: api::UpdateTaskRequest) -> Result<(), TaskError> {
+        let tasks = self.tasks.lock().unwrap();
+        let task = get(&tasks, &req.id)?;
+        let resources = parse_resources(req.resources.as_ref())?;
+        let container = task.container(&req.id)?;
+        let cgroup_manager = container.cgroup_manager()?;
+
+        // the update becomes part of the cgroup configuration, the same as
+        // with youki update
+        let (result, update) = CgroupCheckpoint::record(|| {
+            cgroup_manager.apply(&ControllerOpt {
+                resources: &resources,
+                disable_oom_killer: false,
+                oom_score_adj: None,
+                freezer_state: None,
+            })
+        });
+        result?;
+        container.record_cgroup_update(update)?;
+
+        Ok(())
+    }
+
+    fn shutdown_shim(&self) {
+        // containerd shuts the shim down once it has no tasks anymore
+        if self.tasks.lock().unwrap().is_empty() {
+            self.exit.signal();
+        }
+    }
+
+    /// Reports the exit of a process to containerd once it has been reaped
+    fn watch_exit(&self, id: &str, exec_id: &str, process: &Process) {
+        let publisher = self.publisher.clone();
+        let container_id = id.to_owned();
+        let exec_id = exec_id.to_owned();
+        let pid = process.pid;
+        process.watch_exit(move |exit| {
+            events::publish(
+                publisher.as_ref(),
+                TaskExit {
+                    container_id,
+                    id: exec_id,
+                    pid,
+                    exit_status: exit.status,
+                    exited_at: MessageField::some(Timestamp::from(exit.at)),
+                    ..Default::default()
+                },
+            );
+        });
+    }
+
+    /// Reports the out of memory events of the container until its cgroup
+    /// has been removed
+    fn watch_oom(&self, container: &Container) {
+        let notifier = container
+            .cgroup_manager()
+            .map_err(|err| err.to_string())
+            .and_then(|manager| manager.oom_notifier().map_err(|err| err.to_string()));
+        let mut notifier = match notifier {
+            Ok(notifier) => notifier,
+            Err(err) => {
+                tracing::warn!(id = container.id(), %err, "out of memory events aren't reported");
+                return;
+            }
+        };
+
+        let publisher = self.publisher.clone();
+        let container_id = container.id().to_owned();
+        thread::spawn(move || loop {
+            match notifier.wait() {
+                Ok(true) => events::publish(
+                    publisher.as_ref(),
+                    TaskOOM {
+                        container_id: container_id.clone(),
+                        ..Default::default()
+                    },
+                ),
+                Ok(false) => break,
+                Err(err) => {
+                    tracing::warn!(id = %container_id, %err, "failed to wait for out of memory events");
+                    break;
+                }
+            }
+        });
+    }
+}
+
+impl containerd_shim::Task for TaskService {
+    fn create(
+        &self,
+        _ctx: &TtrpcContext,
+        req: api::CreateTaskRequest,
+    ) -> TtrpcResult<api::CreateTaskResponse> {
+        tracing::info!(id = %req.id, bundle = %req.bundle, "create task");
+        let pid = self.create_task(req)?;
+        Ok(api::CreateTaskResponse {
+            pid,
+            ..Default::default()
+        })
+    }
+
+    fn start(
+        &self,
+        _ctx: &TtrpcContext,
+        req: api::StartRequest,
+    ) -> TtrpcResult<api::StartResponse> {
+        tracing::info!(id = %req.id, exec_id = %req.exec_id, "start task");
+        let pid = self.start_task(req)?;
+        Ok(api::StartResponse {
+            pid,
+            ..Default::default()
+        })
+    }
+
+    fn delete(
+        &self,
+        _ctx: &TtrpcContext,
+        req: api::DeleteRequest,
+    ) -> TtrpcResult<api::DeleteResponse> {
+        tracing::info!(id = %req.id, exec_id = %req.exec_id, "delete task");
+        Ok(self.delete_task(req)?)
+    }
+
+    fn exec(&self, _ctx: &TtrpcContext, req: api::ExecProcessRequest) -> TtrpcResult<Empty> {
+        tracing::info!(id = %req.id, exec_id = %req.exec_id, "exec process");
+        self.exec_process(req)?;
+        Ok(Empty::default())
+    }
+
+    fn kill(&self, _ctx: &TtrpcContext, req: api::KillRequest) -> TtrpcResult<Empty> {
+        tracing::info!(id = %req.id, exec_id = %req.exec_id, signal = req.signal, "kill task");
+        self.kill_task(req)?;
+        Ok(Empty::default())
+    }
+
+    fn state(
+        &self,
+        _ctx: &TtrpcContext,
+        req: api::StateRequest,
+    ) -> TtrpcResult<api::StateResponse> {
+        tracing::debug!(id = %req.id, exec_id = %req.exec_id, "state of task");
+        Ok(self.task_state(req)?)
+    }
+
+    fn wait(&self, _ctx: &TtrpcContext, req: api::WaitRequest) -> TtrpcResult<api::WaitResponse> {
+        tracing::debug!(id = %req.id, exec_id = %req.exec_id, "wait for task");
+        Ok(self.wait_task(req)?)
+    }
+
+    fn pids(&self, _ctx: &TtrpcContext, req: api::PidsRequest) -> TtrpcResult<api::PidsResponse> {
+        Ok(self.task_pids(req)?)
+    }
+
+    fn pause(&self, _ctx: &TtrpcContext, req: api::PauseRequest) -> TtrpcResult<Empty> {
+        tracing::info!(id = %req.id, "pause task");
+        self.pause_task(&req.id, true)?;
+        Ok(Empty::default())
+    }
+
+    fn resume(&self, _ctx: &TtrpcContext, req: api::ResumeRequest) -> TtrpcResult<Empty> {
+        tracing::info!(id = %req.id, "resume task");
+        self.pause_task(&req.id, false)?;
+        Ok(Empty::default())
+    }
+
+    fn update(&self, _ctx: &TtrpcContext, req: api::UpdateTaskRequest) -> TtrpcResult<Empty> {
+        tracing::info!(id = %req.id, "update task");
+        self.update_task(req)?;
+        Ok(Empty::default())
+    }
+
+    fn close_io(&self, _ctx: &TtrpcContext, _req: api::CloseIORequest) -> TtrpcResult<Empty> {
+        // the shim doesn't keep the stdin of the processes open, so it is
+        // closed once containerd closes its end
+        Ok(Empty::default())
+    }
+
+    fn resize_pty(&self, _ctx: &TtrpcContext, _req: api::ResizePtyRequest) -> TtrpcResult<Empty> {
+        Err(TaskError::Unsupported("a terminal").into())
+    }
+
+    fn stats(
+        &self,
+        _ctx: &TtrpcContext,
+        _req: api::StatsRequest,
+    ) -> TtrpcResult<api::StatsResponse> {
+        Err(TaskError::Unsupported("reporting stats").into())
+    }
+
+    fn checkpoint(
+        &self,
+        _ctx: &TtrpcContext,
+        _req: api::CheckpointTaskRequest,
+    ) -> TtrpcResult<Empty> {
+        Err(TaskError::Unsupported("checkpointing").into())
+    }
+
+    fn connect(
+        &self,
+        _ctx: &TtrpcContext,
+        req: api::ConnectRequest,
+    ) -> TtrpcResult<api::ConnectResponse> {
+        let tasks = self.tasks.lock().unwrap();
+        Ok(api::ConnectResponse {
+            shim_pid: std::process::id(),
+            task_pid: tasks.get(&req.id).map(|task| task.init.pid).unwrap_or(0),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            ..Default::default()
+        })
+    }
+
+    fn shutdown(&self, _ctx: &TtrpcContext, _req: api::ShutdownRequest) -> TtrpcResult<Empty> {
+        tracing::info!("shutdown shim");
+        self.shutdown_shim();
+        Ok(Empty::default())
+    }
+}
+
+fn get<'a>(tasks: &'a HashMap<String, Task>, id: &str) -> Result<&'a Task, TaskError> {
+    tasks
+        .get(id)
+        .ok_or_else(|| TaskError::NotFound(id.to_owned()))
+}
+
+fn get_mut<'a>(tasks: &'a mut HashMap<String, Task>, id: &str) -> Result<&'a mut Task, TaskError> {
+    tasks
+        .get_mut(id)
+        .ok_or_else(|| TaskError::NotFound(id.to_owned()))
+}
+
+/// Returns the status of a process, which is the status of the container for
+/// the init process until it has exited
+fn status(exit: Option<Exit>, pid: u32, container: Option<ContainerStatus>) -> Status {
+    if exit.is_some() {
+        return Status::STOPPED;
+    }
+
+    match container {
+        Some(ContainerStatus::Creating | ContainerStatus::Created) => Status::CREATED,
+        Some(ContainerStatus::Running) => Status::RUNNING,
+        Some(ContainerStatus::Stopped) => Status::STOPPED,
+        Some(ContainerStatus::Paused) => Status::PAUSED,
+        None if pid == 0 => Status::CREATED,
+        None => Status::RUNNING,
+    }
+}
+
+fn delete_response(pid: u32, exit: Option<Exit>) -> api::DeleteResponse {
+    api::DeleteResponse {
+        pid,
+        exit_status: exit.map(|exit| exit.status).unwrap_or_default(),
+        exited_at: exit
+            .map(|exit| MessageField::some(Timestamp::from(exit.at)))
+            .unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+/// The resources of an update are the linux resources of the OCI runtime
+/// spec, as JSON
+fn parse_resources(resources: Option<&Any>) -> Result<LinuxResources, TaskError> {
+    let resources =
+        resources.ok_or_else(|| TaskError::InvalidArgument("no resources".to_owned()))?;
+    serde_json::from_slice(&resources.value).map_err(|err| TaskError::Json("resources", err))
+}
+
+/// Mounts the rootfs of the bundle from the mounts containerd passed
+fn mount(bundle: &Path, mounts: &[Mount]) -> Result<(), TaskError> {
+    let rootfs = bundle.join("rootfs");
+    fs::create_dir_all(&rootfs).map_err(|err| TaskError::Io(rootfs.clone(), err))?;
+    for mount in mounts {
+        let fs_type = Some(mount.type_.as_str()).filter(|t| !t.is_empty());
+        if let Err(err) = mount_rootfs(fs_type, Some(&mount.source), &mount.options, &rootfs) {
+            unmount_rootfs(bundle);
+            return Err(TaskError::Shim("mount the rootfs", err));
+        }
+    }
+
+    Ok(())
+}
+
+/// Unmounts the rootfs of the bundle, including the mounts stacked on it
+pub fn unmount_rootfs(bundle: &Path) {
+    let rootfs = bundle.join("rootfs");
+    loop {
+        match umount2(&rootfs, MntFlags::MNT_DETACH) {
+            Ok(()) => continue,
+            // EINVAL once nothing is mounted on the rootfs anymore
+            Err(nix::Error::EINVAL | nix::Error::ENOENT) => break,
+            Err(err) => {
+                tracing::warn!(?rootfs, %err, "failed to unmount the rootfs");
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::events::test_utils::Recorder;
+
+    fn service() -> (TaskService, Arc<Recorder>, tempfile::TempDir) {
+        let tmp = tempfile::tempdir().unwrap();
+        let recorder = Arc::new(Recorder::default());
+        let service = TaskService::new(
+            tmp.path().join("root"),
+            "default",
+            recorder.clone(),
+            Arc::new(ExitSignal::default()),
+        );
+        (service, recorder, tmp)
+    }
+
+    /// Adds a task without a container, for the requests which don't need
+    /// the container
+    fn add_task(service: &TaskService, id: &str, bundle: &Path) {
+        service.tasks.lock().unwrap().insert(
+            id.to_owned(),
+            Task {
+                bundle: bundle.to_owned(),
+                root: service.root.join(&service.namespace),
+                mounted_rootfs: false,
+                init: Process {
+                    pid: 1000,
+                    ..Default::default()
+                },
+                execs: HashMap::new(),
+            },
+        );
+    }
+
+    fn exec_request(id: &str, exec_id: &str) -> api::ExecProcessRequest {
+        let spec = serde_json::to_vec(&ProcessSpec::default()).unwrap();
+        api::ExecProcessRequest {
+            id: id.to_owned(),
+            exec_id: exec_id.to_owned(),
+            spec: MessageField::some(Any {
+                type_url: "types.containerd.io/opencontainers/runtime-spec/1/Process".to_owned(),
+                value: spec,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_unknown_task() {
+        let (service, _, _tmp) = service();
+        let id = || "c1".to_owned();
+
+        let errors = [
+            service
+                .start_task(api::StartRequest {
+                    id: id(),
+                    ..Default::default()
+                })
+                .unwrap_err(),
+            service
+                .delete_task(api::DeleteRequest {
+                    id: id(),
+                    ..Default::default()
+                })
+                .unwrap_err(),
+            service
+                .kill_task(api::KillRequest {
+                    id: id(),
+                    signal: 9,
+                    ..Default::default()
+                })
+                .unwrap_err(),
+            service
+                .task_state(api::StateRequest {
+                    id: id(),
+                    ..Default::default()
+                })
+                .unwrap_err(),
+            service
+                .wait_task(api::WaitRequest {
+                    id: id(),
+                    ..Default::default()
+                })
+                .unwrap_err(),
+            service.exec_process(exec_request("c1", "e1")).unwrap_err(),
+            service.pause_task("c1", true).unwrap_err(),
+        ];
+        for err in errors {
+            assert!(
+                matches!(err, TaskError::NotFound(ref id) if id == "c1"),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_create_unsupported() {
+        let (service, recorder, tmp) = service();
+        let request = || api::CreateTaskRequest {
+            id: "c1".to_owned(),
+            bundle: tmp.path().to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+
+        let err = service
+            .create_task(api::CreateTaskRequest {
+                terminal: true,
+                ..request()
+            })
+            .unwrap_err();
+        assert!(matches!(err, TaskError::Unsupported(_)), "{err}");
+        let err = service
+            .create_task(api::CreateTaskRequest {
+                checkpoint: "/checkpoint".to_owned(),
+                ..request()
+            })
+            .unwrap_err();
+        assert!(matches!(err, TaskError::Unsupported(_)), "{err}");
+        let options = api::Options {
+            criu_path: "/usr/bin/criu".to_owned(),
+            ..Default::default()
+        };
+        let err = service
+            .create_task(api::CreateTaskRequest {
+                options: MessageField::some(Any {
+                    value: options.write_to_bytes().unwrap(),
+                    ..Default::default()
+                }),
+                ..request()
+            })
+            .unwrap_err();
+        assert!(matches!(err, TaskError::Unsupported(_)), "{err}");
+
+        add_task(&service, "c1", tmp.path());
+        let err = service.create_task(request()).unwrap_err();
+        assert!(matches!(err, TaskError::AlreadyExists(_)), "{err}");
+        assert!(recorder.topics().is_empty());
+    }
+
+    #[test]
+    fn test_exec() -> Result<(), TaskError> {
+        let (service, recorder, tmp) = service();
+        add_task(&service, "c1", tmp.path());
+
+        service.exec_process(exec_request("c1", "e1"))?;
+        assert_eq!(recorder.topics(), ["/tasks/exec-added"]);
+        let err = service.exec_process(exec_request("c1", "e1")).unwrap_err();
+        assert!(matches!(err, TaskError::AlreadyExists(_)), "{err}");
+        let err = service
+            .exec_process(api::ExecProcessRequest {
+                terminal: true,
+                ..exec_request("c1", "e2")
+            })
+            .unwrap_err();
+        assert!(matches!(err, TaskError::Unsupported(_)), "{err}");
+        let mut invalid = exec_request("c1", "e2");
+        invalid.spec.as_mut().unwrap().value = b"{".to_vec();
+        let err = service.exec_process(invalid).unwrap_err();
+        assert!(matches!(err, TaskError::Json(..)), "{err}");
+
+        let state = service.task_state(api::StateRequest {
+            id: "c1".to_owned(),
+            exec_id: "e1".to_owned(),
+            ..Default::default()
+        })?;
+        assert_eq!(state.status.enum_value(), Ok(Status::CREATED));
+        assert_eq!(state.pid, 0);
+
+        let err = service
+            .kill_task(api::KillRequest {
+                id: "c1".to_owned(),
+                exec_id: "e1".to_owned(),
+                signal: 9,
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert!(matches!(err, TaskError::FailedPrecondition(_)), "{err}");
+
+        // an exec process which hasn't been started can be deleted
+        let response = service.delete_task(api::DeleteRequest {
+            id: "c1".to_owned(),
+            exec_id: "e1".to_owned(),
+            ..Default::default()
+        })?;
+        assert_eq!(response.pid, 0);
+        let err = service
+            .task_state(api::StateRequest {
+                id: "c1".to_owned(),
+                exec_id: "e1".to_owned(),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert!(matches!(err, TaskError::ExecNotFound { .. }), "{err}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exited_exec() -> Result<(), TaskError> {
+        let (service, recorder, tmp) = service();
+        add_task(&service, "c1", tmp.path());
+        service.exec_process(exec_request("c1", "e1"))?;
+
+        // the shim reaps the exec process, which is a child of the shim
+        let child = Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+        {
+            let mut tasks = service.tasks.lock().unwrap();
+            let exec = tasks.get_mut("c1").unwrap().execs.get_mut("e1").unwrap();
+            exec.process.pid = child.id();
+            service.watch_exit("c1", "e1", &exec.process);
+        }
+
+        let response = service.wait_task(api::WaitRequest {
+            id: "c1".to_owned(),
+            exec_id: "e1".to_owned(),
+            ..Default::default()
+        })?;
+        assert_eq!(response.exit_status, 3);
+        assert!(response.exited_at.is_some());
+
+        let state = service.task_state(api::StateRequest {
+            id: "c1".to_owned(),
+            exec_id: "e1".to_owned(),
+            ..Default::default()
+        })?;
+        assert_eq!(state.status.enum_value(), Ok(Status::STOPPED));
+        assert_eq!(state.exit_status, 3);
+        assert_eq!(state.pid, child.id());
+
+        let err = service
+            .kill_task(api::KillRequest {
+                id: "c1".to_owned(),
+                exec_id: "e1".to_owned(),
+                signal: 15,
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert!(matches!(err, TaskError::Exited(_)), "{err}");
+
+        let response = service.delete_task(api::DeleteRequest {
+            id: "c1".to_owned(),
+            exec_id: "e1".to_owned(),
+            ..Default::default()
+        })?;
+        assert_eq!(response.exit_status, 3);
+        assert_eq!(response.pid, child.id());
+
+        // the exit is published by the thread reaping the process, after
+        // the exit has been recorded
+        for _ in 0..100 {
+            if recorder.topics().len() == 2 {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(recorder.topics(), ["/tasks/exec-added", "/tasks/exit"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_signal() {
+        let (service, _, tmp) = service();
+        add_task(&service, "c1", tmp.path());
+
+        let err = service
+            .kill_task(api::KillRequest {
+                id: "c1".to_owned(),
+                signal: 1000,
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert!(matches!(err, TaskError::InvalidArgument(_)), "{err}");
+    }
+
+    #[test]
+    fn test_status() {
+        let exit = Some(Exit {
+            status: 0,
+            at: SystemTime::now(),
+        });
+        for (exit, pid, container, expected) in [
+            (exit, 10, Some(ContainerStatus::Running), Status::STOPPED),
+            (None, 10, Some(ContainerStatus::Creating), Status::CREATED),
+            (None, 10, Some(ContainerStatus::Created), Status::CREATED),
+            (None, 10, Some(ContainerStatus::Running), Status::RUNNING),
+            (None, 10, Some(ContainerStatus::Paused), Status::PAUSED),
+            (None, 10, Some(ContainerStatus::Stopped), Status::STOPPED),
+            (None, 0, None, Status::CREATED),
+            (None, 10, None, Status::RUNNING),
+            (exit, 10, None, Status::STOPPED),
+        ] {
+            assert_eq!(status(exit, pid, container), expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_resources() -> Result<(), TaskError> {
+        let resources = parse_resources(Some(&Any {
+            value: br#"{"pids": {"limit": 10}}"#.to_vec(),
+            ..Default::default()
+        }))?;
+        assert_eq!(resources.pids().as_ref().map(|pids| pids.limit()), Some(10));
+
+        let err = parse_resources(None).unwrap_err();
+        assert!(matches!(err, TaskError::InvalidArgument(_)), "{err}");
+        let err = parse_resources(Some(&Any {
+            value: b"{".to_vec(),
+            ..Default::default()
+        }))
+        .unwrap_err();
+        assert!(matches!(err, TaskError::Json(..)), "{err}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let (service, _, tmp) = service();
+        add_task(&service, "c1", tmp.path());
+        service.shutdown_shim();
+
+        // the shim keeps serving while it has tasks
+        let exit = service.exit.clone();
+        let waiter = thread::spawn(move || exit.wait());
+        thread::sleep(std::time::Duration::from_millis(10));
+        assert!(!waiter.is_finished());
+
+        service.tasks.lock().unwrap().clear();
+        service.shutdown_shim();
+        waiter.join().unwrap();
+    }
+}
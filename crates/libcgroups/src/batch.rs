@@ -0,0 +1,308 @@
+//! Batching of the writes to cgroup files which apply the resources of a
+//! cgroup. The writes of every controller are planned before any of them is
+//! made, so that resources which a controller rejects don't leave the cgroup
+//! half configured. The planned writes are then made in one pass, which goes
+//! on after a failed write, and all failures are reported together, naming
+//! the files and values.
+//!
+//! Controllers which react to the errors of their own writes, e.g. by
+//! retrying them, can't be planned. They are applied in order with the
+//! planned writes, and their errors are reported along with the failed
+//! writes.
+use std::cell::RefCell;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::{fmt, io};
+
+use crate::checkpoint::CgroupWrite;
+use crate::common::{self, WrappedIoError};
+
+thread_local! {
+    static PLAN: RefCell<Option<Vec<CgroupWrite>>> = RefCell::new(None);
+}
+
+/// Adds a write to the plan and returns true, if writes are planned on the
+/// current thread
+pub(crate) fn plan_write(path: &Path, value: &str) -> bool {
+    PLAN.with(|plan| match plan.borrow_mut().as_mut() {
+        Some(writes) => {
+            writes.push(CgroupWrite {
+                path: path.to_owned(),
+                value: value.to_owned(),
+            });
+            true
+        }
+        None => false,
+    })
+}
+
+/// Failure while applying the resources of a controller
+#[derive(Debug)]
+pub enum WriteFailure {
+    /// A planned write has failed
+    Write {
+        controller: String,
+        path: PathBuf,
+        value: String,
+        err: io::Error,
+    },
+    /// A controller which isn't planned has failed
+    Controller {
+        controller: String,
+        err: Box<dyn Error + Send + Sync>,
+    },
+}
+
+impl WriteFailure {
+    pub fn controller(&self) -> &str {
+        match self {
+            Self::Write { controller, .. } | Self::Controller { controller, .. } => controller,
+        }
+    }
+}
+
+impl fmt::Display for WriteFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Write {
+                controller,
+                path,
+                value,
+                err,
+            } => write!(
+                f,
+                "{controller}: failed to write {value:?} to {path:?}: {err}"
+            ),
+            Self::Controller { controller, err } => write!(f, "{controller}: {err}"),
+        }
+    }
+}
+
+/// Failures of all writes which have failed while applying the resources
+#[derive(Debug)]
+pub struct BatchError {
+    pub failures: Vec<WriteFailure>,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to write to cgroup")?;
+        for (i, failure) in self.failures.iter().enumerate() {
+            let separator = if i == 0 { ": " } else { "; " };
+            write!(f, "{separator}{failure}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for BatchError {}
+
+type Apply<'a> = Box<dyn FnOnce() -> Result<(), Box<dyn Error + Send + Sync>> + 'a>;
+
+enum Step<'a> {
+    Planned {
+        controller: String,
+        writes: Vec<CgroupWrite>,
+    },
+    Unplanned {
+        controller: String,
+        apply: Apply<'a>,
+    },
+}
+
+/// Writes of the controllers applying resources, which are made at once by
+/// [`WriteBatch::apply`]
+pub(crate) struct WriteBatch<'a> {
+    steps: Vec<Step<'a>>,
+    best_effort: &'a [String],
+}
+
+impl<'a> WriteBatch<'a> {
+    /// The failures of the controllers given as best effort are logged
+    /// instead of failing the batch
+    pub fn new(best_effort: &'a [String]) -> Self {
+        Self {
+            steps: Vec::new(),
+            best_effort,
+        }
+    }
+
+    /// Plans the writes `apply` makes for a controller. An error of `apply`,
+    /// e.g. about invalid resources, is returned right away, so that none of
+    /// the writes of the batch are made.
+    pub fn plan<C, E>(
+        &mut self,
+        controller: C,
+        apply: impl FnOnce() -> Result<(), E>,
+    ) -> Result<(), E>
+    where
+        C: fmt::Display,
+    {
+        let previous = PLAN.with(|plan| plan.replace(Some(Vec::new())));
+        let result = apply();
+        let writes = PLAN.with(|plan| plan.replace(previous)).unwrap_or_default();
+        result?;
+
+        self.steps.push(Step::Planned {
+            controller: controller.to_string(),
+            writes,
+        });
+        Ok(())
+    }
+
+    /// Adds a controller which makes its writes itself once the batch is
+    /// applied
+    pub fn unplanned<C, E>(&mut self, controller: C, apply: impl FnOnce() -> Result<(), E> + 'a)
+    where
+        C: fmt::Display,
+        E: Error + Send + Sync + 'static,
+    {
+        self.steps.push(Step::Unplanned {
+            controller: controller.to_string(),
+            apply: Box::new(move || apply().map_err(|err| err.into())),
+        });
+    }
+
+    /// Makes the writes of all controllers, in the order they were added
+    pub fn apply(self) -> Result<(), BatchError> {
+        let mut failures = Vec::new();
+        for step in self.steps {
+            match step {
+                Step::Planned { controller, writes } => {
+                    for write in writes {
+                        if let Err(err) = common::write_cgroup_file_str(&write.path, &write.value) {
+                            failures.push(WriteFailure::Write {
+                                controller: controller.clone(),
+                                path: write.path,
+                                value: write.value,
+                                err: into_io_error(err),
+                            });
+                        }
+                    }
+                }
+                Step::Unplanned { controller, apply } => {
+                    if let Err(err) = apply() {
+                        failures.push(WriteFailure::Controller { controller, err });
+                    }
+                }
+            }
+        }
+
+        let best_effort = self.best_effort;
+        failures.retain(|failure| {
+            if !best_effort.iter().any(|c| c == failure.controller()) {
+                return true;
+            }
+            tracing::warn!("ignoring failure of best effort controller: {}", failure);
+            false
+        });
+        if failures.is_empty() {
+            return Ok(());
+        }
+
+        for failure in &failures {
+            tracing::error!("{}", failure);
+        }
+        Err(BatchError { failures })
+    }
+}
+
+fn into_io_error(err: WrappedIoError) -> io::Error {
+    match err {
+        WrappedIoError::Open { err, .. }
+        | WrappedIoError::Write { err, .. }
+        | WrappedIoError::Read { err, .. }
+        | WrappedIoError::CreateDir { err, .. }
+        | WrappedIoError::Other { err, .. } => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::test::set_fixture;
+
+    #[test]
+    fn test_plan_and_apply() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        set_fixture(tmp.path(), "cpu.weight", "")?;
+        set_fixture(tmp.path(), "pids.max", "")?;
+
+        let mut batch = WriteBatch::new(&[]);
+        batch.plan("cpu", || {
+            common::write_cgroup_file(tmp.path().join("cpu.weight"), 100)
+        })?;
+        batch.plan("pids", || {
+            common::write_cgroup_file_str(tmp.path().join("pids.max"), "max")
+        })?;
+        // nothing is written until the batch is applied
+        assert_eq!(fs::read_to_string(tmp.path().join("cpu.weight"))?, "");
+
+        batch.apply()?;
+        assert_eq!(fs::read_to_string(tmp.path().join("cpu.weight"))?, "100");
+        assert_eq!(fs::read_to_string(tmp.path().join("pids.max"))?, "max");
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_resources() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        set_fixture(tmp.path(), "cpu.weight", "")?;
+
+        let mut batch = WriteBatch::new(&[]);
+        batch.plan("cpu", || {
+            common::write_cgroup_file(tmp.path().join("cpu.weight"), 100)
+        })?;
+        let result = batch.plan("memory", || {
+            common::write_cgroup_file(tmp.path().join("memory.max"), 1)?;
+            Err(WrappedIoError::Other {
+                err: io::Error::from(io::ErrorKind::InvalidInput),
+                path: tmp.path().to_owned(),
+            })
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(tmp.path().join("cpu.weight"))?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregated_failures() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        set_fixture(tmp.path(), "pids.max", "")?;
+        let best_effort = vec!["hugetlb".to_owned()];
+
+        let mut batch = WriteBatch::new(&best_effort);
+        batch.plan("cpu", || {
+            common::write_cgroup_file(tmp.path().join("cpu.weight"), 100)?;
+            common::write_cgroup_file(tmp.path().join("cpu.max"), "max 100000")
+        })?;
+        batch.plan("pids", || {
+            common::write_cgroup_file(tmp.path().join("pids.max"), 10)
+        })?;
+        batch.plan("hugetlb", || {
+            common::write_cgroup_file(tmp.path().join("hugetlb.2MB.max"), 0)
+        })?;
+        batch.unplanned("memory", || {
+            common::write_cgroup_file(tmp.path().join("memory.max"), 1)
+        });
+
+        let err = batch.apply().unwrap_err();
+        // the writes after a failed one are still made
+        assert_eq!(fs::read_to_string(tmp.path().join("pids.max"))?, "10");
+        let failed: Vec<_> = err
+            .failures
+            .iter()
+            .map(|failure| failure.controller())
+            .collect();
+        assert_eq!(failed, ["cpu", "cpu", "memory"]);
+        let message = err.to_string();
+        assert!(message.contains("cpu.weight"), "{message}");
+        assert!(message.contains("\"max 100000\""), "{message}");
+        assert!(message.contains("memory.max"), "{message}");
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+//! Recording of the cgroup files written while applying resources, so that
+//! the effective configuration of a cgroup can be stored and re-applied later
+//! independent of the spec, e.g. after the processes have been restored on a
+//! rebooted host. Only writes to the cgroupfs are recorded, the properties
+//! of systemd units are persisted by systemd itself.
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::{self, WrapIoResult, WrappedIoError};
+
+// files which move processes or change their state instead of configuring
+// the cgroup
+const UNRECORDED: &[&str] = &[
+    "cgroup.procs",
+    "cgroup.threads",
+    "tasks",
+    "cgroup.kill",
+    "cgroup.freeze",
+    "freezer.state",
+    "memory.reclaim",
+];
+
+thread_local! {
+    static RECORDING: RefCell<Option<Vec<CgroupWrite>>> = RefCell::new(None);
+}
+
+/// Value written to a cgroup file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CgroupWrite {
+    pub path: PathBuf,
+    pub value: String,
+}
+
+/// Effective configuration of a cgroup as the files written to configure it,
+/// in the order they were written
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CgroupCheckpoint {
+    pub writes: Vec<CgroupWrite>,
+}
+
+impl CgroupCheckpoint {
+    /// Runs `f`, e.g. applying the resources with a cgroup manager, and
+    /// returns its result together with the cgroup files it has written on
+    /// the current thread
+    pub fn record<T>(f: impl FnOnce() -> T) -> (T, Self) {
+        let previous = RECORDING.with(|recording| recording.replace(Some(Vec::new())));
+        let result = f();
+        let writes = RECORDING
+            .with(|recording| recording.replace(previous))
+            .unwrap_or_default();
+
+        (result, Self { writes })
+    }
+
+    /// Adds the writes of a later update, which take precedence as the writes
+    /// are replayed in order. Earlier identical writes are dropped, so that
+    /// repeated updates don't let the checkpoint grow.
+    pub fn extend(&mut self, later: Self) {
+        self.writes.retain(|write| !later.writes.contains(write));
+        self.writes.extend(later.writes);
+    }
+
+    /// Writes the recorded values again, in the order they were written
+    /// originally. Cgroups which don't exist anymore are created.
+    pub fn replay(&self) -> Result<(), WrappedIoError> {
+        for write in &self.writes {
+            if let Some(cgroup) = write.path.parent() {
+                if !cgroup.exists() {
+                    fs::create_dir_all(cgroup).wrap_create_dir(cgroup)?;
+                }
+            }
+            common::write_cgroup_file_str(&write.path, &write.value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Records a successful write to a cgroup file, if writes are recorded
+pub(crate) fn record_write(path: &Path, value: &str) {
+    let recorded = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| !UNRECORDED.contains(&name));
+    if !recorded {
+        return;
+    }
+
+    RECORDING.with(|recording| {
+        if let Some(writes) = recording.borrow_mut().as_mut() {
+            writes.push(CgroupWrite {
+                path: path.to_owned(),
+                value: value.to_owned(),
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_record() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        for file in ["cpu.weight", "cgroup.procs"] {
+            fs::write(tmp.path().join(file), "")?;
+        }
+
+        let (result, checkpoint) = CgroupCheckpoint::record(|| {
+            common::write_cgroup_file(tmp.path().join("cpu.weight"), 100)?;
+            common::write_cgroup_file(tmp.path().join("cgroup.procs"), 42)
+        });
+        result?;
+        assert_eq!(
+            checkpoint.writes,
+            vec![CgroupWrite {
+                path: tmp.path().join("cpu.weight"),
+                value: "100".to_owned(),
+            }]
+        );
+
+        // nothing is recorded outside of record
+        common::write_cgroup_file(tmp.path().join("cpu.weight"), 200)?;
+        let ((), empty) = CgroupCheckpoint::record(|| ());
+        assert!(empty.writes.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_extend() {
+        let write = |path: &str, value: &str| CgroupWrite {
+            path: PathBuf::from(path),
+            value: value.to_owned(),
+        };
+        let mut checkpoint = CgroupCheckpoint {
+            writes: vec![write("cpu.max", "max 100000"), write("pids.max", "10")],
+        };
+        checkpoint.extend(CgroupCheckpoint {
+            writes: vec![write("pids.max", "20"), write("cpu.max", "max 100000")],
+        });
+        assert_eq!(
+            checkpoint.writes,
+            vec![
+                write("pids.max", "10"),
+                write("pids.max", "20"),
+                write("cpu.max", "max 100000"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replay() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let existing = tmp.path().join("pids.max");
+        fs::write(&existing, "")?;
+        let checkpoint = CgroupCheckpoint {
+            writes: vec![CgroupWrite {
+                path: existing.clone(),
+                value: "16".to_owned(),
+            }],
+        };
+        checkpoint.replay()?;
+        assert_eq!(fs::read_to_string(&existing)?, "16");
+
+        // the files of a recreated cgroup are created by the kernel, but
+        // not in a temporary directory
+        let missing = CgroupCheckpoint {
+            writes: vec![CgroupWrite {
+                path: tmp.path().join("gone/pids.max"),
+                value: "16".to_owned(),
+            }],
+        };
+        assert!(matches!(missing.replay(), Err(WrappedIoError::Open { .. })));
+        assert!(tmp.path().join("gone").is_dir());
+        Ok(())
+    }
+}
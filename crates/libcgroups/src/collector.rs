@@ -0,0 +1,351 @@
+//! Long-lived collection of cgroup statistics for monitoring use cases.
+//! The stat files of the cgroup are opened once and re-read on every sample,
+//! and rates like the cpu usage per second are computed from consecutive samples.
+//! Only cgroup v2 is supported.
+use std::fs::File;
+use std::io::ErrorKind;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::common::{WrapIoResult, WrappedIoError};
+
+const CPU_STAT: &str = "cpu.stat";
+const IO_STAT: &str = "io.stat";
+const MEMORY_CURRENT: &str = "memory.current";
+const PIDS_CURRENT: &str = "pids.current";
+
+#[derive(thiserror::Error, Debug)]
+pub enum StatsCollectorError {
+    #[error("io error: {0}")]
+    WrappedIo(#[from] WrappedIoError),
+    #[error("missing field {field} from {path}")]
+    MissingField { field: &'static str, path: PathBuf },
+    #[error("failed to parse {value} from {path}")]
+    Parse { value: String, path: PathBuf },
+}
+
+type Result<T> = std::result::Result<T, StatsCollectorError>;
+
+/// A single sample of the counters of a cgroup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Sample {
+    /// Cpu time consumed by tasks in total, in microseconds
+    pub cpu_usage_usec: u64,
+    /// Bytes read from all block devices, if the io controller is enabled
+    pub io_read_bytes: Option<u64>,
+    /// Bytes written to all block devices, if the io controller is enabled
+    pub io_write_bytes: Option<u64>,
+    /// Current memory usage in bytes, if the memory controller is enabled
+    pub memory_usage: Option<u64>,
+    /// Current number of pids, if the pids controller is enabled
+    pub pids: Option<u64>,
+}
+
+/// Rates computed from two consecutive samples
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Rates {
+    /// Time elapsed between the two samples
+    pub interval: Duration,
+    /// Cpu time consumed per second, i.e. 2.0 means two fully used cpus
+    pub cpu_usage: f64,
+    /// Bytes read per second from all block devices
+    pub io_read_bytes_per_sec: Option<f64>,
+    /// Bytes written per second to all block devices
+    pub io_write_bytes_per_sec: Option<f64>,
+}
+
+/// Result of a collection
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CollectedStats {
+    pub sample: Sample,
+    /// Rates since the previous collection, not available for the first collection
+    pub rates: Option<Rates>,
+}
+
+struct StatFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl StatFile {
+    fn open(cgroup_path: &Path, name: &str) -> Result<Self> {
+        let path = cgroup_path.join(name);
+        let file = File::open(&path).wrap_open(&path)?;
+        Ok(Self { file, path })
+    }
+
+    /// Opens the stat file of a controller, which does not exist if the
+    /// controller is not enabled for the cgroup
+    fn open_optional(cgroup_path: &Path, name: &str) -> Result<Option<Self>> {
+        match Self::open(cgroup_path, name) {
+            Ok(stat_file) => Ok(Some(stat_file)),
+            Err(StatsCollectorError::WrappedIo(err))
+                if err.inner().kind() == ErrorKind::NotFound =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Reads the current content of the file from the start
+    fn read(&self) -> Result<String> {
+        let mut content = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let n = self
+                .file
+                .read_at(&mut buffer, content.len() as u64)
+                .wrap_read(&self.path)?;
+            if n == 0 {
+                break;
+            }
+            content.extend_from_slice(&buffer[..n]);
+        }
+
+        String::from_utf8(content).map_err(|err| StatsCollectorError::Parse {
+            value: String::from_utf8_lossy(err.as_bytes()).into_owned(),
+            path: self.path.clone(),
+        })
+    }
+
+    fn read_single_value(&self) -> Result<u64> {
+        let content = self.read()?;
+        self.parse(content.trim())
+    }
+
+    fn parse(&self, value: &str) -> Result<u64> {
+        value.parse().map_err(|_| StatsCollectorError::Parse {
+            value: value.to_owned(),
+            path: self.path.clone(),
+        })
+    }
+}
+
+/// Collects the statistics of a cgroup repeatedly. The collector is meant to be
+/// kept alive by an embedding process, e.g. a shim, for as long as the cgroup
+/// is monitored.
+/// # Example
+/// ```no_run
+/// use std::path::Path;
+/// use libcgroups::collector::StatsCollector;
+///
+/// let mut collector = StatsCollector::new(Path::new("/sys/fs/cgroup/mycontainer")).unwrap();
+/// loop {
+///     let stats = collector.collect().unwrap();
+///     if let Some(rates) = stats.rates {
+///         println!("cpu usage: {}", rates.cpu_usage);
+///     }
+///     std::thread::sleep(std::time::Duration::from_secs(1));
+/// }
+/// ```
+pub struct StatsCollector {
+    cpu_stat: StatFile,
+    io_stat: Option<StatFile>,
+    memory_current: Option<StatFile>,
+    pids_current: Option<StatFile>,
+    previous: Option<(Instant, Sample)>,
+}
+
+impl StatsCollector {
+    /// Opens the stat files of the cgroup at the given path of the unified hierarchy
+    pub fn new(cgroup_path: &Path) -> Result<Self> {
+        Ok(Self {
+            cpu_stat: StatFile::open(cgroup_path, CPU_STAT)?,
+            io_stat: StatFile::open_optional(cgroup_path, IO_STAT)?,
+            memory_current: StatFile::open_optional(cgroup_path, MEMORY_CURRENT)?,
+            pids_current: StatFile::open_optional(cgroup_path, PIDS_CURRENT)?,
+            previous: None,
+        })
+    }
+
+    /// Samples the counters of the cgroup and computes the rates since the previous collection
+    pub fn collect(&mut self) -> Result<CollectedStats> {
+        self.collect_at(Instant::now())
+    }
+
+    fn collect_at(&mut self, now: Instant) -> Result<CollectedStats> {
+        let sample = self.sample()?;
+        let rates = self.previous.as_ref().and_then(|(then, previous)| {
+            rates(now.checked_duration_since(*then)?, previous, &sample)
+        });
+        self.previous = Some((now, sample.clone()));
+
+        Ok(CollectedStats { sample, rates })
+    }
+
+    fn sample(&self) -> Result<Sample> {
+        let cpu_usage_usec = self.cpu_usage()?;
+        let (io_read_bytes, io_write_bytes) = match &self.io_stat {
+            Some(io_stat) => {
+                let (read, write) = io_bytes(io_stat)?;
+                (Some(read), Some(write))
+            }
+            None => (None, None),
+        };
+        let memory_usage = self
+            .memory_current
+            .as_ref()
+            .map(StatFile::read_single_value)
+            .transpose()?;
+        let pids = self
+            .pids_current
+            .as_ref()
+            .map(StatFile::read_single_value)
+            .transpose()?;
+
+        Ok(Sample {
+            cpu_usage_usec,
+            io_read_bytes,
+            io_write_bytes,
+            memory_usage,
+            pids,
+        })
+    }
+
+    fn cpu_usage(&self) -> Result<u64> {
+        const USAGE_USEC: &str = "usage_usec";
+
+        let content = self.cpu_stat.read()?;
+        let value = content
+            .lines()
+            .find_map(|line| line.strip_prefix(USAGE_USEC)?.strip_prefix(' '))
+            .ok_or_else(|| StatsCollectorError::MissingField {
+                field: USAGE_USEC,
+                path: self.cpu_stat.path.clone(),
+            })?;
+        self.cpu_stat.parse(value.trim())
+    }
+}
+
+/// Sums up the bytes read and written over all devices listed in io.stat
+fn io_bytes(io_stat: &StatFile) -> Result<(u64, u64)> {
+    let content = io_stat.read()?;
+    let mut read = 0;
+    let mut write = 0;
+    // every line has the format "8:0 rbytes=90430464 wbytes=299008000 rios=8950 ..."
+    for field in content
+        .lines()
+        .flat_map(|line| line.split_whitespace().skip(1))
+    {
+        if let Some(value) = field.strip_prefix("rbytes=") {
+            read += io_stat.parse(value)?;
+        } else if let Some(value) = field.strip_prefix("wbytes=") {
+            write += io_stat.parse(value)?;
+        }
+    }
+
+    Ok((read, write))
+}
+
+fn rates(interval: Duration, previous: &Sample, current: &Sample) -> Option<Rates> {
+    let seconds = interval.as_secs_f64();
+    if seconds <= 0.0 {
+        return None;
+    }
+
+    // counters are reset if the cgroup has been recreated, which
+    // must not be reported as a huge rate
+    let per_second =
+        |previous: u64, current: u64| current.saturating_sub(previous) as f64 / seconds;
+    let per_second_optional =
+        |previous: Option<u64>, current: Option<u64>| Some(per_second(previous?, current?));
+
+    Some(Rates {
+        interval,
+        cpu_usage: per_second(previous.cpu_usage_usec, current.cpu_usage_usec) / 1_000_000.0,
+        io_read_bytes_per_sec: per_second_optional(previous.io_read_bytes, current.io_read_bytes),
+        io_write_bytes_per_sec: per_second_optional(
+            previous.io_write_bytes,
+            current.io_write_bytes,
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::set_fixture;
+
+    fn cpu_stat(usage_usec: u64) -> String {
+        format!("usage_usec {usage_usec}\nuser_usec 100\nsystem_usec 100\n")
+    }
+
+    #[test]
+    fn test_collect() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        set_fixture(tmp.path(), CPU_STAT, &cpu_stat(1_000_000))?;
+        set_fixture(
+            tmp.path(),
+            IO_STAT,
+            "8:0 rbytes=1000 wbytes=2000 rios=1 wios=2\n8:16 rbytes=1000 wbytes=0 rios=1 wios=0\n",
+        )?;
+        set_fixture(tmp.path(), MEMORY_CURRENT, "4096\n")?;
+
+        let mut collector = StatsCollector::new(tmp.path())?;
+        let start = Instant::now();
+        let first = collector.collect_at(start)?;
+        assert_eq!(
+            first.sample,
+            Sample {
+                cpu_usage_usec: 1_000_000,
+                io_read_bytes: Some(2000),
+                io_write_bytes: Some(2000),
+                memory_usage: Some(4096),
+                pids: None,
+            }
+        );
+        assert_eq!(first.rates, None);
+
+        // the files stay open, so the new content is read through the same descriptors
+        set_fixture(tmp.path(), CPU_STAT, &cpu_stat(4_000_000))?;
+        set_fixture(
+            tmp.path(),
+            IO_STAT,
+            "8:0 rbytes=3000 wbytes=6000 rios=2 wios=3\n8:16 rbytes=1000 wbytes=0 rios=1 wios=0\n",
+        )?;
+        let second = collector.collect_at(start + Duration::from_secs(2))?;
+        let rates = second.rates.unwrap();
+        assert_eq!(rates.interval, Duration::from_secs(2));
+        assert_eq!(rates.cpu_usage, 1.5);
+        assert_eq!(rates.io_read_bytes_per_sec, Some(1000.0));
+        assert_eq!(rates.io_write_bytes_per_sec, Some(2000.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_counter_reset() {
+        let previous = Sample {
+            cpu_usage_usec: 5_000_000,
+            io_read_bytes: None,
+            io_write_bytes: None,
+            memory_usage: None,
+            pids: None,
+        };
+        let current = Sample {
+            cpu_usage_usec: 1_000,
+            ..previous.clone()
+        };
+
+        let rates = rates(Duration::from_secs(1), &previous, &current).unwrap();
+        assert_eq!(rates.cpu_usage, 0.0);
+        assert_eq!(rates.io_read_bytes_per_sec, None);
+    }
+
+    #[test]
+    fn test_missing_cpu_usage() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        set_fixture(tmp.path(), CPU_STAT, "user_usec 100\n")?;
+
+        let mut collector = StatsCollector::new(tmp.path())?;
+        assert!(matches!(
+            collector.collect(),
+            Err(StatsCollectorError::MissingField { .. })
+        ));
+        Ok(())
+    }
+}
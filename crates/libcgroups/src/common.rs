@@ -1,22 +1,28 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::{Debug, Display};
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf, StripPrefixError};
+use std::str::FromStr;
 use std::time::Duration;
 
 use nix::sys::statfs::{statfs, CGROUP2_SUPER_MAGIC, TMPFS_MAGIC};
-use nix::unistd::Pid;
-use oci_spec::runtime::LinuxResources;
-#[cfg(any(feature = "cgroupsv2_devices", feature = "v1"))]
+use nix::unistd::{Gid, Pid, Uid};
+#[cfg(any(feature = "cgroupsv2_devices", feature = "v1", feature = "systemd"))]
 use oci_spec::runtime::{
     LinuxDevice, LinuxDeviceBuilder, LinuxDeviceCgroup, LinuxDeviceCgroupBuilder, LinuxDeviceType,
 };
+use oci_spec::runtime::{LinuxRdma, LinuxResources};
 
+use super::events::CgroupEventsWatcher;
+use super::oom::OomNotifier;
 use super::stats::Stats;
-use super::{systemd, v1, v2};
+use super::{batch, checkpoint, hybrid, stats, systemd, v1, v2};
 
 pub const CGROUP_PROCS: &str = "cgroup.procs";
 pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const CGROUP_CONTROLLERS: &str = "cgroup.controllers";
 
 #[cfg(feature = "systemd")]
 #[inline]
@@ -37,6 +43,14 @@ pub trait CgroupManager {
     /// Adds a task specified by its pid to the cgroup
     fn add_task(&self, pid: Pid) -> Result<(), Self::Error>;
 
+    /// Creates the cgroup and opens the directory of the cgroup tasks are
+    /// added to, so that a process can be spawned directly into it with
+    /// CLONE_INTO_CGROUP instead of being added with add_task. Returns None if
+    /// tasks have to be added with add_task, which is the case for cgroup v1,
+    /// where a task joins a cgroup per controller, and for systemd, which has
+    /// to be told about the task.
+    fn open_task_cgroup(&self) -> Result<Option<File>, Self::Error>;
+
     /// Applies resource restrictions to the cgroup
     fn apply(&self, controller_opt: &ControllerOpt) -> Result<(), Self::Error>;
 
@@ -51,6 +65,58 @@ pub trait CgroupManager {
 
     /// Gets the PIDs inside the cgroup
     fn get_all_pids(&self) -> Result<Vec<Pid>, Self::Error>;
+
+    /// Registers for notifications about out of memory events in the cgroup
+    fn oom_notifier(&self) -> Result<OomNotifier, Self::Error>;
+
+    /// Registers for notifications about the cgroup running out of processes
+    /// or being frozen, which is only supported by cgroup v2
+    fn events_watcher(&self) -> Result<CgroupEventsWatcher, Self::Error>;
+
+    /// Kills all processes of the cgroup and its descendants with SIGKILL at
+    /// once, so that processes forking in the meantime can't escape. Returns
+    /// false if this is not supported, which is the case for cgroup v1 and
+    /// for kernels older than 5.14.
+    fn kill_all(&self) -> Result<bool, Self::Error>;
+
+    /// Makes the kernel reclaim the given number of bytes of the memory of
+    /// the cgroup, e.g. to shrink idle containers. Returns false if this is
+    /// not supported, which is the case for cgroup v1 and for kernels older
+    /// than 5.19. Fails if less memory could be reclaimed.
+    fn reclaim_memory(&self, bytes: u64) -> Result<bool, Self::Error>;
+
+    /// Hands the cgroup over to the container, e.g. for a nested systemd or
+    /// container runtime: the cgroup and the files which the kernel allows to
+    /// delegate are chowned to the given owner, and the given controllers are
+    /// enabled for the children of the cgroup, which requires the processes
+    /// to be in a leaf cgroup. Returns false if this is not supported, which
+    /// is the case for cgroup v1.
+    fn delegate(&self, owner: Uid, group: Gid, controllers: &[String])
+        -> Result<bool, Self::Error>;
+}
+
+/// Category of an error of a cgroup manager, which is the same for all
+/// managers, so that callers can react to errors without matching the
+/// variants of each of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CgroupErrorKind {
+    /// A controller required for the request is not available for the cgroup
+    ControllerNotFound,
+    /// The version of systemd is too old for the requested resources
+    SystemdVersionTooOld,
+    /// The cgroups path or the slice is invalid
+    InvalidPath,
+    /// The resources could not be applied to a controller
+    Controller,
+    /// The statistics of a controller could not be read
+    Stats,
+    /// The communication with systemd has failed
+    Systemd,
+    /// The request is not supported by the cgroup setup of the host or by
+    /// the features libcgroups has been built with
+    NotSupported,
+    /// The cgroup filesystem could not be accessed
+    Io,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -61,13 +127,27 @@ pub enum AnyManagerError {
     V1(#[from] v1::manager::V1ManagerError),
     #[error(transparent)]
     V2(#[from] v2::manager::V2ManagerError),
+    #[error(transparent)]
+    Hybrid(#[from] hybrid::manager::HybridManagerError),
 }
 
-// systemd is boxed due to size lint https://rust-lang.github.io/rust-clippy/master/index.html#/large_enum_variant
+impl AnyManagerError {
+    pub fn kind(&self) -> CgroupErrorKind {
+        match self {
+            AnyManagerError::Systemd(err) => err.kind(),
+            AnyManagerError::V1(err) => err.kind(),
+            AnyManagerError::V2(err) => err.kind(),
+            AnyManagerError::Hybrid(err) => err.kind(),
+        }
+    }
+}
+
+// systemd and hybrid are boxed due to size lint https://rust-lang.github.io/rust-clippy/master/index.html#/large_enum_variant
 pub enum AnyCgroupManager {
     Systemd(Box<systemd::manager::Manager>),
     V1(v1::manager::Manager),
     V2(v2::manager::Manager),
+    Hybrid(Box<hybrid::manager::Manager>),
 }
 
 impl CgroupManager for AnyCgroupManager {
@@ -78,6 +158,16 @@ impl CgroupManager for AnyCgroupManager {
             AnyCgroupManager::Systemd(m) => Ok(m.add_task(pid)?),
             AnyCgroupManager::V1(m) => Ok(m.add_task(pid)?),
             AnyCgroupManager::V2(m) => Ok(m.add_task(pid)?),
+            AnyCgroupManager::Hybrid(m) => Ok(m.add_task(pid)?),
+        }
+    }
+
+    fn open_task_cgroup(&self) -> Result<Option<File>, Self::Error> {
+        match self {
+            AnyCgroupManager::Systemd(m) => Ok(m.open_task_cgroup()?),
+            AnyCgroupManager::V1(m) => Ok(m.open_task_cgroup()?),
+            AnyCgroupManager::V2(m) => Ok(m.open_task_cgroup()?),
+            AnyCgroupManager::Hybrid(m) => Ok(m.open_task_cgroup()?),
         }
     }
 
@@ -86,6 +176,7 @@ impl CgroupManager for AnyCgroupManager {
             AnyCgroupManager::Systemd(m) => Ok(m.apply(controller_opt)?),
             AnyCgroupManager::V1(m) => Ok(m.apply(controller_opt)?),
             AnyCgroupManager::V2(m) => Ok(m.apply(controller_opt)?),
+            AnyCgroupManager::Hybrid(m) => Ok(m.apply(controller_opt)?),
         }
     }
 
@@ -94,6 +185,7 @@ impl CgroupManager for AnyCgroupManager {
             AnyCgroupManager::Systemd(m) => Ok(m.remove()?),
             AnyCgroupManager::V1(m) => Ok(m.remove()?),
             AnyCgroupManager::V2(m) => Ok(m.remove()?),
+            AnyCgroupManager::Hybrid(m) => Ok(m.remove()?),
         }
     }
 
@@ -102,6 +194,7 @@ impl CgroupManager for AnyCgroupManager {
             AnyCgroupManager::Systemd(m) => Ok(m.freeze(state)?),
             AnyCgroupManager::V1(m) => Ok(m.freeze(state)?),
             AnyCgroupManager::V2(m) => Ok(m.freeze(state)?),
+            AnyCgroupManager::Hybrid(m) => Ok(m.freeze(state)?),
         }
     }
 
@@ -110,6 +203,7 @@ impl CgroupManager for AnyCgroupManager {
             AnyCgroupManager::Systemd(m) => Ok(m.stats()?),
             AnyCgroupManager::V1(m) => Ok(m.stats()?),
             AnyCgroupManager::V2(m) => Ok(m.stats()?),
+            AnyCgroupManager::Hybrid(m) => Ok(m.stats()?),
         }
     }
 
@@ -118,6 +212,57 @@ impl CgroupManager for AnyCgroupManager {
             AnyCgroupManager::Systemd(m) => Ok(m.get_all_pids()?),
             AnyCgroupManager::V1(m) => Ok(m.get_all_pids()?),
             AnyCgroupManager::V2(m) => Ok(m.get_all_pids()?),
+            AnyCgroupManager::Hybrid(m) => Ok(m.get_all_pids()?),
+        }
+    }
+
+    fn oom_notifier(&self) -> Result<OomNotifier, Self::Error> {
+        match self {
+            AnyCgroupManager::Systemd(m) => Ok(m.oom_notifier()?),
+            AnyCgroupManager::V1(m) => Ok(m.oom_notifier()?),
+            AnyCgroupManager::V2(m) => Ok(m.oom_notifier()?),
+            AnyCgroupManager::Hybrid(m) => Ok(m.oom_notifier()?),
+        }
+    }
+
+    fn events_watcher(&self) -> Result<CgroupEventsWatcher, Self::Error> {
+        match self {
+            AnyCgroupManager::Systemd(m) => Ok(m.events_watcher()?),
+            AnyCgroupManager::V1(m) => Ok(m.events_watcher()?),
+            AnyCgroupManager::V2(m) => Ok(m.events_watcher()?),
+            AnyCgroupManager::Hybrid(m) => Ok(m.events_watcher()?),
+        }
+    }
+
+    fn kill_all(&self) -> Result<bool, Self::Error> {
+        match self {
+            AnyCgroupManager::Systemd(m) => Ok(m.kill_all()?),
+            AnyCgroupManager::V1(m) => Ok(m.kill_all()?),
+            AnyCgroupManager::V2(m) => Ok(m.kill_all()?),
+            AnyCgroupManager::Hybrid(m) => Ok(m.kill_all()?),
+        }
+    }
+
+    fn reclaim_memory(&self, bytes: u64) -> Result<bool, Self::Error> {
+        match self {
+            AnyCgroupManager::Systemd(m) => Ok(m.reclaim_memory(bytes)?),
+            AnyCgroupManager::V1(m) => Ok(m.reclaim_memory(bytes)?),
+            AnyCgroupManager::V2(m) => Ok(m.reclaim_memory(bytes)?),
+            AnyCgroupManager::Hybrid(m) => Ok(m.reclaim_memory(bytes)?),
+        }
+    }
+
+    fn delegate(
+        &self,
+        owner: Uid,
+        group: Gid,
+        controllers: &[String],
+    ) -> Result<bool, Self::Error> {
+        match self {
+            AnyCgroupManager::Systemd(m) => Ok(m.delegate(owner, group, controllers)?),
+            AnyCgroupManager::V1(m) => Ok(m.delegate(owner, group, controllers)?),
+            AnyCgroupManager::V2(m) => Ok(m.delegate(owner, group, controllers)?),
+            AnyCgroupManager::Hybrid(m) => Ok(m.delegate(owner, group, controllers)?),
         }
     }
 }
@@ -198,6 +343,9 @@ impl WrappedIoError {
 #[inline]
 pub fn write_cgroup_file_str<P: AsRef<Path>>(path: P, data: &str) -> Result<(), WrappedIoError> {
     let path = path.as_ref();
+    if batch::plan_write(path, data) {
+        return Ok(());
+    }
 
     fs::OpenOptions::new()
         .create(false)
@@ -214,10 +362,59 @@ pub fn write_cgroup_file_str<P: AsRef<Path>>(path: P, data: &str) -> Result<(),
             path: path.to_path_buf(),
             data: data.into(),
         })?;
+    checkpoint::record_write(path, data);
+
+    Ok(())
+}
+
+/// Writes each line of `data` separately, as keyed files like io.max or
+/// misc.max only accept a single entry per write
+pub fn write_cgroup_file_entries<P: AsRef<Path>>(
+    path: P,
+    data: &str,
+) -> Result<(), WrappedIoError> {
+    let path = path.as_ref();
+    if !data.contains('\n') {
+        return write_cgroup_file_str(path, data);
+    }
+
+    for entry in data.lines().filter(|entry| !entry.trim().is_empty()) {
+        write_cgroup_file_str(path, entry)?;
+    }
 
     Ok(())
 }
 
+/// Writes the rdma limits of the spec to rdma.max, with one write per device
+/// as the kernel only parses a single entry per write. The file format is the
+/// same for cgroup v1 and v2.
+pub(crate) fn write_rdma_limits(
+    cgroup_root: &Path,
+    rdma: &HashMap<String, LinuxRdma>,
+) -> Result<(), WrappedIoError> {
+    for (device, limits) in rdma {
+        write_cgroup_file_str(
+            cgroup_root.join("rdma.max"),
+            &rdma_limit_entry(device, limits),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Formats the limits of an rdma device as expected by rdma.max. Resources
+/// without a limit in the spec are left untouched by the kernel.
+fn rdma_limit_entry(device: &str, rdma: &LinuxRdma) -> String {
+    let mut entry = device.to_owned();
+    if let Some(hca_handles) = rdma.hca_handles() {
+        entry.push_str(&format!(" hca_handle={hca_handles}"));
+    }
+    if let Some(hca_objects) = rdma.hca_objects() {
+        entry.push_str(&format!(" hca_object={hca_objects}"));
+    }
+    entry
+}
+
 #[inline]
 pub fn write_cgroup_file<P: AsRef<Path>, T: ToString>(
     path: P,
@@ -225,6 +422,9 @@ pub fn write_cgroup_file<P: AsRef<Path>, T: ToString>(
 ) -> Result<(), WrappedIoError> {
     let path = path.as_ref();
     let data = data.to_string();
+    if batch::plan_write(path, &data) {
+        return Ok(());
+    }
 
     fs::OpenOptions::new()
         .create(false)
@@ -239,8 +439,9 @@ pub fn write_cgroup_file<P: AsRef<Path>, T: ToString>(
         .map_err(|err| WrappedIoError::Write {
             err,
             path: path.to_path_buf(),
-            data,
+            data: data.clone(),
         })?;
+    checkpoint::record_write(path, &data);
 
     Ok(())
 }
@@ -248,6 +449,10 @@ pub fn write_cgroup_file<P: AsRef<Path>, T: ToString>(
 #[inline]
 pub fn read_cgroup_file<P: AsRef<Path>>(path: P) -> Result<String, WrappedIoError> {
     let path = path.as_ref();
+    if let Some(content) = stats::prefetched(path) {
+        return Ok(content);
+    }
+
     fs::read_to_string(path).map_err(|err| WrappedIoError::Read {
         err,
         path: path.to_path_buf(),
@@ -269,9 +474,9 @@ pub enum GetCgroupSetupError {
 /// - Unified: Pure cgroup v2 system.
 /// - Legacy: Pure cgroup v1 system.
 /// - Hybrid: Hybrid is basically a cgroup v1 system, except for
-///   an additional unified hierarchy which doesn't have any
-///   controllers attached. Resource control can purely be achieved
-///   through the cgroup v1 hierarchy, not through the cgroup v2 hierarchy.
+///   an additional unified hierarchy, which usually doesn't have any
+///   controllers attached. Controllers that are attached to it can
+///   only be managed through the cgroup v2 hierarchy.
 pub fn get_cgroup_setup_with_root(root_path: &Path) -> Result<CgroupSetup, GetCgroupSetupError> {
     match root_path.exists() {
         true => {
@@ -310,6 +515,113 @@ pub fn get_cgroup_setup() -> Result<CgroupSetup, GetCgroupSetupError> {
     get_cgroup_setup_with_root(Path::new(DEFAULT_CGROUP_ROOT))
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum AvailableControllersError {
+    #[error("io error: {0}")]
+    WrappedIo(#[from] WrappedIoError),
+    #[error("failed to read mountinfo: {0}")]
+    MountInfo(#[from] procfs::ProcError),
+}
+
+/// Reports the controllers which can be used by the cgroup at `cgroup_path`,
+/// which is relative to the root of the cgroup hierarchy and doesn't need to
+/// exist yet.
+///
+/// For cgroup v1 these are the controllers that are mounted. For cgroup v2
+/// these are the controllers delegated to the cgroup, i.e. the ones listed
+/// in cgroup.controllers of the cgroup or of its closest existing ancestor.
+/// A hybrid setup reports both.
+pub fn available_controllers(
+    cgroup_path: &Path,
+) -> Result<BTreeSet<String>, AvailableControllersError> {
+    let known = kernel_controllers()?;
+    let mut controllers = BTreeSet::new();
+    for mount in procfs::process::Process::myself()?.mountinfo()? {
+        match mount.fs_type.as_str() {
+            "cgroup" => controllers.extend(
+                mount
+                    .super_options
+                    .keys()
+                    .filter(|option| known.contains(option.as_str()))
+                    .cloned(),
+            ),
+            "cgroup2" => {
+                controllers.extend(delegated_controllers(&mount.mount_point, cgroup_path)?)
+            }
+            _ => {}
+        }
+    }
+
+    Ok(controllers)
+}
+
+/// Names of all controllers the kernel has been built with and which have
+/// not been disabled on the command line
+fn kernel_controllers() -> Result<HashSet<String>, WrappedIoError> {
+    let path = Path::new("/proc/cgroups");
+    let content = fs::read_to_string(path).wrap_read(path)?;
+    Ok(content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [name, _, _, "1"] => Some(name.to_string()),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+fn delegated_controllers(
+    mount_point: &Path,
+    cgroup_path: &Path,
+) -> Result<BTreeSet<String>, WrappedIoError> {
+    let mut path = mount_point.join(cgroup_path.strip_prefix("/").unwrap_or(cgroup_path));
+    while !path.join(CGROUP_CONTROLLERS).exists() {
+        if path == mount_point || !path.pop() {
+            return Ok(BTreeSet::new());
+        }
+    }
+
+    Ok(read_cgroup_file(path.join(CGROUP_CONTROLLERS))?
+        .split_whitespace()
+        .map(String::from)
+        .collect())
+}
+
+/// Inode number of the initial cgroup namespace, see PROC_CGROUP_INIT_INO in
+/// include/linux/proc_ns.h
+const CGROUP_NS_INIT_INO: u64 = 0xEFFFFFFB;
+
+/// Checks if the current process runs in a cgroup namespace other than the
+/// initial one, in which case cgroup paths are relative to the namespace root
+pub fn in_cgroup_namespace() -> Result<bool, WrappedIoError> {
+    let ns_path = Path::new("/proc/self/ns/cgroup");
+    if !ns_path.exists() {
+        // kernels without support for cgroup namespaces
+        return Ok(false);
+    }
+
+    let ino = fs::metadata(ns_path).wrap_other(ns_path)?.ino();
+    Ok(ino != CGROUP_NS_INIT_INO)
+}
+
+/// Converts a cgroup path as listed in /proc/<pid>/cgroup to a path relative
+/// to a mount of the hierarchy whose mountinfo root is `mount_root`.
+///
+/// When youki runs inside a container without its own cgroup namespace, the
+/// listed paths are relative to the root of the host hierarchy, while only
+/// the subtree of the container is mounted. Paths outside of the mounted
+/// subtree are returned unchanged.
+pub fn normalize_cgroup_path<P: AsRef<Path>>(pathname: P, mount_root: &Path) -> PathBuf {
+    let pathname = pathname.as_ref();
+    match pathname.strip_prefix(mount_root) {
+        Ok(relative) => Path::new("/").join(relative),
+        Err(_) => pathname.to_path_buf(),
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum CreateCgroupSetupError {
     #[error("io error: {0}")]
@@ -324,6 +636,113 @@ pub enum CreateCgroupSetupError {
     V2(#[from] v2::manager::V2ManagerError),
     #[error("systemd error: {0}")]
     Systemd(#[from] systemd::manager::SystemdManagerError),
+    #[error("hybrid error: {0}")]
+    Hybrid(#[from] hybrid::manager::HybridManagerError),
+}
+
+impl CreateCgroupSetupError {
+    pub fn kind(&self) -> CgroupErrorKind {
+        match self {
+            CreateCgroupSetupError::WrappedIo(_) => CgroupErrorKind::Io,
+            CreateCgroupSetupError::NonDefault | CreateCgroupSetupError::FailedToDetect => {
+                CgroupErrorKind::NotSupported
+            }
+            CreateCgroupSetupError::V1(err) => err.kind(),
+            CreateCgroupSetupError::V2(err) => err.kind(),
+            CreateCgroupSetupError::Systemd(err) => err.kind(),
+            CreateCgroupSetupError::Hybrid(err) => err.kind(),
+        }
+    }
+}
+
+/// Manager which should be used for the cgroup of a container
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgroupManagerType {
+    /// Manages the cgroup directly through the cgroup filesystem
+    Cgroupfs,
+    /// Manages the cgroup through a transient unit of systemd
+    Systemd,
+    /// Uses systemd if it is available and the cgroup filesystem otherwise
+    Auto,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("unknown cgroup manager {0}, expected cgroupfs, systemd or auto")]
+pub struct UnknownCgroupManagerError(String);
+
+impl FromStr for CgroupManagerType {
+    type Err = UnknownCgroupManagerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cgroupfs" => Ok(Self::Cgroupfs),
+            "systemd" => Ok(Self::Systemd),
+            "auto" => Ok(Self::Auto),
+            _ => Err(UnknownCgroupManagerError(s.to_owned())),
+        }
+    }
+}
+
+impl Display for CgroupManagerType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Cgroupfs => "cgroupfs",
+            Self::Systemd => "systemd",
+            Self::Auto => "auto",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl CgroupManagerType {
+    /// Decides if systemd should be used. If systemd has been requested or is
+    /// to be detected but is not available, the cgroup filesystem is used
+    /// with a warning.
+    pub fn use_systemd(self) -> bool {
+        match self {
+            Self::Cgroupfs => false,
+            Self::Systemd => {
+                let available = systemd_available();
+                if !available {
+                    tracing::warn!(
+                        "systemd cgroup manager requested, but systemd is not available, falling back to cgroupfs"
+                    );
+                }
+                available
+            }
+            Self::Auto => {
+                let available = systemd_available();
+                if available {
+                    tracing::debug!("detected systemd for managing cgroups");
+                } else {
+                    tracing::warn!("systemd is not available, falling back to cgroupfs");
+                }
+                available
+            }
+        }
+    }
+}
+
+/// Checks if cgroups can be managed through systemd, i.e. if the system has
+/// been booted with systemd and its bus can be reached
+#[cfg(feature = "systemd")]
+pub fn systemd_available() -> bool {
+    if !systemd::booted() {
+        return false;
+    }
+
+    match is_true_root() {
+        Ok(use_system) => systemd::bus_reachable(use_system),
+        Err(err) => {
+            tracing::debug!("failed to check for a user namespace: {}", err);
+            false
+        }
+    }
+}
+
+#[cfg(not(feature = "systemd"))]
+pub fn systemd_available() -> bool {
+    false
 }
 
 #[derive(Clone)]
@@ -331,6 +750,15 @@ pub struct CgroupConfig {
     pub cgroup_path: PathBuf,
     pub systemd_cgroup: bool,
     pub container_name: String,
+    /// Name of a leaf cgroup beneath the cgroup path for the processes of
+    /// the container, see `v2::manager::Manager::with_leaf`. It is only
+    /// supported by the cgroupfs driver of cgroup v2 and ignored otherwise.
+    pub leaf_cgroup: Option<String>,
+    /// Names of the controllers, like `hugetlb`, whose resources are applied
+    /// on a best effort basis. Their failed writes are logged instead of
+    /// failing to apply the resources. It is only supported by the cgroupfs
+    /// drivers and ignored by systemd.
+    pub best_effort_controllers: Vec<String>,
 }
 
 // Create any cgroup manager with customize root path. If root_path provided
@@ -350,15 +778,21 @@ pub fn create_cgroup_manager_with_root(
         GetCgroupSetupError::FailedToDetect => CreateCgroupSetupError::FailedToDetect,
     })?;
     let cgroup_path = config.cgroup_path.as_path();
+    let best_effort = config.best_effort_controllers.clone();
 
     match cgroup_setup {
-        CgroupSetup::Legacy | CgroupSetup::Hybrid => {
-            Ok(create_v1_cgroup_manager(cgroup_path)?.any())
-        }
+        CgroupSetup::Legacy => Ok(create_v1_cgroup_manager(cgroup_path, best_effort)?.any()),
+        CgroupSetup::Hybrid => create_hybrid_cgroup_manager(root, cgroup_path, best_effort),
         CgroupSetup::Unified => {
             // ref https://github.com/opencontainers/runtime-spec/blob/main/config-linux.md#cgroups-path
             if cgroup_path.is_absolute() || !config.systemd_cgroup {
-                return Ok(create_v2_cgroup_manager(root, cgroup_path)?.any());
+                return Ok(create_v2_cgroup_manager(
+                    root,
+                    cgroup_path,
+                    config.leaf_cgroup.as_deref(),
+                    best_effort,
+                )?
+                .any());
             }
             Ok(
                 create_systemd_cgroup_manager(root, cgroup_path, config.container_name.as_str())?
@@ -377,14 +811,16 @@ pub fn create_cgroup_manager(
 #[cfg(feature = "v1")]
 fn create_v1_cgroup_manager(
     cgroup_path: &Path,
+    best_effort: Vec<String>,
 ) -> Result<v1::manager::Manager, v1::manager::V1ManagerError> {
     tracing::info!("cgroup manager V1 will be used");
-    v1::manager::Manager::new(cgroup_path)
+    Ok(v1::manager::Manager::new(cgroup_path)?.with_best_effort(best_effort))
 }
 
 #[cfg(not(feature = "v1"))]
 fn create_v1_cgroup_manager(
     _cgroup_path: &Path,
+    _best_effort: Vec<String>,
 ) -> Result<v1::manager::Manager, v1::manager::V1ManagerError> {
     Err(v1::manager::V1ManagerError::NotEnabled)
 }
@@ -393,19 +829,53 @@ fn create_v1_cgroup_manager(
 fn create_v2_cgroup_manager(
     root_path: &Path,
     cgroup_path: &Path,
+    leaf_cgroup: Option<&str>,
+    best_effort: Vec<String>,
 ) -> Result<v2::manager::Manager, v2::manager::V2ManagerError> {
     tracing::info!("cgroup manager V2 will be used");
-    v2::manager::Manager::new(root_path.to_path_buf(), cgroup_path.to_owned())
+    let manager = v2::manager::Manager::new(root_path.to_path_buf(), cgroup_path.to_owned())?
+        .with_best_effort(best_effort);
+    match leaf_cgroup {
+        Some(leaf_cgroup) => manager.with_leaf(leaf_cgroup),
+        None => Ok(manager),
+    }
 }
 
 #[cfg(not(feature = "v2"))]
 fn create_v2_cgroup_manager(
     _root_path: &Path,
     _cgroup_path: &Path,
+    _leaf_cgroup: Option<&str>,
+    _best_effort: Vec<String>,
 ) -> Result<v2::manager::Manager, v2::manager::V2ManagerError> {
     Err(v2::manager::V2ManagerError::NotEnabled)
 }
 
+#[cfg(all(feature = "v1", feature = "v2"))]
+fn create_hybrid_cgroup_manager(
+    root_path: &Path,
+    cgroup_path: &Path,
+    best_effort: Vec<String>,
+) -> Result<AnyCgroupManager, CreateCgroupSetupError> {
+    tracing::info!("cgroup manager hybrid will be used");
+    Ok(
+        hybrid::manager::Manager::new(root_path.join("unified"), cgroup_path)?
+            .with_best_effort(best_effort)
+            .any(),
+    )
+}
+
+// without v2 support the unified hierarchy is left alone, which is fine as
+// long as no controllers are bound to it
+#[cfg(not(all(feature = "v1", feature = "v2")))]
+fn create_hybrid_cgroup_manager(
+    _root_path: &Path,
+    cgroup_path: &Path,
+    best_effort: Vec<String>,
+) -> Result<AnyCgroupManager, CreateCgroupSetupError> {
+    Ok(create_v1_cgroup_manager(cgroup_path, best_effort)?.any())
+}
+
 #[cfg(feature = "systemd")]
 fn create_systemd_cgroup_manager(
     root_path: &Path,
@@ -461,6 +931,44 @@ pub fn get_all_pids(path: &Path) -> Result<Vec<Pid>, WrappedIoError> {
     Ok(result)
 }
 
+/// Describes the threads of the cgroup at `path` which are in uninterruptible
+/// sleep, e.g. waiting for I/O of a hung network filesystem, as `tid (comm)`.
+/// These cannot be frozen, so that they explain why freezing a cgroup failed.
+pub(crate) fn uninterruptible_tasks(path: &Path) -> Vec<String> {
+    let pids = match get_all_pids(path) {
+        Ok(pids) => pids,
+        Err(err) => {
+            tracing::debug!("failed to get the processes of {:?}: {}", path, err);
+            return Vec::new();
+        }
+    };
+
+    let mut tasks = Vec::new();
+    for pid in pids {
+        let threads = match procfs::process::Process::new(pid.as_raw()).and_then(|p| p.tasks()) {
+            Ok(threads) => threads,
+            // the process may have exited in the meantime
+            Err(_) => continue,
+        };
+        for stat in threads.flatten().filter_map(|thread| thread.stat().ok()) {
+            if stat.state == 'D' {
+                tasks.push(format!("{} ({})", stat.pid, stat.comm));
+            }
+        }
+    }
+
+    tasks
+}
+
+/// Formats the tasks returned by [`uninterruptible_tasks`] for an error message
+pub(crate) fn describe_tasks(tasks: &[String]) -> String {
+    if tasks.is_empty() {
+        return String::new();
+    }
+
+    format!(", tasks in uninterruptible sleep: {}", tasks.join(", "))
+}
+
 fn walk_dir<F, E>(path: &Path, c: &mut F) -> Result<(), E>
 where
     F: FnMut(&Path) -> Result<(), E>,
@@ -508,7 +1016,7 @@ impl PathBufExt for PathBuf {
     }
 }
 
-#[cfg(any(feature = "cgroupsv2_devices", feature = "v1"))]
+#[cfg(any(feature = "cgroupsv2_devices", feature = "v1", feature = "systemd"))]
 pub(crate) fn default_allow_devices() -> Vec<LinuxDeviceCgroup> {
     vec![
         LinuxDeviceCgroupBuilder::default()
@@ -560,7 +1068,7 @@ pub(crate) fn default_allow_devices() -> Vec<LinuxDeviceCgroup> {
     ]
 }
 
-#[cfg(any(feature = "cgroupsv2_devices", feature = "v1"))]
+#[cfg(any(feature = "cgroupsv2_devices", feature = "v1", feature = "systemd"))]
 pub(crate) fn default_devices() -> Vec<LinuxDevice> {
     vec![
         LinuxDeviceBuilder::default()
@@ -645,6 +1153,49 @@ pub(crate) fn delete_with_retry<P: AsRef<Path>, L: Into<Option<Duration>>>(
     .wrap_other(path)?
 }
 
+/// Attempts to delete the cgroup at the path together with all cgroups nested
+/// in it, the nested cgroups are deleted first as a cgroup with children can't
+/// be deleted.
+pub(crate) fn delete_tree_with_retry<P: AsRef<Path>, L: Into<Option<Duration>>>(
+    path: P,
+    retries: u32,
+    limit_backoff: L,
+) -> Result<(), WrappedIoError> {
+    let path = path.as_ref();
+    let limit_backoff = limit_backoff.into();
+    for entry in fs::read_dir(path).wrap_read(path)? {
+        let entry = entry.wrap_open(path)?;
+        if entry.file_type().wrap_other(entry.path())?.is_dir() {
+            delete_tree_with_retry(entry.path(), retries, limit_backoff)?;
+        }
+    }
+
+    delete_with_retry(path, retries, limit_backoff)
+}
+
+/// Sends SIGKILL to the processes of the cgroup at the path and of all cgroups
+/// nested in it, until no processes are left or the attempts are exhausted.
+/// Killing is repeated as the processes may fork while they are being killed.
+pub(crate) fn kill_all_with_retry<P: AsRef<Path>>(
+    path: P,
+    retries: u32,
+) -> Result<(), WrappedIoError> {
+    let path = path.as_ref();
+    for _ in 0..retries {
+        let pids = get_all_pids(path)?;
+        if pids.is_empty() {
+            break;
+        }
+
+        for pid in pids {
+            let _ = nix::sys::signal::kill(pid, nix::sys::signal::SIGKILL);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    Ok(())
+}
+
 pub(crate) trait WrapIoResult {
     type Target;
 
@@ -728,3 +1279,121 @@ impl Display for MustBePowerOfTwo {
         f.write_str("page size must be in the format of 2^(integer)")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_cgroup_path() {
+        // inside a container without cgroup namespace
+        assert_eq!(
+            normalize_cgroup_path("/docker/abc/foo", Path::new("/docker/abc")),
+            PathBuf::from("/foo")
+        );
+        assert_eq!(
+            normalize_cgroup_path("/docker/abc", Path::new("/docker/abc")),
+            PathBuf::from("/")
+        );
+        // inside a cgroup namespace or on the host
+        assert_eq!(
+            normalize_cgroup_path("/user.slice", Path::new("/")),
+            PathBuf::from("/user.slice")
+        );
+        // outside of the mounted subtree
+        assert_eq!(
+            normalize_cgroup_path("/docker/other", Path::new("/docker/abc")),
+            PathBuf::from("/docker/other")
+        );
+    }
+
+    #[test]
+    fn test_uninterruptible_tasks() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        // the test process itself is running, not in uninterruptible sleep
+        fs::write(
+            tmp.path().join(CGROUP_PROCS),
+            std::process::id().to_string(),
+        )?;
+        assert!(uninterruptible_tasks(tmp.path()).is_empty());
+
+        assert_eq!(describe_tasks(&[]), "");
+        assert_eq!(
+            describe_tasks(&["42 (dd)".to_owned(), "43 (sync)".to_owned()]),
+            ", tasks in uninterruptible sleep: 42 (dd), 43 (sync)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_delegated_controllers() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let parent = tmp.path().join("youki.slice");
+        fs::create_dir(&parent)?;
+        fs::write(tmp.path().join(CGROUP_CONTROLLERS), "cpu io memory pids")?;
+        fs::write(parent.join(CGROUP_CONTROLLERS), "cpu memory")?;
+
+        // the cgroup of the container has not been created yet
+        let controllers =
+            delegated_controllers(tmp.path(), Path::new("/youki.slice/74f1a4cb3801"))?;
+        assert_eq!(
+            controllers.into_iter().collect::<Vec<_>>(),
+            vec!["cpu", "memory"]
+        );
+        let controllers = delegated_controllers(tmp.path(), Path::new("/"))?;
+        assert_eq!(controllers.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rdma_limit_entry() {
+        let rdma = oci_spec::runtime::LinuxRdmaBuilder::default()
+            .hca_handles(2u32)
+            .build()
+            .unwrap();
+        assert_eq!(rdma_limit_entry("mlx4_0", &rdma), "mlx4_0 hca_handle=2");
+    }
+
+    #[test]
+    fn test_delete_tree_with_retry() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cgroup = tmp.path().join("container");
+        fs::create_dir_all(cgroup.join("nested/deeper")).unwrap();
+        fs::create_dir_all(cgroup.join("sibling")).unwrap();
+
+        delete_tree_with_retry(&cgroup, 1, None).unwrap();
+        assert!(!cgroup.exists());
+    }
+
+    #[cfg(all(feature = "v2", feature = "systemd"))]
+    #[test]
+    fn test_error_kind() {
+        use crate::v2::unified::V2UnifiedError;
+
+        let not_available = || V2UnifiedError::SubsystemNotAvailable {
+            subsystem: "cpu".to_owned(),
+            key: "cpu.weight".to_owned(),
+        };
+        assert_eq!(
+            AnyManagerError::from(v2::manager::V2ManagerError::from(not_available())).kind(),
+            CgroupErrorKind::ControllerNotFound
+        );
+        assert_eq!(
+            AnyManagerError::from(systemd::manager::SystemdManagerError::from(not_available()))
+                .kind(),
+            CgroupErrorKind::ControllerNotFound
+        );
+    }
+
+    #[test]
+    fn test_cgroup_manager_type() {
+        for manager in ["cgroupfs", "systemd", "auto"] {
+            assert_eq!(
+                manager.parse::<CgroupManagerType>().unwrap().to_string(),
+                manager
+            );
+        }
+        assert!("cgroupv1".parse::<CgroupManagerType>().is_err());
+        assert!(!CgroupManagerType::Cgroupfs.use_systemd());
+    }
+}
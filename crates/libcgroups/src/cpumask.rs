@@ -0,0 +1,281 @@
+//! Parsing of the cpu and memory node lists of cpusets, e.g. "0-3,7", which
+//! are used by the cgroupfs controllers as well as by the systemd driver.
+use std::fmt::{self, Debug, Display};
+use std::fs;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use crate::common::{WrapIoResult, WrappedIoError};
+
+/// Highest number of cpus supported by the kernel (NR_CPUS), which is also
+/// larger than the number of memory nodes it supports
+pub const MAX_CPUS: usize = 8192;
+const BITS: usize = u64::BITS as usize;
+const WORDS: usize = MAX_CPUS / BITS;
+
+const ONLINE_CPUS: &str = "/sys/devices/system/cpu/online";
+const ONLINE_NODES: &str = "/sys/devices/system/node/online";
+
+#[derive(thiserror::Error, Debug)]
+pub enum CpuMaskError {
+    #[error("invalid index {index}: {err}")]
+    InvalidIndex { err: ParseIntError, index: String },
+    #[error("invalid cpu range {0}")]
+    InvalidRange(String),
+    #[error("index {0} exceeds the maximum number of cpus")]
+    TooLarge(usize),
+    #[error("io error: {0}")]
+    WrappedIo(#[from] WrappedIoError),
+    #[error("cpus {0} are not online")]
+    Offline(String),
+}
+
+/// Set of cpus or memory nodes. It has a fixed size, so that parsing lists,
+/// which can come from anywhere in the spec, neither allocates nor panics.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CpuMask {
+    words: [u64; WORDS],
+}
+
+impl Default for CpuMask {
+    fn default() -> Self {
+        Self { words: [0; WORDS] }
+    }
+}
+
+impl CpuMask {
+    /// Returns the cpus which are currently online
+    pub fn online() -> Result<Self, CpuMaskError> {
+        fs::read_to_string(ONLINE_CPUS)
+            .wrap_read(ONLINE_CPUS)?
+            .parse()
+    }
+
+    /// Returns the memory nodes which are currently online, or None if the
+    /// kernel has been built without NUMA support
+    pub fn online_nodes() -> Result<Option<Self>, CpuMaskError> {
+        match fs::read_to_string(ONLINE_NODES) {
+            Ok(nodes) => Ok(Some(nodes.parse()?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(WrappedIoError::Read {
+                err,
+                path: ONLINE_NODES.into(),
+            }
+            .into()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|w| *w == 0)
+    }
+
+    pub fn contains(&self, cpu: usize) -> bool {
+        cpu < MAX_CPUS && self.words[cpu / BITS] & (1 << (cpu % BITS)) != 0
+    }
+
+    /// Adds a cpu to the mask, cpus beyond the maximum are ignored
+    pub fn insert(&mut self, cpu: usize) {
+        if cpu < MAX_CPUS {
+            self.words[cpu / BITS] |= 1 << (cpu % BITS);
+        }
+    }
+
+    /// Returns the cpus of the mask in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..MAX_CPUS).filter(move |cpu| self.contains(*cpu))
+    }
+
+    /// Returns the cpus of the mask which are not in other
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut difference = *self;
+        for (word, other) in difference.words.iter_mut().zip(other.words.iter()) {
+            *word &= !other;
+        }
+        difference
+    }
+
+    /// Returns the cpus which are in both masks
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut intersection = *self;
+        for (word, other) in intersection.words.iter_mut().zip(other.words.iter()) {
+            *word &= other;
+        }
+        intersection
+    }
+
+    /// Checks that all cpus of the mask are online, so that misconfigured
+    /// cpus can be reported before the kernel rejects them
+    pub fn validate_online(&self) -> Result<(), CpuMaskError> {
+        let offline = self.difference(&Self::online()?);
+        if !offline.is_empty() {
+            return Err(CpuMaskError::Offline(offline.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the mask as a big endian number without leading zero bytes,
+    /// which is what systemd expects for AllowedCPUs and AllowedMemoryNodes.
+    /// Otherwise the values are not set, without any error message.
+    pub fn to_be_bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.words
+            .iter()
+            .rev()
+            .flat_map(|w| w.to_be_bytes())
+            .skip_while(|b| *b == 0)
+    }
+}
+
+fn parse_index(index: &str) -> Result<usize, CpuMaskError> {
+    let cpu = index.parse().map_err(|err| CpuMaskError::InvalidIndex {
+        err,
+        index: index.into(),
+    })?;
+    if cpu >= MAX_CPUS {
+        return Err(CpuMaskError::TooLarge(cpu));
+    }
+
+    Ok(cpu)
+}
+
+impl FromStr for CpuMask {
+    type Err = CpuMaskError;
+
+    /// Parses a list of comma separated indices and inclusive ranges. Blanks
+    /// and empty entries are ignored, as the kernel does.
+    fn from_str(list: &str) -> Result<Self, Self::Err> {
+        let mut mask = Self::default();
+        for entry in list.split(',') {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+
+            let (start, end) = match entry.split_once('-') {
+                Some((start, end)) => (parse_index(start.trim())?, parse_index(end.trim())?),
+                None => {
+                    let cpu = parse_index(entry)?;
+                    (cpu, cpu)
+                }
+            };
+            if start > end {
+                return Err(CpuMaskError::InvalidRange(entry.into()));
+            }
+
+            for cpu in start..=end {
+                mask.insert(cpu);
+            }
+        }
+
+        Ok(mask)
+    }
+}
+
+impl Display for CpuMask {
+    /// Formats the mask as a list, with consecutive cpus merged into ranges
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut cpus = self.iter().peekable();
+        let mut separator = "";
+        while let Some(start) = cpus.next() {
+            let mut end = start;
+            while cpus.next_if_eq(&(end + 1)).is_some() {
+                end += 1;
+            }
+
+            if start == end {
+                write!(f, "{separator}{start}")?;
+            } else {
+                write!(f, "{separator}{start}-{end}")?;
+            }
+            separator = ",";
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for CpuMask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CpuMask({self})")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn bytes(list: &str) -> Result<Vec<u8>> {
+        Ok(list.parse::<CpuMask>()?.to_be_bytes().collect())
+    }
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        assert_eq!(bytes("0")?, vec![1]);
+        assert_eq!(bytes("0,1,2")?, vec![7]);
+        assert_eq!(bytes("0-2")?, vec![7]);
+        // 0000 0110 1001 1101
+        assert_eq!(bytes("0,2-4,7,9-10")?, vec![6, 157]);
+        assert_eq!(bytes("0, 2- 4,,7   ,,9-10\n")?, vec![6, 157]);
+        assert_eq!(bytes("64")?, vec![1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(bytes("")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for list in [
+            "2-0",
+            "2-",
+            "-2",
+            "1-2-3",
+            "a",
+            "8192",
+            "0-99999999999999999999",
+        ] {
+            assert!(list.parse::<CpuMask>().is_err(), "{list:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn test_display() -> Result<()> {
+        for (list, expected) in [
+            ("0-3,7", "0-3,7"),
+            ("7,0,1,2,3", "0-3,7"),
+            ("5,6,8-9,10", "5-6,8-10"),
+            ("8191", "8191"),
+            ("", ""),
+        ] {
+            assert_eq!(list.parse::<CpuMask>()?.to_string(), expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_difference() -> Result<()> {
+        let mask: CpuMask = "0-7".parse()?;
+        assert_eq!(mask.difference(&"0-3,6".parse()?).to_string(), "4-5,7");
+        assert!(mask.difference(&mask).is_empty());
+        assert_eq!(mask.intersection(&"0-3,6,9".parse()?).to_string(), "0-3,6");
+        assert!(mask.intersection(&"8-9".parse()?).is_empty());
+        Ok(())
+    }
+
+    quickcheck! {
+        fn property_test_round_trip(cpus: Vec<u16>) -> bool {
+            let mut mask = CpuMask::default();
+            for cpu in &cpus {
+                mask.insert(*cpu as usize % MAX_CPUS);
+            }
+
+            mask.to_string().parse::<CpuMask>().map_or(false, |parsed| parsed == mask)
+        }
+
+        fn property_test_parse_does_not_panic(list: String) -> bool {
+            let _ = list.parse::<CpuMask>();
+            true
+        }
+    }
+}
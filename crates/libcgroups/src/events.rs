@@ -0,0 +1,197 @@
+//! Notifications about the state of a v2 cgroup reported by cgroup.events,
+//! which tells whether the cgroup still has processes and whether it is frozen
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+
+use crate::stats::{self, ParseFlatKeyedDataError};
+
+const CGROUP_EVENTS: &str = "cgroup.events";
+
+#[derive(thiserror::Error, Debug)]
+pub enum CgroupEventsError {
+    #[error("failed to parse cgroup events: {0}")]
+    ParseEvents(#[from] ParseFlatKeyedDataError),
+    #[error("inotify error: {0}")]
+    Inotify(nix::Error),
+}
+
+/// Change of the state of a cgroup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgroupEvent {
+    /// The cgroup or one of its descendants has processes, false once the
+    /// last process has exited
+    Populated(bool),
+    /// The cgroup and all its descendants are frozen
+    Frozen(bool),
+}
+
+/// Waits for changes of the state of a v2 cgroup
+pub struct CgroupEventsWatcher {
+    inotify: Inotify,
+    cgroup_path: PathBuf,
+    populated: bool,
+    frozen: bool,
+    pending: VecDeque<CgroupEvent>,
+}
+
+impl CgroupEventsWatcher {
+    /// Registers for changes of the cgroup at `cgroup_path`
+    pub fn new(cgroup_path: &Path) -> Result<Self, CgroupEventsError> {
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC).map_err(CgroupEventsError::Inotify)?;
+        // the watch is added before the state is read, so that no change is missed
+        inotify
+            .add_watch(&cgroup_path.join(CGROUP_EVENTS), AddWatchFlags::IN_MODIFY)
+            .map_err(CgroupEventsError::Inotify)?;
+
+        let mut watcher = Self {
+            inotify,
+            cgroup_path: cgroup_path.to_owned(),
+            populated: false,
+            frozen: false,
+            pending: VecDeque::new(),
+        };
+        watcher.update()?;
+        watcher.pending.clear();
+        Ok(watcher)
+    }
+
+    /// Whether the cgroup had processes when it was last checked
+    pub fn populated(&self) -> bool {
+        self.populated
+    }
+
+    /// Whether the cgroup was frozen when it was last checked
+    pub fn frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Blocks until the next change of the state. Returns None if the cgroup
+    /// has been removed, after which no further events will be delivered.
+    pub fn wait(&mut self) -> Result<Option<CgroupEvent>, CgroupEventsError> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+
+            self.inotify
+                .read_events()
+                .map_err(CgroupEventsError::Inotify)?;
+            if !self.update()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Blocks until all processes of the cgroup have exited
+    pub fn wait_unpopulated(&mut self) -> Result<(), CgroupEventsError> {
+        while self.populated {
+            if self.wait()?.is_none() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Waits for changes in a separate thread, which sends them to the
+    /// returned channel until the cgroup is removed or the receiver is dropped
+    pub fn into_channel(mut self) -> Receiver<CgroupEvent> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || loop {
+            match self.wait() {
+                Ok(Some(event)) => {
+                    if sender.send(event).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    tracing::warn!("failed to wait for cgroup events: {}", err);
+                    break;
+                }
+            }
+        });
+
+        receiver
+    }
+
+    /// Reads the current state and queues its changes, returns false if the
+    /// cgroup has been removed
+    fn update(&mut self) -> Result<bool, CgroupEventsError> {
+        let path = self.cgroup_path.join(CGROUP_EVENTS);
+        if !path.exists() {
+            return Ok(false);
+        }
+
+        // keys that are missing, e.g. because the file is read while it is
+        // being written, leave the state unchanged
+        let events = stats::parse_flat_keyed_data(&path)?;
+        if let Some(populated) = events.get("populated").map(|v| *v != 0) {
+            if populated != self.populated {
+                self.populated = populated;
+                self.pending.push_back(CgroupEvent::Populated(populated));
+            }
+        }
+        if let Some(frozen) = events.get("frozen").map(|v| *v != 0) {
+            if frozen != self.frozen {
+                self.frozen = frozen;
+                self.pending.push_back(CgroupEvent::Frozen(frozen));
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_cgroup_events() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        fs::write(tmp.path().join(CGROUP_EVENTS), "populated 1\nfrozen 0\n")?;
+        let mut watcher = CgroupEventsWatcher::new(tmp.path())?;
+        assert!(watcher.populated());
+        assert!(!watcher.frozen());
+
+        fs::write(tmp.path().join(CGROUP_EVENTS), "populated 1\nfrozen 1\n")?;
+        assert_eq!(watcher.wait()?, Some(CgroupEvent::Frozen(true)));
+
+        fs::write(tmp.path().join(CGROUP_EVENTS), "populated 0\nfrozen 0\n")?;
+        assert_eq!(watcher.wait()?, Some(CgroupEvent::Populated(false)));
+        assert_eq!(watcher.wait()?, Some(CgroupEvent::Frozen(false)));
+
+        fs::remove_file(tmp.path().join(CGROUP_EVENTS))?;
+        assert_eq!(watcher.wait()?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cgroup_events_channel() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        fs::write(tmp.path().join(CGROUP_EVENTS), "populated 1\nfrozen 0\n")?;
+        let receiver = CgroupEventsWatcher::new(tmp.path())?.into_channel();
+
+        fs::write(tmp.path().join(CGROUP_EVENTS), "populated 0\nfrozen 0\n")?;
+        assert_eq!(receiver.recv()?, CgroupEvent::Populated(false));
+
+        fs::remove_file(tmp.path().join(CGROUP_EVENTS))?;
+        assert!(receiver.recv().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_cgroup_events() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(CgroupEventsWatcher::new(tmp.path()).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+use std::fs::File;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use nix::unistd::{Gid, Pid, Uid};
+use oci_spec::runtime::{LinuxCpu, LinuxResources};
+
+use crate::common::{
+    AnyCgroupManager, CgroupErrorKind, CgroupManager, ControllerOpt, FreezerState,
+};
+use crate::events::CgroupEventsWatcher;
+use crate::oom::OomNotifier;
+use crate::stats::Stats;
+use crate::v1::manager::{Manager as V1Manager, V1ManagerError};
+use crate::v2::controller_type::ControllerType;
+use crate::v2::manager::{Manager as V2Manager, V2ManagerError};
+use crate::v2::util::{self, V2UtilError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum HybridManagerError {
+    #[error("v1 error: {0}")]
+    V1(#[from] V1ManagerError),
+    #[error("v2 error: {0}")]
+    V2(#[from] V2ManagerError),
+    #[error("failed to read controllers of the unified hierarchy: {0}")]
+    Util(#[from] V2UtilError),
+}
+
+impl HybridManagerError {
+    pub fn kind(&self) -> CgroupErrorKind {
+        match self {
+            Self::V1(err) => err.kind(),
+            Self::V2(err) => err.kind(),
+            Self::Util(_) => CgroupErrorKind::Io,
+        }
+    }
+}
+
+/// Manages a cgroup on a hybrid system, where the controllers are mounted as
+/// cgroup v1 hierarchies besides a unified hierarchy. Usually no controllers
+/// are bound to the unified hierarchy, which is then only used to track the
+/// processes of the cgroup, but the ones that are have to be managed there,
+/// because a controller can only be bound to a single hierarchy.
+pub struct Manager {
+    v1: V1Manager,
+    v2: V2Manager,
+    /// Controllers bound to the unified hierarchy
+    v2_controllers: Vec<ControllerType>,
+}
+
+impl Manager {
+    /// Constructs a new cgroup manager with unified root being the mount
+    /// point of the unified hierarchy and cgroup path being relative to the
+    /// roots of all hierarchies
+    pub fn new(unified_root: PathBuf, cgroup_path: &Path) -> Result<Self, HybridManagerError> {
+        let v2_controllers = util::get_available_controllers(&unified_root)?;
+        tracing::debug!(
+            "controllers {:?} are bound to the unified hierarchy",
+            v2_controllers
+        );
+
+        Ok(Self {
+            v1: V1Manager::new(cgroup_path)?,
+            v2: V2Manager::new(unified_root, cgroup_path.to_owned())?,
+            v2_controllers,
+        })
+    }
+
+    /// Applies the resources of the named controllers on a best effort
+    /// basis in both hierarchies, see [`crate::batch`]
+    pub fn with_best_effort(mut self, controllers: Vec<String>) -> Self {
+        self.v1 = self.v1.with_best_effort(controllers.clone());
+        self.v2 = self.v2.with_best_effort(controllers);
+        self
+    }
+
+    pub fn any(self) -> AnyCgroupManager {
+        AnyCgroupManager::Hybrid(Box::new(self))
+    }
+
+    fn is_v2(&self, controller: ControllerType) -> bool {
+        self.v2_controllers.contains(&controller)
+    }
+}
+
+/// Splits the resources into the ones of the v1 hierarchies and the ones of
+/// the unified hierarchy, depending on where their controller is bound. The
+/// unified map can only be applied to the unified hierarchy, while devices,
+/// network and the freezer are always handled by v1.
+fn split_resources(
+    resources: &LinuxResources,
+    v2_controllers: &[ControllerType],
+) -> (LinuxResources, LinuxResources) {
+    let is_v2 = |controller| v2_controllers.contains(&controller);
+    let mut v1 = resources.clone();
+    let mut v2 = LinuxResources::default();
+
+    if let Some(cpu) = resources.cpu() {
+        let (mut v1_cpu, mut v2_cpu) = (cpu.clone(), cpu.clone());
+        if is_v2(ControllerType::Cpu) {
+            clear_scheduling(&mut v1_cpu);
+        } else {
+            clear_scheduling(&mut v2_cpu);
+        }
+        if is_v2(ControllerType::CpuSet) {
+            clear_cpuset(&mut v1_cpu);
+        } else {
+            clear_cpuset(&mut v2_cpu);
+        }
+        v1.set_cpu(Some(v1_cpu));
+        v2.set_cpu(Some(v2_cpu));
+    }
+
+    if is_v2(ControllerType::Memory) {
+        v2.set_memory(v1.memory_mut().take());
+    }
+    if is_v2(ControllerType::Pids) {
+        v2.set_pids(v1.pids_mut().take());
+    }
+    if is_v2(ControllerType::Io) {
+        v2.set_block_io(v1.block_io_mut().take());
+    }
+    if is_v2(ControllerType::HugeTlb) {
+        v2.set_hugepage_limits(v1.hugepage_limits_mut().take());
+    }
+    if is_v2(ControllerType::Rdma) {
+        v2.set_rdma(v1.rdma_mut().take());
+    }
+    v2.set_unified(v1.unified_mut().take());
+
+    (v1, v2)
+}
+
+fn clear_scheduling(cpu: &mut LinuxCpu) {
+    cpu.set_shares(None)
+        .set_quota(None)
+        .set_period(None)
+        .set_burst(None)
+        .set_idle(None)
+        .set_realtime_runtime(None)
+        .set_realtime_period(None);
+}
+
+fn clear_cpuset(cpu: &mut LinuxCpu) {
+    cpu.set_cpus(None).set_mems(None);
+}
+
+impl CgroupManager for Manager {
+    type Error = HybridManagerError;
+
+    fn add_task(&self, pid: Pid) -> Result<(), Self::Error> {
+        self.v1.add_task(pid)?;
+        if let Err(err) = self.v2.add_task(pid) {
+            // the unified hierarchy is not needed for resource control if no
+            // controllers are bound to it, e.g. if it has not been delegated
+            if !self.v2_controllers.is_empty() {
+                return Err(err.into());
+            }
+            tracing::warn!("failed to add task to the unified hierarchy: {}", err);
+        }
+
+        Ok(())
+    }
+
+    fn open_task_cgroup(&self) -> Result<Option<File>, Self::Error> {
+        Ok(None)
+    }
+
+    fn apply(&self, controller_opt: &ControllerOpt) -> Result<(), Self::Error> {
+        let (v1_resources, v2_resources) =
+            split_resources(controller_opt.resources, &self.v2_controllers);
+
+        self.v1.apply(&ControllerOpt {
+            resources: &v1_resources,
+            ..*controller_opt
+        })?;
+        self.v2.apply_controllers(
+            &ControllerOpt {
+                resources: &v2_resources,
+                ..*controller_opt
+            },
+            &self.v2_controllers,
+        )?;
+
+        Ok(())
+    }
+
+    fn remove(&self) -> Result<(), Self::Error> {
+        self.v1.remove()?;
+        self.v2.remove()?;
+        Ok(())
+    }
+
+    fn freeze(&self, state: FreezerState) -> Result<(), Self::Error> {
+        Ok(self.v1.freeze(state)?)
+    }
+
+    fn stats(&self) -> Result<Stats, Self::Error> {
+        let mut stats = self.v1.stats()?;
+        let mut v2_stats = self.v2.controller_stats(&self.v2_controllers)?;
+        for controller in &self.v2_controllers {
+            match controller {
+                ControllerType::Cpu => stats.cpu = mem::take(&mut v2_stats.cpu),
+                ControllerType::Pids => stats.pids = mem::take(&mut v2_stats.pids),
+                ControllerType::Memory => stats.memory = mem::take(&mut v2_stats.memory),
+                ControllerType::Io => stats.blkio = mem::take(&mut v2_stats.blkio),
+                ControllerType::HugeTlb => stats.hugetlb = mem::take(&mut v2_stats.hugetlb),
+                ControllerType::Misc => stats.misc = mem::take(&mut v2_stats.misc),
+                ControllerType::Rdma => stats.rdma = mem::take(&mut v2_stats.rdma),
+                ControllerType::CpuSet => continue,
+            }
+        }
+
+        Ok(stats)
+    }
+
+    fn get_all_pids(&self) -> Result<Vec<Pid>, Self::Error> {
+        Ok(self.v1.get_all_pids()?)
+    }
+
+    fn oom_notifier(&self) -> Result<OomNotifier, Self::Error> {
+        if self.is_v2(ControllerType::Memory) {
+            return Ok(self.v2.oom_notifier()?);
+        }
+
+        Ok(self.v1.oom_notifier()?)
+    }
+
+    fn events_watcher(&self) -> Result<CgroupEventsWatcher, Self::Error> {
+        Ok(self.v2.events_watcher()?)
+    }
+
+    fn kill_all(&self) -> Result<bool, Self::Error> {
+        Ok(self.v2.kill_all()?)
+    }
+
+    fn reclaim_memory(&self, bytes: u64) -> Result<bool, Self::Error> {
+        if self.is_v2(ControllerType::Memory) {
+            return Ok(self.v2.reclaim_memory(bytes)?);
+        }
+
+        Ok(false)
+    }
+
+    fn delegate(
+        &self,
+        _owner: Uid,
+        _group: Gid,
+        _controllers: &[String],
+    ) -> Result<bool, Self::Error> {
+        // the controllers of the v1 hierarchies can't be delegated
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oci_spec::runtime::{
+        LinuxCpuBuilder, LinuxMemoryBuilder, LinuxPidsBuilder, LinuxResourcesBuilder,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_split_resources() {
+        let unified: HashMap<String, String> = [("misc.max".to_owned(), "1".to_owned())].into();
+        let resources = LinuxResourcesBuilder::default()
+            .cpu(
+                LinuxCpuBuilder::default()
+                    .shares(1024u64)
+                    .cpus("0-1")
+                    .build()
+                    .unwrap(),
+            )
+            .memory(
+                LinuxMemoryBuilder::default()
+                    .limit(1 << 20)
+                    .build()
+                    .unwrap(),
+            )
+            .pids(LinuxPidsBuilder::default().limit(16).build().unwrap())
+            .unified(unified.clone())
+            .build()
+            .unwrap();
+
+        let (v1, v2) = split_resources(&resources, &[]);
+        assert_eq!(v1.cpu(), resources.cpu());
+        assert_eq!(v1.memory(), resources.memory());
+        assert!(v2.memory().is_none());
+        assert_eq!(v2.unified(), &Some(unified.clone()));
+
+        let (v1, v2) = split_resources(&resources, &[ControllerType::Cpu, ControllerType::Pids]);
+        let (v1_cpu, v2_cpu) = (v1.cpu().as_ref().unwrap(), v2.cpu().as_ref().unwrap());
+        assert_eq!(v1_cpu.shares(), None);
+        assert_eq!(v1_cpu.cpus().as_deref(), Some("0-1"));
+        assert_eq!(v2_cpu.shares(), Some(1024));
+        assert_eq!(v2_cpu.cpus(), &None);
+        assert!(v1.pids().is_none());
+        assert_eq!(v2.pids(), resources.pids());
+        assert_eq!(v1.memory(), resources.memory());
+        assert!(v1.unified().is_none());
+    }
+}
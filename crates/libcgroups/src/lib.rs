@@ -12,7 +12,18 @@ extern crate mockall;
 
 mod test;
 
+pub mod batch;
+pub mod checkpoint;
+pub mod collector;
 pub mod common;
+pub mod cpumask;
+pub mod events;
+#[cfg(all(feature = "v1", feature = "v2"))]
+pub mod hybrid;
+#[cfg(not(all(feature = "v1", feature = "v2")))]
+#[path = "stub/hybrid/mod.rs"]
+pub mod hybrid;
+pub mod oom;
 pub mod stats;
 #[cfg(feature = "systemd")]
 pub mod systemd;
@@ -20,6 +31,9 @@ pub mod systemd;
 #[path = "stub/systemd/mod.rs"]
 pub mod systemd;
 pub mod test_manager;
+pub mod topology;
+#[cfg(feature = "io_uring")]
+mod uring;
 #[cfg(feature = "v1")]
 pub mod v1;
 #[cfg(not(feature = "v1"))]
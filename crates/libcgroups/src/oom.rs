@@ -0,0 +1,186 @@
+//! Notifications about out of memory events inside a cgroup
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use nix::sys::eventfd::{EfdFlags, EventFd};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+
+use crate::common::{self, WrapIoResult, WrappedIoError};
+use crate::stats::{self, ParseFlatKeyedDataError};
+
+const CGROUP_V1_OOM_CONTROL: &str = "memory.oom_control";
+const CGROUP_V1_EVENT_CONTROL: &str = "cgroup.event_control";
+const CGROUP_V2_MEMORY_EVENTS: &str = "memory.events";
+const CGROUP_V2_EVENTS: &str = "cgroup.events";
+
+#[derive(thiserror::Error, Debug)]
+pub enum OomNotifierError {
+    #[error("io error: {0}")]
+    WrappedIo(#[from] WrappedIoError),
+    #[error("failed to parse cgroup events: {0}")]
+    ParseEvents(#[from] ParseFlatKeyedDataError),
+    #[error("eventfd error: {0}")]
+    EventFd(nix::Error),
+    #[error("inotify error: {0}")]
+    Inotify(nix::Error),
+}
+
+enum Source {
+    /// cgroup v1 signals out of memory conditions through an eventfd
+    /// registered for memory.oom_control
+    V1 {
+        event_fd: EventFd,
+        // the registration is only valid as long as the file is open
+        _oom_control: File,
+        oom_control_path: PathBuf,
+    },
+    /// cgroup v2 increments the oom_kill counter in memory.events and
+    /// notifies about modifications of the file
+    V2 {
+        inotify: Inotify,
+        cgroup_path: PathBuf,
+        oom_kills: u64,
+    },
+}
+
+/// Waits for out of memory events of a cgroup
+pub struct OomNotifier {
+    source: Source,
+}
+
+impl OomNotifier {
+    /// Registers for out of memory notifications of the v1 memory cgroup at `cgroup_path`
+    pub fn new_v1(cgroup_path: &Path) -> Result<Self, OomNotifierError> {
+        let oom_control_path = cgroup_path.join(CGROUP_V1_OOM_CONTROL);
+        let oom_control = File::open(&oom_control_path).wrap_open(&oom_control_path)?;
+        let event_fd =
+            EventFd::from_flags(EfdFlags::EFD_CLOEXEC).map_err(OomNotifierError::EventFd)?;
+        common::write_cgroup_file(
+            cgroup_path.join(CGROUP_V1_EVENT_CONTROL),
+            format!("{} {}", event_fd.as_raw_fd(), oom_control.as_raw_fd()),
+        )?;
+
+        Ok(Self {
+            source: Source::V1 {
+                event_fd,
+                _oom_control: oom_control,
+                oom_control_path,
+            },
+        })
+    }
+
+    /// Registers for out of memory notifications of the v2 cgroup at `cgroup_path`
+    pub fn new_v2(cgroup_path: &Path) -> Result<Self, OomNotifierError> {
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC).map_err(OomNotifierError::Inotify)?;
+        for file in [CGROUP_V2_MEMORY_EVENTS, CGROUP_V2_EVENTS] {
+            inotify
+                .add_watch(&cgroup_path.join(file), AddWatchFlags::IN_MODIFY)
+                .map_err(OomNotifierError::Inotify)?;
+        }
+        let oom_kills = Self::read_oom_kills(cgroup_path)?;
+
+        Ok(Self {
+            source: Source::V2 {
+                inotify,
+                cgroup_path: cgroup_path.to_owned(),
+                oom_kills,
+            },
+        })
+    }
+
+    /// Blocks until the next out of memory event. Returns false if the cgroup
+    /// has no processes left or has been removed, after which no further
+    /// events will be delivered.
+    pub fn wait(&mut self) -> Result<bool, OomNotifierError> {
+        match &mut self.source {
+            Source::V1 {
+                event_fd,
+                oom_control_path,
+                ..
+            } => {
+                event_fd.read().map_err(OomNotifierError::EventFd)?;
+                // the eventfd is also signaled when the cgroup is removed
+                Ok(oom_control_path.exists())
+            }
+            Source::V2 {
+                inotify,
+                cgroup_path,
+                oom_kills,
+            } => loop {
+                inotify.read_events().map_err(OomNotifierError::Inotify)?;
+
+                // the last process may have been the one that got killed, so
+                // the counter is checked before the cgroup population
+                if !cgroup_path.exists() {
+                    return Ok(false);
+                }
+                let current = Self::read_oom_kills(cgroup_path)?;
+                if current > *oom_kills {
+                    *oom_kills = current;
+                    return Ok(true);
+                }
+
+                let events = stats::parse_flat_keyed_data(&cgroup_path.join(CGROUP_V2_EVENTS))?;
+                if events.get("populated") == Some(&0) {
+                    return Ok(false);
+                }
+            },
+        }
+    }
+
+    /// Returns the number of processes of the cgroup that have been killed by
+    /// the out of memory killer so far
+    pub fn oom_kills(&self) -> Result<u64, OomNotifierError> {
+        match &self.source {
+            Source::V1 {
+                oom_control_path, ..
+            } => {
+                let oom_control = stats::parse_flat_keyed_data(oom_control_path)?;
+                Ok(oom_control.get("oom_kill").copied().unwrap_or_default())
+            }
+            Source::V2 { cgroup_path, .. } => Self::read_oom_kills(cgroup_path),
+        }
+    }
+
+    fn read_oom_kills(cgroup_path: &Path) -> Result<u64, OomNotifierError> {
+        let events = stats::parse_flat_keyed_data(&cgroup_path.join(CGROUP_V2_MEMORY_EVENTS))?;
+        Ok(events.get("oom_kill").copied().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::Result;
+
+    use super::*;
+
+    const MEMORY_EVENTS: &str = "low 0\nhigh 0\nmax 0\noom 0\noom_kill 0\n";
+
+    #[test]
+    fn test_v2_oom_kill() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        fs::write(tmp.path().join(CGROUP_V2_MEMORY_EVENTS), MEMORY_EVENTS)?;
+        fs::write(tmp.path().join(CGROUP_V2_EVENTS), "populated 1\nfrozen 0\n")?;
+        let mut notifier = OomNotifier::new_v2(tmp.path())?;
+
+        fs::write(
+            tmp.path().join(CGROUP_V2_MEMORY_EVENTS),
+            "low 0\nhigh 0\nmax 3\noom 1\noom_kill 1\n",
+        )?;
+        assert!(notifier.wait()?);
+
+        fs::write(tmp.path().join(CGROUP_V2_EVENTS), "populated 0\nfrozen 0\n")?;
+        assert!(!notifier.wait()?);
+        assert_eq!(notifier.oom_kills()?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_v2_missing_memory_events() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(OomNotifier::new_v2(tmp.path()).is_err());
+    }
+}
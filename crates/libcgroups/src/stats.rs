@@ -1,9 +1,12 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Display;
 use std::fs;
 use std::num::ParseIntError;
 use std::path::{Path, PathBuf};
 
+use nix::errno::Errno;
 use serde::Serialize;
 
 use super::common;
@@ -29,6 +32,12 @@ pub struct Stats {
     pub blkio: BlkioStats,
     /// Memory statistics for the cgroup
     pub memory: MemoryStats,
+    /// Statistics of miscellaneous scalar resources like sgx_epc for the cgroup
+    pub misc: HashMap<String, MiscStats>,
+    /// Network classification of the cgroup, only available with cgroup v1
+    pub network: NetworkStats,
+    /// Rdma statistics for the cgroup per device
+    pub rdma: HashMap<String, RdmaStats>,
 }
 
 /// Reports the cpu statistics for a cgroup
@@ -38,6 +47,9 @@ pub struct CpuStats {
     pub usage: CpuUsage,
     /// Cpu Throttling statistics for the cgroup
     pub throttling: CpuThrottling,
+    /// Bandwidth limit which the throttling enforces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<CpuBandwidth>,
     /// Pressure Stall Information
     pub psi: PSIStats,
 }
@@ -68,6 +80,19 @@ pub struct CpuThrottling {
     pub throttled_periods: u64,
     /// Total time duration for which tasks have been throttled
     pub throttled_time: u64,
+    /// Number of period intervals in which tasks used burst capacity beyond their quota
+    pub bursts: u64,
+    /// Total time duration for which tasks ran on burst capacity beyond their quota
+    pub burst_time: u64,
+}
+
+/// Reports the cfs bandwidth limit of a cgroup in microseconds
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CpuBandwidth {
+    /// Cpu time tasks may consume per period, None if they are not limited
+    pub quota: Option<u64>,
+    /// Length of a period
+    pub period: u64,
 }
 
 /// Reports memory stats for a cgroup
@@ -77,6 +102,11 @@ pub struct MemoryStats {
     pub memory: MemoryData,
     /// Usage of memory and swap
     pub memswap: MemoryData,
+    /// Usage of swap alone
+    pub swap: MemoryData,
+    /// Returns true if the usage of swap is accounted, without which the
+    /// usage of memory and swap and of swap alone is not reported
+    pub swap_accounting: bool,
     /// Usage of kernel memory
     pub kernel: MemoryData,
     /// Usage of kernel tcp memory
@@ -104,6 +134,15 @@ pub struct MemoryData {
     pub limit: u64,
 }
 
+/// Reports the network classification of a cgroup
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct NetworkStats {
+    /// Class id the network packets of the cgroup are tagged with (net_cls)
+    pub class_id: u32,
+    /// Priority of the network traffic of the cgroup per interface (net_prio)
+    pub priorities: HashMap<String, u64>,
+}
+
 /// Reports pid stats for a cgroup
 #[derive(Debug, Default, PartialEq, Eq, Serialize)]
 pub struct PidStats {
@@ -113,6 +152,30 @@ pub struct PidStats {
     pub limit: u64,
 }
 
+/// Reports the usage of a miscellaneous scalar resource for a cgroup
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MiscStats {
+    /// Current usage of the resource
+    pub usage: u64,
+    /// Usage limit of the resource (u64::MAX means no limit)
+    pub limit: u64,
+    /// Number of times the usage was about to exceed the limit
+    pub events: u64,
+}
+
+/// Reports the usage of the resources of an rdma device for a cgroup
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RdmaStats {
+    /// Number of HCA handles in use
+    pub hca_handles: u64,
+    /// Number of HCA objects in use
+    pub hca_objects: u64,
+    /// Limit of HCA handles (u64::MAX means no limit)
+    pub hca_handles_limit: u64,
+    /// Limit of HCA objects (u64::MAX means no limit)
+    pub hca_objects_limit: u64,
+}
+
 /// Reports block io stats for a cgroup
 #[derive(Debug, Default, PartialEq, Serialize)]
 pub struct BlkioStats {
@@ -134,6 +197,135 @@ pub struct BlkioStats {
     pub merged: Vec<BlkioDeviceStat>,
     /// Pressure Stall Information
     pub psi: PSIStats,
+    /// Names of the devices the stats are reported for, once they have been
+    /// resolved with [`BlkioStats::resolve_devices`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<BlockDevice>,
+    /// Sums over all devices, once they have been computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub totals: Option<BlkioTotals>,
+}
+
+impl BlkioStats {
+    fn device_stats_mut(&mut self) -> [&mut Vec<BlkioDeviceStat>; 8] {
+        [
+            &mut self.service_bytes,
+            &mut self.serviced,
+            &mut self.time,
+            &mut self.sectors,
+            &mut self.service_time,
+            &mut self.wait_time,
+            &mut self.queued,
+            &mut self.merged,
+        ]
+    }
+
+    /// Looks up the names of the devices the stats are reported for, and the
+    /// ones of the disks of partitions. Devices which can't be found are left
+    /// out.
+    pub fn resolve_devices(&mut self) {
+        self.resolve_devices_with(block_device)
+    }
+
+    fn resolve_devices_with(&mut self, lookup: impl Fn(u64, u64) -> Option<BlockDevice>) {
+        let numbers: BTreeSet<(u64, u64)> = self
+            .device_stats_mut()
+            .iter()
+            .flat_map(|stats| stats.iter().map(|s| (s.major, s.minor)))
+            .collect();
+        let mut devices: BTreeMap<(u64, u64), BlockDevice> = numbers
+            .into_iter()
+            .filter_map(|(major, minor)| lookup(major, minor))
+            .map(|d| ((d.major, d.minor), d))
+            .collect();
+        let disks: Vec<(u64, u64)> = devices.values().filter_map(|d| d.disk).collect();
+        for (major, minor) in disks {
+            if let Entry::Vacant(entry) = devices.entry((major, minor)) {
+                if let Some(disk) = lookup(major, minor) {
+                    entry.insert(disk);
+                }
+            }
+        }
+        self.devices = devices.into_values().collect();
+    }
+
+    /// Adds up the stats of partitions to the ones of their disks, which
+    /// requires the devices to be resolved. Only the disks are kept in the
+    /// devices afterwards.
+    pub fn aggregate_partitions(&mut self) {
+        let disks: HashMap<(u64, u64), (u64, u64)> = self
+            .devices
+            .iter()
+            .filter_map(|d| d.disk.map(|disk| ((d.major, d.minor), disk)))
+            .collect();
+        if disks.is_empty() {
+            return;
+        }
+
+        for stats in self.device_stats_mut() {
+            let mut aggregated: BTreeMap<(u64, u64, Option<String>), u64> = BTreeMap::new();
+            for stat in stats.drain(..) {
+                let (major, minor) = disks
+                    .get(&(stat.major, stat.minor))
+                    .copied()
+                    .unwrap_or((stat.major, stat.minor));
+                *aggregated.entry((major, minor, stat.op_type)).or_default() += stat.value;
+            }
+            stats.extend(
+                aggregated
+                    .into_iter()
+                    .map(|((major, minor, op_type), value)| BlkioDeviceStat {
+                        major,
+                        minor,
+                        op_type,
+                        value,
+                    }),
+            );
+        }
+        self.devices.retain(|d| d.disk.is_none());
+    }
+
+    /// Sums the transferred bytes and the operations over all devices
+    pub fn compute_totals(&self) -> BlkioTotals {
+        BlkioTotals {
+            service_bytes: sum_by_op_type(&self.service_bytes),
+            serviced: sum_by_op_type(&self.serviced),
+        }
+    }
+}
+
+// the operation types of cgroup v1 are capitalized, the ones of v2 aren't
+fn sum_by_op_type(stats: &[BlkioDeviceStat]) -> BTreeMap<String, u64> {
+    let mut totals = BTreeMap::new();
+    for stat in stats {
+        if let Some(op_type) = &stat.op_type {
+            *totals.entry(op_type.to_lowercase()).or_default() += stat.value;
+        }
+    }
+    totals
+}
+
+/// Sums of block io stats over all devices, by operation type
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct BlkioTotals {
+    /// Number of bytes transferred to/from all devices
+    pub service_bytes: BTreeMap<String, u64>,
+    /// Number of I/O operations performed on all devices
+    pub serviced: BTreeMap<String, u64>,
+}
+
+/// Block device which stats are reported for
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct BlockDevice {
+    /// Major device number
+    pub major: u64,
+    /// Minor device number
+    pub minor: u64,
+    /// Kernel name of the device, e.g. sda1
+    pub name: String,
+    /// Device number of the disk, if the device is a partition of one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk: Option<(u64, u64)>,
 }
 
 /// Reports single stat value for a specific device
@@ -191,6 +383,8 @@ pub struct PSIData {
     pub avg60: f64,
     /// Running average over the last 300 seconds
     pub avg300: f64,
+    /// Total stall time in microseconds
+    pub total: u64,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -283,6 +477,84 @@ pub fn parse_single_value(file_path: &Path) -> Result<u64, WrappedIoError> {
         .wrap_other(file_path)
 }
 
+/// Reads the content of all given files. If libcgroups has been built with the
+/// `io_uring` feature, the reads are submitted at once through io_uring, which
+/// is considerably faster than reading the files one after another if many
+/// cgroups have to be inspected. Otherwise, or if io_uring is not available,
+/// the files are read sequentially.
+/// # Example
+/// ```no_run
+/// use std::path::Path;
+/// use libcgroups::stats::read_files;
+///
+/// let contents = read_files(&[Path::new("cpu.stat"), Path::new("memory.stat")]);
+/// assert_eq!(contents.len(), 2);
+/// ```
+pub fn read_files<P: AsRef<Path>>(paths: &[P]) -> Vec<Result<String, WrappedIoError>> {
+    #[cfg(feature = "io_uring")]
+    match crate::uring::read_files(paths) {
+        Ok(contents) => return contents,
+        Err(err) => tracing::debug!(
+            ?err,
+            "io_uring is not available, reading files sequentially"
+        ),
+    }
+
+    paths.iter().map(common::read_cgroup_file).collect()
+}
+
+thread_local! {
+    static PREFETCHED: RefCell<Option<HashMap<PathBuf, String>>> = RefCell::new(None);
+}
+
+/// Reads the files of the given controllers in the cgroup directory with
+/// [`read_files`], and serves the reads of `read_stats` from the prefetched
+/// contents. The stats of the controllers are read with the usual readers,
+/// which then don't read the files one after another. Files which could not
+/// be prefetched are read when the readers ask for them.
+pub(crate) fn with_prefetched<C, T>(dirs: &[(&Path, C)], read_stats: impl FnOnce() -> T) -> T
+where
+    C: Display,
+{
+    let mut paths = BTreeSet::new();
+    for (dir, controller) in dirs {
+        let prefix = format!("{controller}.");
+        // the stats are reported with the proper errors by the readers
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        paths.extend(
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+                .map(|entry| entry.path()),
+        );
+    }
+    let paths: Vec<_> = paths.into_iter().collect();
+    let contents = read_files(&paths)
+        .into_iter()
+        .zip(&paths)
+        .filter_map(|(content, path)| Some((path.clone(), content.ok()?)))
+        .collect();
+
+    let previous = PREFETCHED.with(|prefetched| prefetched.replace(Some(contents)));
+    let stats = read_stats();
+    PREFETCHED.with(|prefetched| prefetched.replace(previous));
+    stats
+}
+
+/// Returns the prefetched content of a file, if the stats are read with
+/// [`with_prefetched`] on the current thread
+pub(crate) fn prefetched(path: &Path) -> Option<String> {
+    PREFETCHED.with(|prefetched| {
+        prefetched
+            .borrow()
+            .as_ref()
+            .and_then(|contents| contents.get(path).cloned())
+    })
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum ParseFlatKeyedDataError {
     #[error("io error: {0}")]
@@ -394,6 +666,67 @@ pub(crate) fn parse_device_number(device: &str) -> Result<(u64, u64), ParseDevic
     ))
 }
 
+const SYS_DEV_BLOCK: &str = "/sys/dev/block";
+const PROC_PARTITIONS: &str = "/proc/partitions";
+
+/// Looks up the name of the block device and the disk it is a partition of
+/// in sysfs, or only its name in /proc/partitions if sysfs is not mounted
+pub fn block_device(major: u64, minor: u64) -> Option<BlockDevice> {
+    block_device_in(
+        Path::new(SYS_DEV_BLOCK),
+        Path::new(PROC_PARTITIONS),
+        major,
+        minor,
+    )
+}
+
+fn block_device_in(
+    sys_dev_block: &Path,
+    proc_partitions: &Path,
+    major: u64,
+    minor: u64,
+) -> Option<BlockDevice> {
+    // the entry is a link to the directory of the device, which partitions
+    // have in the one of their disk
+    let dir = sys_dev_block.join(format!("{major}:{minor}"));
+    if let Ok(uevent) = fs::read_to_string(dir.join("uevent")) {
+        let name = uevent
+            .lines()
+            .find_map(|line| line.strip_prefix("DEVNAME="))?
+            .to_owned();
+        let disk = if dir.join("partition").exists() {
+            fs::read_to_string(dir.join("../dev"))
+                .ok()
+                .and_then(|dev| parse_device_number(dev.trim()).ok())
+        } else {
+            None
+        };
+        return Some(BlockDevice {
+            major,
+            minor,
+            name,
+            disk,
+        });
+    }
+
+    // major minor #blocks name
+    let partitions = fs::read_to_string(proc_partitions).ok()?;
+    partitions.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [maj, min, _, name] if *maj == major.to_string() && *min == minor.to_string() => {
+                Some(BlockDevice {
+                    major,
+                    minor,
+                    name: (*name).to_owned(),
+                    disk: None,
+                })
+            }
+            _ => None,
+        }
+    })
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum PidStatsError {
     #[error("io error: {0}")]
@@ -423,14 +756,183 @@ pub fn pid_stats(cgroup_path: &Path) -> Result<PidStats, PidStatsError> {
     Ok(stats)
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum MiscStatsError {
+    #[error("io error: {0}")]
+    WrappedIo(#[from] WrappedIoError),
+    #[error("misc data at {path} contains entries that do not conform to 'resource value'")]
+    DoesNotConform { path: PathBuf },
+    #[error("failed to parse value {value} from {path}: {err}")]
+    FailedToParse {
+        value: String,
+        path: PathBuf,
+        err: ParseIntError,
+    },
+}
+
+/// Returns the statistics of the miscellaneous resources of a cgroup. Kernels
+/// without the misc controller report no resources, the misc.events file is
+/// only provided by newer kernels and is optional.
+pub fn misc_stats(cgroup_path: &Path) -> Result<HashMap<String, MiscStats>, MiscStatsError> {
+    let mut stats: HashMap<String, MiscStats> = HashMap::new();
+    let current_path = cgroup_path.join("misc.current");
+    if !current_path.exists() {
+        return Ok(stats);
+    }
+
+    for (resource, usage) in parse_misc_file(&current_path)? {
+        stats.entry(resource).or_default().usage = usage;
+    }
+    for (resource, limit) in parse_misc_file(&cgroup_path.join("misc.max"))? {
+        stats.entry(resource).or_default().limit = limit;
+    }
+
+    let events_path = cgroup_path.join("misc.events");
+    if events_path.exists() {
+        for (event, count) in parse_misc_file(&events_path)? {
+            if let Some(resource) = event.strip_suffix(".max") {
+                stats.entry(resource.to_owned()).or_default().events = count;
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+fn parse_misc_file(path: &Path) -> Result<Vec<(String, u64)>, MiscStatsError> {
+    common::read_cgroup_file(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (resource, value) =
+                line.split_once(' ')
+                    .ok_or_else(|| MiscStatsError::DoesNotConform {
+                        path: path.to_path_buf(),
+                    })?;
+            let value = match value.trim() {
+                "max" => u64::MAX,
+                value => value.parse().map_err(|err| MiscStatsError::FailedToParse {
+                    value: value.to_owned(),
+                    path: path.to_path_buf(),
+                    err,
+                })?,
+            };
+            Ok((resource.to_owned(), value))
+        })
+        .collect()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RdmaStatsError {
+    #[error("io error: {0}")]
+    WrappedIo(#[from] WrappedIoError),
+    #[error("rdma data at {path} contains entries that do not conform to 'device key=value...'")]
+    DoesNotConform { path: PathBuf },
+    #[error("failed to parse value {value} from {path}: {err}")]
+    FailedToParse {
+        value: String,
+        path: PathBuf,
+        err: ParseIntError,
+    },
+}
+
+/// Returns the usage and limits of the rdma devices of a cgroup. Kernels
+/// without the rdma controller report no devices.
+pub fn rdma_stats(cgroup_path: &Path) -> Result<HashMap<String, RdmaStats>, RdmaStatsError> {
+    let mut stats: HashMap<String, RdmaStats> = HashMap::new();
+    let current_path = cgroup_path.join("rdma.current");
+    if !current_path.exists() {
+        return Ok(stats);
+    }
+
+    for (device, resources) in parse_rdma_file(&current_path)? {
+        let device_stats = stats.entry(device).or_default();
+        for (resource, value) in resources {
+            match resource.as_str() {
+                "hca_handle" => device_stats.hca_handles = value,
+                "hca_object" => device_stats.hca_objects = value,
+                _ => {}
+            }
+        }
+    }
+    for (device, resources) in parse_rdma_file(&cgroup_path.join("rdma.max"))? {
+        let device_stats = stats.entry(device).or_default();
+        for (resource, value) in resources {
+            match resource.as_str() {
+                "hca_handle" => device_stats.hca_handles_limit = value,
+                "hca_object" => device_stats.hca_objects_limit = value,
+                _ => {}
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Resources of an rdma device and their values
+type RdmaResources = Vec<(String, u64)>;
+
+/// Parses rdma.current and rdma.max, e.g. "mlx4_0 hca_handle=2 hca_object=max"
+fn parse_rdma_file(path: &Path) -> Result<Vec<(String, RdmaResources)>, RdmaStatsError> {
+    common::read_cgroup_file(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = fields
+                .next()
+                .ok_or_else(|| RdmaStatsError::DoesNotConform {
+                    path: path.to_path_buf(),
+                })?;
+            let resources = fields
+                .map(|field| {
+                    let (resource, value) =
+                        field
+                            .split_once('=')
+                            .ok_or_else(|| RdmaStatsError::DoesNotConform {
+                                path: path.to_path_buf(),
+                            })?;
+                    let value = match value {
+                        "max" => u64::MAX,
+                        value => value.parse().map_err(|err| RdmaStatsError::FailedToParse {
+                            value: value.to_owned(),
+                            path: path.to_path_buf(),
+                            err,
+                        })?,
+                    };
+                    Ok((resource.to_owned(), value))
+                })
+                .collect::<Result<_, RdmaStatsError>>()?;
+            Ok((device.to_owned(), resources))
+        })
+        .collect()
+}
+
+/// Returns the Pressure Stall Information contained in a `*.pressure` file.
+/// Kernels that are built without PSI support, or that have it disabled on
+/// the command line, either don't provide the file or fail to read it. In
+/// that case empty stats are reported instead of an error.
 pub fn psi_stats(psi_file: &Path) -> Result<PSIStats, WrappedIoError> {
     let mut stats = PSIStats::default();
 
-    let psi = common::read_cgroup_file(psi_file)?;
+    let psi = match common::read_cgroup_file(psi_file) {
+        Ok(psi) => psi,
+        Err(err)
+            if matches!(
+                err.inner().raw_os_error().map(Errno::from_raw),
+                Some(Errno::ENOENT) | Some(Errno::EOPNOTSUPP)
+            ) =>
+        {
+            tracing::debug!(?psi_file, "pressure stall information is not available");
+            return Ok(stats);
+        }
+        Err(err) => return Err(err),
+    };
+
     for line in psi.lines() {
-        match &line[0..4] {
-            "some" => stats.some = parse_psi(&line[4..], psi_file)?,
-            "full" => stats.full = parse_psi(&line[4..], psi_file)?,
+        match line.split_once(' ') {
+            Some(("some", data)) => stats.some = parse_psi(data, psi_file)?,
+            Some(("full", data)) => stats.full = parse_psi(data, psi_file)?,
             _ => continue,
         }
     }
@@ -463,6 +965,12 @@ fn parse_psi(stat_line: &str, path: &Path) -> Result<PSIData, WrappedIoError> {
                     .map_err(|err| Error::new(ErrorKind::InvalidData, err))
                     .wrap_other(path)?
             }
+            Some(("total", v)) => {
+                psi_data.total = v
+                    .parse()
+                    .map_err(|err| Error::new(ErrorKind::InvalidData, err))
+                    .wrap_other(path)?
+            }
             _ => continue,
         }
     }
@@ -520,6 +1028,41 @@ mod tests {
         assert!(value.is_err());
     }
 
+    #[test]
+    fn test_read_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let small = set_fixture(tmp.path(), "small_file", "1200\n").unwrap();
+        // larger than the buffer of a single batched read
+        let large_content = "key 1\n".repeat(10000);
+        let large = set_fixture(tmp.path(), "large_file", &large_content).unwrap();
+        let missing = tmp.path().join("missing_file");
+
+        let contents = read_files(&[small, large, missing]);
+        assert_eq!(contents.len(), 3);
+        assert_eq!(contents[0].as_ref().unwrap(), "1200\n");
+        assert_eq!(contents[1].as_ref().unwrap(), &large_content);
+        assert!(contents[2].is_err());
+    }
+
+    #[test]
+    fn test_with_prefetched() {
+        let tmp = tempfile::tempdir().unwrap();
+        let current = set_fixture(tmp.path(), "pids.current", "3\n").unwrap();
+        let weight = set_fixture(tmp.path(), "cpu.weight", "100\n").unwrap();
+
+        let stats = with_prefetched(&[(tmp.path(), "pids")], || {
+            // the files of the controller are read before
+            fs::write(&current, "5\n").unwrap();
+            fs::write(&weight, "200\n").unwrap();
+            (
+                parse_single_value(&current).unwrap(),
+                parse_single_value(&weight).unwrap(),
+            )
+        });
+        assert_eq!(stats, (3, 200));
+        assert_eq!(parse_single_value(&current).unwrap(), 5);
+    }
+
     #[test]
     fn test_parse_flat_keyed_data() {
         let tmp = tempfile::tempdir().unwrap();
@@ -663,12 +1206,111 @@ mod tests {
         assert!(result.is_err());
     }
 
+    fn set_sysfs_device(sys: &Path, device: &str, number: &str, name: &str, partition: bool) {
+        let dir = sys.join("devices").join(device);
+        fs::create_dir_all(&dir).unwrap();
+        set_fixture(&dir, "dev", &format!("{number}\n")).unwrap();
+        set_fixture(&dir, "uevent", &format!("MAJOR=8\nDEVNAME={name}\n")).unwrap();
+        if partition {
+            set_fixture(&dir, "partition", "1\n").unwrap();
+        }
+        std::os::unix::fs::symlink(&dir, sys.join("dev/block").join(number)).unwrap();
+    }
+
+    #[test]
+    fn test_block_device() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("dev/block")).unwrap();
+        set_sysfs_device(tmp.path(), "sda", "8:0", "sda", false);
+        set_sysfs_device(tmp.path(), "sda/sda1", "8:1", "sda1", true);
+        let partitions = set_fixture(
+            tmp.path(),
+            "partitions",
+            "major minor  #blocks  name\n\n 259        0  500107608 nvme0n1\n",
+        )
+        .unwrap();
+
+        let lookup = |major, minor| {
+            block_device_in(&tmp.path().join("dev/block"), &partitions, major, minor)
+        };
+        assert_eq!(
+            lookup(8, 0),
+            Some(BlockDevice {
+                major: 8,
+                minor: 0,
+                name: "sda".to_owned(),
+                disk: None,
+            })
+        );
+        assert_eq!(lookup(8, 1).unwrap().disk, Some((8, 0)));
+        assert_eq!(lookup(259, 0).unwrap().name, "nvme0n1");
+        assert_eq!(lookup(7, 0), None);
+    }
+
+    #[test]
+    fn test_aggregate_blkio_stats() {
+        let stat = |major, minor, op_type: &str, value| BlkioDeviceStat {
+            major,
+            minor,
+            op_type: Some(op_type.to_owned()),
+            value,
+        };
+        let device = |minor, name: &str, disk| BlockDevice {
+            major: 8,
+            minor,
+            name: name.to_owned(),
+            disk,
+        };
+        let mut stats = BlkioStats {
+            service_bytes: vec![
+                stat(8, 1, "Read", 10),
+                stat(8, 2, "Read", 20),
+                stat(8, 1, "Write", 5),
+                stat(7, 0, "Read", 1),
+            ],
+            serviced: vec![stat(8, 1, "read", 1), stat(8, 2, "read", 2)],
+            ..Default::default()
+        };
+
+        stats.resolve_devices_with(|major, minor| match (major, minor) {
+            (8, 0) => Some(device(0, "sda", None)),
+            (8, 1) => Some(device(1, "sda1", Some((8, 0)))),
+            (8, 2) => Some(device(2, "sda2", Some((8, 0)))),
+            _ => None,
+        });
+        assert_eq!(
+            stats.devices,
+            vec![
+                device(0, "sda", None),
+                device(1, "sda1", Some((8, 0))),
+                device(2, "sda2", Some((8, 0))),
+            ]
+        );
+
+        stats.aggregate_partitions();
+        assert_eq!(stats.devices, vec![device(0, "sda", None)]);
+        assert_eq!(
+            stats.service_bytes,
+            vec![
+                stat(7, 0, "Read", 1),
+                stat(8, 0, "Read", 30),
+                stat(8, 0, "Write", 5),
+            ]
+        );
+        assert_eq!(stats.serviced, vec![stat(8, 0, "read", 3)]);
+
+        let totals = stats.compute_totals();
+        assert_eq!(totals.service_bytes.get("read"), Some(&31));
+        assert_eq!(totals.service_bytes.get("write"), Some(&5));
+        assert_eq!(totals.serviced.get("read"), Some(&3));
+    }
+
     #[test]
     fn test_parse_psi_full_stats() {
         let tmp = tempfile::tempdir().unwrap();
         let file_content = [
-            "some avg10=80.00 avg60=50.00 avg300=90.00 total=0",
-            "full avg10=10.00 avg60=30.00 avg300=50.00 total=0",
+            "some avg10=80.00 avg60=50.00 avg300=90.00 total=1234",
+            "full avg10=10.00 avg60=30.00 avg300=50.00 total=567",
         ]
         .join("\n");
         let psi_file = set_fixture(tmp.path(), "psi.pressure", &file_content).unwrap();
@@ -680,12 +1322,14 @@ mod tests {
                 some: PSIData {
                     avg10: 80.0,
                     avg60: 50.0,
-                    avg300: 90.0
+                    avg300: 90.0,
+                    total: 1234,
                 },
                 full: PSIData {
                     avg10: 10.0,
                     avg60: 30.0,
-                    avg300: 50.0
+                    avg300: 50.0,
+                    total: 567,
                 },
             }
         )
@@ -704,10 +1348,114 @@ mod tests {
                 some: PSIData {
                     avg10: 80.0,
                     avg60: 50.0,
-                    avg300: 90.0
+                    avg300: 90.0,
+                    total: 0,
                 },
                 full: PSIData::default(),
             }
         )
     }
+
+    #[test]
+    fn test_parse_psi_missing_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let result = psi_stats(&tmp.path().join("cpu.pressure")).unwrap();
+        assert_eq!(result, PSIStats::default());
+    }
+
+    #[test]
+    fn test_parse_psi_malformed_lines() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file_content = ["", "so", "full avg10=1.00 avg60=2.00 avg300=3.00 total=4"].join("\n");
+        let psi_file = set_fixture(tmp.path(), "psi.pressure", &file_content).unwrap();
+
+        let result = psi_stats(&psi_file).unwrap();
+        assert_eq!(result.some, PSIData::default());
+        assert_eq!(
+            result.full,
+            PSIData {
+                avg10: 1.0,
+                avg60: 2.0,
+                avg300: 3.0,
+                total: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn test_rdma_stats() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(
+            tmp.path(),
+            "rdma.current",
+            "mlx4_0 hca_handle=1 hca_object=20\nocrdma1 hca_handle=0 hca_object=0\n",
+        )
+        .unwrap();
+        set_fixture(
+            tmp.path(),
+            "rdma.max",
+            "mlx4_0 hca_handle=2 hca_object=2000\nocrdma1 hca_handle=3 hca_object=max\n",
+        )
+        .unwrap();
+
+        let stats = rdma_stats(tmp.path()).unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            stats["mlx4_0"],
+            RdmaStats {
+                hca_handles: 1,
+                hca_objects: 20,
+                hca_handles_limit: 2,
+                hca_objects_limit: 2000,
+            }
+        );
+        assert_eq!(stats["ocrdma1"].hca_objects_limit, u64::MAX);
+
+        set_fixture(tmp.path(), "rdma.max", "mlx4_0 hca_handle\n").unwrap();
+        assert!(rdma_stats(tmp.path()).is_err());
+    }
+
+    #[test]
+    fn test_misc_stats() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), "misc.current", "sgx_epc 4096\nsev 1\n").unwrap();
+        set_fixture(tmp.path(), "misc.max", "sgx_epc 65536\nsev max\n").unwrap();
+        set_fixture(tmp.path(), "misc.events", "sgx_epc.max 3\nsev.max 0\n").unwrap();
+
+        let stats = misc_stats(tmp.path()).unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            stats["sgx_epc"],
+            MiscStats {
+                usage: 4096,
+                limit: 65536,
+                events: 3,
+            }
+        );
+        assert_eq!(
+            stats["sev"],
+            MiscStats {
+                usage: 1,
+                limit: u64::MAX,
+                events: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_misc_stats_without_events() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), "misc.current", "sgx_epc 0\n").unwrap();
+        set_fixture(tmp.path(), "misc.max", "sgx_epc max\n").unwrap();
+
+        let stats = misc_stats(tmp.path()).unwrap();
+        assert_eq!(stats["sgx_epc"].limit, u64::MAX);
+        assert_eq!(stats["sgx_epc"].events, 0);
+
+        set_fixture(tmp.path(), "misc.max", "sgx_epc\n").unwrap();
+        assert!(misc_stats(tmp.path()).is_err());
+
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(misc_stats(tmp.path()).unwrap().is_empty());
+    }
 }
@@ -0,0 +1,78 @@
+use crate::common::{AnyCgroupManager, CgroupErrorKind, CgroupManager};
+
+#[derive(thiserror::Error, Debug)]
+pub enum HybridManagerError {
+    #[error("v1 and v2 cgroup features are required, but were not enabled during compile time")]
+    NotEnabled,
+}
+
+impl HybridManagerError {
+    pub fn kind(&self) -> CgroupErrorKind {
+        CgroupErrorKind::NotSupported
+    }
+}
+
+pub struct Manager {}
+
+impl Manager {
+    pub fn any(self) -> AnyCgroupManager {
+        AnyCgroupManager::Hybrid(Box::new(self))
+    }
+}
+
+impl CgroupManager for Manager {
+    type Error = HybridManagerError;
+
+    fn add_task(&self, _pid: nix::unistd::Pid) -> Result<(), Self::Error> {
+        Err(HybridManagerError::NotEnabled)
+    }
+
+    fn open_task_cgroup(&self) -> Result<Option<std::fs::File>, Self::Error> {
+        Err(HybridManagerError::NotEnabled)
+    }
+
+    fn apply(&self, _controller_opt: &crate::common::ControllerOpt) -> Result<(), Self::Error> {
+        Err(HybridManagerError::NotEnabled)
+    }
+
+    fn remove(&self) -> Result<(), Self::Error> {
+        Err(HybridManagerError::NotEnabled)
+    }
+
+    fn freeze(&self, _state: crate::common::FreezerState) -> Result<(), Self::Error> {
+        Err(HybridManagerError::NotEnabled)
+    }
+
+    fn stats(&self) -> Result<crate::stats::Stats, Self::Error> {
+        Err(HybridManagerError::NotEnabled)
+    }
+
+    fn get_all_pids(&self) -> Result<Vec<nix::unistd::Pid>, Self::Error> {
+        Err(HybridManagerError::NotEnabled)
+    }
+
+    fn oom_notifier(&self) -> Result<crate::oom::OomNotifier, Self::Error> {
+        Err(HybridManagerError::NotEnabled)
+    }
+
+    fn events_watcher(&self) -> Result<crate::events::CgroupEventsWatcher, Self::Error> {
+        Err(HybridManagerError::NotEnabled)
+    }
+
+    fn kill_all(&self) -> Result<bool, Self::Error> {
+        Err(HybridManagerError::NotEnabled)
+    }
+
+    fn reclaim_memory(&self, _bytes: u64) -> Result<bool, Self::Error> {
+        Err(HybridManagerError::NotEnabled)
+    }
+
+    fn delegate(
+        &self,
+        _owner: nix::unistd::Uid,
+        _group: nix::unistd::Gid,
+        _controllers: &[String],
+    ) -> Result<bool, Self::Error> {
+        Err(HybridManagerError::NotEnabled)
+    }
+}
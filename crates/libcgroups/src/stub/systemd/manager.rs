@@ -1,4 +1,4 @@
-use crate::common::{AnyCgroupManager, CgroupManager};
+use crate::common::{AnyCgroupManager, CgroupErrorKind, CgroupManager};
 
 #[derive(thiserror::Error, Debug)]
 pub enum SystemdManagerError {
@@ -6,8 +6,18 @@ pub enum SystemdManagerError {
     NotEnabled,
 }
 
+impl SystemdManagerError {
+    pub fn kind(&self) -> CgroupErrorKind {
+        CgroupErrorKind::NotSupported
+    }
+}
+
 pub struct Manager {}
 
+pub fn unit_name(_cgroups_path: &std::path::Path) -> Option<String> {
+    None
+}
+
 impl Manager {
     pub fn any(self) -> AnyCgroupManager {
         AnyCgroupManager::Systemd(Box::new(self))
@@ -21,6 +31,10 @@ impl CgroupManager for Manager {
         Err(SystemdManagerError::NotEnabled)
     }
 
+    fn open_task_cgroup(&self) -> Result<Option<std::fs::File>, Self::Error> {
+        Err(SystemdManagerError::NotEnabled)
+    }
+
     fn apply(&self, _controller_opt: &crate::common::ControllerOpt) -> Result<(), Self::Error> {
         Err(SystemdManagerError::NotEnabled)
     }
@@ -40,4 +54,29 @@ impl CgroupManager for Manager {
     fn get_all_pids(&self) -> Result<Vec<nix::unistd::Pid>, Self::Error> {
         Err(SystemdManagerError::NotEnabled)
     }
+
+    fn oom_notifier(&self) -> Result<crate::oom::OomNotifier, Self::Error> {
+        Err(SystemdManagerError::NotEnabled)
+    }
+
+    fn events_watcher(&self) -> Result<crate::events::CgroupEventsWatcher, Self::Error> {
+        Err(SystemdManagerError::NotEnabled)
+    }
+
+    fn kill_all(&self) -> Result<bool, Self::Error> {
+        Err(SystemdManagerError::NotEnabled)
+    }
+
+    fn reclaim_memory(&self, _bytes: u64) -> Result<bool, Self::Error> {
+        Err(SystemdManagerError::NotEnabled)
+    }
+
+    fn delegate(
+        &self,
+        _owner: nix::unistd::Uid,
+        _group: nix::unistd::Gid,
+        _controllers: &[String],
+    ) -> Result<bool, Self::Error> {
+        Err(SystemdManagerError::NotEnabled)
+    }
 }
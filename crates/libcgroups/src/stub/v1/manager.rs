@@ -1,4 +1,4 @@
-use crate::common::{AnyCgroupManager, CgroupManager};
+use crate::common::{AnyCgroupManager, CgroupErrorKind, CgroupManager};
 
 #[derive(thiserror::Error, Debug)]
 pub enum V1ManagerError {
@@ -6,6 +6,12 @@ pub enum V1ManagerError {
     NotEnabled,
 }
 
+impl V1ManagerError {
+    pub fn kind(&self) -> CgroupErrorKind {
+        CgroupErrorKind::NotSupported
+    }
+}
+
 pub struct Manager {}
 
 impl Manager {
@@ -21,6 +27,10 @@ impl CgroupManager for Manager {
         Err(V1ManagerError::NotEnabled)
     }
 
+    fn open_task_cgroup(&self) -> Result<Option<std::fs::File>, Self::Error> {
+        Err(V1ManagerError::NotEnabled)
+    }
+
     fn apply(&self, _controller_opt: &crate::common::ControllerOpt) -> Result<(), Self::Error> {
         Err(V1ManagerError::NotEnabled)
     }
@@ -40,4 +50,29 @@ impl CgroupManager for Manager {
     fn get_all_pids(&self) -> Result<Vec<nix::unistd::Pid>, Self::Error> {
         Err(V1ManagerError::NotEnabled)
     }
+
+    fn oom_notifier(&self) -> Result<crate::oom::OomNotifier, Self::Error> {
+        Err(V1ManagerError::NotEnabled)
+    }
+
+    fn events_watcher(&self) -> Result<crate::events::CgroupEventsWatcher, Self::Error> {
+        Err(V1ManagerError::NotEnabled)
+    }
+
+    fn kill_all(&self) -> Result<bool, Self::Error> {
+        Err(V1ManagerError::NotEnabled)
+    }
+
+    fn reclaim_memory(&self, _bytes: u64) -> Result<bool, Self::Error> {
+        Err(V1ManagerError::NotEnabled)
+    }
+
+    fn delegate(
+        &self,
+        _owner: nix::unistd::Uid,
+        _group: nix::unistd::Gid,
+        _controllers: &[String],
+    ) -> Result<bool, Self::Error> {
+        Err(V1ManagerError::NotEnabled)
+    }
 }
@@ -6,6 +6,10 @@ pub enum ControllerType {
     Io,
     Memory,
     Pids,
+    /// Not known to systemd, the limits are written to the cgroupfs directly
+    HugeTlb,
+    /// Not known to systemd, the limits are written to the cgroupfs directly
+    Rdma,
 }
 
 impl Display for ControllerType {
@@ -16,6 +20,8 @@ impl Display for ControllerType {
             ControllerType::Io => "io",
             ControllerType::Memory => "memory",
             ControllerType::Pids => "pids",
+            ControllerType::HugeTlb => "hugetlb",
+            ControllerType::Rdma => "rdma",
         };
 
         write!(f, "{print}")
@@ -30,6 +36,8 @@ impl AsRef<str> for ControllerType {
             ControllerType::Io => "io",
             ControllerType::Memory => "memory",
             ControllerType::Pids => "pids",
+            ControllerType::HugeTlb => "hugetlb",
+            ControllerType::Rdma => "rdma",
         }
     }
 }
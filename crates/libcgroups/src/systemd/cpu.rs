@@ -10,6 +10,12 @@ pub const CPU_WEIGHT: &str = "CPUWeight";
 pub const CPU_QUOTA: &str = "CPUQuotaPerSecUSec";
 pub const CPU_PERIOD: &str = "CPUQuotaPeriodUSec";
 const MICROSECS_PER_SEC: u64 = 1_000_000;
+const DEFAULT_CPU_PERIOD: u64 = 100_000;
+const MAX_CPU_WEIGHT: u64 = 10000;
+// systemd applies the quota with a granularity of 10ms per second
+const CPU_QUOTA_GRANULARITY: u64 = 10_000;
+// CPUQuotaPeriodUSec is only known to systemd since version 242
+const MIN_SYSTEMD_VERSION_PERIOD: u32 = 242;
 
 #[derive(thiserror::Error, Debug)]
 pub enum SystemdCpuError {
@@ -24,12 +30,12 @@ impl Controller for Cpu {
 
     fn apply(
         options: &ControllerOpt,
-        _: u32,
+        systemd_version: u32,
         properties: &mut HashMap<&str, Variant>,
     ) -> Result<(), Self::Error> {
         if let Some(cpu) = options.resources.cpu() {
             tracing::debug!("Applying cpu resource restrictions");
-            Self::apply(cpu, properties)?;
+            Self::apply(cpu, systemd_version, properties)?;
         }
 
         Ok(())
@@ -39,6 +45,7 @@ impl Controller for Cpu {
 impl Cpu {
     fn apply(
         cpu: &LinuxCpu,
+        systemd_version: u32,
         properties: &mut HashMap<&str, Variant>,
     ) -> Result<(), SystemdCpuError> {
         if Self::is_realtime_requested(cpu) {
@@ -52,26 +59,40 @@ impl Cpu {
             }
         }
 
+        if cpu.quota().is_none() && cpu.period().is_none() {
+            return Ok(());
+        }
+
+        let period = match cpu.period() {
+            Some(period) if period > 0 => period,
+            _ => DEFAULT_CPU_PERIOD,
+        };
+
         // if quota is unrestricted set to 'max'
         let mut quota = u64::MAX;
         if let Some(specified_quota) = cpu.quota() {
             if specified_quota > 0 {
-                let period = cpu.period().unwrap_or(100_000);
-
                 // cpu quota in systemd must be specified as number of
-                // microseconds per second of cpu time.
+                // microseconds per second of cpu time. It is rounded up, so
+                // that systemd doesn't restrict the container more than
+                // requested.
                 quota = specified_quota as u64 * MICROSECS_PER_SEC / period;
+                if quota % CPU_QUOTA_GRANULARITY != 0 {
+                    quota = (quota / CPU_QUOTA_GRANULARITY + 1) * CPU_QUOTA_GRANULARITY;
+                }
             }
         }
         properties.insert(CPU_QUOTA, Variant::U64(quota));
 
-        let mut period: u64 = 100_000;
-        if let Some(specified_period) = cpu.period() {
-            if specified_period > 0 {
-                period = specified_period;
-            }
+        if systemd_version >= MIN_SYSTEMD_VERSION_PERIOD {
+            properties.insert(CPU_PERIOD, Variant::U64(period));
+        } else if cpu.period().is_some() {
+            tracing::warn!(
+                systemd_version,
+                "cpu period requires systemd version {} or newer, ignoring it",
+                MIN_SYSTEMD_VERSION_PERIOD
+            );
         }
-        properties.insert(CPU_PERIOD, Variant::U64(period));
 
         Ok(())
     }
@@ -86,7 +107,8 @@ pub fn convert_shares_to_cgroup2(shares: u64) -> u64 {
         return 0;
     }
 
-    1 + ((shares.saturating_sub(2)) * 9999) / 262142
+    let weight = 1 + ((shares.saturating_sub(2)) * 9999) / 262142;
+    weight.min(MAX_CPU_WEIGHT)
 }
 
 #[cfg(test)]
@@ -108,7 +130,7 @@ mod tests {
         let mut properties: HashMap<&str, Variant> = HashMap::new();
 
         // act
-        Cpu::apply(&cpu, &mut properties)?;
+        Cpu::apply(&cpu, 245, &mut properties)?;
 
         // assert
         assert!(properties.contains_key(CPU_WEIGHT));
@@ -130,7 +152,7 @@ mod tests {
             let mut properties: HashMap<&str, Variant> = HashMap::new();
 
             // act
-            Cpu::apply(&cpu, &mut properties)?;
+            Cpu::apply(&cpu, 245, &mut properties)?;
 
             // assert
             assert!(properties.contains_key(CPU_QUOTA));
@@ -154,7 +176,7 @@ mod tests {
             let mut properties: HashMap<&str, Variant> = HashMap::new();
 
             // act
-            Cpu::apply(&cpu, &mut properties)?;
+            Cpu::apply(&cpu, 245, &mut properties)?;
 
             // assert
             assert!(properties.contains_key(CPU_PERIOD));
@@ -165,4 +187,78 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_set_shares_range() -> Result<()> {
+        let shares: Vec<(u64, u64)> = vec![(2, 1), (1024, 39), (262144, 10000), (1_000_000, 10000)];
+
+        for (shares, weight) in shares {
+            let cpu = LinuxCpuBuilder::default()
+                .shares(shares)
+                .build()
+                .context("build cpu spec")?;
+            let mut properties: HashMap<&str, Variant> = HashMap::new();
+
+            Cpu::apply(&cpu, 245, &mut properties)?;
+
+            let cpu_weight = &properties[CPU_WEIGHT];
+            let val = recast!(cpu_weight, Variant)?;
+            assert_eq!(val, Variant::U64(weight));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_quota_rounded_up() -> Result<()> {
+        let cpu = LinuxCpuBuilder::default()
+            .quota(33_333)
+            .period(100_000u64)
+            .build()
+            .context("build cpu spec")?;
+        let mut properties: HashMap<&str, Variant> = HashMap::new();
+
+        Cpu::apply(&cpu, 245, &mut properties)?;
+
+        let cpu_quota = &properties[CPU_QUOTA];
+        let val = recast!(cpu_quota, Variant)?;
+        assert_eq!(val, Variant::U64(340_000));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_period_requires_systemd_242() -> Result<()> {
+        let cpu = LinuxCpuBuilder::default()
+            .quota(50_000)
+            .period(200_000u64)
+            .build()
+            .context("build cpu spec")?;
+        let mut properties: HashMap<&str, Variant> = HashMap::new();
+
+        Cpu::apply(&cpu, 241, &mut properties)?;
+
+        assert!(!properties.contains_key(CPU_PERIOD));
+        let cpu_quota = &properties[CPU_QUOTA];
+        let val = recast!(cpu_quota, Variant)?;
+        assert_eq!(val, Variant::U64(250_000));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_quota_and_period() -> Result<()> {
+        let cpu = LinuxCpuBuilder::default()
+            .shares(1024u64)
+            .build()
+            .context("build cpu spec")?;
+        let mut properties: HashMap<&str, Variant> = HashMap::new();
+
+        Cpu::apply(&cpu, 245, &mut properties)?;
+
+        assert!(!properties.contains_key(CPU_QUOTA));
+        assert!(!properties.contains_key(CPU_PERIOD));
+
+        Ok(())
+    }
 }
@@ -20,6 +20,12 @@ pub trait SystemdClient {
 
     fn stop_transient_unit(&self, unit_name: &str) -> Result<(), SystemdClientError>;
 
+    /// Freezes all processes of the unit, available since systemd 246
+    fn freeze_unit(&self, unit_name: &str) -> Result<(), SystemdClientError>;
+
+    /// Thaws all processes of a frozen unit, available since systemd 246
+    fn thaw_unit(&self, unit_name: &str) -> Result<(), SystemdClientError>;
+
     fn set_unit_properties(
         &self,
         unit_name: &str,
@@ -1,11 +1,13 @@
 use std::collections::HashMap;
 use std::io::{IoSlice, IoSliceMut};
-use std::os::fd::AsRawFd;
+use std::os::fd::{AsFd, AsRawFd};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
 
 use nix::errno::Errno;
-use nix::sys::socket;
+use nix::sys::socket::{self, sockopt};
+use nix::sys::time::TimeVal;
 
 use super::client::SystemdClient;
 use super::message::*;
@@ -14,6 +16,9 @@ use super::utils::{DbusError, Result, SystemdClientError};
 use crate::systemd::dbus_native::serialize::{DbusSerialize, Structure, Variant};
 
 const REPLY_BUF_SIZE: usize = 128; // seems good enough tradeoff between extra size and repeated calls
+/// Time after which a call is given up, this is the default timeout of method
+/// calls in libdbus and sd-bus as well
+const CALL_TIMEOUT: Duration = Duration::from_secs(25);
 
 /// NOTE that this is meant for a single-threaded use, and concurrent
 /// usage can cause errors, primarily because then the message received over
@@ -47,17 +52,21 @@ fn uid_to_hex_str(uid: u32) -> String {
 
 fn parse_dbus_address(env_value: String) -> Result<String> {
     // as per spec, the env var can have multiple addresses separated by ;
-    let addr_list: Vec<_> = env_value.split(';').collect();
-    for addr in addr_list {
-        if let Some(s) = addr.strip_prefix("unix:path=") {
-            if !std::path::PathBuf::from(s).exists() {
-                continue;
+    // each of which is a transport followed by comma separated key=value
+    // pairs, e.g. unix:path=/run/user/1000/bus,guid=...
+    for addr in env_value.split(';') {
+        let params = match addr.strip_prefix("unix:") {
+            Some(params) => params,
+            None => continue,
+        };
+        for param in params.split(',') {
+            match param.split_once('=') {
+                Some(("path", path)) if std::path::Path::new(path).exists() => {
+                    return Ok(path.to_owned())
+                }
+                Some(("abstract", name)) => return Ok(name.to_owned()),
+                _ => {}
             }
-            return Ok(s.to_owned());
-        }
-
-        if let Some(s) = addr.strip_prefix("unix:abstract=") {
-            return Ok(s.to_owned());
         }
     }
     // we do not support unix:runtime=
@@ -81,6 +90,13 @@ fn get_session_bus_address() -> Result<String> {
         return Ok(s);
     }
 
+    // the well known location of the bus of the user manager, which is used
+    // when the session environment is not available, e.g. under sudo -u
+    let s = format!("/run/user/{}/bus", nix::unistd::geteuid());
+    if std::path::PathBuf::from(&s).exists() {
+        return Ok(s);
+    }
+
     Err(
         DbusError::BusAddressError("could not find dbus session bus address from env".into())
             .into(),
@@ -98,7 +114,32 @@ fn get_system_bus_address() -> Result<String> {
     Ok("/var/run/dbus/system_bus_socket".into())
 }
 
+// Checks if the process runs in a user namespace other than the initial one,
+// whose uid map covers the whole range of uids.
+fn in_user_namespace() -> bool {
+    match std::fs::read_to_string("/proc/self/uid_map") {
+        Ok(content) => content.split_whitespace().collect::<Vec<_>>() != ["0", "0", "4294967295"],
+        Err(_) => false,
+    }
+}
+
+// Limits how long sending and receiving on the socket may block, so that an
+// unresponsive bus doesn't block youki forever.
+fn set_timeout<Fd: AsFd>(socket: &Fd, timeout: Duration) -> Result<()> {
+    let timeout = TimeVal::new(timeout.as_secs() as _, timeout.subsec_micros() as _);
+    socket::setsockopt(socket, sockopt::ReceiveTimeout, &timeout)?;
+    socket::setsockopt(socket, sockopt::SendTimeout, &timeout)?;
+    Ok(())
+}
+
 fn get_actual_uid() -> Result<u32> {
+    // Outside of a user namespace the session bus belongs to the effective
+    // user. Within one, e.g. when running under rootlesskit, the uid of the
+    // owner of the bus has to be looked up, as it differs from our own uid.
+    if !in_user_namespace() {
+        return Ok(nix::unistd::geteuid().as_raw());
+    }
+
     let output = std::process::Command::new("busctl")
         .arg("--user")
         .arg("--no-pager")
@@ -126,11 +167,18 @@ fn get_actual_uid() -> Result<u32> {
     Ok(uid)
 }
 
+impl Drop for DbusConnection {
+    fn drop(&mut self) {
+        let _ = nix::unistd::close(self.socket);
+    }
+}
+
 impl DbusConnection {
     /// Open a new dbus connection to given address
     /// authenticating as user with given uid
     pub fn new(addr: &str, uid: u32, system: bool) -> Result<Self> {
-        // Use ManuallyDrop to keep the socket open.
+        // Use ManuallyDrop to keep the socket open, it is closed once the
+        // connection is dropped.
         let socket = std::mem::ManuallyDrop::new(socket::socket(
             socket::AddressFamily::Unix,
             socket::SockType::Stream,
@@ -138,6 +186,8 @@ impl DbusConnection {
             None,
         )?);
 
+        set_timeout(&*socket, CALL_TIMEOUT)?;
+
         let addr = socket::UnixAddr::new(addr)?;
         socket::connect(socket.as_raw_fd(), &addr)?;
         let mut dbus = Self {
@@ -259,7 +309,10 @@ impl DbusConnection {
 
             let reply_rcvd = match reply_res {
                 Ok(msg) => msg,
-                Err(Errno::EAGAIN) => continue,
+                Err(Errno::EINTR) => continue,
+                // the socket is blocking, so this is only returned once the
+                // receive timeout expired
+                Err(Errno::EAGAIN) => return Err(DbusError::Timeout(CALL_TIMEOUT).into()),
                 Err(e) => return Err(e.into()),
             };
             let received_byte_count = reply_rcvd.bytes;
@@ -447,6 +500,28 @@ impl SystemdClient for DbusConnection {
         Ok(())
     }
 
+    fn freeze_unit(&self, unit_name: &str) -> Result<()> {
+        let proxy = self.create_proxy();
+
+        proxy
+            .freeze_unit(unit_name)
+            .map_err(|err| SystemdClientError::FailedFreeze {
+                err: Box::new(err),
+                unit_name: unit_name.into(),
+            })
+    }
+
+    fn thaw_unit(&self, unit_name: &str) -> Result<()> {
+        let proxy = self.create_proxy();
+
+        proxy
+            .thaw_unit(unit_name)
+            .map_err(|err| SystemdClientError::FailedThaw {
+                err: Box::new(err),
+                unit_name: unit_name.into(),
+            })
+    }
+
     fn set_unit_properties(
         &self,
         unit_name: &str,
@@ -497,10 +572,59 @@ impl SystemdClient for DbusConnection {
 
 #[cfg(test)]
 mod tests {
+    use std::os::fd::IntoRawFd;
+    use std::os::unix::net::UnixStream;
+    use std::sync::atomic::AtomicU32;
+    use std::time::Duration;
+
     use nix::unistd::getuid;
 
-    use super::super::utils::Result;
-    use super::{uid_to_hex_str, DbusConnection, SystemdClientError};
+    use super::super::utils::{DbusError, Result};
+    use super::{
+        parse_dbus_address, set_timeout, uid_to_hex_str, DbusConnection, SystemdClientError,
+    };
+
+    #[test]
+    fn test_receive_timeout() {
+        let (socket, _peer) = UnixStream::pair().unwrap();
+        set_timeout(&socket, Duration::from_millis(10)).unwrap();
+        // the connection owns the socket and closes it when dropped
+        let conn = DbusConnection {
+            system: false,
+            socket: socket.into_raw_fd(),
+            id: None,
+            msg_ctr: AtomicU32::new(0),
+        };
+
+        let err = conn.receive_complete_response().unwrap_err();
+        assert!(matches!(
+            err,
+            SystemdClientError::DBus(DbusError::Timeout(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_dbus_address() {
+        let tmp = tempfile::tempdir().unwrap();
+        let bus = tmp.path().join("bus");
+        std::fs::write(&bus, "").unwrap();
+        let bus = bus.to_str().unwrap();
+
+        assert_eq!(parse_dbus_address(format!("unix:path={bus}")).unwrap(), bus);
+        assert_eq!(
+            parse_dbus_address(format!("unix:path={bus},guid=0123456789abcdef")).unwrap(),
+            bus
+        );
+        assert_eq!(
+            parse_dbus_address(format!(
+                "unix:path=/does/not/exist;tcp:host=localhost,port=1234;unix:path={bus}"
+            ))
+            .unwrap(),
+            bus
+        );
+        assert!(parse_dbus_address("unix:path=/does/not/exist".to_owned()).is_err());
+        assert!(parse_dbus_address("unix:runtime=yes".to_owned()).is_err());
+    }
 
     #[test]
     fn test_uid_to_hex_str() {
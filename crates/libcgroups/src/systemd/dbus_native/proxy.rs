@@ -202,6 +202,22 @@ impl<'conn> Proxy<'conn> {
         )
     }
 
+    pub fn freeze_unit(&self, name: &str) -> Result<()> {
+        self.method_call::<_, ()>(
+            "org.freedesktop.systemd1.Manager",
+            "FreezeUnit",
+            Some(name.to_string()),
+        )
+    }
+
+    pub fn thaw_unit(&self, name: &str) -> Result<()> {
+        self.method_call::<_, ()>(
+            "org.freedesktop.systemd1.Manager",
+            "ThawUnit",
+            Some(name.to_string()),
+        )
+    }
+
     pub fn set_unit_properties(
         &self,
         name: &str,
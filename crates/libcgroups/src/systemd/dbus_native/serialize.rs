@@ -23,16 +23,28 @@ pub trait DbusSerialize: std::fmt::Debug {
         Self: Sized;
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Value of a dbus property, which is serialized to JSON along with its dbus
+/// signature, e.g. `{"signature": "t", "value": 1024}`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "signature", content = "value")]
 pub enum Variant {
+    #[serde(rename = "s")]
     String(String),
+    #[serde(rename = "b")]
     Bool(bool),
+    #[serde(rename = "t")]
     U64(u64),
+    #[serde(rename = "au")]
     ArrayU32(Vec<u32>),
+    #[serde(rename = "at")]
     ArrayU64(Vec<u64>),
+    #[serde(rename = "a(st)")]
+    ArrayStructU64(Vec<Structure<u64>>),
+    #[serde(rename = "a(ss)")]
+    ArrayStructString(Vec<Structure<String>>),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
 pub struct Structure<T: DbusSerialize> {
     key: String,
     val: T,
@@ -430,6 +442,22 @@ impl DbusSerialize for Variant {
                 buf.push(0);
                 v.serialize(buf);
             }
+            Self::ArrayStructU64(v) => {
+                let sub_type = <Vec<Structure<u64>>>::get_signature();
+                let signature_length = sub_type.len() as u8; // signature length must be < 256
+                buf.push(signature_length);
+                buf.extend_from_slice(sub_type.as_bytes());
+                buf.push(0);
+                v.serialize(buf);
+            }
+            Self::ArrayStructString(v) => {
+                let sub_type = <Vec<Structure<String>>>::get_signature();
+                let signature_length = sub_type.len() as u8; // signature length must be < 256
+                buf.push(signature_length);
+                buf.extend_from_slice(sub_type.as_bytes());
+                buf.push(0);
+                v.serialize(buf);
+            }
             Self::Bool(b) => {
                 let sub_type = bool::get_signature();
                 let signature_length = sub_type.len() as u8; // signature length must be < 256
@@ -469,6 +497,8 @@ impl DbusSerialize for Variant {
         let bool_signature = bool::get_signature();
         let vec32_signature = <Vec<u32>>::get_signature();
         let vec64_signature = <Vec<u64>>::get_signature();
+        let vec_struct64_signature = <Vec<Structure<u64>>>::get_signature();
+        let vec_struct_string_signature = <Vec<Structure<String>>>::get_signature();
         let u64_signature = u64::get_signature();
 
         if signature == string_signature {
@@ -479,6 +509,14 @@ impl DbusSerialize for Variant {
             Ok(Self::ArrayU32(<Vec<u32>>::deserialize(buf, counter)?))
         } else if signature == vec64_signature {
             Ok(Self::ArrayU64(<Vec<u64>>::deserialize(buf, counter)?))
+        } else if signature == vec_struct64_signature {
+            Ok(Self::ArrayStructU64(<Vec<Structure<u64>>>::deserialize(
+                buf, counter,
+            )?))
+        } else if signature == vec_struct_string_signature {
+            Ok(Self::ArrayStructString(
+                <Vec<Structure<String>>>::deserialize(buf, counter)?,
+            ))
         } else if signature == u64_signature {
             Ok(Self::U64(u64::deserialize(buf, counter)?))
         } else {
@@ -20,6 +20,16 @@ pub enum SystemdClientError {
         err: Box<SystemdClientError>,
         unit_name: String,
     },
+    #[error("failed to freeze unit {unit_name}: {err}")]
+    FailedFreeze {
+        err: Box<SystemdClientError>,
+        unit_name: String,
+    },
+    #[error("failed to thaw unit {unit_name}: {err}")]
+    FailedThaw {
+        err: Box<SystemdClientError>,
+        unit_name: String,
+    },
     #[error("could not parse systemd version: {0}")]
     SystemdVersion(ParseIntError),
 }
@@ -38,6 +48,8 @@ pub enum DbusError {
     DeserializationError(String),
     #[error("dbus function call error: {0}")]
     MethodCallErr(String),
+    #[error("dbus call timed out after {0:?}")]
+    Timeout(std::time::Duration),
     #[error("dbus bus address error: {0}")]
     BusAddressError(String),
     #[error("dbus busctl error")]
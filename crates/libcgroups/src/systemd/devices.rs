@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::fs;
+
+use oci_spec::runtime::{LinuxDeviceCgroup, LinuxDeviceType};
+
+use super::controller::Controller;
+use super::dbus_native::serialize::{Structure, Variant};
+use crate::common::{
+    default_allow_devices, default_devices, ControllerOpt, WrapIoResult, WrappedIoError,
+};
+
+pub const DEVICE_POLICY: &str = "DevicePolicy";
+pub const DEVICE_ALLOW: &str = "DeviceAllow";
+
+const PROC_DEVICES: &str = "/proc/devices";
+
+/// Device rules as unit properties. systemd only knows allow lists of
+/// devices, so the rules it is given allow at least the devices the spec
+/// allows. The exact rules are enforced by the eBPF program of the devices
+/// controller of cgroup v2.
+pub struct Devices {}
+
+impl Controller for Devices {
+    type Error = WrappedIoError;
+
+    fn apply(
+        options: &ControllerOpt,
+        _: u32,
+        properties: &mut HashMap<&str, Variant>,
+    ) -> Result<(), Self::Error> {
+        if let Some(devices) = options.resources.devices() {
+            tracing::debug!("Applying device resource restrictions");
+            // the same default rules are added as for the eBPF program
+            let rules: Vec<LinuxDeviceCgroup> = devices
+                .iter()
+                .cloned()
+                .chain(default_devices().iter().map(|d| d.into()))
+                .chain(default_allow_devices())
+                .collect();
+            let proc_devices = fs::read_to_string(PROC_DEVICES).wrap_read(PROC_DEVICES)?;
+            Self::apply(&rules, &proc_devices, properties);
+        }
+
+        Ok(())
+    }
+}
+
+impl Devices {
+    // The rules are applied in order, like by the eBPF program. A rule for all
+    // devices resets the policy, otherwise systemd can only be given the
+    // devices which are allowed if all others are denied.
+    fn apply(
+        rules: &[LinuxDeviceCgroup],
+        proc_devices: &str,
+        properties: &mut HashMap<&str, Variant>,
+    ) {
+        let mut default_allow = false;
+        let mut allowed = Vec::new();
+        for rule in rules {
+            let typ = rule.typ().unwrap_or(LinuxDeviceType::A);
+            if typ == LinuxDeviceType::A && rule.major().is_none() && rule.minor().is_none() {
+                default_allow = rule.allow();
+                allowed.clear();
+                continue;
+            }
+            if default_allow || !rule.allow() {
+                continue;
+            }
+
+            let access = rule.access().clone().unwrap_or_else(|| "rwm".to_owned());
+            for kind in device_kinds(typ) {
+                if let Some(device) = device_specifier(kind, rule, proc_devices) {
+                    allowed.push(Structure::new(device, access.clone()));
+                }
+            }
+        }
+
+        let policy = if default_allow { "auto" } else { "strict" };
+        properties.insert(DEVICE_POLICY, Variant::String(policy.to_owned()));
+        properties.insert(DEVICE_ALLOW, Variant::ArrayStructString(allowed));
+    }
+}
+
+/// Kinds of devices, as named by systemd, matched by a type of rule
+fn device_kinds(typ: LinuxDeviceType) -> &'static [&'static str] {
+    match typ {
+        LinuxDeviceType::A => &["char", "block"],
+        LinuxDeviceType::C | LinuxDeviceType::U => &["char"],
+        LinuxDeviceType::B => &["block"],
+        // fifos are not restricted by cgroups
+        LinuxDeviceType::P => &[],
+    }
+}
+
+/// Returns the devices of the kind matched by the rule in the form of
+/// DeviceAllow, e.g. /dev/char/1:3 or char-pts. A rule for a major number is
+/// given by the name of the driver in /proc/devices, and is skipped if the
+/// driver is not loaded.
+fn device_specifier(kind: &str, rule: &LinuxDeviceCgroup, proc_devices: &str) -> Option<String> {
+    match (rule.major(), rule.minor()) {
+        (Some(major), Some(minor)) => Some(format!("/dev/{kind}/{major}:{minor}")),
+        (Some(major), None) => match driver_name(proc_devices, kind, major) {
+            Some(name) => Some(format!("{kind}-{name}")),
+            None => {
+                tracing::warn!(
+                    "ignoring the rule for {} devices of major {}, which is not in {}",
+                    kind,
+                    major,
+                    PROC_DEVICES
+                );
+                None
+            }
+        },
+        (None, _) => Some(format!("{kind}-*")),
+    }
+}
+
+/// Looks up the name of the driver of a major number in the section of the
+/// kind of devices of /proc/devices
+fn driver_name<'a>(proc_devices: &'a str, kind: &str, major: i64) -> Option<&'a str> {
+    let section = match kind {
+        "char" => "Character devices:",
+        _ => "Block devices:",
+    };
+    proc_devices
+        .lines()
+        .skip_while(|line| *line != section)
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| {
+            let (number, name) = line.trim().split_once(' ')?;
+            if number.parse() == Ok(major) {
+                Some(name)
+            } else {
+                None
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Context, Result};
+    use oci_spec::runtime::LinuxDeviceCgroupBuilder;
+
+    use super::super::dbus_native::serialize::DbusSerialize;
+    use super::*;
+    use crate::recast;
+
+    const PROC_DEVICES_CONTENT: &str = "Character devices:
+  1 mem
+  5 /dev/tty
+136 pts
+180 usb
+
+Block devices:
+  7 loop
+  8 sd
+";
+
+    fn rule(
+        allow: bool,
+        typ: LinuxDeviceType,
+        major: Option<i64>,
+        minor: Option<i64>,
+        access: &str,
+    ) -> Result<LinuxDeviceCgroup> {
+        let mut builder = LinuxDeviceCgroupBuilder::default()
+            .allow(allow)
+            .typ(typ)
+            .access(access);
+        if let Some(major) = major {
+            builder = builder.major(major);
+        }
+        if let Some(minor) = minor {
+            builder = builder.minor(minor);
+        }
+        Ok(builder.build()?)
+    }
+
+    #[test]
+    fn test_device_properties() -> Result<()> {
+        use LinuxDeviceType::{A, B, C, P};
+
+        let deny_all = rule(false, A, None, None, "rwm")?;
+        let allow_all = rule(true, A, None, None, "rwm")?;
+        let cases = vec![
+            ("deny all", vec![deny_all.clone()], "strict", vec![]),
+            ("allow all", vec![allow_all.clone()], "auto", vec![]),
+            (
+                "no rule for all devices",
+                vec![rule(true, C, Some(1), Some(3), "rw")?],
+                "strict",
+                vec![("/dev/char/1:3", "rw")],
+            ),
+            (
+                "allowed devices",
+                vec![
+                    deny_all.clone(),
+                    rule(true, C, Some(1), Some(5), "rwm")?,
+                    rule(true, B, Some(8), Some(0), "r")?,
+                    rule(true, C, Some(136), None, "rwm")?,
+                    rule(true, B, None, None, "m")?,
+                ],
+                "strict",
+                vec![
+                    ("/dev/char/1:5", "rwm"),
+                    ("/dev/block/8:0", "r"),
+                    ("char-pts", "rwm"),
+                    ("block-*", "m"),
+                ],
+            ),
+            (
+                "all kinds of devices",
+                vec![deny_all.clone(), rule(true, A, Some(7), None, "r")?],
+                "strict",
+                vec![("block-loop", "r")],
+            ),
+            (
+                "denied devices are not listed",
+                vec![
+                    deny_all.clone(),
+                    rule(true, C, Some(1), Some(3), "rwm")?,
+                    rule(false, C, Some(1), Some(5), "rwm")?,
+                ],
+                "strict",
+                vec![("/dev/char/1:3", "rwm")],
+            ),
+            (
+                "denied devices are left to the eBPF program",
+                vec![allow_all.clone(), rule(false, C, Some(1), Some(5), "rwm")?],
+                "auto",
+                vec![],
+            ),
+            (
+                "rule for all devices resets the rules",
+                vec![
+                    rule(true, C, Some(1), Some(3), "rwm")?,
+                    allow_all,
+                    rule(true, B, Some(8), Some(0), "rwm")?,
+                    deny_all,
+                    rule(true, C, Some(180), None, "rw")?,
+                ],
+                "strict",
+                vec![("char-usb", "rw")],
+            ),
+            (
+                "unknown driver and fifos",
+                vec![
+                    rule(true, C, Some(4), None, "rwm")?,
+                    rule(true, P, None, None, "rwm")?,
+                ],
+                "strict",
+                vec![],
+            ),
+        ];
+
+        for (name, rules, policy, allowed) in cases {
+            let mut properties = HashMap::new();
+            Devices::apply(&rules, PROC_DEVICES_CONTENT, &mut properties);
+
+            assert_eq!(properties.len(), 2, "{name}");
+            let device_policy = properties.get(DEVICE_POLICY).unwrap();
+            assert_eq!(
+                recast!(device_policy, Variant).context(name)?,
+                Variant::String(policy.to_owned()),
+                "{name}"
+            );
+            let device_allow = properties.get(DEVICE_ALLOW).unwrap();
+            assert_eq!(
+                recast!(device_allow, Variant).context(name)?,
+                Variant::ArrayStructString(
+                    allowed
+                        .into_iter()
+                        .map(|(device, access)| Structure::new(device.into(), access.into()))
+                        .collect()
+                ),
+                "{name}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_devices_are_allowed() -> Result<()> {
+        let resources = oci_spec::runtime::LinuxResourcesBuilder::default()
+            .devices(vec![rule(false, LinuxDeviceType::A, None, None, "rwm")?])
+            .build()?;
+        let options = ControllerOpt {
+            resources: &resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+        let mut properties = HashMap::new();
+
+        <Devices as Controller>::apply(&options, 245, &mut properties).context("apply devices")?;
+
+        let device_allow = properties.get(DEVICE_ALLOW).unwrap();
+        let allowed = match recast!(device_allow, Variant)? {
+            Variant::ArrayStructString(allowed) => allowed,
+            variant => panic!("unexpected {variant:?}"),
+        };
+        for (device, access) in [
+            ("/dev/char/1:3", "rwm"),
+            ("/dev/char/5:1", "rwm"),
+            ("char-*", "m"),
+            ("block-*", "m"),
+        ] {
+            assert!(
+                allowed.contains(&Structure::new(device.into(), access.into())),
+                "{device}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_device_rules() -> Result<()> {
+        let resources = oci_spec::runtime::LinuxResources::default();
+        let options = ControllerOpt {
+            resources: &resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+        let mut properties = HashMap::new();
+
+        <Devices as Controller>::apply(&options, 245, &mut properties).context("apply devices")?;
+
+        assert!(properties.is_empty());
+        Ok(())
+    }
+}
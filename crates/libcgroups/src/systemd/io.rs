@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use oci_spec::runtime::{LinuxBlockIo, LinuxThrottleDevice};
+
+use super::controller::Controller;
+use super::dbus_native::serialize::{Structure, Variant};
+use crate::common::ControllerOpt;
+
+pub const IO_WEIGHT: &str = "IOWeight";
+pub const IO_DEVICE_WEIGHT: &str = "IODeviceWeight";
+pub const IO_READ_BANDWIDTH_MAX: &str = "IOReadBandwidthMax";
+pub const IO_WRITE_BANDWIDTH_MAX: &str = "IOWriteBandwidthMax";
+pub const IO_READ_IOPS_MAX: &str = "IOReadIOPSMax";
+pub const IO_WRITE_IOPS_MAX: &str = "IOWriteIOPSMax";
+
+#[derive(thiserror::Error, Debug)]
+pub enum SystemdIoError {
+    #[error("setting io restrictions requires systemd version greater than 229")]
+    OldSystemd,
+    #[error("cannot set leaf_weight with cgroupv2")]
+    LeafWeight,
+}
+
+pub struct Io {}
+
+impl Controller for Io {
+    type Error = SystemdIoError;
+
+    fn apply(
+        options: &ControllerOpt,
+        systemd_version: u32,
+        properties: &mut HashMap<&str, Variant>,
+    ) -> Result<(), Self::Error> {
+        if let Some(block_io) = options.resources.block_io() {
+            tracing::debug!("Applying io resource restrictions");
+            return Self::apply(block_io, systemd_version, properties);
+        }
+
+        Ok(())
+    }
+}
+
+impl Io {
+    fn apply(
+        block_io: &LinuxBlockIo,
+        systemd_version: u32,
+        properties: &mut HashMap<&str, Variant>,
+    ) -> Result<(), SystemdIoError> {
+        if systemd_version <= 229 {
+            return Err(SystemdIoError::OldSystemd);
+        }
+
+        if let Some(leaf_weight) = block_io.leaf_weight() {
+            if leaf_weight > 0 {
+                return Err(SystemdIoError::LeafWeight);
+            }
+        }
+
+        if let Some(weight) = block_io.weight() {
+            if weight > 0 {
+                properties.insert(IO_WEIGHT, Variant::U64(Self::convert_blkio_weight(weight)));
+            }
+        }
+
+        if let Some(weight_devices) = block_io.weight_device() {
+            let weights: Vec<_> = weight_devices
+                .iter()
+                .filter_map(|wd| {
+                    wd.weight().filter(|w| *w > 0).map(|w| {
+                        Structure::new(
+                            Self::device_path(wd.major(), wd.minor()),
+                            Self::convert_blkio_weight(w),
+                        )
+                    })
+                })
+                .collect();
+            if !weights.is_empty() {
+                properties.insert(IO_DEVICE_WEIGHT, Variant::ArrayStructU64(weights));
+            }
+        }
+
+        let throttles = [
+            (IO_READ_BANDWIDTH_MAX, block_io.throttle_read_bps_device()),
+            (IO_WRITE_BANDWIDTH_MAX, block_io.throttle_write_bps_device()),
+            (IO_READ_IOPS_MAX, block_io.throttle_read_iops_device()),
+            (IO_WRITE_IOPS_MAX, block_io.throttle_write_iops_device()),
+        ];
+        for (property, devices) in throttles {
+            if let Some(devices) = devices {
+                properties.insert(property, Self::throttle_limits(devices));
+            }
+        }
+
+        Ok(())
+    }
+
+    // systemd expects the weight in the cgroup v2 range, while the OCI spec
+    // uses the cgroup v1 range. Convert linearly from [10-1000] to [1-10000]
+    // the same way the v2 io controller does.
+    fn convert_blkio_weight(v: u16) -> u64 {
+        1 + (v.saturating_sub(10) as u64) * 9999 / 990
+    }
+
+    // systemd identifies block devices by path, the /dev/block symlinks
+    // allow addressing them by their device number
+    fn device_path(major: i64, minor: i64) -> String {
+        format!("/dev/block/{major}:{minor}")
+    }
+
+    fn throttle_limits(devices: &[LinuxThrottleDevice]) -> Variant {
+        Variant::ArrayStructU64(
+            devices
+                .iter()
+                .map(|d| Structure::new(Self::device_path(d.major(), d.minor()), d.rate()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Context, Result};
+    use oci_spec::runtime::{
+        LinuxBlockIoBuilder, LinuxResources, LinuxResourcesBuilder, LinuxThrottleDeviceBuilder,
+        LinuxWeightDeviceBuilder,
+    };
+
+    use super::super::dbus_native::serialize::DbusSerialize;
+    use super::*;
+    use crate::recast;
+
+    fn setup(resources: &LinuxResources) -> (ControllerOpt, HashMap<&str, Variant>) {
+        let properties = HashMap::new();
+        let options = ControllerOpt {
+            resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+
+        (options, properties)
+    }
+
+    #[test]
+    fn test_io_weight() -> Result<()> {
+        let resources = LinuxResourcesBuilder::default()
+            .block_io(LinuxBlockIoBuilder::default().weight(1000u16).build()?)
+            .build()?;
+        let (options, mut properties) = setup(&resources);
+
+        <Io as Controller>::apply(&options, 245, &mut properties).context("apply io")?;
+
+        assert_eq!(properties.len(), 1);
+        let weight = properties.get(IO_WEIGHT).unwrap();
+        let val = recast!(weight, Variant)?;
+        assert_eq!(val, Variant::U64(10000));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_io_devices() -> Result<()> {
+        let resources = LinuxResourcesBuilder::default()
+            .block_io(
+                LinuxBlockIoBuilder::default()
+                    .weight_device(vec![LinuxWeightDeviceBuilder::default()
+                        .major(8)
+                        .minor(0)
+                        .weight(10u16)
+                        .build()?])
+                    .throttle_read_bps_device(vec![LinuxThrottleDeviceBuilder::default()
+                        .major(8)
+                        .minor(16)
+                        .rate(1048576u64)
+                        .build()?])
+                    .throttle_write_iops_device(vec![LinuxThrottleDeviceBuilder::default()
+                        .major(253)
+                        .minor(1)
+                        .rate(100u64)
+                        .build()?])
+                    .build()?,
+            )
+            .build()?;
+        let (options, mut properties) = setup(&resources);
+
+        <Io as Controller>::apply(&options, 245, &mut properties).context("apply io")?;
+
+        assert_eq!(properties.len(), 3);
+        let device_weight = properties.get(IO_DEVICE_WEIGHT).unwrap();
+        assert_eq!(
+            recast!(device_weight, Variant)?,
+            Variant::ArrayStructU64(vec![Structure::new("/dev/block/8:0".into(), 1)])
+        );
+        let read_bps = properties.get(IO_READ_BANDWIDTH_MAX).unwrap();
+        assert_eq!(
+            recast!(read_bps, Variant)?,
+            Variant::ArrayStructU64(vec![Structure::new("/dev/block/8:16".into(), 1048576)])
+        );
+        let write_iops = properties.get(IO_WRITE_IOPS_MAX).unwrap();
+        assert_eq!(
+            recast!(write_iops, Variant)?,
+            Variant::ArrayStructU64(vec![Structure::new("/dev/block/253:1".into(), 100)])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_io_old_systemd() -> Result<()> {
+        let resources = LinuxResourcesBuilder::default()
+            .block_io(LinuxBlockIoBuilder::default().weight(500u16).build()?)
+            .build()?;
+        let (options, mut properties) = setup(&resources);
+
+        let result = <Io as Controller>::apply(&options, 229, &mut properties);
+        assert!(matches!(result, Err(SystemdIoError::OldSystemd)));
+
+        Ok(())
+    }
+}
@@ -1,33 +1,46 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::Infallible;
 use std::fmt::{Debug, Display};
-use std::fs::{self};
+use std::fs::{self, File};
 use std::path::Component::RootDir;
 use std::path::{Path, PathBuf};
 
-use nix::unistd::Pid;
+use nix::unistd::{Gid, Pid, Uid};
 use nix::NixPath;
+use serde::Serialize;
 
 use super::controller::Controller;
 use super::controller_type::{ControllerType, CONTROLLER_TYPES};
 use super::cpu::Cpu;
-use super::cpuset::CpuSet;
+use super::cpuset::{CpuSet, SystemdCpuSetError};
 use super::dbus_native::client::SystemdClient;
 use super::dbus_native::dbus::DbusConnection;
 use super::dbus_native::utils::SystemdClientError;
+use super::devices::Devices;
+use super::io::{Io, SystemdIoError};
 use super::memory::Memory;
 use super::pids::Pids;
 use crate::common::{
-    self, AnyCgroupManager, CgroupManager, ControllerOpt, FreezerState, JoinSafelyError,
-    PathBufExt, WrapIoResult, WrappedIoError,
+    self, AnyCgroupManager, CgroupErrorKind, CgroupManager, ControllerOpt, FreezerState,
+    JoinSafelyError, PathBufExt, WrapIoResult, WrappedIoError,
 };
+use crate::events::CgroupEventsWatcher;
+use crate::oom::OomNotifier;
 use crate::stats::Stats;
 use crate::systemd::dbus_native::serialize::Variant;
-use crate::systemd::unified::Unified;
+use crate::systemd::unified::{SystemdUnifiedError, Unified};
+use crate::v2::cpu::{Cpu as CgroupCpu, V2CpuControllerError};
+#[cfg(feature = "cgroupsv2_devices")]
+use crate::v2::devices::Devices as CgroupDevices;
+use crate::v2::hugetlb::{HugeTlb, V2HugeTlbControllerError};
 use crate::v2::manager::{Manager as FsManager, V2ManagerError};
+use crate::v2::rdma::Rdma;
+use crate::v2::util;
 
 const CGROUP_CONTROLLERS: &str = "cgroup.controllers";
 const CGROUP_SUBTREE_CONTROL: &str = "cgroup.subtree_control";
+// FreezeUnit and ThawUnit are only available since systemd 246
+const MIN_SYSTEMD_VERSION_FREEZE: u32 = 246;
 
 pub struct Manager {
     /// Root path of the cgroup hierarchy e.g. /sys/fs/cgroup
@@ -44,7 +57,7 @@ pub struct Manager {
     /// Name of the systemd unit e.g. youki-569d5ce3afe1074769f67.scope
     unit_name: String,
     /// Client for communicating with systemd
-    client: DbusConnection,
+    client: Box<dyn SystemdClient>,
     /// Cgroup manager for the created transient unit
     fs_manager: FsManager,
     /// Last control group which is managed by systemd, e.g. /user.slice/user-1000/user@1000.service
@@ -101,6 +114,9 @@ impl TryFrom<&Path> for CgroupsPath {
             },
             _ => return Err(CgroupsPathError::MalformedPath(cgroups_path.to_path_buf())),
         };
+        if destructured_path.name.is_empty() {
+            return Err(CgroupsPathError::MalformedPath(cgroups_path.to_path_buf()));
+        }
 
         Ok(destructured_path)
     }
@@ -112,6 +128,32 @@ impl Display for CgroupsPath {
     }
 }
 
+/// Escapes a string to be used in a unit name the same as systemd-escape,
+/// i.e. characters which are not allowed in unit names and a leading dot are
+/// replaced by their `\xNN` escape
+fn escape_unit_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for (i, byte) in name.bytes().enumerate() {
+        match byte {
+            b'.' if i == 0 => escaped.push_str("\\x2e"),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b':' | b'_' | b'.' | b'-' => {
+                escaped.push(byte as char)
+            }
+            _ => escaped.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    escaped
+}
+
+/// Returns the name of the unit for a cgroups path of the form
+/// [slice]:[prefix]:[name], without connecting to systemd. None if the path
+/// is not of that form.
+pub fn unit_name(cgroups_path: &Path) -> Option<String> {
+    CgroupsPath::try_from(cgroups_path)
+        .ok()
+        .map(|path| Manager::get_unit_name(&path))
+}
+
 /// ensures that a parent unit for the current unit is specified
 fn ensure_parent_unit(cgroups_path: &mut CgroupsPath, use_system: bool) {
     if cgroups_path.parent.is_empty() {
@@ -160,12 +202,60 @@ pub enum SystemdManagerError {
     Cpu(#[from] super::cpu::SystemdCpuError),
     #[error("in cpuset controller: {0}")]
     CpuSet(#[from] super::cpuset::SystemdCpuSetError),
+    #[error("in io controller: {0}")]
+    Io(#[from] super::io::SystemdIoError),
     #[error("in memory controller: {0}")]
     Memory(#[from] super::memory::SystemdMemoryError),
     #[error("in pids controller: {0}")]
     Pids(Infallible),
+    #[error("the {0} controller is required by the spec, but is not available to the cgroup")]
+    ControllerNotAvailable(crate::v2::controller_type::ControllerType),
+    #[error("in cpu controller: {0}")]
+    CpuCgroup(#[from] V2CpuControllerError),
+    #[error("in hugetlb controller: {0}")]
+    HugeTlb(#[from] V2HugeTlbControllerError),
+    #[cfg(feature = "cgroupsv2_devices")]
+    #[error("in devices controller: {0}")]
+    Devices(#[from] crate::v2::devices::controller::DevicesControllerError),
     #[error("in pids unified controller: {0}")]
     Unified(#[from] super::unified::SystemdUnifiedError),
+    #[error("in unified controller: {0}")]
+    UnifiedCgroup(#[from] crate::v2::unified::V2UnifiedError),
+}
+
+impl SystemdManagerError {
+    pub fn kind(&self) -> CgroupErrorKind {
+        match self {
+            Self::WrappedIo(_) | Self::FileNotFound(_) => CgroupErrorKind::Io,
+            Self::CgroupsPath(_)
+            | Self::InvalidSliceName(_)
+            | Self::JoinSafely(_)
+            | Self::BadDelegationBoundary { .. } => CgroupErrorKind::InvalidPath,
+            Self::SystemdClient(_) => CgroupErrorKind::Systemd,
+            Self::V2Manager(err) => err.kind(),
+            Self::CpuSet(SystemdCpuSetError::OldSystemd)
+            | Self::Io(SystemdIoError::OldSystemd)
+            | Self::Unified(SystemdUnifiedError::OldSystemd(_)) => {
+                CgroupErrorKind::SystemdVersionTooOld
+            }
+            Self::ControllerNotAvailable(_)
+            | Self::UnifiedCgroup(crate::v2::unified::V2UnifiedError::SubsystemNotAvailable {
+                ..
+            }) => CgroupErrorKind::ControllerNotFound,
+            Self::CpuCgroup(V2CpuControllerError::NotSupported(_)) => CgroupErrorKind::NotSupported,
+            Self::Cpu(_)
+            | Self::CpuSet(_)
+            | Self::Io(_)
+            | Self::Memory(_)
+            | Self::Pids(_)
+            | Self::CpuCgroup(_)
+            | Self::HugeTlb(_)
+            | Self::Unified(_)
+            | Self::UnifiedCgroup(_) => CgroupErrorKind::Controller,
+            #[cfg(feature = "cgroupsv2_devices")]
+            Self::Devices(_) => CgroupErrorKind::Controller,
+        }
+    }
 }
 
 impl Manager {
@@ -183,8 +273,22 @@ impl Manager {
             false => DbusConnection::new_session()?,
         };
 
+        Self::with_client(
+            root_path,
+            destructured_path,
+            container_name,
+            Box::new(client),
+        )
+    }
+
+    fn with_client(
+        root_path: PathBuf,
+        destructured_path: CgroupsPath,
+        container_name: String,
+        client: Box<dyn SystemdClient>,
+    ) -> Result<Self, SystemdManagerError> {
         let (cgroups_path, delegation_boundary) =
-            Self::construct_cgroups_path(&destructured_path, &client)?;
+            Self::construct_cgroups_path(&destructured_path, client.as_ref())?;
         let full_path = root_path.join_safely(&cgroups_path)?;
         let fs_manager = FsManager::new(root_path.clone(), cgroups_path.clone())?;
 
@@ -201,12 +305,29 @@ impl Manager {
         })
     }
 
+    /// Returns the full path of the cgroup of the unit in the cgroupfs,
+    /// e.g. /sys/fs/cgroup/system.slice/youki-569d5ce3afe1074769f67.scope
+    pub fn full_path(&self) -> &Path {
+        &self.full_path
+    }
+
+    /// Returns the name of the unit, e.g. youki-569d5ce3afe1074769f67.scope
+    pub fn unit_name(&self) -> &str {
+        &self.unit_name
+    }
+
     /// get_unit_name returns the unit (scope) name from the path provided by the user
-    /// for example: foo:docker:bar returns in '/docker-bar.scope'
+    /// for example: foo:docker:bar returns in '/docker-bar.scope'. The prefix
+    /// and the name are escaped, as e.g. container ids may contain characters
+    /// which are not allowed in unit names.
     fn get_unit_name(cgroups_path: &CgroupsPath) -> String {
         // By default we create a scope unless specified explicitly.
         if !cgroups_path.name.ends_with(".slice") {
-            return format!("{}-{}.scope", cgroups_path.prefix, cgroups_path.name);
+            let name = escape_unit_name(&cgroups_path.name);
+            if cgroups_path.prefix.is_empty() {
+                return format!("{name}.scope");
+            }
+            return format!("{}-{}.scope", escape_unit_name(&cgroups_path.prefix), name);
         }
         cgroups_path.name.clone()
     }
@@ -237,7 +358,7 @@ impl Manager {
         if slice.len() <= suffix.len() || !slice.ends_with(suffix) {
             return Err(SystemdManagerError::InvalidSliceName(slice.into()));
         }
-        if slice.contains('/') {
+        if slice.contains('/') || escape_unit_name(slice) != slice {
             return Err(SystemdManagerError::InvalidSliceName(slice.into()));
         }
         let mut path = "".to_owned();
@@ -321,8 +442,11 @@ impl Manager {
         {
             match controller {
                 "cpu" => controllers.push(ControllerType::Cpu),
+                "io" => controllers.push(ControllerType::Io),
                 "memory" => controllers.push(ControllerType::Memory),
                 "pids" => controllers.push(ControllerType::Pids),
+                "hugetlb" => controllers.push(ControllerType::HugeTlb),
+                "rdma" => controllers.push(ControllerType::Rdma),
                 _ => continue,
             }
         }
@@ -343,6 +467,78 @@ impl Manager {
     }
 }
 
+fn resource_properties<'a>(
+    controller_opt: &ControllerOpt,
+    systemd_version: u32,
+) -> Result<HashMap<&'a str, Variant>, SystemdManagerError> {
+    let mut properties: HashMap<&str, Variant> = HashMap::new();
+    for controller in CONTROLLER_TYPES {
+        match controller {
+            ControllerType::Cpu => {
+                Cpu::apply(controller_opt, systemd_version, &mut properties)?;
+            }
+
+            ControllerType::CpuSet => {
+                CpuSet::apply(controller_opt, systemd_version, &mut properties)?;
+            }
+
+            ControllerType::Io => {
+                Io::apply(controller_opt, systemd_version, &mut properties)?;
+            }
+
+            ControllerType::Pids => {
+                Pids::apply(controller_opt, systemd_version, &mut properties)
+                    .map_err(SystemdManagerError::Pids)?;
+            }
+            ControllerType::Memory => {
+                Memory::apply(controller_opt, systemd_version, &mut properties)?;
+            }
+            // written to the cgroup of the unit, once the unit has been configured
+            ControllerType::HugeTlb | ControllerType::Rdma => {}
+        };
+    }
+    Devices::apply(controller_opt, systemd_version, &mut properties)?;
+    Unified::apply(controller_opt, systemd_version, &mut properties)?;
+
+    Ok(properties)
+}
+
+/// The dbus properties which are set on the unit of a container for its
+/// resources, serialized as a map from the property name to its dbus
+/// signature and value
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct UnitProperties(BTreeMap<String, Variant>);
+
+impl UnitProperties {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Names of the properties, in alphabetical order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+}
+
+/// Returns the properties which would be set on the unit of a container for
+/// the resources with the given version of systemd, without connecting to
+/// systemd. The resources which systemd has no properties for, e.g. the
+/// hugetlb limits, are written to the cgroup of the unit instead and are left
+/// out.
+pub fn unit_properties(
+    controller_opt: &ControllerOpt,
+    systemd_version: u32,
+) -> Result<UnitProperties, SystemdManagerError> {
+    let properties = resource_properties(controller_opt, systemd_version)?;
+    Ok(UnitProperties(
+        properties
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect(),
+    ))
+}
+
 impl CgroupManager for Manager {
     type Error = SystemdManagerError;
 
@@ -369,41 +565,69 @@ impl CgroupManager for Manager {
         Ok(())
     }
 
+    fn open_task_cgroup(&self) -> Result<Option<File>, Self::Error> {
+        Ok(None)
+    }
+
     fn apply(&self, controller_opt: &ControllerOpt) -> Result<(), Self::Error> {
-        let mut properties: HashMap<&str, Variant> = HashMap::new();
         let systemd_version = self.client.systemd_version()?;
-
-        for controller in CONTROLLER_TYPES {
-            match controller {
-                ControllerType::Cpu => {
-                    Cpu::apply(controller_opt, systemd_version, &mut properties)?;
-                }
-
-                ControllerType::CpuSet => {
-                    CpuSet::apply(controller_opt, systemd_version, &mut properties)?;
-                }
-
-                ControllerType::Pids => {
-                    Pids::apply(controller_opt, systemd_version, &mut properties)
-                        .map_err(SystemdManagerError::Pids)?;
-                }
-                ControllerType::Memory => {
-                    Memory::apply(controller_opt, systemd_version, &mut properties)?;
-                }
-                _ => {}
-            };
-        }
-
+        let properties = resource_properties(controller_opt, systemd_version)?;
         tracing::debug!("applying properties {:?}", properties);
-        Unified::apply(controller_opt, systemd_version, &mut properties)?;
 
-        if !properties.is_empty() {
+        let has_hugepage_limits = controller_opt
+            .resources
+            .hugepage_limits()
+            .as_ref()
+            .map_or(false, |limits| !limits.is_empty());
+        let has_rdma_limits = controller_opt
+            .resources
+            .rdma()
+            .as_ref()
+            .map_or(false, |rdma| !rdma.is_empty());
+        let has_cpu_files = controller_opt
+            .resources
+            .cpu()
+            .as_ref()
+            .map_or(false, |cpu| cpu.burst().is_some() || cpu.idle().is_some());
+        let has_cgroupfs_keys = !Unified::cgroupfs_entries(controller_opt).is_empty();
+        if !properties.is_empty()
+            || has_cpu_files
+            || has_hugepage_limits
+            || has_rdma_limits
+            || has_cgroupfs_keys
+        {
             self.ensure_controllers_attached()?;
+        }
 
+        if !properties.is_empty() {
             self.client
                 .set_unit_properties(&self.unit_name, &properties)?;
         }
 
+        // systemd enables the controllers of the unit once its properties are
+        // set, the ones it couldn't enable have not been delegated to it
+        if let Some(controller) =
+            util::missing_controller(&self.full_path, controller_opt.resources)
+                .map_err(V2ManagerError::from)?
+        {
+            return Err(SystemdManagerError::ControllerNotAvailable(controller));
+        }
+
+        // systemd has no properties for the hugetlb and rdma controllers and
+        // for the cpu burst and idle, so these are written to the cgroup of the
+        // unit directly, like runc does.
+        if let Some(cpu) = controller_opt.resources.cpu() {
+            CgroupCpu::apply_burst_and_idle(&self.full_path, cpu)?;
+        }
+        HugeTlb::apply_limits(controller_opt, &self.full_path)?;
+        Rdma::apply_limits(controller_opt, &self.full_path)?;
+        Unified::apply_to_cgroup(controller_opt, &self.full_path)?;
+
+        // systemd only understands allow lists of devices, so the exact rules are
+        // enforced by attaching our own eBPF program to the delegated cgroup.
+        #[cfg(feature = "cgroupsv2_devices")]
+        CgroupDevices::apply_devices(&self.full_path, controller_opt.resources.devices())?;
+
         Ok(())
     }
 
@@ -413,10 +637,28 @@ impl CgroupManager for Manager {
             self.client.stop_transient_unit(&self.unit_name)?;
         }
 
+        // the cgroup is left behind if the unit has not been started, e.g.
+        // when the creation of the container failed
+        if self.full_path.exists() {
+            self.fs_manager.remove()?;
+        }
+
         Ok(())
     }
 
     fn freeze(&self, state: FreezerState) -> Result<(), Self::Error> {
+        // Freezing through systemd keeps its view of the unit consistent,
+        // writing cgroup.freeze directly could race with systemd.
+        if self.client.systemd_version()? >= MIN_SYSTEMD_VERSION_FREEZE
+            && self.client.transient_unit_exists(&self.unit_name)
+        {
+            match state {
+                FreezerState::Frozen => return Ok(self.client.freeze_unit(&self.unit_name)?),
+                FreezerState::Thawed => return Ok(self.client.thaw_unit(&self.unit_name)?),
+                FreezerState::Undefined => {}
+            }
+        }
+
         Ok(self.fs_manager.freeze(state)?)
     }
 
@@ -427,19 +669,68 @@ impl CgroupManager for Manager {
     fn get_all_pids(&self) -> Result<Vec<Pid>, Self::Error> {
         Ok(common::get_all_pids(&self.full_path)?)
     }
+
+    fn oom_notifier(&self) -> Result<OomNotifier, Self::Error> {
+        Ok(self.fs_manager.oom_notifier()?)
+    }
+
+    fn events_watcher(&self) -> Result<CgroupEventsWatcher, Self::Error> {
+        Ok(self.fs_manager.events_watcher()?)
+    }
+
+    fn kill_all(&self) -> Result<bool, Self::Error> {
+        Ok(self.fs_manager.kill_all()?)
+    }
+
+    fn reclaim_memory(&self, bytes: u64) -> Result<bool, Self::Error> {
+        Ok(self.fs_manager.reclaim_memory(bytes)?)
+    }
+
+    fn delegate(
+        &self,
+        owner: Uid,
+        group: Gid,
+        controllers: &[String],
+    ) -> Result<bool, Self::Error> {
+        // the unit is created with Delegate=yes, so that systemd leaves the
+        // cgroup tree beneath it alone
+        Ok(self.fs_manager.delegate(owner, group, controllers)?)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
     use anyhow::{Context, Result};
+    use oci_spec::runtime::{
+        LinuxCpuBuilder, LinuxHugepageLimitBuilder, LinuxMemoryBuilder, LinuxResourcesBuilder,
+    };
 
     use super::*;
     use crate::common::DEFAULT_CGROUP_ROOT;
     use crate::systemd::dbus_native::client::SystemdClient;
     use crate::systemd::dbus_native::serialize::Variant;
     use crate::systemd::dbus_native::utils::SystemdClientError;
+    use crate::test::set_fixture;
 
-    struct TestSystemdClient {}
+    struct TestSystemdClient {
+        version: u32,
+        unit_exists: bool,
+        /// Requests to systemd which change the unit
+        calls: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Default for TestSystemdClient {
+        fn default() -> Self {
+            Self {
+                version: 245,
+                unit_exists: true,
+                calls: Rc::default(),
+            }
+        }
+    }
 
     impl SystemdClient for TestSystemdClient {
         fn is_system(&self) -> bool {
@@ -447,7 +738,7 @@ mod tests {
         }
 
         fn transient_unit_exists(&self, _: &str) -> bool {
-            true
+            self.unit_exists
         }
 
         fn start_transient_unit(
@@ -464,16 +755,33 @@ mod tests {
             Ok(())
         }
 
+        fn freeze_unit(&self, unit_name: &str) -> Result<(), SystemdClientError> {
+            self.calls
+                .borrow_mut()
+                .push(format!("FreezeUnit {unit_name}"));
+            Ok(())
+        }
+
+        fn thaw_unit(&self, unit_name: &str) -> Result<(), SystemdClientError> {
+            self.calls
+                .borrow_mut()
+                .push(format!("ThawUnit {unit_name}"));
+            Ok(())
+        }
+
         fn set_unit_properties(
             &self,
-            _unit_name: &str,
+            unit_name: &str,
             _properties: &HashMap<&str, Variant>,
         ) -> Result<(), SystemdClientError> {
+            self.calls
+                .borrow_mut()
+                .push(format!("SetUnitProperties {unit_name}"));
             Ok(())
         }
 
         fn systemd_version(&self) -> Result<u32, SystemdClientError> {
-            Ok(245)
+            Ok(self.version)
         }
 
         fn control_cgroup_root(&self) -> Result<PathBuf, SystemdClientError> {
@@ -500,6 +808,81 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn expand_slice_rejects_invalid_names() {
+        for slice in [
+            "",
+            ".slice",
+            "test",
+            "a--b.slice",
+            "-a.slice",
+            "a-.slice",
+            "a/b.slice",
+            "a b.slice",
+        ] {
+            assert!(
+                matches!(
+                    Manager::expand_slice(slice),
+                    Err(SystemdManagerError::InvalidSliceName(_))
+                ),
+                "{slice}"
+            );
+        }
+        assert_eq!(
+            Manager::expand_slice("-.slice").unwrap(),
+            PathBuf::from("/")
+        );
+    }
+
+    #[test]
+    fn parse_cgroups_path() {
+        let path = CgroupsPath::try_from(Path::new("machine.slice:libpod:foo")).unwrap();
+        assert_eq!(
+            (
+                path.parent.as_str(),
+                path.prefix.as_str(),
+                path.name.as_str()
+            ),
+            ("machine.slice", "libpod", "foo")
+        );
+        for malformed in ["foo", "a:b:c:d", "machine.slice:libpod:"] {
+            assert!(matches!(
+                CgroupsPath::try_from(Path::new(malformed)),
+                Err(CgroupsPathError::MalformedPath(_))
+            ));
+        }
+        assert!(matches!(
+            CgroupsPath::try_from(Path::new("")),
+            Err(CgroupsPathError::NoPath)
+        ));
+    }
+
+    #[test]
+    fn unit_name_of_cgroups_path() {
+        assert_eq!(
+            unit_name(Path::new(":youki:foo")).as_deref(),
+            Some("youki-foo.scope")
+        );
+        assert_eq!(unit_name(Path::new("/sys/fs/cgroup/foo")), None);
+    }
+
+    #[test]
+    fn unit_name_is_escaped() -> Result<()> {
+        let unit_name = |path: &str| -> Result<String> {
+            Ok(Manager::get_unit_name(&Path::new(path).try_into()?))
+        };
+        assert_eq!(unit_name("system.slice:youki:foo")?, "youki-foo.scope");
+        assert_eq!(unit_name("system.slice::foo")?, "foo.scope");
+        assert_eq!(
+            unit_name("system.slice:cri o:.foo@bar")?,
+            "cri\\x20o-\\x2efoo\\x40bar.scope"
+        );
+        // slices are used as given
+        assert_eq!(unit_name("system.slice:youki:a-b.slice")?, "a-b.slice");
+
+        Ok(())
+    }
+
     #[test]
     fn get_cgroups_path_works_with_a_complex_slice() -> Result<()> {
         let cgroups_path = Path::new("test-a-b.slice:docker:foo")
@@ -507,7 +890,7 @@ mod tests {
             .context("construct path")?;
 
         assert_eq!(
-            Manager::construct_cgroups_path(&cgroups_path, &TestSystemdClient {})?.0,
+            Manager::construct_cgroups_path(&cgroups_path, &TestSystemdClient::default())?.0,
             PathBuf::from("/test.slice/test-a.slice/test-a-b.slice/docker-foo.scope"),
         );
 
@@ -521,7 +904,7 @@ mod tests {
             .context("construct path")?;
 
         assert_eq!(
-            Manager::construct_cgroups_path(&cgroups_path, &TestSystemdClient {})?.0,
+            Manager::construct_cgroups_path(&cgroups_path, &TestSystemdClient::default())?.0,
             PathBuf::from("/machine.slice/libpod-foo.scope"),
         );
 
@@ -536,7 +919,7 @@ mod tests {
         ensure_parent_unit(&mut cgroups_path, true);
 
         assert_eq!(
-            Manager::construct_cgroups_path(&cgroups_path, &TestSystemdClient {})?.0,
+            Manager::construct_cgroups_path(&cgroups_path, &TestSystemdClient::default())?.0,
             PathBuf::from("/system.slice/docker-foo.scope"),
         );
 
@@ -574,4 +957,165 @@ mod tests {
         // for contingency, and thus ignore the result
         let _ = fs::remove_dir(&manager.full_path);
     }
+
+    /// Creates a manager for the unit youki-test.scope in system.slice, whose
+    /// cgroup is below the given root
+    fn test_manager(root: &Path, client: TestSystemdClient) -> Result<Manager> {
+        let mut cgroups_path = Path::new(":youki:test").try_into()?;
+        ensure_parent_unit(&mut cgroups_path, true);
+        Ok(Manager::with_client(
+            root.to_owned(),
+            cgroups_path,
+            "test".to_owned(),
+            Box::new(client),
+        )?)
+    }
+
+    #[test]
+    fn test_apply_hugetlb() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let unit_cgroup = tmp.path().join("system.slice/youki-test.scope");
+        fs::create_dir_all(&unit_cgroup)?;
+        for cgroup in [tmp.path(), &tmp.path().join("system.slice")] {
+            set_fixture(cgroup, CGROUP_CONTROLLERS, "cpu memory hugetlb")?;
+            set_fixture(cgroup, CGROUP_SUBTREE_CONTROL, "")?;
+        }
+        set_fixture(&unit_cgroup, CGROUP_CONTROLLERS, "memory hugetlb")?;
+        set_fixture(&unit_cgroup, "hugetlb.2MB.max", "max")?;
+        set_fixture(&unit_cgroup, "hugetlb.2MB.rsvd.max", "max")?;
+
+        let client = TestSystemdClient::default();
+        let calls = client.calls.clone();
+        let manager = test_manager(tmp.path(), client)?;
+        let resources = LinuxResourcesBuilder::default()
+            .hugepage_limits(vec![LinuxHugepageLimitBuilder::default()
+                .page_size("2MB")
+                .limit(4194304)
+                .build()?])
+            .build()?;
+        manager.apply(&ControllerOpt {
+            resources: &resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        })?;
+
+        // systemd has no properties for the hugetlb limits, which are written
+        // to the cgroup of the unit
+        assert!(calls.borrow().is_empty());
+        assert_eq!(
+            fs::read_to_string(unit_cgroup.join("hugetlb.2MB.max"))?,
+            "4194304"
+        );
+        assert_eq!(
+            fs::read_to_string(unit_cgroup.join("hugetlb.2MB.rsvd.max"))?,
+            "4194304"
+        );
+        assert!(
+            fs::read_to_string(tmp.path().join("system.slice").join(CGROUP_SUBTREE_CONTROL))?
+                .contains("+hugetlb")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_freeze_through_systemd() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let client = TestSystemdClient {
+            version: MIN_SYSTEMD_VERSION_FREEZE,
+            ..Default::default()
+        };
+        let calls = client.calls.clone();
+        let manager = test_manager(tmp.path(), client)?;
+
+        manager.freeze(FreezerState::Frozen)?;
+        manager.freeze(FreezerState::Thawed)?;
+
+        assert_eq!(
+            *calls.borrow(),
+            ["FreezeUnit youki-test.scope", "ThawUnit youki-test.scope"]
+        );
+        // the cgroup of the unit is left to systemd
+        assert!(!manager.full_path.join("cgroup.freeze").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_freeze_falls_back_to_cgroup_freeze() -> Result<()> {
+        for client in [
+            // FreezeUnit and ThawUnit are not available before systemd 246
+            TestSystemdClient {
+                version: MIN_SYSTEMD_VERSION_FREEZE - 1,
+                ..Default::default()
+            },
+            TestSystemdClient {
+                version: MIN_SYSTEMD_VERSION_FREEZE,
+                unit_exists: false,
+                ..Default::default()
+            },
+        ] {
+            let tmp = tempfile::tempdir()?;
+            let calls = client.calls.clone();
+            let manager = test_manager(tmp.path(), client)?;
+            fs::create_dir_all(&manager.full_path)?;
+            set_fixture(&manager.full_path, "cgroup.freeze", "")?;
+            set_fixture(&manager.full_path, "cgroup.events", "populated 1\nfrozen 1")?;
+
+            manager.freeze(FreezerState::Frozen)?;
+            assert_eq!(
+                fs::read_to_string(manager.full_path.join("cgroup.freeze"))?,
+                "1"
+            );
+
+            set_fixture(&manager.full_path, "cgroup.events", "populated 1\nfrozen 0")?;
+            manager.freeze(FreezerState::Thawed)?;
+            assert_eq!(
+                fs::read_to_string(manager.full_path.join("cgroup.freeze"))?,
+                "0"
+            );
+            assert!(calls.borrow().is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unit_properties() -> Result<()> {
+        let resources = LinuxResourcesBuilder::default()
+            .cpu(LinuxCpuBuilder::default().cpus("0-3,9").build()?)
+            .memory(LinuxMemoryBuilder::default().limit(1024 * 1024).build()?)
+            .build()?;
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+
+        let properties = unit_properties(&controller_opt, 245)?;
+        assert_eq!(
+            serde_json::to_value(&properties)?,
+            serde_json::json!({
+                "AllowedCPUs": {"signature": "at", "value": [2, 15]},
+                "MemoryMax": {"signature": "t", "value": 1048576},
+            })
+        );
+        assert!(unit_properties(&controller_opt, 243).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(
+            SystemdManagerError::from(SystemdCpuSetError::OldSystemd).kind(),
+            CgroupErrorKind::SystemdVersionTooOld
+        );
+        assert_eq!(
+            SystemdManagerError::InvalidSliceName("system".to_owned()).kind(),
+            CgroupErrorKind::InvalidPath
+        );
+    }
 }
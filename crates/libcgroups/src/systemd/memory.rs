@@ -20,6 +20,8 @@ pub enum SystemdMemoryError {
     MemoryLimit(i64),
     #[error("cgroup v2 swap value cannot be calculated from swap of {swap} and limit of {limit}")]
     SwapValue { swap: i64, limit: String },
+    #[error("memory+swap limit of {swap} must not be lower than the memory limit of {limit}")]
+    SwapLowerThanLimit { swap: i64, limit: i64 },
 }
 
 pub struct Memory {}
@@ -78,7 +80,8 @@ impl Memory {
     // which corresponds to memory.memsw.limit_in_bytes in cgroup v1. In v2 however swap is a
     // separate value (memory.swap.max). Therefore swap needs to be calculated from memory limit
     // and swap. Specified values could be None (no value specified), -1 (unlimited), zero or a
-    // positive value. Swap needs to be bigger than the memory limit (due to swap being memory + swap)
+    // positive value. Swap needs to be at least the memory limit (due to swap being memory + swap),
+    // if both are equal the container is not allowed to use any swap.
     fn apply_swap(
         swap: Option<i64>,
         limit: Option<i64>,
@@ -102,7 +105,10 @@ impl Memory {
                 })
             }
 
-            (Some(l), Some(s)) if l < s => Variant::U64((s - l) as u64),
+            (Some(l), Some(s)) if l <= s => Variant::U64((s - l) as u64),
+            (Some(l), Some(s)) => {
+                return Err(SystemdMemoryError::SwapLowerThanLimit { swap: s, limit: l })
+            }
             _ => return Ok(()),
         };
 
@@ -171,4 +177,68 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_set_valid_swap() -> Result<()> {
+        let values = vec![
+            // (limit, swap, expected swap)
+            (536870912, 1073741824, 536870912u64),
+            (536870912, 536870912, 0),
+            (536870912, -1, u64::MAX),
+            (-1, -1, u64::MAX),
+        ];
+
+        for (limit, swap, expected) in values {
+            let memory = LinuxMemoryBuilder::default()
+                .limit(limit)
+                .swap(swap)
+                .build()
+                .context("build memory spec")?;
+            let mut properties: HashMap<&str, Variant> = HashMap::new();
+
+            Memory::apply(&memory, &mut properties).context("apply memory")?;
+
+            let actual = &properties[MEMORY_SWAP];
+            let val = recast!(actual, Variant)?;
+            assert_eq!(val, Variant::U64(expected), "limit {limit} swap {swap}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_invalid_swap() -> Result<()> {
+        let values = vec![
+            (Some(1073741824), 536870912),
+            (None, 536870912),
+            (Some(-1), 536870912),
+            (Some(536870912), 0),
+        ];
+
+        for (limit, swap) in values {
+            let mut builder = LinuxMemoryBuilder::default().swap(swap);
+            if let Some(limit) = limit {
+                builder = builder.limit(limit);
+            }
+            let memory = builder.build().context("build memory spec")?;
+            let mut properties: HashMap<&str, Variant> = HashMap::new();
+
+            assert!(
+                Memory::apply(&memory, &mut properties).is_err(),
+                "limit {limit:?} swap {swap}"
+            );
+        }
+
+        let memory = LinuxMemoryBuilder::default()
+            .limit(1073741824)
+            .swap(536870912)
+            .build()
+            .context("build memory spec")?;
+        assert!(matches!(
+            Memory::apply(&memory, &mut HashMap::new()),
+            Err(SystemdMemoryError::SwapLowerThanLimit { .. })
+        ));
+
+        Ok(())
+    }
 }
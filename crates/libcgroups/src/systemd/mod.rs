@@ -1,10 +1,16 @@
 use std::fs;
 
+use self::dbus_native::client::SystemdClient;
+use self::dbus_native::dbus::DbusConnection;
+use self::dbus_native::utils::SystemdClientError;
+
 mod controller;
 pub mod controller_type;
 mod cpu;
 mod cpuset;
 mod dbus_native;
+mod devices;
+mod io;
 pub mod manager;
 mod memory;
 mod pids;
@@ -17,6 +23,45 @@ pub fn booted() -> bool {
         .unwrap_or_default()
 }
 
+/// Checks if the bus of systemd can be connected to, which is the one of the
+/// user manager unless the system instance is used
+pub fn bus_reachable(use_system: bool) -> bool {
+    let connection = match use_system {
+        true => DbusConnection::new_system(),
+        false => DbusConnection::new_session(),
+    };
+    match connection {
+        Ok(_) => true,
+        Err(err) => {
+            tracing::debug!(
+                use_system,
+                "failed to connect to the bus of systemd: {}",
+                err
+            );
+            false
+        }
+    }
+}
+
+/// Returns the version of systemd as reported by the manager on the bus, which
+/// is the user manager unless the system instance is used
+pub fn version(use_system: bool) -> Option<u32> {
+    let connection = match use_system {
+        true => DbusConnection::new_system(),
+        false => DbusConnection::new_session(),
+    };
+    match connection
+        .map_err(SystemdClientError::from)
+        .and_then(|connection| connection.systemd_version())
+    {
+        Ok(version) => Some(version),
+        Err(err) => {
+            tracing::debug!(use_system, "failed to get the version of systemd: {}", err);
+            None
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! recast {
     ($v:ident, $t:ty) => {{
@@ -29,6 +29,8 @@ impl Controller for Pids {
 }
 
 impl Pids {
+    // Like with the cgroupfs managers, a limit that is not positive (the spec
+    // uses -1) means unlimited, which systemd represents as u64::MAX (infinity).
     fn apply(pids: &LinuxPids, properties: &mut HashMap<&str, Variant>) {
         let limit = if pids.limit() > 0 {
             pids.limit() as u64
@@ -104,6 +106,24 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_pids_unlimited() -> Result<()> {
+        let resources = LinuxResourcesBuilder::default()
+            .pids(LinuxPidsBuilder::default().limit(-1).build()?)
+            .build()?;
+        let (options, mut properties) = setup(&resources);
+
+        <Pids as Controller>::apply(&options, 245, &mut properties)
+            .map_err(|err| anyhow!(err))
+            .context("apply pids")?;
+
+        let task_max = properties.get(TASKS_MAX).unwrap();
+        let val = recast!(task_max, Variant)?;
+        assert_eq!(val, Variant::U64(u64::MAX));
+
+        Ok(())
+    }
+
     #[test]
     fn test_pids_negative_limit() -> Result<()> {
         let resources = LinuxResourcesBuilder::default()
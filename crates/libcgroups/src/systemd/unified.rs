@@ -1,12 +1,15 @@
 use std::collections::HashMap;
 use std::num::ParseIntError;
+use std::path::Path;
 
 use super::controller::Controller;
 use super::cpu::{self, convert_shares_to_cgroup2};
-use super::cpuset::{self, to_bitmask, BitmaskError};
 use super::dbus_native::serialize::Variant;
-use super::{memory, pids};
+use super::{cpuset, memory, pids};
 use crate::common::ControllerOpt;
+use crate::cpumask::{CpuMask, CpuMaskError};
+use crate::v2::unified::{Unified as V2Unified, V2UnifiedError};
+use crate::v2::util;
 
 #[derive(thiserror::Error, Debug)]
 pub enum SystemdUnifiedError {
@@ -21,7 +24,7 @@ pub enum SystemdUnifiedError {
     #[error("setting {0} requires systemd version greater than 243")]
     OldSystemd(String),
     #[error("invalid value for cpuset.cpus {0}")]
-    CpuSetCpu(BitmaskError),
+    CpuSetCpu(CpuMaskError),
     #[error("failed to parse {name} {value}: {err}")]
     Memory {
         err: ParseIntError,
@@ -32,6 +35,21 @@ pub enum SystemdUnifiedError {
     PidsMax { err: ParseIntError, value: String },
 }
 
+/// Unified keys which are translated into unit properties. All other keys are
+/// written to the cgroup of the unit directly, like runc does.
+const PROPERTY_KEYS: &[&str] = &[
+    "cpu.weight",
+    "cpu.max",
+    "cpuset.cpus",
+    "cpuset.mems",
+    "memory.min",
+    "memory.low",
+    "memory.high",
+    "memory.max",
+    "memory.swap.max",
+    "pids.max",
+];
+
 pub struct Unified {}
 
 impl Controller for Unified {
@@ -102,11 +120,7 @@ impl Unified {
                         return Err(SystemdUnifiedError::OldSystemd(cpuset.into()));
                     }
 
-                    let bitmask: Vec<u64> = to_bitmask(value)
-                        .map_err(SystemdUnifiedError::CpuSetCpu)?
-                        .into_iter()
-                        .map(|v| v as u64)
-                        .collect();
+                    let mask: CpuMask = value.parse().map_err(SystemdUnifiedError::CpuSetCpu)?;
 
                     let systemd_cpuset = match cpuset {
                         "cpuset.cpus" => cpuset::ALLOWED_CPUS,
@@ -114,51 +128,87 @@ impl Unified {
                         file_name => unreachable!("{} was not matched", file_name),
                     };
 
-                    properties.insert(systemd_cpuset, Variant::ArrayU64(bitmask));
+                    properties.insert(systemd_cpuset, cpuset::to_property(&mask));
                 }
-                memory @ ("memory.min" | "memory.low" | "memory.high" | "memory.max") => {
-                    let value =
-                        value
-                            .parse::<u64>()
-                            .map_err(|err| SystemdUnifiedError::Memory {
-                                err,
-                                name: memory.into(),
-                                value: value.into(),
-                            })?;
+                memory @ ("memory.min" | "memory.low" | "memory.high" | "memory.max"
+                | "memory.swap.max") => {
+                    let value = parse_limit(value).map_err(|err| SystemdUnifiedError::Memory {
+                        err,
+                        name: memory.into(),
+                        value: value.into(),
+                    })?;
                     let systemd_memory = match memory {
                         "memory.min" => memory::MEMORY_MIN,
                         "memory.low" => memory::MEMORY_LOW,
                         "memory.high" => memory::MEMORY_HIGH,
                         "memory.max" => memory::MEMORY_MAX,
+                        "memory.swap.max" => memory::MEMORY_SWAP,
                         file_name => unreachable!("{} was not matched", file_name),
                     };
                     properties.insert(systemd_memory, Variant::U64(value));
                 }
                 "pids.max" => {
-                    let pids = value.trim().parse::<i64>().map_err(|err| {
-                        SystemdUnifiedError::PidsMax {
-                            err,
-                            value: value.into(),
-                        }
+                    let pids = parse_limit(value).map_err(|err| SystemdUnifiedError::PidsMax {
+                        err,
+                        value: value.into(),
                     })?;
-                    properties.insert(pids::TASKS_MAX, Variant::U64(pids as u64));
+                    properties.insert(pids::TASKS_MAX, Variant::U64(pids));
                 }
 
-                unknown => tracing::warn!("could not apply {}. Unknown property.", unknown),
+                other => {
+                    tracing::debug!("{} has no unit property, writing it to the cgroup", other)
+                }
             }
         }
 
         Ok(())
     }
+
+    /// Returns the unified keys which cannot be set through a unit property
+    pub(super) fn cgroupfs_entries(options: &ControllerOpt) -> HashMap<String, String> {
+        options
+            .resources
+            .unified()
+            .iter()
+            .flatten()
+            .filter(|(key, _)| !PROPERTY_KEYS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Writes the unified keys which cannot be set through systemd to the cgroup
+    /// of the unit, after checking that their controllers are enabled for it
+    pub fn apply_to_cgroup(
+        options: &ControllerOpt,
+        cgroup_path: &Path,
+    ) -> Result<(), V2UnifiedError> {
+        let entries = Self::cgroupfs_entries(options);
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let controllers = util::get_available_controllers(cgroup_path)?;
+        V2Unified::apply_entries(&entries, cgroup_path, &controllers)
+    }
+}
+
+/// Parses a limit, where `max` means that there is no limit
+fn parse_limit(value: &str) -> Result<u64, ParseIntError> {
+    match value.trim() {
+        "max" => Ok(u64::MAX),
+        value => value.parse(),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use anyhow::{Context, Result};
+    use oci_spec::runtime::LinuxResourcesBuilder;
 
     use super::super::dbus_native::serialize::DbusSerialize;
     use super::*;
     use crate::recast;
+    use crate::test::set_fixture;
 
     #[test]
     fn test_set() -> Result<()> {
@@ -171,6 +221,7 @@ mod tests {
             ("memory.low", "200000"),
             ("memory.high", "300000"),
             ("memory.max", "400000"),
+            ("memory.swap.max", "max"),
             ("pids.max", "100"),
         ]
         .into_iter()
@@ -185,6 +236,7 @@ mod tests {
         expected.insert(memory::MEMORY_LOW, Variant::U64(200000u64));
         expected.insert(memory::MEMORY_HIGH, Variant::U64(300000u64));
         expected.insert(memory::MEMORY_MAX, Variant::U64(400000u64));
+        expected.insert(memory::MEMORY_SWAP, Variant::U64(u64::MAX));
         expected.insert(pids::TASKS_MAX, Variant::U64(100u64));
 
         // act
@@ -249,4 +301,58 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_untranslated_keys_are_written_to_cgroup() -> Result<()> {
+        // arrange
+        let tmp = tempfile::tempdir()?;
+        set_fixture(tmp.path(), "cgroup.controllers", "cpu memory pids")?;
+        let oom_group = set_fixture(tmp.path(), "memory.oom.group", "0")?;
+        let unified: HashMap<String, String> = [
+            ("memory.oom.group".to_owned(), "1".to_owned()),
+            ("memory.max".to_owned(), "max".to_owned()),
+        ]
+        .into();
+        let resources = LinuxResourcesBuilder::default().unified(unified).build()?;
+        let options = ControllerOpt {
+            resources: &resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+        let mut properties: HashMap<&str, Variant> = HashMap::new();
+
+        // act
+        <Unified as Controller>::apply(&options, 245, &mut properties)?;
+        Unified::apply_to_cgroup(&options, tmp.path())?;
+
+        // assert
+        assert_eq!(properties.len(), 1);
+        assert!(properties.contains_key(memory::MEMORY_MAX));
+        assert_eq!(std::fs::read_to_string(oom_group)?, "1");
+        // translated keys are not written to the cgroup
+        assert!(!tmp.path().join("memory.max").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_untranslated_keys_require_enabled_controller() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        set_fixture(tmp.path(), "cgroup.controllers", "cpu pids")?;
+        let unified: HashMap<String, String> =
+            [("memory.oom.group".to_owned(), "1".to_owned())].into();
+        let resources = LinuxResourcesBuilder::default().unified(unified).build()?;
+        let options = ControllerOpt {
+            resources: &resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+
+        assert!(matches!(
+            Unified::apply_to_cgroup(&options, tmp.path()),
+            Err(V2UnifiedError::SubsystemNotAvailable { .. })
+        ));
+        Ok(())
+    }
 }
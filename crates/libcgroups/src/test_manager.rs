@@ -1,9 +1,12 @@
 use std::cell::RefCell;
 use std::convert::Infallible;
+use std::fs::File;
 
-use nix::unistd::Pid;
+use nix::unistd::{Gid, Pid, Uid};
 
 use crate::common::{CgroupManager, ControllerOpt, FreezerState};
+use crate::events::CgroupEventsWatcher;
+use crate::oom::OomNotifier;
 use crate::stats::Stats;
 
 #[derive(Debug)]
@@ -29,6 +32,10 @@ impl CgroupManager for TestManager {
         Ok(())
     }
 
+    fn open_task_cgroup(&self) -> Result<Option<File>, Infallible> {
+        Ok(None)
+    }
+
     // NOTE: The argument cannot be stored due to lifetime.
     fn apply(&self, _controller_opt: &ControllerOpt) -> Result<(), Infallible> {
         *self.apply_called.borrow_mut() = true;
@@ -50,6 +57,31 @@ impl CgroupManager for TestManager {
     fn get_all_pids(&self) -> Result<Vec<Pid>, Infallible> {
         unimplemented!()
     }
+
+    fn oom_notifier(&self) -> Result<OomNotifier, Infallible> {
+        unimplemented!()
+    }
+
+    fn events_watcher(&self) -> Result<CgroupEventsWatcher, Infallible> {
+        unimplemented!()
+    }
+
+    fn kill_all(&self) -> Result<bool, Infallible> {
+        unimplemented!()
+    }
+
+    fn reclaim_memory(&self, _bytes: u64) -> Result<bool, Infallible> {
+        unimplemented!()
+    }
+
+    fn delegate(
+        &self,
+        _owner: Uid,
+        _group: Gid,
+        _controllers: &[String],
+    ) -> Result<bool, Infallible> {
+        unimplemented!()
+    }
 }
 
 impl TestManager {
@@ -0,0 +1,233 @@
+//! Topology of the cpus and memory nodes of the host, as shown by sysfs. It
+//! is used to derive the cpus and mems lists of a cpuset from a number of
+//! cpus and a memory node, which, unlike hand written lists like "0-63",
+//! only contain cpus and nodes which are online. The kernel rejects lists
+//! with offline cpus, which are common on hosts with hot plugged cpus or
+//! with nodes of different sizes.
+use std::fs;
+use std::path::PathBuf;
+
+use crate::common::{WrapIoResult, WrappedIoError};
+use crate::cpumask::{CpuMask, CpuMaskError};
+
+const SYSFS_SYSTEM: &str = "/sys/devices/system";
+
+#[derive(thiserror::Error, Debug)]
+pub enum TopologyError {
+    #[error(transparent)]
+    CpuMask(#[from] CpuMaskError),
+    #[error("io error: {0}")]
+    WrappedIo(#[from] WrappedIoError),
+    #[error("the kernel has been built without NUMA support")]
+    NoNuma,
+    #[error("memory node {0} is not online")]
+    NodeOffline(usize),
+    #[error("memory node {0} has no online cpus")]
+    NodeWithoutCpus(usize),
+    #[error("at least one cpu has to be requested")]
+    NoCpusRequested,
+    #[error("requested {requested} cpus, but only {available} are online")]
+    NotEnoughCpus { requested: usize, available: usize },
+}
+
+type Result<T> = std::result::Result<T, TopologyError>;
+
+/// Cpus and memory nodes selected for a cpuset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpusetSelection {
+    /// Cpus of the cpuset, to be used as `cpus` of the cpu resources
+    pub cpus: CpuMask,
+    /// Memory nodes of the cpuset, to be used as `mems` of the cpu
+    /// resources. None if no node has been requested.
+    pub mems: Option<CpuMask>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Topology {
+    root: PathBuf,
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Self::with_root(SYSFS_SYSTEM)
+    }
+}
+
+impl Topology {
+    /// Topology of the host
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Topology read from a copy of `/sys/devices/system` at root
+    pub fn with_root<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Returns the cpus which are currently online
+    pub fn online_cpus(&self) -> Result<CpuMask> {
+        read_mask(self.root.join("cpu/online"))
+    }
+
+    /// Returns the memory nodes which are currently online, or None if the
+    /// kernel has been built without NUMA support
+    pub fn online_nodes(&self) -> Result<Option<CpuMask>> {
+        let path = self.root.join("node/online");
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        read_mask(path).map(Some)
+    }
+
+    /// Returns the online cpus of a memory node
+    pub fn node_cpus(&self, node: usize) -> Result<CpuMask> {
+        let nodes = self.online_nodes()?.ok_or(TopologyError::NoNuma)?;
+        if !nodes.contains(node) {
+            return Err(TopologyError::NodeOffline(node));
+        }
+
+        let cpus = read_mask(self.root.join(format!("node/node{node}/cpulist")))?
+            .intersection(&self.online_cpus()?);
+        if cpus.is_empty() {
+            return Err(TopologyError::NodeWithoutCpus(node));
+        }
+
+        Ok(cpus)
+    }
+
+    /// Selects the lowest online cpus, of the given memory node if there is
+    /// one. All of them are selected if no number of cpus is given. The
+    /// memory node becomes the only node of the cpuset.
+    pub fn select(&self, cpus: Option<usize>, node: Option<usize>) -> Result<CpusetSelection> {
+        let available = match node {
+            Some(node) => self.node_cpus(node)?,
+            None => self.online_cpus()?,
+        };
+
+        let selected = match cpus {
+            Some(0) => return Err(TopologyError::NoCpusRequested),
+            Some(requested) => {
+                let mut selected = CpuMask::default();
+                for cpu in available.iter().take(requested) {
+                    selected.insert(cpu);
+                }
+                let count = selected.iter().count();
+                if count < requested {
+                    return Err(TopologyError::NotEnoughCpus {
+                        requested,
+                        available: count,
+                    });
+                }
+                selected
+            }
+            None => available,
+        };
+
+        let mems = node.map(|node| {
+            let mut mems = CpuMask::default();
+            mems.insert(node);
+            mems
+        });
+
+        Ok(CpusetSelection {
+            cpus: selected,
+            mems,
+        })
+    }
+}
+
+fn read_mask(path: PathBuf) -> Result<CpuMask> {
+    Ok(fs::read_to_string(&path).wrap_read(&path)?.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use anyhow::Result;
+
+    use super::*;
+
+    /// Two nodes with cpus 0-3 and 4-7, of which 2 and 6 are offline, and a
+    /// third node with memory only
+    fn setup() -> Result<tempfile::TempDir> {
+        let tmp = tempfile::tempdir()?;
+        let write = |path: &str, content: &str| -> Result<()> {
+            let path = tmp.path().join(path);
+            fs::create_dir_all(path.parent().unwrap_or(Path::new("/")))?;
+            fs::write(path, content)?;
+            Ok(())
+        };
+        write("cpu/online", "0-1,3-5,7\n")?;
+        write("node/online", "0-2\n")?;
+        write("node/node0/cpulist", "0-1,3\n")?;
+        write("node/node1/cpulist", "4-7\n")?;
+        write("node/node2/cpulist", "\n")?;
+        Ok(tmp)
+    }
+
+    #[test]
+    fn test_node_cpus() -> Result<()> {
+        let tmp = setup()?;
+        let topology = Topology::with_root(tmp.path());
+
+        assert_eq!(topology.node_cpus(0)?.to_string(), "0-1,3");
+        // offline cpus of a node are left out
+        assert_eq!(topology.node_cpus(1)?.to_string(), "4-5,7");
+        assert!(matches!(
+            topology.node_cpus(2),
+            Err(TopologyError::NodeWithoutCpus(2))
+        ));
+        assert!(matches!(
+            topology.node_cpus(3),
+            Err(TopologyError::NodeOffline(3))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_select() -> Result<()> {
+        let tmp = setup()?;
+        let topology = Topology::with_root(tmp.path());
+
+        let selection = topology.select(Some(3), None)?;
+        assert_eq!(selection.cpus.to_string(), "0-1,3");
+        assert_eq!(selection.mems, None);
+
+        let selection = topology.select(Some(2), Some(1))?;
+        assert_eq!(selection.cpus.to_string(), "4-5");
+        assert_eq!(selection.mems.map(|m| m.to_string()).as_deref(), Some("1"));
+
+        let selection = topology.select(None, Some(0))?;
+        assert_eq!(selection.cpus.to_string(), "0-1,3");
+
+        assert!(matches!(
+            topology.select(Some(4), Some(0)),
+            Err(TopologyError::NotEnoughCpus {
+                requested: 4,
+                available: 3
+            })
+        ));
+        assert!(matches!(
+            topology.select(Some(0), None),
+            Err(TopologyError::NoCpusRequested)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_without_numa() -> Result<()> {
+        let tmp = setup()?;
+        fs::remove_dir_all(tmp.path().join("node"))?;
+        let topology = Topology::with_root(tmp.path());
+
+        assert_eq!(topology.online_nodes()?, None);
+        assert_eq!(topology.select(None, None)?.cpus.to_string(), "0-1,3-5,7");
+        assert!(matches!(
+            topology.select(Some(1), Some(0)),
+            Err(TopologyError::NoNuma)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,522 @@
+//! Minimal io_uring instance, used to submit the reads of many cgroup files at once.
+//! Only the parts of the interface needed for IORING_OP_READ (Linux 5.6) are implemented.
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::{mem, ptr};
+
+use crate::common::{self, WrappedIoError};
+
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x8000000;
+const IORING_OFF_SQES: libc::off_t = 0x10000000;
+const IORING_ENTER_GETEVENTS: libc::c_uint = 1;
+const IORING_OP_ASYNC_CANCEL: u8 = 14;
+const IORING_OP_READ: u8 = 22;
+/// User data of the cancellations, which doesn't collide with the index of a read
+const CANCEL_USER_DATA: u64 = u64::MAX;
+
+/// Number of reads submitted to the ring at once
+const RING_ENTRIES: u32 = 128;
+/// Size of the buffer used for the read of a file. Larger files are read to the
+/// end after the batched read.
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+
+#[repr(C)]
+#[derive(Default)]
+struct SqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqringOffsets,
+    cq_off: CqringOffsets,
+}
+
+#[repr(C)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mmap {
+    fn new(fd: RawFd, len: usize, offset: libc::off_t) -> io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd,
+                offset,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { ptr, len })
+    }
+
+    /// Pointer to the value at the given byte offset of the mapping
+    fn at<T>(&self, offset: u32) -> *mut T {
+        debug_assert!(offset as usize + mem::size_of::<T>() <= self.len);
+        unsafe { self.ptr.add(offset as usize) as *mut T }
+    }
+
+    fn atomic(&self, offset: u32) -> &AtomicU32 {
+        unsafe { &*self.at::<AtomicU32>(offset) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// Error of the batched reads
+struct ReadError {
+    err: io::Error,
+    /// Flag indicating that reads could not be cancelled, so the kernel may
+    /// still write to their buffers
+    in_flight: bool,
+}
+
+struct IoUring {
+    // the mappings have to be released before the ring is closed
+    sq: Mmap,
+    cq: Mmap,
+    sqes: Mmap,
+    params: Params,
+    ring: File,
+}
+
+impl IoUring {
+    fn new(entries: u32) -> io::Result<Self> {
+        let mut params = Params::default();
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_setup,
+                entries,
+                &mut params as *mut Params,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ring = unsafe { File::from_raw_fd(fd as RawFd) };
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len =
+            params.cq_off.cqes as usize + params.cq_entries as usize * mem::size_of::<Cqe>();
+        let sqes_len = params.sq_entries as usize * mem::size_of::<Sqe>();
+
+        let sq = Mmap::new(ring.as_raw_fd(), sq_len, IORING_OFF_SQ_RING)?;
+        let cq = Mmap::new(ring.as_raw_fd(), cq_len, IORING_OFF_CQ_RING)?;
+        let sqes = Mmap::new(ring.as_raw_fd(), sqes_len, IORING_OFF_SQES)?;
+
+        Ok(Self {
+            sq,
+            cq,
+            sqes,
+            params,
+            ring,
+        })
+    }
+
+    /// Reads the start of every file into its buffer and returns the result of
+    /// each read, which is the number of bytes read or a negated errno. If the
+    /// reads fail, the submitted reads are cancelled before returning, since
+    /// the kernel writes to the buffers until they have completed.
+    fn read(&self, files: &mut [(&File, &mut Vec<u8>)]) -> Result<Vec<i32>, ReadError> {
+        let mut results = vec![0; files.len()];
+        for (chunk_index, chunk) in files
+            .chunks_mut(self.params.sq_entries as usize)
+            .enumerate()
+        {
+            let base = chunk_index * self.params.sq_entries as usize;
+            let mut pending = BTreeSet::new();
+            let result = self
+                .submit(chunk, base, &mut pending)
+                .and_then(|()| self.wait(&mut pending, &mut results));
+            if let Err(err) = result {
+                let in_flight = match self.cancel(&mut pending, &mut results) {
+                    Ok(()) => false,
+                    Err(cancel_err) => {
+                        tracing::error!(?cancel_err, "failed to cancel io_uring reads");
+                        true
+                    }
+                };
+                return Err(ReadError { err, in_flight });
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Submits the reads of the files, and adds the submitted ones to the
+    /// pending reads
+    fn submit(
+        &self,
+        files: &mut [(&File, &mut Vec<u8>)],
+        base: usize,
+        pending: &mut BTreeSet<u64>,
+    ) -> io::Result<()> {
+        let sqes = files.iter_mut().enumerate().map(|(i, (file, buffer))| Sqe {
+            opcode: IORING_OP_READ,
+            flags: 0,
+            ioprio: 0,
+            fd: file.as_raw_fd(),
+            off: 0,
+            addr: buffer.as_mut_ptr() as u64,
+            len: buffer.capacity() as u32,
+            rw_flags: 0,
+            user_data: (base + i) as u64,
+            buf_index: 0,
+            personality: 0,
+            splice_fd_in: 0,
+            addr3: 0,
+            pad: 0,
+        });
+        let submitted = self.push(sqes)?;
+        pending.extend((base..base + submitted).map(|user_data| user_data as u64));
+        if submitted < files.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "io_uring submitted only part of the reads",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Queues the entries and submits them to the kernel, returning the
+    /// number of submitted entries. Entries which could not be submitted are
+    /// taken from the queue again, so that the kernel never sees them.
+    fn push(&self, entries: impl ExactSizeIterator<Item = Sqe>) -> io::Result<usize> {
+        let mask = unsafe { *self.sq.at::<u32>(self.params.sq_off.ring_mask) };
+        let array = self.sq.at::<u32>(self.params.sq_off.array);
+        let sqes = self.sqes.at::<Sqe>(0);
+        let sq_tail = self.sq.atomic(self.params.sq_off.tail);
+
+        // the kernel only consumes entries, so the tail is only changed by us
+        let tail = sq_tail.load(Ordering::Relaxed);
+        let count = entries.len();
+        for (i, sqe) in entries.enumerate() {
+            let index = tail.wrapping_add(i as u32) & mask;
+            unsafe {
+                sqes.add(index as usize).write(sqe);
+                array.add(index as usize).write(index);
+            }
+        }
+        sq_tail.store(tail.wrapping_add(count as u32), Ordering::Release);
+
+        let mut submitted = 0;
+        while submitted < count {
+            match self.enter((count - submitted) as u32, 0) {
+                Ok(n) => submitted += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    tracing::debug!(?err, submitted, count, "failed to submit to io_uring");
+                    break;
+                }
+            }
+        }
+        if submitted < count {
+            // without SQPOLL the kernel only reads the tail while entering,
+            // so the entries it hasn't consumed can be withdrawn
+            sq_tail.store(tail.wrapping_add(submitted as u32), Ordering::Release);
+        }
+
+        Ok(submitted)
+    }
+
+    /// Waits until all pending reads have completed and records their results
+    fn wait(&self, pending: &mut BTreeSet<u64>, results: &mut [i32]) -> io::Result<()> {
+        while !pending.is_empty() {
+            match self.enter(0, pending.len() as u32) {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+            self.reap(pending, results);
+        }
+
+        Ok(())
+    }
+
+    /// Takes the completions from the completion queue
+    fn reap(&self, pending: &mut BTreeSet<u64>, results: &mut [i32]) {
+        let mask = unsafe { *self.cq.at::<u32>(self.params.cq_off.ring_mask) };
+        let cqes = self.cq.at::<Cqe>(self.params.cq_off.cqes);
+
+        let cq_head = self.cq.atomic(self.params.cq_off.head);
+        let mut head = cq_head.load(Ordering::Relaxed);
+        let tail = self
+            .cq
+            .atomic(self.params.cq_off.tail)
+            .load(Ordering::Acquire);
+        while head != tail {
+            let cqe = unsafe { &*cqes.add((head & mask) as usize) };
+            // the completions of the cancellations are only awaited through
+            // the completions of the reads they cancel
+            if cqe.user_data != CANCEL_USER_DATA {
+                results[cqe.user_data as usize] = cqe.res;
+                pending.remove(&cqe.user_data);
+            }
+            head = head.wrapping_add(1);
+        }
+        cq_head.store(head, Ordering::Release);
+    }
+
+    /// Cancels the pending reads and waits until the kernel has completed
+    /// them, as cancelled or not
+    fn cancel(&self, pending: &mut BTreeSet<u64>, results: &mut [i32]) -> io::Result<()> {
+        self.reap(pending, results);
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let sqes = pending.iter().map(|&user_data| Sqe {
+            opcode: IORING_OP_ASYNC_CANCEL,
+            flags: 0,
+            ioprio: 0,
+            fd: -1,
+            off: 0,
+            addr: user_data,
+            len: 0,
+            rw_flags: 0,
+            user_data: CANCEL_USER_DATA,
+            buf_index: 0,
+            personality: 0,
+            splice_fd_in: 0,
+            addr3: 0,
+            pad: 0,
+        });
+        let count = pending.len();
+        let submitted = self.push(sqes)?;
+        if submitted < count {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "failed to submit the cancellations to io_uring",
+            ));
+        }
+
+        self.wait(pending, results)
+    }
+
+    fn enter(&self, to_submit: u32, min_complete: u32) -> io::Result<usize> {
+        let flags = if min_complete > 0 {
+            IORING_ENTER_GETEVENTS
+        } else {
+            0
+        };
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_enter,
+                self.ring.as_raw_fd(),
+                to_submit,
+                min_complete,
+                flags,
+                ptr::null::<libc::sigset_t>(),
+                0,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(ret as usize)
+    }
+}
+
+/// Reads all given files with a single batch of reads. Fails if io_uring is not
+/// available, e.g. because the kernel is too old or io_uring has been disabled.
+pub(crate) fn read_files<P: AsRef<Path>>(
+    paths: &[P],
+) -> io::Result<Vec<Result<String, WrappedIoError>>> {
+    let ring = IoUring::new(RING_ENTRIES.min(paths.len().max(1) as u32))?;
+
+    let mut results: Vec<Result<String, WrappedIoError>> = Vec::with_capacity(paths.len());
+    let mut opened = Vec::new();
+    for (index, path) in paths.iter().enumerate() {
+        let path = path.as_ref();
+        match File::open(path) {
+            Ok(file) => {
+                opened.push((index, file, Vec::with_capacity(READ_BUFFER_SIZE)));
+                results.push(Ok(String::new()));
+            }
+            Err(err) => results.push(Err(WrappedIoError::Open {
+                err,
+                path: path.to_path_buf(),
+            })),
+        }
+    }
+
+    let mut reads: Vec<(&File, &mut Vec<u8>)> = opened
+        .iter_mut()
+        .map(|(_, file, buffer)| (&*file, buffer))
+        .collect();
+    let read_results = ring.read(&mut reads);
+    drop(reads);
+    let read_results = match read_results {
+        Ok(read_results) => read_results,
+        Err(ReadError { err, in_flight }) => {
+            if in_flight {
+                // the buffers are leaked rather than released while the
+                // kernel may write to them
+                mem::forget(opened);
+            }
+            return Err(err);
+        }
+    };
+
+    for ((index, file, mut buffer), result) in opened.into_iter().zip(read_results) {
+        let path = paths[index].as_ref();
+        if result < 0 {
+            // e.g. IORING_OP_READ is not supported before Linux 5.6, so
+            // retry with a plain read to get the proper error
+            results[index] = common::read_cgroup_file(path);
+            continue;
+        }
+
+        // the kernel has written `result` bytes of the buffer
+        unsafe { buffer.set_len(result as usize) };
+        results[index] = finish_read(&file, buffer).map_err(|err| WrappedIoError::Read {
+            err,
+            path: path.to_path_buf(),
+        });
+    }
+
+    Ok(results)
+}
+
+/// Reads the rest of the file if it did not fit into the buffer of the batched read
+fn finish_read(mut file: &File, mut buffer: Vec<u8>) -> io::Result<String> {
+    if buffer.len() == buffer.capacity() {
+        file.seek(SeekFrom::Start(buffer.len() as u64))?;
+        file.read_to_end(&mut buffer)?;
+    }
+
+    String::from_utf8(buffer).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::set_fixture;
+
+    #[test]
+    fn test_read_files_batched() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths: Vec<_> = (0..RING_ENTRIES + 10)
+            .map(|i| set_fixture(tmp.path(), &format!("file{i}"), &format!("{i}\n")).unwrap())
+            .collect();
+
+        let contents = match read_files(&paths) {
+            Ok(contents) => contents,
+            // io_uring is not available in every environment
+            Err(err) => {
+                eprintln!("skipping test, io_uring is not available: {err}");
+                return;
+            }
+        };
+
+        assert_eq!(contents.len(), paths.len());
+        for (i, content) in contents.iter().enumerate() {
+            assert_eq!(content.as_ref().unwrap(), &format!("{i}\n"));
+        }
+    }
+
+    #[test]
+    fn test_cancel_pending_reads() {
+        let ring = match IoUring::new(4) {
+            Ok(ring) => ring,
+            Err(err) => {
+                eprintln!("skipping test, io_uring is not available: {err}");
+                return;
+            }
+        };
+        // the read of an empty pipe only completes once it is cancelled
+        let (reader, _writer) = nix::unistd::pipe().unwrap();
+        let reader = File::from(reader);
+        let mut buffer = Vec::with_capacity(16);
+        let mut files = [(&reader, &mut buffer)];
+
+        let mut pending = BTreeSet::new();
+        ring.submit(&mut files, 0, &mut pending).unwrap();
+        assert_eq!(pending.len(), 1);
+        let mut results = [0];
+        ring.cancel(&mut pending, &mut results).unwrap();
+
+        assert!(pending.is_empty());
+        assert!(
+            [-libc::ECANCELED, -libc::EINTR].contains(&results[0]),
+            "{}",
+            results[0]
+        );
+    }
+}
@@ -14,6 +14,8 @@ pub enum ControllerType {
     NetworkPriority,
     NetworkClassifier,
     Freezer,
+    Misc,
+    Rdma,
 }
 
 impl Display for ControllerType {
@@ -31,6 +33,8 @@ impl Display for ControllerType {
             Self::NetworkPriority => "net_prio",
             Self::NetworkClassifier => "net_cls",
             Self::Freezer => "freezer",
+            Self::Misc => "misc",
+            Self::Rdma => "rdma",
         };
 
         write!(f, "{print}")
@@ -52,6 +56,8 @@ impl AsRef<str> for ControllerType {
             Self::NetworkPriority => "net_prio",
             Self::NetworkClassifier => "net_cls",
             Self::Freezer => "freezer",
+            Self::Misc => "misc",
+            Self::Rdma => "rdma",
         }
     }
 }
@@ -69,4 +75,6 @@ pub const CONTROLLERS: &[ControllerType] = &[
     ControllerType::NetworkPriority,
     ControllerType::NetworkClassifier,
     ControllerType::Freezer,
+    ControllerType::Misc,
+    ControllerType::Rdma,
 ];
@@ -4,7 +4,10 @@ use oci_spec::runtime::LinuxCpu;
 
 use super::controller::Controller;
 use crate::common::{self, ControllerOpt, WrappedIoError};
-use crate::stats::{parse_flat_keyed_data, CpuThrottling, ParseFlatKeyedDataError, StatsProvider};
+use crate::stats::{
+    parse_flat_keyed_data, parse_single_value, CpuBandwidth, CpuThrottling,
+    ParseFlatKeyedDataError, StatsProvider,
+};
 
 const CGROUP_CPU_SHARES: &str = "cpu.shares";
 const CGROUP_CPU_QUOTA: &str = "cpu.cfs_quota_us";
@@ -54,6 +57,10 @@ pub enum V1CpuStatsError {
     ParseData(#[from] ParseFlatKeyedDataError),
     #[error("missing field {field} from {path}")]
     MissingField { field: &'static str, path: PathBuf },
+    #[error("io error: {0}")]
+    WrappedIo(#[from] WrappedIoError),
+    #[error("invalid quota {content:?} in {path}")]
+    InvalidQuota { content: String, path: PathBuf },
 }
 
 impl StatsProvider for Cpu {
@@ -81,12 +88,40 @@ impl StatsProvider for Cpu {
         get!("nr_periods" => periods);
         get!("nr_throttled" => throttled_periods);
         get!("throttled_time" => throttled_time);
+        // the bursts are only reported since Linux 5.14
+        stats.bursts = stat_table.get("nr_bursts").copied().unwrap_or_default();
+        stats.burst_time = stat_table.get("burst_time").copied().unwrap_or_default();
 
         Ok(stats)
     }
 }
 
 impl Cpu {
+    /// Returns the cfs bandwidth limit, None if the kernel has been built
+    /// without CONFIG_CFS_BANDWIDTH
+    pub(crate) fn bandwidth(cgroup_path: &Path) -> Result<Option<CpuBandwidth>, V1CpuStatsError> {
+        let quota_path = cgroup_path.join(CGROUP_CPU_QUOTA);
+        if !quota_path.exists() {
+            return Ok(None);
+        }
+
+        let content = common::read_cgroup_file(&quota_path)?;
+        // an unlimited quota is -1
+        let quota: i64 = content
+            .trim()
+            .parse()
+            .map_err(|_| V1CpuStatsError::InvalidQuota {
+                content: content.trim().to_owned(),
+                path: quota_path.clone(),
+            })?;
+        let period = parse_single_value(&cgroup_path.join(CGROUP_CPU_PERIOD))?;
+
+        Ok(Some(CpuBandwidth {
+            quota: u64::try_from(quota).ok(),
+            period,
+        }))
+    }
+
     fn apply(root_path: &Path, cpu: &LinuxCpu) -> Result<(), WrappedIoError> {
         if let Some(cpu_shares) = cpu.shares() {
             if cpu_shares != 0 {
@@ -269,10 +304,36 @@ mod tests {
             periods: 165000,
             throttled_periods: 27,
             throttled_time: 1080,
+            ..Default::default()
         };
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_bandwidth() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert_eq!(Cpu::bandwidth(tmp.path()).expect("get bandwidth"), None);
+
+        set_fixture(tmp.path(), CGROUP_CPU_QUOTA, "-1\n").expect("create quota file");
+        set_fixture(tmp.path(), CGROUP_CPU_PERIOD, "100000\n").expect("create period file");
+        assert_eq!(
+            Cpu::bandwidth(tmp.path()).expect("get bandwidth"),
+            Some(CpuBandwidth {
+                quota: None,
+                period: 100000
+            })
+        );
+
+        set_fixture(tmp.path(), CGROUP_CPU_QUOTA, "50000\n").expect("create quota file");
+        assert_eq!(
+            Cpu::bandwidth(tmp.path()).expect("get bandwidth"),
+            Some(CpuBandwidth {
+                quota: Some(50000),
+                period: 100000
+            })
+        );
+    }
+
     #[test]
     fn test_set_burst() {
         // arrange
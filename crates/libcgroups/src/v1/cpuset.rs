@@ -9,6 +9,7 @@ use super::controller::Controller;
 use super::util::{self, V1MountPointError};
 use super::ControllerType;
 use crate::common::{self, ControllerOpt, WrapIoResult, WrappedIoError, CGROUP_PROCS};
+use crate::cpumask::{CpuMask, CpuMaskError};
 
 const CGROUP_CPUSET_CPUS: &str = "cpuset.cpus";
 const CGROUP_CPUSET_MEMS: &str = "cpuset.mems";
@@ -26,6 +27,8 @@ pub enum V1CpuSetControllerError {
     EmptyParent,
     #[error("mount point error: {0}")]
     MountPoint(#[from] V1MountPointError),
+    #[error("invalid cpuset: {0}")]
+    CpuMask(#[from] CpuMaskError),
 }
 
 pub struct CpuSet {}
@@ -68,11 +71,17 @@ impl Controller for CpuSet {
 impl CpuSet {
     fn apply(cgroup_path: &Path, cpuset: &LinuxCpu) -> Result<(), V1CpuSetControllerError> {
         if let Some(cpus) = &cpuset.cpus() {
-            common::write_cgroup_file_str(cgroup_path.join(CGROUP_CPUSET_CPUS), cpus)?;
+            let cpus: CpuMask = cpus.parse()?;
+            // the kernel rejects offline cpus without telling which ones they are
+            if let Err(err) = cpus.validate_online() {
+                tracing::warn!("{}", err);
+            }
+            common::write_cgroup_file(cgroup_path.join(CGROUP_CPUSET_CPUS), cpus)?;
         }
 
         if let Some(mems) = &cpuset.mems() {
-            common::write_cgroup_file_str(cgroup_path.join(CGROUP_CPUSET_MEMS), mems)?;
+            let mems: CpuMask = mems.parse()?;
+            common::write_cgroup_file(cgroup_path.join(CGROUP_CPUSET_MEMS), mems)?;
         }
 
         Ok(())
@@ -17,8 +17,10 @@ pub enum V1FreezerControllerError {
     WrappedIo(#[from] WrappedIoError),
     #[error("unexpected state {state} while freezing")]
     UnexpectedState { state: String },
-    #[error("unable to freeze")]
-    UnableToFreeze,
+    #[error("unable to freeze{}", common::describe_tasks(.tasks))]
+    UnableToFreeze { tasks: Vec<String> },
+    #[error("unable to thaw, the state is still {state}")]
+    UnableToThaw { state: String },
 }
 
 pub struct Freezer {}
@@ -50,68 +52,99 @@ impl Freezer {
     ) -> Result<(), V1FreezerControllerError> {
         match freezer_state {
             FreezerState::Undefined => {}
-            FreezerState::Thawed => {
-                common::write_cgroup_file(
-                    cgroup_root.join(CGROUP_FREEZER_STATE),
-                    FREEZER_STATE_THAWED,
-                )?;
-            }
+            FreezerState::Thawed => Self::thaw(cgroup_root)?,
             FreezerState::Frozen => {
-                let r = || -> Result<(), V1FreezerControllerError> {
-                    // We should do our best to retry if FREEZING is seen until it becomes FROZEN.
-                    // Add sleep between retries occasionally helped when system is extremely slow.
-                    // see:
-                    // https://github.com/opencontainers/runc/blob/b9ee9c6314599f1b4a7f497e1f1f856fe433d3b7/libcontainer/cgroups/fs/freezer.go#L42
-                    for i in 0..1000 {
-                        if i % 50 == 49 {
-                            let _ = common::write_cgroup_file(
-                                cgroup_root.join(CGROUP_FREEZER_STATE),
-                                FREEZER_STATE_THAWED,
-                            );
-                            thread::sleep(time::Duration::from_millis(10));
+                if let Err(err) = Self::freeze(cgroup_root) {
+                    // Freezing failed, and it is bad and dangerous to leave the cgroup in FROZEN or
+                    // FREEZING, so try to thaw it back.
+                    let _ = Self::write_freezer_state(cgroup_root, FREEZER_STATE_THAWED);
+                    return Err(match err {
+                        // the tasks that could not be frozen can only be told
+                        // apart from the frozen ones once the cgroup is thawed
+                        V1FreezerControllerError::UnableToFreeze { .. } => {
+                            V1FreezerControllerError::UnableToFreeze {
+                                tasks: common::uninterruptible_tasks(cgroup_root),
+                            }
                         }
+                        err => err,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
 
-                        common::write_cgroup_file(
-                            cgroup_root.join(CGROUP_FREEZER_STATE),
-                            FREEZER_STATE_FROZEN,
-                        )?;
+    fn freeze(cgroup_root: &Path) -> Result<(), V1FreezerControllerError> {
+        // We should do our best to retry if FREEZING is seen until it becomes FROZEN.
+        // Add sleep between retries occasionally helped when system is extremely slow.
+        // see:
+        // https://github.com/opencontainers/runc/blob/b9ee9c6314599f1b4a7f497e1f1f856fe433d3b7/libcontainer/cgroups/fs/freezer.go#L42
+        for i in 0..1000 {
+            if i % 50 == 49 {
+                let _ = Self::write_freezer_state(cgroup_root, FREEZER_STATE_THAWED);
+                thread::sleep(time::Duration::from_millis(10));
+            }
 
-                        if i % 25 == 24 {
-                            thread::sleep(time::Duration::from_millis(10));
-                        }
+            Self::write_freezer_state(cgroup_root, FREEZER_STATE_FROZEN)?;
 
-                        let r = Self::read_freezer_state(cgroup_root)?;
-                        match r.trim() {
-                            FREEZER_STATE_FREEZING => {
-                                continue;
-                            }
-                            FREEZER_STATE_FROZEN => {
-                                if i > 1 {
-                                    tracing::debug!("frozen after {} retries", i)
-                                }
-                                return Ok(());
-                            }
-                            _ => {
-                                // should not reach here.
-                                return Err(V1FreezerControllerError::UnexpectedState { state: r });
-                            }
-                        }
+            if i % 25 == 24 {
+                thread::sleep(time::Duration::from_millis(10));
+            }
+
+            let r = Self::read_freezer_state(cgroup_root)?;
+            match r.trim() {
+                FREEZER_STATE_FREEZING => {
+                    continue;
+                }
+                FREEZER_STATE_FROZEN => {
+                    if i > 1 {
+                        tracing::debug!("frozen after {} retries", i)
                     }
-                    Err(V1FreezerControllerError::UnableToFreeze)
-                }();
+                    return Ok(());
+                }
+                _ => {
+                    // should not reach here.
+                    return Err(V1FreezerControllerError::UnexpectedState { state: r });
+                }
+            }
+        }
 
-                if r.is_err() {
-                    // Freezing failed, and it is bad and dangerous to leave the cgroup in FROZEN or
-                    // FREEZING, so try to thaw it back.
-                    let _ = common::write_cgroup_file(
-                        cgroup_root.join(CGROUP_FREEZER_STATE),
-                        FREEZER_STATE_THAWED,
-                    );
+        Err(V1FreezerControllerError::UnableToFreeze { tasks: Vec::new() })
+    }
+
+    /// Thaws the cgroup and checks that the state has become THAWED, which
+    /// is not the case while a parent cgroup is still frozen
+    fn thaw(cgroup_root: &Path) -> Result<(), V1FreezerControllerError> {
+        let mut state = String::new();
+        for i in 0..100 {
+            Self::write_freezer_state(cgroup_root, FREEZER_STATE_THAWED)?;
+            state = Self::read_freezer_state(cgroup_root)?;
+            if state.trim() == FREEZER_STATE_THAWED {
+                if i > 1 {
+                    tracing::debug!("thawed after {} retries", i)
                 }
-                return r;
+                return Ok(());
             }
+
+            thread::sleep(time::Duration::from_millis(10));
+        }
+
+        Err(V1FreezerControllerError::UnableToThaw {
+            state: state.trim().to_owned(),
+        })
+    }
+
+    /// Writes the state to freezer.state. The kernel rejects changes with
+    /// EBUSY while a transition is in progress, which is retried by the
+    /// callers like a transient FREEZING state.
+    fn write_freezer_state(cgroup_root: &Path, state: &str) -> Result<(), WrappedIoError> {
+        match common::write_cgroup_file(cgroup_root.join(CGROUP_FREEZER_STATE), state) {
+            Err(err) if err.inner().raw_os_error() == Some(nix::errno::Errno::EBUSY as i32) => {
+                tracing::debug!("freezer of {:?} is busy", cgroup_root);
+                Ok(())
+            }
+            result => result,
         }
-        Ok(())
     }
 
     fn read_freezer_state(cgroup_root: &Path) -> Result<String, WrappedIoError> {
@@ -1,9 +1,9 @@
 use std::collections::HashMap;
-use std::fs;
+use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use nix::unistd::Pid;
+use nix::unistd::{Gid, Pid, Uid};
 use procfs::process::Process;
 use procfs::ProcError;
 
@@ -17,22 +17,39 @@ use super::devices::Devices;
 use super::freezer::{Freezer, V1FreezerControllerError};
 use super::hugetlb::{HugeTlb, V1HugeTlbControllerError, V1HugeTlbStatsError};
 use super::memory::{Memory, V1MemoryControllerError, V1MemoryStatsError};
+use super::misc::Misc;
 use super::network_classifier::NetworkClassifier;
 use super::network_priority::NetworkPriority;
 use super::perf_event::PerfEvent;
 use super::pids::Pids;
+use super::rdma::Rdma;
 use super::util::V1MountPointError;
 use super::{util, ControllerType as CtrlType};
+use crate::batch::{BatchError, WriteBatch};
 use crate::common::{
-    self, AnyCgroupManager, CgroupManager, ControllerOpt, FreezerState, JoinSafelyError,
-    PathBufExt, WrapIoResult, WrappedIoError, CGROUP_PROCS,
+    self, AnyCgroupManager, CgroupErrorKind, CgroupManager, ControllerOpt, FreezerState,
+    JoinSafelyError, PathBufExt, WrappedIoError,
+};
+use crate::events::CgroupEventsWatcher;
+use crate::oom::{OomNotifier, OomNotifierError};
+use crate::stats::{
+    self, MiscStatsError, ParseFlatKeyedDataError, PidStatsError, RdmaStatsError, Stats,
+    StatsProvider,
 };
-use crate::stats::{PidStatsError, Stats, StatsProvider};
 
 pub struct Manager {
     subsystems: HashMap<CtrlType, PathBuf>,
+    best_effort: Vec<String>,
 }
 
+// controllers which react to the errors of their writes, so that they can't
+// be planned
+const UNPLANNED: &[CtrlType] = &[
+    CtrlType::Memory,
+    CtrlType::Freezer,
+    CtrlType::NetworkPriority,
+];
+
 #[derive(thiserror::Error, Debug)]
 pub enum V1ManagerError {
     #[error("io error: {0}")]
@@ -47,6 +64,12 @@ pub enum V1ManagerError {
     CGroupRequired(CtrlType),
     #[error("subsystem does not exist")]
     SubsystemDoesNotExist,
+    #[error(transparent)]
+    OomNotifier(#[from] OomNotifierError),
+    #[error("cgroup events are only supported by cgroup v2")]
+    EventsNotSupported,
+    #[error(transparent)]
+    Batch(#[from] BatchError),
 
     #[error(transparent)]
     BlkioController(WrappedIoError),
@@ -77,6 +100,48 @@ pub enum V1ManagerError {
     HugeTlbStats(#[from] V1HugeTlbStatsError),
     #[error(transparent)]
     MemoryStats(#[from] V1MemoryStatsError),
+    #[error(transparent)]
+    MiscStats(#[from] MiscStatsError),
+    #[error(transparent)]
+    RdmaStats(#[from] RdmaStatsError),
+    #[error(transparent)]
+    NetworkClassifierStats(WrappedIoError),
+    #[error(transparent)]
+    NetworkPriorityStats(#[from] ParseFlatKeyedDataError),
+}
+
+impl V1ManagerError {
+    pub fn kind(&self) -> CgroupErrorKind {
+        match self {
+            Self::WrappedIo(_) | Self::MountPoint(_) | Self::Proc(_) | Self::OomNotifier(_) => {
+                CgroupErrorKind::Io
+            }
+            Self::JoinSafely(_) => CgroupErrorKind::InvalidPath,
+            Self::CGroupRequired(_) | Self::SubsystemDoesNotExist => {
+                CgroupErrorKind::ControllerNotFound
+            }
+            Self::EventsNotSupported => CgroupErrorKind::NotSupported,
+            Self::BlkioController(_)
+            | Self::CpuController(_)
+            | Self::CpuAcctController(_)
+            | Self::CpuSetController(_)
+            | Self::FreezerController(_)
+            | Self::HugeTlbController(_)
+            | Self::MemoryController(_)
+            | Self::PidsController(_)
+            | Self::Batch(_) => CgroupErrorKind::Controller,
+            Self::BlkioStats(_)
+            | Self::CpuStats(_)
+            | Self::CpuAcctStats(_)
+            | Self::PidsStats(_)
+            | Self::HugeTlbStats(_)
+            | Self::MemoryStats(_)
+            | Self::MiscStats(_)
+            | Self::RdmaStats(_)
+            | Self::NetworkClassifierStats(_)
+            | Self::NetworkPriorityStats(_) => CgroupErrorKind::Stats,
+        }
+    }
 }
 
 impl Manager {
@@ -91,7 +156,17 @@ impl Manager {
             }
         }
 
-        Ok(Manager { subsystems })
+        Ok(Manager {
+            subsystems,
+            best_effort: Vec::new(),
+        })
+    }
+
+    /// Applies the resources of the named controllers on a best effort
+    /// basis, see [`crate::batch`]
+    pub fn with_best_effort(mut self, controllers: Vec<String>) -> Self {
+        self.best_effort = controllers;
+        self
     }
 
     fn get_subsystem_path(
@@ -99,7 +174,7 @@ impl Manager {
         subsystem: &CtrlType,
     ) -> Result<PathBuf, V1ManagerError> {
         tracing::debug!("Get path for subsystem: {}", subsystem);
-        let mount_point = util::get_subsystem_mount_point(subsystem)?;
+        let mount = util::get_subsystem_mount_info(subsystem)?;
 
         let cgroup = Process::myself()?
             .cgroups()?
@@ -108,9 +183,10 @@ impl Manager {
             .ok_or(V1ManagerError::SubsystemDoesNotExist)?;
 
         let p = if cgroup_path.as_os_str().is_empty() {
-            mount_point.join_safely(Path::new(&cgroup.pathname))?
+            let current = common::normalize_cgroup_path(&cgroup.pathname, Path::new(&mount.root));
+            mount.mount_point.join_safely(current)?
         } else {
-            mount_point.join_safely(cgroup_path)?
+            mount.mount_point.join_safely(cgroup_path)?
         };
 
         Ok(p)
@@ -140,6 +216,8 @@ impl Manager {
                     NetworkClassifier::needs_to_handle(controller_opt).is_some()
                 }
                 CtrlType::Freezer => Freezer::needs_to_handle(controller_opt).is_some(),
+                CtrlType::Misc => Misc::needs_to_handle(controller_opt).is_some(),
+                CtrlType::Rdma => Rdma::needs_to_handle(controller_opt).is_some(),
             };
 
             if required {
@@ -154,6 +232,31 @@ impl Manager {
         Ok(required_controllers)
     }
 
+    fn apply_controller(
+        ctrl_type: &CtrlType,
+        controller_opt: &ControllerOpt,
+        cgroup_path: &Path,
+    ) -> Result<(), V1ManagerError> {
+        match ctrl_type {
+            CtrlType::Cpu => Cpu::apply(controller_opt, cgroup_path)?,
+            CtrlType::CpuAcct => CpuAcct::apply(controller_opt, cgroup_path)?,
+            CtrlType::CpuSet => CpuSet::apply(controller_opt, cgroup_path)?,
+            CtrlType::Devices => Devices::apply(controller_opt, cgroup_path)?,
+            CtrlType::HugeTlb => HugeTlb::apply(controller_opt, cgroup_path)?,
+            CtrlType::Memory => Memory::apply(controller_opt, cgroup_path)?,
+            CtrlType::Pids => Pids::apply(controller_opt, cgroup_path)?,
+            CtrlType::PerfEvent => PerfEvent::apply(controller_opt, cgroup_path)?,
+            CtrlType::Blkio => Blkio::apply(controller_opt, cgroup_path)?,
+            CtrlType::NetworkPriority => NetworkPriority::apply(controller_opt, cgroup_path)?,
+            CtrlType::NetworkClassifier => NetworkClassifier::apply(controller_opt, cgroup_path)?,
+            CtrlType::Freezer => Freezer::apply(controller_opt, cgroup_path)?,
+            CtrlType::Misc => Misc::apply(controller_opt, cgroup_path)?,
+            CtrlType::Rdma => Rdma::apply(controller_opt, cgroup_path)?,
+        }
+
+        Ok(())
+    }
+
     pub fn any(self) -> AnyCgroupManager {
         AnyCgroupManager::V1(self)
     }
@@ -186,31 +289,29 @@ impl CgroupManager for Manager {
                 CtrlType::NetworkPriority => NetworkPriority::add_task(pid, cgroup_path)?,
                 CtrlType::NetworkClassifier => NetworkClassifier::add_task(pid, cgroup_path)?,
                 CtrlType::Freezer => Freezer::add_task(pid, cgroup_path)?,
+                CtrlType::Misc => Misc::add_task(pid, cgroup_path)?,
+                CtrlType::Rdma => Rdma::add_task(pid, cgroup_path)?,
             }
         }
 
         Ok(())
     }
 
+    fn open_task_cgroup(&self) -> Result<Option<File>, Self::Error> {
+        Ok(None)
+    }
+
     fn apply(&self, controller_opt: &ControllerOpt) -> Result<(), Self::Error> {
+        let mut batch = WriteBatch::new(&self.best_effort);
         for (ctrl_type, cgroup_path) in self.get_required_controllers(controller_opt)? {
-            match ctrl_type {
-                CtrlType::Cpu => Cpu::apply(controller_opt, cgroup_path)?,
-                CtrlType::CpuAcct => CpuAcct::apply(controller_opt, cgroup_path)?,
-                CtrlType::CpuSet => CpuSet::apply(controller_opt, cgroup_path)?,
-                CtrlType::Devices => Devices::apply(controller_opt, cgroup_path)?,
-                CtrlType::HugeTlb => HugeTlb::apply(controller_opt, cgroup_path)?,
-                CtrlType::Memory => Memory::apply(controller_opt, cgroup_path)?,
-                CtrlType::Pids => Pids::apply(controller_opt, cgroup_path)?,
-                CtrlType::PerfEvent => PerfEvent::apply(controller_opt, cgroup_path)?,
-                CtrlType::Blkio => Blkio::apply(controller_opt, cgroup_path)?,
-                CtrlType::NetworkPriority => NetworkPriority::apply(controller_opt, cgroup_path)?,
-                CtrlType::NetworkClassifier => {
-                    NetworkClassifier::apply(controller_opt, cgroup_path)?
-                }
-                CtrlType::Freezer => Freezer::apply(controller_opt, cgroup_path)?,
+            let apply = move || Self::apply_controller(ctrl_type, controller_opt, cgroup_path);
+            if UNPLANNED.contains(ctrl_type) {
+                batch.unplanned(ctrl_type, apply);
+            } else {
+                batch.plan(ctrl_type, apply)?;
             }
         }
+        batch.apply()?;
 
         Ok(())
     }
@@ -219,18 +320,8 @@ impl CgroupManager for Manager {
         for cgroup_path in self.subsystems.values() {
             if cgroup_path.exists() {
                 tracing::debug!("remove cgroup {:?}", cgroup_path);
-                let procs_path = cgroup_path.join(CGROUP_PROCS);
-                let procs = fs::read_to_string(&procs_path).wrap_read(&procs_path)?;
-
-                for line in procs.lines() {
-                    let pid: i32 = line
-                        .parse()
-                        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
-                        .wrap_other(&procs_path)?;
-                    let _ = nix::sys::signal::kill(Pid::from_raw(pid), nix::sys::signal::SIGKILL);
-                }
-
-                common::delete_with_retry(cgroup_path, 4, Duration::from_millis(100))?;
+                common::kill_all_with_retry(cgroup_path, 10)?;
+                common::delete_tree_with_retry(cgroup_path, 4, Duration::from_millis(100))?;
             }
         }
 
@@ -253,20 +344,76 @@ impl CgroupManager for Manager {
     }
 
     fn stats(&self) -> Result<Stats, Self::Error> {
-        let mut stats = Stats::default();
+        // the files of all controllers are read at once, see
+        // [`stats::with_prefetched`]
+        let dirs: Vec<_> = self
+            .subsystems
+            .iter()
+            .filter(|(ctrl_type, _)| {
+                !matches!(
+                    ctrl_type,
+                    CtrlType::CpuSet | CtrlType::Devices | CtrlType::Freezer | CtrlType::PerfEvent
+                )
+            })
+            .map(|(ctrl_type, cgroup_path)| (cgroup_path.as_path(), ctrl_type))
+            .collect();
+        stats::with_prefetched(&dirs, || {
+            let mut stats = Stats::default();
 
-        for (ctrl_type, cgroup_path) in &self.subsystems {
-            match ctrl_type {
-                CtrlType::Cpu => stats.cpu.throttling = Cpu::stats(cgroup_path)?,
-                CtrlType::CpuAcct => stats.cpu.usage = CpuAcct::stats(cgroup_path)?,
-                CtrlType::Pids => stats.pids = Pids::stats(cgroup_path)?,
-                CtrlType::HugeTlb => stats.hugetlb = HugeTlb::stats(cgroup_path)?,
-                CtrlType::Blkio => stats.blkio = Blkio::stats(cgroup_path)?,
-                CtrlType::Memory => stats.memory = Memory::stats(cgroup_path)?,
-                _ => continue,
+            for (ctrl_type, cgroup_path) in &self.subsystems {
+                match ctrl_type {
+                    CtrlType::Cpu => {
+                        stats.cpu.throttling = Cpu::stats(cgroup_path)?;
+                        stats.cpu.bandwidth = Cpu::bandwidth(cgroup_path)?;
+                    }
+                    CtrlType::CpuAcct => stats.cpu.usage = CpuAcct::stats(cgroup_path)?,
+                    CtrlType::Pids => stats.pids = Pids::stats(cgroup_path)?,
+                    CtrlType::HugeTlb => stats.hugetlb = HugeTlb::stats(cgroup_path)?,
+                    CtrlType::Blkio => stats.blkio = Blkio::stats(cgroup_path)?,
+                    CtrlType::Memory => stats.memory = Memory::stats(cgroup_path)?,
+                    CtrlType::Misc => stats.misc = Misc::stats(cgroup_path)?,
+                    CtrlType::Rdma => stats.rdma = Rdma::stats(cgroup_path)?,
+                    CtrlType::NetworkClassifier => {
+                        stats.network.class_id = NetworkClassifier::stats(cgroup_path)
+                            .map_err(V1ManagerError::NetworkClassifierStats)?
+                    }
+                    CtrlType::NetworkPriority => {
+                        stats.network.priorities = NetworkPriority::stats(cgroup_path)?
+                    }
+                    _ => continue,
+                }
             }
-        }
 
-        Ok(stats)
+            Ok(stats)
+        })
+    }
+
+    fn oom_notifier(&self) -> Result<OomNotifier, Self::Error> {
+        let memory = self
+            .subsystems
+            .get(&CtrlType::Memory)
+            .ok_or(V1ManagerError::CGroupRequired(CtrlType::Memory))?;
+        Ok(OomNotifier::new_v1(memory)?)
+    }
+
+    fn events_watcher(&self) -> Result<CgroupEventsWatcher, Self::Error> {
+        Err(V1ManagerError::EventsNotSupported)
+    }
+
+    fn kill_all(&self) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    fn reclaim_memory(&self, _bytes: u64) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    fn delegate(
+        &self,
+        _owner: Uid,
+        _group: Gid,
+        _controllers: &[String],
+    ) -> Result<bool, Self::Error> {
+        Ok(false)
     }
 }
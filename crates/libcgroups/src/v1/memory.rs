@@ -5,11 +5,15 @@ use std::io::prelude::*;
 use std::io::Write;
 use std::num::ParseIntError;
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 
 use nix::errno::Errno;
 use oci_spec::runtime::LinuxMemory;
+use procfs::KernelVersion;
 
 use super::controller::Controller;
+use crate::checkpoint;
 use crate::common::{self, ControllerOpt, WrapIoResult, WrappedIoError};
 use crate::stats::{
     self, parse_single_value, MemoryData, MemoryStats, ParseFlatKeyedDataError, StatsProvider,
@@ -25,6 +29,9 @@ const CGROUP_MEMORY_OOM_CONTROL: &str = "memory.oom_control";
 
 const CGROUP_KERNEL_MEMORY_LIMIT: &str = "memory.kmem.limit_in_bytes";
 const CGROUP_KERNEL_TCP_MEMORY_LIMIT: &str = "memory.kmem.tcp.limit_in_bytes";
+// Error of the kernel for operations which are not supported, which is not
+// part of the errno values of userspace but is returned for some cgroup files
+const ENOTSUPP: i32 = 524;
 
 // Shows various memory statistics
 const MEMORY_STAT: &str = "memory.stat";
@@ -46,6 +53,10 @@ const MEMORY_MAX_USAGE_IN_BYTES: &str = ".max_usage_in_bytes";
 const MEMORY_LIMIT_IN_BYTES: &str = ".limit_in_bytes";
 // Number of times memory usage hit limits
 const MEMORY_FAIL_COUNT: &str = ".failcnt";
+// Number of writes of a memory limit below the usage, between which the
+// kernel gets time to reclaim
+const LIMIT_ATTEMPTS: usize = 5;
+const LIMIT_RETRY_DELAY: Duration = Duration::from_millis(100);
 
 #[derive(Debug)]
 pub enum MalformedThing {
@@ -85,6 +96,8 @@ pub enum V1MemoryControllerError {
         current: u64,
         peak: u64,
     },
+    #[error("memory and swap limit ({swap}) must not be smaller than the memory limit ({limit})")]
+    SwapTooSmall { swap: i64, limit: i64 },
 }
 
 pub struct Memory {}
@@ -102,6 +115,7 @@ impl Controller for Memory {
         if let Some(memory) = &controller_opt.resources.memory() {
             let reservation = memory.reservation().unwrap_or(0);
 
+            Self::ensure_hierarchy(cgroup_root);
             Self::apply(memory, cgroup_root)?;
 
             if reservation != 0 {
@@ -111,10 +125,17 @@ impl Controller for Memory {
                 )?;
             }
 
-            if controller_opt.disable_oom_killer {
-                common::write_cgroup_file(cgroup_root.join(CGROUP_MEMORY_OOM_CONTROL), 0)?;
-            } else {
-                common::write_cgroup_file(cgroup_root.join(CGROUP_MEMORY_OOM_CONTROL), 1)?;
+            // writing 1 to oom_control disables the oom killer. It is only
+            // written if requested, so that updates keep the current setting.
+            let disable_oom_killer = match controller_opt.disable_oom_killer {
+                true => Some(true),
+                false => memory.disable_oom_killer(),
+            };
+            if let Some(disable) = disable_oom_killer {
+                common::write_cgroup_file(
+                    cgroup_root.join(CGROUP_MEMORY_OOM_CONTROL),
+                    u8::from(disable),
+                )?;
             }
 
             if let Some(swappiness) = memory.swappiness() {
@@ -131,17 +152,16 @@ impl Controller for Memory {
                 }
             }
 
-            // NOTE: Seems as though kernel and kernelTCP are both deprecated
-            // neither are implemented by runc. Tests pass without this, but
-            // kept in per the spec.
             if let Some(kmem) = memory.kernel() {
-                common::write_cgroup_file(cgroup_root.join(CGROUP_KERNEL_MEMORY_LIMIT), kmem)?;
+                if Self::kernel_memory_deprecated() {
+                    tracing::warn!(
+                        "kernel memory limits are deprecated since Linux 5.4 and may be ignored by the kernel"
+                    );
+                }
+                Self::set_kernel_limit(kmem, &cgroup_root.join(CGROUP_KERNEL_MEMORY_LIMIT))?;
             }
             if let Some(tcp_mem) = memory.kernel_tcp() {
-                common::write_cgroup_file(
-                    cgroup_root.join(CGROUP_KERNEL_TCP_MEMORY_LIMIT),
-                    tcp_mem,
-                )?;
+                Self::set_kernel_limit(tcp_mem, &cgroup_root.join(CGROUP_KERNEL_TCP_MEMORY_LIMIT))?;
             }
         }
 
@@ -166,7 +186,15 @@ impl StatsProvider for Memory {
 
     fn stats(cgroup_path: &Path) -> Result<Self::Stats, Self::Error> {
         let memory = Self::get_memory_data(cgroup_path, MEMORY_PREFIX)?;
-        let memswap = Self::get_memory_data(cgroup_path, MEMORY_AND_SWAP_PREFIX)?;
+        // the memsw files only exist with swap accounting
+        let swap_accounting = cgroup_path.join(CGROUP_MEMORY_SWAP_LIMIT).exists();
+        let (memswap, swap) = if swap_accounting {
+            let memswap = Self::get_memory_data(cgroup_path, MEMORY_AND_SWAP_PREFIX)?;
+            let swap = swap_only(&memory, &memswap);
+            (memswap, swap)
+        } else {
+            Default::default()
+        };
         let kernel = Self::get_memory_data(cgroup_path, MEMORY_KERNEL_PREFIX)?;
         let kernel_tcp = Self::get_memory_data(cgroup_path, MEMORY_KERNEL_TCP_PREFIX)?;
         let hierarchy = Self::hierarchy_enabled(cgroup_path)?;
@@ -175,6 +203,8 @@ impl StatsProvider for Memory {
         Ok(MemoryStats {
             memory,
             memswap,
+            swap,
+            swap_accounting,
             kernel,
             kernel_tcp,
             cache: stats["cache"],
@@ -185,6 +215,28 @@ impl StatsProvider for Memory {
     }
 }
 
+// The kernel reports no limit as the largest number of pages, i.e. i64::MAX
+// rounded down to the page size, which is at most 64k
+const UNLIMITED: u64 = i64::MAX as u64 & !0xffff;
+
+/// Subtracts the memory from the memory and swap, as cgroup v1 only reports
+/// them together
+fn swap_only(memory: &MemoryData, memswap: &MemoryData) -> MemoryData {
+    let limit = if memswap.limit >= UNLIMITED {
+        memswap.limit
+    } else {
+        memswap.limit.saturating_sub(memory.limit)
+    };
+    MemoryData {
+        usage: memswap.usage.saturating_sub(memory.usage),
+        // the peaks of memory and of memory and swap are usually not reached
+        // at once
+        max_usage: 0,
+        fail_count: memswap.fail_count,
+        limit,
+    }
+}
+
 impl Memory {
     fn get_memory_data(
         cgroup_path: &Path,
@@ -307,6 +359,55 @@ impl Memory {
         Ok(val)
     }
 
+    // Limits of the parent cgroups only apply to the container if the memory
+    // hierarchy is enabled, which can only be changed as long as the cgroup
+    // has no children.
+    fn ensure_hierarchy(cgroup_root: &Path) {
+        // newer kernels don't provide the file, as the hierarchy is always enabled
+        if !matches!(Self::hierarchy_enabled(cgroup_root), Ok(false)) {
+            return;
+        }
+
+        if let Err(err) = common::write_cgroup_file(cgroup_root.join(MEMORY_USE_HIERARCHY), 1) {
+            tracing::warn!(
+                ?err,
+                ?cgroup_root,
+                "memory hierarchy is disabled, limits of the parent cgroups don't apply"
+            );
+        }
+    }
+
+    fn kernel_memory_deprecated() -> bool {
+        KernelVersion::current()
+            .map(|version| version >= KernelVersion::new(5, 4, 0))
+            .unwrap_or(false)
+    }
+
+    /// Sets a kernel memory limit, which is skipped with a warning if the
+    /// kernel does not support it, as it has been built without kernel
+    /// memory accounting or has removed the limit
+    fn set_kernel_limit(limit: i64, path: &Path) -> Result<(), WrappedIoError> {
+        let err = match common::write_cgroup_file(path, limit) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+
+        let unsupported = err.inner().kind() == std::io::ErrorKind::NotFound
+            || matches!(
+                err.inner().raw_os_error(),
+                Some(code) if code == Errno::EOPNOTSUPP as i32 || code == ENOTSUPP
+            );
+        if !unsupported {
+            return Err(err);
+        }
+
+        tracing::warn!(
+            ?path,
+            "kernel memory limit is not supported by the kernel, ignoring it"
+        );
+        Ok(())
+    }
+
     fn set<T: ToString>(val: T, path: &Path) -> Result<(), WrappedIoError> {
         let data = val.to_string();
         OpenOptions::new()
@@ -316,7 +417,8 @@ impl Memory {
             .open(path)
             .wrap_open(path)?
             .write_all(data.as_bytes())
-            .wrap_write(path, data)?;
+            .wrap_write(path, data.clone())?;
+        checkpoint::record_write(path, &data);
         Ok(())
     }
 
@@ -326,27 +428,34 @@ impl Memory {
         }
         let path = cgroup_root.join(CGROUP_MEMORY_LIMIT);
 
-        match Self::set(val, &path) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                // we need to look into the raw OS error for an EBUSY status
-                match e.inner().raw_os_error() {
-                    Some(code) => match Errno::from_raw(code) {
-                        Errno::EBUSY => {
-                            let usage = Self::get_memory_usage(cgroup_root)?;
-                            let max_usage = Self::get_memory_max_usage(cgroup_root)?;
-                            Err(V1MemoryControllerError::UnableToSet {
-                                target: val,
-                                current: usage,
-                                peak: max_usage,
-                            })
-                        }
-                        _ => Err(e)?,
-                    },
-                    None => Err(e)?,
-                }
+        // a limit below the usage fails with EBUSY if the kernel could not
+        // reclaim enough memory, which may succeed on a later attempt
+        for attempt in 1..=LIMIT_ATTEMPTS {
+            let err = match Self::set(val, &path) {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+            let busy = err.inner().raw_os_error() == Some(Errno::EBUSY as i32);
+            if !busy {
+                return Err(err.into());
+            }
+            if attempt < LIMIT_ATTEMPTS {
+                tracing::debug!(
+                    attempt,
+                    limit = val,
+                    "memory usage exceeds the new limit, retrying"
+                );
+                thread::sleep(LIMIT_RETRY_DELAY);
             }
         }
+
+        let usage = Self::get_memory_usage(cgroup_root)?;
+        let max_usage = Self::get_memory_max_usage(cgroup_root)?;
+        Err(V1MemoryControllerError::UnableToSet {
+            target: val,
+            current: usage,
+            peak: max_usage,
+        })
     }
 
     fn set_swap(swap: i64, cgroup_root: &Path) -> Result<(), V1MemoryControllerError> {
@@ -398,7 +507,18 @@ impl Memory {
                 }
             }
             None => match resource.swap() {
-                Some(swap) => Self::set_memory_and_swap(0, swap, false, cgroup_root)?,
+                Some(swap) => {
+                    // the memory and swap limit covers the memory limit, which
+                    // stays as it is
+                    let current_limit = Self::get_memory_limit(cgroup_root)?;
+                    if swap > 0 && swap < current_limit {
+                        return Err(V1MemoryControllerError::SwapTooSmall {
+                            swap,
+                            limit: current_limit,
+                        });
+                    }
+                    Self::set_memory_and_swap(0, swap, false, cgroup_root)?
+                }
                 None => Self::set_memory_and_swap(0, 0, false, cgroup_root)?,
             },
         }
@@ -498,6 +618,97 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_set_swap_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), CGROUP_MEMORY_LIMIT, "1024").expect("Set fixure for memory limit");
+        set_fixture(tmp.path(), CGROUP_MEMORY_SWAP_LIMIT, "1024")
+            .expect("Set fixure for swap limit");
+
+        let linux_memory = LinuxMemoryBuilder::default().swap(4096).build().unwrap();
+        Memory::apply(&linux_memory, tmp.path()).expect("Set swap");
+        let limit_content =
+            std::fs::read_to_string(tmp.path().join(CGROUP_MEMORY_LIMIT)).expect("Read to string");
+        assert_eq!(limit_content, "1024");
+        let swap_content = std::fs::read_to_string(tmp.path().join(CGROUP_MEMORY_SWAP_LIMIT))
+            .expect("Read to string");
+        assert_eq!(swap_content, "4096");
+
+        let linux_memory = LinuxMemoryBuilder::default().swap(512).build().unwrap();
+        assert!(matches!(
+            Memory::apply(&linux_memory, tmp.path()),
+            Err(V1MemoryControllerError::SwapTooSmall {
+                swap: 512,
+                limit: 1024
+            })
+        ));
+    }
+
+    #[test]
+    fn test_disable_oom_killer() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), CGROUP_MEMORY_LIMIT, "0").expect("Set fixure for memory limit");
+        set_fixture(tmp.path(), CGROUP_MEMORY_OOM_CONTROL, "0")
+            .expect("Set fixure for oom control");
+        let apply = |disable_oom_killer: Option<bool>| {
+            let mut builder = LinuxMemoryBuilder::default();
+            if let Some(disable_oom_killer) = disable_oom_killer {
+                builder = builder.disable_oom_killer(disable_oom_killer);
+            }
+            let memory = builder.build().unwrap();
+            let resources = LinuxResourcesBuilder::default()
+                .memory(memory)
+                .build()
+                .unwrap();
+            let controller_opt = ControllerOpt {
+                resources: &resources,
+                disable_oom_killer: false,
+                oom_score_adj: None,
+                freezer_state: None,
+            };
+            <Memory as Controller>::apply(&controller_opt, tmp.path()).expect("apply memory");
+            std::fs::read_to_string(tmp.path().join(CGROUP_MEMORY_OOM_CONTROL)).unwrap()
+        };
+
+        // 1 disables the oom killer, an unset value keeps the current setting
+        assert_eq!(apply(Some(true)), "1");
+        assert_eq!(apply(None), "1");
+        assert_eq!(apply(Some(false)), "0");
+    }
+
+    #[test]
+    fn test_kernel_memory_unsupported() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), CGROUP_MEMORY_LIMIT, "0").expect("Set fixure for memory limit");
+        let memory = LinuxMemoryBuilder::default()
+            .kernel(1024)
+            .kernel_tcp(1024)
+            .build()
+            .unwrap();
+        let resources = LinuxResourcesBuilder::default()
+            .memory(memory)
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+
+        <Memory as Controller>::apply(&controller_opt, tmp.path())
+            .expect("missing kernel memory files are ignored");
+        assert!(!tmp.path().join(CGROUP_KERNEL_MEMORY_LIMIT).exists());
+    }
+
+    #[test]
+    fn test_enable_hierarchy() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), MEMORY_USE_HIERARCHY, "0").expect("Set fixure for hierarchy");
+        Memory::ensure_hierarchy(tmp.path());
+        assert!(Memory::hierarchy_enabled(tmp.path()).unwrap());
+    }
+
     quickcheck! {
             fn property_test_set_memory(linux_memory: LinuxMemory, disable_oom_killer: bool) -> bool {
                 let tmp = tempfile::tempdir().unwrap();
@@ -663,6 +874,37 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_swap_only() {
+        let memory = MemoryData {
+            usage: 1024,
+            max_usage: 2048,
+            limit: 4096,
+            fail_count: 1,
+        };
+        let memswap = MemoryData {
+            usage: 1536,
+            max_usage: 2048,
+            limit: 8192,
+            fail_count: 2,
+        };
+        assert_eq!(
+            swap_only(&memory, &memswap),
+            MemoryData {
+                usage: 512,
+                max_usage: 0,
+                limit: 4096,
+                fail_count: 2,
+            }
+        );
+
+        let unlimited = MemoryData {
+            limit: 9223372036854771712,
+            ..memswap
+        };
+        assert_eq!(swap_only(&memory, &unlimited).limit, 9223372036854771712);
+    }
+
     #[test]
     fn test_stat_hierarchy_enabled() {
         let tmp = tempfile::tempdir().unwrap();
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::controller::Controller;
+use crate::common::{self, ControllerOpt, WrappedIoError};
+use crate::stats::{self, MiscStats, MiscStatsError, StatsProvider};
+
+const CGROUP_MISC_PREFIX: &str = "misc.";
+
+/// The misc controller doesn't have a counterpart in the runtime spec yet, so
+/// limits like misc.max are taken from the unified map, which is otherwise
+/// ignored on cgroup v1
+pub struct Misc {}
+
+impl Controller for Misc {
+    type Error = WrappedIoError;
+    type Resource = HashMap<String, String>;
+
+    fn apply(controller_opt: &ControllerOpt, cgroup_root: &Path) -> Result<(), Self::Error> {
+        tracing::debug!("Apply misc cgroup config");
+
+        if let Some(unified) = Self::needs_to_handle(controller_opt) {
+            Self::apply(cgroup_root, unified)?;
+        }
+
+        Ok(())
+    }
+
+    fn needs_to_handle<'a>(controller_opt: &'a ControllerOpt) -> Option<&'a Self::Resource> {
+        controller_opt
+            .resources
+            .unified()
+            .as_ref()
+            .filter(|unified| unified.keys().any(|k| k.starts_with(CGROUP_MISC_PREFIX)))
+    }
+}
+
+impl StatsProvider for Misc {
+    type Error = MiscStatsError;
+    type Stats = HashMap<String, MiscStats>;
+
+    fn stats(cgroup_path: &Path) -> Result<Self::Stats, Self::Error> {
+        stats::misc_stats(cgroup_path)
+    }
+}
+
+impl Misc {
+    fn apply(root_path: &Path, unified: &HashMap<String, String>) -> Result<(), WrappedIoError> {
+        for (file, value) in unified
+            .iter()
+            .filter(|(k, _)| k.starts_with(CGROUP_MISC_PREFIX))
+        {
+            common::write_cgroup_file_entries(root_path.join(file), value)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oci_spec::runtime::LinuxResourcesBuilder;
+
+    use super::*;
+    use crate::test::set_fixture;
+
+    #[test]
+    fn test_set_misc_max() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), "misc.max", "").expect("set fixture for misc.max");
+
+        let unified = HashMap::from([
+            ("misc.max".to_owned(), "sgx_epc 65536".to_owned()),
+            ("memory.high".to_owned(), "1048576".to_owned()),
+        ]);
+        let resources = LinuxResourcesBuilder::default()
+            .unified(unified)
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+        };
+
+        assert!(Misc::needs_to_handle(&controller_opt).is_some());
+        <Misc as Controller>::apply(&controller_opt, tmp.path()).expect("apply misc");
+
+        let content = std::fs::read_to_string(tmp.path().join("misc.max")).expect("read misc.max");
+        assert_eq!(content, "sgx_epc 65536");
+    }
+
+    #[test]
+    fn test_misc_not_needed() {
+        let resources = LinuxResourcesBuilder::default()
+            .unified(HashMap::from([(
+                "memory.high".to_owned(),
+                "1048576".to_owned(),
+            )]))
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+        };
+
+        assert!(Misc::needs_to_handle(&controller_opt).is_none());
+    }
+}
@@ -9,10 +9,12 @@ mod freezer;
 mod hugetlb;
 pub mod manager;
 mod memory;
+mod misc;
 mod network_classifier;
 mod network_priority;
 pub mod perf_event;
 mod pids;
+mod rdma;
 pub mod util;
 pub use controller_type::ControllerType;
 pub use manager::Manager;
@@ -3,7 +3,10 @@ use std::path::Path;
 use oci_spec::runtime::LinuxNetwork;
 
 use super::controller::Controller;
-use crate::common::{self, ControllerOpt, WrappedIoError};
+use crate::common::{self, ControllerOpt, WrapIoResult, WrappedIoError};
+use crate::stats::{self, StatsProvider};
+
+const CGROUP_NET_CLS_CLASSID: &str = "net_cls.classid";
 
 pub struct NetworkClassifier {}
 
@@ -22,14 +25,31 @@ impl Controller for NetworkClassifier {
     }
 
     fn needs_to_handle<'a>(controller_opt: &'a ControllerOpt) -> Option<&'a Self::Resource> {
-        controller_opt.resources.network().as_ref()
+        controller_opt
+            .resources
+            .network()
+            .as_ref()
+            .filter(|network| network.class_id().is_some())
+    }
+}
+
+impl StatsProvider for NetworkClassifier {
+    type Error = WrappedIoError;
+    type Stats = u32;
+
+    fn stats(cgroup_path: &Path) -> Result<Self::Stats, Self::Error> {
+        let path = cgroup_path.join(CGROUP_NET_CLS_CLASSID);
+        let class_id = stats::parse_single_value(&path)?;
+        u32::try_from(class_id)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+            .wrap_other(path)
     }
 }
 
 impl NetworkClassifier {
     fn apply(root_path: &Path, network: &LinuxNetwork) -> Result<(), WrappedIoError> {
         if let Some(class_id) = network.class_id() {
-            common::write_cgroup_file(root_path.join("net_cls.classid"), class_id)?;
+            common::write_cgroup_file(root_path.join(CGROUP_NET_CLS_CLASSID), class_id)?;
         }
 
         Ok(())
@@ -38,7 +58,7 @@ impl NetworkClassifier {
 
 #[cfg(test)]
 mod tests {
-    use oci_spec::runtime::LinuxNetworkBuilder;
+    use oci_spec::runtime::{LinuxNetworkBuilder, LinuxResourcesBuilder};
 
     use super::*;
     use crate::test::set_fixture;
@@ -61,4 +81,33 @@ mod tests {
             .expect("Read classID contents");
         assert_eq!(id.to_string(), content);
     }
+
+    #[test]
+    fn test_network_classifier_not_required() {
+        let network = LinuxNetworkBuilder::default()
+            .priorities(vec![])
+            .build()
+            .unwrap();
+        let resources = LinuxResourcesBuilder::default()
+            .network(network)
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+
+        assert!(NetworkClassifier::needs_to_handle(&controller_opt).is_none());
+    }
+
+    #[test]
+    fn test_stat_network_classifier() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), CGROUP_NET_CLS_CLASSID, "1048577\n").unwrap();
+
+        let class_id = NetworkClassifier::stats(tmp.path()).expect("get network classifier stats");
+        assert_eq!(class_id, 0x100001);
+    }
 }
@@ -1,9 +1,16 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
 use std::path::Path;
 
 use oci_spec::runtime::LinuxNetwork;
 
 use super::controller::Controller;
-use crate::common::{self, ControllerOpt, WrappedIoError};
+use crate::checkpoint;
+use crate::common::{ControllerOpt, WrapIoResult, WrappedIoError};
+use crate::stats::{self, ParseFlatKeyedDataError, StatsProvider};
+
+const CGROUP_NET_PRIO_IFPRIOMAP: &str = "net_prio.ifpriomap";
 
 pub struct NetworkPriority {}
 
@@ -22,15 +29,38 @@ impl Controller for NetworkPriority {
     }
 
     fn needs_to_handle<'a>(controller_opt: &'a ControllerOpt) -> Option<&'a Self::Resource> {
-        controller_opt.resources.network().as_ref()
+        controller_opt
+            .resources
+            .network()
+            .as_ref()
+            .filter(|network| matches!(network.priorities(), Some(p) if !p.is_empty()))
+    }
+}
+
+impl StatsProvider for NetworkPriority {
+    type Error = ParseFlatKeyedDataError;
+    type Stats = HashMap<String, u64>;
+
+    fn stats(cgroup_path: &Path) -> Result<Self::Stats, Self::Error> {
+        stats::parse_flat_keyed_data(&cgroup_path.join(CGROUP_NET_PRIO_IFPRIOMAP))
     }
 }
 
 impl NetworkPriority {
     fn apply(root_path: &Path, network: &LinuxNetwork) -> Result<(), WrappedIoError> {
         if let Some(ni_priorities) = network.priorities() {
-            let priorities: String = ni_priorities.iter().map(|p| p.to_string()).collect();
-            common::write_cgroup_file_str(root_path.join("net_prio.ifpriomap"), priorities.trim())?;
+            let path = root_path.join(CGROUP_NET_PRIO_IFPRIOMAP);
+            let mut file = OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .wrap_open(&path)?;
+            // the kernel only parses a single "<interface> <priority>" entry per write
+            for priority in ni_priorities {
+                let data = priority.to_string();
+                file.write_all(data.as_bytes())
+                    .wrap_write(&path, data.clone())?;
+                checkpoint::record_write(&path, &data);
+            }
         }
 
         Ok(())
@@ -70,6 +100,16 @@ mod tests {
 
         let content = std::fs::read_to_string(tmp.path().join("net_prio.ifpriomap"))
             .expect("Read classID contents");
-        assert_eq!(priorities_string.trim(), content);
+        assert_eq!(priorities_string, content);
+    }
+
+    #[test]
+    fn test_stat_network_priorities() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), CGROUP_NET_PRIO_IFPRIOMAP, "lo 0\neth0 5\n").unwrap();
+
+        let priorities = NetworkPriority::stats(tmp.path()).expect("get network priority stats");
+        assert_eq!(priorities.len(), 2);
+        assert_eq!(priorities["eth0"], 5);
     }
 }
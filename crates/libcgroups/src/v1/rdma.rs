@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use oci_spec::runtime::LinuxRdma;
+
+use super::controller::Controller;
+use crate::common::{self, ControllerOpt, WrappedIoError};
+use crate::stats::{self, RdmaStats, RdmaStatsError, StatsProvider};
+
+pub struct Rdma {}
+
+impl Controller for Rdma {
+    type Error = WrappedIoError;
+    type Resource = HashMap<String, LinuxRdma>;
+
+    fn apply(controller_opt: &ControllerOpt, cgroup_root: &Path) -> Result<(), Self::Error> {
+        tracing::debug!("Apply rdma cgroup config");
+
+        if let Some(rdma) = Self::needs_to_handle(controller_opt) {
+            common::write_rdma_limits(cgroup_root, rdma)?;
+        }
+
+        Ok(())
+    }
+
+    fn needs_to_handle<'a>(controller_opt: &'a ControllerOpt) -> Option<&'a Self::Resource> {
+        controller_opt
+            .resources
+            .rdma()
+            .as_ref()
+            .filter(|rdma| !rdma.is_empty())
+    }
+}
+
+impl StatsProvider for Rdma {
+    type Error = RdmaStatsError;
+    type Stats = HashMap<String, RdmaStats>;
+
+    fn stats(cgroup_path: &Path) -> Result<Self::Stats, Self::Error> {
+        stats::rdma_stats(cgroup_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oci_spec::runtime::{LinuxRdmaBuilder, LinuxResourcesBuilder};
+
+    use super::*;
+    use crate::test::set_fixture;
+
+    #[test]
+    fn test_apply_rdma() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), "rdma.max", "").expect("set fixture for rdma.max");
+
+        let rdma = LinuxRdmaBuilder::default()
+            .hca_handles(2u32)
+            .hca_objects(2000u32)
+            .build()
+            .unwrap();
+        let resources = LinuxResourcesBuilder::default()
+            .rdma(HashMap::from([("mlx4_0".to_owned(), rdma)]))
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+
+        Rdma::apply(&controller_opt, tmp.path()).expect("apply rdma limits");
+
+        let content = std::fs::read_to_string(tmp.path().join("rdma.max")).unwrap();
+        assert_eq!(content, "mlx4_0 hca_handle=2 hca_object=2000");
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 
-use procfs::process::Process;
+use procfs::process::{MountInfo, Process};
 use procfs::ProcError;
 
 use super::controller_type::CONTROLLERS;
@@ -45,6 +45,15 @@ pub fn list_supported_mount_points() -> Result<HashMap<ControllerType, PathBuf>,
 }
 
 pub fn get_subsystem_mount_point(subsystem: &ControllerType) -> Result<PathBuf, V1MountPointError> {
+    get_subsystem_mount_info(subsystem).map(|m| m.mount_point)
+}
+
+/// Returns the mountinfo entry of the hierarchy of a subsystem. Its root is
+/// the path of the mounted cgroup within the hierarchy, which is not `/` if
+/// only a subtree is mounted, e.g. inside of a container.
+pub fn get_subsystem_mount_info(
+    subsystem: &ControllerType,
+) -> Result<MountInfo, V1MountPointError> {
     let subsystem_name = subsystem.to_string();
     Process::myself()
         .map_err(V1MountPointError::ReadSelf)?
@@ -77,7 +86,6 @@ pub fn get_subsystem_mount_point(subsystem: &ControllerType) -> Result<PathBuf,
             }
             m.mount_point.ends_with(&subsystem_name)
         })
-        .map(|m| m.mount_point)
         .ok_or(V1MountPointError::NotFound {
             subsystem: *subsystem,
         })
@@ -8,6 +8,8 @@ pub enum ControllerType {
     Memory,
     HugeTlb,
     Pids,
+    Misc,
+    Rdma,
 }
 
 impl Display for ControllerType {
@@ -19,6 +21,8 @@ impl Display for ControllerType {
             Self::Memory => "memory",
             Self::HugeTlb => "hugetlb",
             Self::Pids => "pids",
+            Self::Misc => "misc",
+            Self::Rdma => "rdma",
         };
 
         write!(f, "{print}")
@@ -32,6 +36,8 @@ pub const CONTROLLER_TYPES: &[ControllerType] = &[
     ControllerType::Io,
     ControllerType::Memory,
     ControllerType::Pids,
+    ControllerType::Misc,
+    ControllerType::Rdma,
 ];
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
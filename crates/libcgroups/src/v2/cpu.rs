@@ -5,7 +5,7 @@ use oci_spec::runtime::LinuxCpu;
 
 use super::controller::Controller;
 use crate::common::{self, ControllerOpt, WrappedIoError};
-use crate::stats::{self, CpuStats, ParseFlatKeyedDataError, StatsProvider};
+use crate::stats::{self, CpuBandwidth, CpuStats, ParseFlatKeyedDataError, StatsProvider};
 
 const CGROUP_CPU_WEIGHT: &str = "cpu.weight";
 const CGROUP_CPU_MAX: &str = "cpu.max";
@@ -23,6 +23,8 @@ pub enum V2CpuControllerError {
     WrappedIo(#[from] WrappedIoError),
     #[error("realtime is not supported on v2 yet")]
     RealtimeV2,
+    #[error("{0} is not supported by the kernel")]
+    NotSupported(&'static str),
 }
 
 pub struct Cpu {}
@@ -47,6 +49,8 @@ pub enum V2CpuStatsError {
     ParseNestedKeyedData(#[from] ParseFlatKeyedDataError),
     #[error("missing field {field} from {path}")]
     MissingField { field: &'static str, path: PathBuf },
+    #[error("invalid bandwidth {content:?} in {path}")]
+    InvalidBandwidth { content: String, path: PathBuf },
 }
 
 impl StatsProvider for Cpu {
@@ -71,18 +75,58 @@ impl StatsProvider for Cpu {
             };
         }
 
+        // the throttling is only reported if the cpu controller is enabled for
+        // the cgroup, the bursts since Linux 5.14
+        macro_rules! get_optional {
+            ($name: expr => $field1:ident.$field2:ident) => {
+                if let Some(value) = stats_table.get($name) {
+                    stats.$field1.$field2 = *value;
+                }
+            };
+        }
+
         get!("usage_usec" => usage.usage_total);
         get!("user_usec" => usage.usage_user);
         get!("system_usec" => usage.usage_kernel);
-        get!("nr_periods" => throttling.periods);
-        get!("nr_throttled" => throttling.throttled_periods);
-        get!("throttled_usec" => throttling.throttled_time);
+        get_optional!("nr_periods" => throttling.periods);
+        get_optional!("nr_throttled" => throttling.throttled_periods);
+        get_optional!("throttled_usec" => throttling.throttled_time);
+        get_optional!("nr_bursts" => throttling.bursts);
+        get_optional!("burst_usec" => throttling.burst_time);
+
+        let cpu_max = cgroup_path.join(CGROUP_CPU_MAX);
+        if cpu_max.exists() {
+            let content = common::read_cgroup_file(&cpu_max)?;
+            stats.bandwidth =
+                Some(
+                    parse_cpu_max(&content).ok_or_else(|| V2CpuStatsError::InvalidBandwidth {
+                        content: content.trim().to_owned(),
+                        path: cpu_max,
+                    })?,
+                );
+        }
 
         stats.psi = stats::psi_stats(&cgroup_path.join(CPU_PSI))?;
         Ok(stats)
     }
 }
 
+/// Parses the content of cpu.max, i.e. `$MAX $PERIOD` where $MAX is `max`
+/// if the cgroup is not limited
+fn parse_cpu_max(content: &str) -> Option<CpuBandwidth> {
+    let mut fields = content.split_whitespace();
+    let quota = match fields.next()? {
+        UNRESTRICTED_QUOTA => None,
+        quota => Some(quota.parse().ok()?),
+    };
+    let period = fields.next()?.parse().ok()?;
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some(CpuBandwidth { quota, period })
+}
+
 impl Cpu {
     fn apply(path: &Path, cpu: &LinuxCpu) -> Result<(), V2CpuControllerError> {
         if Self::is_realtime_requested(cpu) {
@@ -117,17 +161,48 @@ impl Cpu {
             common::write_cgroup_file_str(&cpu_max_file, &cpu_max)?;
         }
 
+        Self::apply_burst_and_idle(path, cpu)
+    }
+
+    /// Writes the cfs burst and whether the cgroup is a SCHED_IDLE cgroup.
+    /// Besides the v2 manager, this is used by the systemd manager, as
+    /// systemd has no unit properties for them.
+    pub(crate) fn apply_burst_and_idle(
+        path: &Path,
+        cpu: &LinuxCpu,
+    ) -> Result<(), V2CpuControllerError> {
+        // cpu.max.burst requires Linux 5.14 and cpu.idle Linux 5.15
         if let Some(burst) = cpu.burst() {
-            common::write_cgroup_file(path.join(CGROUP_CPU_BURST), burst)?;
+            Self::write_if_supported(path, CGROUP_CPU_BURST, burst, burst == 0)?;
         }
 
         if let Some(idle) = cpu.idle() {
-            common::write_cgroup_file(path.join(CGROUP_CPU_IDLE), idle)?;
+            Self::write_if_supported(path, CGROUP_CPU_IDLE, idle, idle == 0)?;
         }
 
         Ok(())
     }
 
+    /// Writes a file which older kernels don't provide. Asking for the default
+    /// value is not an error on those kernels.
+    fn write_if_supported<T: ToString>(
+        path: &Path,
+        file: &'static str,
+        value: T,
+        is_default: bool,
+    ) -> Result<(), V2CpuControllerError> {
+        let file_path = path.join(file);
+        if !file_path.exists() {
+            if is_default {
+                return Ok(());
+            }
+            return Err(V2CpuControllerError::NotSupported(file));
+        }
+
+        common::write_cgroup_file(file_path, value)?;
+        Ok(())
+    }
+
     fn convert_shares_to_cgroup2(shares: u64) -> u64 {
         if shares == 0 {
             return 0;
@@ -350,12 +425,83 @@ mod tests {
                 periods: 400,
                 throttled_periods: 20,
                 throttled_time: 5000,
+                ..Default::default()
             },
             ..Default::default()
         };
 
         assert_eq!(actual.usage, expected.usage);
         assert_eq!(actual.throttling, expected.throttling);
+        assert_eq!(actual.bandwidth, None);
+    }
+
+    #[test]
+    fn test_stat_throttling() {
+        let tmp = tempfile::tempdir().unwrap();
+        // without the cpu controller only the usage is reported
+        let content = ["usage_usec 7730", "user_usec 4387", "system_usec 3498"].join("\n");
+        set_fixture(tmp.path(), CPU_STAT, &content).expect("create stat file");
+        set_fixture(tmp.path(), CPU_PSI, "").expect("create psi file");
+        let actual = Cpu::stats(tmp.path()).expect("get cgroup stats");
+        assert_eq!(actual.usage.usage_total, 7730);
+        assert_eq!(actual.throttling, CpuThrottling::default());
+
+        let content = [
+            &content,
+            "nr_periods 400",
+            "nr_throttled 20",
+            "throttled_usec 5000",
+            "nr_bursts 3",
+            "burst_usec 1200",
+        ]
+        .join("\n");
+        set_fixture(tmp.path(), CPU_STAT, &content).expect("create stat file");
+        set_fixture(tmp.path(), CGROUP_CPU_MAX, "50000 100000\n").expect("create max file");
+        let actual = Cpu::stats(tmp.path()).expect("get cgroup stats");
+        assert_eq!(
+            actual.throttling,
+            CpuThrottling {
+                periods: 400,
+                throttled_periods: 20,
+                throttled_time: 5000,
+                bursts: 3,
+                burst_time: 1200,
+            }
+        );
+        assert_eq!(
+            actual.bandwidth,
+            Some(CpuBandwidth {
+                quota: Some(50000),
+                period: 100000
+            })
+        );
+
+        set_fixture(tmp.path(), CGROUP_CPU_MAX, "50000").expect("create max file");
+        assert!(matches!(
+            Cpu::stats(tmp.path()),
+            Err(V2CpuStatsError::InvalidBandwidth { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_cpu_max() {
+        assert_eq!(
+            parse_cpu_max("max 100000\n"),
+            Some(CpuBandwidth {
+                quota: None,
+                period: 100000
+            })
+        );
+        assert_eq!(
+            parse_cpu_max("20000 50000"),
+            Some(CpuBandwidth {
+                quota: Some(20000),
+                period: 50000
+            })
+        );
+        for invalid in ["", "max", "-1 100000", "max 100000 1", "max max"] {
+            assert_eq!(parse_cpu_max(invalid), None, "{invalid:?}");
+        }
     }
 
     #[test]
@@ -369,4 +515,22 @@ mod tests {
         let actual = fs::read_to_string(burst_file).expect("read burst file");
         assert_eq!(actual, expected.to_string());
     }
+
+    #[test]
+    fn test_burst_and_idle_not_supported() {
+        let tmp = tempfile::tempdir().unwrap();
+        let defaults = LinuxCpuBuilder::default()
+            .burst(0u64)
+            .idle(0)
+            .build()
+            .unwrap();
+        Cpu::apply(tmp.path(), &defaults).expect("defaults are fine on older kernels");
+
+        let idle = LinuxCpuBuilder::default().idle(1).build().unwrap();
+        let result = Cpu::apply(tmp.path(), &idle);
+        assert!(matches!(
+            result,
+            Err(V2CpuControllerError::NotSupported(CGROUP_CPU_IDLE))
+        ));
+    }
 }
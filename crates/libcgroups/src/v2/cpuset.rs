@@ -4,32 +4,106 @@ use oci_spec::runtime::LinuxCpu;
 
 use super::controller::Controller;
 use crate::common::{self, ControllerOpt, WrappedIoError};
+use crate::cpumask::{CpuMask, CpuMaskError};
 
 const CGROUP_CPUSET_CPUS: &str = "cpuset.cpus";
 const CGROUP_CPUSET_MEMS: &str = "cpuset.mems";
+const CGROUP_CPUSET_PARTITION: &str = "cpuset.cpus.partition";
+
+#[derive(thiserror::Error, Debug)]
+pub enum V2CpuSetControllerError {
+    #[error("io error: {0}")]
+    WrappedIo(#[from] WrappedIoError),
+    #[error("invalid cpuset: {0}")]
+    CpuMask(#[from] CpuMaskError),
+    #[error("memory nodes {nodes} are not online, the online nodes are {online}")]
+    OfflineNodes { nodes: String, online: String },
+    #[error("invalid value {0} for cpuset.cpus.partition, must be member, root or isolated")]
+    InvalidPartition(String),
+    #[error("the cpuset could not be made a partition: {0}")]
+    Partition(String),
+}
 
 pub struct CpuSet {}
 
 impl Controller for CpuSet {
-    type Error = WrappedIoError;
+    type Error = V2CpuSetControllerError;
 
     fn apply(controller_opt: &ControllerOpt, cgroup_path: &Path) -> Result<(), Self::Error> {
         if let Some(cpuset) = &controller_opt.resources.cpu() {
             Self::apply(cgroup_path, cpuset)?;
         }
 
+        // the partition can only be requested through the unified map. It is
+        // written again with the other unified entries afterwards, which
+        // doesn't change a partition that has already been set up.
+        let partition = controller_opt
+            .resources
+            .unified()
+            .as_ref()
+            .and_then(|unified| unified.get(CGROUP_CPUSET_PARTITION));
+        if let Some(partition) = partition {
+            Self::apply_partition(cgroup_path, partition)?;
+        }
+
         Ok(())
     }
 }
 
 impl CpuSet {
-    fn apply(path: &Path, cpuset: &LinuxCpu) -> Result<(), WrappedIoError> {
+    fn apply(path: &Path, cpuset: &LinuxCpu) -> Result<(), V2CpuSetControllerError> {
         if let Some(cpus) = &cpuset.cpus() {
-            common::write_cgroup_file_str(path.join(CGROUP_CPUSET_CPUS), cpus)?;
+            let cpus: CpuMask = cpus.parse()?;
+            // offline cpus are allowed, they are only not part of the effective cpus
+            if let Err(err) = cpus.validate_online() {
+                tracing::warn!("{}", err);
+            }
+            common::write_cgroup_file(path.join(CGROUP_CPUSET_CPUS), cpus)?;
         }
 
         if let Some(mems) = &cpuset.mems() {
-            common::write_cgroup_file_str(path.join(CGROUP_CPUSET_MEMS), mems)?;
+            let mems: CpuMask = mems.parse()?;
+            if let Some(online) = CpuMask::online_nodes()? {
+                Self::validate_mems(&mems, &online)?;
+            }
+            common::write_cgroup_file(path.join(CGROUP_CPUSET_MEMS), mems)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the memory nodes are online, as the kernel only rejects
+    /// them with a generic error
+    fn validate_mems(mems: &CpuMask, online: &CpuMask) -> Result<(), V2CpuSetControllerError> {
+        let offline = mems.difference(online);
+        if !offline.is_empty() {
+            return Err(V2CpuSetControllerError::OfflineNodes {
+                nodes: offline.to_string(),
+                online: online.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Turns the cpuset into a partition, so that its cpus are used
+    /// exclusively by the cgroup. If the cpus are not exclusive or the parent
+    /// is no partition, the kernel accepts the value but marks the partition
+    /// invalid, which is why it's read back.
+    fn apply_partition(path: &Path, partition: &str) -> Result<(), V2CpuSetControllerError> {
+        let partition = partition.trim();
+        if !matches!(partition, "member" | "root" | "isolated") {
+            return Err(V2CpuSetControllerError::InvalidPartition(
+                partition.to_owned(),
+            ));
+        }
+
+        let partition_path = path.join(CGROUP_CPUSET_PARTITION);
+        common::write_cgroup_file_str(&partition_path, partition)?;
+        // e.g. "root invalid (Cpu list in cpuset.cpus not exclusive)"
+        let state = common::read_cgroup_file(&partition_path)?;
+        if state.contains("invalid") {
+            return Err(V2CpuSetControllerError::Partition(state.trim().to_owned()));
         }
 
         Ok(())
@@ -38,9 +112,10 @@ impl CpuSet {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
     use std::fs;
 
-    use oci_spec::runtime::LinuxCpuBuilder;
+    use oci_spec::runtime::{LinuxCpuBuilder, LinuxResourcesBuilder};
 
     use super::*;
     use crate::test::setup;
@@ -68,7 +143,7 @@ mod tests {
         // arrange
         let (tmp, mems) = setup(CGROUP_CPUSET_MEMS);
         let cpuset = LinuxCpuBuilder::default()
-            .mems("1-3".to_owned())
+            .mems("0".to_owned())
             .build()
             .unwrap();
 
@@ -78,6 +153,49 @@ mod tests {
         // assert
         let content = fs::read_to_string(mems)
             .unwrap_or_else(|_| panic!("read {CGROUP_CPUSET_MEMS} file content"));
-        assert_eq!(content, "1-3");
+        assert_eq!(content, "0");
+    }
+
+    #[test]
+    fn test_validate_mems() -> anyhow::Result<()> {
+        let online: CpuMask = "0-1".parse()?;
+        CpuSet::validate_mems(&"0-1".parse()?, &online)?;
+
+        let err = CpuSet::validate_mems(&"1-3".parse()?, &online).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "memory nodes 2-3 are not online, the online nodes are 0-1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_partition() {
+        // arrange
+        let (tmp, partition) = setup(CGROUP_CPUSET_PARTITION);
+        let unified: HashMap<String, String> =
+            [(CGROUP_CPUSET_PARTITION.to_owned(), "root".to_owned())].into();
+        let resources = LinuxResourcesBuilder::default()
+            .unified(unified)
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        };
+
+        // act
+        <CpuSet as Controller>::apply(&controller_opt, tmp.path()).expect("apply partition");
+
+        // assert
+        let content = fs::read_to_string(partition)
+            .unwrap_or_else(|_| panic!("read {CGROUP_CPUSET_PARTITION} file content"));
+        assert_eq!(content, "root");
+        assert!(matches!(
+            CpuSet::apply_partition(tmp.path(), "exclusive"),
+            Err(V2CpuSetControllerError::InvalidPartition(_))
+        ));
     }
 }
@@ -4,16 +4,20 @@ use oci_spec::runtime::{LinuxDeviceCgroup, LinuxDeviceType};
 // a list of user-defined rules.
 // https://github.com/opencontainers/runc/commit/2353ffec2bb670a200009dc7a54a56b93145f141
 //
-// I chose to implement a very simple algorithm, which will just work in most cases, but with
-// diversion from cgroupv1 in some cases:
-//  1. just add used-defined rules one by one
-//  2. discard existing rules when encountering a rule with type='a', and change to deny/allow all
-//     list according the 'allow' of the rule
-//  3. bpf program will check rule one by one in *reversed* order, return action of first rule
-//     which matches device access operation
-//
+// The rules are applied in order and the last rule matching an access decides about it, which is
+// how the bpf program checks them, see `Program`. The list is minimized while adding rules:
+//  1. a rule with type='a' discards all existing rules and changes to deny/allow all according to
+//     the 'allow' of the rule
+//  2. the access of a rule is removed from the earlier rules for all devices it covers, as the
+//     earlier rules can't match that access anymore, and rules without any access left are dropped
+//  3. a rule with the default action is only kept if it overrides an earlier rule
+//  4. rules of the same device and action are merged, unless a rule in between overrides them
+//  5. a major or minor of None or -1 is a wildcard matching all devices
+
+const ACCESS_READ: u8 = 1;
+const ACCESS_WRITE: u8 = 2;
+const ACCESS_MKNOD: u8 = 4;
 
-// FIXME: should we use runc's implementation?
 pub struct Emulator {
     pub default_allow: bool,
     pub rules: Vec<LinuxDeviceCgroup>,
@@ -43,14 +47,117 @@ impl Emulator {
         }
 
         // empty access match nothing, just discard this rule
-        if rule.access().is_none() {
+        let access = match rule.access().as_deref().map(access_bits) {
+            None | Some(Some(0)) => return,
+            Some(Some(access)) => access,
+            // leave it to the program to report the invalid access
+            Some(None) => {
+                self.rules.push(rule.clone());
+                return;
+            }
+        };
+
+        for existing in &mut self.rules {
+            if covers(rule, existing) {
+                if let Some(existing_access) = rule_access(existing) {
+                    existing.set_access(Some(access_string(existing_access & !access)));
+                }
+            }
+        }
+        self.rules.retain(|r| rule_access(r) != Some(0));
+
+        let overrides = self
+            .rules
+            .iter()
+            .any(|r| r.allow() != rule.allow() && overlaps(r, rule) && shares_access(r, access));
+        if rule.allow() == self.default_allow && !overrides {
             return;
         }
 
-        self.rules.push(rule.clone());
+        let mut access = access;
+        if let Some(index) = self
+            .rules
+            .iter()
+            .rposition(|r| r.allow() == rule.allow() && same_devices(r, rule))
+        {
+            let earlier_access = rule_access(&self.rules[index]).unwrap_or_default();
+            let interferes = self.rules[index + 1..]
+                .iter()
+                .any(|r| overlaps(r, rule) && shares_access(r, earlier_access));
+            if !interferes {
+                access |= earlier_access;
+                self.rules.remove(index);
+            }
+        }
+
+        let mut rule = rule.clone();
+        rule.set_access(Some(access_string(access)));
+        self.rules.push(rule);
     }
 }
 
+/// Returns the access bits, or None if the access contains invalid characters
+fn access_bits(access: &str) -> Option<u8> {
+    access.chars().try_fold(0, |bits, c| match c {
+        'r' => Some(bits | ACCESS_READ),
+        'w' => Some(bits | ACCESS_WRITE),
+        'm' => Some(bits | ACCESS_MKNOD),
+        _ => None,
+    })
+}
+
+fn rule_access(rule: &LinuxDeviceCgroup) -> Option<u8> {
+    access_bits(rule.access().as_deref().unwrap_or_default())
+}
+
+/// Checks if the rule has some of the access, rules with an invalid access are
+/// assumed to have all of it
+fn shares_access(rule: &LinuxDeviceCgroup, access: u8) -> bool {
+    rule_access(rule).unwrap_or(u8::MAX) & access != 0
+}
+
+fn access_string(access: u8) -> String {
+    [(ACCESS_READ, 'r'), (ACCESS_WRITE, 'w'), (ACCESS_MKNOD, 'm')]
+        .iter()
+        .filter(|(bit, _)| access & bit != 0)
+        .map(|(_, c)| c)
+        .collect()
+}
+
+/// Returns the number of a device, or None if it is a wildcard
+fn device_number(number: Option<i64>) -> Option<i64> {
+    number.filter(|n| *n >= 0)
+}
+
+fn same_devices(a: &LinuxDeviceCgroup, b: &LinuxDeviceCgroup) -> bool {
+    a.typ().unwrap_or_default() == b.typ().unwrap_or_default()
+        && device_number(a.major()) == device_number(b.major())
+        && device_number(a.minor()) == device_number(b.minor())
+}
+
+/// Checks if the rule matches all devices matched by the other rule
+fn covers(rule: &LinuxDeviceCgroup, other: &LinuxDeviceCgroup) -> bool {
+    let covers_number = |number: Option<i64>, other: Option<i64>| match device_number(number) {
+        None => true,
+        number => number == device_number(other),
+    };
+    rule.typ().unwrap_or_default() == other.typ().unwrap_or_default()
+        && covers_number(rule.major(), other.major())
+        && covers_number(rule.minor(), other.minor())
+}
+
+/// Checks if there is a device matched by both rules
+fn overlaps(a: &LinuxDeviceCgroup, b: &LinuxDeviceCgroup) -> bool {
+    let overlaps_number =
+        |a: Option<i64>, b: Option<i64>| match (device_number(a), device_number(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        };
+    a.typ().unwrap_or_default() == b.typ().unwrap_or_default()
+        && overlaps_number(a.major(), b.major())
+        && overlaps_number(a.minor(), b.minor())
+}
+
 #[cfg(test)]
 mod tests {
     use oci_spec::runtime::LinuxDeviceCgroupBuilder;
@@ -117,4 +224,109 @@ mod tests {
         assert_eq!(top_rule.access(), &Some(permission.to_string()));
         assert!(!emulator.default_allow);
     }
+
+    fn rule(
+        allow: bool,
+        major: Option<i64>,
+        minor: Option<i64>,
+        access: &str,
+    ) -> LinuxDeviceCgroup {
+        let mut builder = LinuxDeviceCgroupBuilder::default()
+            .allow(allow)
+            .typ(LinuxDeviceType::C)
+            .access(access);
+        if let Some(major) = major {
+            builder = builder.major(major);
+        }
+        if let Some(minor) = minor {
+            builder = builder.minor(minor);
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn test_shadowed_rules() {
+        let mut emulator = Emulator::with_default_allow(false);
+        emulator.add_rules(&[
+            rule(true, Some(1), Some(3), "rwm"),
+            rule(true, Some(1), Some(5), "r"),
+            // denies most of the earlier rules again
+            rule(false, Some(1), None, "rw"),
+        ]);
+
+        assert_eq!(emulator.rules, vec![rule(true, Some(1), Some(3), "m")]);
+    }
+
+    #[test]
+    fn test_redundant_default_rules() {
+        let mut emulator = Emulator::with_default_allow(false);
+        emulator.add_rules(&[
+            // denies what is already denied
+            rule(false, Some(1), Some(3), "rwm"),
+            rule(true, Some(1), None, "rw"),
+            // overrides a part of the earlier rule, so it has to be kept
+            rule(false, Some(1), Some(5), "w"),
+        ]);
+
+        assert_eq!(
+            emulator.rules,
+            vec![
+                rule(true, Some(1), None, "rw"),
+                rule(false, Some(1), Some(5), "w")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_rules() {
+        let mut emulator = Emulator::with_default_allow(false);
+        emulator.add_rules(&[
+            rule(true, Some(1), Some(3), "r"),
+            rule(true, Some(1), Some(5), "rw"),
+            rule(true, Some(1), Some(3), "w"),
+            // a wildcard major or minor is the same as -1
+            rule(true, Some(-1), Some(7), "r"),
+            rule(true, None, Some(7), "m"),
+        ]);
+
+        assert_eq!(
+            emulator.rules,
+            vec![
+                rule(true, Some(1), Some(5), "rw"),
+                rule(true, Some(1), Some(3), "rw"),
+                rule(true, None, Some(7), "rm"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_merge_across_overriding_rule() {
+        let mut emulator = Emulator::with_default_allow(false);
+        emulator.add_rules(&[
+            rule(true, Some(1), Some(3), "r"),
+            rule(true, Some(1), None, "rw"),
+            rule(false, Some(1), None, "r"),
+            rule(true, Some(1), None, "w"),
+        ]);
+
+        // the deny rule removes the read access of both earlier rules
+        assert_eq!(emulator.rules, vec![rule(true, Some(1), None, "w")]);
+
+        let mut emulator = Emulator::with_default_allow(false);
+        emulator.add_rules(&[
+            rule(true, Some(1), None, "rw"),
+            rule(false, Some(1), Some(3), "r"),
+            rule(true, Some(1), None, "m"),
+        ]);
+
+        // moving the first rule after the deny rule would allow reading 1:3
+        assert_eq!(
+            emulator.rules,
+            vec![
+                rule(true, Some(1), None, "rw"),
+                rule(false, Some(1), Some(3), "r"),
+                rule(true, Some(1), None, "m"),
+            ]
+        );
+    }
 }
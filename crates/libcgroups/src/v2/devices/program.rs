@@ -136,7 +136,6 @@ impl Program {
 
         if has_access {
             next_rule_offset -= 3;
-            // if (R3 & access != R3 /* use R1 as a temp var */) goto next rule
             self.prog
                 .mov(Source::Reg, RbpfArch::X32)
                 .set_dst(1)
@@ -149,12 +148,25 @@ impl Program {
                 .set_imm(access as i32)
                 .push();
 
-            self.prog
-                .jump_conditional(Cond::NotEquals, Source::Reg)
-                .set_dst(1)
-                .set_src(3)
-                .set_off(next_rule_offset)
-                .push();
+            if rule.allow() {
+                // an allow rule has to allow all of the requested access
+                // if (R3 & access != R3 /* use R1 as a temp var */) goto next rule
+                self.prog
+                    .jump_conditional(Cond::NotEquals, Source::Reg)
+                    .set_dst(1)
+                    .set_src(3)
+                    .set_off(next_rule_offset)
+                    .push();
+            } else {
+                // a deny rule denies the request if any of the requested access is denied
+                // if (R3 & access == 0 /* use R1 as a temp var */) goto next rule
+                self.prog
+                    .jump_conditional(Cond::Equals, Source::Imm)
+                    .set_dst(1)
+                    .set_imm(0)
+                    .set_off(next_rule_offset)
+                    .push();
+            }
         }
 
         if has_major {
@@ -466,4 +478,30 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_devices_deny_partial_access() -> Result<()> {
+        let rules = vec![
+            LinuxDeviceCgroupBuilder::default()
+                .allow(true)
+                .typ(LinuxDeviceType::C)
+                .major(1)
+                .access("rwm")
+                .build()?,
+            LinuxDeviceCgroupBuilder::default()
+                .allow(false)
+                .typ(LinuxDeviceType::C)
+                .major(1)
+                .minor(3)
+                .access("w")
+                .build()?,
+        ];
+
+        let prog = build_bpf_program(&Some(rules))?;
+        assert_eq!(prog.execute(LinuxDeviceType::C, 1, 3, "r".to_string())?, 1);
+        // opening for reading and writing is denied by the write access
+        assert_eq!(prog.execute(LinuxDeviceType::C, 1, 3, "rw".to_string())?, 0);
+        assert_eq!(prog.execute(LinuxDeviceType::C, 1, 5, "rw".to_string())?, 1);
+        Ok(())
+    }
 }
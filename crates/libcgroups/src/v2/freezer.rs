@@ -6,7 +6,7 @@ use std::thread;
 use std::time::Duration;
 
 use super::controller::Controller;
-use crate::common::{ControllerOpt, FreezerState, WrapIoResult, WrappedIoError};
+use crate::common::{self, ControllerOpt, FreezerState, WrapIoResult, WrappedIoError};
 
 const CGROUP_FREEZE: &str = "cgroup.freeze";
 const CGROUP_EVENTS: &str = "cgroup.events";
@@ -24,8 +24,11 @@ pub enum V2FreezerError {
     },
     #[error("unexpected \"cgroup.freeze\" state: {state}")]
     UnknownState { state: String },
-    #[error("timeout of {0} ms reached waiting for the cgroup to freeze")]
-    Timeout(u128),
+    #[error(
+        "timeout of {millis} ms reached waiting for the cgroup to change its freezer state{}",
+        common::describe_tasks(.tasks)
+    )]
+    Timeout { millis: u128, tasks: Vec<String> },
     #[error("invalid utf8: {0}")]
     InvalidUtf8(#[from] Utf8Error),
 }
@@ -69,15 +72,34 @@ impl Freezer {
         };
 
         // confirm that the cgroup did actually change states.
-        let actual_state = Self::read_freezer_state(path)?;
-        if !actual_state.eq(&freezer_state) {
-            return Err(V2FreezerError::ExpectedToBe {
-                expected: freezer_state,
-                actual: actual_state,
-            });
+        let result = Self::read_freezer_state(path).and_then(|actual_state| {
+            if !actual_state.eq(&freezer_state) {
+                return Err(V2FreezerError::ExpectedToBe {
+                    expected: freezer_state,
+                    actual: actual_state,
+                });
+            }
+            Ok(())
+        });
+
+        if let (Err(err), FreezerState::Frozen) = (&result, freezer_state) {
+            // like on cgroup v1, the cgroup is not left partially frozen
+            if let Err(thaw_err) = Self::write_freeze(path, "0") {
+                tracing::warn!("failed to thaw {:?} after {}: {}", path, err, thaw_err);
+            }
+            if let Err(V2FreezerError::Timeout { millis, .. }) = result {
+                return Err(V2FreezerError::Timeout {
+                    millis,
+                    tasks: common::uninterruptible_tasks(path),
+                });
+            }
         }
 
-        Ok(())
+        result
+    }
+
+    fn write_freeze(path: &Path, state: &str) -> Result<(), WrappedIoError> {
+        common::write_cgroup_file_str(path.join(CGROUP_FREEZE), state)
     }
 
     fn read_freezer_state(path: &Path) -> Result<FreezerState, V2FreezerError> {
@@ -93,16 +115,22 @@ impl Freezer {
 
         let state = str::from_utf8(&buf)?;
         match state {
-            "0" => Ok(FreezerState::Thawed),
-            "1" => Self::wait_frozen(path),
+            "0" => Self::wait_frozen(path, false),
+            "1" => Self::wait_frozen(path, true),
             _ => Err(V2FreezerError::UnknownState {
                 state: state.into(),
             }),
         }
     }
 
-    // wait_frozen polls cgroup.events until it sees "frozen 1" in it.
-    fn wait_frozen(path: &Path) -> Result<FreezerState, V2FreezerError> {
+    // wait_frozen polls cgroup.events until it sees "frozen 1" or "frozen 0"
+    // in it, as the processes are frozen and thawed asynchronously.
+    fn wait_frozen(path: &Path, frozen: bool) -> Result<FreezerState, V2FreezerError> {
+        let (expected, state) = if frozen {
+            ("frozen 1", FreezerState::Frozen)
+        } else {
+            ("frozen 0", FreezerState::Thawed)
+        };
         let path = path.join(CGROUP_EVENTS);
         let f = OpenOptions::new()
             .create(false)
@@ -118,7 +146,10 @@ impl Freezer {
 
         loop {
             if iter == max_iter {
-                return Err(V2FreezerError::Timeout(wait_time.as_millis() * max_iter));
+                return Err(V2FreezerError::Timeout {
+                    millis: wait_time.as_millis() * max_iter,
+                    tasks: Vec::new(),
+                });
             }
             line.clear();
             let num_bytes = f.read_line(&mut line).wrap_read(&path)?;
@@ -126,11 +157,11 @@ impl Freezer {
                 break;
             }
             if line.starts_with("frozen ") {
-                if line.starts_with("frozen 1") {
+                if line.starts_with(expected) {
                     if iter > 1 {
-                        tracing::debug!("frozen after {} retries", iter)
+                        tracing::debug!("{} after {} retries", expected, iter)
                     }
-                    return Ok(FreezerState::Frozen);
+                    return Ok(state);
                 }
                 iter += 1;
                 thread::sleep(wait_time);
@@ -176,6 +207,8 @@ mod tests {
 
         // set Thawed state.
         {
+            set_fixture(tmp.path(), CGROUP_EVENTS, "populated 0\nfrozen 0")
+                .expect("Set fixure for freezer state");
             let freezer_state = FreezerState::Thawed;
             Freezer::apply(freezer_state, tmp.path()).expect("Set freezer state");
 
@@ -33,12 +33,7 @@ impl Controller for HugeTlb {
         cgroup_root: &std::path::Path,
     ) -> Result<(), Self::Error> {
         tracing::debug!("Apply hugetlb cgroup v2 config");
-        if let Some(hugepage_limits) = controller_opt.resources.hugepage_limits() {
-            for hugetlb in hugepage_limits {
-                Self::apply(cgroup_root, hugetlb)?
-            }
-        }
-        Ok(())
+        Self::apply_limits(controller_opt, cgroup_root)
     }
 }
 
@@ -72,6 +67,21 @@ impl StatsProvider for HugeTlb {
 }
 
 impl HugeTlb {
+    /// Writes the hugepage limits to the cgroup. Besides the v2 manager, this
+    /// is used by the systemd manager, as systemd has no unit properties for
+    /// the hugetlb controller.
+    pub(crate) fn apply_limits(
+        controller_opt: &ControllerOpt,
+        cgroup_root: &Path,
+    ) -> Result<(), V2HugeTlbControllerError> {
+        if let Some(hugepage_limits) = controller_opt.resources.hugepage_limits() {
+            for hugetlb in hugepage_limits {
+                Self::apply(cgroup_root, hugetlb)?
+            }
+        }
+        Ok(())
+    }
+
     fn apply(
         root_path: &Path,
         hugetlb: &LinuxHugepageLimit,
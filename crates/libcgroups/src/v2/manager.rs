@@ -1,33 +1,49 @@
-use std::fs::{self};
-use std::os::unix::fs::PermissionsExt;
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::path::Component::RootDir;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use nix::unistd::Pid;
+use nix::fcntl::OFlag;
+use nix::unistd::{self, Gid, Pid, Uid};
 
 use super::controller::Controller;
 use super::controller_type::{
     ControllerType, PseudoControllerType, CONTROLLER_TYPES, PSEUDO_CONTROLLER_TYPES,
 };
 use super::cpu::{Cpu, V2CpuControllerError, V2CpuStatsError};
-use super::cpuset::CpuSet;
+use super::cpuset::{CpuSet, V2CpuSetControllerError};
 #[cfg(feature = "cgroupsv2_devices")]
 use super::devices::Devices;
 use super::freezer::{Freezer, V2FreezerError};
 use super::hugetlb::{HugeTlb, V2HugeTlbControllerError, V2HugeTlbStatsError};
 use super::io::{Io, V2IoControllerError, V2IoStatsError};
 use super::memory::{Memory, V2MemoryControllerError, V2MemoryStatsError};
+use super::misc::Misc;
 use super::pids::Pids;
+use super::rdma::Rdma;
 use super::unified::{Unified, V2UnifiedError};
 use super::util::{self, V2UtilError, CGROUP_SUBTREE_CONTROL};
+use crate::batch::{BatchError, WriteBatch};
 use crate::common::{
-    self, AnyCgroupManager, CgroupManager, ControllerOpt, FreezerState, JoinSafelyError,
-    PathBufExt, WrapIoResult, WrappedIoError, CGROUP_PROCS,
+    self, AnyCgroupManager, CgroupErrorKind, CgroupManager, ControllerOpt, FreezerState,
+    JoinSafelyError, PathBufExt, WrapIoResult, WrappedIoError, CGROUP_PROCS,
 };
-use crate::stats::{PidStatsError, Stats, StatsProvider};
+use crate::events::{CgroupEventsError, CgroupEventsWatcher};
+use crate::oom::{OomNotifier, OomNotifierError};
+use crate::stats::{self, MiscStatsError, PidStatsError, RdmaStatsError, Stats, StatsProvider};
 
 pub const CGROUP_KILL: &str = "cgroup.kill";
+pub const CGROUP_MEMORY_RECLAIM: &str = "memory.reclaim";
+
+/// Leaf cgroup that takes the processes of the root of a nested hierarchy
+const NESTED_LEAF_CGROUP: &str = "init";
+
+/// Lists the files of a cgroup which are owned by the delegatee, see
+/// cgroups(7)
+const KERNEL_DELEGATE_FILES: &str = "/sys/kernel/cgroup/delegate";
+/// Files to delegate for kernels which don't list them
+const DEFAULT_DELEGATE_FILES: &[&str] = &[CGROUP_PROCS, "cgroup.threads", CGROUP_SUBTREE_CONTROL];
 
 #[derive(thiserror::Error, Debug)]
 pub enum V2ManagerError {
@@ -37,11 +53,21 @@ pub enum V2ManagerError {
     JoinSafely(#[from] JoinSafelyError),
     #[error(transparent)]
     Util(#[from] V2UtilError),
+    #[error("the {0} controller is required by the spec, but is not available to the cgroup")]
+    ControllerNotAvailable(ControllerType),
+    #[error("invalid name {0:?} for the leaf cgroup")]
+    InvalidLeaf(String),
+    #[error("the {0} controller can't be delegated, as it is not available to the cgroup")]
+    DelegateController(String),
+    #[error("controllers can only be delegated if the processes are in a leaf cgroup")]
+    DelegateWithoutLeaf,
+    #[error(transparent)]
+    Batch(#[from] BatchError),
 
     #[error(transparent)]
     CpuController(#[from] V2CpuControllerError),
     #[error(transparent)]
-    CpuSetController(WrappedIoError),
+    CpuSetController(#[from] V2CpuSetControllerError),
     #[error(transparent)]
     HugeTlbController(#[from] V2HugeTlbControllerError),
     #[error(transparent)]
@@ -58,6 +84,11 @@ pub enum V2ManagerError {
     #[error(transparent)]
     DevicesController(#[from] super::devices::controller::DevicesControllerError),
 
+    #[error(transparent)]
+    OomNotifier(#[from] OomNotifierError),
+    #[error(transparent)]
+    CgroupEvents(#[from] CgroupEventsError),
+
     #[error(transparent)]
     CpuStats(#[from] V2CpuStatsError),
     #[error(transparent)]
@@ -68,6 +99,48 @@ pub enum V2ManagerError {
     MemoryStats(#[from] V2MemoryStatsError),
     #[error(transparent)]
     IoStats(#[from] V2IoStatsError),
+    #[error(transparent)]
+    MiscStats(#[from] MiscStatsError),
+    #[error(transparent)]
+    RdmaStats(#[from] RdmaStatsError),
+}
+
+impl V2ManagerError {
+    pub fn kind(&self) -> CgroupErrorKind {
+        match self {
+            Self::WrappedIo(_) | Self::Util(_) | Self::OomNotifier(_) | Self::CgroupEvents(_) => {
+                CgroupErrorKind::Io
+            }
+            Self::JoinSafely(_) | Self::InvalidLeaf(_) => CgroupErrorKind::InvalidPath,
+            Self::DelegateWithoutLeaf => CgroupErrorKind::NotSupported,
+            Self::ControllerNotAvailable(_)
+            | Self::DelegateController(_)
+            | Self::UnifiedController(V2UnifiedError::SubsystemNotAvailable { .. }) => {
+                CgroupErrorKind::ControllerNotFound
+            }
+            Self::CpuController(
+                V2CpuControllerError::RealtimeV2 | V2CpuControllerError::NotSupported(_),
+            ) => CgroupErrorKind::NotSupported,
+            Self::CpuController(_)
+            | Self::CpuSetController(_)
+            | Self::HugeTlbController(_)
+            | Self::IoController(_)
+            | Self::MemoryController(_)
+            | Self::PidsController(_)
+            | Self::UnifiedController(_)
+            | Self::FreezerController(_)
+            | Self::Batch(_) => CgroupErrorKind::Controller,
+            #[cfg(feature = "cgroupsv2_devices")]
+            Self::DevicesController(_) => CgroupErrorKind::Controller,
+            Self::CpuStats(_)
+            | Self::HugeTlbStats(_)
+            | Self::PidsStats(_)
+            | Self::MemoryStats(_)
+            | Self::IoStats(_)
+            | Self::MiscStats(_)
+            | Self::RdmaStats(_) => CgroupErrorKind::Stats,
+        }
+    }
 }
 
 /// Represents a management interface for a cgroup located at `{root_path}/{cgroup_path}`
@@ -77,6 +150,9 @@ pub struct Manager {
     root_path: PathBuf,
     cgroup_path: PathBuf,
     full_path: PathBuf,
+    /// Leaf cgroup beneath the cgroup which contains the processes
+    leaf: Option<PathBuf>,
+    best_effort: Vec<String>,
 }
 
 impl Manager {
@@ -89,23 +165,56 @@ impl Manager {
             root_path,
             cgroup_path,
             full_path,
+            leaf: None,
+            best_effort: Vec::new(),
         })
     }
 
-    /// Creates a unified cgroup at `self.full_path` and attaches a process to it
-    fn create_unified_cgroup(&self, pid: Pid) -> Result<(), V2ManagerError> {
+    /// Applies the resources of the named controllers on a best effort
+    /// basis, see [`crate::batch`]
+    pub fn with_best_effort(mut self, controllers: Vec<String>) -> Self {
+        self.best_effort = controllers;
+        self
+    }
+
+    /// Places the processes into a leaf cgroup with the given name, while the
+    /// resources are still applied to the cgroup itself. A cgroup can't both
+    /// contain processes and enable controllers for its children, so that
+    /// this allows the container to create sub-cgroups with controllers.
+    pub fn with_leaf(mut self, name: &str) -> Result<Self, V2ManagerError> {
+        if matches!(name, "" | "." | "..") || name.contains('/') {
+            return Err(V2ManagerError::InvalidLeaf(name.to_owned()));
+        }
+
+        self.leaf = Some(PathBuf::from(name));
+        Ok(self)
+    }
+
+    /// Path of the cgroup which contains the processes
+    fn procs_path(&self) -> PathBuf {
+        match &self.leaf {
+            Some(leaf) => self.full_path.join(leaf),
+            None => self.full_path.clone(),
+        }
+    }
+
+    /// Creates a unified cgroup at `self.full_path`, together with its leaf
+    /// cgroup if there is one, and returns the path of the cgroup which
+    /// contains the processes
+    fn create_unified_cgroup(&self) -> Result<PathBuf, V2ManagerError> {
         let controllers: Vec<String> = util::get_available_controllers(&self.root_path)?
             .iter()
             .map(|c| format!("+{c}"))
             .collect();
 
-        Self::write_controllers(&self.root_path, &controllers)?;
+        self.enable_root_controllers(&controllers)?;
 
         let mut current_path = self.root_path.clone();
         let mut components = self
             .cgroup_path
             .components()
             .filter(|c| c.ne(&RootDir))
+            .chain(self.leaf.iter().flat_map(|leaf| leaf.components()))
             .peekable();
         while let Some(component) = components.next() {
             current_path = current_path.join(component);
@@ -124,7 +233,86 @@ impl Manager {
             }
         }
 
-        common::write_cgroup_file(self.full_path.join(CGROUP_PROCS), pid)?;
+        Ok(current_path)
+    }
+
+    /// Enables the controllers for the children of the root cgroup. The root
+    /// of a nested hierarchy can't do so while it contains processes, e.g. the
+    /// one of youki itself when running inside a container. These processes
+    /// are moved into a leaf cgroup beneath the root first, the same way
+    /// nested container engines set up their hierarchy.
+    fn enable_root_controllers(&self, controllers: &[String]) -> Result<(), V2ManagerError> {
+        match Self::write_controllers(&self.root_path, controllers) {
+            Err(err)
+                if err.inner().raw_os_error() == Some(nix::errno::Errno::EBUSY as i32)
+                    && util::is_nested_hierarchy()? =>
+            {
+                tracing::debug!(
+                    "moving processes of nested root cgroup {:?} to a leaf cgroup",
+                    self.root_path
+                );
+                self.move_to_leaf_cgroup()?;
+                Self::write_controllers(&self.root_path, controllers)?;
+                Ok(())
+            }
+            result => Ok(result?),
+        }
+    }
+
+    fn move_to_leaf_cgroup(&self) -> Result<(), V2ManagerError> {
+        let leaf = self.root_path.join(NESTED_LEAF_CGROUP);
+        if !leaf.exists() {
+            fs::create_dir(&leaf).wrap_create_dir(&leaf)?;
+        }
+
+        let procs = common::read_cgroup_file(self.root_path.join(CGROUP_PROCS))?;
+        for pid in procs.lines() {
+            if let Err(err) = common::write_cgroup_file_str(leaf.join(CGROUP_PROCS), pid) {
+                // the process may have exited in the meantime
+                if err.inner().raw_os_error() != Some(nix::errno::Errno::ESRCH as i32) {
+                    return Err(err.into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fails with the first controller that the resources need, but which has
+    /// not been delegated to the cgroup, instead of failing to write one of
+    /// its files
+    fn check_required_controllers(
+        &self,
+        controller_opt: &ControllerOpt,
+    ) -> Result<(), V2ManagerError> {
+        match util::missing_controller(&self.full_path, controller_opt.resources)? {
+            Some(controller) => Err(V2ManagerError::ControllerNotAvailable(controller)),
+            None => Ok(()),
+        }
+    }
+
+    /// Changes the owner of the cgroup and of the files in it which are
+    /// delegated, which aren't all present depending on the kernel
+    fn chown_delegated(path: &Path, owner: Uid, group: Gid) -> Result<(), WrappedIoError> {
+        let files = fs::read_to_string(KERNEL_DELEGATE_FILES).ok();
+        let files: Vec<&str> = match &files {
+            Some(files) => files.lines().map(str::trim).collect(),
+            None => DEFAULT_DELEGATE_FILES.to_vec(),
+        };
+
+        let chown = |path: &Path| {
+            unistd::chown(path, Some(owner), Some(group))
+                .map_err(std::io::Error::from)
+                .wrap_other(path)
+        };
+        chown(path)?;
+        for file in files {
+            let file = path.join(file);
+            if file.exists() {
+                chown(&file)?;
+            }
+        }
+
         Ok(())
     }
 
@@ -137,6 +325,91 @@ impl Manager {
         Ok(())
     }
 
+    /// Applies the resources of the given controllers and the unified map,
+    /// which allows a hybrid setup to apply only the controllers bound to
+    /// the unified hierarchy
+    pub(crate) fn apply_controllers(
+        &self,
+        controller_opt: &ControllerOpt,
+        controllers: &[ControllerType],
+    ) -> Result<(), V2ManagerError> {
+        self.check_required_controllers(controller_opt)?;
+        let mut batch = WriteBatch::new(&self.best_effort);
+        for controller in controllers {
+            // lowering the memory limit depends on the usage once the memory
+            // has been reclaimed, so it can't be planned
+            if *controller == ControllerType::Memory {
+                let full_path = &self.full_path;
+                batch.unplanned(controller, move || Memory::apply(controller_opt, full_path));
+                continue;
+            }
+            batch.plan(controller, || -> Result<(), V2ManagerError> {
+                match controller {
+                    ControllerType::Cpu => Cpu::apply(controller_opt, &self.full_path)?,
+                    ControllerType::CpuSet => CpuSet::apply(controller_opt, &self.full_path)?,
+                    ControllerType::HugeTlb => HugeTlb::apply(controller_opt, &self.full_path)?,
+                    ControllerType::Io => Io::apply(controller_opt, &self.full_path)?,
+                    // applied unplanned, see above
+                    ControllerType::Memory => {}
+                    ControllerType::Pids => Pids::apply(controller_opt, &self.full_path)?,
+                    // misc limits can only be set through the unified map
+                    ControllerType::Misc => {}
+                    ControllerType::Rdma => Rdma::apply(controller_opt, &self.full_path)?,
+                }
+                Ok(())
+            })?;
+        }
+
+        for pseudoctlr in PSEUDO_CONTROLLER_TYPES {
+            if let PseudoControllerType::Unified = pseudoctlr {
+                // the controllers of the cgroup are the ones enabled by its parent
+                let parent = self.full_path.parent().unwrap_or(&self.root_path);
+                let controllers = util::get_subtree_controllers(parent)?;
+                batch.plan(pseudoctlr, || {
+                    Unified::apply(controller_opt, &self.full_path, controllers)
+                })?;
+            }
+        }
+        batch.apply()?;
+
+        Ok(())
+    }
+
+    /// Reads the stats of the given controllers
+    pub(crate) fn controller_stats(
+        &self,
+        controllers: &[ControllerType],
+    ) -> Result<Stats, V2ManagerError> {
+        // the files of all controllers are read at once, see
+        // [`stats::with_prefetched`]
+        let dirs: Vec<_> = controllers
+            .iter()
+            .filter(|controller| **controller != ControllerType::CpuSet)
+            .map(|controller| (self.full_path.as_path(), controller))
+            .collect();
+        stats::with_prefetched(&dirs, || {
+            let mut stats = Stats::default();
+
+            for subsystem in controllers {
+                match subsystem {
+                    ControllerType::Cpu => stats.cpu = Cpu::stats(&self.full_path)?,
+                    ControllerType::HugeTlb => stats.hugetlb = HugeTlb::stats(&self.full_path)?,
+                    ControllerType::Pids => {
+                        stats.pids =
+                            Pids::stats(&self.full_path).map_err(V2ManagerError::PidsStats)?
+                    }
+                    ControllerType::Memory => stats.memory = Memory::stats(&self.full_path)?,
+                    ControllerType::Io => stats.blkio = Io::stats(&self.full_path)?,
+                    ControllerType::Misc => stats.misc = Misc::stats(&self.full_path)?,
+                    ControllerType::Rdma => stats.rdma = Rdma::stats(&self.full_path)?,
+                    _ => continue,
+                }
+            }
+
+            Ok(stats)
+        })
+    }
+
     pub fn any(self) -> AnyCgroupManager {
         AnyCgroupManager::V2(self)
     }
@@ -146,38 +419,32 @@ impl CgroupManager for Manager {
     type Error = V2ManagerError;
 
     fn add_task(&self, pid: Pid) -> Result<(), Self::Error> {
-        if self.full_path.exists() {
-            common::write_cgroup_file(self.full_path.join(CGROUP_PROCS), pid)?;
-            return Ok(());
+        let mut procs_path = self.procs_path();
+        if !procs_path.exists() {
+            procs_path = self.create_unified_cgroup()?;
         }
-        self.create_unified_cgroup(pid)?;
+        common::write_cgroup_file(procs_path.join(CGROUP_PROCS), pid)?;
         Ok(())
     }
 
-    fn apply(&self, controller_opt: &ControllerOpt) -> Result<(), Self::Error> {
-        for controller in CONTROLLER_TYPES {
-            match controller {
-                ControllerType::Cpu => Cpu::apply(controller_opt, &self.full_path)?,
-                ControllerType::CpuSet => CpuSet::apply(controller_opt, &self.full_path)?,
-                ControllerType::HugeTlb => HugeTlb::apply(controller_opt, &self.full_path)?,
-                ControllerType::Io => Io::apply(controller_opt, &self.full_path)?,
-                ControllerType::Memory => Memory::apply(controller_opt, &self.full_path)?,
-                ControllerType::Pids => Pids::apply(controller_opt, &self.full_path)?,
-            }
+    fn open_task_cgroup(&self) -> Result<Option<File>, Self::Error> {
+        let mut procs_path = self.procs_path();
+        if !procs_path.exists() {
+            procs_path = self.create_unified_cgroup()?;
         }
+        let cgroup = OpenOptions::new()
+            .read(true)
+            .custom_flags((OFlag::O_DIRECTORY | OFlag::O_PATH).bits())
+            .open(&procs_path)
+            .wrap_open(&procs_path)?;
+        Ok(Some(cgroup))
+    }
 
-        #[cfg(feature = "cgroupsv2_devices")]
-        Devices::apply(controller_opt, &self.cgroup_path)?;
+    fn apply(&self, controller_opt: &ControllerOpt) -> Result<(), Self::Error> {
+        self.apply_controllers(controller_opt, CONTROLLER_TYPES)?;
 
-        for pseudoctlr in PSEUDO_CONTROLLER_TYPES {
-            if let PseudoControllerType::Unified = pseudoctlr {
-                Unified::apply(
-                    controller_opt,
-                    &self.full_path,
-                    util::get_available_controllers(&self.root_path)?,
-                )?;
-            }
-        }
+        #[cfg(feature = "cgroupsv2_devices")]
+        Devices::apply(controller_opt, &self.full_path)?;
 
         Ok(())
     }
@@ -185,23 +452,11 @@ impl CgroupManager for Manager {
     fn remove(&self) -> Result<(), Self::Error> {
         if self.full_path.exists() {
             tracing::debug!("remove cgroup {:?}", self.full_path);
-            let kill_file = self.full_path.join(CGROUP_KILL);
-            if kill_file.exists() {
-                fs::write(&kill_file, "1").wrap_write(&kill_file, "1")?;
-            } else {
-                let procs_path = self.full_path.join(CGROUP_PROCS);
-                let procs = fs::read_to_string(&procs_path).wrap_read(&procs_path)?;
-
-                for line in procs.lines() {
-                    let pid: i32 = line
-                        .parse()
-                        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
-                        .wrap_other(&procs_path)?;
-                    let _ = nix::sys::signal::kill(Pid::from_raw(pid), nix::sys::signal::SIGKILL);
-                }
+            if !self.kill_all()? {
+                common::kill_all_with_retry(&self.full_path, 10)?;
             }
 
-            common::delete_with_retry(&self.full_path, 4, Duration::from_millis(100))?;
+            common::delete_tree_with_retry(&self.full_path, 4, Duration::from_millis(100))?;
         }
 
         Ok(())
@@ -218,25 +473,225 @@ impl CgroupManager for Manager {
     }
 
     fn stats(&self) -> Result<Stats, Self::Error> {
-        let mut stats = Stats::default();
-
-        for subsystem in CONTROLLER_TYPES {
-            match subsystem {
-                ControllerType::Cpu => stats.cpu = Cpu::stats(&self.full_path)?,
-                ControllerType::HugeTlb => stats.hugetlb = HugeTlb::stats(&self.full_path)?,
-                ControllerType::Pids => {
-                    stats.pids = Pids::stats(&self.full_path).map_err(V2ManagerError::PidsStats)?
-                }
-                ControllerType::Memory => stats.memory = Memory::stats(&self.full_path)?,
-                ControllerType::Io => stats.blkio = Io::stats(&self.full_path)?,
-                _ => continue,
+        self.controller_stats(CONTROLLER_TYPES)
+    }
+
+    fn get_all_pids(&self) -> Result<Vec<Pid>, Self::Error> {
+        Ok(common::get_all_pids(&self.full_path)?)
+    }
+    fn oom_notifier(&self) -> Result<OomNotifier, Self::Error> {
+        Ok(OomNotifier::new_v2(&self.full_path)?)
+    }
+
+    fn events_watcher(&self) -> Result<CgroupEventsWatcher, Self::Error> {
+        Ok(CgroupEventsWatcher::new(&self.full_path)?)
+    }
+
+    fn kill_all(&self) -> Result<bool, Self::Error> {
+        let kill_file = self.full_path.join(CGROUP_KILL);
+        if !kill_file.exists() {
+            return Ok(false);
+        }
+
+        tracing::debug!("kill all processes of cgroup {:?}", self.full_path);
+        fs::write(&kill_file, "1").wrap_write(&kill_file, "1")?;
+        Ok(true)
+    }
+
+    fn reclaim_memory(&self, bytes: u64) -> Result<bool, Self::Error> {
+        let reclaim_file = self.full_path.join(CGROUP_MEMORY_RECLAIM);
+        if !reclaim_file.exists() {
+            return Ok(false);
+        }
+
+        tracing::debug!("reclaim {} bytes of cgroup {:?}", bytes, self.full_path);
+        // fails with EAGAIN if less than the requested amount was reclaimed
+        common::write_cgroup_file(&reclaim_file, bytes)?;
+        Ok(true)
+    }
+
+    fn delegate(
+        &self,
+        owner: Uid,
+        group: Gid,
+        controllers: &[String],
+    ) -> Result<bool, Self::Error> {
+        if !controllers.is_empty() {
+            // the cgroup itself must not contain processes to enable
+            // controllers for its children
+            if self.leaf.is_none() {
+                return Err(V2ManagerError::DelegateWithoutLeaf);
+            }
+
+            let controllers_path = self.full_path.join(util::CGROUP_CONTROLLERS);
+            let available = common::read_cgroup_file(&controllers_path)?;
+            let available: Vec<&str> = available.split_whitespace().collect();
+            if let Some(missing) = controllers
+                .iter()
+                .find(|c| !available.contains(&c.as_str()))
+            {
+                return Err(V2ManagerError::DelegateController(missing.to_owned()));
             }
+
+            let controllers: Vec<String> = controllers.iter().map(|c| format!("+{c}")).collect();
+            Self::write_controllers(&self.full_path, &controllers)?;
         }
 
-        Ok(stats)
+        tracing::debug!(
+            "delegate cgroup {:?} to {}:{} with controllers {:?}",
+            self.full_path,
+            owner,
+            group,
+            controllers
+        );
+        Self::chown_delegated(&self.full_path, owner, group)?;
+        if self.leaf.is_some() {
+            Self::chown_delegated(&self.procs_path(), owner, group)?;
+        }
+        Ok(true)
     }
+}
 
-    fn get_all_pids(&self) -> Result<Vec<Pid>, Self::Error> {
-        Ok(common::get_all_pids(&self.full_path)?)
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_kill_all() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let manager = Manager::new(tmp.path().to_owned(), PathBuf::from("/container"))?;
+        fs::create_dir(&manager.full_path)?;
+        // kernels older than 5.14 don't have cgroup.kill
+        assert!(!manager.kill_all()?);
+
+        fs::write(manager.full_path.join(CGROUP_KILL), "")?;
+        assert!(manager.kill_all()?);
+        assert_eq!(
+            fs::read_to_string(manager.full_path.join(CGROUP_KILL))?,
+            "1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_reclaim_memory() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let manager = Manager::new(tmp.path().to_owned(), PathBuf::from("/container"))?;
+        fs::create_dir(&manager.full_path)?;
+        // kernels older than 5.19 don't have memory.reclaim
+        assert!(!manager.reclaim_memory(1 << 20)?);
+
+        fs::write(manager.full_path.join(CGROUP_MEMORY_RECLAIM), "")?;
+        assert!(manager.reclaim_memory(1 << 20)?);
+        assert_eq!(
+            fs::read_to_string(manager.full_path.join(CGROUP_MEMORY_RECLAIM))?,
+            "1048576"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_delegate() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let (owner, group) = (unistd::getuid(), unistd::getgid());
+        let manager = Manager::new(tmp.path().to_owned(), PathBuf::from("/container"))?;
+        fs::create_dir(&manager.full_path)?;
+        let controllers = vec!["memory".to_owned()];
+        assert!(matches!(
+            manager.delegate(owner, group, &controllers),
+            Err(V2ManagerError::DelegateWithoutLeaf)
+        ));
+        assert!(manager.delegate(owner, group, &[])?);
+
+        let manager = manager.with_leaf("init")?;
+        fs::create_dir(manager.full_path.join("init"))?;
+        fs::write(
+            manager.full_path.join(util::CGROUP_CONTROLLERS),
+            "cpu memory",
+        )?;
+        fs::write(manager.full_path.join(CGROUP_SUBTREE_CONTROL), "")?;
+        assert!(manager.delegate(owner, group, &controllers)?);
+        assert_eq!(
+            fs::read_to_string(manager.full_path.join(CGROUP_SUBTREE_CONTROL))?,
+            "+memory"
+        );
+
+        assert!(matches!(
+            manager.delegate(owner, group, &["pids".to_owned()]),
+            Err(V2ManagerError::DelegateController(c)) if c == "pids"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_leaf() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let manager =
+            Manager::new(tmp.path().to_owned(), PathBuf::from("/container"))?.with_leaf("init")?;
+        let leaf = manager.full_path.join("init");
+        fs::create_dir_all(&leaf)?;
+        fs::write(leaf.join(CGROUP_PROCS), "")?;
+
+        manager.add_task(Pid::from_raw(42))?;
+        assert_eq!(fs::read_to_string(leaf.join(CGROUP_PROCS))?, "42");
+        assert!(!manager.full_path.join(CGROUP_PROCS).exists());
+
+        for name in ["", "..", "a/b"] {
+            let manager = Manager::new(tmp.path().to_owned(), PathBuf::from("/container"))?;
+            assert!(matches!(
+                manager.with_leaf(name),
+                Err(V2ManagerError::InvalidLeaf(_))
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_task_cgroup() -> Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = tempfile::tempdir()?;
+        let manager =
+            Manager::new(tmp.path().to_owned(), PathBuf::from("/container"))?.with_leaf("init")?;
+        let leaf = manager.full_path.join("init");
+        fs::create_dir_all(&leaf)?;
+
+        let cgroup = manager.open_task_cgroup()?.expect("cgroup can be opened");
+        assert_eq!(cgroup.metadata()?.ino(), fs::metadata(&leaf)?.ino());
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_not_available() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let manager = Manager::new(tmp.path().to_owned(), PathBuf::from("/container"))?;
+        fs::create_dir(&manager.full_path)?;
+        fs::write(
+            manager.full_path.join(util::CGROUP_CONTROLLERS),
+            "cpu memory",
+        )?;
+        let resources = oci_spec::runtime::LinuxResourcesBuilder::default()
+            .pids(
+                oci_spec::runtime::LinuxPidsBuilder::default()
+                    .limit(10)
+                    .build()?,
+            )
+            .build()?;
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+        };
+
+        let err = manager.apply(&controller_opt).unwrap_err();
+        assert!(matches!(
+            err,
+            V2ManagerError::ControllerNotAvailable(ControllerType::Pids)
+        ));
+        assert_eq!(err.kind(), CgroupErrorKind::ControllerNotFound);
+        Ok(())
     }
 }
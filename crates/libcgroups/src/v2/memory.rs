@@ -1,5 +1,6 @@
 use std::path::Path;
 
+use nix::errno::Errno;
 use oci_spec::runtime::LinuxMemory;
 
 use super::controller::Controller;
@@ -9,8 +10,13 @@ use crate::stats::{self, MemoryData, MemoryStats, ParseFlatKeyedDataError, Stats
 const CGROUP_MEMORY_SWAP: &str = "memory.swap.max";
 const CGROUP_MEMORY_MAX: &str = "memory.max";
 const CGROUP_MEMORY_LOW: &str = "memory.low";
+const MEMORY_CURRENT: &str = "memory.current";
+const MEMORY_RECLAIM: &str = "memory.reclaim";
+const MEMORY_SWAP_CURRENT: &str = "memory.swap.current";
 const MEMORY_STAT: &str = "memory.stat";
 const MEMORY_PSI: &str = "memory.pressure";
+// Number of reclaims before lowering the memory limit below the usage
+const RECLAIM_ATTEMPTS: usize = 5;
 
 #[derive(thiserror::Error, Debug)]
 pub enum V2MemoryControllerError {
@@ -26,6 +32,8 @@ pub enum V2MemoryControllerError {
     SwapWithoutLimit,
     #[error("invalid memory reservation value: {0}")]
     MemoryReservation(i64),
+    #[error("unable to set memory limit to {target} (current usage: {current})")]
+    UnableToSet { target: i64, current: u64 },
 }
 
 pub struct Memory {}
@@ -54,9 +62,21 @@ impl StatsProvider for Memory {
     type Stats = MemoryStats;
 
     fn stats(cgroup_path: &Path) -> Result<Self::Stats, Self::Error> {
+        let memory = Self::get_memory_data(cgroup_path, "memory", "oom")?;
+        // the swap files only exist with swap accounting
+        let swap_accounting = cgroup_path.join(MEMORY_SWAP_CURRENT).exists();
+        let (memswap, swap) = if swap_accounting {
+            let swap = Self::get_memory_data(cgroup_path, "memory.swap", "fail")?;
+            (memory_and_swap(&memory, &swap), swap)
+        } else {
+            Default::default()
+        };
+
         let stats = MemoryStats {
-            memory: Self::get_memory_data(cgroup_path, "memory", "oom")?,
-            memswap: Self::get_memory_data(cgroup_path, "memory.swap", "fail")?,
+            memory,
+            memswap,
+            swap,
+            swap_accounting,
             hierarchy: true,
             stats: stats::parse_flat_keyed_data(&cgroup_path.join(MEMORY_STAT))?,
             psi: stats::psi_stats(&cgroup_path.join(MEMORY_PSI))?,
@@ -67,6 +87,17 @@ impl StatsProvider for Memory {
     }
 }
 
+/// Adds up the usage of memory and swap, which cgroup v1 reports as one
+fn memory_and_swap(memory: &MemoryData, swap: &MemoryData) -> MemoryData {
+    MemoryData {
+        usage: memory.usage.saturating_add(swap.usage),
+        // the peaks of memory and swap are usually not reached at once
+        max_usage: 0,
+        fail_count: swap.fail_count,
+        limit: memory.limit.saturating_add(swap.limit),
+    }
+}
+
 impl Memory {
     fn get_memory_data(
         cgroup_path: &Path,
@@ -108,6 +139,50 @@ impl Memory {
         }
     }
 
+    /// Lowering the limit below the usage makes the kernel reclaim memory and
+    /// kill processes of the cgroup if that isn't enough. The memory is
+    /// reclaimed before, so that the limit is only lowered once the usage
+    /// fits, instead of killing the container.
+    fn set_limit(path: &Path, limit: i64) -> Result<(), V2MemoryControllerError> {
+        if limit > 0 {
+            Self::reclaim_below(path, limit)?;
+        }
+        Ok(Memory::set(path.join(CGROUP_MEMORY_MAX), limit)?)
+    }
+
+    fn reclaim_below(path: &Path, limit: i64) -> Result<(), V2MemoryControllerError> {
+        let reclaim_file = path.join(MEMORY_RECLAIM);
+        // kernels older than 5.19 can't reclaim on request
+        if !reclaim_file.exists() {
+            return Ok(());
+        }
+        let usage = || stats::parse_single_value(&path.join(MEMORY_CURRENT));
+
+        for _ in 0..RECLAIM_ATTEMPTS {
+            let current = usage()?;
+            if current <= limit as u64 {
+                return Ok(());
+            }
+
+            let bytes = current - limit as u64;
+            tracing::debug!(bytes, limit, "reclaim memory before lowering the limit");
+            // fails with EAGAIN if less than the requested amount was reclaimed
+            if let Err(err) = common::write_cgroup_file(&reclaim_file, bytes) {
+                if err.inner().raw_os_error() != Some(Errno::EAGAIN as i32) {
+                    return Err(err.into());
+                }
+            }
+        }
+
+        match usage()? {
+            current if current <= limit as u64 => Ok(()),
+            current => Err(V2MemoryControllerError::UnableToSet {
+                target: limit,
+                current,
+            }),
+        }
+    }
+
     fn apply(path: &Path, memory: &LinuxMemory) -> Result<(), V2MemoryControllerError> {
         // if nothing is set just exit right away
         if memory.reservation().is_none() && memory.limit().is_none() && memory.swap().is_none() {
@@ -137,20 +212,35 @@ impl Memory {
                         // by subtracting limit from swap
                         Memory::set(path.join(CGROUP_MEMORY_SWAP), swap - limit)?;
                     }
-                    Memory::set(path.join(CGROUP_MEMORY_MAX), limit)?;
+                    Memory::set_limit(path, limit)?;
                 }
                 None => {
                     if limit == -1 {
                         Memory::set(path.join(CGROUP_MEMORY_SWAP), -1)?;
                     }
-                    Memory::set(path.join(CGROUP_MEMORY_MAX), limit)?;
+                    Memory::set_limit(path, limit)?;
                 }
             },
-            None => {
-                if memory.swap().is_some() {
-                    return Err(V2MemoryControllerError::SwapWithoutLimit);
+            None => match memory.swap() {
+                Some(swap) if swap < -1 => {
+                    return Err(V2MemoryControllerError::SwapValue(swap));
                 }
-            }
+                Some(-1) => Memory::set(path.join(CGROUP_MEMORY_SWAP), -1)?,
+                Some(swap) => {
+                    // the swap is converted with the current limit, which
+                    // stays as it is
+                    let limit = stats::parse_single_value(&path.join(CGROUP_MEMORY_MAX))?;
+                    let limit = match i64::try_from(limit) {
+                        Ok(limit) if limit != i64::MAX => limit,
+                        _ => return Err(V2MemoryControllerError::SwapWithoutLimit),
+                    };
+                    if swap < limit {
+                        return Err(V2MemoryControllerError::SwapTooSmall { swap, limit });
+                    }
+                    Memory::set(path.join(CGROUP_MEMORY_SWAP), swap - limit)?;
+                }
+                None => {}
+            },
         };
 
         if let Some(reservation) = memory.reservation() {
@@ -228,10 +318,36 @@ mod tests {
         assert_eq!(swap_content, "max");
     }
 
+    #[test]
+    fn test_set_swap_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), CGROUP_MEMORY_MAX, "1024").expect("set fixture for memory limit");
+        set_fixture(tmp.path(), CGROUP_MEMORY_SWAP, "0").expect("set fixture for swap limit");
+
+        let memory_limits = LinuxMemoryBuilder::default().swap(4096).build().unwrap();
+        Memory::apply(tmp.path(), &memory_limits).expect("apply swap limit");
+
+        let limit_content =
+            read_to_string(tmp.path().join(CGROUP_MEMORY_MAX)).expect("read memory limit");
+        assert_eq!(limit_content, "1024");
+        let swap_content =
+            read_to_string(tmp.path().join(CGROUP_MEMORY_SWAP)).expect("read swap limit");
+        assert_eq!(swap_content, "3072");
+
+        let memory_limits = LinuxMemoryBuilder::default().swap(512).build().unwrap();
+        assert!(matches!(
+            Memory::apply(tmp.path(), &memory_limits),
+            Err(V2MemoryControllerError::SwapTooSmall {
+                swap: 512,
+                limit: 1024
+            })
+        ));
+    }
+
     #[test]
     fn test_err_swap_no_memory() {
         let tmp = tempfile::tempdir().unwrap();
-        set_fixture(tmp.path(), CGROUP_MEMORY_MAX, "0").expect("set fixture for memory limit");
+        set_fixture(tmp.path(), CGROUP_MEMORY_MAX, "max").expect("set fixture for memory limit");
         set_fixture(tmp.path(), CGROUP_MEMORY_LOW, "0")
             .expect("set fixture for memory reservation");
         set_fixture(tmp.path(), CGROUP_MEMORY_SWAP, "0").expect("set fixture for swap limit");
@@ -240,7 +356,43 @@ mod tests {
 
         let result = Memory::apply(tmp.path(), &memory_limits);
 
-        assert!(result.is_err());
+        assert!(matches!(
+            result,
+            Err(V2MemoryControllerError::SwapWithoutLimit)
+        ));
+    }
+
+    #[test]
+    fn test_lower_limit_with_reclaim() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), CGROUP_MEMORY_MAX, "max").expect("set fixture for memory limit");
+        set_fixture(tmp.path(), MEMORY_CURRENT, "4096").expect("set fixture for memory usage");
+        set_fixture(tmp.path(), MEMORY_RECLAIM, "").expect("set fixture for memory reclaim");
+
+        Memory::set_limit(tmp.path(), 8192).expect("set limit above the usage");
+        assert_eq!(
+            read_to_string(tmp.path().join(CGROUP_MEMORY_MAX)).unwrap(),
+            "8192"
+        );
+        assert_eq!(read_to_string(tmp.path().join(MEMORY_RECLAIM)).unwrap(), "");
+
+        // the fixture doesn't shrink, so the usage never fits
+        let result = Memory::set_limit(tmp.path(), 1024);
+        assert!(matches!(
+            result,
+            Err(V2MemoryControllerError::UnableToSet {
+                target: 1024,
+                current: 4096
+            })
+        ));
+        assert_eq!(
+            read_to_string(tmp.path().join(MEMORY_RECLAIM)).unwrap(),
+            "3072"
+        );
+        assert_eq!(
+            read_to_string(tmp.path().join(CGROUP_MEMORY_MAX)).unwrap(),
+            "8192"
+        );
     }
 
     #[test]
@@ -279,8 +431,11 @@ mod tests {
 
     quickcheck! {
         fn property_test_set_memory(linux_memory: LinuxMemory) -> bool {
+            // the limit the cgroup already has, which swap only updates
+            // convert the swap with
+            const CURRENT_LIMIT: i64 = 1024;
             let tmp = tempfile::tempdir().unwrap();
-            set_fixture(tmp.path(), CGROUP_MEMORY_MAX, "0").expect("set fixture for memory limit");
+            set_fixture(tmp.path(), CGROUP_MEMORY_MAX, &CURRENT_LIMIT.to_string()).expect("set fixture for memory limit");
             set_fixture(tmp.path(), CGROUP_MEMORY_LOW, "0").expect("set fixture for memory reservation");
             set_fixture(tmp.path(), CGROUP_MEMORY_SWAP, "0").expect("set fixture for swap limit");
 
@@ -298,14 +453,10 @@ mod tests {
                 if swap < -1 {
                     return result.is_err();
                 }
-                if linux_memory.limit().is_none() {
+                let limit = linux_memory.limit().unwrap_or(CURRENT_LIMIT);
+                if limit != -1 && swap != -1 && swap < limit {
                     return result.is_err();
                 }
-                if let Some(limit) = linux_memory.limit() {
-                    if limit != -1 && swap != -1 && swap < limit {
-                        return result.is_err();
-                    }
-                }
             }
 
             if let Some(reservation) = linux_memory.reservation() {
@@ -318,25 +469,20 @@ mod tests {
             let limit_content = read_to_string(tmp.path().join(CGROUP_MEMORY_MAX)).expect("read memory limit to string");
             let limit_check = match linux_memory.limit() {
                 Some(-1) => limit_content == "max",
+                // a limit of 0 is ignored
+                Some(0) | None => limit_content == CURRENT_LIMIT.to_string(),
                 Some(limit) => limit_content == limit.to_string(),
-                None => limit_content == "0",
             };
 
             // check the swap file is set as expected
             let swap_content = read_to_string(tmp.path().join(CGROUP_MEMORY_SWAP)).expect("read swap limit to string");
             let swap_check = match linux_memory.swap() {
                 Some(-1)=> swap_content == "max",
-                Some(swap) => {
-                    if let Some(limit) = linux_memory.limit() {
-                        if limit == -1 {
-                            swap_content == swap.to_string()
-                        } else {
-                            swap_content == (swap - linux_memory.limit().unwrap()).to_string()
-                        }
-                    } else {
-                        false
-                    }
-                }
+                Some(swap) => match linux_memory.limit() {
+                    Some(-1) => swap_content == swap.to_string(),
+                    Some(limit) => swap_content == (swap - limit).to_string(),
+                    None => swap_content == (swap - CURRENT_LIMIT).to_string(),
+                },
                 None => {
                     match linux_memory.limit() {
                         Some(-1) => swap_content == "max",
@@ -381,6 +527,45 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_stats_swap() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), "memory.current", "12500\n").unwrap();
+        set_fixture(tmp.path(), "memory.max", "25000\n").unwrap();
+        set_fixture(tmp.path(), "memory.events", "oom 3\n").unwrap();
+        set_fixture(tmp.path(), MEMORY_STAT, "anon 13\n").unwrap();
+        set_fixture(tmp.path(), MEMORY_PSI, "").unwrap();
+
+        let stats = Memory::stats(tmp.path()).expect("get cgroup stats");
+        assert!(!stats.swap_accounting);
+        assert_eq!(stats.memswap, MemoryData::default());
+
+        set_fixture(tmp.path(), MEMORY_SWAP_CURRENT, "500\n").unwrap();
+        set_fixture(tmp.path(), CGROUP_MEMORY_SWAP, "max\n").unwrap();
+        set_fixture(tmp.path(), "memory.swap.events", "max 0\nfail 2\n").unwrap();
+
+        let stats = Memory::stats(tmp.path()).expect("get cgroup stats");
+        assert!(stats.swap_accounting);
+        assert_eq!(
+            stats.swap,
+            MemoryData {
+                usage: 500,
+                limit: u64::MAX,
+                fail_count: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            stats.memswap,
+            MemoryData {
+                usage: 13000,
+                limit: u64::MAX,
+                fail_count: 2,
+                ..Default::default()
+            }
+        );
+    }
+
     #[test]
     fn test_get_memory_data_with_peak() {
         let tmp = tempfile::tempdir().unwrap();
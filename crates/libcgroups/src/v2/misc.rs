@@ -0,0 +1,17 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::stats::{self, MiscStats, MiscStatsError, StatsProvider};
+
+/// The misc controller doesn't have a counterpart in the runtime spec yet, its
+/// limits are set through the unified map
+pub struct Misc {}
+
+impl StatsProvider for Misc {
+    type Error = MiscStatsError;
+    type Stats = HashMap<String, MiscStats>;
+
+    fn stats(cgroup_path: &Path) -> Result<Self::Stats, Self::Error> {
+        stats::misc_stats(cgroup_path)
+    }
+}
@@ -1,14 +1,16 @@
 mod controller;
 pub mod controller_type;
-mod cpu;
+pub(crate) mod cpu;
 mod cpuset;
 #[cfg(feature = "cgroupsv2_devices")]
 pub mod devices;
 mod freezer;
-mod hugetlb;
+pub(crate) mod hugetlb;
 mod io;
 pub mod manager;
 mod memory;
+mod misc;
 mod pids;
-mod unified;
+pub(crate) mod rdma;
+pub(crate) mod unified;
 pub mod util;
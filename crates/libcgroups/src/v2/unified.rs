@@ -2,17 +2,19 @@ use std::collections::HashMap;
 use std::path::Path;
 
 use super::controller_type::ControllerType;
+use super::util::V2UtilError;
 use crate::common::{self, ControllerOpt, WrappedIoError};
 
 #[derive(thiserror::Error, Debug)]
 pub enum V2UnifiedError {
     #[error("io error: {0}")]
     WrappedIo(#[from] WrappedIoError),
-    #[error("subsystem {subsystem} is not available: {err}")]
-    SubsystemNotAvailable {
-        subsystem: String,
-        err: WrappedIoError,
-    },
+    #[error("failed to get the enabled controllers: {0}")]
+    Controllers(#[from] V2UtilError),
+    #[error("invalid unified key {0}")]
+    InvalidKey(String),
+    #[error("subsystem {subsystem} of {key} is not enabled for the cgroup")]
+    SubsystemNotAvailable { subsystem: String, key: String },
 }
 
 pub struct Unified {}
@@ -24,35 +26,56 @@ impl Unified {
         controllers: Vec<ControllerType>,
     ) -> Result<(), V2UnifiedError> {
         if let Some(unified) = &controller_opt.resources.unified() {
-            Self::apply_impl(unified, cgroup_path, &controllers)?;
+            Self::apply_entries(unified, cgroup_path, &controllers)?;
         }
 
         Ok(())
     }
 
-    fn apply_impl(
+    /// Writes the given entries to the interface files of the cgroup
+    pub(crate) fn apply_entries(
         unified: &HashMap<String, String>,
         cgroup_path: &Path,
         controllers: &[ControllerType],
     ) -> Result<(), V2UnifiedError> {
         tracing::debug!("Apply unified cgroup config");
+        // nothing is written unless all keys are valid, so that a bad key does
+        // not leave the cgroup partially configured
+        for cgroup_file in unified.keys() {
+            Self::validate_key(cgroup_file, controllers)?;
+        }
+
         for (cgroup_file, value) in unified {
-            if let Err(err) = common::write_cgroup_file_str(cgroup_path.join(cgroup_file), value) {
-                let (subsystem, _) = cgroup_file.split_once('.').unwrap_or((cgroup_file, ""));
-
-                if controllers.iter().any(|c| c.to_string() == subsystem) {
-                    Err(err)?;
-                } else {
-                    return Err(V2UnifiedError::SubsystemNotAvailable {
-                        subsystem: subsystem.into(),
-                        err,
-                    });
-                }
-            }
+            common::write_cgroup_file_entries(cgroup_path.join(cgroup_file), value)?;
         }
 
         Ok(())
     }
+
+    /// Checks that the key names an interface file of the cgroup, i.e. of the
+    /// core or of a controller enabled in the `cgroup.subtree_control` of the parent
+    fn validate_key(
+        cgroup_file: &str,
+        controllers: &[ControllerType],
+    ) -> Result<(), V2UnifiedError> {
+        let subsystem = match cgroup_file.split_once('.') {
+            Some((subsystem, name))
+                if !subsystem.is_empty() && !name.is_empty() && !cgroup_file.contains('/') =>
+            {
+                subsystem
+            }
+            _ => return Err(V2UnifiedError::InvalidKey(cgroup_file.into())),
+        };
+
+        if subsystem == "cgroup" || controllers.iter().any(|c| c.to_string() == subsystem) {
+            return Ok(());
+        }
+
+        Err(V2UnifiedError::SubsystemNotAvailable {
+            subsystem: subsystem.into(),
+            key: cgroup_file.into(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -96,7 +119,12 @@ mod tests {
         };
 
         // act
-        Unified::apply(&controller_opt, tmp.path(), vec![]).expect("apply unified");
+        Unified::apply(
+            &controller_opt,
+            tmp.path(),
+            vec![ControllerType::HugeTlb, ControllerType::Cpu],
+        )
+        .expect("apply unified");
 
         // assert
         let hugetlb_limit = fs::read_to_string(hugetlb_limit_path).expect("read hugetlb limit");
@@ -109,6 +137,7 @@ mod tests {
     fn test_set_unified_failed_to_write_subsystem_not_enabled() {
         // arrange
         let tmp = tempfile::tempdir().unwrap();
+        let cpu_weight_path = set_fixture(tmp.path(), "cpu.weight", "").unwrap();
 
         let unified = {
             let mut u = HashMap::new();
@@ -133,10 +162,32 @@ mod tests {
         };
 
         // act
-        let result = Unified::apply(&controller_opt, tmp.path(), vec![]);
+        let result = Unified::apply(&controller_opt, tmp.path(), vec![ControllerType::Cpu]);
 
         // assert
-        assert!(result.is_err());
+        assert!(matches!(
+            result,
+            Err(V2UnifiedError::SubsystemNotAvailable { subsystem, .. }) if subsystem == "hugetlb"
+        ));
+        // nothing has been written, as the validation failed
+        let cpu_weight = fs::read_to_string(cpu_weight_path).expect("read cpu weight");
+        assert_eq!(cpu_weight, "");
+    }
+
+    #[test]
+    fn test_set_unified_invalid_key() {
+        for key in ["cpu", "cpu.", ".weight", "../cpu.weight", "cpu.weight/x"] {
+            assert!(
+                matches!(
+                    Unified::validate_key(key, &[ControllerType::Cpu]),
+                    Err(V2UnifiedError::InvalidKey(_))
+                ),
+                "{key} must be rejected"
+            );
+        }
+
+        // core interface files are always available
+        assert!(Unified::validate_key("cgroup.max.depth", &[]).is_ok());
     }
 
     #[test]
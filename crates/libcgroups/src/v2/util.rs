@@ -1,5 +1,6 @@
 use std::path::{Path, PathBuf};
 
+use oci_spec::runtime::LinuxResources;
 use procfs::process::Process;
 use procfs::ProcError;
 
@@ -19,6 +20,8 @@ pub enum V2UtilError {
     CouldNotFind,
     #[error("cannot get available controllers. {0} does not exist")]
     DoesNotExist(PathBuf),
+    #[error("could not find the unified cgroup of the process")]
+    NoProcessCgroup,
 }
 
 // Reads the `/proc/self/mountinfo` to get the mount point of this cgroup
@@ -31,6 +34,42 @@ pub fn get_unified_mount_point() -> Result<PathBuf, V2UtilError> {
         .ok_or(V2UtilError::CouldNotFind)
 }
 
+/// Returns the cgroup of the current process relative to the mount point of
+/// the unified hierarchy
+pub fn get_current_cgroup() -> Result<PathBuf, V2UtilError> {
+    let process = Process::myself()?;
+    let mount_root = process
+        .mountinfo()?
+        .into_iter()
+        .find(|m| m.fs_type == "cgroup2")
+        .map(|m| PathBuf::from(m.root))
+        .ok_or(V2UtilError::CouldNotFind)?;
+    let pathname = process
+        .cgroups()?
+        .into_iter()
+        .find(|c| c.hierarchy == 0)
+        .map(|c| c.pathname)
+        .ok_or(V2UtilError::NoProcessCgroup)?;
+
+    Ok(common::normalize_cgroup_path(pathname, &mount_root))
+}
+
+/// Checks if the unified hierarchy visible to the current process is nested
+/// in the hierarchy of the host, either because only a subtree of it is
+/// mounted or because the process runs in its own cgroup namespace. The root
+/// of a nested hierarchy is an ordinary cgroup, which has to obey the no
+/// internal processes rule.
+pub fn is_nested_hierarchy() -> Result<bool, V2UtilError> {
+    let mounts_subtree = Process::myself()?
+        .mountinfo()?
+        .into_iter()
+        .find(|m| m.fs_type == "cgroup2")
+        .map(|m| m.root != "/")
+        .ok_or(V2UtilError::CouldNotFind)?;
+
+    Ok(mounts_subtree || common::in_cgroup_namespace()?)
+}
+
 /// Reads the `{root_path}/cgroup.controllers` file to get the list of the controllers that are
 /// available in this cgroup
 pub fn get_available_controllers<P: AsRef<Path>>(
@@ -42,8 +81,107 @@ pub fn get_available_controllers<P: AsRef<Path>>(
         return Err(V2UtilError::DoesNotExist(controllers_path));
     }
 
+    Ok(parse_controllers(&common::read_cgroup_file(
+        controllers_path,
+    )?))
+}
+
+/// Reads the `{cgroup_path}/cgroup.subtree_control` file to get the list of the controllers
+/// that are enabled for the children of this cgroup
+pub fn get_subtree_controllers<P: AsRef<Path>>(
+    cgroup_path: P,
+) -> Result<Vec<ControllerType>, V2UtilError> {
+    let subtree_control_path = cgroup_path.as_ref().join(CGROUP_SUBTREE_CONTROL);
+    if !subtree_control_path.exists() {
+        return Err(V2UtilError::DoesNotExist(subtree_control_path));
+    }
+
+    Ok(parse_controllers(&common::read_cgroup_file(
+        subtree_control_path,
+    )?))
+}
+
+/// Returns the first controller which the resources need, but which is not
+/// available to the cgroup. Nothing can be checked for a cgroup which has
+/// not been created yet.
+pub(crate) fn missing_controller(
+    cgroup_path: &Path,
+    resources: &LinuxResources,
+) -> Result<Option<ControllerType>, V2UtilError> {
+    let available = match get_available_controllers(cgroup_path) {
+        Ok(available) => available,
+        Err(V2UtilError::DoesNotExist(_)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    Ok(required_controllers(resources)
+        .into_iter()
+        .find(|controller| !available.contains(controller)))
+}
+
+/// Returns the controllers which have to be available to the cgroup, so that
+/// the resources can be applied to it
+fn required_controllers(resources: &LinuxResources) -> Vec<ControllerType> {
+    let mut controllers = Vec::new();
+    if let Some(cpu) = resources.cpu() {
+        if cpu.shares().is_some()
+            || cpu.quota().is_some()
+            || cpu.period().is_some()
+            || cpu.burst().is_some()
+            || cpu.idle().is_some()
+        {
+            controllers.push(ControllerType::Cpu);
+        }
+        if cpu.cpus().is_some() || cpu.mems().is_some() {
+            controllers.push(ControllerType::CpuSet);
+        }
+    }
+    if let Some(memory) = resources.memory() {
+        if memory.limit().is_some() || memory.reservation().is_some() || memory.swap().is_some() {
+            controllers.push(ControllerType::Memory);
+        }
+    }
+    if resources.pids().is_some() {
+        controllers.push(ControllerType::Pids);
+    }
+    if let Some(io) = resources.block_io() {
+        let has_devices = [
+            io.throttle_read_bps_device(),
+            io.throttle_write_bps_device(),
+            io.throttle_read_iops_device(),
+            io.throttle_write_iops_device(),
+        ]
+        .iter()
+        .any(|devices| devices.as_ref().map_or(false, |d| !d.is_empty()));
+        if io.weight().is_some()
+            || io.leaf_weight().is_some()
+            || io.weight_device().as_ref().map_or(false, |d| !d.is_empty())
+            || has_devices
+        {
+            controllers.push(ControllerType::Io);
+        }
+    }
+    if resources
+        .hugepage_limits()
+        .as_ref()
+        .map_or(false, |limits| !limits.is_empty())
+    {
+        controllers.push(ControllerType::HugeTlb);
+    }
+    if resources
+        .rdma()
+        .as_ref()
+        .map_or(false, |rdma| !rdma.is_empty())
+    {
+        controllers.push(ControllerType::Rdma);
+    }
+
+    controllers
+}
+
+fn parse_controllers(content: &str) -> Vec<ControllerType> {
     let mut controllers = Vec::new();
-    for controller in common::read_cgroup_file(controllers_path)?.split_whitespace() {
+    for controller in content.split_whitespace() {
         match controller {
             "cpu" => controllers.push(ControllerType::Cpu),
             "cpuset" => controllers.push(ControllerType::CpuSet),
@@ -51,9 +189,11 @@ pub fn get_available_controllers<P: AsRef<Path>>(
             "io" => controllers.push(ControllerType::Io),
             "memory" => controllers.push(ControllerType::Memory),
             "pids" => controllers.push(ControllerType::Pids),
+            "misc" => controllers.push(ControllerType::Misc),
+            "rdma" => controllers.push(ControllerType::Rdma),
             tpe => tracing::warn!("Controller {} is not yet implemented.", tpe),
         }
     }
 
-    Ok(controllers)
+    controllers
 }
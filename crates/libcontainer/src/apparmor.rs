@@ -21,8 +21,12 @@ const ENABLED_PARAMETER_PATH: &str = "/sys/module/apparmor/parameters/enabled";
 
 /// Checks if AppArmor has been enabled on the system.
 pub fn is_enabled() -> std::result::Result<bool, std::io::Error> {
-    let aa_enabled = fs::read_to_string(ENABLED_PARAMETER_PATH)?;
-    Ok(aa_enabled.starts_with('Y'))
+    match fs::read_to_string(ENABLED_PARAMETER_PATH) {
+        Ok(aa_enabled) => Ok(aa_enabled.starts_with('Y')),
+        // the parameter only exists if the kernel has been built with AppArmor
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
 }
 
 /// Applies an AppArmor profile to the container.
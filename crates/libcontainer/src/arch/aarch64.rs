@@ -0,0 +1,21 @@
+//! 64-bit ARM, which can also run 32-bit ARM processes on CPUs supporting
+//! AArch32
+use oci_spec::runtime::Arch;
+
+/// Name of the architecture as reported by uname
+pub const NAME: &str = "aarch64";
+
+/// Seccomp architectures of the native syscall ABI, followed by the ones
+/// processes can switch to, which a filter has to cover as well
+pub const SECCOMP_ARCHS: &[Arch] = &[Arch::ScmpArchAarch64, Arch::ScmpArchArm];
+
+/// Whether the LINUX32 personality makes processes see a 32-bit machine
+pub const LINUX32_PERSONALITY: bool = true;
+
+/// Architecture the kernel passes to seccomp filters for native syscalls,
+/// AUDIT_ARCH_AARCH64 of linux/audit.h
+pub const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+
+/// Bit set in the numbers of syscalls of another ABI which the kernel passes
+/// to seccomp filters with the native architecture. There is none.
+pub const X32_SYSCALL_BIT: Option<u32> = None;
@@ -0,0 +1,22 @@
+//! Architectures without specific support, for which nothing is assumed
+//! beyond what libc provides
+use oci_spec::runtime::Arch;
+
+/// Name of the architecture as Rust calls it, which may differ from the one
+/// reported by uname
+pub const NAME: &str = std::env::consts::ARCH;
+
+/// Seccomp architectures of the native syscall ABI, followed by the ones
+/// processes can switch to. Unknown, so that only SCMP_ARCH_NATIVE is used.
+pub const SECCOMP_ARCHS: &[Arch] = &[];
+
+/// Whether the LINUX32 personality makes processes see a 32-bit machine
+pub const LINUX32_PERSONALITY: bool = false;
+
+/// Architecture the kernel passes to seccomp filters for native syscalls.
+/// Unknown, so that the built-in seccomp backend can't filter syscalls.
+pub const AUDIT_ARCH: Option<u32> = None;
+
+/// Bit set in the numbers of syscalls of another ABI which the kernel passes
+/// to seccomp filters with the native architecture. There is none.
+pub const X32_SYSCALL_BIT: Option<u32> = None;
@@ -0,0 +1,57 @@
+//! Architecture specific details of the process setup. Syscall numbers and the
+//! layout of the structs passed to the kernel come from libc for the target,
+//! so only what libc doesn't cover is kept here, with a module per supported
+//! architecture and conservative defaults for the others.
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+#[cfg(target_arch = "aarch64")]
+pub use self::aarch64::*;
+
+#[cfg(target_arch = "riscv64")]
+mod riscv64;
+#[cfg(target_arch = "riscv64")]
+pub use self::riscv64::*;
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+#[cfg(target_arch = "x86_64")]
+pub use self::x86_64::*;
+
+#[cfg(not(any(
+    target_arch = "aarch64",
+    target_arch = "riscv64",
+    target_arch = "x86_64"
+)))]
+mod generic;
+#[cfg(not(any(
+    target_arch = "aarch64",
+    target_arch = "riscv64",
+    target_arch = "x86_64"
+)))]
+pub use self::generic::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "riscv64",
+        target_arch = "x86_64"
+    ))]
+    #[test]
+    fn test_name() {
+        let uname = nix::sys::utsname::uname().unwrap();
+        assert_eq!(uname.machine().to_str(), Some(NAME));
+    }
+
+    #[cfg(feature = "libseccomp")]
+    #[test]
+    fn test_native_seccomp_arch() {
+        use libseccomp::ScmpArch;
+
+        if let Some(&native) = SECCOMP_ARCHS.first() {
+            assert_eq!(crate::seccomp::translate_arch(native), ScmpArch::native());
+        }
+    }
+}
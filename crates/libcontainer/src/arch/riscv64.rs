@@ -0,0 +1,22 @@
+//! 64-bit RISC-V. The kernel has no 32-bit compat mode for it that processes
+//! could switch to with a personality.
+use oci_spec::runtime::Arch;
+
+/// Name of the architecture as reported by uname
+pub const NAME: &str = "riscv64";
+
+/// Seccomp architectures of the native syscall ABI, followed by the ones
+/// processes can switch to. The spec types have no token for riscv64 yet, so
+/// that profiles refer to it as SCMP_ARCH_NATIVE.
+pub const SECCOMP_ARCHS: &[Arch] = &[];
+
+/// Whether the LINUX32 personality makes processes see a 32-bit machine
+pub const LINUX32_PERSONALITY: bool = false;
+
+/// Architecture the kernel passes to seccomp filters for native syscalls,
+/// AUDIT_ARCH_RISCV64 of linux/audit.h
+pub const AUDIT_ARCH: Option<u32> = Some(0xc000_00f3);
+
+/// Bit set in the numbers of syscalls of another ABI which the kernel passes
+/// to seccomp filters with the native architecture. There is none.
+pub const X32_SYSCALL_BIT: Option<u32> = None;
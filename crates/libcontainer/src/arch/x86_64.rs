@@ -0,0 +1,20 @@
+//! 64-bit x86, which can also run 32-bit x86 and x32 processes
+use oci_spec::runtime::Arch;
+
+/// Name of the architecture as reported by uname
+pub const NAME: &str = "x86_64";
+
+/// Seccomp architectures of the native syscall ABI, followed by the ones
+/// processes can switch to, which a filter has to cover as well
+pub const SECCOMP_ARCHS: &[Arch] = &[Arch::ScmpArchX86_64, Arch::ScmpArchX86, Arch::ScmpArchX32];
+
+/// Whether the LINUX32 personality makes processes see a 32-bit machine
+pub const LINUX32_PERSONALITY: bool = true;
+
+/// Architecture the kernel passes to seccomp filters for native syscalls,
+/// AUDIT_ARCH_X86_64 of linux/audit.h
+pub const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+
+/// Bit set in the numbers of x32 syscalls, which the kernel passes to
+/// seccomp filters with the native architecture
+pub const X32_SYSCALL_BIT: Option<u32> = Some(0x4000_0000);
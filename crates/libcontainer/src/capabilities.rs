@@ -152,11 +152,13 @@ pub fn drop_privileges<S: Syscall + ?Sized>(
         syscall.set_capability(CapSet::Inheritable, &to_set(inheritable))?;
     }
 
-    if let Some(ambient) = cs.ambient() {
-        // check specifically for ambient, as those might not always be available
-        if let Err(e) = syscall.set_capability(CapSet::Ambient, &to_set(ambient)) {
-            tracing::error!("failed to set ambient capabilities: {}", e);
-        }
+    // the kernel only lowers the ambient capabilities the caller has passed on
+    // when they leave the permitted or inheritable set, so they are cleared
+    // unless requested
+    let ambient = cs.ambient().as_ref().map(to_set).unwrap_or_default();
+    // check specifically for ambient, as those might not always be available
+    if let Err(e) = syscall.set_capability(CapSet::Ambient, &ambient) {
+        tracing::error!("failed to set ambient capabilities: {}", e);
     }
 
     Ok(())
@@ -610,7 +612,28 @@ mod tests {
                     (CapSet::Effective, cps.clone()),
                     (CapSet::Permitted, cps.clone()),
                     (CapSet::Inheritable, cps.clone()),
-                    (CapSet::Ambient, cps),
+                    (CapSet::Ambient, cps.clone()),
+                ],
+            },
+            Testcase {
+                name: format!("no ambient capabilities with caps: {cps:?}"),
+                input: {
+                    let mut input = LinuxCapabilitiesBuilder::default()
+                        .bounding(cps.clone().into_iter().collect::<Capabilities>())
+                        .effective(cps.clone().into_iter().collect::<Capabilities>())
+                        .inheritable(cps.clone().into_iter().collect::<Capabilities>())
+                        .permitted(cps.clone().into_iter().collect::<Capabilities>())
+                        .build()
+                        .unwrap();
+                    input.set_ambient(None);
+                    input
+                },
+                want: vec![
+                    (CapSet::Bounding, cps.clone()),
+                    (CapSet::Effective, cps.clone()),
+                    (CapSet::Permitted, cps.clone()),
+                    (CapSet::Inheritable, cps),
+                    (CapSet::Ambient, vec![]),
                 ],
             },
         ];
@@ -2,7 +2,7 @@ use std::fs;
 use std::io::{BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 
-use oci_spec::runtime::{Hooks, Spec};
+use oci_spec::runtime::{Hooks, LinuxIntelRdt, Spec};
 use serde::{Deserialize, Serialize};
 
 use crate::utils;
@@ -44,19 +44,21 @@ const YOUKI_CONFIG_NAME: &str = "youki_config.json";
 pub struct YoukiConfig {
     pub hooks: Option<Hooks>,
     pub cgroup_path: PathBuf,
+    #[serde(default)]
+    pub intel_rdt: Option<LinuxIntelRdt>,
+    /// Root filesystem of the container, relative to the bundle unless absolute
+    #[serde(default)]
+    pub rootfs: Option<PathBuf>,
 }
 
 impl<'a> YoukiConfig {
     pub fn from_spec(spec: &'a Spec, container_id: &str) -> Result<Self> {
+        let linux = spec.linux().as_ref().ok_or(ConfigError::MissingLinux)?;
         Ok(YoukiConfig {
             hooks: spec.hooks().clone(),
-            cgroup_path: utils::get_cgroup_path(
-                spec.linux()
-                    .as_ref()
-                    .ok_or(ConfigError::MissingLinux)?
-                    .cgroups_path(),
-                container_id,
-            ),
+            cgroup_path: utils::get_cgroup_path(linux.cgroups_path(), container_id),
+            intel_rdt: linux.intel_rdt().clone(),
+            rootfs: spec.root().as_ref().map(|root| root.path().to_owned()),
         })
     }
 
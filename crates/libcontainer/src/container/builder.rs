@@ -1,12 +1,18 @@
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
 use std::path::PathBuf;
+use std::rc::Rc;
 
 use super::init_builder::InitContainerBuilder;
+use super::lifecycle::{LifecycleObserver, Observers};
 use super::tenant_builder::TenantContainerBuilder;
 use crate::error::{ErrInvalidID, LibcontainerError};
 use crate::syscall::syscall::SyscallType;
 use crate::utils::PathBufExt;
 use crate::workload::{self, Executor};
 
+/// Directory in the root directory the compiled seccomp filters are cached in
+pub const SECCOMP_CACHE_DIR: &str = ".seccomp-cache";
+
 pub struct ContainerBuilder {
     /// Id of the container
     pub(super) container_id: String,
@@ -24,6 +30,33 @@ pub struct ContainerBuilder {
     /// The function that actually runs on the container init process. Default
     /// is to execute the specified command in the oci spec.
     pub(super) executor: Box<dyn Executor>,
+    /// Observers of the lifecycle of the container
+    pub(super) observers: Observers,
+    /// Flag indicating if the compiled seccomp filters are cached in the
+    /// root directory
+    pub(super) seccomp_cache: bool,
+    /// Flag indicating if entries of the environment which aren't
+    /// `NAME=value` are rejected instead of left out
+    pub(super) strict_env: bool,
+    /// Files the container process uses as stdio instead of the stdio of
+    /// the caller
+    pub(super) stdio: Stdio,
+}
+
+/// Files of the stdio of the container process. The container process
+/// inherits the respective stdio of the caller for the files which aren't
+/// set.
+#[derive(Debug, Default)]
+pub struct Stdio {
+    pub stdin: Option<OwnedFd>,
+    pub stdout: Option<OwnedFd>,
+    pub stderr: Option<OwnedFd>,
+}
+
+impl Stdio {
+    pub(super) fn raw_fds(&self) -> [Option<RawFd>; 3] {
+        [&self.stdin, &self.stdout, &self.stderr].map(|fd| fd.as_ref().map(|fd| fd.as_raw_fd()))
+    }
 }
 
 /// Builder that can be used to configure the common properties of
@@ -70,6 +103,10 @@ impl ContainerBuilder {
             console_socket: None,
             preserve_fds: 0,
             executor: workload::default::get_executor(),
+            observers: Observers::default(),
+            seccomp_cache: true,
+            strict_env: false,
+            stdio: Stdio::default(),
         }
     }
 
@@ -239,6 +276,33 @@ impl ContainerBuilder {
         self
     }
 
+    /// Sets the files the container process uses as stdin, stdout and
+    /// stderr, e.g. the fifos of a containerd shim. A console socket takes
+    /// precedence over them.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::fs::File;
+    /// # use libcontainer::container::builder::{ContainerBuilder, Stdio};
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .with_stdio(Stdio {
+    ///     stdout: Some(File::create("/tmp/74f1a4cb3801.log")?.into()),
+    ///     ..Default::default()
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_stdio(mut self, stdio: Stdio) -> Self {
+        self.stdio = stdio;
+        self
+    }
+
     /// Sets the function that actually runs on the container init process.
     /// # Example
     ///
@@ -257,6 +321,80 @@ impl ContainerBuilder {
         self.executor = Box::new(executor);
         self
     }
+
+    /// Registers an observer which is notified about the lifecycle
+    /// transitions of the container, see [`LifecycleObserver`]
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::container::{Container, LifecycleObserver};
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    /// struct CreationLogger;
+    ///
+    /// impl LifecycleObserver for CreationLogger {
+    ///     fn on_created(&self, container: &Container) {
+    ///         println!("created container {}", container.id());
+    ///     }
+    /// }
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .with_observer(CreationLogger);
+    /// ```
+    pub fn with_observer(mut self, observer: impl LifecycleObserver + 'static) -> Self {
+        self.observers.add(Rc::new(observer));
+        self
+    }
+
+    /// Sets if the BPF programs seccomp filters compile to are cached in the
+    /// root directory, so that the filter of a profile which has been used
+    /// before is loaded without compiling it again. Enabled by default.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .with_seccomp_cache(false);
+    /// ```
+    pub fn with_seccomp_cache(mut self, enabled: bool) -> Self {
+        self.seccomp_cache = enabled;
+        self
+    }
+
+    /// Sets if the creation fails for entries of the environment of the
+    /// process which aren't `NAME=value`, instead of leaving them out of the
+    /// environment with a warning. Disabled by default.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .with_strict_env(true);
+    /// ```
+    pub fn with_strict_env(mut self, strict: bool) -> Self {
+        self.strict_env = strict;
+        self
+    }
+
+    /// Returns the directory the compiled seccomp filters are cached in, if
+    /// caching is enabled
+    pub(super) fn seccomp_cache_dir(&self) -> Option<PathBuf> {
+        self.seccomp_cache
+            .then(|| self.root_path.join(SECCOMP_CACHE_DIR))
+    }
 }
 
 #[cfg(test)]
@@ -265,7 +403,7 @@ mod tests {
 
     use anyhow::{Context, Result};
 
-    use crate::container::builder::ContainerBuilder;
+    use crate::container::builder::{ContainerBuilder, Stdio};
     use crate::syscall::syscall::SyscallType;
 
     #[test]
@@ -334,4 +472,21 @@ mod tests {
         assert!(result.is_ok());
         Ok(())
     }
+
+    #[test]
+    fn test_with_stdio() -> Result<()> {
+        let tmp = tempfile::tempdir().context("failed to create temp dir")?;
+        let stdout = std::fs::File::create(tmp.path().join("stdout"))?;
+        let stdout_fd = std::os::fd::AsRawFd::as_raw_fd(&stdout);
+
+        let builder = ContainerBuilder::new("74f1a4cb3801".to_owned(), SyscallType::default());
+        assert_eq!(builder.stdio.raw_fds(), [None, None, None]);
+        let builder = builder.with_stdio(Stdio {
+            stdout: Some(stdout.into()),
+            ..Default::default()
+        });
+        assert_eq!(builder.stdio.raw_fds(), [None, Some(stdout_fd), None]);
+
+        Ok(())
+    }
 }
@@ -1,23 +1,27 @@
 use std::fs;
 use std::io::Write;
 use std::os::unix::prelude::RawFd;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use libcgroups::common::CgroupManager;
 use nix::unistd::Pid;
-use oci_spec::runtime::Spec;
+use oci_spec::runtime::{Linux, Spec};
 
+use super::pod_cgroup::PodCgroup;
 use super::{Container, ContainerStatus};
 use crate::error::{LibcontainerError, MissingSpecError};
 use crate::notify_socket::NotifyListener;
 use crate::process::args::{ContainerArgs, ContainerType};
-use crate::process::intel_rdt::delete_resctrl_subdirectory;
+use crate::process::container_intermediate_process::{
+    best_effort_controllers, CGROUP_LEAF_ANNOTATION,
+};
+use crate::process::intel_rdt::{delete_resctrl_subdirectory, leave_shared_group};
 use crate::process::{self};
 use crate::syscall::syscall::SyscallType;
 use crate::user_ns::UserNamespaceConfig;
 use crate::workload::Executor;
-use crate::{hooks, utils};
+use crate::{net_devices, utils};
 
 pub(super) struct ContainerBuilderImpl {
     /// Flag indicating if an init or a tenant container should be created
@@ -37,6 +41,8 @@ pub(super) struct ContainerBuilderImpl {
     pub pid_file: Option<PathBuf>,
     /// Socket to communicate the file descriptor of the ptty
     pub console_socket: Option<RawFd>,
+    /// Files used as stdin, stdout and stderr of the container process
+    pub stdio: [Option<RawFd>; 3],
     /// Options for new user namespace
     pub user_ns_config: Option<UserNamespaceConfig>,
     /// Path to the Unix Domain Socket to communicate container start
@@ -45,10 +51,23 @@ pub(super) struct ContainerBuilderImpl {
     pub container: Option<Container>,
     /// File descriptos preserved/passed to the container init process.
     pub preserve_fds: i32,
+    /// Number of fds passed by systemd socket activation
+    pub listen_fds: i32,
     /// If the container is to be run in detached mode
     pub detached: bool,
+    /// Flag indicating if the rootfs should be entered with chroot instead
+    /// of pivot_root
+    pub no_pivot: bool,
+    /// Flag indicating if the container should inherit the session keyring
+    /// instead of creating a new one
+    pub no_new_keyring: bool,
     /// Default executes the specified execution of a generic command
     pub executor: Box<dyn Executor>,
+    /// Directory the compiled seccomp filters are cached in, if they are
+    /// cached
+    pub seccomp_cache: Option<PathBuf>,
+    /// Pod the cgroup of the container is nested in
+    pub pod_cgroup: Option<PodCgroup>,
 }
 
 impl ContainerBuilderImpl {
@@ -74,6 +93,13 @@ impl ContainerBuilderImpl {
             cgroup_path: cgroups_path,
             systemd_cgroup: self.use_systemd || self.user_ns_config.is_some(),
             container_name: self.container_id.to_owned(),
+            leaf_cgroup: self
+                .spec
+                .annotations()
+                .as_ref()
+                .and_then(|a| a.get(CGROUP_LEAF_ANNOTATION))
+                .cloned(),
+            best_effort_controllers: best_effort_controllers(self.spec.annotations().as_ref()),
         };
         let process = self
             .spec
@@ -81,16 +107,6 @@ impl ContainerBuilderImpl {
             .as_ref()
             .ok_or(MissingSpecError::Process)?;
 
-        if matches!(self.container_type, ContainerType::InitContainer) {
-            if let Some(hooks) = self.spec.hooks() {
-                hooks::run_hooks(
-                    hooks.create_runtime().as_ref(),
-                    self.container.as_ref(),
-                    None,
-                )?
-            }
-        }
-
         // Need to create the notify socket before we pivot root, since the unix
         // domain socket used here is outside of the rootfs of container. During
         // exec, need to create the socket before we enter into existing mount
@@ -99,6 +115,14 @@ impl ContainerBuilderImpl {
         // root can access.
         let notify_listener = NotifyListener::new(&self.notify_path)?;
 
+        // the membership is recorded before the cgroup of the container is
+        // created in the pod, so that the pod cgroup isn't removed meanwhile
+        // by the failure of another member
+        let first_in_pod = match self.pod_cgroup_root() {
+            Some((pod_cgroup, root_path)) => pod_cgroup.join(root_path, &self.container_id)?,
+            None => false,
+        };
+
         // If Out-of-memory score adjustment is set in specification.  set the score
         // value for the current process check
         // https://dev.to/rrampage/surviving-the-linux-oom-killer-2ki9 for some more
@@ -147,13 +171,18 @@ impl ContainerBuilderImpl {
             spec: Rc::clone(&self.spec),
             rootfs: self.rootfs.to_owned(),
             console_socket: self.console_socket,
+            stdio: self.stdio,
             notify_listener,
             preserve_fds: self.preserve_fds,
+            listen_fds: self.listen_fds,
             container: self.container.to_owned(),
             user_ns_config: self.user_ns_config.to_owned(),
             cgroup_config,
             detached: self.detached,
+            no_pivot: self.no_pivot,
+            no_new_keyring: self.no_new_keyring,
             executor: self.executor.clone(),
+            seccomp_program: self.seccomp_program(linux),
         };
 
         let (init_pid, need_to_clean_up_intel_rdt_dir) =
@@ -164,6 +193,12 @@ impl ContainerBuilderImpl {
                 },
             )?;
 
+        if first_in_pod {
+            if let Some(pod_cgroup) = &self.pod_cgroup {
+                pod_cgroup.apply(&self.container_id)?;
+            }
+        }
+
         // if file to write the pid to is specified, write pid of the child
         if let Some(pid_file) = &self.pid_file {
             fs::write(pid_file, format!("{init_pid}")).map_err(|err| {
@@ -185,6 +220,36 @@ impl ContainerBuilderImpl {
         Ok(init_pid)
     }
 
+    /// Loads the program the seccomp filter compiles to from the cache, so
+    /// that the container process doesn't have to compile it. Failures to
+    /// use the cache only fall back to compiling the filter.
+    fn seccomp_program(&self, linux: &Linux) -> Option<Vec<u8>> {
+        let dir = self.seccomp_cache.as_ref()?;
+        let seccomp = linux.seccomp().as_ref()?;
+        #[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
+        {
+            match crate::seccomp::cache::load_or_compile(dir, seccomp) {
+                Ok(program) => program,
+                Err(err) => {
+                    tracing::warn!(?err, "failed to use the seccomp cache");
+                    None
+                }
+            }
+        }
+        #[cfg(not(any(feature = "libseccomp", feature = "seccomp-bpf")))]
+        {
+            let _ = (dir, seccomp);
+            None
+        }
+    }
+
+    /// Returns the pod of the container along with the root directory its
+    /// members are recorded in
+    fn pod_cgroup_root(&self) -> Option<(&PodCgroup, &Path)> {
+        let root_path = self.container.as_ref()?.root.parent()?;
+        Some((self.pod_cgroup.as_ref()?, root_path))
+    }
+
     fn cleanup_container(&self) -> Result<(), LibcontainerError> {
         let linux = self.spec.linux().as_ref().ok_or(MissingSpecError::Linux)?;
         let cgroups_path = utils::get_cgroup_path(linux.cgroups_path(), &self.container_id);
@@ -193,6 +258,8 @@ impl ContainerBuilderImpl {
                 cgroup_path: cgroups_path,
                 systemd_cgroup: self.use_systemd || self.user_ns_config.is_some(),
                 container_name: self.container_id.to_string(),
+                leaf_cgroup: None,
+                best_effort_controllers: Vec::new(),
             })?;
 
         let mut errors = Vec::new();
@@ -200,6 +267,13 @@ impl ContainerBuilderImpl {
         if let Err(e) = cmanager.remove() {
             tracing::error!(error = ?e, "failed to remove cgroup manager");
             errors.push(e.to_string());
+        } else if let Some((pod_cgroup, root_path)) = self.pod_cgroup_root() {
+            // the container stays a member while its cgroup is left in the
+            // pod, which keeps the pod cgroup from being removed
+            if let Err(e) = pod_cgroup.leave(root_path, &self.container_id) {
+                tracing::error!(pod = ?pod_cgroup.path(), error = ?e, "failed to leave pod cgroup");
+                errors.push(e.to_string());
+            }
         }
 
         if let Some(container) = &self.container {
@@ -209,6 +283,21 @@ impl ContainerBuilderImpl {
                     errors.push(e.to_string());
                 }
             }
+            let clos_id = linux
+                .intel_rdt()
+                .as_ref()
+                .and_then(|rdt| rdt.clos_id().as_ref());
+            if let (Some(clos_id), Some(root_path)) = (clos_id, container.root.parent()) {
+                if let Err(e) = leave_shared_group(root_path, clos_id, container.id()) {
+                    tracing::error!(id = ?container.id(), error = ?e, "failed to leave shared resctrl group");
+                    errors.push(e.to_string());
+                }
+            }
+
+            if let Err(e) = net_devices::restore(&container.root) {
+                tracing::error!(error = ?e, "failed to restore network devices");
+                errors.push(e.to_string());
+            }
 
             if container.root.exists() {
                 if let Err(e) = fs::remove_dir_all(&container.root) {
@@ -0,0 +1,199 @@
+//! Guard against cgroups and transient systemd units outliving their
+//! containers. They are removed by the deletion of a container, which needs
+//! its state directory though. If the directory is gone, e.g. because it has
+//! been removed by hand or the runtime was killed while removing it, the
+//! cgroup and the unit would be leaked, which slowly adds up on busy nodes.
+//!
+//! The cgroup of every container is recorded in `<root>/.cgroups/<id>` once
+//! the state directory of the container exists, and the record is removed
+//! together with the cgroup. [`collect_garbage`] removes the cgroups of the
+//! records whose container has no state directory anymore.
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use libcgroups::common::CgroupManager;
+use nix::fcntl::{Flock, FlockArg};
+use serde::{Deserialize, Serialize};
+
+use crate::error::LibcontainerError;
+
+/// Directory in the root directory which the cgroups of the containers are
+/// recorded in
+pub const CGROUP_GUARD_DIR: &str = ".cgroups";
+
+/// Record of the cgroup of a container
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CgroupGuard {
+    container_id: String,
+    cgroup_path: PathBuf,
+    use_systemd: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    systemd_unit: Option<String>,
+}
+
+impl CgroupGuard {
+    pub(super) fn new(container_id: &str, cgroup_path: &Path, use_systemd: bool) -> Self {
+        let systemd_unit = match use_systemd {
+            true => libcgroups::systemd::manager::unit_name(cgroup_path),
+            false => None,
+        };
+        Self {
+            container_id: container_id.to_owned(),
+            cgroup_path: cgroup_path.to_owned(),
+            use_systemd,
+            systemd_unit,
+        }
+    }
+
+    pub fn container_id(&self) -> &str {
+        &self.container_id
+    }
+
+    pub fn cgroup_path(&self) -> &Path {
+        &self.cgroup_path
+    }
+
+    /// Name of the transient unit of the container, if its cgroup is managed
+    /// by systemd
+    pub fn systemd_unit(&self) -> Option<&str> {
+        self.systemd_unit.as_deref()
+    }
+
+    pub(super) fn record(&self, root_path: &Path) -> Result<(), LibcontainerError> {
+        let _lock = lock_guards(root_path)?;
+        let data = serde_json::to_vec(self).map_err(LibcontainerError::OtherSerialization)?;
+        fs::write(
+            root_path.join(CGROUP_GUARD_DIR).join(&self.container_id),
+            data,
+        )
+        .map_err(LibcontainerError::OtherIO)?;
+        tracing::debug!(
+            container_id = %self.container_id,
+            cgroup_path = ?self.cgroup_path,
+            "recorded cgroup"
+        );
+
+        Ok(())
+    }
+
+    fn remove_cgroup(&self) -> Result<(), LibcontainerError> {
+        let cmanager =
+            libcgroups::common::create_cgroup_manager(libcgroups::common::CgroupConfig {
+                cgroup_path: self.cgroup_path.clone(),
+                systemd_cgroup: self.use_systemd,
+                container_name: self.container_id.clone(),
+                leaf_cgroup: None,
+                best_effort_controllers: Vec::new(),
+            })?;
+        cmanager.remove()?;
+
+        Ok(())
+    }
+}
+
+/// Removes the record of the cgroup of a container, once the cgroup has been
+/// removed
+pub(super) fn release(root_path: &Path, container_id: &str) -> Result<(), LibcontainerError> {
+    let _lock = lock_guards(root_path)?;
+    match fs::remove_file(root_path.join(CGROUP_GUARD_DIR).join(container_id)) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(LibcontainerError::OtherIO(err)),
+    }
+}
+
+/// Removes the cgroups, and stops the transient units, of the containers in
+/// the root directory whose state directory is gone. Returns the records of
+/// the removed cgroups. A cgroup which can't be removed is kept recorded, so
+/// that it is retried by the next collection.
+pub fn collect_garbage(root_path: &Path) -> Result<Vec<CgroupGuard>, LibcontainerError> {
+    let _lock = lock_guards(root_path)?;
+    let dir = root_path.join(CGROUP_GUARD_DIR);
+    let mut collected = Vec::new();
+
+    for entry in fs::read_dir(&dir).map_err(LibcontainerError::OtherIO)? {
+        let path = entry.map_err(LibcontainerError::OtherIO)?.path();
+        let guard: CgroupGuard = match fs::read(&path)
+            .map_err(LibcontainerError::OtherIO)
+            .and_then(|data| {
+                serde_json::from_slice(&data).map_err(LibcontainerError::OtherSerialization)
+            }) {
+            Ok(guard) => guard,
+            Err(err) => {
+                tracing::warn!(?path, "skipping unreadable cgroup record: {err}");
+                continue;
+            }
+        };
+        if root_path.join(&guard.container_id).exists() {
+            continue;
+        }
+
+        tracing::info!(
+            container_id = %guard.container_id,
+            cgroup_path = ?guard.cgroup_path,
+            systemd_unit = ?guard.systemd_unit,
+            "removing the cgroup of a container without state"
+        );
+        if let Err(err) = guard.remove_cgroup() {
+            tracing::warn!(
+                container_id = %guard.container_id,
+                "failed to remove leaked cgroup: {err}"
+            );
+            continue;
+        }
+        fs::remove_file(&path).map_err(LibcontainerError::OtherIO)?;
+        collected.push(guard);
+    }
+
+    Ok(collected)
+}
+
+fn lock_guards(root_path: &Path) -> Result<Flock<File>, LibcontainerError> {
+    let dir = root_path.join(CGROUP_GUARD_DIR);
+    fs::create_dir_all(&dir).map_err(LibcontainerError::OtherIO)?;
+    let dir = File::open(&dir).map_err(LibcontainerError::OtherIO)?;
+    Flock::lock(dir, FlockArg::LockExclusive)
+        .map_err(|(_, err)| LibcontainerError::OtherSyscall(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_systemd_unit() {
+        let guard = CgroupGuard::new("c1", Path::new(":youki:c1"), false);
+        assert_eq!(guard.systemd_unit(), None);
+        let guard = CgroupGuard::new("c1", Path::new(":youki:c1"), true);
+        if cfg!(feature = "systemd") {
+            assert_eq!(guard.systemd_unit(), Some("youki-c1.scope"));
+        }
+    }
+
+    #[test]
+    fn test_collect_garbage() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let kept = CgroupGuard::new("kept", Path::new("/youki-test/kept"), false);
+        let leaked = CgroupGuard::new("leaked", Path::new("/youki-test/leaked"), false);
+        fs::create_dir(root.path().join("kept"))?;
+        kept.record(root.path())?;
+        leaked.record(root.path())?;
+
+        // the cgroups don't exist, so there is nothing to remove
+        assert_eq!(collect_garbage(root.path())?, vec![leaked]);
+        let dir = root.path().join(CGROUP_GUARD_DIR);
+        assert!(dir.join("kept").exists());
+        assert!(!dir.join("leaked").exists());
+
+        release(root.path(), "kept")?;
+        assert!(!dir.join("kept").exists());
+        // releasing twice, e.g. when a forced delete is repeated, does nothing
+        release(root.path(), "kept")?;
+        assert!(collect_garbage(root.path())?.is_empty());
+        Ok(())
+    }
+}
@@ -2,14 +2,20 @@ use std::collections::HashMap;
 use std::ffi::OsString;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use chrono::{DateTime, Utc};
+use libcgroups::common::AnyCgroupManager;
 use nix::unistd::Pid;
 use procfs::process::Process;
 
+use super::lifecycle::{LifecycleObserver, Observers};
+use super::PageServer;
 use crate::config::YoukiConfig;
-use crate::container::{ContainerStatus, State};
+use crate::container::{ContainerStatus, State, StateLock};
+use crate::cpu_bandwidth::CpuBandwidth;
 use crate::error::LibcontainerError;
+use crate::pidfd;
 use crate::syscall::syscall::create_syscall;
 
 /// Structure representing the container data
@@ -19,6 +25,8 @@ pub struct Container {
     pub state: State,
     // indicated the directory for the root path in the container
     pub root: PathBuf,
+    // observers of the lifecycle transitions performed through this instance
+    pub(crate) observers: Observers,
 }
 
 impl Default for Container {
@@ -26,6 +34,7 @@ impl Default for Container {
         Self {
             state: State::default(),
             root: PathBuf::from("/run/youki"),
+            observers: Observers::default(),
         }
     }
 }
@@ -51,6 +60,7 @@ impl Container {
         Ok(Self {
             state,
             root: container_root,
+            observers: Observers::default(),
         })
     }
 
@@ -95,11 +105,25 @@ impl Container {
         self.state.pid.map(Pid::from_raw)
     }
 
+    /// Sets the pid of the container process, whose start time is recorded
+    /// as well, so that a reuse of the pid can be detected later
     pub fn set_pid(&mut self, pid: i32) -> &mut Self {
         self.state.pid = Some(pid);
+        self.state.init_process_start_time = pidfd::start_time(Pid::from_raw(pid));
         self
     }
 
+    /// Returns whether the process with the pid of the container process is
+    /// still the container process, and not a process which reused its pid
+    pub(crate) fn is_init_process(&self, pid: Pid) -> bool {
+        match self.state.init_process_start_time {
+            Some(start_time) => pidfd::start_time(pid) == Some(start_time),
+            // the start time is not known for containers created by older
+            // versions
+            None => true,
+        }
+    }
+
     pub fn created(&self) -> Option<DateTime<Utc>> {
         self.state.created
     }
@@ -125,6 +149,18 @@ impl Container {
         self.state.use_systemd
     }
 
+    /// Exit status of the init process, once its exit has been recorded
+    pub fn exit_status(&self) -> Option<i32> {
+        self.state.exit_status
+    }
+
+    /// Whether processes of the container have been killed by the out of
+    /// memory killer, as far as it has been recorded, see
+    /// [`update_oom_killed`](Container::update_oom_killed)
+    pub fn oom_killed(&self) -> bool {
+        self.state.oom_killed
+    }
+
     pub fn set_systemd(&mut self, should_use: bool) -> &mut Self {
         self.state.use_systemd = should_use;
         self
@@ -139,6 +175,45 @@ impl Container {
         self.state.clean_up_intel_rdt_subdirectory
     }
 
+    /// Path of the cgroup of the pod the container is a member of, see
+    /// [`PodCgroup`](super::PodCgroup)
+    pub fn pod_cgroup(&self) -> Option<&Path> {
+        self.state.pod_cgroup.as_deref()
+    }
+
+    pub fn set_pod_cgroup(&mut self, pod_cgroup: Option<PathBuf>) -> &mut Self {
+        self.state.pod_cgroup = pod_cgroup;
+        self
+    }
+
+    /// Name of the transient systemd unit of the container, if its cgroup is
+    /// managed by systemd
+    pub fn systemd_unit(&self) -> Option<&str> {
+        self.state.systemd_unit.as_deref()
+    }
+
+    pub fn set_systemd_unit(&mut self, systemd_unit: Option<String>) -> &mut Self {
+        self.state.systemd_unit = systemd_unit;
+        self
+    }
+
+    /// Cpu period and quota of the cgroup of the container, after rounding
+    pub fn cpu_bandwidth(&self) -> Option<CpuBandwidth> {
+        self.state.cpu_bandwidth
+    }
+
+    pub fn set_cpu_bandwidth(&mut self, cpu_bandwidth: Option<CpuBandwidth>) -> &mut Self {
+        self.state.cpu_bandwidth = cpu_bandwidth;
+        self
+    }
+
+    /// Registers an observer which is notified about the lifecycle
+    /// transitions performed through this instance of the container
+    pub fn add_observer(&mut self, observer: impl LifecycleObserver + 'static) -> &mut Self {
+        self.observers.add(Rc::new(observer));
+        self
+    }
+
     pub fn status(&self) -> ContainerStatus {
         self.state.status
     }
@@ -161,7 +236,10 @@ impl Container {
                 // Note that Process::new does not spawn a new process
                 // but instead creates a new Process structure, and fill
                 // it with information about the process with given pid
-                if let Ok(proc) = Process::new(pid.as_raw()) {
+                let proc = Process::new(pid.as_raw())
+                    .ok()
+                    .filter(|_| self.is_init_process(pid));
+                if let Some(proc) = proc {
                     use procfs::process::ProcState;
 
                     match proc.stat()?.state()? {
@@ -191,16 +269,75 @@ impl Container {
         Ok(self)
     }
 
+    /// Loads the container with the given id from the state directory
+    /// `root_path`, which has to be the root path the container was created
+    /// with
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::Container;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let container = Container::load_from_root("/run/youki", "74f1a4cb3801")?;
+    /// println!("{:?}", container.status());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_from_root<P: AsRef<Path>>(
+        root_path: P,
+        container_id: &str,
+    ) -> Result<Self, LibcontainerError> {
+        let root_path = root_path.as_ref();
+        let root_path = fs::canonicalize(root_path).map_err(|err| {
+            LibcontainerError::InvalidInput(format!("invalid root path {root_path:?}: {err:?}"))
+        })?;
+        let container_root = root_path.join(container_id);
+        if !container_root.exists() {
+            tracing::error!(id = ?container_id, dir = ?container_root, "container does not exist");
+            return Err(LibcontainerError::NoDirectory);
+        }
+
+        Self::load(container_root)
+    }
+
     pub fn load(container_root: PathBuf) -> Result<Self, LibcontainerError> {
         let state = State::load(&container_root)?;
         let mut container = Self {
             state,
             root: container_root,
+            observers: Observers::default(),
         };
         container.refresh_status()?;
         Ok(container)
     }
 
+    /// Takes the lock of the container state and reloads the state, so that
+    /// a state transition is based on the latest state and isn't interleaved
+    /// with the transitions of other processes
+    pub(crate) fn lock_state(&mut self) -> Result<StateLock, LibcontainerError> {
+        let lock = State::lock(&self.root)?;
+        self.refresh_state()?;
+        self.refresh_status()?;
+        Ok(lock)
+    }
+
+    /// Removes the container state in `container_root` if it is orphaned,
+    /// i.e. the directory exists but the state file is missing or can't be
+    /// parsed, because the runtime crashed while the container was created.
+    /// Returns true if the state has been removed.
+    pub fn remove_stale(container_root: &Path) -> Result<bool, LibcontainerError> {
+        let _lock = State::lock(container_root)?;
+        if State::load(container_root).is_ok() {
+            return Ok(false);
+        }
+
+        tracing::warn!(?container_root, "removing orphaned container state");
+        super::container_keep::release_mount_namespace(container_root);
+        fs::remove_dir_all(container_root).map_err(LibcontainerError::OtherIO)?;
+        Ok(true)
+    }
+
     pub fn save(&self) -> Result<(), LibcontainerError> {
         tracing::debug!("Save container status: {:?} in {:?}", self, self.root);
         self.state.save(&self.root)?;
@@ -212,6 +349,20 @@ impl Container {
         let spec = YoukiConfig::load(&self.root)?;
         Ok(spec)
     }
+
+    /// Creates a manager for the cgroup of the container, e.g. to update its
+    /// resources or to query the processes running in it
+    pub fn cgroup_manager(&self) -> Result<AnyCgroupManager, LibcontainerError> {
+        let cgroup_manager =
+            libcgroups::common::create_cgroup_manager(libcgroups::common::CgroupConfig {
+                cgroup_path: self.spec()?.cgroup_path,
+                systemd_cgroup: self.systemd(),
+                container_name: self.id().to_string(),
+                leaf_cgroup: None,
+                best_effort_controllers: Vec::new(),
+            })?;
+        Ok(cgroup_manager)
+    }
 }
 
 /// Checkpoint parameter structure
@@ -219,7 +370,34 @@ pub struct CheckpointOptions {
     pub ext_unix_sk: bool,
     pub file_locks: bool,
     pub image_path: PathBuf,
+    /// Dump only the state of the processes and serve their memory pages
+    /// from the page server, for restoring them lazily on another host
+    pub lazy_pages: bool,
     pub leave_running: bool,
+    /// Send the memory pages to a page server, or with lazy pages listen on
+    /// the address for the lazy pages daemon of the restoring host
+    pub page_server: Option<PageServer>,
+    pub shell_job: bool,
+    pub tcp_established: bool,
+    pub work_path: Option<PathBuf>,
+}
+
+/// Restore parameter structure
+pub struct RestoreOptions {
+    /// Deduplicate the images, i.e. punch the restored pages out of them
+    pub auto_dedup: bool,
+    /// Restore the container into an empty network namespace, which is set
+    /// up afterwards, instead of restoring its network configuration
+    pub empty_ns: bool,
+    pub ext_unix_sk: bool,
+    pub file_locks: bool,
+    pub image_path: PathBuf,
+    /// Restore the memory pages on demand through userfaultfd from a
+    /// `criu lazy-pages` daemon, which has to be started beforehand
+    pub lazy_pages: bool,
+    /// Let CRIU restore the cgroups which don't exist yet, in the soft mode
+    /// of CRIU. Otherwise the cgroups are left to youki.
+    pub manage_cgroups: bool,
     pub shell_job: bool,
     pub tcp_established: bool,
     pub work_path: Option<PathBuf>,
@@ -325,6 +503,28 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_remove_stale() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let container_root = tmp_dir.path().join("container");
+        fs::create_dir(&container_root)?;
+        Container::new(
+            "container",
+            ContainerStatus::Created,
+            None,
+            &PathBuf::from("."),
+            &container_root,
+        )?
+        .save()?;
+        assert!(!Container::remove_stale(&container_root)?);
+        assert!(container_root.exists());
+
+        fs::write(State::file_path(&container_root), "{\"ociVersion\":")?;
+        assert!(Container::remove_stale(&container_root)?);
+        assert!(!container_root.exists());
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_get_spec() -> Result<()> {
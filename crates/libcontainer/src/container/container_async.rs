@@ -0,0 +1,263 @@
+//! Async variants of the operations on containers, for callers running an
+//! async runtime such as tokio. Each operation runs on a thread of its own,
+//! so that the blocking syscalls it makes don't stall the runtime, and its
+//! result is awaited through a channel. The futures don't depend on any
+//! particular runtime.
+use std::os::fd::{AsFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use futures_channel::oneshot;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+
+use super::{Container, ExitInfo, State};
+use crate::error::LibcontainerError;
+use crate::pidfd::PidFd;
+use crate::signal::Signal;
+
+/// Handle of a container whose operations are awaited instead of blocking
+/// the caller. As a [`Container`] can't be moved between threads, the handle
+/// only refers to the container by its id, which each operation loads the
+/// container by. Observers registered for the container aren't notified
+/// about the operations.
+///
+/// # Example
+///
+/// ```no_run
+/// use libcontainer::container::builder::ContainerBuilder;
+/// use libcontainer::container::AsyncContainer;
+/// use libcontainer::syscall::syscall::SyscallType;
+///
+/// # async fn run() -> Result<(), libcontainer::error::LibcontainerError> {
+/// let container = AsyncContainer::create(|| {
+///     ContainerBuilder::new("74f1a4cb3801".to_owned(), SyscallType::default())
+///         .with_root_path("/run/my-runtime")?
+///         .as_init("/var/lib/my-runtime/bundle")
+///         .with_detach(false)
+///         .build()
+/// })
+/// .await?;
+/// container.start().await?;
+/// let exit = container.wait().await?;
+/// println!("exited with {}", exit.status());
+/// container.delete(false).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsyncContainer {
+    root_path: PathBuf,
+    id: String,
+}
+
+impl AsyncContainer {
+    /// Refers to an existing container of the root path
+    pub fn new<P: Into<PathBuf>>(root_path: P, id: &str) -> Self {
+        Self {
+            root_path: root_path.into(),
+            id: id.to_owned(),
+        }
+    }
+
+    /// Creates a container by running `build`, which usually builds it with
+    /// a [`ContainerBuilder`](super::builder::ContainerBuilder). The builder
+    /// is set up on the thread running the creation, as it can't be moved
+    /// between threads either.
+    pub async fn create<F>(build: F) -> Result<Self, LibcontainerError>
+    where
+        F: FnOnce() -> Result<Container, LibcontainerError> + Send + 'static,
+    {
+        run_blocking("create", move || {
+            let container = build()?;
+            let root_path = container
+                .root
+                .parent()
+                .map(Path::to_owned)
+                .unwrap_or_default();
+            Ok(Self::new(root_path, container.id()))
+        })
+        .await
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
+    }
+
+    /// Loads the current state of the container
+    pub async fn state(&self) -> Result<State, LibcontainerError> {
+        self.with_container("state", |container| Ok(container.state.clone()))
+            .await
+    }
+
+    pub async fn start(&self) -> Result<(), LibcontainerError> {
+        self.with_container("start", |container| container.start())
+            .await
+    }
+
+    pub async fn kill<S: Into<Signal>>(
+        &self,
+        signal: S,
+        all: bool,
+    ) -> Result<(), LibcontainerError> {
+        let signal = signal.into();
+        self.with_container("kill", move |container| container.kill(signal, all))
+            .await
+    }
+
+    pub async fn delete(&self, force: bool) -> Result<(), LibcontainerError> {
+        self.with_container("delete", move |container| container.delete(force))
+            .await
+    }
+
+    /// Waits for the init process of the container to exit, see
+    /// [`Container::wait`]. Dropping the future stops the wait, which leaves
+    /// the container as it is. On kernels without pidfds, i.e. older than
+    /// 5.3, the thread waiting for the process only stops once it has exited.
+    pub async fn wait(&self) -> Result<ExitInfo, LibcontainerError> {
+        // the thread is woken up by the hang up of the pipe once the future,
+        // which holds the write end, is dropped
+        let (cancel, cancel_guard) =
+            nix::unistd::pipe().map_err(LibcontainerError::OtherSyscall)?;
+        let result = self
+            .with_container("wait", move |container| {
+                let pid = container.pid().ok_or(LibcontainerError::Other(
+                    "container process pid not found in state".into(),
+                ))?;
+                // a process which has exited already can't be opened anymore
+                // once it has been reaped, which the wait reports
+                if let Ok(Some(pidfd)) = PidFd::open(pid) {
+                    if !wait_exit_or_cancel(&pidfd, &cancel)
+                        .map_err(LibcontainerError::OtherSyscall)?
+                    {
+                        tracing::debug!(id = ?container.id(), "wait for container cancelled");
+                        return Err(LibcontainerError::Other("wait cancelled".into()));
+                    }
+                }
+                container.wait()
+            })
+            .await;
+        drop(cancel_guard);
+        result
+    }
+
+    async fn with_container<T, F>(&self, op: &str, f: F) -> Result<T, LibcontainerError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Container) -> Result<T, LibcontainerError> + Send + 'static,
+    {
+        let root_path = self.root_path.clone();
+        let id = self.id.clone();
+        run_blocking(op, move || {
+            let mut container = Container::load_from_root(root_path, &id)?;
+            f(&mut container)
+        })
+        .await
+    }
+}
+
+async fn run_blocking<T, F>(op: &str, f: F) -> Result<T, LibcontainerError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, LibcontainerError> + Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    thread::Builder::new()
+        .name(format!("youki-{op}"))
+        .spawn(move || {
+            // the receiver is gone if the future has been dropped
+            let _ = sender.send(f());
+        })
+        .map_err(LibcontainerError::OtherIO)?;
+
+    receiver.await.unwrap_or_else(|_| {
+        tracing::error!(op, "thread running container operation panicked");
+        Err(LibcontainerError::Other(format!(
+            "failed to {op} container"
+        )))
+    })
+}
+
+/// Waits until the process has exited or the write end of the pipe is closed,
+/// and returns whether the process has exited
+fn wait_exit_or_cancel(pidfd: &PidFd, cancel: &OwnedFd) -> nix::Result<bool> {
+    let mut fds = [
+        PollFd::new(pidfd.as_fd(), PollFlags::POLLIN),
+        PollFd::new(cancel.as_fd(), PollFlags::POLLIN),
+    ];
+    loop {
+        match poll(&mut fds, PollTimeout::NONE) {
+            Ok(_) => break,
+            Err(Errno::EINTR) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(fds[0].any().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use anyhow::Result;
+    use futures_executor::block_on;
+    use nix::unistd::Pid;
+
+    use super::*;
+    use crate::container::ContainerStatus;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn test_missing_container() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let container = AsyncContainer::new(root.path(), "missing");
+        let err = block_on(container.start()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ContainerNotFound);
+        let err = block_on(container.wait()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ContainerNotFound);
+        Ok(())
+    }
+
+    #[test]
+    fn test_create() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let container_root = root.path().join("c1");
+        std::fs::create_dir(&container_root)?;
+        let state = State::new("c1", ContainerStatus::Stopped, None, "/bundle".into());
+        state.save(&container_root)?;
+
+        let container = block_on(AsyncContainer::create(move || {
+            Container::load(container_root)
+        }))?;
+        assert_eq!(container, AsyncContainer::new(root.path(), "c1"));
+        assert_eq!(block_on(container.state())?.id, "c1");
+
+        let err = block_on(AsyncContainer::create(|| Err(LibcontainerError::Exist))).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ContainerExists);
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_exit_or_cancel() -> Result<()> {
+        let mut child = Command::new("sleep").arg("10").spawn()?;
+        let pidfd = match PidFd::open(Pid::from_raw(child.id() as i32))? {
+            Some(pidfd) => pidfd,
+            // kernels older than 5.3
+            None => return Ok(()),
+        };
+
+        let (cancel, cancel_guard) = nix::unistd::pipe()?;
+        drop(cancel_guard);
+        assert!(!wait_exit_or_cancel(&pidfd, &cancel)?);
+
+        let (cancel, _cancel_guard) = nix::unistd::pipe()?;
+        child.kill()?;
+        assert!(wait_exit_or_cancel(&pidfd, &cancel)?);
+        child.wait()?;
+        Ok(())
+    }
+}
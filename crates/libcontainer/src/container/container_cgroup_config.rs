@@ -0,0 +1,120 @@
+//! Persistence of the effective cgroup configuration of a container, i.e. all
+//! cgroup files written when it was created or updated, see
+//! [`CgroupCheckpoint`]
+use std::io::ErrorKind;
+use std::path::Path;
+use std::{fs, process};
+
+use libcgroups::checkpoint::CgroupCheckpoint;
+
+use super::Container;
+use crate::error::LibcontainerError;
+
+/// File with the cgroup configuration in the state directory of a container
+/// and in the image directory of a checkpoint
+pub const CGROUP_CONFIG_FILE: &str = "cgroup.json";
+
+/// Loads the cgroup configuration stored in the directory, which is empty if
+/// none has been stored
+pub(crate) fn load_cgroup_config(dir: &Path) -> Result<CgroupCheckpoint, LibcontainerError> {
+    let path = dir.join(CGROUP_CONFIG_FILE);
+    match fs::read(&path) {
+        Ok(content) => serde_json::from_slice(&content).map_err(|err| {
+            tracing::error!(?path, %err, "failed to parse cgroup configuration");
+            LibcontainerError::OtherSerialization(err)
+        }),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(CgroupCheckpoint::default()),
+        Err(err) => {
+            tracing::error!(?path, %err, "failed to read cgroup configuration");
+            Err(LibcontainerError::OtherIO(err))
+        }
+    }
+}
+
+/// Stores the cgroup configuration in the directory, the file is replaced
+/// atomically the same as the state
+pub(crate) fn save_cgroup_config(
+    dir: &Path,
+    config: &CgroupCheckpoint,
+) -> Result<(), LibcontainerError> {
+    let path = dir.join(CGROUP_CONFIG_FILE);
+    let tmp_path = dir.join(format!(".{}.{}", CGROUP_CONFIG_FILE, process::id()));
+    let content = serde_json::to_vec(config).map_err(LibcontainerError::OtherSerialization)?;
+    let result = fs::write(&tmp_path, content).and_then(|_| fs::rename(&tmp_path, &path));
+    if let Err(err) = result {
+        tracing::error!(?path, %err, "failed to write cgroup configuration");
+        let _ = fs::remove_file(&tmp_path);
+        return Err(LibcontainerError::OtherIO(err));
+    }
+
+    Ok(())
+}
+
+impl Container {
+    /// Returns the effective cgroup configuration of the container
+    pub fn cgroup_config(&self) -> Result<CgroupCheckpoint, LibcontainerError> {
+        load_cgroup_config(&self.root)
+    }
+
+    /// Adds the cgroup files written by an update of the resources, which
+    /// has to be recorded with [`CgroupCheckpoint::record`], to the cgroup
+    /// configuration of the container
+    pub fn record_cgroup_update(&self, update: CgroupCheckpoint) -> Result<(), LibcontainerError> {
+        let mut config = self.cgroup_config()?;
+        config.extend(update);
+        save_cgroup_config(&self.root, &config)
+    }
+
+    /// Writes the cgroup configuration of the container again, so that the
+    /// resources survive e.g. a reboot of the host after which the processes
+    /// are restored by an external tool
+    pub fn reapply_cgroup_config(&self) -> Result<(), LibcontainerError> {
+        let config = self.cgroup_config()?;
+        config.replay().map_err(|err| {
+            tracing::error!(id = ?self.id(), ?err, "failed to re-apply cgroup configuration");
+            LibcontainerError::OtherCgroup(err.to_string())
+        })?;
+
+        tracing::debug!(id = ?self.id(), writes = config.writes.len(), "re-applied cgroup configuration");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use anyhow::Result;
+    use libcgroups::checkpoint::CgroupWrite;
+
+    use super::*;
+
+    #[test]
+    fn test_record_cgroup_update() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let container = Container {
+            root: tmp.path().to_owned(),
+            ..Default::default()
+        };
+        assert!(container.cgroup_config()?.writes.is_empty());
+
+        let write = |value: &str| CgroupWrite {
+            path: PathBuf::from("/sys/fs/cgroup/a/pids.max"),
+            value: value.to_owned(),
+        };
+        save_cgroup_config(
+            tmp.path(),
+            &CgroupCheckpoint {
+                writes: vec![write("10")],
+            },
+        )?;
+        container.record_cgroup_update(CgroupCheckpoint {
+            writes: vec![write("20")],
+        })?;
+        assert_eq!(
+            container.cgroup_config()?.writes,
+            vec![write("10"), write("20")]
+        );
+        Ok(())
+    }
+}
@@ -1,23 +1,155 @@
 use std::fs::{self, File};
 use std::io::Write;
 use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use libcgroups::common::CgroupSetup::{Hybrid, Legacy};
 #[cfg(feature = "v1")]
 use libcgroups::common::DEFAULT_CGROUP_ROOT;
 use oci_spec::runtime::Spec;
 
-use super::{Container, ContainerStatus};
+use super::{save_cgroup_config, Container, ContainerStatus};
 use crate::container::container::CheckpointOptions;
 use crate::error::LibcontainerError;
 
 const CRIU_CHECKPOINT_LOG_FILE: &str = "dump.log";
 const DESCRIPTORS_JSON: &str = "descriptors.json";
+const CRIU_CONFIG_FILE: &str = "criu.conf";
+// evaluated by CRIU in RPC mode, as long as the RPC request names no
+// configuration file itself
+const CRIU_CONFIG_FILE_ENV: &str = "CRIU_CONFIG_FILE";
 
 #[derive(thiserror::Error, Debug)]
 pub enum CheckpointError {
     #[error("criu error: {0}")]
     CriuError(String),
+    #[error("invalid page server {0:?}, expected <address>:<port>")]
+    InvalidPageServer(String),
+    #[error("lazy pages require the address of a page server")]
+    MissingPageServer,
+}
+
+/// Address of a CRIU page server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageServer {
+    pub address: String,
+    pub port: u16,
+}
+
+impl FromStr for PageServer {
+    type Err = CheckpointError;
+
+    /// Parses `<address>:<port>`, where an IPv6 address may be in brackets
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CheckpointError::InvalidPageServer(s.to_owned());
+        let (address, port) = s.rsplit_once(':').ok_or_else(invalid)?;
+        let address = address
+            .strip_prefix('[')
+            .and_then(|a| a.strip_suffix(']'))
+            .unwrap_or(address);
+        if address.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            address: address.to_owned(),
+            port: port.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Configuration file of CRIU for the options which rust_criu has no setters
+/// for. It is passed to CRIU through the environment until it is dropped.
+pub(super) struct CriuConfig {
+    path: Option<PathBuf>,
+}
+
+impl CriuConfig {
+    /// Writes the options, given by their long name without dashes and
+    /// followed by their value if they take one, to the directory
+    pub(super) fn new(dir: &Path, options: &[String]) -> Result<Self, LibcontainerError> {
+        if options.is_empty() {
+            return Ok(Self { path: None });
+        }
+
+        let path = dir.join(CRIU_CONFIG_FILE);
+        let mut content = options.join("\n");
+        content.push('\n');
+        fs::write(&path, content).map_err(|err| {
+            tracing::error!(?path, ?err, "failed to write criu configuration");
+            LibcontainerError::OtherIO(err)
+        })?;
+        std::env::set_var(CRIU_CONFIG_FILE_ENV, &path);
+
+        Ok(Self { path: Some(path) })
+    }
+}
+
+impl Drop for CriuConfig {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            std::env::remove_var(CRIU_CONFIG_FILE_ENV);
+            if let Err(err) = fs::remove_file(path) {
+                tracing::warn!(?path, ?err, "failed to remove criu configuration");
+            }
+        }
+    }
+}
+
+/// With lazy pages CRIU listens on the address of the page server for the
+/// lazy pages daemon of the restoring host, otherwise it sends the pages to
+/// the page server instead of writing them to the image directory.
+fn checkpoint_config_options(opts: &CheckpointOptions) -> Result<Vec<String>, CheckpointError> {
+    let server = match (&opts.page_server, opts.lazy_pages) {
+        (Some(server), _) => server,
+        (None, true) => return Err(CheckpointError::MissingPageServer),
+        (None, false) => return Ok(Vec::new()),
+    };
+    let mode = if opts.lazy_pages {
+        "lazy-pages"
+    } else {
+        "page-server"
+    };
+
+    Ok(vec![
+        mode.to_owned(),
+        format!("address {}", server.address),
+        format!("port {}", server.port),
+    ])
+}
+
+/// For cgroup v1 it is necessary to list all cgroup mounts as external mounts,
+/// both when checkpointing and restoring a container.
+#[cfg_attr(not(feature = "v1"), allow(unused_variables))]
+pub(super) fn set_external_cgroup_mounts(
+    criu: &mut rust_criu::Criu,
+) -> Result<(), LibcontainerError> {
+    match libcgroups::common::get_cgroup_setup()? {
+        Legacy | Hybrid => {
+            #[cfg(not(feature = "v1"))]
+            panic!(
+                "libcontainer can't run in a Legacy or Hybrid cgroup setup without the v1 feature"
+            );
+            #[cfg(feature = "v1")]
+            for mp in libcgroups::v1::util::list_subsystem_mount_points().map_err(|err| {
+                tracing::error!(?err, "failed to get subsystem mount points");
+                LibcontainerError::OtherCgroup(err.to_string())
+            })? {
+                let cgroup_mount = mp
+                    .clone()
+                    .into_os_string()
+                    .into_string()
+                    .expect("failed to convert mount point");
+                if cgroup_mount.starts_with(DEFAULT_CGROUP_ROOT) {
+                    criu.set_external_mount(cgroup_mount.clone(), cgroup_mount);
+                }
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
 }
 
 impl Container {
@@ -57,32 +189,7 @@ impl Container {
                         .expect("failed to convert mount destination");
                     criu.set_external_mount(dest.clone(), dest);
                 }
-                Some("cgroup") => {
-                    match libcgroups::common::get_cgroup_setup()? {
-                        // For v1 it is necessary to list all cgroup mounts as external mounts
-                        Legacy | Hybrid => {
-                            #[cfg(not(feature = "v1"))]
-                            panic!("libcontainer can't run in a Legacy or Hybrid cgroup setup without the v1 feature");
-                            #[cfg(feature = "v1")]
-                            for mp in libcgroups::v1::util::list_subsystem_mount_points().map_err(
-                                |err| {
-                                    tracing::error!(?err, "failed to get subsystem mount points");
-                                    LibcontainerError::OtherCgroup(err.to_string())
-                                },
-                            )? {
-                                let cgroup_mount = mp
-                                    .clone()
-                                    .into_os_string()
-                                    .into_string()
-                                    .expect("failed to convert mount point");
-                                if cgroup_mount.starts_with(DEFAULT_CGROUP_ROOT) {
-                                    criu.set_external_mount(cgroup_mount.clone(), cgroup_mount);
-                                }
-                            }
-                        }
-                        _ => (),
-                    }
-                }
+                Some("cgroup") => set_external_cgroup_mounts(&mut criu)?,
                 _ => (),
             }
         }
@@ -101,6 +208,11 @@ impl Container {
             criu.set_work_dir_fd(work_dir.as_raw_fd());
         }
 
+        let config = CriuConfig::new(
+            opts.work_path.as_ref().unwrap_or(&opts.image_path),
+            &checkpoint_config_options(opts)?,
+        )?;
+
         let pid: i32 = self
             .pid()
             .ok_or(LibcontainerError::Other(
@@ -128,6 +240,9 @@ impl Container {
             serde_json::to_string(&descriptors).map_err(LibcontainerError::OtherSerialization)?
         )
         .map_err(LibcontainerError::OtherIO)?;
+        // the cgroup configuration is re-applied on restore, so that the
+        // resources are the same even if the cgroup has to be recreated
+        save_cgroup_config(&opts.image_path, &self.cgroup_config()?)?;
 
         criu.set_log_file(CRIU_CHECKPOINT_LOG_FILE.to_string());
         criu.set_log_level(4);
@@ -151,6 +266,7 @@ impl Container {
             tracing::error!(?err, id = ?self.id(), logfile = ?opts.image_path.join(CRIU_CHECKPOINT_LOG_FILE), "checkpointing container failed");
             LibcontainerError::Other(err.to_string())
         })?;
+        drop(config);
 
         if !opts.leave_running {
             self.set_status(ContainerStatus::Stopped).save()?;
@@ -160,3 +276,68 @@ impl Container {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(lazy_pages: bool, page_server: Option<&str>) -> CheckpointOptions {
+        CheckpointOptions {
+            ext_unix_sk: false,
+            file_locks: false,
+            image_path: PathBuf::from("checkpoint"),
+            lazy_pages,
+            leave_running: false,
+            page_server: page_server.map(|s| s.parse().unwrap()),
+            shell_job: false,
+            tcp_established: false,
+            work_path: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_page_server() {
+        assert_eq!(
+            "192.168.1.2:27".parse::<PageServer>().unwrap(),
+            PageServer {
+                address: "192.168.1.2".to_owned(),
+                port: 27
+            }
+        );
+        assert_eq!(
+            "[fd00::2]:27".parse::<PageServer>().unwrap().address,
+            "fd00::2"
+        );
+        for invalid in [
+            "192.168.1.2",
+            ":27",
+            "192.168.1.2:",
+            "host:port",
+            "host:65536",
+        ] {
+            assert!(
+                invalid.parse::<PageServer>().is_err(),
+                "{invalid} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_config_options() {
+        assert!(checkpoint_config_options(&options(false, None))
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            checkpoint_config_options(&options(true, None)),
+            Err(CheckpointError::MissingPageServer)
+        ));
+        assert_eq!(
+            checkpoint_config_options(&options(false, Some("10.0.0.1:27"))).unwrap(),
+            ["page-server", "address 10.0.0.1", "port 27"]
+        );
+        assert_eq!(
+            checkpoint_config_options(&options(true, Some("10.0.0.1:27"))).unwrap(),
+            ["lazy-pages", "address 10.0.0.1", "port 27"]
+        );
+    }
+}
@@ -1,17 +1,25 @@
 use std::fs;
+use std::path::Path;
 
 use libcgroups::common::CgroupManager;
 use libcgroups::{self};
+use nix::mount::MntFlags;
 use nix::sys::signal;
+use procfs::process::Process;
 
-use super::{Container, ContainerStatus};
+use super::{cgroup_guard, container_keep, Container, ContainerStatus, PodCgroup};
 use crate::config::YoukiConfig;
 use crate::error::LibcontainerError;
-use crate::hooks;
-use crate::process::intel_rdt::delete_resctrl_subdirectory;
+use crate::process::intel_rdt::{
+    delete_resctrl_monitoring_group, delete_resctrl_subdirectory, leave_shared_group,
+};
+use crate::{hooks, net_devices};
 
 impl Container {
-    /// Deletes the container
+    /// Deletes the container. With `force`, a container that is still running
+    /// is killed, and a container whose creation failed halfway is removed as
+    /// completely as possible, including its cgroups and the mounts left below
+    /// its root filesystem, instead of failing on the first error.
     ///
     /// # Example
     ///
@@ -32,7 +40,7 @@ impl Container {
     /// # }
     /// ```
     pub fn delete(&mut self, force: bool) -> Result<(), LibcontainerError> {
-        self.refresh_status()?;
+        let _lock = self.lock_state()?;
 
         tracing::debug!("container status: {:?}", self.status());
 
@@ -53,7 +61,11 @@ impl Container {
                 // force flag is set. In the force case, we need to clean up any
                 // processes associated with containers.
                 if force {
-                    self.do_kill(signal::Signal::SIGKILL, true)?;
+                    // the cgroup may not exist if the creation failed, the
+                    // removal of the cgroup kills the processes as well
+                    if let Err(err) = self.do_kill(signal::Signal::SIGKILL, true) {
+                        tracing::warn!(id = ?self.id(), "failed to kill container processes: {err}");
+                    }
                     self.set_status(ContainerStatus::Stopped).save()?;
                 } else {
                     tracing::error!(
@@ -82,6 +94,23 @@ impl Container {
                 Ok(config) => {
                     tracing::debug!("config: {:?}", config);
 
+                    if let Some(intel_rdt) = &config.intel_rdt {
+                        if let Err(err) = delete_resctrl_monitoring_group(self.id(), intel_rdt) {
+                            tracing::warn!(
+                                "failed to delete resctrl monitoring group due to: {err:?}, continue to delete"
+                            );
+                        }
+                        if let (Some(clos_id), Some(root_path)) =
+                            (intel_rdt.clos_id(), self.root.parent())
+                        {
+                            if let Err(err) = leave_shared_group(root_path, clos_id, self.id()) {
+                                tracing::warn!(
+                                    "failed to leave shared resctrl group due to: {err:?}, continue to delete"
+                                );
+                            }
+                        }
+                    }
+
                     // remove the cgroup created for the container
                     // check https://man7.org/linux/man-pages/man7/cgroups.7.html
                     // creating and removing cgroups section for more information on cgroups
@@ -90,12 +119,46 @@ impl Container {
                             cgroup_path: config.cgroup_path.to_owned(),
                             systemd_cgroup: self.systemd(),
                             container_name: self.id().to_string(),
+                            leaf_cgroup: None,
+                            best_effort_controllers: Vec::new(),
                         },
                     )?;
-                    cmanager.remove().map_err(|err| {
+                    if let Err(err) = cmanager.remove() {
                         tracing::error!(cgroup_path = ?config.cgroup_path, "failed to remove cgroup due to: {err:?}");
-                        err
-                    })?;
+                        // a forced delete removes the state regardless, so
+                        // that the container can be created again
+                        if !force {
+                            return Err(err.into());
+                        }
+                    } else {
+                        if let Err(err) = self.root.parent().map_or(Ok(()), |root_path| {
+                            cgroup_guard::release(root_path, self.id())
+                        }) {
+                            tracing::warn!("failed to release cgroup record due to: {err:?}, continue to delete");
+                        }
+
+                        if let Some(pod_cgroup) = self.pod_cgroup() {
+                            // the pod cgroup goes with its last member, which is
+                            // only left once the cgroup of the container is gone
+                            let pod_cgroup = PodCgroup::new(pod_cgroup);
+                            if let Err(err) = self
+                                .root
+                                .parent()
+                                .map_or(Ok(()), |root_path| pod_cgroup.leave(root_path, self.id()))
+                            {
+                                tracing::error!(pod = ?pod_cgroup.path(), "failed to leave pod cgroup due to: {err:?}");
+                                if !force {
+                                    return Err(err);
+                                }
+                            }
+                        }
+                    }
+
+                    if force {
+                        if let Some(rootfs) = &config.rootfs {
+                            unmount_leftovers(&self.state.bundle.join(rootfs));
+                        }
+                    }
 
                     if let Some(hooks) = config.hooks.as_ref() {
                         hooks::run_hooks(hooks.poststop().as_ref(), Some(self), None).map_err(
@@ -117,6 +180,15 @@ impl Container {
                 }
             }
 
+            if let Err(err) = net_devices::restore(&self.root) {
+                tracing::warn!(
+                    ?err,
+                    "failed to restore network devices, continue to delete"
+                );
+            }
+
+            container_keep::release_mount_namespace(&self.root);
+
             // remove the directory storing container state
             tracing::debug!("remove dir {:?}", self.root);
             fs::remove_dir_all(&self.root).map_err(|err| {
@@ -125,6 +197,35 @@ impl Container {
             })?;
         }
 
+        self.observers.notify(|observer| observer.on_deleted(self));
         Ok(())
     }
 }
+
+/// Unmounts the mounts below the root filesystem of the container that are
+/// left in the mount namespace of the runtime, e.g. because they propagated
+/// out of the container through a shared mount. The mount of the root
+/// filesystem itself has been set up by the caller and is kept.
+fn unmount_leftovers(rootfs: &Path) {
+    let mount_infos = match Process::myself().and_then(|process| process.mountinfo()) {
+        Ok(mount_infos) => mount_infos,
+        Err(err) => {
+            tracing::warn!("failed to get mount info: {err}, skipping leftover mounts");
+            return;
+        }
+    };
+
+    // the mounts are unmounted in reverse order, so that the mounts stacked on
+    // top of others or nested in them are unmounted first
+    for mount_info in mount_infos.0.iter().rev() {
+        let mount_point = &mount_info.mount_point;
+        if mount_point == rootfs || !mount_point.starts_with(rootfs) {
+            continue;
+        }
+
+        tracing::debug!(?mount_point, "unmounting leftover mount");
+        if let Err(err) = nix::mount::umount2(mount_point, MntFlags::MNT_DETACH) {
+            tracing::warn!(?mount_point, "failed to unmount leftover mount: {err}");
+        }
+    }
+}
@@ -1,13 +1,21 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use libcgroups::common::CgroupManager;
+use libcgroups::events::CgroupEvent;
+use libcgroups::stats::Stats;
+use serde::Serialize;
 
 use super::{Container, ContainerStatus};
 use crate::error::LibcontainerError;
+use crate::process::intel_rdt::{self, IntelRdtStats};
 
 impl Container {
-    /// Displays container events
+    /// Displays container events on stdout in the JSON format of runc.
+    /// Unless only the stats are requested, stats are reported every
+    /// `interval` seconds together with out of memory events until the
+    /// container stops running.
     ///
     /// # Example
     ///
@@ -23,43 +31,240 @@ impl Container {
     /// .as_init("/var/run/docker/bundle")
     /// .build()?;
     ///
-    /// container.events(5000, false)?;
+    /// container.events(5, false)?;
     /// # Ok(())
     /// # }
     /// ```
     pub fn events(&mut self, interval: u32, stats: bool) -> Result<(), LibcontainerError> {
+        self.watch_events(interval, stats, |event| {
+            println!(
+                "{}",
+                serde_json::to_string(event).map_err(LibcontainerError::OtherSerialization)?
+            );
+            Ok(())
+        })
+    }
+
+    /// Reports container events to `on_event` the same way as
+    /// [`Container::events`], for callers that process the events themselves.
+    /// An error returned by `on_event` stops watching for further events.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::Container;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = Container::load_from_root("/run/youki", "74f1a4cb3801")?;
+    /// container.watch_events(5, false, |event| {
+    ///     if event.typ == "oom" {
+    ///         println!("container {} ran out of memory", event.id);
+    ///     }
+    ///     Ok(())
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn watch_events<F>(
+        &mut self,
+        interval: u32,
+        stats: bool,
+        mut on_event: F,
+    ) -> Result<(), LibcontainerError>
+    where
+        F: FnMut(&Event) -> Result<(), LibcontainerError>,
+    {
         self.refresh_status()?;
         if !self.state.status.eq(&ContainerStatus::Running) {
             tracing::error!(id = ?self.id(), status = ?self.state.status, "container is not running");
             return Err(LibcontainerError::IncorrectStatus);
         }
 
-        let cgroup_manager =
-            libcgroups::common::create_cgroup_manager(libcgroups::common::CgroupConfig {
-                cgroup_path: self.spec()?.cgroup_path,
-                systemd_cgroup: self.systemd(),
-                container_name: self.id().to_string(),
-            })?;
-        match stats {
-            true => {
-                let stats = cgroup_manager.stats()?;
-                println!(
-                    "{}",
-                    serde_json::to_string_pretty(&stats)
-                        .map_err(LibcontainerError::OtherSerialization)?
-                );
+        let config = self.spec()?;
+        let cgroup_manager = self.cgroup_manager()?;
+        let id = self.id().to_owned();
+        let collect_stats = || -> Result<EventData, LibcontainerError> {
+            let intel_rdt = match &config.intel_rdt {
+                Some(intel_rdt) => Some(intel_rdt::stats(&id, intel_rdt)?),
+                None => None,
+            };
+            // devices are reported by name and per disk, so that the stats
+            // don't have to be matched with the devices of the host
+            let mut stats = cgroup_manager.stats()?;
+            stats.blkio.resolve_devices();
+            stats.blkio.aggregate_partitions();
+            stats.blkio.totals = Some(stats.blkio.compute_totals());
+            Ok(EventData { stats, intel_rdt })
+        };
+
+        if stats {
+            return on_event(&Event::stats(self.id(), collect_stats()?));
+        }
+
+        // Waiting for out of memory events blocks, so it happens in a separate
+        // thread which forwards them to the thread that reports the stats.
+        let mut oom_events = match cgroup_manager.oom_notifier() {
+            Ok(mut notifier) => {
+                let (sender, receiver) = mpsc::channel();
+                thread::spawn(move || loop {
+                    match notifier.wait() {
+                        Ok(true) => {
+                            if sender.send(()).is_err() {
+                                break;
+                            }
+                        }
+                        Ok(false) => break,
+                        Err(err) => {
+                            tracing::warn!("failed to wait for oom events: {}", err);
+                            break;
+                        }
+                    }
+                });
+                Some(receiver)
+            }
+            Err(err) => {
+                tracing::warn!("oom events are not available: {}", err);
+                None
+            }
+        };
+
+        let interval = Duration::from_secs(interval as u64);
+        loop {
+            on_event(&Event::stats(self.id(), collect_stats()?))?;
+
+            let deadline = Instant::now() + interval;
+            while let Some(receiver) = &oom_events {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match receiver.recv_timeout(timeout) {
+                    Ok(()) => {
+                        if let Err(err) = self.update_oom_killed() {
+                            tracing::warn!("failed to record the oom kill: {}", err);
+                        }
+                        on_event(&Event::oom(self.id()))?
+                    }
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => oom_events = None,
+                }
+            }
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+
+            self.refresh_status()?;
+            if !self.state.status.eq(&ContainerStatus::Running) {
+                return Ok(());
             }
-            false => loop {
-                let stats = cgroup_manager.stats()?;
-                println!(
-                    "{}",
-                    serde_json::to_string_pretty(&stats)
-                        .map_err(LibcontainerError::OtherSerialization)?
-                );
-                thread::sleep(Duration::from_secs(interval as u64));
-            },
         }
+    }
+
+    /// Reports the changes of the cgroup of the container to the returned
+    /// channel, i.e. when all processes of the container have exited or
+    /// when the container has been frozen or thawed. The channel is closed
+    /// once the cgroup is removed. Requires cgroup v2.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcgroups::events::CgroupEvent;
+    /// use libcontainer::container::Container;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let container = Container::load_from_root("/run/youki", "74f1a4cb3801")?;
+    /// for event in container.cgroup_events()? {
+    ///     if event == CgroupEvent::Populated(false) {
+    ///         println!("all processes of container {} exited", container.id());
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn cgroup_events(&self) -> Result<Receiver<CgroupEvent>, LibcontainerError> {
+        Ok(self.cgroup_manager()?.events_watcher()?.into_channel())
+    }
+
+    /// Blocks until all processes of the container have exited. Unlike
+    /// waiting for the init process, this also waits for processes which
+    /// outlive it, e.g. when the container shares the pid namespace of
+    /// another container. Requires cgroup v2.
+    pub fn wait_all_exited(&self) -> Result<(), LibcontainerError> {
+        let mut watcher = self.cgroup_manager()?.events_watcher()?;
+        watcher
+            .wait_unpopulated()
+            .map_err(|err| LibcontainerError::OtherCgroup(err.to_string()))
+    }
+}
+
+/// Event reported by [`Container::events`], using the same format as runc
+#[derive(Debug, Serialize)]
+pub struct Event<'a> {
+    /// Either "stats" or "oom"
+    #[serde(rename = "type")]
+    pub typ: &'static str,
+    pub id: &'a str,
+    /// Only set for stats events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<EventData>,
+}
+
+/// Statistics of a container, the Intel RDT statistics are only reported if
+/// Intel RDT is configured for the container
+#[derive(Debug, Serialize)]
+pub struct EventData {
+    #[serde(flatten)]
+    pub stats: Stats,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intel_rdt: Option<IntelRdtStats>,
+}
+
+impl<'a> Event<'a> {
+    fn stats(id: &'a str, stats: EventData) -> Self {
+        Self {
+            typ: "stats",
+            id,
+            data: Some(stats),
+        }
+    }
+
+    fn oom(id: &'a str) -> Self {
+        Self {
+            typ: "oom",
+            id,
+            data: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_event_format() -> Result<()> {
+        let oom = serde_json::to_value(Event::oom("container"))?;
+        assert_eq!(oom, serde_json::json!({"type": "oom", "id": "container"}));
+
+        let data = EventData {
+            stats: Stats::default(),
+            intel_rdt: None,
+        };
+        let stats = serde_json::to_value(Event::stats("container", data))?;
+        assert_eq!(stats["type"], "stats");
+        assert_eq!(stats["id"], "container");
+        assert!(stats["data"]["cpu"].is_object());
+        assert!(stats["data"].get("intel_rdt").is_none());
 
+        let data = EventData {
+            stats: Stats::default(),
+            intel_rdt: Some(IntelRdtStats {
+                l3_cache_schema: Some("L3:0=f".to_owned()),
+                ..Default::default()
+            }),
+        };
+        let stats = serde_json::to_value(Event::stats("container", data))?;
+        assert_eq!(
+            stats["data"]["intel_rdt"],
+            serde_json::json!({"l3_cache_schema": "L3:0=f"})
+        );
         Ok(())
     }
 }
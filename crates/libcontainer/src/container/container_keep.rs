@@ -0,0 +1,85 @@
+use std::fs::{self, File};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use nix::errno::Errno;
+use nix::mount::{mount, umount2, MntFlags, MsFlags};
+
+use super::Container;
+use crate::error::LibcontainerError;
+
+/// File in the state directory which the mount namespace of a kept container
+/// is bind mounted to, e.g. for `nsenter --mount=<file>`
+pub const KEPT_MOUNT_NAMESPACE: &str = "mntns";
+
+impl Container {
+    /// Keeps the mount namespace of the container, and with it the mounts of
+    /// its root filesystem, after all processes of the container have exited,
+    /// until the container is deleted. Nothing needs to be kept if the
+    /// container shares the mount namespace of the runtime.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::builder::ContainerBuilder;
+    /// use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .as_init("/var/run/docker/bundle")
+    /// .build()?;
+    ///
+    /// container.keep_mount_namespace()?;
+    /// container.start()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn keep_mount_namespace(&self) -> Result<(), LibcontainerError> {
+        let pid = self.pid().ok_or(LibcontainerError::IncorrectStatus)?;
+        let namespace = format!("/proc/{pid}/ns/mnt");
+        let (ours, theirs) = match (fs::metadata("/proc/self/ns/mnt"), fs::metadata(&namespace)) {
+            (Ok(ours), Ok(theirs)) => (ours, theirs),
+            (Err(err), _) | (_, Err(err)) => return Err(LibcontainerError::OtherIO(err)),
+        };
+        if ours.dev() == theirs.dev() && ours.ino() == theirs.ino() {
+            tracing::debug!(id = ?self.id(), "container shares the mount namespace of the runtime");
+            return Ok(());
+        }
+
+        let target = self.root.join(KEPT_MOUNT_NAMESPACE);
+        File::create(&target).map_err(LibcontainerError::OtherIO)?;
+        mount(
+            Some(namespace.as_str()),
+            &target,
+            None::<&str>,
+            MsFlags::MS_BIND,
+            None::<&str>,
+        )
+        .map_err(|err| {
+            tracing::error!(?err, ?target, "failed to keep mount namespace");
+            LibcontainerError::OtherSyscall(err)
+        })?;
+        tracing::debug!(id = ?self.id(), ?target, "kept mount namespace");
+
+        Ok(())
+    }
+}
+
+/// Releases the mount namespace kept in the state directory, so that the
+/// directory can be removed
+pub(super) fn release_mount_namespace(container_root: &Path) {
+    let target = container_root.join(KEPT_MOUNT_NAMESPACE);
+    if !target.exists() {
+        return;
+    }
+
+    match umount2(&target, MntFlags::MNT_DETACH) {
+        // the namespace wasn't mounted, e.g. because the runtime failed
+        // before mounting it
+        Ok(()) | Err(Errno::EINVAL) => {}
+        Err(err) => tracing::warn!(?err, ?target, "failed to release kept mount namespace"),
+    }
+}
@@ -1,8 +1,11 @@
 use libcgroups::common::{get_cgroup_setup, CgroupManager};
 use nix::sys::signal::{self};
+use nix::unistd::Pid;
 
+use super::exec_process::{self, ExecProcess};
 use super::{Container, ContainerStatus};
 use crate::error::LibcontainerError;
+use crate::pidfd::PidFd;
 use crate::signal::Signal;
 
 impl Container {
@@ -28,7 +31,7 @@ impl Container {
     /// # }
     /// ```
     pub fn kill<S: Into<Signal>>(&mut self, signal: S, all: bool) -> Result<(), LibcontainerError> {
-        self.refresh_status()?;
+        let _lock = self.lock_state()?;
         match self.can_kill() {
             true => {
                 self.do_kill(signal, all)?;
@@ -45,6 +48,49 @@ impl Container {
         Ok(())
     }
 
+    /// Sends the signal to a process executed in the container, which has
+    /// been recorded under the exec id, see
+    /// [`TenantContainerBuilder::with_exec_id`](super::tenant_builder::TenantContainerBuilder::with_exec_id).
+    /// The init process and the other processes of the container aren't
+    /// signaled. A process which has exited already isn't signaled either.
+    pub fn kill_exec<S: Into<Signal>>(
+        &self,
+        exec_id: &str,
+        signal: S,
+    ) -> Result<(), LibcontainerError> {
+        exec_process::validate_exec_id(exec_id)?;
+        let process = ExecProcess::load(&self.root, exec_id)?.ok_or_else(|| {
+            LibcontainerError::InvalidInput(format!(
+                "no exec process {exec_id:?} in container {}",
+                self.id()
+            ))
+        })?;
+        let signal = signal.into().into_raw();
+        let pid = Pid::from_raw(process.pid);
+
+        // the pidfd is opened before checking that the pid hasn't been
+        // reused, so that it can't refer to another process afterwards
+        let pidfd = PidFd::open(pid);
+        if !process.is_running() {
+            tracing::debug!(id = ?self.id(), ?exec_id, ?pid, "exec process has exited already");
+            return Ok(());
+        }
+        tracing::debug!("kill signal {} to exec process {}", signal, pid);
+        let result = match pidfd {
+            Ok(Some(pidfd)) => pidfd.send_signal(signal),
+            // kernels older than 5.3 don't support pidfds
+            Ok(None) => signal::kill(pid, signal),
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(_) | Err(nix::errno::Errno::ESRCH) => Ok(()),
+            Err(err) => {
+                tracing::error!(id = ?self.id(), ?err, ?exec_id, ?pid, ?signal, "failed to kill exec process");
+                Err(LibcontainerError::OtherSyscall(err))
+            }
+        }
+    }
+
     pub(crate) fn do_kill<S: Into<Signal>>(
         &self,
         signal: S,
@@ -65,7 +111,17 @@ impl Container {
 
         tracing::debug!("kill signal {} to {}", signal, pid);
 
-        match signal::kill(pid, signal) {
+        // Once the pidfd has been opened, it can't refer to another process,
+        // so checking that the pid has not been reused yet makes sure that
+        // only the container process can be signaled
+        let result = match PidFd::open(pid) {
+            Ok(Some(pidfd)) if self.is_init_process(pid) => pidfd.send_signal(signal),
+            Ok(Some(_)) => Err(nix::errno::Errno::ESRCH),
+            // kernels older than 5.3 don't support pidfds
+            Ok(None) => signal::kill(pid, signal),
+            Err(err) => Err(err),
+        };
+        match result {
             Ok(_) => {}
             Err(nix::errno::Errno::ESRCH) => {
                 // the process does not exist, which is what we want
@@ -82,13 +138,7 @@ impl Container {
             match get_cgroup_setup()? {
                 libcgroups::common::CgroupSetup::Legacy
                 | libcgroups::common::CgroupSetup::Hybrid => {
-                    let cmanager = libcgroups::common::create_cgroup_manager(
-                        libcgroups::common::CgroupConfig {
-                            cgroup_path: self.spec()?.cgroup_path,
-                            systemd_cgroup: self.systemd(),
-                            container_name: self.id().to_string(),
-                        },
-                    )?;
+                    let cmanager = self.cgroup_manager()?;
                     cmanager.freeze(libcgroups::common::FreezerState::Thawed)?;
                 }
                 libcgroups::common::CgroupSetup::Unified => {}
@@ -99,12 +149,13 @@ impl Container {
 
     fn kill_all_processes<S: Into<Signal>>(&self, signal: S) -> Result<(), LibcontainerError> {
         let signal = signal.into().into_raw();
-        let cmanager =
-            libcgroups::common::create_cgroup_manager(libcgroups::common::CgroupConfig {
-                cgroup_path: self.spec()?.cgroup_path,
-                systemd_cgroup: self.systemd(),
-                container_name: self.id().to_string(),
-            })?;
+        let cmanager = self.cgroup_manager()?;
+
+        // Signaling the processes one by one races with processes forking in
+        // the meantime, which cgroup.kill avoids, but it only supports SIGKILL
+        if signal == signal::Signal::SIGKILL && cmanager.kill_all()? {
+            return Ok(());
+        }
 
         if let Err(e) = cmanager.freeze(libcgroups::common::FreezerState::Frozen) {
             tracing::warn!(
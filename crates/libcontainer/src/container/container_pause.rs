@@ -25,23 +25,19 @@ impl Container {
     /// # }
     /// ```
     pub fn pause(&mut self) -> Result<(), LibcontainerError> {
-        self.refresh_status()?;
+        let _lock = self.lock_state()?;
 
         if !self.can_pause() {
             tracing::error!(status = ?self.status(), id = ?self.id(), "cannot pause container");
             return Err(LibcontainerError::IncorrectStatus);
         }
 
-        let cmanager =
-            libcgroups::common::create_cgroup_manager(libcgroups::common::CgroupConfig {
-                cgroup_path: self.spec()?.cgroup_path,
-                systemd_cgroup: self.systemd(),
-                container_name: self.id().to_string(),
-            })?;
+        let cmanager = self.cgroup_manager()?;
         cmanager.freeze(FreezerState::Frozen)?;
 
         tracing::debug!("saving paused status");
         self.set_status(ContainerStatus::Paused).save()?;
+        self.observers.notify(|observer| observer.on_paused(self));
 
         tracing::debug!("container {} paused", self.id());
         Ok(())
@@ -0,0 +1,219 @@
+use std::fs;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use oci_spec::runtime::Spec;
+
+use super::container_checkpoint::{set_external_cgroup_mounts, CheckpointError, CriuConfig};
+use super::{load_cgroup_config, save_cgroup_config, Container, ContainerStatus};
+use crate::container::container::RestoreOptions;
+use crate::error::{LibcontainerError, MissingSpecError};
+
+const CRIU_RESTORE_LOG_FILE: &str = "restore.log";
+/// File CRIU writes the pid of the restored init process to
+const CRIU_PID_FILE: &str = "restore.pid";
+
+impl Container {
+    pub fn restore(&mut self, opts: &RestoreOptions) -> Result<(), LibcontainerError> {
+        if self.status() != ContainerStatus::Creating {
+            tracing::error!(status = ?self.status(), id = ?self.id(), "cannot restore into a container that was already created");
+            return Err(LibcontainerError::IncorrectStatus);
+        }
+
+        let mut criu = rust_criu::Criu::new().map_err(|e| {
+            LibcontainerError::Checkpoint(CheckpointError::CriuError(format!(
+                "error in creating criu struct: {}",
+                e
+            )))
+        })?;
+
+        let source_spec_path = self.bundle().join("config.json");
+        let mut spec = Spec::load(source_spec_path)?;
+        spec.canonicalize_rootfs(self.bundle())?;
+
+        // The bind mounts were marked as external during checkpoint using their
+        // destination as key. On restore, CRIU needs to know where to find the
+        // source of these mounts on this host, which may differ from the host
+        // the checkpoint was taken on.
+        for m in spec.mounts().clone().unwrap_or_default() {
+            match m.typ().as_deref() {
+                Some("bind") => {
+                    let dest = m
+                        .destination()
+                        .clone()
+                        .into_os_string()
+                        .into_string()
+                        .expect("failed to convert mount destination");
+                    let source = m
+                        .source()
+                        .clone()
+                        .unwrap_or_else(|| m.destination().clone())
+                        .into_os_string()
+                        .into_string()
+                        .expect("failed to convert mount source");
+                    criu.set_external_mount(dest, source);
+                }
+                Some("cgroup") => set_external_cgroup_mounts(&mut criu)?,
+                _ => (),
+            }
+        }
+
+        let directory = std::fs::File::open(&opts.image_path).map_err(|err| {
+            tracing::error!(path = ?opts.image_path, ?err, "failed to open criu image directory");
+            LibcontainerError::OtherIO(err)
+        })?;
+        criu.set_images_dir_fd(directory.as_raw_fd());
+
+        // It seems to be necessary to be defined outside of 'if' to
+        // keep the FD open until CRIU uses it.
+        let work_dir: std::fs::File;
+        if let Some(wp) = &opts.work_path {
+            work_dir = std::fs::File::open(wp).map_err(LibcontainerError::OtherIO)?;
+            criu.set_work_dir_fd(work_dir.as_raw_fd());
+        }
+
+        let config_dir = opts.work_path.as_ref().unwrap_or(&opts.image_path);
+        // CRIU runs in the work directory, so the pid file is given by its
+        // absolute path
+        let pid_file = fs::canonicalize(config_dir)
+            .map_err(LibcontainerError::OtherIO)?
+            .join(CRIU_PID_FILE);
+        // CRIU doesn't overwrite the pid file of an earlier restore
+        if let Err(err) = fs::remove_file(&pid_file) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                return Err(LibcontainerError::OtherIO(err));
+            }
+        }
+        let config = CriuConfig::new(config_dir, &restore_config_options(opts, &pid_file))?;
+
+        let rootfs = spec.root().as_ref().ok_or(MissingSpecError::Root)?.path();
+
+        criu.set_log_file(CRIU_RESTORE_LOG_FILE.to_string());
+        criu.set_log_level(4);
+        criu.set_ext_unix_sk(opts.ext_unix_sk);
+        criu.set_shell_job(opts.shell_job);
+        criu.set_tcp_established(opts.tcp_established);
+        criu.set_file_locks(opts.file_locks);
+        criu.set_orphan_pts_master(true);
+        criu.set_manage_cgroups(opts.manage_cgroups);
+        criu.set_root(rootfs.clone().into_os_string().into_string().unwrap());
+
+        criu.restore().map_err(|err| {
+            tracing::error!(?err, id = ?self.id(), logfile = ?opts.image_path.join(CRIU_RESTORE_LOG_FILE), "restoring container failed");
+            LibcontainerError::Other(err.to_string())
+        })?;
+        drop(config);
+
+        // the resources of the checkpointed container are re-applied, as
+        // CRIU doesn't restore the properties of cgroups which already exist
+        let cgroup_config = load_cgroup_config(&opts.image_path)?;
+        save_cgroup_config(&self.root, &cgroup_config)?;
+        self.reapply_cgroup_config()?;
+
+        // the pid of the restored init process is the one CRIU reports, like
+        // runc takes it from the response of CRIU
+        let init_pid = read_pid_file(&pid_file)?;
+
+        self.set_status(ContainerStatus::Running)
+            .set_creator(nix::unistd::geteuid().as_raw())
+            .set_pid(init_pid)
+            .save()?;
+
+        tracing::debug!("container {} restored", self.id());
+        Ok(())
+    }
+}
+
+/// Options of CRIU which rust_criu has no setters for. The pages missing from
+/// the images are faulted in from the lazy pages daemon while the restored
+/// processes run.
+fn restore_config_options(opts: &RestoreOptions, pid_file: &Path) -> Vec<String> {
+    let mut options = vec![format!("pidfile {}", pid_file.display())];
+    if opts.lazy_pages {
+        options.push("lazy-pages".to_owned());
+    }
+    if opts.auto_dedup {
+        options.push("auto-dedup".to_owned());
+    }
+    if opts.empty_ns {
+        options.push("empty-ns net".to_owned());
+    }
+
+    options
+}
+
+fn read_pid_file(pid_file: &Path) -> Result<i32, LibcontainerError> {
+    let content = fs::read_to_string(pid_file).map_err(|err| {
+        tracing::error!(
+            ?pid_file,
+            ?err,
+            "failed to read the pid of the restored container"
+        );
+        LibcontainerError::OtherIO(err)
+    })?;
+    content.trim().parse().map_err(|err| {
+        LibcontainerError::Other(format!(
+            "invalid pid {content:?} of the restored container in {pid_file:?}: {err}"
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn options() -> RestoreOptions {
+        RestoreOptions {
+            auto_dedup: false,
+            empty_ns: false,
+            ext_unix_sk: false,
+            file_locks: false,
+            image_path: PathBuf::from("/checkpoint"),
+            lazy_pages: false,
+            manage_cgroups: true,
+            shell_job: false,
+            tcp_established: false,
+            work_path: None,
+        }
+    }
+
+    #[test]
+    fn test_restore_config_options() {
+        let pid_file = Path::new("/checkpoint/restore.pid");
+        assert_eq!(
+            restore_config_options(&options(), pid_file),
+            ["pidfile /checkpoint/restore.pid"]
+        );
+
+        let opts = RestoreOptions {
+            auto_dedup: true,
+            empty_ns: true,
+            lazy_pages: true,
+            ..options()
+        };
+        assert_eq!(
+            restore_config_options(&opts, pid_file),
+            [
+                "pidfile /checkpoint/restore.pid",
+                "lazy-pages",
+                "auto-dedup",
+                "empty-ns net"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_pid_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pid_file = tmp.path().join(CRIU_PID_FILE);
+        assert!(read_pid_file(&pid_file).is_err());
+
+        fs::write(&pid_file, "4242\n").unwrap();
+        assert_eq!(read_pid_file(&pid_file).unwrap(), 4242);
+
+        fs::write(&pid_file, "").unwrap();
+        assert!(read_pid_file(&pid_file).is_err());
+    }
+}
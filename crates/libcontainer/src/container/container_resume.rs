@@ -25,7 +25,7 @@ impl Container {
     /// # }
     /// ```
     pub fn resume(&mut self) -> Result<(), LibcontainerError> {
-        self.refresh_status()?;
+        let _lock = self.lock_state()?;
         // check if container can be resumed :
         // for example, a running process cannot be resumed
         if !self.can_resume() {
@@ -33,17 +33,13 @@ impl Container {
             return Err(LibcontainerError::IncorrectStatus);
         }
 
-        let cmanager =
-            libcgroups::common::create_cgroup_manager(libcgroups::common::CgroupConfig {
-                cgroup_path: self.spec()?.cgroup_path,
-                systemd_cgroup: self.systemd(),
-                container_name: self.id().to_string(),
-            })?;
+        let cmanager = self.cgroup_manager()?;
         // resume the frozen container
         cmanager.freeze(FreezerState::Thawed)?;
 
         tracing::debug!("saving running status");
         self.set_status(ContainerStatus::Running).save()?;
+        self.observers.notify(|observer| observer.on_resumed(self));
 
         tracing::debug!("container {} resumed", self.id());
         Ok(())
@@ -1,5 +1,3 @@
-use nix::sys::signal;
-
 use super::{Container, ContainerStatus};
 use crate::config::YoukiConfig;
 use crate::error::LibcontainerError;
@@ -28,7 +26,7 @@ impl Container {
     /// # }
     /// ```
     pub fn start(&mut self) -> Result<(), LibcontainerError> {
-        self.refresh_status()?;
+        let lock = self.lock_state()?;
 
         if !self.can_start() {
             tracing::error!(status = ?self.status(), id = ?self.id(), "cannot start container due to incorrect state");
@@ -43,20 +41,6 @@ impl Container {
             );
             err
         })?;
-        if let Some(hooks) = config.hooks.as_ref() {
-            // While prestart is marked as deprecated in the OCI spec, the docker and integration test still
-            // uses it.
-            #[allow(deprecated)]
-            hooks::run_hooks(hooks.prestart().as_ref(), Some(self), None).map_err(|err| {
-                tracing::error!("failed to run pre start hooks: {}", err);
-                // In the case where prestart hook fails, the runtime must
-                // stop the container before generating an error and exiting.
-                let _ = self.kill(signal::Signal::SIGKILL, true);
-
-                err
-            })?;
-        }
-
         let mut notify_socket = NotifySocket::new(self.root.join(NOTIFY_FILE));
         notify_socket.notify_container_start()?;
         self.set_status(ContainerStatus::Running)
@@ -65,6 +49,9 @@ impl Container {
                 tracing::error!(id = ?self.id(), ?err, "failed to save state for container");
                 err
             })?;
+        // the hooks may operate on the container themselves
+        drop(lock);
+        self.observers.notify(|observer| observer.on_started(self));
 
         // Run post start hooks. It runs after the container process is started.
         // It is called in the runtime namespace.
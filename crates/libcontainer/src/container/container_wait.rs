@@ -0,0 +1,96 @@
+use libcgroups::common::CgroupManager;
+use nix::errno::Errno;
+use nix::sys::wait::waitpid;
+
+use super::{Container, ContainerStatus, ExitInfo};
+use crate::error::LibcontainerError;
+
+impl Container {
+    /// Waits for the init process of the container to exit and notifies the
+    /// observers of the container about it. The calling process has to be the
+    /// parent or the subreaper of the init process, e.g. the process which
+    /// has created the container without detaching from it.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::builder::ContainerBuilder;
+    /// use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .as_init("/var/run/docker/bundle")
+    /// .build()?;
+    ///
+    /// container.start()?;
+    /// let exit = container.wait()?;
+    /// println!("exited with {}, oom killed: {}", exit.status(), exit.oom_killed);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn wait(&mut self) -> Result<ExitInfo, LibcontainerError> {
+        let pid = self.pid().ok_or(LibcontainerError::Other(
+            "container process pid not found in state".into(),
+        ))?;
+
+        let mut exit = loop {
+            match waitpid(pid, None) {
+                Ok(status) => match ExitInfo::from_wait_status(status) {
+                    Some(exit) => break exit,
+                    None => continue,
+                },
+                Err(Errno::EINTR) => continue,
+                Err(err) => {
+                    tracing::error!(id = ?self.id(), ?pid, ?err, "failed to wait for container process");
+                    return Err(LibcontainerError::OtherSyscall(err));
+                }
+            }
+        };
+        exit.oom_killed = self.update_oom_killed().unwrap_or_else(|err| {
+            tracing::warn!(id = ?self.id(), "failed to check for out of memory kills: {err}");
+            false
+        });
+
+        self.record_exit(&exit)?;
+        Ok(exit)
+    }
+
+    /// Records the exit of the init process in the state of the container,
+    /// for callers that reap the init process themselves instead of using
+    /// [`wait`](Container::wait), and notifies the observers about it
+    pub fn record_exit(&mut self, exit: &ExitInfo) -> Result<(), LibcontainerError> {
+        self.set_status(ContainerStatus::Stopped);
+        self.state.exit_status = Some(exit.status());
+        self.state.oom_killed |= exit.oom_killed;
+        self.save()?;
+        self.observers
+            .notify(|observer| observer.on_exited(self, exit));
+        Ok(())
+    }
+
+    /// Checks the oom_kill counter of the cgroup of the container, i.e. of
+    /// memory.events on cgroup v2 or memory.oom_control on v1, and records in
+    /// the state if processes have been killed by the out of memory killer.
+    /// This stays recorded once it has happened. The cgroup has to exist,
+    /// which is the case until the container is deleted.
+    pub fn update_oom_killed(&mut self) -> Result<bool, LibcontainerError> {
+        if self.state.oom_killed {
+            return Ok(true);
+        }
+
+        let notifier = self.cgroup_manager()?.oom_notifier()?;
+        let oom_kills = notifier
+            .oom_kills()
+            .map_err(|err| LibcontainerError::OtherCgroup(err.to_string()))?;
+        if oom_kills > 0 {
+            tracing::debug!(id = ?self.id(), oom_kills, "processes were killed by the oom killer");
+            self.state.oom_killed = true;
+            self.save()?;
+        }
+
+        Ok(self.state.oom_killed)
+    }
+}
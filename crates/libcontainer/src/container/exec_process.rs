@@ -0,0 +1,123 @@
+//! Records of the processes executed in a container under an exec id, so that
+//! a single exec session can be signaled later without the init process or
+//! the other processes of the container
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+
+use crate::error::LibcontainerError;
+use crate::pidfd;
+
+/// Directory in the state directory of a container which the exec processes
+/// are recorded in, one `<exec id>.json` each
+pub const EXEC_PROCESS_DIR: &str = "execs";
+
+/// Process executed in a container
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct ExecProcess {
+    pub pid: i32,
+    /// Tells the process apart from processes which have reused its pid
+    /// after it exited
+    pub start_time: Option<u64>,
+}
+
+impl ExecProcess {
+    pub fn new(pid: Pid) -> Self {
+        Self {
+            pid: pid.as_raw(),
+            start_time: pidfd::start_time(pid),
+        }
+    }
+
+    /// Returns whether the recorded process is still running
+    pub fn is_running(&self) -> bool {
+        self.start_time.is_some() && pidfd::start_time(Pid::from_raw(self.pid)) == self.start_time
+    }
+
+    /// Loads the exec process recorded under the id, if there is one
+    pub fn load(container_root: &Path, exec_id: &str) -> Result<Option<Self>, LibcontainerError> {
+        let path = record_path(container_root, exec_id);
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(LibcontainerError::OtherIO(err)),
+        };
+        serde_json::from_slice(&content)
+            .map(Some)
+            .map_err(LibcontainerError::OtherSerialization)
+    }
+
+    /// Records the process under the id, replacing the record of a process
+    /// which has exited
+    pub fn save(&self, container_root: &Path, exec_id: &str) -> Result<(), LibcontainerError> {
+        let path = record_path(container_root, exec_id);
+        fs::create_dir_all(container_root.join(EXEC_PROCESS_DIR))
+            .map_err(LibcontainerError::OtherIO)?;
+        let content = serde_json::to_vec(self).map_err(LibcontainerError::OtherSerialization)?;
+        fs::write(&path, content).map_err(|err| {
+            tracing::error!(?path, ?err, "failed to record exec process");
+            LibcontainerError::OtherIO(err)
+        })
+    }
+}
+
+/// Checks that the exec id is usable as a file name, with the same
+/// characters as allowed in a container id
+pub(super) fn validate_exec_id(exec_id: &str) -> Result<(), LibcontainerError> {
+    let valid = !exec_id.is_empty()
+        && exec_id != "."
+        && exec_id != ".."
+        && exec_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-' | '.'));
+    if !valid {
+        return Err(LibcontainerError::InvalidInput(format!(
+            "invalid exec id {exec_id:?}"
+        )));
+    }
+    Ok(())
+}
+
+fn record_path(container_root: &Path, exec_id: &str) -> PathBuf {
+    container_root
+        .join(EXEC_PROCESS_DIR)
+        .join(format!("{exec_id}.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        assert_eq!(ExecProcess::load(root.path(), "e1")?, None);
+
+        let mut child = Command::new("sleep").arg("10").spawn()?;
+        let process = ExecProcess::new(Pid::from_raw(child.id() as i32));
+        process.save(root.path(), "e1")?;
+        let loaded = ExecProcess::load(root.path(), "e1")?.unwrap();
+        assert_eq!(loaded, process);
+        assert!(loaded.is_running());
+
+        child.kill()?;
+        child.wait()?;
+        assert!(!loaded.is_running());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_exec_id() {
+        assert!(validate_exec_id("exec-1.a_b+c").is_ok());
+        for id in ["", ".", "..", "../e1", "e 1"] {
+            assert!(validate_exec_id(id).is_err(), "{id:?}");
+        }
+    }
+}
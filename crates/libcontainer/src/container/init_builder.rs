@@ -2,24 +2,39 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+use libcgroups::common::CgroupManagerType;
 use oci_spec::runtime::Spec;
 use user_ns::UserNamespaceConfig;
 
 use super::builder::ContainerBuilder;
 use super::builder_impl::ContainerBuilderImpl;
-use super::{Container, ContainerStatus};
+use super::cgroup_guard::CgroupGuard;
+use super::pod_cgroup::PodCgroup;
+use super::{Container, ContainerStatus, RestoreOptions, State};
 use crate::config::YoukiConfig;
+use crate::cpu_bandwidth::{self, CpuBandwidth};
 use crate::error::{ErrInvalidSpec, LibcontainerError, MissingSpecError};
 use crate::notify_socket::NOTIFY_FILE;
 use crate::process::args::ContainerType;
-use crate::{apparmor, tty, user_ns, utils};
+use crate::{hooks, notify_proxy, rootless, tty, user_ns, utils, validation};
+
+/// Annotation to choose the cgroup manager of a single container, which takes
+/// precedence over the one set with
+/// [`with_cgroup_manager`](InitContainerBuilder::with_cgroup_manager). The
+/// value is one of `cgroupfs`, `systemd` or `auto`.
+pub const CGROUP_MANAGER_ANNOTATION: &str = "org.youki.cgroup-manager";
 
 // Builder that can be used to configure the properties of a new container
 pub struct InitContainerBuilder {
     base: ContainerBuilder,
     bundle: PathBuf,
-    use_systemd: bool,
+    cgroup_manager: CgroupManagerType,
     detached: bool,
+    no_pivot: bool,
+    no_new_keyring: bool,
+    notify_socket: Option<PathBuf>,
+    ignore_unsupported: bool,
+    pod_cgroup: Option<PodCgroup>,
 }
 
 impl InitContainerBuilder {
@@ -29,14 +44,30 @@ impl InitContainerBuilder {
         Self {
             base: builder,
             bundle,
-            use_systemd: true,
+            cgroup_manager: CgroupManagerType::Systemd,
             detached: true,
+            no_pivot: false,
+            no_new_keyring: false,
+            notify_socket: None,
+            ignore_unsupported: false,
+            pod_cgroup: None,
         }
     }
 
     /// Sets if systemd should be used for managing cgroups
     pub fn with_systemd(mut self, should_use: bool) -> Self {
-        self.use_systemd = should_use;
+        self.cgroup_manager = match should_use {
+            true => CgroupManagerType::Systemd,
+            false => CgroupManagerType::Cgroupfs,
+        };
+        self
+    }
+
+    /// Sets the manager of the cgroup of the container. The manager which is
+    /// eventually used is recorded in the state of the container, so that it
+    /// is managed the same way until it is deleted.
+    pub fn with_cgroup_manager(mut self, cgroup_manager: CgroupManagerType) -> Self {
+        self.cgroup_manager = cgroup_manager;
         self
     }
 
@@ -45,15 +76,76 @@ impl InitContainerBuilder {
         self
     }
 
+    /// Sets if the rootfs should be entered by moving it over / and
+    /// chrooting into it instead of using pivot_root
+    pub fn with_no_pivot(mut self, no_pivot: bool) -> Self {
+        self.no_pivot = no_pivot;
+        self
+    }
+
+    /// Sets if the container should inherit the session keyring of the
+    /// caller instead of creating a new one
+    pub fn with_no_new_keyring(mut self, no_new_keyring: bool) -> Self {
+        self.no_new_keyring = no_new_keyring;
+        self
+    }
+
+    /// Sets the notify socket of the host, usually given by NOTIFY_SOCKET.
+    /// The container gets a socket of its own, whose messages are forwarded
+    /// by a [`NotifyProxy`](crate::notify_proxy::NotifyProxy).
+    pub fn with_notify_socket<P: Into<PathBuf>>(mut self, path: Option<P>) -> Self {
+        self.notify_socket = path.map(|p| p.into());
+        self
+    }
+
+    /// Sets if the parts of the spec which are not supported for rootless
+    /// containers should be removed instead of failing, see
+    /// [`rootless`](crate::rootless). This can also be requested by the
+    /// [`IGNORE_UNSUPPORTED_ANNOTATION`](crate::rootless::IGNORE_UNSUPPORTED_ANNOTATION).
+    pub fn with_ignore_unsupported(mut self, ignore_unsupported: bool) -> Self {
+        self.ignore_unsupported = ignore_unsupported;
+        self
+    }
+
+    /// Makes the container a member of a pod, whose cgroup its own cgroup is
+    /// nested in, see [`PodCgroup`]. This can also be requested by the
+    /// [`POD_CGROUP_ANNOTATION`](super::pod_cgroup::POD_CGROUP_ANNOTATION),
+    /// which can't set the limits of the pod though. Pod cgroups are only
+    /// supported if the cgroups are managed by youki itself.
+    pub fn with_pod_cgroup(mut self, pod_cgroup: PodCgroup) -> Self {
+        self.pod_cgroup = Some(pod_cgroup);
+        self
+    }
+
     /// Creates a new container
     pub fn build(self) -> Result<Container, LibcontainerError> {
-        let spec = self.load_spec()?;
+        let mut spec = self.load_spec()?;
+        let use_systemd = self.use_systemd(&spec);
+        let user_ns_config = UserNamespaceConfig::new(&spec)?;
+        let pod_cgroup = self.pod_cgroup(&spec);
+        if let Some(pod_cgroup) = &pod_cgroup {
+            self.nest_in_pod(
+                &mut spec,
+                pod_cgroup,
+                use_systemd || user_ns_config.is_some(),
+            )?;
+        }
         let container_dir = self.create_container_dir()?;
+        // the lock is only held until the state is saved, as the processes of
+        // the container would inherit it
+        let lock = State::lock(&container_dir)?;
+        if self.notify_socket.is_some() {
+            notify_proxy::setup_spec(&mut spec, &container_dir)?;
+        }
 
         let mut container = self.create_container_state(&container_dir)?;
+        drop(lock);
+        container.observers = self.base.observers.clone();
         container
-            .set_systemd(self.use_systemd)
-            .set_annotations(spec.annotations().clone());
+            .set_systemd(use_systemd)
+            .set_annotations(spec.annotations().clone())
+            .set_cpu_bandwidth(cpu_bandwidth::cpu_of(&spec).and_then(CpuBandwidth::of))
+            .set_pod_cgroup(pod_cgroup.as_ref().map(|pod| pod.path().to_owned()));
 
         let notify_path = container_dir.join(NOTIFY_FILE);
         // convert path of root file system of the container to absolute path
@@ -72,34 +164,101 @@ impl InitContainerBuilder {
             None
         };
 
-        let user_ns_config = UserNamespaceConfig::new(&spec)?;
-
         let config = YoukiConfig::from_spec(&spec, container.id())?;
         config.save(&container_dir).map_err(|err| {
             tracing::error!(?container_dir, "failed to save config: {}", err);
             err
         })?;
+        Self::guard_cgroup(&mut container, &container_dir, &config, use_systemd)?;
 
+        let seccomp_cache = self.base.seccomp_cache_dir();
         let mut builder_impl = ContainerBuilderImpl {
             container_type: ContainerType::InitContainer,
             syscall: self.base.syscall,
             container_id: self.base.container_id,
             pid_file: self.base.pid_file,
             console_socket: csocketfd,
-            use_systemd: self.use_systemd,
+            stdio: self.base.stdio.raw_fds(),
+            use_systemd,
             spec: Rc::new(spec),
             rootfs,
             user_ns_config,
             notify_path,
             container: Some(container.clone()),
             preserve_fds: self.base.preserve_fds,
+            listen_fds: utils::listen_fds(),
             detached: self.detached,
+            no_pivot: self.no_pivot,
+            no_new_keyring: self.no_new_keyring,
             executor: self.base.executor,
+            seccomp_cache,
+            pod_cgroup,
         };
 
         builder_impl.create()?;
 
         container.refresh_state()?;
+        container
+            .observers
+            .notify(|observer| observer.on_created(&container));
+
+        Ok(container)
+    }
+
+    /// Creates a new container from a checkpoint image instead of starting
+    /// the process specified in the spec. As the restored container is
+    /// running already, its poststart hooks are run once it has been restored.
+    pub fn restore(self, opts: &RestoreOptions) -> Result<Container, LibcontainerError> {
+        let spec = self.load_spec()?;
+        let use_systemd = self.use_systemd(&spec);
+        let container_dir = self.create_container_dir()?;
+
+        let mut container = self.create_container_state(&container_dir)?;
+        container.observers = self.base.observers.clone();
+        container
+            .set_systemd(use_systemd)
+            .set_annotations(spec.annotations().clone())
+            .set_cpu_bandwidth(cpu_bandwidth::cpu_of(&spec).and_then(CpuBandwidth::of));
+
+        let config = YoukiConfig::from_spec(&spec, container.id())?;
+        config.save(&container_dir).map_err(|err| {
+            tracing::error!(?container_dir, "failed to save config: {}", err);
+            err
+        })?;
+        Self::guard_cgroup(&mut container, &container_dir, &config, use_systemd)?;
+
+        if let Err(err) = container.restore(opts) {
+            // Nothing has been restored, so there is nothing to keep around.
+            if let Err(e) = fs::remove_dir_all(&container_dir) {
+                tracing::error!(?container_dir, error = ?e, "failed to delete container root");
+            }
+            return Err(err);
+        }
+
+        if let Some(pid_file) = &self.base.pid_file {
+            let pid = container.pid().ok_or(LibcontainerError::Other(
+                "container process pid not found in state".into(),
+            ))?;
+            fs::write(pid_file, format!("{pid}")).map_err(|err| {
+                tracing::error!("failed to write pid to file: {}", err);
+                LibcontainerError::OtherIO(err)
+            })?;
+        }
+
+        container
+            .observers
+            .notify(|observer| observer.on_started(&container));
+        if let Some(hooks) = config.hooks.as_ref() {
+            hooks::run_hooks(
+                hooks.poststart().as_ref(),
+                Some(&container),
+                Some(&container_dir),
+            )
+            .map_err(|err| {
+                tracing::error!("failed to run post start hooks: {}", err);
+                err
+            })?;
+        }
 
         Ok(container)
     }
@@ -128,9 +287,13 @@ impl InitContainerBuilder {
     fn load_spec(&self) -> Result<Spec, LibcontainerError> {
         let source_spec_path = self.bundle.join("config.json");
         let mut spec = Spec::load(source_spec_path)?;
-        println!("validating spec...");
-        Self::validate_spec(&spec)?;
-        println!("validated spec! :)");
+        if utils::rootless_required().map_err(LibcontainerError::OtherIO)? {
+            self.check_rootless(&mut spec)?;
+        }
+        if cpu_bandwidth::round_requested(spec.annotations().as_ref()) {
+            cpu_bandwidth::round_spec(&mut spec);
+        }
+        Self::validate_spec(&spec, self.base.strict_env)?;
 
         spec.canonicalize_rootfs(&self.bundle).map_err(|err| {
             tracing::error!(bundle = ?self.bundle, "failed to canonicalize rootfs: {}", err);
@@ -140,52 +303,108 @@ impl InitContainerBuilder {
         Ok(spec)
     }
 
-    fn validate_spec(spec: &Spec) -> Result<(), LibcontainerError> {
-        let version = spec.version();
-        if !version.starts_with("1.") {
-            tracing::error!(
-                "runtime spec has incompatible version '{}'. Only 1.X.Y is supported",
-                spec.version()
-            );
-            Err(ErrInvalidSpec::UnsupportedVersion)?;
+    /// Reports all parts of the spec that a rootless container can't
+    /// provide at once, unless they should be ignored
+    fn check_rootless(&self, spec: &mut Spec) -> Result<(), LibcontainerError> {
+        let unsupported = rootless::unsupported(spec, &rootless::delegated_controllers());
+        if unsupported.is_empty() {
+            return Ok(());
         }
 
-        println!("check process...");
-        if let Some(process) = spec.process() {
-            println!("check apparmor...");
-            if let Some(profile) = process.apparmor_profile() {
-                let apparmor_is_enabled = false;
-                if !apparmor_is_enabled {
-                    tracing::error!(?profile,
-                        "apparmor profile exists in the spec, but apparmor is not activated on this system");
-                    Err(ErrInvalidSpec::AppArmorNotEnabled)?;
-                }
+        if self.ignore_unsupported
+            || rootless::ignore_unsupported_requested(spec.annotations().as_ref())
+        {
+            for u in &unsupported {
+                tracing::warn!("ignoring {u}, which is not supported for rootless containers");
             }
+            rootless::remove_unsupported(spec, &unsupported);
+            return Ok(());
+        }
 
-            println!("check io_priority...");
-            if let Some(io_priority) = process.io_priority() {
-                let priority = io_priority.priority();
-                let iop_class_res = serde_json::to_string(&io_priority.class());
-                match iop_class_res {
-                    Ok(iop_class) => {
-                        if !(0..=7).contains(&priority) {
-                            println!("Err(ErrInvalidSpec::IoPriority)?; #1");
-                            tracing::error!(?priority, "io priority '{}' not between 0 and 7 (inclusive), class '{}' not in (IO_PRIO_CLASS_RT,IO_PRIO_CLASS_BE,IO_PRIO_CLASS_IDLE)",priority, iop_class);
-                            Err(ErrInvalidSpec::IoPriority)?;
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!(?priority, ?e, "failed to parse io priority class");
-                        println!("Err(ErrInvalidSpec::IoPriority)?; #2");
-                        Err(ErrInvalidSpec::IoPriority)?;
-                    }
-                }
-            }
+        let report = rootless::report(&unsupported);
+        tracing::error!(%report, "spec is not supported for rootless containers");
+        Err(ErrInvalidSpec::Rootless(report))?
+    }
+
+    /// Reports all problems of the spec at once, before anything is created
+    /// for the container
+    fn validate_spec(spec: &Spec, strict_env: bool) -> Result<(), LibcontainerError> {
+        let mut invalid = validation::check(spec);
+        if strict_env {
+            invalid.extend(validation::check_env(spec));
+        }
+        if invalid.is_empty() {
+            return Ok(());
         }
 
-        println!("utils::validate_spec_for_new_user_ns(spec)?;");
-        utils::validate_spec_for_new_user_ns(spec)?;
+        let report = validation::report(&invalid);
+        tracing::error!(%report, "invalid runtime spec");
+        Err(ErrInvalidSpec::Invalid(report))?
+    }
 
+    /// Records the cgroup of the container, which can be removed by
+    /// [`collect_garbage`](super::collect_garbage) if the container directory
+    /// goes missing
+    fn guard_cgroup(
+        container: &mut Container,
+        container_dir: &Path,
+        config: &YoukiConfig,
+        use_systemd: bool,
+    ) -> Result<(), LibcontainerError> {
+        let guard = CgroupGuard::new(container.id(), &config.cgroup_path, use_systemd);
+        if let Some(root_path) = container_dir.parent() {
+            guard.record(root_path)?;
+        }
+        container.set_systemd_unit(guard.systemd_unit().map(ToOwned::to_owned));
+
+        Ok(())
+    }
+
+    /// Decides on the cgroup manager, which the spec can override for the
+    /// container with an annotation
+    fn use_systemd(&self, spec: &Spec) -> bool {
+        let annotation = spec
+            .annotations()
+            .as_ref()
+            .and_then(|a| a.get(CGROUP_MANAGER_ANNOTATION));
+        let cgroup_manager = match annotation.map(|value| value.parse()) {
+            Some(Ok(cgroup_manager)) => cgroup_manager,
+            Some(Err(err)) => {
+                tracing::warn!("ignoring {}: {}", CGROUP_MANAGER_ANNOTATION, err);
+                self.cgroup_manager
+            }
+            None => self.cgroup_manager,
+        };
+        cgroup_manager.use_systemd()
+    }
+
+    fn pod_cgroup(&self, spec: &Spec) -> Option<PodCgroup> {
+        self.pod_cgroup
+            .clone()
+            .or_else(|| PodCgroup::from_annotations(spec.annotations().as_ref()))
+    }
+
+    /// Nests the cgroup of the container in the pod cgroup. The cgroups
+    /// created by systemd are placed in slices instead, which youki can't
+    /// create pods of.
+    fn nest_in_pod(
+        &self,
+        spec: &mut Spec,
+        pod_cgroup: &PodCgroup,
+        systemd_cgroup: bool,
+    ) -> Result<(), LibcontainerError> {
+        if systemd_cgroup {
+            tracing::error!(pod = ?pod_cgroup.path(), "pod cgroups require the cgroupfs manager");
+            return Err(LibcontainerError::InvalidInput(
+                "pod cgroups require the cgroupfs cgroup manager".to_owned(),
+            ));
+        }
+
+        let linux = spec.linux_mut().as_mut().ok_or(MissingSpecError::Linux)?;
+        let cgroups_path =
+            pod_cgroup.container_path(linux.cgroups_path().as_deref(), &self.base.container_id);
+        tracing::debug!(pod = ?pod_cgroup.path(), ?cgroups_path, "nesting cgroup in pod");
+        linux.set_cgroups_path(Some(cgroups_path));
         Ok(())
     }
 
@@ -201,3 +420,32 @@ impl InitContainerBuilder {
         Ok(container)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oci_spec::runtime::SpecBuilder;
+
+    use super::*;
+    use crate::syscall::syscall::SyscallType;
+
+    #[test]
+    fn test_cgroup_manager_annotation() {
+        let builder = ContainerBuilder::new("74f1a4cb3801".to_owned(), SyscallType::default())
+            .as_init("/var/lib/youki/bundle")
+            .with_cgroup_manager(CgroupManagerType::Auto);
+        let spec = |manager: &str| {
+            let annotations: HashMap<String, String> =
+                [(CGROUP_MANAGER_ANNOTATION.to_owned(), manager.to_owned())].into();
+            SpecBuilder::default()
+                .annotations(annotations)
+                .build()
+                .unwrap()
+        };
+
+        assert!(!builder.use_systemd(&spec("cgroupfs")));
+        let builder = builder.with_cgroup_manager(CgroupManagerType::Cgroupfs);
+        assert!(!builder.use_systemd(&spec("invalid")));
+    }
+}
@@ -0,0 +1,151 @@
+//! Notifications about the lifecycle of a container for applications which
+//! embed libcontainer, so that they don't have to poll the container state
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use nix::sys::signal::Signal;
+use nix::sys::wait::WaitStatus;
+
+use super::Container;
+
+/// How the init process of a container has exited, see [`Container::wait`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitInfo {
+    /// Exit code of the init process, unless it has been killed by a signal
+    pub exit_code: Option<i32>,
+    /// Signal which killed the init process
+    pub signal: Option<Signal>,
+    /// Whether processes of the container have been killed by the out of
+    /// memory killer
+    pub oom_killed: bool,
+}
+
+impl ExitInfo {
+    /// Exit of a process as reported by waitpid, None if the process has not
+    /// exited, e.g. because it has only been stopped
+    pub fn from_wait_status(status: WaitStatus) -> Option<Self> {
+        let (exit_code, signal) = match status {
+            WaitStatus::Exited(_, code) => (Some(code), None),
+            WaitStatus::Signaled(_, signal, _) => (None, Some(signal)),
+            _ => return None,
+        };
+
+        Some(Self {
+            exit_code,
+            signal,
+            oom_killed: false,
+        })
+    }
+
+    /// Exit status in the format of a shell, i.e. 128 plus the number of the
+    /// signal for a process killed by a signal
+    pub fn status(&self) -> i32 {
+        match (self.exit_code, self.signal) {
+            (Some(code), _) => code,
+            (None, Some(signal)) => 128 + signal as i32,
+            (None, None) => 0,
+        }
+    }
+}
+
+/// Observer of the lifecycle transitions of a container. The methods are
+/// called by the process which performs the transition, right after the new
+/// state has been saved. All methods do nothing by default, so that only the
+/// transitions of interest have to be implemented.
+///
+/// # Example
+///
+/// ```no_run
+/// use libcontainer::container::{Container, ExitInfo, LifecycleObserver};
+///
+/// struct ExitLogger;
+///
+/// impl LifecycleObserver for ExitLogger {
+///     fn on_exited(&self, container: &Container, exit: &ExitInfo) {
+///         println!("container {} exited with {}", container.id(), exit.status());
+///     }
+/// }
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut container = Container::load_from_root("/run/youki", "74f1a4cb3801")?;
+/// container.add_observer(ExitLogger);
+/// container.wait()?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait LifecycleObserver {
+    /// The container has been created and waits to be started
+    fn on_created(&self, _container: &Container) {}
+
+    /// The container process has been started
+    fn on_started(&self, _container: &Container) {}
+
+    /// The processes of the container have been frozen
+    fn on_paused(&self, _container: &Container) {}
+
+    /// The processes of the container have been thawed
+    fn on_resumed(&self, _container: &Container) {}
+
+    /// The init process of the container has exited
+    fn on_exited(&self, _container: &Container, _exit: &ExitInfo) {}
+
+    /// The container has been deleted
+    fn on_deleted(&self, _container: &Container) {}
+}
+
+/// Observers registered for a container
+#[derive(Clone, Default)]
+pub(crate) struct Observers(Vec<Rc<dyn LifecycleObserver>>);
+
+impl Observers {
+    pub(crate) fn add(&mut self, observer: Rc<dyn LifecycleObserver>) {
+        self.0.push(observer);
+    }
+
+    pub(crate) fn notify<F: Fn(&dyn LifecycleObserver)>(&self, f: F) {
+        for observer in &self.0 {
+            f(observer.as_ref());
+        }
+    }
+}
+
+impl Debug for Observers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_status() {
+        let exit = |exit_code, signal| ExitInfo {
+            exit_code,
+            signal,
+            oom_killed: false,
+        };
+        assert_eq!(exit(Some(0), None).status(), 0);
+        assert_eq!(exit(Some(3), None).status(), 3);
+        assert_eq!(exit(None, Some(Signal::SIGKILL)).status(), 137);
+    }
+
+    #[test]
+    fn test_from_wait_status() {
+        let pid = nix::unistd::Pid::from_raw(1);
+        assert_eq!(
+            ExitInfo::from_wait_status(WaitStatus::Signaled(pid, Signal::SIGTERM, false))
+                .map(|exit| exit.status()),
+            Some(143)
+        );
+        assert_eq!(
+            ExitInfo::from_wait_status(WaitStatus::Exited(pid, 2)).map(|exit| exit.status()),
+            Some(2)
+        );
+        assert_eq!(
+            ExitInfo::from_wait_status(WaitStatus::Stopped(pid, Signal::SIGSTOP)),
+            None
+        );
+    }
+}
@@ -6,18 +6,38 @@
 /// the exec command).
 pub mod builder;
 mod builder_impl;
+pub mod cgroup_guard;
 #[allow(clippy::module_inception)]
 mod container;
+#[cfg(feature = "async")]
+mod container_async;
+mod container_cgroup_config;
 mod container_checkpoint;
 mod container_delete;
 mod container_events;
+mod container_keep;
 mod container_kill;
 mod container_pause;
+mod container_restore;
 mod container_resume;
 mod container_start;
+mod container_wait;
+mod exec_process;
 pub mod init_builder;
+mod lifecycle;
+pub mod pod_cgroup;
 pub mod state;
 pub mod tenant_builder;
-pub use container::{CheckpointOptions, Container};
-pub use container_checkpoint::CheckpointError;
-pub use state::{ContainerProcessState, ContainerStatus, State};
+pub use cgroup_guard::{collect_garbage, CgroupGuard};
+pub use container::{CheckpointOptions, Container, RestoreOptions};
+#[cfg(feature = "async")]
+pub use container_async::AsyncContainer;
+pub use container_cgroup_config::CGROUP_CONFIG_FILE;
+pub(crate) use container_cgroup_config::{load_cgroup_config, save_cgroup_config};
+pub use container_checkpoint::{CheckpointError, PageServer};
+pub use container_events::{Event, EventData};
+pub use container_keep::KEPT_MOUNT_NAMESPACE;
+pub use exec_process::EXEC_PROCESS_DIR;
+pub use lifecycle::{ExitInfo, LifecycleObserver};
+pub use pod_cgroup::{PodCgroup, POD_CGROUP_ANNOTATION};
+pub use state::{ContainerProcessState, ContainerStatus, State, StateLock};
@@ -0,0 +1,254 @@
+//! Cgroup shared by the containers of a pod. The cgroup of every container
+//! of the pod is nested in the pod cgroup, so that the limits of the pod are
+//! applied once to all of its containers, while the limits of each container
+//! apply to its own cgroup only.
+//!
+//! The pod cgroup is owned by youki. It is created along with the cgroup of
+//! its first container, which applies the limits of the pod, and removed
+//! after the cgroup of its last container. The containers which are members
+//! of a pod are recorded in `<root>/.pods/<pod>`, the pods of different root
+//! directories are managed apart from each other.
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use libcgroups::common::{CgroupManager, ControllerOpt};
+use nix::fcntl::{Flock, FlockArg};
+use oci_spec::runtime::LinuxResources;
+
+use crate::error::LibcontainerError;
+
+/// Annotation to make a container a member of a pod, whose value is the path
+/// of the pod cgroup. A pod cgroup set with
+/// [`with_pod_cgroup`](super::init_builder::InitContainerBuilder::with_pod_cgroup)
+/// takes precedence.
+pub const POD_CGROUP_ANNOTATION: &str = "io.kubernetes.cri.sandbox-cgroup";
+
+/// Directory in the root directory which the members of the pods are
+/// recorded in
+pub const POD_CGROUP_DIR: &str = ".pods";
+
+/// Cgroup of a pod, which its containers are nested in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PodCgroup {
+    path: PathBuf,
+    resources: Option<LinuxResources>,
+}
+
+impl PodCgroup {
+    /// The path is relative to the root of the cgroup hierarchy, like the
+    /// cgroups path of a spec
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: Path::new("/").join(path),
+            resources: None,
+        }
+    }
+
+    /// Sets the limits of the pod, which are applied when the pod cgroup is
+    /// created. Joining an existing pod doesn't change its limits.
+    pub fn with_resources(mut self, resources: LinuxResources) -> Self {
+        self.resources = Some(resources);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(super) fn from_annotations(annotations: Option<&HashMap<String, String>>) -> Option<Self> {
+        annotations
+            .and_then(|a| a.get(POD_CGROUP_ANNOTATION))
+            .filter(|path| !path.is_empty())
+            .map(Self::new)
+    }
+
+    /// Returns the path of the cgroup of a container in the pod. The cgroups
+    /// path of the spec is kept if it is in the pod already, as the one a CRI
+    /// runtime passes usually is, and nested in the pod otherwise.
+    pub(super) fn container_path(
+        &self,
+        cgroups_path: Option<&Path>,
+        container_id: &str,
+    ) -> PathBuf {
+        match cgroups_path {
+            Some(path) if path.starts_with(&self.path) => path.to_owned(),
+            Some(path) => self.path.join(path.strip_prefix("/").unwrap_or(path)),
+            None => self.path.join(container_id),
+        }
+    }
+
+    /// Records the container as a member of the pod and returns whether it
+    /// is the first one, which has to apply the limits of the pod
+    pub(super) fn join(
+        &self,
+        root_path: &Path,
+        container_id: &str,
+    ) -> Result<bool, LibcontainerError> {
+        let _lock = lock_pods(root_path)?;
+        let members = self.members_dir(root_path);
+        let first = is_empty(&members)?;
+        fs::create_dir_all(&members).map_err(LibcontainerError::OtherIO)?;
+        File::create(members.join(container_id)).map_err(LibcontainerError::OtherIO)?;
+        tracing::debug!(pod = ?self.path, container_id, first, "joined pod cgroup");
+
+        Ok(first)
+    }
+
+    /// Applies the limits of the pod to the pod cgroup, which exists once
+    /// the cgroup of a container has been created in it
+    pub(super) fn apply(&self, container_id: &str) -> Result<(), LibcontainerError> {
+        let resources = match &self.resources {
+            Some(resources) => resources,
+            None => return Ok(()),
+        };
+        let cmanager = self.cgroup_manager(container_id)?;
+        cmanager.apply(&ControllerOpt {
+            resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+        })?;
+
+        Ok(())
+    }
+
+    /// Removes the container from the members of the pod, after its own
+    /// cgroup has been removed, and removes the pod cgroup along with its
+    /// last member
+    pub(super) fn leave(
+        &self,
+        root_path: &Path,
+        container_id: &str,
+    ) -> Result<(), LibcontainerError> {
+        let _lock = lock_pods(root_path)?;
+        let members = self.members_dir(root_path);
+        match fs::remove_file(members.join(container_id)) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(LibcontainerError::OtherIO(err)),
+        }
+        if !is_empty(&members)? {
+            tracing::debug!(pod = ?self.path, container_id, "left pod cgroup");
+            return Ok(());
+        }
+
+        // the removal kills all processes in the cgroup, which is fine as no
+        // container is left in the pod
+        self.cgroup_manager(container_id)?.remove()?;
+        match fs::remove_dir(&members) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(LibcontainerError::OtherIO(err)),
+        }
+        tracing::debug!(pod = ?self.path, container_id, "removed pod cgroup");
+
+        Ok(())
+    }
+
+    fn cgroup_manager(
+        &self,
+        container_id: &str,
+    ) -> Result<libcgroups::common::AnyCgroupManager, LibcontainerError> {
+        let cmanager =
+            libcgroups::common::create_cgroup_manager(libcgroups::common::CgroupConfig {
+                cgroup_path: self.path.clone(),
+                systemd_cgroup: false,
+                container_name: container_id.to_owned(),
+                leaf_cgroup: None,
+                best_effort_controllers: Vec::new(),
+            })?;
+        Ok(cmanager)
+    }
+
+    fn members_dir(&self, root_path: &Path) -> PathBuf {
+        root_path.join(POD_CGROUP_DIR).join(escape(&self.path))
+    }
+}
+
+// the pods are locked all at once, as the members of several pods are only
+// changed by the creation and deletion of containers
+fn lock_pods(root_path: &Path) -> Result<Flock<File>, LibcontainerError> {
+    let dir = root_path.join(POD_CGROUP_DIR);
+    fs::create_dir_all(&dir).map_err(LibcontainerError::OtherIO)?;
+    let dir = File::open(&dir).map_err(LibcontainerError::OtherIO)?;
+    Flock::lock(dir, FlockArg::LockExclusive)
+        .map_err(|(_, err)| LibcontainerError::OtherSyscall(err))
+}
+
+fn is_empty(dir: &Path) -> Result<bool, LibcontainerError> {
+    match fs::read_dir(dir) {
+        Ok(mut entries) => Ok(entries.next().is_none()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(true),
+        Err(err) => Err(LibcontainerError::OtherIO(err)),
+    }
+}
+
+// the path is flattened into a single file name, so that the directory of a
+// pod never contains the directory of a pod nested in it
+fn escape(path: &Path) -> String {
+    path.to_string_lossy()
+        .trim_matches('/')
+        .replace('%', "%25")
+        .replace('/', "%2F")
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_container_path() {
+        let pod = PodCgroup::new("kubepods/pod1");
+        assert_eq!(pod.path(), Path::new("/kubepods/pod1"));
+        assert_eq!(
+            pod.container_path(Some(Path::new("/kubepods/pod1/c1")), "c1"),
+            PathBuf::from("/kubepods/pod1/c1")
+        );
+        assert_eq!(
+            pod.container_path(Some(Path::new("/youki/c1")), "c1"),
+            PathBuf::from("/kubepods/pod1/youki/c1")
+        );
+        assert_eq!(
+            pod.container_path(None, "c1"),
+            PathBuf::from("/kubepods/pod1/c1")
+        );
+    }
+
+    #[test]
+    fn test_from_annotations() {
+        let mut annotations = HashMap::new();
+        assert_eq!(PodCgroup::from_annotations(Some(&annotations)), None);
+        annotations.insert(
+            POD_CGROUP_ANNOTATION.to_owned(),
+            "/kubepods/pod1".to_owned(),
+        );
+        assert_eq!(
+            PodCgroup::from_annotations(Some(&annotations)),
+            Some(PodCgroup::new("/kubepods/pod1"))
+        );
+    }
+
+    #[test]
+    fn test_membership() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let pod = PodCgroup::new("/youki-test/pod");
+        let nested = PodCgroup::new("/youki-test/pod/nested");
+        assert!(pod.join(root.path(), "c1")?);
+        assert!(!pod.join(root.path(), "c2")?);
+        assert!(nested.join(root.path(), "c3")?);
+
+        pod.leave(root.path(), "c1")?;
+        assert!(pod.members_dir(root.path()).join("c2").exists());
+        // the pod cgroup doesn't exist, so there is nothing to remove
+        pod.leave(root.path(), "c2")?;
+        assert!(!pod.members_dir(root.path()).exists());
+        assert!(nested.members_dir(root.path()).join("c3").exists());
+        // leaving twice, e.g. when a forced delete is repeated, does nothing
+        pod.leave(root.path(), "c2")?;
+        Ok(())
+    }
+}
@@ -1,15 +1,18 @@
 //! Information about status and state of the container
 use std::collections::HashMap;
 use std::fmt::Display;
-use std::fs;
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::{fs, process};
 
 use chrono::{DateTime, Utc};
+use nix::fcntl::{Flock, FlockArg};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
+use crate::cpu_bandwidth::CpuBandwidth;
+
 /// Indicates status of the container
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
 #[serde(rename_all = "camelCase")]
@@ -84,6 +87,11 @@ pub enum StateError {
         state_file_path: PathBuf,
         source: std::io::Error,
     },
+    #[error("failed to lock container state in {container_root:?}")]
+    Lock {
+        container_root: PathBuf,
+        source: std::io::Error,
+    },
 }
 
 type Result<T> = std::result::Result<T, StateError>;
@@ -101,6 +109,10 @@ pub struct State {
     // Pid is the process ID for the container process.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pid: Option<i32>,
+    // Start time of the container process in clock ticks since boot, which
+    // tells whether its pid has been reused by another process
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub init_process_start_time: Option<u64>,
     // Bundle is the path to the container's bundle directory.
     pub bundle: PathBuf,
     // Annotations are key values associated with the container.
@@ -112,10 +124,28 @@ pub struct State {
     // User that created the container
     #[serde(skip_serializing_if = "Option::is_none")]
     pub creator: Option<u32>,
+    // Exit status of the init process once it has been reaped, which is 128
+    // plus the number of the signal for a process killed by a signal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<i32>,
+    // Whether processes of the container have been killed by the out of
+    // memory killer, like the OOMKilled reported by Docker
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub oom_killed: bool,
     // Specifies if systemd should be used to manage cgroups
     pub use_systemd: bool,
     // Specifies if the Intel RDT subdirectory needs be cleaned up.
     pub clean_up_intel_rdt_subdirectory: Option<bool>,
+    // Path of the cgroup of the pod the container is a member of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pod_cgroup: Option<PathBuf>,
+    // Name of the transient systemd unit of the container
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub systemd_unit: Option<String>,
+    // Cpu period and quota the cgroup of the container has been given, which
+    // differ from the spec if they have been rounded into the kernel bounds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_bandwidth: Option<CpuBandwidth>,
 }
 
 impl State {
@@ -132,25 +162,54 @@ impl State {
             id: container_id.to_string(),
             status,
             pid,
+            init_process_start_time: None,
             bundle,
             annotations: Some(HashMap::default()),
             created: None,
             creator: None,
+            exit_status: None,
+            oom_killed: false,
             use_systemd: false,
             clean_up_intel_rdt_subdirectory: None,
+            pod_cgroup: None,
+            systemd_unit: None,
+            cpu_bandwidth: None,
         }
     }
 
+    /// Saves the state, the file is replaced atomically so that a crash never
+    /// leaves a partially written state behind
     #[instrument(level = "trace")]
     pub fn save(&self, container_root: &Path) -> Result<()> {
         let state_file_path = Self::file_path(container_root);
+        let tmp_file_path =
+            container_root.join(format!(".{}.{}", Self::STATE_FILE_PATH, process::id()));
+        let result = self.write(&tmp_file_path).and_then(|_| {
+            fs::rename(&tmp_file_path, &state_file_path).map_err(|err| {
+                tracing::error!(
+                    ?state_file_path,
+                    %err,
+                    "failed to replace container state file",
+                );
+                StateError::WriteStateFile {
+                    state_file_path: state_file_path.to_owned(),
+                    source: err,
+                }
+            })
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_file_path);
+        }
+
+        result
+    }
+
+    fn write(&self, state_file_path: &Path) -> Result<()> {
         let file = fs::OpenOptions::new()
-            .read(true)
             .write(true)
-            .append(false)
             .create(true)
             .truncate(true)
-            .open(&state_file_path)
+            .open(state_file_path)
             .map_err(|err| {
                 tracing::error!(
                     state_file_path = ?state_file_path,
@@ -174,17 +233,20 @@ impl State {
                 source: err,
             }
         })?;
-        writer.flush().map_err(|err| {
-            tracing::error!(
-                ?state_file_path,
-                %err,
-                "failed to write container state file",
-            );
-            StateError::WriteStateFile {
-                state_file_path: state_file_path.to_owned(),
-                source: err,
-            }
-        })?;
+        writer
+            .flush()
+            .and_then(|_| writer.get_ref().sync_all())
+            .map_err(|err| {
+                tracing::error!(
+                    ?state_file_path,
+                    %err,
+                    "failed to write container state file",
+                );
+                StateError::WriteStateFile {
+                    state_file_path: state_file_path.to_owned(),
+                    source: err,
+                }
+            })?;
 
         Ok(())
     }
@@ -218,6 +280,22 @@ impl State {
         Ok(state)
     }
 
+    /// Takes an exclusive lock of the state in `container_root`, which is held
+    /// until the returned lock is dropped. The lock is advisory, it only
+    /// serializes the state transitions of processes which take it as well.
+    pub fn lock(container_root: &Path) -> Result<StateLock> {
+        let lock_err = |err| StateError::Lock {
+            container_root: container_root.to_owned(),
+            source: err,
+        };
+        // the directory is locked as the state file is replaced on every save
+        let dir = File::open(container_root).map_err(lock_err)?;
+        let lock = Flock::lock(dir, FlockArg::LockExclusive)
+            .map_err(|(_, err)| lock_err(std::io::Error::from(err)))?;
+
+        Ok(StateLock { _lock: lock })
+    }
+
     /// Returns the path to the state JSON file for the provided `container_root`.
     ///
     /// ```
@@ -233,6 +311,12 @@ impl State {
     }
 }
 
+/// Lock of the state of a container, see [`State::lock`]
+#[derive(Debug)]
+pub struct StateLock {
+    _lock: Flock<File>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct ContainerProcessState {
@@ -252,6 +336,8 @@ pub struct ContainerProcessState {
 
 #[cfg(test)]
 mod tests {
+    use anyhow::Result;
+
     use super::*;
 
     #[test]
@@ -303,4 +389,62 @@ mod tests {
         assert!(!cstatus.can_pause());
         assert!(cstatus.can_resume());
     }
+
+    #[test]
+    fn test_save_replaces_state() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let mut state = State::new(
+            "container",
+            ContainerStatus::Created,
+            None,
+            "/bundle".into(),
+        );
+        state.save(tmp.path())?;
+        state.status = ContainerStatus::Running;
+        state.save(tmp.path())?;
+
+        assert_eq!(State::load(tmp.path())?.status, ContainerStatus::Running);
+        // no temporary file is left behind
+        assert_eq!(fs::read_dir(tmp.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_oom_killed() -> Result<()> {
+        let mut state = State::new(
+            "container",
+            ContainerStatus::Stopped,
+            None,
+            "/bundle".into(),
+        );
+        assert!(serde_json::to_value(&state)?.get("oomKilled").is_none());
+
+        state.oom_killed = true;
+        let value = serde_json::to_value(&state)?;
+        assert_eq!(value["oomKilled"], serde_json::json!(true));
+
+        // states saved by older versions don't have the field
+        let mut value = value;
+        value.as_object_mut().unwrap().remove("oomKilled");
+        assert!(!serde_json::from_value::<State>(value)?.oom_killed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let lock = State::lock(tmp.path())?;
+        let locked = |path: &Path| -> Result<bool> {
+            match Flock::lock(File::open(path)?, FlockArg::LockExclusiveNonblock) {
+                Ok(_) => Ok(false),
+                Err((_, nix::errno::Errno::EWOULDBLOCK)) => Ok(true),
+                Err((_, err)) => Err(err.into()),
+            }
+        };
+        assert!(locked(tmp.path())?);
+
+        drop(lock);
+        assert!(!locked(tmp.path())?);
+        Ok(())
+    }
 }
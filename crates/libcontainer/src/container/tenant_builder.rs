@@ -15,11 +15,12 @@ use nix::unistd::{pipe2, read, Pid};
 use oci_spec::runtime::{
     Capabilities as SpecCapabilities, Capability as SpecCapability, LinuxBuilder,
     LinuxCapabilities, LinuxCapabilitiesBuilder, LinuxNamespace, LinuxNamespaceBuilder,
-    LinuxNamespaceType, LinuxSchedulerPolicy, Process, ProcessBuilder, Spec,
+    LinuxNamespaceType, LinuxSeccomp, Process, ProcessBuilder, Spec, User,
 };
 use procfs::process::Namespace;
 
 use super::builder::ContainerBuilder;
+use super::exec_process::{self, ExecProcess};
 use super::Container;
 use crate::capabilities::CapabilityExt;
 use crate::container::builder_impl::ContainerBuilderImpl;
@@ -27,9 +28,15 @@ use crate::error::{ErrInvalidSpec, LibcontainerError, MissingSpecError};
 use crate::notify_socket::NotifySocket;
 use crate::process::args::ContainerType;
 use crate::user_ns::UserNamespaceConfig;
-use crate::{tty, utils};
+use crate::{tty, utils, validation};
 
-const NAMESPACE_TYPES: &[&str] = &["ipc", "uts", "net", "pid", "mnt", "cgroup"];
+/// Annotation of the container with the seccomp profile of the processes
+/// executed in it, as the path of a JSON file with the `linux.seccomp` of a
+/// spec, or `unconfined`. Without it, they get the profile of the container.
+pub const EXEC_SECCOMP_ANNOTATION: &str = "org.youki.exec.seccomp";
+const UNCONFINED: &str = "unconfined";
+
+const NAMESPACE_TYPES: &[&str] = &["ipc", "uts", "net", "pid", "mnt", "cgroup", "time"];
 const TENANT_NOTIFY: &str = "tenant-notify-";
 const TENANT_TTY: &str = "tenant-tty-";
 
@@ -42,8 +49,13 @@ pub struct TenantContainerBuilder {
     args: Vec<String>,
     no_new_privs: Option<bool>,
     capabilities: Vec<String>,
+    user: Option<String>,
+    additional_gids: Vec<u32>,
+    apparmor_profile: Option<String>,
+    process_label: Option<String>,
     process: Option<PathBuf>,
     detached: bool,
+    exec_id: Option<String>,
 }
 
 impl TenantContainerBuilder {
@@ -58,8 +70,13 @@ impl TenantContainerBuilder {
             args: Vec::new(),
             no_new_privs: None,
             capabilities: Vec::new(),
+            user: None,
+            additional_gids: Vec::new(),
+            apparmor_profile: None,
+            process_label: None,
             process: None,
             detached: false,
+            exec_id: None,
         }
     }
 
@@ -91,6 +108,37 @@ impl TenantContainerBuilder {
         self
     }
 
+    /// Sets the user of the process as `user[:group]`, both by id or by name.
+    /// Names are resolved with the /etc/passwd and /etc/group of the
+    /// container. Without a user, the process runs as the user of the
+    /// container process, including its umask and additional gids.
+    pub fn with_user(mut self, user: Option<String>) -> Self {
+        self.user = user;
+        self
+    }
+
+    /// Adds supplementary groups to the ones of the user
+    pub fn with_additional_gids(mut self, additional_gids: Vec<u32>) -> Self {
+        self.additional_gids = additional_gids;
+        self
+    }
+
+    /// Sets the apparmor profile of the process instead of the one of the
+    /// container process
+    pub fn with_apparmor_profile(mut self, profile: Option<String>) -> Self {
+        self.apparmor_profile = profile;
+        self
+    }
+
+    /// Sets the selinux label of the process instead of the one of the
+    /// container process
+    pub fn with_process_label(mut self, label: Option<String>) -> Self {
+        self.process_label = label;
+        self
+    }
+
+    /// Reads the process from a process.json instead of building it from the
+    /// other options, except for the apparmor profile and selinux label
     pub fn with_process<P: Into<PathBuf>>(mut self, path: Option<P>) -> Self {
         self.process = path.map(|p| p.into());
         self
@@ -101,9 +149,28 @@ impl TenantContainerBuilder {
         self
     }
 
+    /// Records the process under an id, by which it can be signaled with
+    /// [`Container::kill_exec`](super::Container::kill_exec) while it runs
+    pub fn with_exec_id(mut self, exec_id: Option<String>) -> Self {
+        self.exec_id = exec_id;
+        self
+    }
+
     /// Joins an existing container
     pub fn build(self) -> Result<Pid, LibcontainerError> {
         let container_dir = self.lookup_container_dir()?;
+        if let Some(exec_id) = &self.exec_id {
+            exec_process::validate_exec_id(exec_id)?;
+            let running = ExecProcess::load(&container_dir, exec_id)?
+                .map(|process| process.is_running())
+                .unwrap_or(false);
+            if running {
+                tracing::error!(?exec_id, "exec id is used by a running process");
+                return Err(LibcontainerError::InvalidInput(format!(
+                    "exec id {exec_id:?} is in use"
+                )));
+            }
+        }
         let container = self.load_container_state(container_dir.clone())?;
         let mut spec = self.load_init_spec(&container)?;
         self.adapt_spec_for_tenant(&mut spec, &container)?;
@@ -125,6 +192,7 @@ impl TenantContainerBuilder {
         let (read_end, write_end) =
             pipe2(OFlag::O_CLOEXEC).map_err(LibcontainerError::OtherSyscall)?;
 
+        let seccomp_cache = self.base.seccomp_cache_dir();
         let mut builder_impl = ContainerBuilderImpl {
             container_type: ContainerType::TenantContainer {
                 exec_notify_fd: write_end.as_raw_fd(),
@@ -133,6 +201,7 @@ impl TenantContainerBuilder {
             container_id: self.base.container_id,
             pid_file: self.base.pid_file,
             console_socket: csocketfd,
+            stdio: self.base.stdio.raw_fds(),
             use_systemd,
             spec: Rc::new(spec),
             rootfs,
@@ -140,8 +209,16 @@ impl TenantContainerBuilder {
             notify_path: notify_path.clone(),
             container: None,
             preserve_fds: self.base.preserve_fds,
+            // like runc, socket activation is only supported for the init process
+            listen_fds: 0,
             detached: self.detached,
+            // the process joins the already prepared rootfs and keyring of
+            // the container
+            no_pivot: false,
+            no_new_keyring: true,
             executor: self.base.executor,
+            seccomp_cache,
+            pod_cgroup: None,
         };
 
         let pid = builder_impl.create()?;
@@ -164,6 +241,9 @@ impl TenantContainerBuilder {
             match read(read_end.as_raw_fd(), &mut buf).map_err(LibcontainerError::OtherSyscall)? {
                 0 => {
                     if err_str_buf.is_empty() {
+                        if let Some(exec_id) = &self.exec_id {
+                            ExecProcess::new(pid).save(&container_dir, exec_id)?;
+                        }
                         return Ok(pid);
                     } else {
                         return Err(LibcontainerError::Other(
@@ -214,76 +294,11 @@ impl TenantContainerBuilder {
 
         if let Some(process) = spec.process() {
             if let Some(io_priority) = process.io_priority() {
-                let priority = io_priority.priority();
-                let iop_class_res = serde_json::to_string(&io_priority.class());
-                match iop_class_res {
-                    Ok(iop_class) => {
-                        if !(0..=7).contains(&priority) {
-                            tracing::error!(?priority, "io priority '{}' not between 0 and 7 (inclusive), class '{}' not in (IO_PRIO_CLASS_RT,IO_PRIO_CLASS_BE,IO_PRIO_CLASS_IDLE)",priority, iop_class);
-                            Err(ErrInvalidSpec::IoPriority)?;
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!(?priority, ?e, "failed to parse io priority class");
-                        Err(ErrInvalidSpec::IoPriority)?;
-                    }
-                }
+                utils::validate_io_priority(io_priority)?;
             }
 
-            if let Some(sc) = process.scheduler() {
-                let policy = sc.policy();
-                if let Some(nice) = sc.nice() {
-                    // https://man7.org/linux/man-pages/man2/sched_setattr.2.html#top_of_page
-                    if (*policy == LinuxSchedulerPolicy::SchedBatch
-                        || *policy == LinuxSchedulerPolicy::SchedOther)
-                        && (*nice < -20 || *nice > 19)
-                    {
-                        tracing::error!(
-                            ?nice,
-                            "invalid scheduler.nice: '{}', must be within -20 to 19",
-                            nice
-                        );
-                        Err(ErrInvalidSpec::Scheduler)?;
-                    }
-                }
-                if let Some(priority) = sc.priority() {
-                    if *priority != 0
-                        && (*policy != LinuxSchedulerPolicy::SchedFifo
-                            && *policy != LinuxSchedulerPolicy::SchedRr)
-                    {
-                        tracing::error!(?policy,"scheduler.priority can only be specified for SchedFIFO or SchedRR policy");
-                        Err(ErrInvalidSpec::Scheduler)?;
-                    }
-                }
-                if *policy != LinuxSchedulerPolicy::SchedDeadline {
-                    if let Some(runtime) = sc.runtime() {
-                        if *runtime != 0 {
-                            tracing::error!(
-                                ?runtime,
-                                "scheduler runtime can only be specified for SchedDeadline policy"
-                            );
-                            Err(ErrInvalidSpec::Scheduler)?;
-                        }
-                    }
-                    if let Some(deadline) = sc.deadline() {
-                        if *deadline != 0 {
-                            tracing::error!(
-                                ?deadline,
-                                "scheduler deadline can only be specified for SchedDeadline policy"
-                            );
-                            Err(ErrInvalidSpec::Scheduler)?;
-                        }
-                    }
-                    if let Some(period) = sc.period() {
-                        if *period != 0 {
-                            tracing::error!(
-                                ?period,
-                                "scheduler period can only be specified for SchedDeadline policy"
-                            );
-                            Err(ErrInvalidSpec::Scheduler)?;
-                        }
-                    }
-                }
+            if let Some(scheduler) = process.scheduler() {
+                utils::validate_scheduler(scheduler)?;
             }
         }
 
@@ -307,7 +322,7 @@ impl TenantContainerBuilder {
         spec: &mut Spec,
         container: &Container,
     ) -> Result<(), LibcontainerError> {
-        let process = if let Some(process) = &self.process {
+        let mut process = if let Some(process) = &self.process {
             self.get_process(process)?
         } else {
             let mut process_builder = ProcessBuilder::default()
@@ -321,12 +336,51 @@ impl TenantContainerBuilder {
                 process_builder = process_builder.no_new_privileges(no_new_priv);
             }
 
-            if let Some(caps) = self.get_capabilities(spec)? {
+            // the process is confined like the container process, unless
+            // capabilities are added
+            let init_process = spec.process().as_ref();
+            let caps = match self.get_capabilities(spec)? {
+                Some(caps) => Some(caps),
+                None => init_process.and_then(|p| p.capabilities().clone()),
+            };
+            if let Some(caps) = caps {
                 process_builder = process_builder.capabilities(caps);
             }
+            if let Some(profile) = init_process.and_then(|p| p.apparmor_profile().clone()) {
+                process_builder = process_builder.apparmor_profile(profile);
+            }
+            if let Some(label) = init_process.and_then(|p| p.selinux_label().clone()) {
+                process_builder = process_builder.selinux_label(label);
+            }
 
-            process_builder.build()?
+            let mut process = process_builder.user(self.get_user(spec)).build()?;
+            // the builder only limits the open files by default
+            if let Some(init_process) = init_process {
+                process.set_rlimits(init_process.rlimits().clone());
+            }
+            process
         };
+        if let Some(profile) = &self.apparmor_profile {
+            process.set_apparmor_profile(Some(profile.clone()));
+        }
+        if let Some(label) = &self.process_label {
+            process.set_selinux_label(Some(label.clone()));
+        }
+
+        // like runc, exec'd processes get the OOM score and the exec cpu
+        // affinity of the container if none is given, instead of inheriting
+        // the ones of the caller
+        if process.oom_score_adj().is_none() {
+            let oom_score_adj = spec.process().as_ref().and_then(|p| p.oom_score_adj());
+            process.set_oom_score_adj(oom_score_adj);
+        }
+        if process.exec_cpu_affinity().is_none() {
+            let exec_cpu_affinity = spec
+                .process()
+                .as_ref()
+                .and_then(|p| p.exec_cpu_affinity().clone());
+            process.set_exec_cpu_affinity(exec_cpu_affinity);
+        }
 
         let container_pid = container.pid().ok_or(LibcontainerError::Other(
             "could not retrieve container init pid".into(),
@@ -342,12 +396,49 @@ impl TenantContainerBuilder {
         if let Some(ref cgroup_path) = spec_linux.cgroups_path() {
             linux_builder = linux_builder.cgroups_path(cgroup_path.clone());
         }
+        // the execution domain of the container applies to its exec processes too
+        if let Some(ref personality) = spec_linux.personality() {
+            linux_builder = linux_builder.personality(personality.clone());
+        }
+        if let Some(seccomp) = Self::get_seccomp(spec)? {
+            linux_builder = linux_builder.seccomp(seccomp);
+        }
         let linux = linux_builder.build()?;
         spec.set_process(Some(process)).set_linux(Some(linux));
 
+        // the init spec has been validated on create, but the process and
+        // the seccomp profile of the exec may come from separate files
+        let mut invalid = validation::check_exec(spec);
+        if self.base.strict_env {
+            invalid.extend(validation::check_env(spec));
+        }
+        if !invalid.is_empty() {
+            let report = validation::report(&invalid);
+            tracing::error!(%report, "invalid process");
+            Err(ErrInvalidSpec::Invalid(report))?;
+        }
+
         Ok(())
     }
 
+    fn get_seccomp(spec: &Spec) -> Result<Option<LinuxSeccomp>, LibcontainerError> {
+        let init_seccomp = spec.linux().as_ref().and_then(|l| l.seccomp().clone());
+        let annotation = spec
+            .annotations()
+            .as_ref()
+            .and_then(|a| a.get(EXEC_SECCOMP_ANNOTATION));
+        match annotation.map(String::as_str) {
+            None => Ok(init_seccomp),
+            Some(UNCONFINED) => Ok(None),
+            Some(path) => {
+                let file = utils::open(path).map_err(LibcontainerError::OtherIO)?;
+                let seccomp = serde_json::from_reader(BufReader::new(file))
+                    .map_err(LibcontainerError::OtherSerialization)?;
+                Ok(Some(seccomp))
+            }
+        }
+    }
+
     fn get_process(&self, process: &Path) -> Result<Process, LibcontainerError> {
         if !process.exists() {
             tracing::error!(?process, "process.json file does not exist");
@@ -363,6 +454,29 @@ impl TenantContainerBuilder {
         Ok(process_spec)
     }
 
+    fn get_user(&self, spec: &Spec) -> User {
+        let mut user = spec
+            .process()
+            .as_ref()
+            .map(|p| p.user().clone())
+            .unwrap_or_default();
+        if let Some(name) = &self.user {
+            // the ids are resolved in the container, the additional gids of
+            // the container process belong to its own user
+            user.set_uid(0)
+                .set_gid(0)
+                .set_username(Some(name.clone()))
+                .set_additional_gids(None);
+        }
+        if !self.additional_gids.is_empty() {
+            user.additional_gids_mut()
+                .get_or_insert_with(Vec::new)
+                .extend(&self.additional_gids);
+        }
+
+        user
+    }
+
     fn get_working_dir(&self) -> Result<Option<PathBuf>, LibcontainerError> {
         if let Some(cwd) = &self.cwd {
             if cwd.is_relative() {
@@ -524,3 +638,53 @@ impl TenantContainerBuilder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use oci_spec::runtime::{LinuxBuilder, LinuxSeccompAction, LinuxSeccompBuilder, SpecBuilder};
+
+    use super::*;
+
+    #[test]
+    fn test_get_seccomp() -> Result<()> {
+        let seccomp = |action| {
+            LinuxSeccompBuilder::default()
+                .default_action(action)
+                .build()
+        };
+        let init_seccomp = seccomp(LinuxSeccompAction::ScmpActErrno)?;
+        let spec = |annotation: Option<&str>| {
+            let annotations = annotation
+                .map(|a| HashMap::from([(EXEC_SECCOMP_ANNOTATION.to_owned(), a.to_owned())]));
+            SpecBuilder::default()
+                .linux(
+                    LinuxBuilder::default()
+                        .seccomp(init_seccomp.clone())
+                        .build()?,
+                )
+                .annotations(annotations.unwrap_or_default())
+                .build()
+        };
+
+        assert_eq!(
+            TenantContainerBuilder::get_seccomp(&spec(None)?)?,
+            Some(init_seccomp.clone())
+        );
+        assert_eq!(
+            TenantContainerBuilder::get_seccomp(&spec(Some(UNCONFINED))?)?,
+            None
+        );
+
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("exec-seccomp.json");
+        let exec_seccomp = seccomp(LinuxSeccompAction::ScmpActLog)?;
+        fs::write(&path, serde_json::to_vec(&exec_seccomp)?)?;
+        assert_eq!(
+            TenantContainerBuilder::get_seccomp(&spec(path.to_str())?)?,
+            Some(exec_seccomp)
+        );
+        assert!(TenantContainerBuilder::get_seccomp(&spec(Some("/youki-missing"))?).is_err());
+        Ok(())
+    }
+}
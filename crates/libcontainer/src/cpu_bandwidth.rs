@@ -0,0 +1,235 @@
+//! Bounds of the cfs bandwidth of the cpu controller. The kernel rejects
+//! periods outside of 1ms to 1s and quotas below 1ms with EINVAL, which
+//! doesn't tell which of the values is the problem. Quotas computed from
+//! millicores, e.g. 100us for a limit of 1m with the default period of
+//! 100ms, regularly end up below the minimum.
+//!
+//! The values can be clamped into the bounds instead of being rejected, if
+//! requested with the [`ROUND_ANNOTATION`].
+use std::collections::HashMap;
+
+use oci_spec::runtime::{LinuxCpu, Spec};
+use serde::{Deserialize, Serialize};
+
+/// Annotation to clamp a cpu period or quota outside of the bounds of the
+/// kernel into them instead of failing
+pub const ROUND_ANNOTATION: &str = "org.youki.cpu.bandwidth.round";
+
+/// Shortest period in microseconds
+pub const MIN_PERIOD: u64 = 1_000;
+/// Longest period in microseconds
+pub const MAX_PERIOD: u64 = 1_000_000;
+/// Smallest quota in microseconds, quotas of 0 and below are unlimited
+pub const MIN_QUOTA: i64 = 1_000;
+
+/// Cpu bandwidth the cgroup of a container is given, which is recorded in
+/// its state as the values may have been rounded
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuBandwidth {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period: Option<u64>,
+}
+
+impl CpuBandwidth {
+    /// Returns the cpu bandwidth of the cpu resources, if they set any
+    pub fn of(cpu: &LinuxCpu) -> Option<Self> {
+        if cpu.quota().is_none() && cpu.period().is_none() {
+            return None;
+        }
+
+        Some(Self {
+            quota: cpu.quota(),
+            period: cpu.period(),
+        })
+    }
+
+    /// Returns the bandwidth after an update, which leaves the values it
+    /// doesn't set unchanged
+    pub fn updated(current: Option<Self>, update: Option<Self>) -> Option<Self> {
+        match (current, update) {
+            (Some(current), Some(update)) => Some(Self {
+                quota: update.quota.or(current.quota),
+                period: update.period.or(current.period),
+            }),
+            (current, None) => current,
+            (None, update) => update,
+        }
+    }
+}
+
+/// Returns the cpu resources of the spec
+pub fn cpu_of(spec: &Spec) -> Option<&LinuxCpu> {
+    spec.linux().as_ref()?.resources().as_ref()?.cpu().as_ref()
+}
+
+/// Checks if values outside of the bounds should be clamped
+pub fn round_requested(annotations: Option<&HashMap<String, String>>) -> bool {
+    match annotations.and_then(|a| a.get(ROUND_ANNOTATION)) {
+        Some(value) => match value.as_str() {
+            "1" | "true" => true,
+            "0" | "false" => false,
+            _ => {
+                tracing::warn!(?value, "ignoring invalid value of {}", ROUND_ANNOTATION);
+                false
+            }
+        },
+        None => false,
+    }
+}
+
+/// Returns why the period is out of bounds, if it is. A period of 0 is left
+/// to the kernel default.
+pub fn check_period(period: u64) -> Option<String> {
+    match period {
+        0 | MIN_PERIOD..=MAX_PERIOD => None,
+        _ => Some(format!(
+            "period of {period}us is not within {MIN_PERIOD}us and {MAX_PERIOD}us"
+        )),
+    }
+}
+
+/// Returns why the quota is out of bounds, if it is
+pub fn check_quota(quota: i64) -> Option<String> {
+    if quota > 0 && quota < MIN_QUOTA {
+        return Some(format!(
+            "quota of {quota}us is below the minimum of {MIN_QUOTA}us"
+        ));
+    }
+
+    None
+}
+
+/// Returns the problems of the period and quota, with the name of the field
+pub fn check(cpu: &LinuxCpu) -> Vec<(&'static str, String)> {
+    let mut invalid = Vec::new();
+    if let Some(reason) = cpu.period().and_then(check_period) {
+        invalid.push(("period", reason));
+    }
+    if let Some(reason) = cpu.quota().and_then(check_quota) {
+        invalid.push(("quota", reason));
+    }
+
+    invalid
+}
+
+/// Clamps the period and quota into the bounds
+pub fn round(cpu: &mut LinuxCpu) {
+    if let Some(period) = cpu.period().filter(|p| check_period(*p).is_some()) {
+        let rounded = period.clamp(MIN_PERIOD, MAX_PERIOD);
+        tracing::warn!(
+            period,
+            rounded,
+            "rounding cpu period into the kernel bounds"
+        );
+        cpu.set_period(Some(rounded));
+    }
+    if let Some(quota) = cpu.quota().filter(|q| check_quota(*q).is_some()) {
+        tracing::warn!(
+            quota,
+            rounded = MIN_QUOTA,
+            "rounding cpu quota up to the kernel minimum"
+        );
+        cpu.set_quota(Some(MIN_QUOTA));
+    }
+}
+
+/// Clamps the period and quota of the spec into the bounds
+pub fn round_spec(spec: &mut Spec) {
+    if let Some(cpu) = spec
+        .linux_mut()
+        .as_mut()
+        .and_then(|linux| linux.resources_mut().as_mut())
+        .and_then(|resources| resources.cpu_mut().as_mut())
+    {
+        round(cpu);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use oci_spec::runtime::LinuxCpuBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check_period(100_000), None);
+        assert_eq!(check_period(0), None);
+        assert!(check_period(999).is_some());
+        assert!(check_period(1_000_001).is_some());
+        assert_eq!(check_quota(1_000), None);
+        assert_eq!(check_quota(-1), None);
+        assert_eq!(check_quota(0), None);
+        assert!(check_quota(100).is_some());
+
+        let cpu = LinuxCpuBuilder::default()
+            .quota(100)
+            .period(500u64)
+            .build()
+            .unwrap();
+        let fields: Vec<_> = check(&cpu).into_iter().map(|(field, _)| field).collect();
+        assert_eq!(fields, ["period", "quota"]);
+    }
+
+    #[test]
+    fn test_round() -> Result<()> {
+        let mut cpu = LinuxCpuBuilder::default()
+            .quota(100)
+            .period(500u64)
+            .build()?;
+        round(&mut cpu);
+        assert_eq!(
+            CpuBandwidth::of(&cpu),
+            Some(CpuBandwidth {
+                quota: Some(MIN_QUOTA),
+                period: Some(MIN_PERIOD),
+            })
+        );
+
+        let mut cpu = LinuxCpuBuilder::default().period(2_000_000u64).build()?;
+        round(&mut cpu);
+        assert_eq!(cpu.period(), Some(MAX_PERIOD));
+        assert_eq!(cpu.quota(), None);
+
+        // values within the bounds are kept
+        let mut cpu = LinuxCpuBuilder::default().quota(50_000).build()?;
+        round(&mut cpu);
+        assert_eq!(cpu.quota(), Some(50_000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_updated() {
+        let current = CpuBandwidth {
+            quota: Some(50_000),
+            period: Some(100_000),
+        };
+        let update = CpuBandwidth {
+            quota: Some(20_000),
+            period: None,
+        };
+        assert_eq!(
+            CpuBandwidth::updated(Some(current), Some(update)),
+            Some(CpuBandwidth {
+                quota: Some(20_000),
+                period: Some(100_000),
+            })
+        );
+        assert_eq!(CpuBandwidth::updated(Some(current), None), Some(current));
+        assert_eq!(CpuBandwidth::updated(None, Some(update)), Some(update));
+    }
+
+    #[test]
+    fn test_round_requested() {
+        let annotations =
+            |value: &str| HashMap::from([(ROUND_ANNOTATION.to_owned(), value.to_owned())]);
+        assert!(round_requested(Some(&annotations("true"))));
+        assert!(!round_requested(Some(&annotations("false"))));
+        assert!(!round_requested(Some(&annotations("yes"))));
+        assert!(!round_requested(None));
+    }
+}
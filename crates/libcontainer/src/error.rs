@@ -1,3 +1,5 @@
+use libcgroups::common::CgroupErrorKind;
+
 #[derive(Debug, thiserror::Error)]
 pub enum MissingSpecError {
     #[error("missing process in spec")]
@@ -41,6 +43,8 @@ pub enum LibcontainerError {
     #[error(transparent)]
     NotifyListener(#[from] crate::notify_socket::NotifyListenerError),
     #[error(transparent)]
+    NotifyProxy(#[from] crate::notify_proxy::NotifyProxyError),
+    #[error(transparent)]
     Config(#[from] crate::config::ConfigError),
     #[error(transparent)]
     Hook(#[from] crate::hooks::HookError),
@@ -62,6 +66,8 @@ pub enum LibcontainerError {
     CgroupGet(#[from] libcgroups::common::GetCgroupSetupError),
     #[error[transparent]]
     Checkpoint(#[from] crate::container::CheckpointError),
+    #[error(transparent)]
+    IntelRdt(#[from] crate::process::intel_rdt::IntelRdtError),
 
     // Catch all errors that are not covered by the above
     #[error("syscall error")]
@@ -76,6 +82,82 @@ pub enum LibcontainerError {
     Other(String),
 }
 
+/// Category of an error, which stays the same when the errors of the
+/// submodules change, so that callers can react to it deterministically, e.g.
+/// by choosing an exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The spec is invalid or can't be provided on this host
+    SpecInvalid,
+    /// An argument such as the container id or a path is invalid
+    InvalidInput,
+    /// A container with the id already exists
+    ContainerExists,
+    /// There is no container with the id
+    ContainerNotFound,
+    /// The container is not in a status which allows the operation
+    IncorrectStatus,
+    /// A cgroup controller required by the resources is not available
+    CgroupControllerNotFound,
+    /// The version of systemd is too old for the requested resources
+    SystemdVersionTooOld,
+    /// Any other failure to manage the cgroup of the container
+    Cgroup,
+    /// A hook of the spec has failed
+    Hook,
+    /// Any other failure, e.g. of a syscall
+    Other,
+}
+
+impl ErrorKind {
+    /// Name of the kind as used in logs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::SpecInvalid => "spec_invalid",
+            ErrorKind::InvalidInput => "invalid_input",
+            ErrorKind::ContainerExists => "container_exists",
+            ErrorKind::ContainerNotFound => "container_not_found",
+            ErrorKind::IncorrectStatus => "incorrect_status",
+            ErrorKind::CgroupControllerNotFound => "cgroup_controller_not_found",
+            ErrorKind::SystemdVersionTooOld => "systemd_version_too_old",
+            ErrorKind::Cgroup => "cgroup",
+            ErrorKind::Hook => "hook",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
+impl From<CgroupErrorKind> for ErrorKind {
+    fn from(kind: CgroupErrorKind) -> Self {
+        match kind {
+            CgroupErrorKind::ControllerNotFound => ErrorKind::CgroupControllerNotFound,
+            CgroupErrorKind::SystemdVersionTooOld => ErrorKind::SystemdVersionTooOld,
+            _ => ErrorKind::Cgroup,
+        }
+    }
+}
+
+impl LibcontainerError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::IncorrectStatus => ErrorKind::IncorrectStatus,
+            Self::Exist => ErrorKind::ContainerExists,
+            Self::NoDirectory => ErrorKind::ContainerNotFound,
+            Self::InvalidInput(_) | Self::NoExecutors | Self::InvalidID(_) => {
+                ErrorKind::InvalidInput
+            }
+            Self::NoUserNamespace | Self::MissingSpec(_) | Self::InvalidSpec(_) | Self::Spec(_) => {
+                ErrorKind::SpecInvalid
+            }
+            Self::Hook(_) => ErrorKind::Hook,
+            Self::CgroupManager(err) => err.kind().into(),
+            Self::CgroupCreate(err) => err.kind().into(),
+            Self::CgroupGet(_) | Self::OtherCgroup(_) => ErrorKind::Cgroup,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ErrInvalidID {
     #[error("container id can't be empty")]
@@ -92,8 +174,24 @@ pub enum ErrInvalidSpec {
     UnsupportedVersion,
     #[error("apparmor is specified but not enabled on this system")]
     AppArmorNotEnabled,
+    #[error("selinux label is specified but selinux is not enabled on this system")]
+    SELinuxNotEnabled,
     #[error("invalid io priority or class.")]
     IoPriority,
     #[error("invalid scheduler config for process")]
     Scheduler,
+    #[error("invalid seccomp profile")]
+    Seccomp,
+    #[error("idmapped mounts require a new user namespace")]
+    IdmappedMount,
+    #[error("invalid time offsets, a new time namespace with offsets of the monotonic or boottime clock is required")]
+    TimeOffsets,
+    #[error("invalid sysctl: {0}")]
+    Sysctl(String),
+    #[error("invalid namespace: {0}")]
+    Namespace(String),
+    #[error("not supported for rootless containers: {0}")]
+    Rootless(String),
+    #[error("invalid fields:\n{0}")]
+    Invalid(String),
 }
@@ -0,0 +1,50 @@
+//! Features libcontainer has been built with. These are reported to
+//! high-level runtimes, which decide based on them what they can ask the
+//! runtime to do.
+
+/// Checks if seccomp profiles can be applied to containers
+pub fn seccomp_enabled() -> bool {
+    seccomp_backend().is_some()
+}
+
+/// Returns the backend seccomp profiles are compiled with, libseccomp if
+/// libcontainer has been built with it and the built-in BPF compiler
+/// otherwise
+pub fn seccomp_backend() -> Option<&'static str> {
+    if cfg!(feature = "libseccomp") {
+        Some("libseccomp")
+    } else if cfg!(feature = "seccomp-bpf") {
+        Some("bpf")
+    } else {
+        None
+    }
+}
+
+/// Returns the version of the libseccomp library in use, e.g. 2.5.4
+pub fn libseccomp_version() -> Option<String> {
+    #[cfg(feature = "libseccomp")]
+    {
+        libseccomp::ScmpVersion::current()
+            .ok()
+            .map(|version| format!("{}.{}.{}", version.major, version.minor, version.micro))
+    }
+    #[cfg(not(feature = "libseccomp"))]
+    {
+        None
+    }
+}
+
+/// Checks if containers can be managed with cgroup v1
+pub fn cgroup_v1_enabled() -> bool {
+    cfg!(feature = "v1")
+}
+
+/// Checks if containers can be managed with cgroup v2
+pub fn cgroup_v2_enabled() -> bool {
+    cfg!(feature = "v2")
+}
+
+/// Checks if the cgroups of containers can be managed by systemd
+pub fn systemd_enabled() -> bool {
+    cfg!(feature = "systemd")
+}
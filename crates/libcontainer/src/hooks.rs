@@ -1,12 +1,13 @@
 use std::collections::HashMap;
-use std::io::{ErrorKind, Write};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::os::unix::prelude::CommandExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 use std::{process, thread, time};
 
 use nix::sys::signal;
 use nix::unistd::Pid;
-use oci_spec::runtime::Hook;
+use oci_spec::runtime::{Hook, Hooks};
 
 use crate::container::Container;
 use crate::utils;
@@ -31,6 +32,39 @@ pub enum HookError {
 
 type Result<T> = std::result::Result<T, HookError>;
 
+/// Time to wait for the output of a hook to be logged after it has exited.
+/// Daemons started by a hook may keep the pipes open forever.
+const OUTPUT_DRAIN_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+/// Checks if there are hooks which have to be run in the runtime namespace
+/// during the create operation, once the container environment exists
+pub fn has_create_runtime_hooks(hooks: Option<&Hooks>) -> bool {
+    #[allow(deprecated)]
+    hooks.map_or(false, |hooks| {
+        hooks.prestart().iter().flatten().next().is_some()
+            || hooks.create_runtime().iter().flatten().next().is_some()
+    })
+}
+
+/// Runs the prestart and createRuntime hooks, in this order, as required by
+/// the OCI spec. Both are run in the runtime namespace after the container
+/// environment, including the container process, has been created, but before
+/// pivot_root.
+pub fn run_create_runtime_hooks(
+    hooks: Option<&Hooks>,
+    container: Option<&Container>,
+) -> Result<()> {
+    if let Some(hooks) = hooks {
+        // While prestart is marked as deprecated in the OCI spec, docker and
+        // the integration tests still use it.
+        #[allow(deprecated)]
+        run_hooks(hooks.prestart().as_ref(), container, None)?;
+        run_hooks(hooks.create_runtime().as_ref(), container, None)?;
+    }
+
+    Ok(())
+}
+
 pub fn run_hooks(
     hooks: Option<&Vec<Hook>>,
     container: Option<&Container>,
@@ -66,13 +100,34 @@ pub fn run_hooks(
             };
             tracing::debug!("run_hooks envs: {:?}", envs);
 
+            // The hook gets its own process group, so that processes forked by
+            // the hook are killed along with it on a timeout.
+            unsafe {
+                hook_command.pre_exec(|| {
+                    nix::unistd::setpgid(Pid::from_raw(0), Pid::from_raw(0))?;
+                    Ok(())
+                });
+            }
+
             let mut hook_process = hook_command
                 .env_clear()
                 .envs(envs)
                 .stdin(process::Stdio::piped())
+                .stdout(process::Stdio::piped())
+                .stderr(process::Stdio::piped())
                 .spawn()
                 .map_err(HookError::CommandExecute)?;
             let hook_process_pid = Pid::from_raw(hook_process.id() as i32);
+
+            // The output is logged, so that failing hooks can be diagnosed.
+            let (output_sender, output_receiver) = mpsc::channel();
+            if let Some(stdout) = hook_process.stdout.take() {
+                log_output(hook.path().clone(), false, stdout, output_sender.clone());
+            }
+            if let Some(stderr) = hook_process.stderr.take() {
+                log_output(hook.path().clone(), true, stderr, output_sender.clone());
+            }
+            drop(output_sender);
             // Based on the OCI spec, we need to pipe the container state into
             // the hook command through stdin.
             if let Some(stdin) = &mut hook_process.stdin {
@@ -90,7 +145,7 @@ pub fn run_hooks(
                     if e.kind() != ErrorKind::BrokenPipe {
                         // Not a broken pipe. The hook command may be waiting
                         // for us.
-                        let _ = signal::kill(hook_process_pid, signal::Signal::SIGKILL);
+                        let _ = signal::killpg(hook_process_pid, signal::Signal::SIGKILL);
                         return Err(HookError::WriteContainerState(e));
                     }
                 }
@@ -113,28 +168,33 @@ pub fn run_hooks(
                     let _ = s.send(res);
                 });
                 match r.recv_timeout(time::Duration::from_secs(timeout_sec as u64)) {
-                    Ok(res) => res,
+                    Ok(res) => res.map_err(HookError::CommandExecute),
                     Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
-                        // Kill the process. There is no need to further clean
-                        // up because we will be error out.
-                        let _ = signal::kill(hook_process_pid, signal::Signal::SIGKILL);
-                        return Err(HookError::Timeout);
+                        // Kill the process group of the hook. There is no need
+                        // to further clean up because we will be error out.
+                        tracing::error!(hook = ?hook.path(), timeout_sec, "hook timed out");
+                        let _ = signal::killpg(hook_process_pid, signal::Signal::SIGKILL);
+                        Err(HookError::Timeout)
                     }
                     Err(_) => {
                         unreachable!();
                     }
                 }
             } else {
-                hook_process.wait()
+                hook_process.wait().map_err(HookError::CommandExecute)
             };
 
-            match res {
-                Ok(exit_status) => match exit_status.code() {
-                    Some(0) => Ok(()),
-                    Some(exit_code) => Err(HookError::NonZeroExitCode(exit_code)),
-                    None => Err(HookError::Killed),
-                },
-                Err(e) => Err(HookError::CommandExecute(e)),
+            // Both loggers disconnect once the output has been read to the end.
+            let deadline = time::Instant::now() + OUTPUT_DRAIN_TIMEOUT;
+            while output_receiver
+                .recv_timeout(deadline.saturating_duration_since(time::Instant::now()))
+                .is_ok()
+            {}
+
+            match res?.code() {
+                Some(0) => Ok(()),
+                Some(exit_code) => Err(HookError::NonZeroExitCode(exit_code)),
+                None => Err(HookError::Killed),
             }?;
         }
     }
@@ -142,12 +202,32 @@ pub fn run_hooks(
     Ok(())
 }
 
+/// Logs the output of a hook line by line and signals `done` once it has been
+/// read to the end
+fn log_output<R: Read + Send + 'static>(
+    hook_path: PathBuf,
+    is_stderr: bool,
+    output: R,
+    done: mpsc::Sender<()>,
+) {
+    thread::spawn(move || {
+        for line in BufReader::new(output).lines() {
+            match line {
+                Ok(line) if is_stderr => tracing::warn!(hook = ?hook_path, "stderr: {}", line),
+                Ok(line) => tracing::info!(hook = ?hook_path, "stdout: {}", line),
+                Err(_) => break,
+            }
+        }
+        let _ = done.send(());
+    });
+}
+
 #[cfg(test)]
 mod test {
     use std::{env, fs};
 
     use anyhow::{bail, Context, Result};
-    use oci_spec::runtime::HookBuilder;
+    use oci_spec::runtime::{HookBuilder, HooksBuilder};
     use serial_test::serial;
 
     use super::*;
@@ -231,6 +311,29 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_has_create_runtime_hooks() -> Result<()> {
+        let hook = HookBuilder::default().path("true").build()?;
+        assert!(!has_create_runtime_hooks(None));
+        assert!(!has_create_runtime_hooks(Some(&Hooks::default())));
+
+        let hooks = HooksBuilder::default()
+            .poststart(vec![hook.clone()])
+            .create_container(vec![hook.clone()])
+            .build()?;
+        assert!(!has_create_runtime_hooks(Some(&hooks)));
+
+        let hooks = HooksBuilder::default()
+            .create_runtime(vec![hook.clone()])
+            .build()?;
+        assert!(has_create_runtime_hooks(Some(&hooks)));
+
+        #[allow(deprecated)]
+        let hooks = HooksBuilder::default().prestart(vec![hook]).build()?;
+        assert!(has_create_runtime_hooks(Some(&hooks)));
+        Ok(())
+    }
+
     #[test]
     #[serial]
     // This will test executing hook with a timeout. Since the timeout is set in
@@ -263,4 +366,59 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    #[serial]
+    fn test_run_hook_timeout_kills_process_group() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let pid_file = tmp.path().join("pid");
+        let default_container: Container = Default::default();
+        // the background sleep is part of the process group of the hook
+        let hook = HookBuilder::default()
+            .path("bash")
+            .args(vec![
+                String::from("bash"),
+                String::from("-c"),
+                format!("sleep 100 & echo $! > {}; wait", pid_file.display()),
+            ])
+            .timeout(1)
+            .build()?;
+        let hooks = Some(vec![hook]);
+        assert!(matches!(
+            run_hooks(hooks.as_ref(), Some(&default_container), None),
+            Err(HookError::Timeout)
+        ));
+
+        let pid: i32 = fs::read_to_string(&pid_file)?.trim().parse()?;
+        for _ in 0..50 {
+            // the killed sleep may stay a zombie until it has been reaped
+            match procfs::process::Process::new(pid).and_then(|p| p.stat()) {
+                Ok(stat) if stat.state != 'Z' => {
+                    thread::sleep(time::Duration::from_millis(20));
+                }
+                _ => return Ok(()),
+            }
+        }
+        bail!("the background process of the hook has not been killed");
+    }
+
+    #[test]
+    #[serial]
+    fn test_run_hook_with_output() -> Result<()> {
+        let default_container: Container = Default::default();
+        // the output is logged instead of being inherited, which must not
+        // block the hook even if it exceeds the capacity of a pipe
+        let hook = HookBuilder::default()
+            .path("bash")
+            .args(vec![
+                String::from("bash"),
+                String::from("-c"),
+                String::from("head -c 200000 /dev/zero | tr '\\0' 'x'; echo error >&2"),
+            ])
+            .timeout(10)
+            .build()?;
+        let hooks = Some(vec![hook]);
+        run_hooks(hooks.as_ref(), Some(&default_container), None).context("Failed output test")?;
+        Ok(())
+    }
 }
@@ -1,22 +1,72 @@
+//! Library to create and manage OCI containers, which the youki binary is
+//! built on. Containers are created through the
+//! [`ContainerBuilder`](container::builder::ContainerBuilder) and managed
+//! through the returned [`Container`](container::Container). All state is kept
+//! in the root path passed to the builder, so containers created by one
+//! process can be loaded and managed by another.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use libcontainer::container::builder::ContainerBuilder;
+//! use libcontainer::container::Container;
+//! use libcontainer::syscall::syscall::SyscallType;
+//!
+//! # fn main() -> Result<(), libcontainer::error::LibcontainerError> {
+//! let mut container = ContainerBuilder::new("74f1a4cb3801".to_owned(), SyscallType::default())
+//!     .with_root_path("/run/my-runtime")?
+//!     .as_init("/var/lib/my-runtime/bundle")
+//!     .with_systemd(false)
+//!     .build()?;
+//! container.start()?;
+//!
+//! // run an additional process in the namespaces of the container
+//! let pid = ContainerBuilder::new("74f1a4cb3801".to_owned(), SyscallType::default())
+//!     .with_root_path("/run/my-runtime")?
+//!     .as_tenant()
+//!     .with_container_args(vec!["ls".to_owned(), "/".to_owned()])
+//!     .build()?;
+//! nix::sys::wait::waitpid(pid, None).unwrap();
+//!
+//! let mut container = Container::load_from_root("/run/my-runtime", "74f1a4cb3801")?;
+//! container.kill(nix::sys::signal::Signal::SIGKILL, true)?;
+//! container.delete(true)?;
+//! # Ok(())
+//! # }
+//! ```
+
 pub mod apparmor;
+pub mod arch;
 pub mod capabilities;
 pub mod channel;
 pub mod config;
 pub mod container;
+pub mod cpu_bandwidth;
 pub mod error;
+pub mod features;
 pub mod hooks;
+pub mod log_capture;
 pub mod namespaces;
+pub mod net_devices;
+pub mod notify_proxy;
 pub mod notify_socket;
+pub mod pidfd;
 pub mod process;
 pub mod rootfs;
-#[cfg(feature = "libseccomp")]
+pub mod rootless;
+pub mod rtnetlink;
+#[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
 pub mod seccomp;
+pub mod selinux;
 pub mod signal;
+pub mod socket_address;
 pub mod syscall;
 pub mod test_utils;
 pub mod tty;
+pub mod user;
 pub mod user_ns;
 pub mod utils;
+pub mod validation;
 pub mod workload;
 
 // Because the `libcontainer` api uses the oci_spec who resides in a different
@@ -0,0 +1,181 @@
+//! Capture of the last log lines of the runtime, so that the init process is
+//! able to relay them to the main process when it fails. The init process
+//! logs to the stderr of the container once its stdio has been set up, where
+//! whoever invoked the runtime doesn't see them. The capture only sees what is
+//! written through a [`CaptureWriter`], which the logger of the runtime wraps
+//! its writer in.
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::{Mutex, MutexGuard};
+
+// longer lines are truncated, so that the relayed logs fit into one message
+const MAX_LINE_LEN: usize = 4096;
+
+static CAPTURE: Mutex<Option<Ring>> = Mutex::new(None);
+
+struct Ring {
+    lines: VecDeque<String>,
+    capacity: usize,
+    partial: Vec<u8>,
+}
+
+impl Ring {
+    fn record(&mut self, buf: &[u8]) {
+        let buf = strip_escapes(buf);
+        let mut buf = buf.as_slice();
+        while let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+            self.push_partial(&buf[..pos]);
+            let line = String::from_utf8_lossy(&self.partial).into_owned();
+            self.partial.clear();
+            if self.lines.len() == self.capacity {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(line);
+            buf = &buf[pos + 1..];
+        }
+        self.push_partial(buf);
+    }
+
+    fn push_partial(&mut self, buf: &[u8]) {
+        let len = buf
+            .len()
+            .min(MAX_LINE_LEN.saturating_sub(self.partial.len()));
+        self.partial.extend_from_slice(&buf[..len]);
+    }
+}
+
+// the colors of the text format are dropped, as the lines are logged again
+fn strip_escapes(buf: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(buf.len());
+    let mut bytes = buf.iter().copied();
+    while let Some(b) = bytes.next() {
+        if b == 0x1b {
+            // CSI sequences end with a byte in 0x40..=0x7e
+            if bytes.next() == Some(b'[') {
+                for b in bytes.by_ref() {
+                    if (0x40..=0x7e).contains(&b) {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        stripped.push(b);
+    }
+    stripped
+}
+
+// the process may have been cloned while another thread held the lock, which
+// then stays locked in the clone, so capturing is skipped rather than blocking
+fn lock() -> Option<MutexGuard<'static, Option<Ring>>> {
+    CAPTURE.try_lock().ok()
+}
+
+/// Keeps the last lines written through a [`CaptureWriter`]
+pub fn enable(capacity: usize) {
+    if let Some(mut capture) = lock() {
+        *capture = Some(Ring {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+            partial: Vec::new(),
+        });
+    }
+}
+
+/// Forgets the lines captured so far, e.g. the ones a cloned process has
+/// inherited from its parent
+pub fn clear() {
+    if let Some(Some(ring)) = lock().as_deref_mut() {
+        ring.lines.clear();
+        ring.partial.clear();
+    }
+}
+
+/// Returns the captured lines, oldest first, and forgets them
+pub fn take() -> Vec<String> {
+    match lock().as_deref_mut() {
+        Some(Some(ring)) => ring.lines.drain(..).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Writer which keeps what is written through it in the capture, if it is
+/// enabled, besides writing it to the inner writer
+#[derive(Debug)]
+pub struct CaptureWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+}
+
+impl<W: Write> Write for CaptureWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // the line is captured even if the inner writer is gone
+        let result = self.inner.write(buf);
+        let len = *result.as_ref().unwrap_or(&buf.len());
+        if let Some(Some(ring)) = lock().as_deref_mut() {
+            ring.record(&buf[..len]);
+        }
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestCallbackError;
+
+    #[test]
+    fn test_ring() {
+        let mut ring = Ring {
+            lines: VecDeque::new(),
+            capacity: 2,
+            partial: Vec::new(),
+        };
+        ring.record(b"first\nsecond\nthi");
+        ring.record(b"rd\nfou");
+        assert_eq!(ring.lines, ["second", "third"]);
+        assert_eq!(ring.partial, b"fou");
+
+        ring.record(b"rth\n\x1b[31mERROR\x1b[0m \x1b[2minit\x1b[0m\n");
+        assert_eq!(ring.lines[1], "ERROR init");
+
+        ring.record(&[b'x'; 2 * MAX_LINE_LEN]);
+        ring.record(b"\n");
+        assert_eq!(ring.lines[1].len(), MAX_LINE_LEN);
+    }
+
+    #[test]
+    fn test_capture_writer() -> anyhow::Result<()> {
+        // the capture is global to the process
+        crate::test_utils::test_in_child_process(|| {
+            let mut sink = Vec::new();
+            let mut writer = CaptureWriter::new(&mut sink);
+            let mut write = |line: &[u8]| {
+                writer
+                    .write_all(line)
+                    .map_err(|err| TestCallbackError::Other(err.into()))
+            };
+            write(b"before\n")?;
+            assert!(take().is_empty());
+
+            enable(8);
+            write(b"inherited\n")?;
+            clear();
+            write(b"failed to pivot root\n")?;
+            assert_eq!(take(), ["failed to pivot root"]);
+            assert!(take().is_empty());
+            assert_eq!(sink, b"before\ninherited\nfailed to pivot root\n");
+            Ok(())
+        })?;
+        Ok(())
+    }
+}
@@ -6,11 +6,14 @@
 //! Network (which network devices can be seen by the processes in the namespace), User (User configs),
 //! UTS (hostname and domain information, processes will think they're running on servers with different names),
 //! Cgroup (Resource limits, execution priority etc.)
+//! Time (offsets of the monotonic and boot time clocks, processes can be migrated without noticing clocks jumping)
 
-use std::collections;
+use std::collections::{self, HashMap};
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
 
 use nix::sched::CloneFlags;
-use nix::sys::stat;
+use nix::sys::{stat, statfs};
 use nix::{fcntl, unistd};
 use oci_spec::runtime::{LinuxNamespace, LinuxNamespaceType};
 
@@ -29,8 +32,26 @@ pub enum NamespaceError {
     Syscall(#[from] crate::syscall::SyscallError),
     #[error("Namespace type not supported: {0}")]
     NotSupported(String),
+    #[error("invalid time offset for clock {clock}: {offset}")]
+    InvalidTimeOffset { clock: String, offset: String },
+    #[error("{path:?} is not a namespace")]
+    NotANamespace { path: PathBuf },
+    #[error("{path:?} is not a {expected:?} namespace")]
+    WrongNamespaceType {
+        path: PathBuf,
+        expected: LinuxNamespaceType,
+    },
 }
 
+// nix doesn't provide the flag for time namespaces yet
+pub const CLONE_NEWTIME: CloneFlags = CloneFlags::from_bits_retain(libc::CLONE_NEWTIME);
+
+// ioctl to get the type of a namespace, _IO(NSIO, 0x3) of linux/nsfs.h
+const NS_GET_NSTYPE: libc::c_ulong = 0xb703;
+
+/// Clocks whose offsets can be changed in a time namespace
+const TIME_NAMESPACE_CLOCKS: &[&str] = &["monotonic", "boottime"];
+
 static ORDERED_NAMESPACES: &[CloneFlags] = &[
     CloneFlags::CLONE_NEWUSER,
     CloneFlags::CLONE_NEWPID,
@@ -38,6 +59,7 @@ static ORDERED_NAMESPACES: &[CloneFlags] = &[
     CloneFlags::CLONE_NEWIPC,
     CloneFlags::CLONE_NEWNET,
     CloneFlags::CLONE_NEWCGROUP,
+    CLONE_NEWTIME,
     CloneFlags::CLONE_NEWNS,
 ];
 
@@ -56,7 +78,7 @@ fn get_clone_flag(namespace_type: LinuxNamespaceType) -> Result<CloneFlags> {
         LinuxNamespaceType::Network => CloneFlags::CLONE_NEWNET,
         LinuxNamespaceType::Cgroup => CloneFlags::CLONE_NEWCGROUP,
         LinuxNamespaceType::Mount => CloneFlags::CLONE_NEWNS,
-        LinuxNamespaceType::Time => return Err(NamespaceError::NotSupported("time".to_string())),
+        LinuxNamespaceType::Time => CLONE_NEWTIME,
     };
 
     Ok(flag)
@@ -99,16 +121,48 @@ impl Namespaces {
         Ok(())
     }
 
+    /// Joins the namespaces given by path, except the ones rejected by the
+    /// filter, with the user namespace first. This has to be done before any
+    /// namespace is created, because the joined namespaces are usually owned
+    /// by the user namespace of the caller, e.g. the shared namespaces of a
+    /// pod sandbox, and can't be joined anymore from a new user namespace.
+    pub fn join_existing<F: Fn(CloneFlags) -> bool>(&self, filter: F) -> Result<()> {
+        for flag in ORDERED_NAMESPACES.iter().filter(|c| filter(**c)) {
+            if let Some(ns) = self.namespace_map.get(flag) {
+                if ns.path().is_some() {
+                    self.unshare_or_setns(ns)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks if the namespace of the type is joined by path instead of
+    /// being created
+    pub fn is_joined(&self, flag: CloneFlags) -> bool {
+        self.namespace_map
+            .get(&flag)
+            .map_or(false, |ns| ns.path().is_some())
+    }
+
     pub fn unshare_or_setns(&self, namespace: &LinuxNamespace) -> Result<()> {
         tracing::debug!("unshare or setns: {:?}", namespace);
         match namespace.path() {
             Some(path) => {
-                let fd = fcntl::open(path, fcntl::OFlag::empty(), stat::Mode::empty()).map_err(
-                    |err| {
-                        tracing::error!(?err, ?namespace, "failed to open namespace file");
-                        err
-                    },
-                )?;
+                let fd = fcntl::open(
+                    path,
+                    fcntl::OFlag::O_RDONLY | fcntl::OFlag::O_CLOEXEC,
+                    stat::Mode::empty(),
+                )
+                .map_err(|err| {
+                    tracing::error!(?err, ?namespace, "failed to open namespace file");
+                    err
+                })?;
+                if let Err(err) = validate_namespace_fd(fd, path, namespace.typ()) {
+                    tracing::error!(?err, ?namespace, "invalid namespace path");
+                    let _ = unistd::close(fd);
+                    return Err(err);
+                }
                 self.command
                     .set_ns(fd, get_clone_flag(namespace.typ())?)
                     .map_err(|err| {
@@ -138,6 +192,84 @@ impl Namespaces {
     }
 }
 
+/// Checks that the path refers to a namespace of the given type
+pub fn validate_namespace_path(path: &Path, typ: LinuxNamespaceType) -> Result<()> {
+    let fd = fcntl::open(
+        path,
+        fcntl::OFlag::O_RDONLY | fcntl::OFlag::O_CLOEXEC,
+        stat::Mode::empty(),
+    )?;
+    let result = validate_namespace_fd(fd, path, typ);
+    unistd::close(fd)?;
+    result
+}
+
+fn validate_namespace_fd(fd: RawFd, path: &Path, typ: LinuxNamespaceType) -> Result<()> {
+    let fs = statfs::fstatfs(unsafe { std::os::fd::BorrowedFd::borrow_raw(fd) })?;
+    if fs.filesystem_type() != statfs::NSFS_MAGIC {
+        return Err(NamespaceError::NotANamespace {
+            path: path.to_owned(),
+        });
+    }
+
+    // NS_GET_NSTYPE is only available since Linux 4.11, without it the type
+    // is checked by setns
+    let nstype = unsafe { libc::ioctl(fd, NS_GET_NSTYPE) };
+    if nstype >= 0 && nstype != get_clone_flag(typ)?.bits() {
+        return Err(NamespaceError::WrongNamespaceType {
+            path: path.to_owned(),
+            expected: typ,
+        });
+    }
+
+    Ok(())
+}
+
+/// Converts the time offsets of the spec into the format of
+/// /proc/<pid>/timens_offsets. The offset of each clock is given in seconds,
+/// optionally followed by the nanoseconds separated by whitespace.
+pub fn format_time_offsets(offsets: &HashMap<String, String>) -> Result<String> {
+    let mut formatted = String::new();
+    for clock in TIME_NAMESPACE_CLOCKS {
+        let offset = match offsets.get(*clock) {
+            Some(offset) => offset,
+            None => continue,
+        };
+
+        let invalid = || NamespaceError::InvalidTimeOffset {
+            clock: clock.to_string(),
+            offset: offset.to_owned(),
+        };
+        let mut parts = offset.split_whitespace();
+        let secs: i64 = parts
+            .next()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?;
+        let nanosecs: u32 = match parts.next() {
+            Some(nanosecs) => nanosecs.parse().map_err(|_| invalid())?,
+            None => 0,
+        };
+        if nanosecs >= 1_000_000_000 || parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        formatted.push_str(&format!("{clock} {secs} {nanosecs}\n"));
+    }
+
+    if let Some(clock) = offsets
+        .keys()
+        .find(|clock| !TIME_NAMESPACE_CLOCKS.contains(&clock.as_str()))
+    {
+        return Err(NamespaceError::InvalidTimeOffset {
+            clock: clock.to_owned(),
+            offset: offsets[clock].to_owned(),
+        });
+    }
+
+    Ok(formatted)
+}
+
 #[cfg(test)]
 mod tests {
     use oci_spec::runtime::{LinuxNamespaceBuilder, LinuxNamespaceType};
@@ -150,12 +282,12 @@ mod tests {
         vec![
             LinuxNamespaceBuilder::default()
                 .typ(LinuxNamespaceType::Mount)
-                .path("/dev/null")
+                .path("/proc/self/ns/mnt")
                 .build()
                 .unwrap(),
             LinuxNamespaceBuilder::default()
                 .typ(LinuxNamespaceType::Network)
-                .path("/dev/null")
+                .path("/proc/self/ns/net")
                 .build()
                 .unwrap(),
             LinuxNamespaceBuilder::default()
@@ -200,4 +332,74 @@ mod tests {
         expect.sort();
         assert_eq!(unshare_args, expect)
     }
+
+    #[test]
+    #[serial]
+    fn test_join_existing() {
+        let sample_linux_namespaces = gen_sample_linux_namespaces();
+        let namespaces = Namespaces::try_from(Some(&sample_linux_namespaces))
+            .expect("create namespace struct should be good");
+        let test_command: &TestHelperSyscall = namespaces.command.as_any().downcast_ref().unwrap();
+        assert!(namespaces
+            .join_existing(|ns_type| ns_type != CloneFlags::CLONE_NEWNS)
+            .is_ok());
+
+        let setns_args: Vec<_> = test_command
+            .get_setns_args()
+            .into_iter()
+            .map(|(_fd, cf)| cf)
+            .collect();
+        assert_eq!(setns_args, vec![CloneFlags::CLONE_NEWNET]);
+        assert!(test_command.get_unshare_args().is_empty());
+        assert!(namespaces.is_joined(CloneFlags::CLONE_NEWNET));
+        assert!(!namespaces.is_joined(CloneFlags::CLONE_NEWPID));
+    }
+
+    #[test]
+    fn test_validate_namespace_path() {
+        assert!(validate_namespace_path(
+            Path::new("/proc/self/ns/net"),
+            LinuxNamespaceType::Network
+        )
+        .is_ok());
+        assert!(matches!(
+            validate_namespace_path(Path::new("/proc/self/ns/net"), LinuxNamespaceType::Ipc),
+            Err(NamespaceError::WrongNamespaceType { .. })
+        ));
+        assert!(matches!(
+            validate_namespace_path(Path::new("/dev/null"), LinuxNamespaceType::Network),
+            Err(NamespaceError::NotANamespace { .. })
+        ));
+        assert!(
+            validate_namespace_path(Path::new("/does/not/exist"), LinuxNamespaceType::Uts).is_err()
+        );
+    }
+
+    #[test]
+    fn test_format_time_offsets() -> Result<()> {
+        let offsets = HashMap::from([
+            ("boottime".to_string(), "-100".to_string()),
+            ("monotonic".to_string(), "86400 500".to_string()),
+        ]);
+        assert_eq!(
+            format_time_offsets(&offsets)?,
+            "monotonic 86400 500\nboottime -100 0\n"
+        );
+
+        for (clock, offset) in [
+            ("monotonic", ""),
+            ("monotonic", "1 1000000000"),
+            ("monotonic", "1 2 3"),
+            ("boottime", "one"),
+            ("realtime", "1"),
+        ] {
+            let offsets = HashMap::from([(clock.to_string(), offset.to_string())]);
+            assert!(
+                format_time_offsets(&offsets).is_err(),
+                "{clock} {offset} should be rejected"
+            );
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,338 @@
+//! Network interfaces of the host which are moved into the network namespace
+//! of the container, e.g. SR-IOV virtual functions or macvlan interfaces
+//! prepared for it, so that no separate CNI step is needed. This is the
+//! `linux.netDevices` of newer versions of the runtime spec, which the spec
+//! types in use don't have yet, so the interfaces are requested with the
+//! [`NET_DEVICES_ANNOTATION`] as a comma separated list of
+//! `<host name>[:<name in the container>]`.
+//!
+//! The interfaces are renamed and moved back to the host when the container is
+//! deleted. Physical interfaces are moved back by the kernel once the network
+//! namespace is destroyed, only their name has to be restored then, while
+//! virtual interfaces are destroyed along with the namespace.
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::os::fd::AsFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::sched::CloneFlags;
+use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+
+use crate::rtnetlink;
+
+/// Annotation requesting interfaces of the host to be moved into the container
+pub const NET_DEVICES_ANNOTATION: &str = "org.youki.net.devices";
+
+/// File in the state directory of a container with the interfaces which have
+/// been moved into it
+const NET_DEVICES_FILE: &str = "net_devices.json";
+
+// the size of interface names including the terminating NUL
+// the name the kernel gives an interface which is moved back to the host
+// while its name is taken
+const FALLBACK_NAME_PREFIX: &str = "dev";
+// how long it is waited for the kernel to move the interfaces of a destroyed
+// network namespace back, which happens asynchronously
+const RESTORE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, thiserror::Error)]
+pub enum NetDeviceError {
+    #[error("invalid network device {0:?} in the {NET_DEVICES_ANNOTATION} annotation")]
+    Invalid(String),
+    #[error("network device {0} doesn't exist")]
+    NotFound(String),
+    #[error("failed to open network namespace {path:?}")]
+    OpenNetns {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("failed to enter network namespace")]
+    EnterNetns(#[source] nix::Error),
+    #[error("failed to open netlink socket")]
+    Netlink(#[source] nix::Error),
+    #[error("failed to move network device {name}")]
+    Move { source: nix::Error, name: String },
+    #[error("failed to store moved network devices at {path:?}")]
+    Store {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("failed to load moved network devices from {path:?}")]
+    Load {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+}
+
+type Result<T> = std::result::Result<T, NetDeviceError>;
+
+/// Interface of the host requested for the container
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetDevice {
+    /// Name of the interface on the host
+    pub host_name: String,
+    /// Name of the interface in the container
+    pub name: String,
+}
+
+/// Interface which has been moved into the container, with what is needed to
+/// move it back
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct MovedNetDevice {
+    #[serde(flatten)]
+    device: NetDevice,
+    /// Index of the interface, which is kept when it is moved between
+    /// namespaces unless the index is taken
+    index: u32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MovedNetDevices {
+    /// Network namespace of the container if it outlives the container
+    netns: Option<PathBuf>,
+    devices: Vec<MovedNetDevice>,
+}
+
+/// Returns the interfaces requested with the annotation
+pub fn from_annotations(annotations: Option<&HashMap<String, String>>) -> Result<Vec<NetDevice>> {
+    let value = match annotations.and_then(|a| a.get(NET_DEVICES_ANNOTATION)) {
+        Some(value) => value,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut devices: Vec<NetDevice> = Vec::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (host_name, name) = entry.split_once(':').unwrap_or((entry, entry));
+        if !is_valid_name(host_name) || !is_valid_name(name) {
+            return Err(NetDeviceError::Invalid(entry.to_owned()));
+        }
+        if devices
+            .iter()
+            .any(|d| d.host_name == host_name || d.name == name)
+        {
+            return Err(NetDeviceError::Invalid(entry.to_owned()));
+        }
+        devices.push(NetDevice {
+            host_name: host_name.to_owned(),
+            name: name.to_owned(),
+        });
+    }
+
+    Ok(devices)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() < rtnetlink::IFNAMSIZ
+        && name != "."
+        && name != ".."
+        && !name.contains(|c: char| c == '/' || c == ':' || c.is_whitespace())
+}
+
+/// Moves the interfaces into the network namespace of the process and renames
+/// them. The interfaces are recorded in the state directory of the container
+/// as they are moved, so that [`restore`] moves back all of them even if a
+/// later one fails.
+pub fn move_into(
+    state_dir: &Path,
+    devices: &[NetDevice],
+    pid: Pid,
+    netns: Option<&Path>,
+) -> Result<()> {
+    let netns_path = PathBuf::from(format!("/proc/{pid}/ns/net"));
+    let netns_file = File::open(&netns_path).map_err(|err| NetDeviceError::OpenNetns {
+        source: err,
+        path: netns_path,
+    })?;
+
+    let client = rtnetlink::Client::new().map_err(NetDeviceError::Netlink)?;
+    let mut moved = MovedNetDevices {
+        netns: netns.map(Path::to_owned),
+        devices: Vec::new(),
+    };
+    for device in devices {
+        let index = rtnetlink::index_of(&device.host_name)
+            .ok_or_else(|| NetDeviceError::NotFound(device.host_name.clone()))?;
+        tracing::debug!(?device, index, "moving network device into the container");
+        client
+            .set_name(index, &device.name, Some(netns_file.as_fd()))
+            .map_err(|err| NetDeviceError::Move {
+                source: err,
+                name: device.host_name.clone(),
+            })?;
+
+        moved.devices.push(MovedNetDevice {
+            device: device.clone(),
+            index,
+        });
+        store(state_dir, &moved)?;
+    }
+
+    Ok(())
+}
+
+/// Moves the interfaces which have been moved into the container back to the
+/// host with their original names. Failures are only logged, so that all
+/// interfaces are tried.
+pub fn restore(state_dir: &Path) -> Result<()> {
+    let moved = load(state_dir)?;
+    if moved.devices.is_empty() {
+        return Ok(());
+    }
+
+    let netns = moved.netns.as_deref().filter(|netns| netns.exists());
+    match netns {
+        Some(netns) => restore_from_netns(netns, &moved.devices)?,
+        None => rename_returned(&moved.devices),
+    }
+
+    Ok(())
+}
+
+// the namespace outlives the container, so the interfaces are still in it
+fn restore_from_netns(netns: &Path, devices: &[MovedNetDevice]) -> Result<()> {
+    let open = |path: &Path| {
+        File::open(path).map_err(|err| NetDeviceError::OpenNetns {
+            source: err,
+            path: path.to_owned(),
+        })
+    };
+    let host_netns = open(Path::new("/proc/self/ns/net"))?;
+    let container_netns = open(netns)?;
+
+    // only the thread enters the namespace of the container, which keeps the
+    // rest of the process in the namespace of the host
+    thread::scope(|s| {
+        s.spawn(|| {
+            nix::sched::setns(&container_netns, CloneFlags::CLONE_NEWNET)
+                .map_err(NetDeviceError::EnterNetns)?;
+            let client = rtnetlink::Client::new().map_err(NetDeviceError::Netlink)?;
+            for moved in devices {
+                let index = match rtnetlink::index_of(&moved.device.name) {
+                    Some(index) => index,
+                    None => {
+                        tracing::warn!(device = ?moved.device, "network device is gone from the container");
+                        continue;
+                    }
+                };
+                if let Err(err) =
+                    client.set_name(index, &moved.device.host_name, Some(host_netns.as_fd()))
+                {
+                    tracing::warn!(device = ?moved.device, ?err, "failed to move network device back");
+                }
+            }
+            Ok(())
+        })
+        .join()
+        .unwrap_or(Err(NetDeviceError::EnterNetns(Errno::EINVAL)))
+    })
+}
+
+// the kernel moves physical interfaces back to the host when the namespace is
+// destroyed, under the name they had in the container or a fallback name if
+// that is taken
+fn rename_returned(devices: &[MovedNetDevice]) {
+    let client = match rtnetlink::Client::new() {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::warn!(?err, "failed to restore the names of network devices");
+            return;
+        }
+    };
+
+    let deadline = Instant::now() + RESTORE_TIMEOUT;
+    for moved in devices {
+        let current = loop {
+            match rtnetlink::name_of(moved.index) {
+                Some(name) => break Some(name),
+                None if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                // virtual interfaces are destroyed with the namespace
+                None => break None,
+            }
+        };
+
+        // the index may have been taken by another interface in the meantime
+        match current {
+            Some(name) if name == moved.device.host_name => {}
+            Some(name) if name == moved.device.name || name.starts_with(FALLBACK_NAME_PREFIX) => {
+                if let Err(err) = client.set_name(moved.index, &moved.device.host_name, None) {
+                    tracing::warn!(device = ?moved.device, ?err, "failed to restore the name of network device");
+                }
+            }
+            _ => tracing::debug!(device = ?moved.device, "network device has not been returned"),
+        }
+    }
+}
+
+fn store(state_dir: &Path, moved: &MovedNetDevices) -> Result<()> {
+    let path = state_dir.join(NET_DEVICES_FILE);
+    serde_json::to_vec(moved)
+        .map_err(std::io::Error::from)
+        .and_then(|content| fs::write(&path, content))
+        .map_err(|err| NetDeviceError::Store { source: err, path })
+}
+
+fn load(state_dir: &Path) -> Result<MovedNetDevices> {
+    let path = state_dir.join(NET_DEVICES_FILE);
+    match fs::read(&path) {
+        Ok(content) => serde_json::from_slice(&content).map_err(|err| NetDeviceError::Load {
+            source: err.into(),
+            path,
+        }),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(MovedNetDevices::default()),
+        Err(err) => Err(NetDeviceError::Load { source: err, path }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_from_annotations() -> Result<()> {
+        let annotations =
+            |value: &str| HashMap::from([(NET_DEVICES_ANNOTATION.to_owned(), value.to_owned())]);
+        assert!(from_annotations(None)?.is_empty());
+        assert_eq!(
+            from_annotations(Some(&annotations("ens1f0v1:eth1, macvlan0")))?,
+            vec![
+                NetDevice {
+                    host_name: "ens1f0v1".to_owned(),
+                    name: "eth1".to_owned(),
+                },
+                NetDevice {
+                    host_name: "macvlan0".to_owned(),
+                    name: "macvlan0".to_owned(),
+                },
+            ]
+        );
+        for invalid in [
+            "eth0:",
+            "a/b",
+            "averyveryverylongname",
+            "eth0:a:b",
+            "eth0,eth0:eth1",
+            "eth0:eth1,eth2:eth1",
+        ] {
+            assert!(
+                from_annotations(Some(&annotations(invalid))).is_err(),
+                "{invalid} is valid"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_without_devices() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        restore(tmp.path())?;
+        Ok(())
+    }
+}
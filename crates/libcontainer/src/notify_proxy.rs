@@ -0,0 +1,343 @@
+//! Proxy for the sd_notify protocol of systemd, see
+//! https://www.freedesktop.org/software/systemd/man/sd_notify.html.
+//! The container gets a notify socket of its own, whose messages are
+//! forwarded to the notify socket of the host for as long as the container
+//! is running. The socket of the host can't be used directly, as it isn't
+//! reachable from the mount namespace of the container and the pids in the
+//! messages don't mean anything outside of the pid namespace of the container.
+use std::fs;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use nix::errno::Errno;
+use nix::fcntl::{open, OFlag};
+use nix::sys::signal::kill;
+use nix::sys::socket::{self, MsgFlags, UnixAddr};
+use nix::sys::stat::Mode;
+use nix::unistd::Pid;
+use oci_spec::runtime::{MountBuilder, Spec};
+
+/// Environment variable with the path of the notify socket
+pub const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+/// Directory of the container dir which holds the socket of the container
+const SOCKET_DIR: &str = "notify";
+const SOCKET_NAME: &str = "notify.sock";
+/// Where the socket directory is mounted into the container
+const CONTAINER_SOCKET_DIR: &str = "/run/notify";
+/// How often is checked if the container is still running
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Variables which are forwarded to the host. Others, like MAINPID or the
+/// ones of the fd store, refer to the namespaces of the container.
+const FORWARDED_VARIABLES: &[&str] = &[
+    "READY",
+    "RELOADING",
+    "STOPPING",
+    "STATUS",
+    "ERRNO",
+    "BUSERROR",
+    "EXIT_STATUS",
+    "MONOTONIC_USEC",
+    "WATCHDOG",
+    "WATCHDOG_USEC",
+    "EXTEND_TIMEOUT_USEC",
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum NotifyProxyError {
+    #[error("failed to create notify socket directory {path:?}")]
+    CreateDir {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("failed to open notify socket directory {path:?}")]
+    OpenDir { source: nix::Error, path: PathBuf },
+    #[error("failed to remove previous notify socket in {path:?}")]
+    RemoveSocket {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("failed to bind notify socket in {path:?}")]
+    Bind { source: nix::Error, path: PathBuf },
+    #[error("invalid host notify socket {0:?}")]
+    InvalidHostSocket(PathBuf),
+    #[error("invalid notify socket mount")]
+    Mount(#[from] oci_spec::OciSpecError),
+    #[error("failed to receive notify message")]
+    Receive(#[source] nix::Error),
+    #[error("failed to forward notify message to {path:?}")]
+    Forward { source: nix::Error, path: PathBuf },
+}
+
+type Result<T> = std::result::Result<T, NotifyProxyError>;
+
+/// Adds the notify socket of the container to the spec. The directory of the
+/// socket is mounted instead of the socket itself, so that the socket can be
+/// bound again by a later process, e.g. `start` after `create`.
+pub fn setup_spec(spec: &mut Spec, container_dir: &Path) -> Result<()> {
+    let socket_dir = container_dir.join(SOCKET_DIR);
+    fs::create_dir_all(&socket_dir).map_err(|err| NotifyProxyError::CreateDir {
+        source: err,
+        path: socket_dir.clone(),
+    })?;
+
+    let mount = MountBuilder::default()
+        .destination(CONTAINER_SOCKET_DIR)
+        .typ("bind")
+        .source(socket_dir)
+        .options(
+            ["bind", "nosuid", "noexec", "nodev", "ro"]
+                .iter()
+                .map(|o| o.to_string())
+                .collect::<Vec<_>>(),
+        )
+        .build()?;
+    let mut mounts = spec.mounts().clone().unwrap_or_default();
+    mounts.push(mount);
+    spec.set_mounts(Some(mounts));
+
+    if let Some(process) = spec.process_mut() {
+        let prefix = format!("{NOTIFY_SOCKET_ENV}=");
+        let mut env = process.env().clone().unwrap_or_default();
+        env.retain(|e| !e.starts_with(&prefix));
+        env.push(format!("{prefix}{CONTAINER_SOCKET_DIR}/{SOCKET_NAME}"));
+        process.set_env(Some(env));
+    }
+
+    Ok(())
+}
+
+/// Checks if the container has been created with a notify socket
+pub fn is_enabled(container_dir: &Path) -> bool {
+    container_dir.join(SOCKET_DIR).is_dir()
+}
+
+pub struct NotifyProxy {
+    socket: OwnedFd,
+    host_socket: PathBuf,
+    host_addr: UnixAddr,
+}
+
+impl NotifyProxy {
+    /// Binds the notify socket of the container, replacing the socket of a
+    /// previous proxy
+    pub fn bind(container_dir: &Path, host_socket: &Path) -> Result<Self> {
+        let host_addr = host_addr(host_socket)?;
+        let socket_dir = container_dir.join(SOCKET_DIR);
+        let bind_err = |err| NotifyProxyError::Bind {
+            source: err,
+            path: socket_dir.clone(),
+        };
+
+        // The path of the container dir may exceed the maximum length of a
+        // socket address, so the socket is bound through the fd of the directory
+        let dir = open(
+            &socket_dir,
+            OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )
+        .map_err(|err| NotifyProxyError::OpenDir {
+            source: err,
+            path: socket_dir.clone(),
+        })?;
+        let dir = unsafe { OwnedFd::from_raw_fd(dir) };
+        let socket_path =
+            PathBuf::from(format!("/proc/self/fd/{}/{}", dir.as_raw_fd(), SOCKET_NAME));
+        match fs::remove_file(&socket_path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(NotifyProxyError::RemoveSocket {
+                    source: err,
+                    path: socket_dir,
+                })
+            }
+        }
+
+        let socket = socket::socket(
+            socket::AddressFamily::Unix,
+            socket::SockType::Datagram,
+            socket::SockFlag::SOCK_CLOEXEC,
+            None,
+        )
+        .map_err(bind_err)?;
+        socket::bind(
+            socket.as_raw_fd(),
+            &UnixAddr::new(&socket_path).map_err(bind_err)?,
+        )
+        .map_err(bind_err)?;
+        let timeout = nix::sys::time::TimeVal::new(POLL_INTERVAL.as_secs() as _, 0);
+        socket::setsockopt(&socket, socket::sockopt::ReceiveTimeout, &timeout).map_err(bind_err)?;
+
+        Ok(Self {
+            socket,
+            host_socket: host_socket.to_owned(),
+            host_addr,
+        })
+    }
+
+    /// Forwards the messages of the container until the process with the
+    /// given pid, which is reported to systemd as MAINPID, has exited
+    pub fn run(&self, pid: Pid) -> Result<()> {
+        let mut ready = false;
+        let mut buf = [0u8; 4096];
+        loop {
+            match socket::recv(self.socket.as_raw_fd(), &mut buf, MsgFlags::empty()) {
+                Ok(n) => {
+                    let message = String::from_utf8_lossy(&buf[..n]);
+                    self.forward(&message, pid, &mut ready)?;
+                }
+                Err(Errno::EAGAIN) | Err(Errno::EINTR) => {}
+                Err(err) => return Err(NotifyProxyError::Receive(err)),
+            }
+
+            if kill(pid, None).is_err() {
+                tracing::debug!(?pid, "process exited, stopping notify proxy");
+                return Ok(());
+            }
+        }
+    }
+
+    fn forward(&self, message: &str, pid: Pid, ready: &mut bool) -> Result<()> {
+        let message = translate_message(message, pid, ready);
+        if message.is_empty() {
+            return Ok(());
+        }
+
+        socket::sendto(
+            self.socket.as_raw_fd(),
+            message.as_bytes(),
+            &self.host_addr,
+            MsgFlags::empty(),
+        )
+        .map_err(|err| NotifyProxyError::Forward {
+            source: err,
+            path: self.host_socket.clone(),
+        })?;
+
+        Ok(())
+    }
+}
+
+/// The notify socket of the host may be in the abstract namespace
+fn host_addr(host_socket: &Path) -> Result<UnixAddr> {
+    let bytes = host_socket.as_os_str().as_bytes();
+    let addr = match bytes.strip_prefix(b"@") {
+        Some(name) => UnixAddr::new_abstract(name),
+        None => UnixAddr::new(host_socket),
+    };
+    addr.map_err(|_| NotifyProxyError::InvalidHostSocket(host_socket.to_owned()))
+}
+
+/// Drops the variables that are not forwarded and adds the MAINPID to the
+/// first READY=1
+fn translate_message(message: &str, pid: Pid, ready: &mut bool) -> String {
+    let mut lines: Vec<String> = message
+        .lines()
+        .filter(|line| {
+            let name = line.split_once('=').map_or(*line, |(name, _)| name);
+            FORWARDED_VARIABLES.contains(&name)
+        })
+        .map(|line| line.to_owned())
+        .collect();
+
+    if !*ready && lines.iter().any(|line| line == "READY=1") {
+        *ready = true;
+        lines.push(format!("MAINPID={pid}"));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixDatagram;
+
+    use anyhow::Result;
+    use oci_spec::runtime::ProcessBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_translate_message() {
+        let pid = Pid::from_raw(42);
+        let mut ready = false;
+        assert_eq!(
+            translate_message("STATUS=starting\nMAINPID=1", pid, &mut ready),
+            "STATUS=starting"
+        );
+        assert!(!ready);
+        assert_eq!(
+            translate_message("READY=1\nSTATUS=running\nFDSTORE=1", pid, &mut ready),
+            "READY=1\nSTATUS=running\nMAINPID=42"
+        );
+        assert!(ready);
+        assert_eq!(translate_message("READY=1", pid, &mut ready), "READY=1");
+        assert_eq!(
+            translate_message("WATCHDOG=1", pid, &mut ready),
+            "WATCHDOG=1"
+        );
+        assert_eq!(translate_message("FDSTORE=1", pid, &mut ready), "");
+    }
+
+    #[test]
+    fn test_setup_spec() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let mut spec = Spec::default();
+        spec.set_process(Some(
+            ProcessBuilder::default()
+                .env(vec!["NOTIFY_SOCKET=/run/systemd/notify".to_owned()])
+                .build()?,
+        ));
+
+        setup_spec(&mut spec, tmp.path())?;
+
+        assert!(is_enabled(tmp.path()));
+        let mount = spec.mounts().as_ref().unwrap().last().unwrap();
+        assert_eq!(mount.destination(), Path::new(CONTAINER_SOCKET_DIR));
+        assert_eq!(
+            mount.source().as_deref(),
+            Some(tmp.path().join(SOCKET_DIR).as_path())
+        );
+        assert_eq!(
+            spec.process().as_ref().unwrap().env().as_ref().unwrap(),
+            &vec!["NOTIFY_SOCKET=/run/notify/notify.sock".to_owned()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_forward() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let host_socket = tmp.path().join("host.sock");
+        let host = UnixDatagram::bind(&host_socket)?;
+        host.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let container_dir = tmp.path().join("container");
+        fs::create_dir_all(container_dir.join(SOCKET_DIR))?;
+        // a stale socket of a previous proxy is replaced
+        NotifyProxy::bind(&container_dir, &host_socket)?;
+        let proxy = NotifyProxy::bind(&container_dir, &host_socket)?;
+
+        let container = UnixDatagram::unbound()?;
+        container.send_to(
+            b"READY=1\nMAINPID=1",
+            container_dir.join(SOCKET_DIR).join(SOCKET_NAME),
+        )?;
+
+        let mut buf = [0u8; 4096];
+        let n = socket::recv(proxy.socket.as_raw_fd(), &mut buf, MsgFlags::empty())?;
+        let mut ready = false;
+        proxy.forward(
+            &String::from_utf8_lossy(&buf[..n]),
+            Pid::from_raw(42),
+            &mut ready,
+        )?;
+
+        let n = host.recv(&mut buf)?;
+        assert_eq!(&buf[..n], b"READY=1\nMAINPID=42");
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! Process file descriptors, which keep referring to the same process after it
+//! has exited, so that signaling or waiting for a process can't hit another
+//! process which has reused its pid. They are supported since Linux 5.3.
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::time::Duration;
+
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use procfs::process::Process;
+
+/// File descriptor referring to a process
+#[derive(Debug)]
+pub struct PidFd {
+    pid: Pid,
+    fd: OwnedFd,
+}
+
+impl PidFd {
+    /// Opens a pidfd for the process, or returns None if the kernel doesn't
+    /// support pidfds. Fails with ESRCH if there is no process with the pid.
+    pub fn open(pid: Pid) -> nix::Result<Option<Self>> {
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) };
+        match Errno::result(fd) {
+            Ok(fd) => Ok(Some(Self {
+                pid,
+                fd: unsafe { OwnedFd::from_raw_fd(fd as RawFd) },
+            })),
+            Err(Errno::ENOSYS) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Wraps a pidfd of the process, e.g. the one returned by clone3
+    pub(crate) fn from_fd(pid: Pid, fd: OwnedFd) -> Self {
+        Self { pid, fd }
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Sends the signal to the process, which fails with ESRCH once the
+    /// process has exited, even if its pid has been reused since
+    pub fn send_signal(&self, signal: Signal) -> nix::Result<()> {
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_pidfd_send_signal,
+                self.fd.as_raw_fd(),
+                signal as libc::c_int,
+                std::ptr::null::<libc::siginfo_t>(),
+                0,
+            )
+        };
+        Errno::result(res).map(drop)
+    }
+
+    /// Waits until the process has exited, for at most the timeout if one is
+    /// given, and returns whether it has exited. An exited child still has to
+    /// be reaped with waitpid.
+    pub fn wait_exit(&self, timeout: Option<Duration>) -> nix::Result<bool> {
+        let timeout = match timeout {
+            Some(timeout) => PollTimeout::try_from(timeout).unwrap_or(PollTimeout::MAX),
+            None => PollTimeout::NONE,
+        };
+        let mut fds = [PollFd::new(self.fd.as_fd(), PollFlags::POLLIN)];
+        loop {
+            match poll(&mut fds, timeout) {
+                Ok(ready) => return Ok(ready > 0),
+                Err(Errno::EINTR) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl AsFd for PidFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// Returns the time the process has been started at, in clock ticks since
+/// boot, which tells apart processes which had the same pid
+pub fn start_time(pid: Pid) -> Option<u64> {
+    Process::new(pid.as_raw())
+        .and_then(|process| process.stat())
+        .map(|stat| stat.starttime)
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_signal_and_wait() -> Result<()> {
+        let mut child = Command::new("sleep").arg("10").spawn()?;
+        let pid = Pid::from_raw(child.id() as i32);
+        let pidfd = match PidFd::open(pid)? {
+            Some(pidfd) => pidfd,
+            // kernels older than 5.3
+            None => return Ok(()),
+        };
+        assert_eq!(pidfd.pid(), pid);
+        assert!(!pidfd.wait_exit(Some(Duration::ZERO))?);
+
+        pidfd.send_signal(Signal::SIGKILL)?;
+        assert!(pidfd.wait_exit(Some(Duration::from_secs(10)))?);
+        child.wait()?;
+        // the pidfd keeps referring to the reaped process
+        assert_eq!(pidfd.send_signal(Signal::SIGKILL), Err(Errno::ESRCH));
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_time() -> Result<()> {
+        let pid = nix::unistd::getpid();
+        assert_eq!(start_time(pid), Some(Process::myself()?.stat()?.starttime));
+        assert_eq!(start_time(Pid::from_raw(i32::MAX)), None);
+        Ok(())
+    }
+}
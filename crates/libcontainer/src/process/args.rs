@@ -28,10 +28,15 @@ pub struct ContainerArgs {
     pub rootfs: PathBuf,
     /// Socket to communicate the file descriptor of the ptty
     pub console_socket: Option<RawFd>,
+    /// Files used as stdin, stdout and stderr of the container process
+    pub stdio: [Option<RawFd>; 3],
     /// The Unix Domain Socket to communicate container start
     pub notify_listener: NotifyListener,
     /// File descriptors preserved/passed to the container init process.
     pub preserve_fds: i32,
+    /// Number of fds passed by systemd socket activation, which are
+    /// preserved in front of the `preserve_fds`
+    pub listen_fds: i32,
     /// Container state
     pub container: Option<Container>,
     /// Options for new namespace creation
@@ -40,6 +45,13 @@ pub struct ContainerArgs {
     pub cgroup_config: CgroupConfig,
     /// If the container is to be run in detached mode
     pub detached: bool,
+    /// Enter the rootfs with chroot instead of pivot_root
+    pub no_pivot: bool,
+    /// Keep the session keyring of the caller instead of creating a new one
+    pub no_new_keyring: bool,
     /// Manage the functions that actually run on the container
     pub executor: Box<dyn Executor>,
+    /// BPF program of the seccomp filter from the cache, which is loaded
+    /// instead of compiling the filter in the container process
+    pub seccomp_program: Option<Vec<u8>>,
 }
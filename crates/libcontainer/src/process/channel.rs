@@ -3,6 +3,7 @@ use std::os::unix::prelude::{AsRawFd, RawFd};
 use nix::unistd::Pid;
 
 use crate::channel::{channel, Receiver, Sender};
+use crate::log_capture;
 use crate::process::message::Message;
 
 #[derive(Debug, thiserror::Error)]
@@ -22,6 +23,8 @@ pub enum ChannelError {
     BaseChannelError(#[from] crate::channel::ChannelError),
     #[error("missing fds from seccomp request")]
     MissingSeccompFds,
+    #[error("missing fd of idmapped mount")]
+    MissingIdmappedMountFd,
     #[error("exec process failed with error {0}")]
     ExecError(String),
     #[error("intermediate process error {0}")]
@@ -65,6 +68,15 @@ impl MainSender {
         Ok(())
     }
 
+    // requests the Main to run the hooks of the create operation which run
+    // in the runtime namespace, once the container environment has been set up
+    pub fn hook_request(&mut self) -> Result<(), ChannelError> {
+        tracing::debug!("send hook request");
+        self.sender.send(Message::HookRequest)?;
+
+        Ok(())
+    }
+
     pub fn intermediate_ready(&mut self, pid: Pid) -> Result<(), ChannelError> {
         // Send over the IntermediateReady follow by the pid.
         tracing::debug!("sending init pid ({:?})", pid);
@@ -79,8 +91,13 @@ impl MainSender {
         Ok(())
     }
 
+    /// Reports the failure of the init process along with the log lines which
+    /// have been captured in it
     pub fn exec_failed(&mut self, err: String) -> Result<(), ChannelError> {
-        self.sender.send(Message::ExecFailed(err))?;
+        self.sender.send(Message::ExecFailed {
+            error: err,
+            logs: log_capture::take(),
+        })?;
         Ok(())
     }
 
@@ -96,6 +113,15 @@ impl MainSender {
     }
 }
 
+// the logs of the init process may have gone to the stdio of the container,
+// so they are logged again where the caller of the runtime sees them
+fn exec_error(error: String, logs: Vec<String>) -> ChannelError {
+    for line in logs {
+        tracing::error!(target: "init", "{line}");
+    }
+    ChannelError::ExecError(error)
+}
+
 pub struct MainReceiver {
     receiver: Receiver<Message>,
 }
@@ -114,7 +140,7 @@ impl MainReceiver {
 
         match msg {
             Message::IntermediateReady(pid) => Ok(Pid::from_raw(pid)),
-            Message::ExecFailed(err) => Err(ChannelError::ExecError(err)),
+            Message::ExecFailed { error, logs } => Err(exec_error(error, logs)),
             Message::OtherError(err) => Err(ChannelError::OtherError(err)),
             msg => Err(ChannelError::UnexpectedMessage {
                 expected: Message::IntermediateReady(0),
@@ -169,6 +195,28 @@ impl MainReceiver {
         }
     }
 
+    /// Waits for the init process to be ready for the prestart and
+    /// createRuntime hooks
+    pub fn wait_for_hook_request(&mut self) -> Result<(), ChannelError> {
+        let msg = self
+            .receiver
+            .recv()
+            .map_err(|err| ChannelError::ReceiveError {
+                msg: "waiting for hook request".to_string(),
+                source: err,
+            })?;
+
+        match msg {
+            Message::HookRequest => Ok(()),
+            Message::ExecFailed { error, logs } => Err(exec_error(error, logs)),
+            Message::OtherError(err) => Err(ChannelError::OtherError(err)),
+            msg => Err(ChannelError::UnexpectedMessage {
+                expected: Message::HookRequest,
+                received: msg,
+            }),
+        }
+    }
+
     /// Waits for associated init process to send ready message
     /// and return the pid of init process which is forked by init process
     pub fn wait_for_init_ready(&mut self) -> Result<(), ChannelError> {
@@ -182,9 +230,10 @@ impl MainReceiver {
         match msg {
             Message::InitReady => Ok(()),
             // this case in unique and known enough to have a special error format
-            Message::ExecFailed(err) => Err(ChannelError::ExecError(format!(
-                "error in executing process : {err}"
-            ))),
+            Message::ExecFailed { error, logs } => Err(exec_error(
+                format!("error in executing process : {error}"),
+                logs,
+            )),
             msg => Err(ChannelError::UnexpectedMessage {
                 expected: Message::InitReady,
                 received: msg,
@@ -273,6 +322,18 @@ impl InitSender {
         Ok(())
     }
 
+    pub fn hook_done(&mut self) -> Result<(), ChannelError> {
+        self.sender.send(Message::HookDone)?;
+
+        Ok(())
+    }
+
+    pub fn idmapped_mount(&mut self, fd: RawFd) -> Result<(), ChannelError> {
+        self.sender.send_fds(Message::IdmappedMount, &[fd])?;
+
+        Ok(())
+    }
+
     pub fn close(&self) -> Result<(), ChannelError> {
         self.sender.close()?;
 
@@ -303,6 +364,44 @@ impl InitReceiver {
         }
     }
 
+    pub fn wait_for_hook_done(&mut self) -> Result<(), ChannelError> {
+        let msg = self
+            .receiver
+            .recv()
+            .map_err(|err| ChannelError::ReceiveError {
+                msg: "waiting for hook done".to_string(),
+                source: err,
+            })?;
+
+        match msg {
+            Message::HookDone => Ok(()),
+            msg => Err(ChannelError::UnexpectedMessage {
+                expected: Message::HookDone,
+                received: msg,
+            }),
+        }
+    }
+
+    pub fn wait_for_idmapped_mount(&mut self) -> Result<RawFd, ChannelError> {
+        let (msg, fds) = self.receiver.recv_with_fds::<[RawFd; 1]>().map_err(|err| {
+            ChannelError::ReceiveError {
+                msg: "waiting for idmapped mount".to_string(),
+                source: err,
+            }
+        })?;
+
+        match msg {
+            Message::IdmappedMount => match fds {
+                Some([fd]) => Ok(fd),
+                None => Err(ChannelError::MissingIdmappedMountFd),
+            },
+            msg => Err(ChannelError::UnexpectedMessage {
+                expected: Message::IdmappedMount,
+                received: msg,
+            }),
+        }
+    }
+
     pub fn close(&self) -> Result<(), ChannelError> {
         self.receiver.close()?;
 
@@ -392,6 +491,34 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[serial]
+    fn test_channel_hook_request() -> Result<()> {
+        let (main_sender, main_receiver) = &mut main_channel()?;
+        let (init_sender, init_receiver) = &mut init_channel()?;
+        match unsafe { unistd::fork()? } {
+            unistd::ForkResult::Parent { child } => {
+                main_receiver.wait_for_hook_request()?;
+                init_sender.hook_done()?;
+                wait::waitpid(child, None)?;
+                main_receiver.close()?;
+                init_sender.close()?;
+            }
+            unistd::ForkResult::Child => {
+                main_sender
+                    .hook_request()
+                    .with_context(|| "Failed to send hook request")?;
+                init_receiver
+                    .wait_for_hook_done()
+                    .with_context(|| "Failed to wait for hook done")?;
+                main_sender.close()?;
+                std::process::exit(0);
+            }
+        };
+
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_channel_init_ready() -> Result<()> {
@@ -1,7 +1,8 @@
 use std::collections::HashMap;
+use std::os::fd::{FromRawFd, OwnedFd};
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
-use std::{env, fs, mem};
+use std::{fs, mem};
 
 use nc;
 use nix::mount::MsFlags;
@@ -9,20 +10,24 @@ use nix::sched::CloneFlags;
 use nix::sys::stat::Mode;
 use nix::unistd::{self, setsid, Gid, Uid};
 use oci_spec::runtime::{
-    IOPriorityClass, LinuxIOPriority, LinuxNamespaceType, LinuxSchedulerFlag, LinuxSchedulerPolicy,
-    Scheduler, Spec, User,
+    IOPriorityClass, LinuxIOPriority, LinuxNamespaceType, LinuxPersonality, LinuxPersonalityDomain,
+    LinuxSchedulerFlag, LinuxSchedulerPolicy, Scheduler, Spec, User,
 };
 
 use super::args::{ContainerArgs, ContainerType};
+use crate::container::{Container, ContainerStatus};
 use crate::error::MissingSpecError;
-use crate::namespaces::{NamespaceError, Namespaces};
+use crate::namespaces::{self, NamespaceError, Namespaces};
 use crate::process::channel;
 use crate::rootfs::RootFS;
-#[cfg(feature = "libseccomp")]
+#[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
 use crate::seccomp;
-use crate::syscall::{Syscall, SyscallError};
+use crate::syscall::{linux, Syscall, SyscallError};
 use crate::user_ns::UserNamespaceConfig;
-use crate::{apparmor, capabilities, hooks, notify_socket, rootfs, tty, utils, workload};
+use crate::{
+    apparmor, capabilities, hooks, notify_socket, rootfs, rtnetlink, selinux, tty, user, utils,
+    workload,
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum InitProcessError {
@@ -38,6 +43,8 @@ pub enum InitProcessError {
     SetHostname(#[source] SyscallError),
     #[error("failed to set domainname")]
     SetDomainname(#[source] SyscallError),
+    #[error("failed to bring up the loopback interface")]
+    Loopback(#[source] nix::Error),
     #[error("failed to reopen /dev/null")]
     ReopenDevNull(#[source] std::io::Error),
     #[error("failed to unix syscall")]
@@ -54,10 +61,12 @@ pub enum InitProcessError {
     SyscallOther(#[source] SyscallError),
     #[error("failed apparmor")]
     AppArmor(#[source] apparmor::AppArmorError),
+    #[error("failed selinux")]
+    SELinux(#[source] selinux::SELinuxError),
     #[error("invalid umask")]
     InvalidUmask(u32),
     #[error(transparent)]
-    #[cfg(feature = "libseccomp")]
+    #[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
     Seccomp(#[from] seccomp::SeccompError),
     #[error("invalid executable: {0}")]
     InvalidExecutable(String),
@@ -75,12 +84,12 @@ pub enum InitProcessError {
     WorkloadValidation(#[from] workload::ExecutorValidationError),
     #[error(transparent)]
     WorkloadSetEnvs(#[from] workload::ExecutorSetEnvsError),
-    #[error("invalid io priority class: {0}")]
-    IoPriorityClass(String),
     #[error("call exec sched_setattr error: {0}")]
     SchedSetattr(String),
     #[error("failed to verify if current working directory is safe")]
     InvalidCwd(#[source] nix::Error),
+    #[error(transparent)]
+    User(#[from] user::UserError),
 }
 
 type Result<T> = std::result::Result<T, InitProcessError>;
@@ -104,10 +113,75 @@ fn sysctl(kernel_params: &HashMap<String, String>) -> Result<()> {
 }
 
 // make a read only path
+// With the new mount API, the whole mount tree below the path is made read only,
+// including submounts. On kernels without mount_setattr(2) we fall back to a bind remount.
+fn readonly_path(path: &Path, syscall: &dyn Syscall) -> Result<()> {
+    match readonly_path_recursive(path, syscall) {
+        Ok(()) => {
+            tracing::debug!("readonly path {:?} mounted", path);
+            return Ok(());
+        }
+        // ignore error if path is not exist.
+        Err(SyscallError::Nix(nix::errno::Errno::ENOENT)) => return Ok(()),
+        // the new mount API is not available, or it is blocked by a seccomp profile
+        Err(SyscallError::Nix(nix::errno::Errno::ENOSYS))
+        | Err(SyscallError::Nix(nix::errno::Errno::EPERM)) => {
+            tracing::debug!(
+                ?path,
+                "new mount API is not available, remounting readonly path"
+            );
+        }
+        Err(err) => {
+            tracing::error!(?path, ?err, "failed to mount path as readonly");
+            return Err(InitProcessError::MountPathReadonly(err));
+        }
+    }
+
+    readonly_path_remount(path, syscall)
+}
+
+// Clones the mount tree at the path, makes the clone read only recursively and
+// mounts it over the path.
+fn readonly_path_recursive(
+    path: &Path,
+    syscall: &dyn Syscall,
+) -> std::result::Result<(), SyscallError> {
+    let tree = syscall.open_tree(
+        libc::AT_FDCWD,
+        path,
+        linux::OPEN_TREE_CLONE | linux::OPEN_TREE_CLOEXEC | linux::AT_RECURSIVE,
+    )?;
+
+    let mount_attr = linux::MountAttr {
+        attr_set: linux::MOUNT_ATTR_RDONLY
+            | linux::MOUNT_ATTR_NOSUID
+            | linux::MOUNT_ATTR_NODEV
+            | linux::MOUNT_ATTR_NOEXEC,
+        attr_clr: 0,
+        propagation: 0,
+        userns_fd: 0,
+    };
+    syscall.mount_setattr(
+        tree.as_raw_fd(),
+        Path::new(""),
+        linux::AT_EMPTY_PATH | linux::AT_RECURSIVE,
+        &mount_attr,
+        mem::size_of::<linux::MountAttr>(),
+    )?;
+
+    syscall.move_mount(
+        tree.as_raw_fd(),
+        Path::new(""),
+        libc::AT_FDCWD,
+        path,
+        linux::MOVE_MOUNT_F_EMPTY_PATH,
+    )
+}
+
 // The first time we bind mount, other flags are ignored,
 // so we need to mount it once and then remount it with the necessary flags specified.
 // https://man7.org/linux/man-pages/man2/mount.2.html
-fn readonly_path(path: &Path, syscall: &dyn Syscall) -> Result<()> {
+fn readonly_path_remount(path: &Path, syscall: &dyn Syscall) -> Result<()> {
     if let Err(err) = syscall.mount(
         Some(path),
         path,
@@ -194,8 +268,8 @@ fn masked_path(path: &Path, mount_label: &Option<String>, syscall: &dyn Syscall)
     Ok(())
 }
 
-// Enter into rest of namespace. Note, we already entered into user and pid
-// namespace. We also have to enter into mount namespace last since
+// Enter into rest of namespace. Note, we already entered into user, pid and
+// time namespace. We also have to enter into mount namespace last since
 // namespace may be bind to /proc path. The /proc path will need to be
 // accessed before pivot_root.
 fn apply_rest_namespaces(
@@ -205,12 +279,17 @@ fn apply_rest_namespaces(
 ) -> Result<()> {
     namespaces
         .apply_namespaces(|ns_type| -> bool {
-            ns_type != CloneFlags::CLONE_NEWUSER && ns_type != CloneFlags::CLONE_NEWPID
+            // all namespaces given by path but the mount namespace have
+            // already been joined by the intermediate process
+            ns_type != CloneFlags::CLONE_NEWUSER
+                && ns_type != CloneFlags::CLONE_NEWPID
+                && ns_type != namespaces::CLONE_NEWTIME
+                && (ns_type == CloneFlags::CLONE_NEWNS || !namespaces.is_joined(ns_type))
         })
         .map_err(|err| {
             tracing::error!(
                 ?err,
-                "failed to apply rest of the namespaces (exclude user and pid)"
+                "failed to apply rest of the namespaces (exclude user, pid, time and joined)"
             );
             InitProcessError::Namespaces(err)
         })?;
@@ -233,6 +312,20 @@ fn apply_rest_namespaces(
             }
         }
     }
+
+    // A new network namespace only has a loopback interface, which is down.
+    // Images commonly expect 127.0.0.1 to be reachable before any CNI plugin
+    // has run, so it is brought up like other runtimes do.
+    if let Some(net_namespace) = namespaces.get(LinuxNamespaceType::Network)? {
+        if net_namespace.path().is_none() {
+            rtnetlink::Client::new()
+                .and_then(|client| client.set_up(rtnetlink::LOOPBACK_INDEX))
+                .map_err(|err| {
+                    tracing::error!(?err, "failed to bring up the loopback interface");
+                    InitProcessError::Loopback(err)
+                })?;
+        }
+    }
     Ok(())
 }
 
@@ -296,8 +389,19 @@ pub fn container_init_process(
 
     set_io_priority(syscall.as_ref(), proc.io_priority())?;
 
+    set_personality(syscall.as_ref(), linux.personality())?;
+
     setup_scheduler(proc.scheduler())?;
 
+    for (fd, target) in args.stdio.iter().zip(0..) {
+        if let Some(fd) = fd {
+            unistd::dup2(*fd, target).map_err(|err| {
+                tracing::error!(?err, target, "failed to set up stdio");
+                InitProcessError::NixOther(err)
+            })?;
+        }
+    }
+
     // set up tty if specified
     if let Some(csocketfd) = args.console_socket {
         tty::setup_console(&csocketfd).map_err(|err| {
@@ -312,11 +416,30 @@ pub fn container_init_process(
         let _ = prctl::set_no_new_privileges(true);
     }
 
+    if !args.no_new_keyring {
+        join_session_keyring(syscall.as_ref(), container, proc.selinux_label().as_deref())?;
+    }
+
     if matches!(args.container_type, ContainerType::InitContainer) {
+        // the idmapped mounts have been sent before anything else on the channel
+        let idmapped_mounts = if args.user_ns_config.is_some() {
+            receive_idmapped_mounts(spec, init_receiver)?
+        } else {
+            HashMap::new()
+        };
+
+        // prestart and createRuntime hooks are run by the main process in the
+        // runtime namespace, now that the container namespaces exist
+        if hooks::has_create_runtime_hooks(hooks) {
+            main_sender.hook_request()?;
+            init_receiver.wait_for_hook_done()?;
+        }
+
         // create_container hook needs to be called after the namespace setup, but
         // before pivot_root is called. This runs in the container namespaces.
         if let Some(hooks) = hooks {
-            hooks::run_hooks(hooks.create_container().as_ref(), container, None).map_err(
+            let state = hook_state(container, ContainerStatus::Creating);
+            hooks::run_hooks(hooks.create_container().as_ref(), state.as_ref(), None).map_err(
                 |err| {
                     tracing::error!(?err, "failed to run create container hooks");
                     InitProcessError::Hooks(err)
@@ -325,30 +448,45 @@ pub fn container_init_process(
         }
 
         let in_user_ns = utils::is_in_new_userns().map_err(InitProcessError::Io)?;
-        let bind_service = namespaces.get(LinuxNamespaceType::User)?.is_some() || in_user_ns;
+        let user_ns = namespaces.get(LinuxNamespaceType::User)?.is_some() || in_user_ns;
         let rootfs = RootFS::new();
         rootfs
             .prepare_rootfs(
                 spec,
                 rootfs_path,
-                bind_service,
+                user_ns,
                 namespaces.get(LinuxNamespaceType::Cgroup)?.is_some(),
+                &idmapped_mounts,
             )
             .map_err(|err| {
                 tracing::error!(?err, "failed to prepare rootfs");
                 InitProcessError::RootFS(err)
             })?;
 
+        if args.console_socket.is_some() {
+            tty::mount_console(rootfs_path).map_err(|err| {
+                tracing::error!(?err, "failed to mount console");
+                InitProcessError::Tty(err)
+            })?;
+        }
+
         // Entering into the rootfs jail. If mount namespace is specified, then
         // we use pivot_root, but if we are on the host mount namespace, we will
         // use simple chroot. Scary things will happen if you try to pivot_root
         // in the host mount namespace...
         if namespaces.get(LinuxNamespaceType::Mount)?.is_some() {
-            // change the root of filesystem of the process to the rootfs
-            syscall.pivot_rootfs(rootfs_path).map_err(|err| {
-                tracing::error!(?err, ?rootfs_path, "failed to pivot root");
-                InitProcessError::SyscallOther(err)
-            })?;
+            if args.no_pivot {
+                syscall.move_rootfs(rootfs_path).map_err(|err| {
+                    tracing::error!(?err, ?rootfs_path, "failed to move root");
+                    InitProcessError::SyscallOther(err)
+                })?;
+            } else {
+                // change the root of filesystem of the process to the rootfs
+                syscall.pivot_rootfs(rootfs_path).map_err(|err| {
+                    tracing::error!(?err, ?rootfs_path, "failed to pivot root");
+                    InitProcessError::SyscallOther(err)
+                })?;
+            }
         } else {
             syscall.chroot(rootfs_path).map_err(|err| {
                 tracing::error!(?err, ?rootfs_path, "failed to chroot");
@@ -383,6 +521,13 @@ pub fn container_init_process(
         })?;
     }
 
+    if let Some(label) = proc.selinux_label() {
+        selinux::set_exec_label(label).map_err(|err| {
+            tracing::error!(?err, "failed to set selinux label");
+            InitProcessError::SELinux(err)
+        })?;
+    }
+
     if let Some(true) = spec.root().as_ref().map(|r| r.readonly().unwrap_or(false)) {
         syscall
             .mount(
@@ -446,60 +591,43 @@ pub fn container_init_process(
         }
     };
 
-    set_supplementary_gids(proc.user(), &args.user_ns_config, syscall.as_ref()).map_err(|err| {
+    // the rootfs of the container is the root by now, so that a user given
+    // by name is resolved with its /etc/passwd and /etc/group
+    let user = user::resolve(proc.user(), Path::new("/")).map_err(|err| {
+        tracing::error!(?err, "failed to resolve user");
+        err
+    })?;
+
+    set_supplementary_gids(&user, &args.user_ns_config, syscall.as_ref()).map_err(|err| {
         tracing::error!(?err, "failed to set supplementary gids");
         err
     })?;
 
+    if args.console_socket.is_some() {
+        tty::chown_stdio_ptys(Uid::from_raw(user.uid())).map_err(|err| {
+            tracing::error!(?err, "failed to change the owner of the tty");
+            InitProcessError::Tty(err)
+        })?;
+    }
+
     syscall
-        .set_id(
-            Uid::from_raw(proc.user().uid()),
-            Gid::from_raw(proc.user().gid()),
-        )
+        .set_id(Uid::from_raw(user.uid()), Gid::from_raw(user.gid()))
         .map_err(|err| {
-            let uid = proc.user().uid();
-            let gid = proc.user().gid();
+            let uid = user.uid();
+            let gid = user.gid();
             tracing::error!(?err, ?uid, ?gid, "failed to set uid and gid");
             InitProcessError::SyscallOther(err)
         })?;
 
-    // Take care of LISTEN_FDS used for systemd-active-socket. If the value is
-    // not 0, then we have to preserve those fds as well, and set up the correct
-    // environment variables.
-    let preserve_fds: i32 = match env::var("LISTEN_FDS") {
-        std::result::Result::Ok(listen_fds_str) => {
-            let listen_fds = match listen_fds_str.parse::<i32>() {
-                std::result::Result::Ok(v) => v,
-                Err(error) => {
-                    tracing::warn!(
-                        "LISTEN_FDS entered is not a fd. Ignore the value. {:?}",
-                        error
-                    );
-
-                    0
-                }
-            };
-
-            // The LISTEN_FDS will have to be passed to container init process.
-            // The LISTEN_PID will be set to PID 1. Based on the spec, if
-            // LISTEN_FDS is 0, the variable should be unset, so we just ignore
-            // it here, if it is 0.
-            if listen_fds > 0 {
-                envs.insert("LISTEN_FDS".to_owned(), listen_fds.to_string());
-                envs.insert("LISTEN_PID".to_owned(), 1.to_string());
-            }
-
-            args.preserve_fds + listen_fds
-        }
-        Err(env::VarError::NotPresent) => args.preserve_fds,
-        Err(env::VarError::NotUnicode(value)) => {
-            tracing::warn!(
-                "LISTEN_FDS entered is malformed: {:?}. Ignore the value.",
-                &value
-            );
-            args.preserve_fds
-        }
-    };
+    // The fds of systemd socket activation come first and are followed by
+    // the fds preserved on request. The container process gets the
+    // environment to find the fds of socket activation, with the LISTEN_PID
+    // relabeled to the pid the process has in its pid namespace.
+    if args.listen_fds > 0 {
+        envs.insert("LISTEN_FDS".to_owned(), args.listen_fds.to_string());
+        envs.insert("LISTEN_PID".to_owned(), 1.to_string());
+    }
+    let preserve_fds = args.preserve_fds + args.listen_fds;
 
     // Cleanup any extra file descriptors, so the new container process will not
     // leak a file descriptor from before execve gets executed. The first 3 fd will
@@ -516,20 +644,21 @@ pub fn container_init_process(
     // Without no new privileges, seccomp is a privileged operation. We have to
     // do this before dropping capabilities. Otherwise, we should do it later,
     // as close to exec as possible.
-    #[cfg(feature = "libseccomp")]
+    #[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
     if let Some(seccomp) = linux.seccomp() {
         if proc.no_new_privileges().is_none() {
-            let notify_fd = seccomp::initialize_seccomp(seccomp).map_err(|err| {
-                tracing::error!(?err, "failed to initialize seccomp");
-                err
-            })?;
+            let notify_fd =
+                initialize_seccomp(seccomp, args.seccomp_program.as_deref()).map_err(|err| {
+                    tracing::error!(?err, "failed to initialize seccomp");
+                    err
+                })?;
             sync_seccomp(notify_fd, main_sender, init_receiver).map_err(|err| {
                 tracing::error!(?err, "failed to sync seccomp");
                 err
             })?;
         }
     }
-    #[cfg(not(feature = "libseccomp"))]
+    #[cfg(not(any(feature = "libseccomp", feature = "seccomp-bpf")))]
     if proc.no_new_privileges().is_none() {
         tracing::warn!("seccomp not available, unable to enforce no_new_privileges!")
     }
@@ -563,30 +692,38 @@ pub fn container_init_process(
     // Initialize seccomp profile right before we are ready to execute the
     // payload so as few syscalls will happen between here and payload exec. The
     // notify socket will still need network related syscalls.
-    #[cfg(feature = "libseccomp")]
+    #[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
     if let Some(seccomp) = linux.seccomp() {
         if proc.no_new_privileges().is_some() {
-            let notify_fd = seccomp::initialize_seccomp(seccomp).map_err(|err| {
-                tracing::error!(?err, "failed to initialize seccomp");
-                err
-            })?;
+            let notify_fd =
+                initialize_seccomp(seccomp, args.seccomp_program.as_deref()).map_err(|err| {
+                    tracing::error!(?err, "failed to initialize seccomp");
+                    err
+                })?;
             sync_seccomp(notify_fd, main_sender, init_receiver).map_err(|err| {
                 tracing::error!(?err, "failed to sync seccomp");
                 err
             })?;
         }
     }
-    #[cfg(not(feature = "libseccomp"))]
+    #[cfg(not(any(feature = "libseccomp", feature = "seccomp-bpf")))]
     if proc.no_new_privileges().is_some() {
         tracing::warn!("seccomp not available, unable to set seccomp privileges!")
     }
 
-    // add HOME into envs if not exists
+    // many images rely on the runtime to set HOME, which is the home of the
+    // user in the /etc/passwd of the container
     if !envs.contains_key("HOME") {
-        if let Some(dir_home) = utils::get_user_home(proc.user().uid()) {
-            envs.insert("HOME".to_owned(), dir_home.to_string_lossy().to_string());
+        match user::home(user.uid(), Path::new("/")) {
+            Ok(home) => {
+                envs.insert("HOME".to_owned(), home.to_string_lossy().into_owned());
+            }
+            Err(err) => tracing::warn!(?err, "failed to look up home of container user"),
         }
     }
+    if !envs.contains_key("PATH") {
+        envs.insert("PATH".to_owned(), utils::DEFAULT_PATH.to_owned());
+    }
 
     args.executor.validate(spec)?;
     args.executor.setup_envs(envs)?;
@@ -617,14 +754,17 @@ pub fn container_init_process(
         err
     })?;
 
-    // create_container hook needs to be called after the namespace setup, but
-    // before pivot_root is called. This runs in the container namespaces.
+    // start_container hook needs to be called after the start command, but
+    // before the user process is executed. This runs in the container namespaces.
     if matches!(args.container_type, ContainerType::InitContainer) {
         if let Some(hooks) = hooks {
-            hooks::run_hooks(hooks.start_container().as_ref(), container, None).map_err(|err| {
-                tracing::error!(?err, "failed to run start container hooks");
-                err
-            })?;
+            let state = hook_state(container, ContainerStatus::Created);
+            hooks::run_hooks(hooks.start_container().as_ref(), state.as_ref(), None).map_err(
+                |err| {
+                    tracing::error!(?err, "failed to run start container hooks");
+                    err
+                },
+            )?;
         }
     }
 
@@ -652,12 +792,12 @@ pub fn container_init_process(
 // This leaves us with three scenarios:
 //
 // Unprivileged user starting a rootless container: The main process is running as an
-// unprivileged user and therefore cannot write the mapping until "deny" has been written
-// to /proc/{pid}/setgroups. Once written /proc/{pid}/setgroups cannot be reset and the
-// setgroups system call will be disabled for all processes in this user namespace. This
-// also means that we should detect if the user is unprivileged and additional gids have
-// been specified and bail out early as this can never work. This is not handled here,
-// but during the validation for rootless containers.
+// unprivileged user and therefore cannot write the mapping without newgidmap until "deny"
+// has been written to /proc/{pid}/setgroups. Once written /proc/{pid}/setgroups cannot be
+// reset and the setgroups system call will be disabled for all processes in this user
+// namespace. This also means that we should detect if the user is unprivileged, newgidmap
+// is not used and additional gids have been specified and bail out early as this can never
+// work. This is not handled here, but during the validation for rootless containers.
 //
 // Privileged user starting a rootless container: It is not necessary to write "deny" to
 // /proc/setgroups in order to create the gid mapping and therefore we don't. This means
@@ -693,7 +833,7 @@ fn set_supplementary_gids(
             .collect();
 
         match user_ns_config {
-            Some(r) if r.privileged => {
+            Some(r) if !r.setgroups_deny_required() => {
                 syscall.set_groups(&gids).map_err(|err| {
                     tracing::error!(?err, ?gids, "failed to set privileged supplementary gids");
                     InitProcessError::SyscallOther(err)
@@ -715,44 +855,95 @@ fn set_supplementary_gids(
     Ok(())
 }
 
+// The keyring is named after the container, so that keys added by the
+// container don't end up in the session keyring of the caller. With SELinux,
+// the keyring gets the label of the container process, so that the process
+// can still use it once its label is applied on exec.
+fn join_session_keyring(
+    syscall: &dyn Syscall,
+    container: Option<&Container>,
+    label: Option<&str>,
+) -> Result<()> {
+    let name = match container {
+        Some(container) => format!("_ses.{}", container.id()),
+        None => "_ses".to_owned(),
+    };
+
+    let label = label.filter(|label| !label.is_empty());
+    if let Some(label) = label {
+        selinux::set_key_label(label).map_err(|err| {
+            tracing::error!(?err, "failed to set selinux key label");
+            InitProcessError::SELinux(err)
+        })?;
+    }
+
+    let result = syscall.join_session_keyring(&name);
+    // only the session keyring gets the label, not the keyrings the
+    // process creates itself
+    if label.is_some() {
+        selinux::set_key_label("").map_err(|err| {
+            tracing::error!(?err, "failed to reset selinux key label");
+            InitProcessError::SELinux(err)
+        })?;
+    }
+
+    match result {
+        // kernels without keyring support have no keyring to inherit either
+        Err(SyscallError::Nix(nix::Error::ENOSYS)) => {
+            tracing::warn!("keyrings are not supported by the kernel");
+            Ok(())
+        }
+        Err(err) => {
+            tracing::error!(?err, ?name, "failed to create session keyring");
+            Err(InitProcessError::SyscallOther(err))
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
 /// set_io_priority set io priority
 fn set_io_priority(syscall: &dyn Syscall, io_priority_op: &Option<LinuxIOPriority>) -> Result<()> {
-    match io_priority_op {
-        Some(io_priority) => {
-            let io_prio_class_mapping: HashMap<_, _> = [
-                (IOPriorityClass::IoprioClassRt, 1i64),
-                (IOPriorityClass::IoprioClassBe, 2i64),
-                (IOPriorityClass::IoprioClassIdle, 3i64),
-            ]
-            .iter()
-            .filter_map(|(class, num)| match serde_json::to_string(&class) {
-                Ok(class_str) => Some((class_str, *num)),
-                Err(err) => {
-                    tracing::error!(?err, "failed to parse io priority class");
-                    None
-                }
-            })
-            .collect();
+    if let Some(io_priority) = io_priority_op {
+        // IOPRIO_CLASS_* of include/uapi/linux/ioprio.h
+        let class = match io_priority.class() {
+            IOPriorityClass::IoprioClassRt => 1,
+            IOPriorityClass::IoprioClassBe => 2,
+            IOPriorityClass::IoprioClassIdle => 3,
+        };
+        syscall
+            .set_io_priority(class, io_priority.priority())
+            .map_err(|err| {
+                tracing::error!(?err, ?io_priority, "failed to set io_priority");
+                InitProcessError::SyscallOther(err)
+            })?;
+    }
 
-            let iop_class = serde_json::to_string(&io_priority.class())
-                .map_err(|err| InitProcessError::IoPriorityClass(err.to_string()))?;
-
-            match io_prio_class_mapping.get(&iop_class) {
-                Some(value) => {
-                    syscall
-                        .set_io_priority(*value, io_priority.priority())
-                        .map_err(|err| {
-                            tracing::error!(?err, ?io_priority, "failed to set io_priority");
-                            InitProcessError::SyscallOther(err)
-                        })?;
-                }
-                None => {
-                    return Err(InitProcessError::IoPriorityClass(iop_class));
-                }
-            }
+    Ok(())
+}
+
+/// Sets the execution domain, e.g. LINUX32 for a 32 bit userspace. Like
+/// runc, the flags of the personality are not supported and ignored.
+fn set_personality(syscall: &dyn Syscall, personality: &Option<LinuxPersonality>) -> Result<()> {
+    if let Some(personality) = personality {
+        if personality
+            .flags()
+            .as_ref()
+            .map_or(false, |f| !f.is_empty())
+        {
+            tracing::warn!(flags = ?personality.flags(), "ignoring unsupported personality flags");
         }
-        None => {}
+
+        // PER_LINUX and PER_LINUX32 of include/uapi/linux/personality.h
+        let persona = match personality.domain() {
+            LinuxPersonalityDomain::PerLinux => 0x0000,
+            LinuxPersonalityDomain::PerLinux32 => 0x0008,
+        };
+        syscall.set_personality(persona).map_err(|err| {
+            tracing::error!(?err, ?personality, "failed to set personality");
+            InitProcessError::SyscallOther(err)
+        })?;
     }
+
     Ok(())
 }
 
@@ -811,7 +1002,47 @@ fn setup_scheduler(sc_op: &Option<Scheduler>) -> Result<()> {
     Ok(())
 }
 
-#[cfg(feature = "libseccomp")]
+/// The state passed to the hooks run in the container namespaces. Like runc,
+/// the pid is the one of the init process in the pid namespace of the container.
+fn hook_state(container: Option<&Container>, status: ContainerStatus) -> Option<Container> {
+    let mut container = container.cloned()?;
+    container
+        .set_pid(unistd::getpid().as_raw())
+        .set_status(status);
+    Some(container)
+}
+
+/// Receives the idmapped mounts prepared by the main process, keyed by the
+/// index of the mount in the spec
+fn receive_idmapped_mounts(
+    spec: &Spec,
+    init_receiver: &mut channel::InitReceiver,
+) -> Result<HashMap<usize, OwnedFd>> {
+    let mut idmapped_mounts = HashMap::new();
+    for (index, mount) in spec.mounts().iter().flatten().enumerate() {
+        if rootfs::utils::idmap_type(mount).is_some() {
+            let fd = init_receiver.wait_for_idmapped_mount()?;
+            idmapped_mounts.insert(index, unsafe { OwnedFd::from_raw_fd(fd) });
+        }
+    }
+
+    Ok(idmapped_mounts)
+}
+
+/// Loads the seccomp filter, from the program it has been compiled to if the
+/// main process has passed it from the cache
+#[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
+fn initialize_seccomp(
+    seccomp: &oci_spec::runtime::LinuxSeccomp,
+    program: Option<&[u8]>,
+) -> std::result::Result<Option<i32>, seccomp::SeccompError> {
+    match program {
+        Some(program) => seccomp::load_seccomp_program(seccomp, program).map(|_| None),
+        None => seccomp::initialize_seccomp(seccomp),
+    }
+}
+
+#[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
 fn sync_seccomp(
     fd: Option<i32>,
     main_sender: &mut channel::MainSender,
@@ -865,10 +1096,12 @@ mod tests {
     use std::fs;
 
     use anyhow::Result;
-    #[cfg(feature = "libseccomp")]
+    #[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
     use nix::unistd;
-    use oci_spec::runtime::{LinuxNamespaceBuilder, SpecBuilder, UserBuilder};
-    #[cfg(feature = "libseccomp")]
+    use oci_spec::runtime::{
+        LinuxNamespaceBuilder, LinuxPersonalityBuilder, SpecBuilder, UserBuilder,
+    };
+    #[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
     use serial_test::serial;
 
     use super::*;
@@ -880,6 +1113,46 @@ mod tests {
         let syscall = create_syscall();
         readonly_path(Path::new("/proc/sys"), syscall.as_ref())?;
 
+        let mocks = syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        let open_tree_args = mocks.get_open_tree_args();
+        assert_eq!(open_tree_args.len(), 1);
+        assert_eq!(open_tree_args[0].pathname, PathBuf::from("/proc/sys"));
+        assert_ne!(open_tree_args[0].flags & linux::AT_RECURSIVE, 0);
+
+        let mount_setattr_args = mocks.get_mount_setattr_args();
+        assert_eq!(mount_setattr_args.len(), 1);
+        assert_eq!(
+            mount_setattr_args[0].flags,
+            linux::AT_EMPTY_PATH | linux::AT_RECURSIVE
+        );
+        assert_ne!(
+            mount_setattr_args[0].mount_attr.attr_set & linux::MOUNT_ATTR_RDONLY,
+            0
+        );
+
+        let move_mount_args = mocks.get_move_mount_args();
+        assert_eq!(move_mount_args.len(), 1);
+        assert_eq!(move_mount_args[0].from_dirfd, mount_setattr_args[0].dirfd);
+        assert_eq!(move_mount_args[0].to_pathname, PathBuf::from("/proc/sys"));
+        assert!(mocks.get_mount_args().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_readonly_path_without_mount_api() -> Result<()> {
+        let syscall = create_syscall();
+        syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .set_ret_err(ArgName::OpenTree, || {
+                Err(SyscallError::Nix(nix::errno::Errno::ENOSYS))
+            });
+        readonly_path(Path::new("/proc/sys"), syscall.as_ref())?;
+
         let want = vec![
             MountArgs {
                 source: Some(PathBuf::from("/proc/sys")),
@@ -1007,7 +1280,7 @@ mod tests {
 
     #[test]
     #[serial]
-    #[cfg(feature = "libseccomp")]
+    #[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
     fn test_sync_seccomp() -> Result<()> {
         use std::os::unix::io::IntoRawFd;
         use std::thread;
@@ -1135,5 +1408,59 @@ mod tests {
         };
         let set_io_prioritys = test_command.get_io_priority_args();
         assert_eq!(set_io_prioritys[0], want_io_priority);
+
+        let data = "{\"class\":\"IOPRIO_CLASS_IDLE\",\"priority\":0}";
+        let iop: LinuxIOPriority = serde_json::from_str(data).unwrap();
+        assert!(set_io_priority(&test_command, &Some(iop)).is_ok());
+        let set_io_prioritys = test_command.get_io_priority_args();
+        assert_eq!(
+            set_io_prioritys[1],
+            IoPriorityArgs {
+                class: 3,
+                priority: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_set_personality() -> Result<()> {
+        let syscall = TestHelperSyscall::default();
+        set_personality(&syscall, &None)?;
+        assert!(syscall.get_personality_args().is_empty());
+
+        let personality = LinuxPersonalityBuilder::default()
+            .domain(LinuxPersonalityDomain::PerLinux32)
+            .build()?;
+        set_personality(&syscall, &Some(personality))?;
+        assert_eq!(syscall.get_personality_args(), vec![0x0008]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_session_keyring() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let container = Container::new(
+            "74f1a4cb3801",
+            crate::container::ContainerStatus::Creating,
+            None,
+            tmp.path(),
+            tmp.path(),
+        )?;
+        let syscall = TestHelperSyscall::default();
+        join_session_keyring(&syscall, Some(&container), None)?;
+        assert_eq!(
+            syscall.get_session_keyring_args(),
+            vec!["_ses.74f1a4cb3801"]
+        );
+
+        syscall.set_ret_err(ArgName::SessionKeyring, || {
+            Err(SyscallError::Nix(nix::Error::ENOSYS))
+        });
+        assert!(join_session_keyring(&syscall, Some(&container), None).is_ok());
+        syscall.set_ret_err(ArgName::SessionKeyring, || {
+            Err(SyscallError::Nix(nix::Error::EDQUOT))
+        });
+        assert!(join_session_keyring(&syscall, Some(&container), None).is_err());
+        Ok(())
     }
 }
@@ -1,17 +1,28 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
 use std::os::fd::FromRawFd;
 
+use libcgroups::checkpoint::CgroupCheckpoint;
 use libcgroups::common::CgroupManager;
+use nix::sched::{CloneFlags, CpuSet};
 use nix::unistd::{close, write, Gid, Pid, Uid};
-use oci_spec::runtime::{LinuxNamespace, LinuxNamespaceType, LinuxResources};
+use oci_spec::runtime::{
+    ExecCPUAffinity, Linux, LinuxIdMapping, LinuxNamespace, LinuxNamespaceType, LinuxResources,
+    PosixRlimitType,
+};
 use procfs::process::Process;
 
 use super::args::{ContainerArgs, ContainerType};
 use super::channel::{IntermediateReceiver, MainSender};
 use super::container_init_process::container_init_process;
 use super::fork::CloneCb;
+use crate::container::save_cgroup_config;
 use crate::error::MissingSpecError;
-use crate::namespaces::Namespaces;
+use crate::log_capture;
+use crate::namespaces::{self, Namespaces};
 use crate::process::{channel, fork};
+use crate::syscall::Syscall;
 
 #[derive(Debug, thiserror::Error)]
 pub enum IntermediateProcessError {
@@ -31,12 +42,63 @@ pub enum IntermediateProcessError {
     ExecNotify(#[source] nix::Error),
     #[error(transparent)]
     MissingSpec(#[from] crate::error::MissingSpecError),
+    #[error("failed to write time namespace offsets")]
+    TimeOffsets(#[source] std::io::Error),
+    #[error("invalid cpu list {0:?}")]
+    CpuList(String),
+    #[error("failed to set {typ} to soft limit {soft} and hard limit {hard}")]
+    Rlimit {
+        typ: PosixRlimitType,
+        soft: u64,
+        hard: u64,
+        source: crate::syscall::SyscallError,
+    },
+    #[error("the cgroup can't be delegated, as root of the container is not mapped")]
+    UnmappedRoot,
     #[error("other error")]
     Other(String),
 }
 
 type Result<T> = std::result::Result<T, IntermediateProcessError>;
 
+/// Annotation to kill all processes of the container cgroup together if one of
+/// them is selected by the OOM killer, the same as setting `memory.oom.group`
+/// in the unified resources. Only takes effect on cgroup v2.
+pub const OOM_GROUP_ANNOTATION: &str = "org.youki.cgroup.memory.oom.group";
+
+/// Annotation to make the cpuset of the container a partition, e.g. `root`
+/// for cpus which are used exclusively by the container, the same as setting
+/// `cpuset.cpus.partition` in the unified resources. Only takes effect on
+/// cgroup v2.
+pub const CPUSET_PARTITION_ANNOTATION: &str = "org.youki.cgroup.cpuset.cpus.partition";
+
+/// Annotation to place the processes of the container into a leaf cgroup with
+/// the given name, e.g. `container`, beneath the cgroup of the container. The
+/// resources are still applied to the cgroup of the container, which can then
+/// enable controllers for the sub-cgroups created by the container. Only takes
+/// effect with the cgroupfs driver on cgroup v2.
+pub const CGROUP_LEAF_ANNOTATION: &str = "org.youki.cgroup.leaf";
+
+/// Annotation to reset the cpu affinity of the container processes once they
+/// have joined the cgroup, so that they can run on all cpus allowed by the
+/// cpuset instead of inheriting the affinity of the caller, e.g. one set by
+/// the CPUAffinity of a systemd unit.
+pub const RESET_CPU_AFFINITY_ANNOTATION: &str = "org.youki.cpu.affinity.reset";
+
+/// Annotation to hand the cgroup of the container over to it, so that e.g. a
+/// nested systemd or container runtime can manage the sub-cgroups. The cgroup
+/// is chowned to root of the container and the controllers given as a comma
+/// separated list, e.g. `cpu,memory,pids`, are enabled for its children,
+/// which requires a leaf cgroup, see [`CGROUP_LEAF_ANNOTATION`]. Only takes
+/// effect on cgroup v2.
+pub const CGROUP_DELEGATE_ANNOTATION: &str = "org.youki.cgroup.delegate";
+
+/// Annotation to apply the resources of the controllers given as a comma
+/// separated list, e.g. `hugetlb,rdma`, on a best effort basis. Failures to
+/// write their cgroup files are logged instead of failing the creation of the
+/// container. Only takes effect with the cgroupfs driver.
+pub const CGROUP_BEST_EFFORT_ANNOTATION: &str = "org.youki.cgroup.best-effort";
+
 pub fn container_intermediate_process(
     args: &ContainerArgs,
     intermediate_chan: &mut (channel::IntermediateSender, channel::IntermediateReceiver),
@@ -62,12 +124,57 @@ pub fn container_intermediate_process(
     // In addition this needs to be done before we enter the cgroup namespace as
     // the cgroup of the process will form the root of the cgroup hierarchy in
     // the cgroup namespace.
-    apply_cgroups(
+    let resources = resources_with_annotations(spec.annotations().as_ref(), linux.resources());
+    // exec'd processes may run on a separate set of cpus until they have
+    // joined the cgroup, e.g. to not disturb latency critical workloads of the
+    // container. It is not applicable to the init process.
+    let exec_cpu_affinity = match args.container_type {
+        ContainerType::InitContainer => None,
+        ContainerType::TenantContainer { .. } => spec
+            .process()
+            .as_ref()
+            .and_then(|process| process.exec_cpu_affinity().as_ref()),
+    };
+    if let Some(cpus) = exec_cpu_affinity.and_then(|a| a.cpu_affinity_initial().as_deref()) {
+        set_cpu_affinity(command.as_ref(), cpus)?;
+    }
+    let mut cgroup_config = apply_cgroups(
         &cgroup_manager,
-        linux.resources().as_ref(),
+        resources.as_deref(),
         matches!(args.container_type, ContainerType::InitContainer),
+        fork::spawned_into_cgroup(),
+    )?;
+    if let ContainerType::InitContainer = args.container_type {
+        let delegate = delegated_controllers(spec.annotations().as_ref());
+        if let Some(controllers) = delegate {
+            let (owner, group) = container_root(linux, &namespaces)?;
+            cgroup_config.extend(delegate_cgroup(
+                &cgroup_manager,
+                owner,
+                group,
+                &controllers,
+            )?);
+        }
+    }
+    // the cgroup configuration is kept, so that it can be re-applied
+    // independent of the spec
+    if let (ContainerType::InitContainer, Some(container)) = (&args.container_type, &args.container)
+    {
+        save_cgroup_config(&container.root, &cgroup_config)
+            .map_err(|err| IntermediateProcessError::Cgroup(err.to_string()))?;
+    }
+    set_cpu_affinity_in_cgroup(
+        command.as_ref(),
+        exec_cpu_affinity,
+        reset_cpu_affinity_requested(spec.annotations().as_ref()),
     )?;
 
+    // The namespaces given by path are joined before any namespace is
+    // created, see `Namespaces::join_existing`. The mount namespace is
+    // entered last by the init process, as the rootfs is prepared from the
+    // mount namespace of the caller.
+    namespaces.join_existing(|ns_type| ns_type != CloneFlags::CLONE_NEWNS)?;
+
     // if new user is specified in specification, this will be true and new
     // namespace will be created, check
     // https://man7.org/linux/man-pages/man7/user_namespaces.7.html for more
@@ -88,16 +195,34 @@ pub fn container_intermediate_process(
     let proc = spec.process().as_ref().ok_or(MissingSpecError::Process)?;
     if let Some(rlimits) = proc.rlimits() {
         for rlimit in rlimits {
-            command.set_rlimit(rlimit).map_err(|err| {
-                tracing::error!(?err, ?rlimit, "failed to set rlimit");
-                err
-            })?;
+            command
+                .set_rlimit(rlimit)
+                .map_err(|err| IntermediateProcessError::Rlimit {
+                    typ: rlimit.typ(),
+                    soft: rlimit.soft(),
+                    hard: rlimit.hard(),
+                    source: err,
+                })?;
         }
     }
 
     // Pid namespace requires an extra fork to enter, so we enter pid namespace now.
     if let Some(pid_namespace) = namespaces.get(LinuxNamespaceType::Pid)? {
-        namespaces.unshare_or_setns(pid_namespace)?;
+        if pid_namespace.path().is_none() {
+            namespaces.unshare_or_setns(pid_namespace)?;
+        }
+    }
+
+    // Like the pid namespace, a new time namespace is only entered by the
+    // children of the process creating it. The clock offsets can only be set
+    // until the first process has entered it.
+    if let Some(time_namespace) = namespaces.get(LinuxNamespaceType::Time)? {
+        if time_namespace.path().is_none() {
+            namespaces.unshare_or_setns(time_namespace)?;
+            if let Some(offsets) = linux.time_offsets() {
+                setup_time_offsets(offsets)?;
+            }
+        }
     }
 
     let cb: CloneCb = {
@@ -106,6 +231,8 @@ pub fn container_intermediate_process(
                 tracing::error!(?ret, "failed to set name for child process");
                 return ret;
             }
+            // only the lines of the init process are relayed on failure
+            log_capture::clear();
 
             // We are inside the forked process here. The first thing we have to do
             // is to close any unused senders, since fork will make a dup for all
@@ -189,16 +316,26 @@ pub fn container_intermediate_process(
     Ok(())
 }
 
+fn setup_time_offsets(offsets: &HashMap<String, String>) -> Result<()> {
+    let offsets = namespaces::format_time_offsets(offsets)?;
+    tracing::debug!(?offsets, "setting time namespace offsets");
+    fs::write("/proc/self/timens_offsets", offsets).map_err(|err| {
+        tracing::error!(?err, "failed to write time namespace offsets");
+        IntermediateProcessError::TimeOffsets(err)
+    })
+}
+
 fn setup_userns(
     namespaces: &Namespaces,
     user_namespace: &LinuxNamespace,
     sender: &mut MainSender,
     receiver: &mut IntermediateReceiver,
 ) -> Result<()> {
-    namespaces.unshare_or_setns(user_namespace)?;
+    // an existing user namespace has already been joined
     if user_namespace.path().is_some() {
         return Ok(());
     }
+    namespaces.unshare_or_setns(user_namespace)?;
 
     tracing::debug!("creating new user namespace");
     // child needs to be dumpable, otherwise the non root parent is not
@@ -226,6 +363,129 @@ fn setup_userns(
     Ok(())
 }
 
+fn reset_cpu_affinity_requested(annotations: Option<&HashMap<String, String>>) -> bool {
+    match annotations.and_then(|a| a.get(RESET_CPU_AFFINITY_ANNOTATION)) {
+        Some(value) => match value.as_str() {
+            "1" | "true" => true,
+            "0" | "false" => false,
+            _ => {
+                tracing::warn!(
+                    ?value,
+                    "ignoring invalid value of {}",
+                    RESET_CPU_AFFINITY_ANNOTATION
+                );
+                false
+            }
+        },
+        None => false,
+    }
+}
+
+/// Sets the cpu affinity of the process once it has joined the cgroup. This
+/// is the final affinity of an exec'd process if there is one, otherwise the
+/// affinity is reset if requested or if an initial affinity has been set.
+fn set_cpu_affinity_in_cgroup(
+    syscall: &dyn Syscall,
+    exec_cpu_affinity: Option<&ExecCPUAffinity>,
+    reset: bool,
+) -> Result<()> {
+    if let Some(cpus) = exec_cpu_affinity.and_then(|a| a.cpu_affinity_final().as_deref()) {
+        if !cpus.is_empty() {
+            return set_cpu_affinity(syscall, cpus);
+        }
+    }
+
+    let has_initial = exec_cpu_affinity.map_or(false, |a| a.cpu_affinity_initial().is_some());
+    if reset || has_initial {
+        reset_cpu_affinity(syscall)?;
+    }
+
+    Ok(())
+}
+
+/// Allows the process to run on all cpus of its cgroup
+fn reset_cpu_affinity(syscall: &dyn Syscall) -> Result<()> {
+    // the kernel drops the cpus which are not in the cpuset of the cgroup
+    let cpus: Vec<usize> = (0..CpuSet::count()).collect();
+    syscall.set_cpu_affinity(&cpus).map_err(|err| {
+        tracing::error!(?err, "failed to reset cpu affinity");
+        err.into()
+    })
+}
+
+fn set_cpu_affinity(syscall: &dyn Syscall, cpus: &str) -> Result<()> {
+    let cpu_list = parse_cpu_list(cpus)?;
+    syscall.set_cpu_affinity(&cpu_list).map_err(|err| {
+        tracing::error!(?err, ?cpus, "failed to set cpu affinity");
+        err.into()
+    })
+}
+
+/// Parses a list of cpus like `0-3,7`
+fn parse_cpu_list(list: &str) -> Result<Vec<usize>> {
+    let invalid = || IntermediateProcessError::CpuList(list.to_owned());
+    let mut cpus = Vec::new();
+    for range in list.split(',') {
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (start, end),
+            None => (range, range),
+        };
+        let start: usize = start.trim().parse().map_err(|_| invalid())?;
+        let end: usize = end.trim().parse().map_err(|_| invalid())?;
+        if start > end {
+            return Err(invalid());
+        }
+        cpus.extend(start..=end);
+    }
+
+    Ok(cpus)
+}
+
+/// Adds the entries requested by annotations, i.e. `memory.oom.group` and
+/// `cpuset.cpus.partition`, to the unified resources. A value in the unified
+/// resources of the spec takes precedence.
+fn resources_with_annotations<'a>(
+    annotations: Option<&HashMap<String, String>>,
+    resources: &'a Option<LinuxResources>,
+) -> Option<Cow<'a, LinuxResources>> {
+    let unified = resources.as_ref().and_then(|r| r.unified().as_ref());
+    let mut entries = Vec::new();
+    for (annotation, key) in [
+        (OOM_GROUP_ANNOTATION, "memory.oom.group"),
+        (CPUSET_PARTITION_ANNOTATION, "cpuset.cpus.partition"),
+    ] {
+        let value = match annotations.and_then(|a| a.get(annotation)) {
+            Some(value) => value.as_str(),
+            None => continue,
+        };
+        let value = match (key, value) {
+            ("memory.oom.group", "1" | "true") => "1",
+            ("memory.oom.group", "0" | "false") => "0",
+            ("cpuset.cpus.partition", "member" | "root" | "isolated") => value,
+            _ => {
+                tracing::warn!(?value, "ignoring invalid value of {}", annotation);
+                continue;
+            }
+        };
+
+        if !unified.map_or(false, |unified| unified.contains_key(key)) {
+            entries.push((key, value));
+        }
+    }
+
+    if entries.is_empty() {
+        return resources.as_ref().map(Cow::Borrowed);
+    }
+
+    let mut resources = resources.clone().unwrap_or_default();
+    let mut unified = resources.unified().clone().unwrap_or_default();
+    for (key, value) in entries {
+        unified.insert(key.to_owned(), value.to_owned());
+    }
+    resources.set_unified(Some(unified));
+    Some(Cow::Owned(resources))
+}
+
 fn apply_cgroups<
     C: CgroupManager<Error = E> + ?Sized,
     E: std::error::Error + Send + Sync + 'static,
@@ -233,12 +493,16 @@ fn apply_cgroups<
     cmanager: &C,
     resources: Option<&LinuxResources>,
     init: bool,
-) -> Result<()> {
+    spawned_into_cgroup: bool,
+) -> Result<CgroupCheckpoint> {
     let pid = Pid::from_raw(Process::myself()?.pid());
-    cmanager.add_task(pid).map_err(|err| {
-        tracing::error!(?pid, ?err, ?init, "failed to add task to cgroup");
-        IntermediateProcessError::Cgroup(err.to_string())
-    })?;
+    // a process spawned with CLONE_INTO_CGROUP is already in the cgroup
+    if !spawned_into_cgroup {
+        cmanager.add_task(pid).map_err(|err| {
+            tracing::error!(?pid, ?err, ?init, "failed to add task to cgroup");
+            IntermediateProcessError::Cgroup(err.to_string())
+        })?;
+    }
 
     if let Some(resources) = resources {
         if init {
@@ -249,14 +513,90 @@ fn apply_cgroups<
                 disable_oom_killer: false,
             };
 
-            cmanager.apply(&controller_opt).map_err(|err| {
+            let (result, cgroup_config) =
+                CgroupCheckpoint::record(|| cmanager.apply(&controller_opt));
+            result.map_err(|err| {
                 tracing::error!(?pid, ?err, ?init, "failed to apply cgroup");
                 IntermediateProcessError::Cgroup(err.to_string())
             })?;
+            return Ok(cgroup_config);
         }
     }
 
-    Ok(())
+    Ok(CgroupCheckpoint::default())
+}
+
+/// Returns the controllers to enable for the children of the cgroup if the
+/// delegation of the cgroup has been requested by the annotation
+fn delegated_controllers(annotations: Option<&HashMap<String, String>>) -> Option<Vec<String>> {
+    controller_list(annotations, CGROUP_DELEGATE_ANNOTATION)
+}
+
+/// Returns the controllers whose resources are applied on a best effort
+/// basis according to the annotation
+pub(crate) fn best_effort_controllers(
+    annotations: Option<&HashMap<String, String>>,
+) -> Vec<String> {
+    controller_list(annotations, CGROUP_BEST_EFFORT_ANNOTATION).unwrap_or_default()
+}
+
+fn controller_list(
+    annotations: Option<&HashMap<String, String>>,
+    annotation: &str,
+) -> Option<Vec<String>> {
+    let value = annotations.and_then(|a| a.get(annotation))?;
+    Some(
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(str::to_owned)
+            .collect(),
+    )
+}
+
+/// Returns the host ids of root of the container, which are the ones of root
+/// on the host unless the container gets a new user namespace
+fn container_root(linux: &Linux, namespaces: &Namespaces) -> Result<(Uid, Gid)> {
+    if namespaces.get(LinuxNamespaceType::User)?.is_none() {
+        return Ok((Uid::from_raw(0), Gid::from_raw(0)));
+    }
+
+    let root = |mappings: &Option<Vec<LinuxIdMapping>>| {
+        mappings
+            .iter()
+            .flatten()
+            .find(|m| m.container_id() == 0)
+            .map(|m| m.host_id())
+    };
+    match (root(linux.uid_mappings()), root(linux.gid_mappings())) {
+        (Some(uid), Some(gid)) => Ok((Uid::from_raw(uid), Gid::from_raw(gid))),
+        _ => Err(IntermediateProcessError::UnmappedRoot),
+    }
+}
+
+fn delegate_cgroup<
+    C: CgroupManager<Error = E> + ?Sized,
+    E: std::error::Error + Send + Sync + 'static,
+>(
+    cmanager: &C,
+    owner: Uid,
+    group: Gid,
+    controllers: &[String],
+) -> Result<CgroupCheckpoint> {
+    let (result, delegated) =
+        CgroupCheckpoint::record(|| cmanager.delegate(owner, group, controllers));
+    match result {
+        Ok(true) => Ok(delegated),
+        Ok(false) => {
+            tracing::warn!("the cgroup can't be delegated to the container on cgroup v1");
+            Ok(CgroupCheckpoint::default())
+        }
+        Err(err) => {
+            tracing::error!(?err, ?controllers, "failed to delegate cgroup");
+            Err(IntermediateProcessError::Cgroup(err.to_string()))
+        }
+    }
 }
 
 #[cfg(test)]
@@ -264,10 +604,11 @@ mod tests {
     use anyhow::Result;
     use libcgroups::test_manager::TestManager;
     use nix::unistd::Pid;
-    use oci_spec::runtime::LinuxResources;
+    use oci_spec::runtime::{ExecCPUAffinityBuilder, LinuxResources};
     use procfs::process::Process;
 
     use super::*;
+    use crate::syscall::test::TestHelperSyscall;
 
     #[test]
     fn apply_cgroup_init() -> Result<()> {
@@ -276,7 +617,7 @@ mod tests {
         let resources = LinuxResources::default();
 
         // act
-        apply_cgroups(&cmanager, Some(&resources), true)?;
+        apply_cgroups(&cmanager, Some(&resources), true, false)?;
 
         // assert
         assert!(cmanager.get_add_task_args().len() == 1);
@@ -295,7 +636,7 @@ mod tests {
         let resources = LinuxResources::default();
 
         // act
-        apply_cgroups(&cmanager, Some(&resources), false)?;
+        apply_cgroups(&cmanager, Some(&resources), false, false)?;
 
         // assert
         assert_eq!(
@@ -306,13 +647,111 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn oom_group_from_annotation() {
+        let annotations: HashMap<String, String> =
+            [(OOM_GROUP_ANNOTATION.to_owned(), "true".to_owned())].into();
+
+        let resources = resources_with_annotations(Some(&annotations), &None).unwrap();
+        assert_eq!(
+            resources.unified().as_ref().unwrap()["memory.oom.group"],
+            "1"
+        );
+
+        // the unified resources of the spec take precedence over the annotation
+        let unified: HashMap<String, String> =
+            [("memory.oom.group".to_owned(), "0".to_owned())].into();
+        let mut spec_resources = LinuxResources::default();
+        spec_resources.set_unified(Some(unified));
+        let spec_resources = Some(spec_resources);
+        let resources = resources_with_annotations(Some(&annotations), &spec_resources).unwrap();
+        assert!(matches!(resources, Cow::Borrowed(_)));
+        assert_eq!(
+            resources.unified().as_ref().unwrap()["memory.oom.group"],
+            "0"
+        );
+
+        assert!(resources_with_annotations(None, &None).is_none());
+    }
+
+    #[test]
+    fn cpuset_partition_from_annotation() {
+        let annotations: HashMap<String, String> = [
+            (CPUSET_PARTITION_ANNOTATION.to_owned(), "root".to_owned()),
+            (OOM_GROUP_ANNOTATION.to_owned(), "invalid".to_owned()),
+        ]
+        .into();
+
+        let resources = resources_with_annotations(Some(&annotations), &None).unwrap();
+        let unified = resources.unified().as_ref().unwrap();
+        assert_eq!(unified["cpuset.cpus.partition"], "root");
+        assert!(!unified.contains_key("memory.oom.group"));
+
+        let annotations: HashMap<String, String> = [(
+            CPUSET_PARTITION_ANNOTATION.to_owned(),
+            "exclusive".to_owned(),
+        )]
+        .into();
+        assert!(resources_with_annotations(Some(&annotations), &None).is_none());
+    }
+
+    #[test]
+    fn test_parse_cpu_list() -> Result<()> {
+        assert_eq!(parse_cpu_list("0-3,7")?, vec![0, 1, 2, 3, 7]);
+        assert_eq!(parse_cpu_list("5")?, vec![5]);
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("0,,1").is_err());
+        assert!(parse_cpu_list("").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_cpu_affinity_in_cgroup() -> Result<()> {
+        let syscall = TestHelperSyscall::default();
+        let affinity = ExecCPUAffinityBuilder::default()
+            .cpu_affinity_initial("0")
+            .cpu_affinity_final("1-2")
+            .build()?;
+        set_cpu_affinity_in_cgroup(&syscall, Some(&affinity), true)?;
+        assert_eq!(syscall.get_cpu_affinity_args(), vec![vec![1, 2]]);
+
+        // the initial affinity is reset without a final one
+        let syscall = TestHelperSyscall::default();
+        let affinity = ExecCPUAffinityBuilder::default()
+            .cpu_affinity_initial("0")
+            .build()?;
+        set_cpu_affinity_in_cgroup(&syscall, Some(&affinity), false)?;
+        assert_eq!(syscall.get_cpu_affinity_args()[0].len(), CpuSet::count());
+
+        let syscall = TestHelperSyscall::default();
+        set_cpu_affinity_in_cgroup(&syscall, Some(&ExecCPUAffinity::default()), false)?;
+        set_cpu_affinity_in_cgroup(&syscall, None, false)?;
+        assert!(syscall.get_cpu_affinity_args().is_empty());
+
+        set_cpu_affinity_in_cgroup(&syscall, None, true)?;
+        assert_eq!(syscall.get_cpu_affinity_args()[0].len(), CpuSet::count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reset_cpu_affinity_requested() {
+        let annotations = |value: &str| -> HashMap<String, String> {
+            [(RESET_CPU_AFFINITY_ANNOTATION.to_owned(), value.to_owned())].into()
+        };
+        assert!(reset_cpu_affinity_requested(Some(&annotations("true"))));
+        assert!(reset_cpu_affinity_requested(Some(&annotations("1"))));
+        assert!(!reset_cpu_affinity_requested(Some(&annotations("0"))));
+        assert!(!reset_cpu_affinity_requested(Some(&annotations("yes"))));
+        assert!(!reset_cpu_affinity_requested(None));
+    }
+
     #[test]
     fn apply_cgroup_no_resources() -> Result<()> {
         // arrange
         let cmanager = TestManager::default();
 
         // act
-        apply_cgroups(&cmanager, None, true)?;
+        apply_cgroups(&cmanager, None, true, false)?;
         // assert
         assert_eq!(
             cmanager.get_add_task_args()[0],
@@ -321,4 +760,83 @@ mod tests {
         assert!(!cmanager.apply_called());
         Ok(())
     }
+
+    #[test]
+    fn apply_cgroup_spawned_into_cgroup() -> Result<()> {
+        let cmanager = TestManager::default();
+        let resources = LinuxResources::default();
+
+        apply_cgroups(&cmanager, Some(&resources), true, true)?;
+
+        assert!(cmanager.get_add_task_args().is_empty());
+        assert!(cmanager.apply_called());
+        Ok(())
+    }
+
+    #[test]
+    fn test_delegated_controllers() {
+        assert_eq!(delegated_controllers(None), None);
+        let annotations: HashMap<String, String> = [(
+            CGROUP_DELEGATE_ANNOTATION.to_owned(),
+            "cpu, memory,,pids".to_owned(),
+        )]
+        .into();
+        assert_eq!(
+            delegated_controllers(Some(&annotations)),
+            Some(vec![
+                "cpu".to_owned(),
+                "memory".to_owned(),
+                "pids".to_owned()
+            ])
+        );
+
+        // only delegates the ownership
+        let annotations: HashMap<String, String> =
+            [(CGROUP_DELEGATE_ANNOTATION.to_owned(), "".to_owned())].into();
+        assert_eq!(delegated_controllers(Some(&annotations)), Some(vec![]));
+    }
+
+    #[test]
+    fn test_container_root() -> Result<()> {
+        use oci_spec::runtime::{LinuxBuilder, LinuxIdMappingBuilder, LinuxNamespaceBuilder};
+
+        let mapping = |container_id: u32, host_id: u32| {
+            LinuxIdMappingBuilder::default()
+                .container_id(container_id)
+                .host_id(host_id)
+                .size(65536u32)
+                .build()
+        };
+        let user_ns = vec![LinuxNamespaceBuilder::default()
+            .typ(LinuxNamespaceType::User)
+            .build()?];
+        let linux = LinuxBuilder::default()
+            .namespaces(user_ns.clone())
+            .uid_mappings(vec![mapping(0, 100000)?])
+            .gid_mappings(vec![mapping(0, 200000)?])
+            .build()?;
+        let namespaces = Namespaces::try_from(linux.namespaces().as_ref())?;
+        assert_eq!(
+            container_root(&linux, &namespaces)?,
+            (Uid::from_raw(100000), Gid::from_raw(200000))
+        );
+
+        let unmapped = LinuxBuilder::default()
+            .namespaces(user_ns)
+            .uid_mappings(vec![mapping(1, 100000)?])
+            .gid_mappings(vec![mapping(0, 200000)?])
+            .build()?;
+        assert!(matches!(
+            container_root(&unmapped, &namespaces),
+            Err(IntermediateProcessError::UnmappedRoot)
+        ));
+
+        let host = LinuxBuilder::default().namespaces(vec![]).build()?;
+        let namespaces = Namespaces::try_from(host.namespaces().as_ref())?;
+        assert_eq!(
+            container_root(&host, &namespaces)?,
+            (Uid::from_raw(0), Gid::from_raw(0))
+        );
+        Ok(())
+    }
 }
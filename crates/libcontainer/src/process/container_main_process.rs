@@ -1,12 +1,21 @@
-use nix::sys::wait::{waitpid, WaitStatus};
+use std::fs::File;
+use std::mem;
+use std::os::fd::{AsFd, AsRawFd};
+use std::path::Path;
+
+use libcgroups::common::CgroupManager;
+use nix::sys::wait::{waitid, waitpid, Id, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
+use oci_spec::runtime::{LinuxNamespaceType, Spec};
 
-use crate::process::args::ContainerArgs;
+use crate::process::args::{ContainerArgs, ContainerType};
 use crate::process::fork::{self, CloneCb};
 use crate::process::intel_rdt::setup_intel_rdt;
 use crate::process::{channel, container_intermediate_process};
-use crate::syscall::SyscallError;
+use crate::rootfs::utils::idmap_type;
+use crate::syscall::{linux, Syscall, SyscallError};
 use crate::user_ns::UserNamespaceConfig;
+use crate::{hooks, net_devices};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ProcessError {
@@ -25,10 +34,18 @@ pub enum ProcessError {
     #[error("failed to create intermediate process")]
     IntermediateProcessFailed(#[source] fork::CloneError),
     #[error("failed seccomp listener")]
-    #[cfg(feature = "libseccomp")]
+    #[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
     SeccompListener(#[from] crate::process::seccomp_listener::SeccompListenerError),
     #[error("failed syscall")]
     SyscallOther(#[source] SyscallError),
+    #[error("failed to open user namespace of the container")]
+    OpenUserNamespace(#[source] std::io::Error),
+    #[error("failed to create idmapped mount of {0}")]
+    IdmappedMount(String, #[source] SyscallError),
+    #[error("failed to run hooks")]
+    Hooks(#[from] hooks::HookError),
+    #[error("failed to move network devices into the container")]
+    NetDevices(#[from] net_devices::NetDeviceError),
 }
 
 type Result<T> = std::result::Result<T, ProcessError>;
@@ -84,10 +101,19 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
         ProcessError::SyscallOther(err)
     })?;
 
-    let intermediate_pid = fork::container_clone(cb).map_err(|err| {
-        tracing::error!("failed to fork intermediate process: {}", err);
-        ProcessError::IntermediateProcessFailed(err)
-    })?;
+    let cgroup = open_task_cgroup(container_args);
+    let intermediate =
+        fork::container_clone(cb, cgroup.as_ref().map(File::as_fd)).map_err(|err| {
+            tracing::error!("failed to fork intermediate process: {}", err);
+            ProcessError::IntermediateProcessFailed(err)
+        })?;
+    let intermediate_pid = intermediate.pid;
+    tracing::debug!(
+        ?intermediate_pid,
+        in_cgroup = intermediate.in_cgroup,
+        "cloned intermediate process"
+    );
+    drop(cgroup);
 
     // Close down unused fds. The corresponding fds are duplicated to the
     // child process during clone.
@@ -97,10 +123,7 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
     })?;
 
     let (mut inter_sender, inter_receiver) = inter_chan;
-    #[cfg(feature = "libseccomp")]
     let (mut init_sender, init_receiver) = init_chan;
-    #[cfg(not(feature = "libseccomp"))]
-    let (init_sender, init_receiver) = init_chan;
 
     // If creating a container with new user namespace, the intermediate process will ask
     // the main process to set up uid and gid mapping, once the intermediate
@@ -109,6 +132,14 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
         main_receiver.wait_for_mapping_request()?;
         setup_mapping(config, intermediate_pid)?;
         inter_sender.mapping_written()?;
+        // Idmapping a mount requires privileges over the filesystem, which the
+        // container process doesn't have inside of its user namespace.
+        send_idmapped_mounts(
+            syscall.as_ref(),
+            &container_args.spec,
+            intermediate_pid,
+            &mut init_sender,
+        )?;
     }
 
     // At this point, we don't need to send any message to intermediate process anymore,
@@ -123,8 +154,28 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
     let init_pid = main_receiver.wait_for_intermediate_ready()?;
     let mut need_to_clean_up_intel_rdt_subdirectory = false;
 
+    // The prestart and createRuntime hooks run in the runtime namespace, once
+    // the init process has set up the namespaces of the container. The init
+    // process waits for them before it calls pivot_root.
+    let hooks = container_args.spec.hooks().as_ref();
+    if matches!(container_args.container_type, ContainerType::InitContainer)
+        && hooks::has_create_runtime_hooks(hooks)
+    {
+        main_receiver.wait_for_hook_request()?;
+        let mut container = container_args
+            .container
+            .clone()
+            .ok_or(ProcessError::ContainerStateRequired)?;
+        container.set_pid(init_pid.as_raw());
+        hooks::run_create_runtime_hooks(hooks, Some(&container)).map_err(|err| {
+            tracing::error!(?err, "failed to run create runtime hooks");
+            err
+        })?;
+        init_sender.hook_done()?;
+    }
+
     if let Some(linux) = container_args.spec.linux() {
-        #[cfg(feature = "libseccomp")]
+        #[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
         if let Some(seccomp) = linux.seccomp() {
             let state = crate::container::ContainerProcessState {
                 oci_version: container_args.spec.version().to_string(),
@@ -148,12 +199,11 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
         }
 
         if let Some(intel_rdt) = linux.intel_rdt() {
-            let container_id = container_args
-                .container
-                .as_ref()
-                .map(|container| container.id());
+            let container = container_args.container.as_ref();
+            let container_id = container.map(|container| container.id());
+            let root_path = container.and_then(|container| container.root.parent());
             need_to_clean_up_intel_rdt_subdirectory =
-                setup_intel_rdt(container_id, &init_pid, intel_rdt)?;
+                setup_intel_rdt(container_id, root_path, &init_pid, intel_rdt)?;
         }
     }
 
@@ -171,6 +221,10 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
 
     tracing::debug!("init pid is {:?}", init_pid);
 
+    if matches!(container_args.container_type, ContainerType::InitContainer) {
+        move_net_devices(container_args, init_pid)?;
+    }
+
     // Close the receiver ends to avoid leaking file descriptors.
 
     inter_receiver.close().map_err(|err| {
@@ -192,7 +246,11 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
     // process is exit and reaped. By this point, the intermediate process
     // should already exited successfully. If intermediate process errors out,
     // the `init_ready` will not be sent.
-    match waitpid(intermediate_pid, None) {
+    let status = match &intermediate.pidfd {
+        Some(pidfd) => waitid(Id::PIDFd(pidfd.as_fd()), WaitPidFlag::WEXITED),
+        None => waitpid(intermediate_pid, None),
+    };
+    match status {
         Ok(WaitStatus::Exited(_, 0)) => (),
         Ok(WaitStatus::Exited(_, s)) => {
             tracing::warn!("intermediate process failed with exit status: {s}");
@@ -212,11 +270,76 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
     Ok((init_pid, need_to_clean_up_intel_rdt_subdirectory))
 }
 
+/// Opens the cgroup of the container, so that the intermediate process can be
+/// spawned directly into it. If that isn't possible, the intermediate process
+/// joins the cgroup itself, which also reports the errors.
+fn open_task_cgroup(container_args: &ContainerArgs) -> Option<File> {
+    // systemd has to be told about the processes of the container, which
+    // needs its own connection to systemd
+    if container_args.cgroup_config.systemd_cgroup {
+        return None;
+    }
+    // exec'd processes with an initial cpu affinity run outside of the cgroup
+    // until they have set it
+    if let ContainerType::TenantContainer { .. } = container_args.container_type {
+        let initial_affinity = container_args
+            .spec
+            .process()
+            .as_ref()
+            .and_then(|process| process.exec_cpu_affinity().as_ref())
+            .and_then(|affinity| affinity.cpu_affinity_initial().as_ref());
+        if initial_affinity.is_some() {
+            return None;
+        }
+    }
+
+    let cgroup = libcgroups::common::create_cgroup_manager(container_args.cgroup_config.to_owned())
+        .map_err(|err| err.to_string())
+        .and_then(|manager| manager.open_task_cgroup().map_err(|err| err.to_string()));
+    match cgroup {
+        Ok(cgroup) => cgroup,
+        Err(err) => {
+            tracing::debug!(?err, "failed to open the cgroup to clone into");
+            None
+        }
+    }
+}
+
+/// Moves the network devices requested for the container into its network
+/// namespace, which needs the privileges of the runtime in the namespace of
+/// the host
+fn move_net_devices(container_args: &ContainerArgs, init_pid: Pid) -> Result<()> {
+    let spec = &container_args.spec;
+    let devices = net_devices::from_annotations(spec.annotations().as_ref())?;
+    if devices.is_empty() {
+        return Ok(());
+    }
+
+    let container = container_args
+        .container
+        .as_ref()
+        .ok_or(ProcessError::ContainerStateRequired)?;
+    let netns = spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.namespaces().as_ref())
+        .and_then(|namespaces| {
+            namespaces
+                .iter()
+                .find(|ns| ns.typ() == LinuxNamespaceType::Network)
+        })
+        .and_then(|ns| ns.path().as_deref());
+    net_devices::move_into(&container.root, &devices, init_pid, netns).map_err(|err| {
+        tracing::error!(?err, "failed to move network devices into the container");
+        err.into()
+    })
+}
+
 fn setup_mapping(config: &UserNamespaceConfig, pid: Pid) -> Result<()> {
     tracing::debug!("write mapping for pid {:?}", pid);
-    if !config.privileged {
+    if config.setgroups_deny_required() {
         // The main process is running as an unprivileged user and cannot write the mapping
-        // until "deny" has been written to setgroups. See CVE-2014-8989.
+        // without newgidmap until "deny" has been written to setgroups. See CVE-2014-8989.
         std::fs::write(format!("/proc/{pid}/setgroups"), "deny")
             .map_err(ProcessError::SetGroupsDeny)?;
     }
@@ -232,6 +355,63 @@ fn setup_mapping(config: &UserNamespaceConfig, pid: Pid) -> Result<()> {
     Ok(())
 }
 
+/// Creates detached mounts for the idmapped mounts of the spec, idmapped with
+/// the user namespace of the intermediate process, and sends them to the init
+/// process in the order they appear in the spec.
+fn send_idmapped_mounts(
+    syscall: &dyn Syscall,
+    spec: &Spec,
+    pid: Pid,
+    init_sender: &mut channel::InitSender,
+) -> Result<()> {
+    let mut userns: Option<File> = None;
+    for mount in spec.mounts().iter().flatten() {
+        let idmap = match idmap_type(mount) {
+            Some(idmap) => idmap,
+            None => continue,
+        };
+        let userns = match &mut userns {
+            Some(userns) => userns,
+            None => userns.insert(
+                File::open(format!("/proc/{pid}/ns/user"))
+                    .map_err(ProcessError::OpenUserNamespace)?,
+            ),
+        };
+        let source = mount.source().as_deref().unwrap_or(mount.destination());
+        let err = |err| {
+            tracing::error!(?source, ?err, "failed to create idmapped mount");
+            ProcessError::IdmappedMount(source.display().to_string(), err)
+        };
+
+        let recursive = idmap.recursive_flag();
+        let tree = syscall
+            .open_tree(
+                libc::AT_FDCWD,
+                source,
+                linux::OPEN_TREE_CLONE | linux::OPEN_TREE_CLOEXEC | recursive,
+            )
+            .map_err(err)?;
+        let mount_attr = linux::MountAttr {
+            attr_set: linux::MOUNT_ATTR_IDMAP,
+            attr_clr: 0,
+            propagation: 0,
+            userns_fd: userns.as_raw_fd() as u64,
+        };
+        syscall
+            .mount_setattr(
+                tree.as_raw_fd(),
+                Path::new(""),
+                linux::AT_EMPTY_PATH | recursive,
+                &mount_attr,
+                mem::size_of::<linux::MountAttr>(),
+            )
+            .map_err(err)?;
+        init_sender.idmapped_mount(tree.as_raw_fd())?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
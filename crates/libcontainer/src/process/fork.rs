@@ -1,10 +1,20 @@
 use std::ffi::c_int;
 use std::num::NonZeroUsize;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use libc::SIGCHLD;
 use nix::sys::{mman, resource};
 use nix::unistd::Pid;
 
+use crate::pidfd::PidFd;
+
+// not defined by libc yet, available since Linux 5.7
+const CLONE_INTO_CGROUP: u64 = 0x200000000;
+
+/// Set in a process which clone3 has spawned into a cgroup
+static SPAWNED_INTO_CGROUP: AtomicBool = AtomicBool::new(false);
+
 #[derive(Debug, thiserror::Error)]
 pub enum CloneError {
     #[error("failed to clone process")]
@@ -34,6 +44,24 @@ pub enum CloneError {
 /// correctly.
 pub type CloneCb<'a> = Box<dyn FnMut() -> i32 + 'a>;
 
+/// Process created by [`container_clone`]
+#[derive(Debug)]
+pub struct ClonedProcess {
+    pub pid: Pid,
+    /// pidfd of the process, which is returned by clone3 since Linux 5.3
+    pub pidfd: Option<PidFd>,
+    /// Whether the process has been spawned into the cgroup, which requires
+    /// clone3 with CLONE_INTO_CGROUP, i.e. Linux 5.7. Otherwise the process
+    /// has to join the cgroup itself.
+    pub in_cgroup: bool,
+}
+
+/// Returns whether the calling process has been spawned into its cgroup by
+/// [`container_clone`]
+pub fn spawned_into_cgroup() -> bool {
+    SPAWNED_INTO_CGROUP.load(Ordering::Relaxed)
+}
+
 // Clone a sibling process that shares the same parent as the calling
 // process. This is used to launch the container init process so the parent
 // process of the calling process can receive ownership of the process. If we
@@ -47,12 +75,19 @@ pub fn container_clone_sibling(cb: CloneCb) -> Result<Pid, CloneError> {
     // The older `clone` will not return EINVAL in this case. Instead it ignores
     // the exit signal bits in the glibc wrapper. Therefore, we explicitly set
     // the exit_signal to None here, so this works for both version of clone.
-    clone_internal(cb, libc::CLONE_PARENT as u64, None)
+    clone_internal(cb, libc::CLONE_PARENT as u64, None, None).map(|process| process.pid)
 }
 
-// Clone a child process and execute the callback.
-pub fn container_clone(cb: CloneCb) -> Result<Pid, CloneError> {
-    clone_internal(cb, 0, Some(SIGCHLD as u64))
+// Clone a child process and execute the callback. The child is spawned
+// directly into the cgroup, if one is given, which saves it from writing itself
+// to cgroup.procs and it can't run outside of the cgroup, not even briefly.
+// Kernels without CLONE_INTO_CGROUP or clone3 fall back to a plain clone, after
+// which the child has to join the cgroup itself.
+pub fn container_clone(
+    cb: CloneCb,
+    cgroup: Option<BorrowedFd>,
+) -> Result<ClonedProcess, CloneError> {
+    clone_internal(cb, libc::CLONE_PIDFD as u64, Some(SIGCHLD as u64), cgroup)
 }
 
 // An internal wrapper to manage the clone3 vs clone fallback logic.
@@ -60,15 +95,48 @@ fn clone_internal(
     mut cb: CloneCb,
     flags: u64,
     exit_signal: Option<u64>,
-) -> Result<Pid, CloneError> {
-    match clone3(&mut cb, flags, exit_signal) {
-        Ok(pid) => Ok(pid),
+    cgroup: Option<BorrowedFd>,
+) -> Result<ClonedProcess, CloneError> {
+    if let Some(cgroup) = cgroup {
+        match clone3(
+            &mut cb,
+            flags | CLONE_INTO_CGROUP,
+            exit_signal,
+            Some(cgroup),
+        ) {
+            Ok((pid, pidfd)) => {
+                return Ok(ClonedProcess {
+                    pid,
+                    pidfd,
+                    in_cgroup: true,
+                })
+            }
+            // older kernels fail with E2BIG or EINVAL, but e.g. the cgroup
+            // may also not accept processes, which joining it reports better
+            Err(err) => {
+                tracing::debug!(?err, "failed to clone into cgroup, fallback to joining it")
+            }
+        }
+    }
+
+    match clone3(&mut cb, flags, exit_signal, None) {
+        Ok((pid, pidfd)) => Ok(ClonedProcess {
+            pid,
+            pidfd,
+            in_cgroup: false,
+        }),
         // For now, we decide to only fallback on ENOSYS
         Err(CloneError::Clone(nix::Error::ENOSYS)) => {
             tracing::debug!("clone3 is not supported, fallback to clone");
-            let pid = clone(cb, flags, exit_signal)?;
-
-            Ok(pid)
+            // clone returns the pidfd differently, kernels without clone3
+            // don't have pidfds anyway
+            let pid = clone(cb, flags & !(libc::CLONE_PIDFD as u64), exit_signal)?;
+
+            Ok(ClonedProcess {
+                pid,
+                pidfd: None,
+                in_cgroup: false,
+            })
         }
         Err(err) => Err(err),
     }
@@ -77,7 +145,12 @@ fn clone_internal(
 // Unlike the clone call, clone3 is currently using the kernel syscall, mimicking
 // the interface of fork. There is not need to explicitly manage the memory, so
 // we can safely passing the callback closure as reference.
-fn clone3(cb: &mut CloneCb, flags: u64, exit_signal: Option<u64>) -> Result<Pid, CloneError> {
+fn clone3(
+    cb: &mut CloneCb,
+    flags: u64,
+    exit_signal: Option<u64>,
+    cgroup: Option<BorrowedFd>,
+) -> Result<(Pid, Option<PidFd>), CloneError> {
     #[repr(C)]
     struct clone3_args {
         flags: u64,
@@ -92,9 +165,10 @@ fn clone3(cb: &mut CloneCb, flags: u64, exit_signal: Option<u64>) -> Result<Pid,
         set_tid_size: u64,
         cgroup: u64,
     }
+    let mut pidfd: c_int = -1;
     let mut args = clone3_args {
         flags,
-        pidfd: 0,
+        pidfd: &mut pidfd as *mut c_int as u64,
         child_tid: 0,
         parent_tid: 0,
         exit_signal: exit_signal.unwrap_or(0),
@@ -103,7 +177,7 @@ fn clone3(cb: &mut CloneCb, flags: u64, exit_signal: Option<u64>) -> Result<Pid,
         tls: 0,
         set_tid: 0,
         set_tid_size: 0,
-        cgroup: 0,
+        cgroup: cgroup.map_or(0, |fd| fd.as_raw_fd() as u64),
     };
     let args_ptr = &mut args as *mut clone3_args;
     let args_size = std::mem::size_of::<clone3_args>();
@@ -118,9 +192,17 @@ fn clone3(cb: &mut CloneCb, flags: u64, exit_signal: Option<u64>) -> Result<Pid,
         0 => {
             // Inside the cloned process, we execute the callback and exit with
             // the return code.
+            if flags & CLONE_INTO_CGROUP != 0 {
+                SPAWNED_INTO_CGROUP.store(true, Ordering::Relaxed);
+            }
             std::process::exit(cb());
         }
-        ret if ret >= 0 => Ok(Pid::from_raw(ret as i32)),
+        ret if ret >= 0 => {
+            let pid = Pid::from_raw(ret as i32);
+            let pidfd = (flags & libc::CLONE_PIDFD as u64 != 0)
+                .then(|| PidFd::from_fd(pid, unsafe { OwnedFd::from_raw_fd(pidfd) }));
+            Ok((pid, pidfd))
+        }
         ret => Err(CloneError::UnknownErrno(ret as i32)),
     }
 }
@@ -247,7 +329,13 @@ mod test {
 
     #[test]
     fn test_container_fork() -> Result<()> {
-        let pid = container_clone(Box::new(|| 0))?;
+        let cloned = container_clone(Box::new(|| 0), None)?;
+        let pid = cloned.pid;
+        assert!(!cloned.in_cgroup);
+        // kernels older than 5.3 don't return pidfds
+        if let Some(pidfd) = &cloned.pidfd {
+            assert_eq!(pidfd.pid(), pid);
+        }
         match waitpid(pid, None).expect("wait pid failed.") {
             WaitStatus::Exited(p, status) => {
                 assert_eq!(pid, p);
@@ -260,7 +348,7 @@ mod test {
 
     #[test]
     fn test_container_err_fork() -> Result<()> {
-        let pid = container_clone(Box::new(|| -1))?;
+        let pid = container_clone(Box::new(|| -1), None)?.pid;
         match waitpid(pid, None).expect("wait pid failed.") {
             WaitStatus::Exited(p, status) => {
                 assert_eq!(pid, p);
@@ -322,8 +410,8 @@ mod test {
         Ok(())
     }
 
-    // This test depends on libseccomp to work.
-    #[cfg(feature = "libseccomp")]
+    // This test depends on seccomp to work.
+    #[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
     #[test]
     fn test_clone_fallback() -> Result<()> {
         use oci_spec::runtime::{
@@ -367,7 +455,9 @@ mod test {
                 ));
             }
 
-            let pid = container_clone(Box::new(|| 0)).map_err(|err| err.to_string())?;
+            let pid = container_clone(Box::new(|| 0), None)
+                .map_err(|err| err.to_string())?
+                .pid;
             match waitpid(pid, None).expect("wait pid failed.") {
                 WaitStatus::Exited(p, status) => {
                     assert_eq!(pid, p);
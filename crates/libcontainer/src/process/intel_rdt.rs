@@ -1,13 +1,15 @@
 use std::collections::HashMap;
-use std::fs::{self, OpenOptions};
-use std::io::Write;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
+use nix::fcntl::{Flock, FlockArg};
 use nix::unistd::Pid;
 use oci_spec::runtime::LinuxIntelRdt;
 use once_cell::sync::Lazy;
 use procfs::process::Process;
 use regex::Regex;
+use serde::Serialize;
 
 #[derive(Debug, thiserror::Error)]
 pub enum IntelRdtError {
@@ -45,6 +47,26 @@ pub enum IntelRdtError {
     CreateClosIDDirectory(#[source] std::io::Error),
     #[error("failed to canonicalize path")]
     Canonicalize(#[source] std::io::Error),
+    #[error("{0} monitoring is not supported")]
+    MonitoringNotSupported(&'static str),
+    #[error("failed to read supported monitoring features")]
+    ReadMonitoringFeatures(#[source] std::io::Error),
+    #[error("failed to create resctrl monitoring group")]
+    CreateMonitoringGroup(#[source] std::io::Error),
+    #[error("failed to write to resctrl monitoring group")]
+    WriteMonitoringGroup(#[source] std::io::Error),
+    #[error("failed to remove resctrl monitoring group")]
+    RemoveMonitoringGroup(#[source] std::io::Error),
+    #[error("failed to read resctrl monitoring data")]
+    ReadMonitoringData(#[source] std::io::Error),
+    #[error("invalid resctrl monitoring data {0}")]
+    ParseMonitoringData(String),
+    #[error("invalid closID {0:?}")]
+    InvalidClosID(String),
+    #[error("failed to lock the shared resctrl groups")]
+    LockSharedGroups(#[source] nix::Error),
+    #[error("failed to record the containers of a shared resctrl group")]
+    RecordSharedGroup(#[source] std::io::Error),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -61,6 +83,51 @@ pub enum ParseLineError {
 
 type Result<T> = std::result::Result<T, IntelRdtError>;
 
+const LLC_OCCUPANCY: &str = "llc_occupancy";
+const MBM_TOTAL_BYTES: &str = "mbm_total_bytes";
+const MBM_LOCAL_BYTES: &str = "mbm_local_bytes";
+
+/// Directory in the root directory which the containers sharing a resctrl
+/// group by its closID are recorded in, as `<closID>/members/<container id>`
+pub const SHARED_GROUP_DIR: &str = ".resctrl";
+const SHARED_GROUP_MEMBERS: &str = "members";
+/// Marks a shared resctrl group which has been created by the runtime, so
+/// that it is removed along with its last member
+const SHARED_GROUP_CREATED: &str = "created";
+
+/// Reports the Intel RDT configuration and monitoring data of a container
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct IntelRdtStats {
+    /// Current L3 cache schema of the resctrl group
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l3_cache_schema: Option<String>,
+    /// Current memory bandwidth schema of the resctrl group
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mem_bw_schema: Option<String>,
+    /// Memory bandwidth usage per L3 cache domain, if MBM is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mbm_stats: Option<Vec<MbmStats>>,
+    /// Last level cache occupancy per L3 cache domain, if CMT is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cmt_stats: Option<Vec<CmtStats>>,
+}
+
+/// Memory bandwidth monitoring data of a L3 cache domain
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MbmStats {
+    /// Total memory bandwidth used by the container in bytes
+    pub mbm_total_bytes: u64,
+    /// Memory bandwidth used by the container on the local NUMA node in bytes
+    pub mbm_local_bytes: u64,
+}
+
+/// Cache monitoring data of a L3 cache domain
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CmtStats {
+    /// Last level cache occupied by the container in bytes
+    pub llc_occupancy: u64,
+}
+
 pub fn delete_resctrl_subdirectory(id: &str) -> Result<()> {
     let dir = find_resctrl_mount_point().map_err(|err| {
         tracing::error!("failed to find resctrl mount point: {}", err);
@@ -359,11 +426,275 @@ fn write_resctrl_schemata(
     Ok(())
 }
 
+/// Returns the monitoring files of the features enabled in the OCI config.
+fn monitoring_features(intel_rdt: &LinuxIntelRdt) -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if intel_rdt.enable_cmt().unwrap_or(false) {
+        features.push(LLC_OCCUPANCY);
+    }
+    if intel_rdt.enable_mbm().unwrap_or(false) {
+        features.push(MBM_TOTAL_BYTES);
+        features.push(MBM_LOCAL_BYTES);
+    }
+    features
+}
+
+fn monitoring_group_path(path: &Path, id: &str, container_id: &str) -> PathBuf {
+    path.join(id).join("mon_groups").join(container_id)
+}
+
+/// Adds container PID to the tasks file of a monitoring group inside of the
+/// resctrl subdirectory of the container, after checking that the requested
+/// monitoring features are supported by the system.
+fn write_container_pid_to_monitoring_group(
+    path: &Path,
+    id: &str,
+    container_id: &str,
+    init_pid: Pid,
+    features: Vec<&'static str>,
+) -> Result<()> {
+    let supported = fs::read_to_string(path.join("info").join("L3_MON").join("mon_features"))
+        .map_err(|err| {
+            tracing::error!(
+                "failed to read supported resctrl monitoring features: {}",
+                err
+            );
+            IntelRdtError::ReadMonitoringFeatures(err)
+        })?;
+    if let Some(feature) = features
+        .into_iter()
+        .find(|feature| !supported.lines().any(|line| line == *feature))
+    {
+        return Err(IntelRdtError::MonitoringNotSupported(feature));
+    }
+
+    let mon_group = monitoring_group_path(path, id, container_id);
+    fs::create_dir_all(&mon_group).map_err(|err| {
+        tracing::error!(
+            ?mon_group,
+            "failed to create resctrl monitoring group: {}",
+            err
+        );
+        IntelRdtError::CreateMonitoringGroup(err)
+    })?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(mon_group.join("tasks"))
+        .map_err(IntelRdtError::WriteMonitoringGroup)?;
+    write!(file, "{init_pid}").map_err(|err| {
+        tracing::error!(
+            "failed to write to resctrl monitoring group tasks file: {}",
+            err
+        );
+        IntelRdtError::WriteMonitoringGroup(err)
+    })?;
+
+    Ok(())
+}
+
+/// Removes the monitoring group of the container. The group is already gone
+/// if the resctrl subdirectory it lives in has been removed.
+pub fn delete_resctrl_monitoring_group(
+    container_id: &str,
+    intel_rdt: &LinuxIntelRdt,
+) -> Result<()> {
+    if monitoring_features(intel_rdt).is_empty() {
+        return Ok(());
+    }
+
+    let path = find_resctrl_mount_point()?;
+    let id = intel_rdt.clos_id().as_deref().unwrap_or(container_id);
+    let mon_group = monitoring_group_path(&path, id, container_id);
+    if mon_group.exists() {
+        fs::remove_dir(&mon_group).map_err(|err| {
+            tracing::error!(
+                ?mon_group,
+                "failed to remove resctrl monitoring group: {}",
+                err
+            );
+            IntelRdtError::RemoveMonitoringGroup(err)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Collects the schemas and the monitoring data of the resctrl groups of the
+/// container.
+pub fn stats(container_id: &str, intel_rdt: &LinuxIntelRdt) -> Result<IntelRdtStats> {
+    let path = find_resctrl_mount_point()?;
+    read_stats(&path, container_id, intel_rdt)
+}
+
+fn read_stats(path: &Path, container_id: &str, intel_rdt: &LinuxIntelRdt) -> Result<IntelRdtStats> {
+    let id = intel_rdt.clos_id().as_deref().unwrap_or(container_id);
+    let schemata =
+        fs::read_to_string(path.join(id).join("schemata")).map_err(IntelRdtError::ReadSchemata)?;
+    let schema = |prefix: &str| {
+        let lines: Vec<_> = schemata
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with(prefix))
+            .collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    };
+
+    let mut stats = IntelRdtStats {
+        l3_cache_schema: schema("L3"),
+        mem_bw_schema: schema("MB:"),
+        ..Default::default()
+    };
+
+    if monitoring_features(intel_rdt).is_empty() {
+        return Ok(stats);
+    }
+
+    let mon_data = monitoring_group_path(path, id, container_id).join("mon_data");
+    let mut domains: Vec<_> = fs::read_dir(&mon_data)
+        .map_err(IntelRdtError::ReadMonitoringData)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()
+        .map_err(IntelRdtError::ReadMonitoringData)?;
+    // only the L3 cache domains carry data of CMT and MBM
+    domains.retain(|domain| {
+        domain
+            .file_name()
+            .map_or(false, |name| name.to_string_lossy().starts_with("mon_L3_"))
+    });
+    domains.sort();
+
+    if intel_rdt.enable_cmt().unwrap_or(false) {
+        stats.cmt_stats = Some(
+            domains
+                .iter()
+                .map(|domain| {
+                    Ok(CmtStats {
+                        llc_occupancy: read_monitoring_value(&domain.join(LLC_OCCUPANCY))?,
+                    })
+                })
+                .collect::<Result<_>>()?,
+        );
+    }
+    if intel_rdt.enable_mbm().unwrap_or(false) {
+        stats.mbm_stats = Some(
+            domains
+                .iter()
+                .map(|domain| {
+                    Ok(MbmStats {
+                        mbm_total_bytes: read_monitoring_value(&domain.join(MBM_TOTAL_BYTES))?,
+                        mbm_local_bytes: read_monitoring_value(&domain.join(MBM_LOCAL_BYTES))?,
+                    })
+                })
+                .collect::<Result<_>>()?,
+        );
+    }
+
+    Ok(stats)
+}
+
+fn read_monitoring_value(path: &Path) -> Result<u64> {
+    let value = fs::read_to_string(path).map_err(IntelRdtError::ReadMonitoringData)?;
+    value
+        .trim()
+        .parse()
+        .map_err(|_| IntelRdtError::ParseMonitoringData(format!("{path:?}: {value}")))
+}
+
+// the resctrl directory of the closID is joined, as the closID is a name
+// of a directory in the resctrl filesystem
+fn validate_clos_id(clos_id: &str) -> Result<()> {
+    if clos_id.is_empty() || clos_id == "." || clos_id == ".." || clos_id.contains('/') {
+        return Err(IntelRdtError::InvalidClosID(clos_id.to_owned()));
+    }
+    Ok(())
+}
+
+// the shared groups are locked all at once, so that a group isn't removed
+// by its last member leaving while another container joins it
+fn lock_shared_groups(root_path: &Path) -> Result<Flock<File>> {
+    let dir = root_path.join(SHARED_GROUP_DIR);
+    fs::create_dir_all(&dir).map_err(IntelRdtError::RecordSharedGroup)?;
+    let dir = File::open(&dir).map_err(IntelRdtError::RecordSharedGroup)?;
+    Flock::lock(dir, FlockArg::LockExclusive)
+        .map_err(|(_, err)| IntelRdtError::LockSharedGroups(err))
+}
+
+/// Records the container as a member of the resctrl group of the closID,
+/// which the container has created if it is the first one using it
+fn join_shared_group(
+    root_path: &Path,
+    clos_id: &str,
+    container_id: &str,
+    created: bool,
+) -> Result<()> {
+    let group = root_path.join(SHARED_GROUP_DIR).join(clos_id);
+    let members = group.join(SHARED_GROUP_MEMBERS);
+    fs::create_dir_all(&members).map_err(IntelRdtError::RecordSharedGroup)?;
+    File::create(members.join(container_id)).map_err(IntelRdtError::RecordSharedGroup)?;
+    if created {
+        File::create(group.join(SHARED_GROUP_CREATED)).map_err(IntelRdtError::RecordSharedGroup)?;
+    }
+    tracing::debug!(
+        clos_id,
+        container_id,
+        created,
+        "joined shared resctrl group"
+    );
+
+    Ok(())
+}
+
+/// Removes the container from the members of the resctrl group of the
+/// closID and returns whether the group has to be removed, which is the case
+/// if the runtime has created it and no other container is using it
+fn leave_shared_group_record(root_path: &Path, clos_id: &str, container_id: &str) -> Result<bool> {
+    let group = root_path.join(SHARED_GROUP_DIR).join(clos_id);
+    let members = group.join(SHARED_GROUP_MEMBERS);
+    match fs::remove_file(members.join(container_id)) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(IntelRdtError::RecordSharedGroup(err)),
+    }
+    let last = match fs::read_dir(&members) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => true,
+        Err(err) => return Err(IntelRdtError::RecordSharedGroup(err)),
+    };
+    if !last {
+        tracing::debug!(clos_id, container_id, "left shared resctrl group");
+        return Ok(false);
+    }
+
+    let created = group.join(SHARED_GROUP_CREATED).exists();
+    match fs::remove_dir_all(&group) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(IntelRdtError::RecordSharedGroup(err)),
+    }
+    Ok(created)
+}
+
+/// Removes the container from the containers sharing the resctrl group of
+/// the closID, and removes the group along with its last member if the
+/// runtime has created it. Groups which existed before are left alone.
+pub fn leave_shared_group(root_path: &Path, clos_id: &str, container_id: &str) -> Result<()> {
+    validate_clos_id(clos_id)?;
+    let _lock = lock_shared_groups(root_path)?;
+    if leave_shared_group_record(root_path, clos_id, container_id)? {
+        tracing::debug!(clos_id, container_id, "removing shared resctrl group");
+        delete_resctrl_subdirectory(clos_id)?;
+    }
+
+    Ok(())
+}
+
 /// Sets up Intel RDT configuration for the container process based on the
 /// OCI config. The result bool tells whether or not we need to clean up
 /// the created subdirectory.
 pub fn setup_intel_rdt(
     maybe_container_id: Option<&str>,
+    root_path: Option<&Path>,
     init_pid: &Pid,
     intel_rdt: &LinuxIntelRdt,
 ) -> Result<bool> {
@@ -381,11 +712,29 @@ pub fn setup_intel_rdt(
         (None, None) => Err(IntelRdtError::ResctrlIdNotFound)?,
     };
 
+    if let Some(clos_id) = intel_rdt.clos_id() {
+        validate_clos_id(clos_id)?;
+    }
+    // the containers giving the same closID share its group, which is kept
+    // until the last of them is deleted
+    let shared = match (intel_rdt.clos_id(), root_path, maybe_container_id) {
+        (Some(clos_id), Some(root_path), Some(container_id)) => Some((
+            lock_shared_groups(root_path)?,
+            root_path,
+            clos_id,
+            container_id,
+        )),
+        _ => None,
+    };
+
     let created_dir = write_container_pid_to_resctrl_tasks(&path, id, *init_pid, only_clos_id_set)
         .map_err(|err| {
             tracing::error!("failed to write container pid to resctrl tasks file");
             err
         })?;
+    if let Some((_lock, root_path, clos_id, container_id)) = &shared {
+        join_shared_group(root_path, clos_id, container_id, created_dir)?;
+    }
     write_resctrl_schemata(
         &path,
         id,
@@ -399,8 +748,20 @@ pub fn setup_intel_rdt(
         err
     })?;
 
+    let monitoring = monitoring_features(intel_rdt);
+    if !monitoring.is_empty() {
+        let container_id = maybe_container_id.unwrap_or(id);
+        write_container_pid_to_monitoring_group(&path, id, container_id, *init_pid, monitoring)
+            .map_err(|err| {
+                tracing::error!("failed to write container pid to resctrl monitoring group");
+                err
+            })?;
+    }
+
     // If closID is not set and the runtime has created the sub-directory,
     // the runtime MUST remove the sub-directory when the container is deleted.
+    // A sub-directory of a closID is removed with the last container sharing
+    // it instead.
     let need_to_delete_directory = !clos_id_set && created_dir;
 
     Ok(need_to_delete_directory)
@@ -549,6 +910,29 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_shared_group() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        join_shared_group(root.path(), "clos", "c1", true)?;
+        join_shared_group(root.path(), "clos", "c2", false)?;
+        join_shared_group(root.path(), "existing", "c3", false)?;
+
+        assert!(!leave_shared_group_record(root.path(), "clos", "c1")?);
+        // the group created by the first container is removed with the last one
+        assert!(leave_shared_group_record(root.path(), "clos", "c2")?);
+        assert!(!root.path().join(SHARED_GROUP_DIR).join("clos").exists());
+        // leaving twice, e.g. when a forced delete is repeated, does nothing
+        assert!(!leave_shared_group_record(root.path(), "clos", "c2")?);
+        // a group the runtime hasn't created is kept
+        assert!(!leave_shared_group_record(root.path(), "existing", "c3")?);
+
+        assert!(validate_clos_id("clos-1").is_ok());
+        for clos_id in ["", ".", "..", "../clos"] {
+            assert!(validate_clos_id(clos_id).is_err(), "{clos_id:?}");
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_write_resctrl_schemata() -> Result<()> {
         let tmp = tempfile::tempdir().unwrap();
@@ -610,4 +994,112 @@ mod test {
 
         Ok(())
     }
+
+    fn intel_rdt(enable_cmt: bool, enable_mbm: bool) -> LinuxIntelRdt {
+        let mut intel_rdt = LinuxIntelRdt::default();
+        intel_rdt.set_enable_cmt(Some(enable_cmt));
+        intel_rdt.set_enable_mbm(Some(enable_mbm));
+        intel_rdt
+    }
+
+    #[test]
+    fn test_write_pid_to_monitoring_group() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        let features = monitoring_features(&intel_rdt(true, true));
+        assert_eq!(
+            features,
+            vec![LLC_OCCUPANCY, MBM_TOTAL_BYTES, MBM_LOCAL_BYTES]
+        );
+
+        fs::create_dir_all(tmp.path().join("info/L3_MON"))?;
+        fs::write(
+            tmp.path().join("info/L3_MON/mon_features"),
+            "llc_occupancy\n",
+        )?;
+        let res = write_container_pid_to_monitoring_group(
+            tmp.path(),
+            "clos",
+            "foo",
+            Pid::from_raw(1000),
+            features.clone(),
+        );
+        assert!(matches!(
+            res,
+            Err(IntelRdtError::MonitoringNotSupported(MBM_TOTAL_BYTES))
+        ));
+
+        fs::write(
+            tmp.path().join("info/L3_MON/mon_features"),
+            "llc_occupancy\nmbm_total_bytes\nmbm_local_bytes\n",
+        )?;
+        write_container_pid_to_monitoring_group(
+            tmp.path(),
+            "clos",
+            "foo",
+            Pid::from_raw(1000),
+            features,
+        )?;
+        let res = fs::read_to_string(tmp.path().join("clos/mon_groups/foo/tasks"))?;
+        assert_eq!(res, "1000");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_stats() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("foo"))?;
+        fs::write(
+            tmp.path().join("foo/schemata"),
+            "    L3:0=7ff;1=7ff\n    MB:0=100;1=50\n",
+        )?;
+
+        let stats = read_stats(tmp.path(), "foo", &LinuxIntelRdt::default())?;
+        assert_eq!(
+            stats,
+            IntelRdtStats {
+                l3_cache_schema: Some("L3:0=7ff;1=7ff".to_owned()),
+                mem_bw_schema: Some("MB:0=100;1=50".to_owned()),
+                ..Default::default()
+            }
+        );
+
+        for (domain, occupancy, total, local) in
+            [("mon_L3_00", 4096, 1000, 800), ("mon_L3_01", 8192, 2000, 0)]
+        {
+            let dir = tmp.path().join("foo/mon_groups/foo/mon_data").join(domain);
+            fs::create_dir_all(&dir)?;
+            fs::write(dir.join(LLC_OCCUPANCY), format!("{occupancy}\n"))?;
+            fs::write(dir.join(MBM_TOTAL_BYTES), format!("{total}\n"))?;
+            fs::write(dir.join(MBM_LOCAL_BYTES), format!("{local}\n"))?;
+        }
+
+        let stats = read_stats(tmp.path(), "foo", &intel_rdt(true, true))?;
+        assert_eq!(
+            stats.cmt_stats,
+            Some(vec![
+                CmtStats {
+                    llc_occupancy: 4096
+                },
+                CmtStats {
+                    llc_occupancy: 8192
+                }
+            ])
+        );
+        assert_eq!(
+            stats.mbm_stats,
+            Some(vec![
+                MbmStats {
+                    mbm_total_bytes: 1000,
+                    mbm_local_bytes: 800
+                },
+                MbmStats {
+                    mbm_total_bytes: 2000,
+                    mbm_local_bytes: 0
+                }
+            ])
+        );
+
+        Ok(())
+    }
 }
@@ -11,7 +11,14 @@ pub enum Message {
     MappingWritten,
     SeccompNotify,
     SeccompNotifyDone,
-    ExecFailed(String),
+    IdmappedMount,
+    HookRequest,
+    HookDone,
+    /// The init process failed, with the last lines it logged
+    ExecFailed {
+        error: String,
+        logs: Vec<String>,
+    },
     OtherError(String),
 }
 
@@ -24,7 +31,10 @@ impl fmt::Display for Message {
             Message::MappingWritten => write!(f, "MappingWritten"),
             Message::SeccompNotify => write!(f, "SeccompNotify"),
             Message::SeccompNotifyDone => write!(f, "SeccompNotifyDone"),
-            Message::ExecFailed(s) => write!(f, "ExecFailed({})", s),
+            Message::IdmappedMount => write!(f, "IdmappedMount"),
+            Message::HookRequest => write!(f, "HookRequest"),
+            Message::HookDone => write!(f, "HookDone"),
+            Message::ExecFailed { error, .. } => write!(f, "ExecFailed({})", error),
             Message::OtherError(s) => write!(f, "OtherError({})", s),
         }
     }
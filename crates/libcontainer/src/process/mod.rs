@@ -9,5 +9,5 @@ pub mod container_main_process;
 mod fork;
 pub mod intel_rdt;
 mod message;
-#[cfg(feature = "libseccomp")]
+#[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
 mod seccomp_listener;
@@ -9,6 +9,7 @@ use oci_spec::runtime;
 use super::channel;
 use crate::container::ContainerProcessState;
 use crate::seccomp;
+use crate::socket_address::{SocketAddress, SocketAddressError};
 
 #[derive(Debug, thiserror::Error)]
 pub enum SeccompListenerError {
@@ -18,6 +19,8 @@ pub enum SeccompListenerError {
     EncodeState(#[source] serde_json::Error),
     #[error(transparent)]
     ChannelError(#[from] channel::ChannelError),
+    #[error("invalid seccomp listener path")]
+    InvalidListenerPath(#[source] SocketAddressError),
     #[error("unix syscall fails")]
     UnixOther(#[source] nix::Error),
 }
@@ -56,28 +59,11 @@ fn sync_seccomp_send_msg(listener_path: &Path, msg: &[u8], fd: i32) -> Result<()
     // The seccomp listener has specific instructions on how to transmit the
     // information through seccomp listener.  Therefore, we have to use
     // libc/nix APIs instead of Rust std lib APIs to maintain flexibility.
-    let socket = socket::socket(
-        socket::AddressFamily::Unix,
-        socket::SockType::Stream,
-        socket::SockFlag::empty(),
-        None,
-    )
-    .map_err(|err| {
-        tracing::error!(
-            ?err,
-            "failed to create unix domain socket for seccomp listener"
-        );
-        SeccompListenerError::UnixOther(err)
-    })?;
-    let unix_addr = socket::UnixAddr::new(listener_path).map_err(|err| {
-        tracing::error!(
-            ?err,
-            ?listener_path,
-            "failed to create unix domain socket address"
-        );
-        SeccompListenerError::UnixOther(err)
+    let address = SocketAddress::parse(listener_path).map_err(|err| {
+        tracing::error!(?err, ?listener_path, "invalid seccomp listener address");
+        SeccompListenerError::InvalidListenerPath(err)
     })?;
-    socket::connect(socket.as_raw_fd(), &unix_addr).map_err(|err| {
+    let socket = address.connect().map_err(|err| {
         tracing::error!(
             ?err,
             ?listener_path,
@@ -1,5 +1,7 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
+use nix::errno::Errno;
 use nix::fcntl::{open, OFlag};
 use nix::mount::MsFlags;
 use nix::sys::stat::{umask, Mode};
@@ -8,13 +10,65 @@ use oci_spec::runtime::LinuxDevice;
 
 use super::utils::to_sflag;
 use crate::syscall::syscall::create_syscall;
-use crate::syscall::Syscall;
+use crate::syscall::{Syscall, SyscallError};
 use crate::utils::PathBufExt;
 
+/// Annotation to choose how single devices are created, as a comma separated
+/// list of `path=mode`, e.g. `/dev/fuse=bind,/dev/kvm=mknod`. See
+/// [`DeviceCreation`] for the modes.
+pub const DEVICE_CREATION_ANNOTATION: &str = "org.youki.devices.creation";
+
+/// How the node of a device is created in the container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeviceCreation {
+    /// Bind mounts the node of the host in a user namespace, where creating
+    /// device nodes is not permitted. Otherwise the node is created with
+    /// mknod, falling back to a bind mount if that is not permitted either,
+    /// e.g. without CAP_MKNOD.
+    #[default]
+    Auto,
+    /// Always creates the node with mknod
+    Mknod,
+    /// Always bind mounts the node of the host
+    Bind,
+}
+
+/// Parses the [`DEVICE_CREATION_ANNOTATION`], ignoring invalid entries
+pub fn device_creation(
+    annotations: Option<&HashMap<String, String>>,
+) -> HashMap<PathBuf, DeviceCreation> {
+    let value = match annotations.and_then(|a| a.get(DEVICE_CREATION_ANNOTATION)) {
+        Some(value) => value,
+        None => return HashMap::new(),
+    };
+
+    value
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .filter_map(|entry| {
+            let creation = match entry.trim().split_once('=') {
+                Some((path, "auto")) => (path, DeviceCreation::Auto),
+                Some((path, "mknod")) => (path, DeviceCreation::Mknod),
+                Some((path, "bind")) => (path, DeviceCreation::Bind),
+                _ => {
+                    tracing::warn!(
+                        ?entry,
+                        "ignoring invalid entry of {DEVICE_CREATION_ANNOTATION}"
+                    );
+                    return None;
+                }
+            };
+            Some((PathBuf::from(creation.0), creation.1))
+        })
+        .collect()
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum DeviceError {
     #[error("{0:?} is not a valid device path")]
     InvalidDevicePath(std::path::PathBuf),
+    #[error("not permitted to create device {0:?}")]
+    MknodNotPermitted(std::path::PathBuf),
     #[error("failed syscall to create device")]
     Syscall(#[from] crate::syscall::SyscallError),
     #[error(transparent)]
@@ -48,7 +102,15 @@ impl Device {
         Device { syscall }
     }
 
-    pub fn create_devices<'a, I>(&self, rootfs: &Path, devices: I, bind: bool) -> Result<()>
+    /// Creates the devices in the rootfs according to the given modes, where
+    /// devices without a mode are created with [`DeviceCreation::Auto`]
+    pub fn create_devices<'a, I>(
+        &self,
+        rootfs: &Path,
+        devices: I,
+        in_user_ns: bool,
+        creation: &HashMap<PathBuf, DeviceCreation>,
+    ) -> Result<()>
     where
         I: IntoIterator<Item = &'a LinuxDevice>,
     {
@@ -64,10 +126,17 @@ impl Device {
                     return Err(DeviceError::InvalidDevicePath(dev.path().to_path_buf()));
                 }
 
-                if bind {
-                    self.bind_dev(rootfs, dev)
-                } else {
-                    self.mknod_dev(rootfs, dev)
+                match creation.get(dev.path()).copied().unwrap_or_default() {
+                    DeviceCreation::Bind => self.bind_dev(rootfs, dev),
+                    DeviceCreation::Mknod => self.mknod_dev(rootfs, dev),
+                    DeviceCreation::Auto if in_user_ns => self.bind_dev(rootfs, dev),
+                    DeviceCreation::Auto => match self.mknod_dev(rootfs, dev) {
+                        Err(DeviceError::MknodNotPermitted(path)) => {
+                            tracing::debug!(?path, "mknod is not permitted, bind mounting device");
+                            self.bind_dev(rootfs, dev)
+                        }
+                        result => result,
+                    },
                 }
             })
             .collect::<Result<Vec<_>>>()?;
@@ -139,7 +208,12 @@ impl Device {
                     "failed to mknod device"
                 );
 
-                err
+                match err {
+                    SyscallError::Nix(Errno::EPERM) => {
+                        DeviceError::MknodNotPermitted(dev.path().clone())
+                    }
+                    err => err.into(),
+                }
             })?;
         self.syscall
             .chown(
@@ -203,7 +277,7 @@ mod tests {
     use oci_spec::runtime::{LinuxDeviceBuilder, LinuxDeviceType};
 
     use super::*;
-    use crate::syscall::test::{ChownArgs, MknodArgs, MountArgs, TestHelperSyscall};
+    use crate::syscall::test::{ArgName, ChownArgs, MknodArgs, MountArgs, TestHelperSyscall};
 
     #[test]
     fn test_bind_dev() -> Result<()> {
@@ -303,7 +377,7 @@ mod tests {
             .unwrap()];
 
         assert!(device
-            .create_devices(tmp_dir.path(), &devices, true)
+            .create_devices(tmp_dir.path(), &devices, true, &HashMap::new())
             .is_ok());
 
         let want = MountArgs {
@@ -322,7 +396,7 @@ mod tests {
         assert_eq!(want, *got);
 
         assert!(device
-            .create_devices(tmp_dir.path(), &devices, false)
+            .create_devices(tmp_dir.path(), &devices, false, &HashMap::new())
             .is_ok());
 
         let want = MknodArgs {
@@ -341,4 +415,55 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_create_devices_fallback() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let device = Device::new_with_syscall(Box::<TestHelperSyscall>::default());
+        let syscall = device
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        let devices = vec![LinuxDeviceBuilder::default()
+            .path(PathBuf::from("/dev/fuse"))
+            .major(10)
+            .minor(229)
+            .typ(LinuxDeviceType::C)
+            .build()?];
+
+        syscall.set_ret_err(ArgName::Mknod, || {
+            Err(SyscallError::Nix(nix::errno::Errno::EPERM))
+        });
+        device.create_devices(tmp_dir.path(), &devices, false, &HashMap::new())?;
+        assert_eq!(syscall.get_mount_args().len(), 1);
+
+        // without the fallback the error is returned
+        syscall.set_ret_err(ArgName::Mknod, || {
+            Err(SyscallError::Nix(nix::errno::Errno::EPERM))
+        });
+        let creation = HashMap::from([(PathBuf::from("/dev/fuse"), DeviceCreation::Mknod)]);
+        assert!(matches!(
+            device.create_devices(tmp_dir.path(), &devices, false, &creation),
+            Err(DeviceError::MknodNotPermitted(_))
+        ));
+        assert_eq!(syscall.get_mount_args().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_creation() {
+        let annotations = HashMap::from([(
+            DEVICE_CREATION_ANNOTATION.to_owned(),
+            "/dev/fuse=bind, /dev/kvm=mknod,/dev/tun=copy".to_owned(),
+        )]);
+        assert_eq!(
+            device_creation(Some(&annotations)),
+            HashMap::from([
+                (PathBuf::from("/dev/fuse"), DeviceCreation::Bind),
+                (PathBuf::from("/dev/kvm"), DeviceCreation::Mknod),
+            ])
+        );
+        assert!(device_creation(None).is_empty());
+    }
 }
@@ -0,0 +1,321 @@
+//! Mounts filesystems with the mount API of Linux 5.2, which configures a new
+//! filesystem option by option with fsconfig(2), creates a detached mount of
+//! it with fsmount(2) and attaches that with move_mount(2). Unlike the data
+//! string of mount(2), the options don't have to be joined, so values may
+//! contain commas, and the flags of the mount are set apart from the ones of
+//! the filesystem.
+
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use nix::mount::MsFlags;
+use procfs::KernelVersion;
+
+use crate::syscall::{linux, Syscall, SyscallError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FsMountError {
+    #[error("mount flags {0:?} can't be set with the new mount API")]
+    UnsupportedFlags(MsFlags),
+    #[error("failed to configure {fstype} filesystem")]
+    Configure {
+        fstype: String,
+        source: SyscallError,
+    },
+    #[error("failed to attach mount to {dest:?}")]
+    Attach { dest: PathBuf, source: SyscallError },
+}
+
+impl FsMountError {
+    /// Whether mounting with mount(2) may succeed nonetheless, i.e. nothing
+    /// has been mounted yet
+    pub fn can_fall_back(&self) -> bool {
+        !matches!(self, FsMountError::Attach { .. })
+    }
+}
+
+type Result<T> = std::result::Result<T, FsMountError>;
+
+/// An option of a filesystem, which is a flag if it has no value
+#[derive(Debug, PartialEq, Eq)]
+pub struct FsOption {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+pub fn is_supported(kernel: &KernelVersion) -> bool {
+    *kernel >= KernelVersion::new(5, 2, 0)
+}
+
+/// Splits the data of a mount into its options. Commas within double quotes,
+/// as in the SELinux context of a mount, don't separate options. The quotes
+/// are removed, as fsconfig takes values as they are.
+pub fn parse_options(data: &str) -> Vec<FsOption> {
+    let mut options = Vec::new();
+    let mut option = String::new();
+    let mut quoted = false;
+    for c in data.chars().chain(std::iter::once(',')) {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                if !option.is_empty() {
+                    let (key, value) = match option.split_once('=') {
+                        Some((key, value)) => (key.to_owned(), Some(value.to_owned())),
+                        None => (option.clone(), None),
+                    };
+                    options.push(FsOption { key, value });
+                }
+                option.clear();
+            }
+            c => option.push(c),
+        }
+    }
+    options
+}
+
+/// Splits the flags of mount(2) into the flags of the filesystem, which are
+/// set with fsconfig, and the attributes of the mount given to fsmount.
+/// Flags of mount(2) that only concern bind mounts, remounts and propagation
+/// are left out.
+pub fn split_flags(flags: MsFlags) -> Result<(Vec<&'static str>, u64)> {
+    let mut fs_flags = Vec::new();
+    let mut attr_flags = 0;
+    let mut rest = flags - (MsFlags::MS_REC | MsFlags::MS_SILENT);
+
+    if rest.contains(MsFlags::MS_RDONLY) {
+        // a new mount is made read-only along with its filesystem by mount(2)
+        fs_flags.push("ro");
+        attr_flags |= linux::MOUNT_ATTR_RDONLY;
+    }
+    for (flag, name) in [
+        (MsFlags::MS_SYNCHRONOUS, "sync"),
+        (MsFlags::MS_DIRSYNC, "dirsync"),
+        (MsFlags::MS_LAZYTIME, "lazytime"),
+        (MsFlags::MS_MANDLOCK, "mand"),
+    ] {
+        if rest.contains(flag) {
+            fs_flags.push(name);
+        }
+    }
+    for (flag, attr) in [
+        (MsFlags::MS_NOSUID, linux::MOUNT_ATTR_NOSUID),
+        (MsFlags::MS_NODEV, linux::MOUNT_ATTR_NODEV),
+        (MsFlags::MS_NOEXEC, linux::MOUNT_ATTR_NOEXEC),
+        (MsFlags::MS_NODIRATIME, linux::MOUNT_ATTR_NODIRATIME),
+    ] {
+        if rest.contains(flag) {
+            attr_flags |= attr;
+        }
+    }
+    rest -= MsFlags::MS_RDONLY
+        | MsFlags::MS_SYNCHRONOUS
+        | MsFlags::MS_DIRSYNC
+        | MsFlags::MS_LAZYTIME
+        | MsFlags::MS_MANDLOCK
+        | MsFlags::MS_NOSUID
+        | MsFlags::MS_NODEV
+        | MsFlags::MS_NOEXEC
+        | MsFlags::MS_NODIRATIME;
+
+    // only one of the atime modes can be set on a mount
+    let atime = MsFlags::MS_RELATIME | MsFlags::MS_NOATIME | MsFlags::MS_STRICTATIME;
+    match rest & atime {
+        modes if modes.is_empty() => {}
+        MsFlags::MS_RELATIME => attr_flags |= linux::MOUNT_ATTR_RELATIME,
+        MsFlags::MS_NOATIME => attr_flags |= linux::MOUNT_ATTR_NOATIME,
+        MsFlags::MS_STRICTATIME => attr_flags |= linux::MOUNT_ATTR_STRICTATIME,
+        modes => return Err(FsMountError::UnsupportedFlags(modes)),
+    }
+    rest -= atime;
+
+    if !rest.is_empty() {
+        return Err(FsMountError::UnsupportedFlags(rest));
+    }
+    Ok((fs_flags, attr_flags))
+}
+
+/// Mounts a new filesystem of type `fstype` at `dest`, with the flags and
+/// data that would have been passed to mount(2)
+pub fn mount(
+    syscall: &dyn Syscall,
+    source: &Path,
+    dest: &Path,
+    fstype: &str,
+    flags: MsFlags,
+    data: &str,
+) -> Result<()> {
+    let (fs_flags, attr_flags) = split_flags(flags)?;
+    let configure = |err| FsMountError::Configure {
+        fstype: fstype.to_owned(),
+        source: err,
+    };
+
+    let fs = syscall
+        .fsopen(fstype, linux::FSOPEN_CLOEXEC)
+        .map_err(configure)?;
+    let fd = fs.as_raw_fd();
+    syscall
+        .fsconfig(
+            fd,
+            linux::FSCONFIG_SET_STRING,
+            Some("source"),
+            Some(&source.to_string_lossy()),
+        )
+        .map_err(configure)?;
+    for flag in fs_flags {
+        syscall
+            .fsconfig(fd, linux::FSCONFIG_SET_FLAG, Some(flag), None)
+            .map_err(configure)?;
+    }
+    for option in parse_options(data) {
+        let cmd = match option.value {
+            Some(_) => linux::FSCONFIG_SET_STRING,
+            None => linux::FSCONFIG_SET_FLAG,
+        };
+        syscall
+            .fsconfig(fd, cmd, Some(&option.key), option.value.as_deref())
+            .map_err(configure)?;
+    }
+    syscall
+        .fsconfig(fd, linux::FSCONFIG_CMD_CREATE, None, None)
+        .map_err(configure)?;
+    let mount = syscall
+        .fsmount(fd, linux::FSMOUNT_CLOEXEC, attr_flags)
+        .map_err(configure)?;
+
+    syscall
+        .move_mount(
+            mount.as_raw_fd(),
+            Path::new(""),
+            libc::AT_FDCWD,
+            dest,
+            linux::MOVE_MOUNT_F_EMPTY_PATH,
+        )
+        .map_err(|err| FsMountError::Attach {
+            dest: dest.to_owned(),
+            source: err,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::syscall::test::{FsconfigArgs, TestHelperSyscall};
+
+    fn option(key: &str, value: Option<&str>) -> FsOption {
+        FsOption {
+            key: key.to_owned(),
+            value: value.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(parse_options(""), []);
+        assert_eq!(
+            parse_options("mode=755,,size=65536k,nr_inodes=1k,noswap"),
+            [
+                option("mode", Some("755")),
+                option("size", Some("65536k")),
+                option("nr_inodes", Some("1k")),
+                option("noswap", None),
+            ]
+        );
+        assert_eq!(
+            parse_options("mode=620,context=\"system_u:object_r:container_file_t:s0:c1,c2\""),
+            [
+                option("mode", Some("620")),
+                option(
+                    "context",
+                    Some("system_u:object_r:container_file_t:s0:c1,c2")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_flags() -> Result<()> {
+        assert_eq!(split_flags(MsFlags::empty())?, (vec![], 0));
+        assert_eq!(
+            split_flags(
+                MsFlags::MS_RDONLY
+                    | MsFlags::MS_NOSUID
+                    | MsFlags::MS_NOEXEC
+                    | MsFlags::MS_STRICTATIME
+                    | MsFlags::MS_SYNCHRONOUS
+                    | MsFlags::MS_REC
+            )?,
+            (
+                vec!["ro", "sync"],
+                linux::MOUNT_ATTR_RDONLY
+                    | linux::MOUNT_ATTR_NOSUID
+                    | linux::MOUNT_ATTR_NOEXEC
+                    | linux::MOUNT_ATTR_STRICTATIME
+            )
+        );
+        assert!(matches!(
+            split_flags(MsFlags::MS_NOATIME | MsFlags::MS_STRICTATIME),
+            Err(FsMountError::UnsupportedFlags(_))
+        ));
+        assert!(matches!(
+            split_flags(MsFlags::MS_BIND),
+            Err(FsMountError::UnsupportedFlags(flags)) if flags == MsFlags::MS_BIND
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount() -> Result<()> {
+        let syscall = TestHelperSyscall::default();
+        let err = mount(
+            &syscall,
+            Path::new("tmpfs"),
+            Path::new("/rootfs/dev"),
+            "tmpfs",
+            MsFlags::empty(),
+            "",
+        )
+        .unwrap_err();
+        assert!(err.can_fall_back());
+
+        syscall.enable_fs_context();
+        mount(
+            &syscall,
+            Path::new("tmpfs"),
+            Path::new("/rootfs/dev"),
+            "tmpfs",
+            MsFlags::MS_NOSUID | MsFlags::MS_RDONLY,
+            "mode=755,size=65536k",
+        )?;
+        assert_eq!(syscall.get_fsopen_args(), ["tmpfs"]);
+        let set = |cmd, key: &str, value: Option<&str>| FsconfigArgs {
+            cmd,
+            key: Some(key.to_owned()),
+            value: value.map(str::to_owned),
+        };
+        assert_eq!(
+            syscall.get_fsconfig_args(),
+            [
+                set(linux::FSCONFIG_SET_STRING, "source", Some("tmpfs")),
+                set(linux::FSCONFIG_SET_FLAG, "ro", None),
+                set(linux::FSCONFIG_SET_STRING, "mode", Some("755")),
+                set(linux::FSCONFIG_SET_STRING, "size", Some("65536k")),
+                FsconfigArgs {
+                    cmd: linux::FSCONFIG_CMD_CREATE,
+                    key: None,
+                    value: None,
+                },
+            ]
+        );
+        assert_eq!(
+            syscall.get_fsmount_args(),
+            [linux::MOUNT_ATTR_RDONLY | linux::MOUNT_ATTR_NOSUID]
+        );
+        let move_mount = &syscall.get_move_mount_args()[0];
+        assert_eq!(move_mount.to_pathname, PathBuf::from("/rootfs/dev"));
+        assert_eq!(move_mount.flags, linux::MOVE_MOUNT_F_EMPTY_PATH);
+        Ok(())
+    }
+}
@@ -8,7 +8,10 @@ pub use rootfs::RootFS;
 pub mod device;
 pub use device::Device;
 
+pub(super) mod fsmount;
 pub(super) mod mount;
+pub(super) mod overlay;
+pub(super) mod proc;
 pub(super) mod symlink;
 
 pub mod utils;
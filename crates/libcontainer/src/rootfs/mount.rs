@@ -1,5 +1,6 @@
 use std::fs::{canonicalize, create_dir_all, OpenOptions};
 use std::mem;
+use std::os::fd::BorrowedFd;
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 #[cfg(feature = "v1")]
@@ -11,19 +12,27 @@ use libcgroups::common::DEFAULT_CGROUP_ROOT;
 use nix::dir::Dir;
 use nix::errno::Errno;
 use nix::fcntl::OFlag;
-use nix::mount::MsFlags;
+use nix::mount::{MntFlags, MsFlags};
 use nix::sys::stat::Mode;
 use nix::NixPath;
 use oci_spec::runtime::{Mount as SpecMount, MountBuilder as SpecMountBuilder};
 use procfs::process::{MountInfo, MountOptFields, Process};
+use procfs::KernelVersion;
 use safe_path;
 
+use super::fsmount::{self, FsMountError};
+use super::overlay::{self, OverlayError};
+use super::proc::{self, ProcMountError};
 #[cfg(feature = "v1")]
 use super::symlink::Symlink;
 use super::symlink::SymlinkError;
-use super::utils::{parse_mount, MountOptionConfig};
+use super::utils::{
+    copy_dir_contents, parse_mount, relabel_type, remove_unmapped_gid, tmpcopyup, MountOptionConfig,
+};
+use crate::selinux::{self, SELinuxError};
 use crate::syscall::syscall::create_syscall;
 use crate::syscall::{linux, Syscall, SyscallError};
+#[cfg(any(feature = "v1", feature = "v2"))]
 use crate::utils::PathBufExt;
 
 #[derive(Debug, thiserror::Error)]
@@ -48,6 +57,14 @@ pub enum MountError {
     Procfs(#[from] procfs::ProcError),
     #[error("unknown mount option: {0}")]
     UnsupportedMountOption(String),
+    #[error(transparent)]
+    Overlay(#[from] OverlayError),
+    #[error("selinux")]
+    SELinux(#[from] SELinuxError),
+    #[error(transparent)]
+    FsMount(#[from] FsMountError),
+    #[error(transparent)]
+    Proc(#[from] ProcMountError),
 }
 
 type Result<T> = std::result::Result<T, MountError>;
@@ -58,10 +75,20 @@ pub struct MountOptions<'a> {
     pub label: Option<&'a str>,
     #[allow(dead_code)]
     pub cgroup_ns: bool,
+    /// The container shares the network namespace of the runtime
+    pub host_network: bool,
+    /// The container joins an existing pid namespace rather than creating
+    /// one, so its proc may not be a new instance
+    pub joins_pid_ns: bool,
+    /// Detached mount prepared by the main process for idmapped mounts
+    pub idmapped_mount: Option<BorrowedFd<'a>>,
 }
 
 pub struct Mount {
     syscall: Box<dyn Syscall>,
+    /// New filesystems are mounted with fsopen(2) and fsmount(2) rather than
+    /// mount(2), falling back to the latter if that fails
+    fs_context: bool,
 }
 
 impl Default for Mount {
@@ -74,12 +101,21 @@ impl Mount {
     pub fn new() -> Mount {
         Mount {
             syscall: create_syscall(),
+            fs_context: KernelVersion::current()
+                .map(|kernel| fsmount::is_supported(&kernel))
+                .unwrap_or(false),
         }
     }
 
     pub fn setup_mount(&self, mount: &SpecMount, options: &MountOptions) -> Result<()> {
         tracing::debug!("mounting {:?}", mount);
         let mut mount_option_config = parse_mount(mount)?;
+        if mount.typ().as_deref() == Some("devpts") {
+            // the init process has joined the user namespace of the container already
+            if let Ok(gid_map) = std::fs::read_to_string("/proc/self/gid_map") {
+                mount_option_config.data = remove_unmapped_gid(&mount_option_config.data, &gid_map);
+            }
+        }
 
         match mount.typ().as_deref() {
             Some("cgroup") => {
@@ -109,6 +145,36 @@ impl Mount {
                     }
                 }
             }
+            Some("sysfs") if options.host_network => self
+                .mount_host_sysfs(mount, options, &mount_option_config)
+                .map_err(|err| {
+                    tracing::error!("failed to bind mount the sysfs of the host: {}", err);
+                    err
+                })?,
+            Some("proc") => self
+                .mount_proc(mount, options, &mount_option_config)
+                .map_err(|err| {
+                    tracing::error!("failed to mount {:?}: {}", mount, err);
+                    err
+                })?,
+            Some("overlay") => {
+                mount_option_config.data = overlay::prepare_overlay(&mount_option_config.data)
+                    .map_err(|err| {
+                        tracing::error!("invalid overlay mount {:?}: {}", mount, err);
+                        err
+                    })?;
+                self.mount_into_container(
+                    mount,
+                    options.root,
+                    &mount_option_config,
+                    options.label,
+                    options.idmapped_mount,
+                )
+                .map_err(|err| {
+                    tracing::error!("failed to mount {:?}: {}", mount, err);
+                    err
+                })?;
+            }
             _ => {
                 if *mount.destination() == PathBuf::from("/dev") {
                     mount_option_config.flags &= !MsFlags::MS_RDONLY;
@@ -117,6 +183,7 @@ impl Mount {
                         options.root,
                         &mount_option_config,
                         options.label,
+                        options.idmapped_mount,
                     )
                     .map_err(|err| {
                         tracing::error!("failed to mount /dev: {}", err);
@@ -128,6 +195,7 @@ impl Mount {
                         options.root,
                         &mount_option_config,
                         options.label,
+                        options.idmapped_mount,
                     )
                     .map_err(|err| {
                         tracing::error!("failed to mount {:?}: {}", mount, err);
@@ -287,6 +355,7 @@ impl Mount {
             flags: MsFlags::MS_NOEXEC | MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
             data: data.to_string(),
             rec_attr: None,
+            propagation: MsFlags::empty(),
         };
 
         self.mount_into_container(
@@ -294,6 +363,7 @@ impl Mount {
             options.root,
             &mount_options_config,
             options.label,
+            None,
         )
         .map_err(|err| {
             tracing::error!("failed to mount {subsystem_mount:?}: {err}");
@@ -387,6 +457,7 @@ impl Mount {
                 options.root,
                 mount_option_config,
                 options.label,
+                None,
             )
             .is_err()
         {
@@ -395,22 +466,10 @@ impl Mount {
                 MountError::Other(err.into())
             })?;
 
-            let process_cgroup = Process::myself()
-                .map_err(|err| {
-                    tracing::error!("failed to get /proc/self: {}", err);
-                    MountError::Other(err.into())
-                })?
-                .cgroups()
-                .map_err(|err| {
-                    tracing::error!("failed to get process cgroups: {}", err);
-                    MountError::Other(err.into())
-                })?
-                .into_iter()
-                .find(|c| c.hierarchy == 0)
-                .map(|c| PathBuf::from(c.pathname))
-                .ok_or_else(|| {
-                    MountError::Custom("failed to find unified process cgroup".into())
-                })?;
+            let process_cgroup = libcgroups::v2::util::get_current_cgroup().map_err(|err| {
+                tracing::error!("failed to get unified process cgroup: {}", err);
+                MountError::Other(err.into())
+            })?;
             let bind_mount = SpecMountBuilder::default()
                 .typ("bind")
                 .source(host_mount.join_safely(process_cgroup).map_err(|err| {
@@ -433,6 +492,7 @@ impl Mount {
                 options.root,
                 &mount_option_config,
                 options.label,
+                None,
             )
             .map_err(|err| {
                 tracing::error!("failed to bind mount cgroup hierarchy: {}", err);
@@ -443,6 +503,85 @@ impl Mount {
         Ok(())
     }
 
+    /// A new sysfs shows the network devices of the network namespace of the
+    /// mounting process, which is why it can't be mounted in a user namespace
+    /// without a network namespace of its own. Instead, the sysfs of the host
+    /// is bound with the options of the mount, but without the filesystems
+    /// mounted below it, e.g. the cgroup filesystems, which are mounted for
+    /// the cgroup setup of the container by the following mounts of the spec.
+    fn mount_host_sysfs(
+        &self,
+        sysfs_mount: &SpecMount,
+        options: &MountOptions,
+        mount_option_config: &MountOptionConfig,
+    ) -> Result<()> {
+        tracing::debug!("binding the sysfs of the host for the host network namespace");
+        let bind_mount = SpecMountBuilder::default()
+            .typ("bind")
+            .source("/sys")
+            .destination(sysfs_mount.destination())
+            .options(Vec::new())
+            .build()?;
+        let mut mount_option_config = mount_option_config.clone();
+        mount_option_config.flags |= MsFlags::MS_BIND;
+        mount_option_config.flags &= !MsFlags::MS_REC;
+        self.mount_into_container(&bind_mount, options.root, &mount_option_config, None, None)
+    }
+
+    /// Mounts proc with the hardening options of the mount. A proc mounted in
+    /// a joined pid namespace may be handed an existing superblock, which
+    /// ignores the options of the new mount, so they are applied by
+    /// remounting it. If that fails, the mount is detached again rather than
+    /// leaving the processes of the namespace visible.
+    fn mount_proc(
+        &self,
+        proc_mount: &SpecMount,
+        options: &MountOptions,
+        mount_option_config: &MountOptionConfig,
+    ) -> Result<()> {
+        let hardening = proc::hardening_options(&mount_option_config.data)?;
+        self.mount_into_container(
+            proc_mount,
+            options.root,
+            mount_option_config,
+            options.label,
+            options.idmapped_mount,
+        )?;
+        if hardening.is_empty() || !options.joins_pid_ns {
+            return Ok(());
+        }
+
+        if proc::shares_superblock()? {
+            tracing::warn!(
+                ?hardening,
+                "the proc options apply to every proc of the joined pid namespace"
+            );
+        }
+        let dest =
+            safe_path::scoped_join(options.root, proc_mount.destination()).map_err(|err| {
+                tracing::error!(
+                    "failed to join rootfs {:?} with mount destination {:?}: {}",
+                    options.root,
+                    proc_mount.destination(),
+                    err
+                );
+                MountError::Other(err.into())
+            })?;
+        let flags = mount_option_config.flags | MsFlags::MS_REMOUNT;
+        if let Err(err) = self
+            .syscall
+            .mount(None, &dest, None, flags, Some(&hardening))
+        {
+            tracing::error!("failed to remount {:?} with {}: {}", dest, hardening, err);
+            if let Err(err) = self.syscall.umount2(&dest, MntFlags::MNT_DETACH) {
+                tracing::error!("failed to detach {:?}: {}", dest, err);
+            }
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
     /// Make parent mount of rootfs private if it was shared, which is required by pivot_root.
     /// It also makes sure following bind mount does not propagate in other namespaces.
     pub fn make_parent_mount_private(&self, rootfs: &Path) -> Result<Option<MountInfo>> {
@@ -483,6 +622,7 @@ impl Mount {
         rootfs: &Path,
         mount_option_config: &MountOptionConfig,
         label: Option<&str>,
+        idmapped_mount: Option<BorrowedFd>,
     ) -> Result<()> {
         let typ = m.typ().as_deref();
         let mut d = mount_option_config.data.to_string();
@@ -513,6 +653,12 @@ impl Mount {
                 tracing::error!("failed to canonicalize {:?}: {}", source, err);
                 err
             })?;
+            if let (Some(label), Some(relabel)) = (label, relabel_type(m)) {
+                selinux::relabel(&src, label, relabel).map_err(|err| {
+                    tracing::error!("failed to relabel {:?}: {}", src, err);
+                    err
+                })?;
+            }
             let dir = if src.is_file() {
                 Path::new(&dest).parent().unwrap()
             } else {
@@ -546,7 +692,34 @@ impl Mount {
             PathBuf::from(source)
         };
 
-        if let Err(err) =
+        // the directory stays reachable through the fd once the tmpfs has
+        // been mounted over it
+        let copyup_dir = if tmpcopyup(m) {
+            Some(Dir::open(
+                dest,
+                OFlag::O_DIRECTORY | OFlag::O_RDONLY | OFlag::O_CLOEXEC,
+                Mode::empty(),
+            )?)
+        } else {
+            None
+        };
+
+        if let Some(fd) = idmapped_mount {
+            self.syscall
+                .move_mount(
+                    fd.as_raw_fd(),
+                    Path::new(""),
+                    libc::AT_FDCWD,
+                    dest,
+                    linux::MOVE_MOUNT_F_EMPTY_PATH,
+                )
+                .map_err(|err| {
+                    tracing::error!("failed to attach idmapped mount to {dest:?}: {err}");
+                    err
+                })?;
+        } else if self.mount_fs_context(&src, dest, typ, mount_option_config, &d)? {
+            tracing::debug!("mounted {dest:?} with the new mount API");
+        } else if let Err(err) =
             self.syscall
                 .mount(Some(&*src), dest, typ, mount_option_config.flags, Some(&*d))
         {
@@ -571,15 +744,18 @@ impl Mount {
                 })?;
         }
 
+        if let Some(dir) = copyup_dir {
+            let underlying = PathBuf::from(format!("/proc/self/fd/{}", dir.as_raw_fd()));
+            copy_dir_contents(&underlying, dest).map_err(|err| {
+                tracing::error!("failed to copy the contents of {dest:?} into the tmpfs: {err}");
+                err
+            })?;
+        }
+
         if typ == Some("bind")
-            && mount_option_config.flags.intersects(
-                !(MsFlags::MS_REC
-                    | MsFlags::MS_REMOUNT
-                    | MsFlags::MS_BIND
-                    | MsFlags::MS_PRIVATE
-                    | MsFlags::MS_SHARED
-                    | MsFlags::MS_SLAVE),
-            )
+            && mount_option_config
+                .flags
+                .intersects(!(MsFlags::MS_REC | MsFlags::MS_REMOUNT | MsFlags::MS_BIND))
         {
             self.syscall
                 .mount(
@@ -595,6 +771,21 @@ impl Mount {
                 })?;
         }
 
+        // the propagation type can only be changed on its own, once the mount
+        // exists and its flags have been applied
+        if !mount_option_config.propagation.is_empty() {
+            self.syscall
+                .mount(None, dest, None, mount_option_config.propagation, None)
+                .map_err(|err| {
+                    tracing::error!(
+                        "failed to change the propagation type of {:?}: {}",
+                        dest,
+                        err
+                    );
+                    err
+                })?;
+        }
+
         if let Some(mount_attr) = &mount_option_config.rec_attr {
             let open_dir = Dir::open(dest, OFlag::O_DIRECTORY, Mode::empty())?;
             let dir_fd_pathbuf = PathBuf::from(format!("/proc/self/fd/{}", open_dir.as_raw_fd()));
@@ -609,6 +800,44 @@ impl Mount {
 
         Ok(())
     }
+
+    /// Mounts a new filesystem with the new mount API if the kernel supports
+    /// it. Returns false if the filesystem has to be mounted with mount(2).
+    fn mount_fs_context(
+        &self,
+        src: &Path,
+        dest: &Path,
+        typ: Option<&str>,
+        mount_option_config: &MountOptionConfig,
+        data: &str,
+    ) -> Result<bool> {
+        let fstype = match typ {
+            Some(fstype) if self.fs_context && fstype != "bind" => fstype,
+            _ => return Ok(false),
+        };
+        if mount_option_config.flags.contains(MsFlags::MS_BIND) {
+            return Ok(false);
+        }
+
+        match fsmount::mount(
+            self.syscall.as_ref(),
+            src,
+            dest,
+            fstype,
+            mount_option_config.flags,
+            data,
+        ) {
+            Ok(()) => Ok(true),
+            Err(err) if err.can_fall_back() => {
+                tracing::debug!("falling back to mount(2) for {dest:?}: {err}");
+                Ok(false)
+            }
+            Err(err) => {
+                tracing::error!("failed to mount {src:?} to {dest:?}: {err}");
+                Err(err.into())
+            }
+        }
+    }
 }
 
 /// Find parent mount of rootfs in given mount infos
@@ -631,11 +860,64 @@ pub fn find_parent_mount(
 mod tests {
     #[cfg(feature = "v1")]
     use std::fs;
+    use std::fs::File;
+    use std::os::fd::AsFd;
 
     use anyhow::{Context, Ok, Result};
 
     use super::*;
-    use crate::syscall::test::{MountArgs, TestHelperSyscall};
+    use crate::syscall::test::{MountArgs, MoveMountArgs, TestHelperSyscall};
+
+    #[test]
+    fn test_mount_with_fs_context() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mut m = Mount::new();
+        m.fs_context = true;
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        syscall.enable_fs_context();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/dev/pts"))
+            .typ("devpts")
+            .source(PathBuf::from("devpts"))
+            .options(vec!["nosuid".to_string(), "mode=0620".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount)?;
+
+        m.mount_into_container(
+            mount,
+            tmp_dir.path(),
+            &mount_option_config,
+            Some("system_u:object_r:container_file_t:s0:c1,c2"),
+            None,
+        )?;
+
+        assert!(syscall.get_mount_args().is_empty());
+        assert_eq!(syscall.get_fsopen_args(), ["devpts"]);
+        let options: Vec<_> = syscall
+            .get_fsconfig_args()
+            .into_iter()
+            .filter_map(|args| args.value)
+            .collect();
+        assert_eq!(
+            options,
+            [
+                "devpts",
+                "0620",
+                "0666",
+                "system_u:object_r:container_file_t:s0:c1,c2"
+            ]
+        );
+        assert_eq!(syscall.get_fsmount_args(), [linux::MOUNT_ATTR_NOSUID]);
+        assert_eq!(
+            syscall.get_move_mount_args()[0].to_pathname,
+            tmp_dir.path().join("dev/pts")
+        );
+        Ok(())
+    }
 
     #[test]
     fn test_mount_to_container() -> Result<()> {
@@ -662,7 +944,8 @@ mod tests {
                     mount,
                     tmp_dir.path(),
                     &mount_option_config,
-                    Some("defaults")
+                    Some("defaults"),
+                    None,
                 )
                 .is_ok());
 
@@ -700,7 +983,7 @@ mod tests {
                 .open(tmp_dir.path().join("null"))?;
 
             assert!(m
-                .mount_into_container(mount, tmp_dir.path(), &mount_option_config, None)
+                .mount_into_container(mount, tmp_dir.path(), &mount_option_config, None, None)
                 .is_ok());
 
             let want = vec![
@@ -733,6 +1016,101 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_mount_propagation() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        std::fs::create_dir(tmp_dir.path().join("volume"))?;
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/volume"))
+            .typ("bind")
+            .source(tmp_dir.path().join("volume"))
+            .options(vec![
+                "rbind".to_string(),
+                "nosuid".to_string(),
+                "rslave".to_string(),
+            ])
+            .build()?;
+        let mount_option_config = parse_mount(mount)?;
+
+        m.mount_into_container(mount, tmp_dir.path(), &mount_option_config, None, None)?;
+
+        let flags = MsFlags::MS_BIND | MsFlags::MS_REC | MsFlags::MS_NOSUID;
+        let volume = tmp_dir.path().join("volume");
+        let want = vec![
+            MountArgs {
+                source: Some(volume.canonicalize()?),
+                target: volume.clone(),
+                fstype: Some("bind".to_string()),
+                flags,
+                data: Some("".to_string()),
+            },
+            // the flags are applied by remounting, before the propagation type
+            MountArgs {
+                source: Some(volume.clone()),
+                target: volume.clone(),
+                fstype: None,
+                flags: flags | MsFlags::MS_REMOUNT,
+                data: None,
+            },
+            MountArgs {
+                source: None,
+                target: volume,
+                fstype: None,
+                flags: MsFlags::MS_SLAVE | MsFlags::MS_REC,
+                data: None,
+            },
+        ];
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(want, got);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_idmapped_to_container() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(tmp_dir.path().join("data"))
+            .options(vec!["rbind".to_string(), "idmap".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount)?;
+        std::fs::create_dir(tmp_dir.path().join("data"))?;
+        let tree = File::open(tmp_dir.path())?;
+
+        m.mount_into_container(
+            mount,
+            tmp_dir.path(),
+            &mount_option_config,
+            None,
+            Some(tree.as_fd()),
+        )?;
+
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        assert!(syscall.get_mount_args().is_empty());
+        assert_eq!(
+            vec![MoveMountArgs {
+                from_dirfd: tree.as_raw_fd(),
+                to_pathname: tmp_dir.path().join("data"),
+                flags: linux::MOVE_MOUNT_F_EMPTY_PATH,
+            }],
+            syscall.get_move_mount_args()
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_make_parent_mount_private() -> Result<()> {
         let tmp_dir = tempfile::tempdir()?;
@@ -783,6 +1161,9 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: true,
+            host_network: false,
+            joins_pid_ns: false,
+            idmapped_mount: None,
         };
 
         let subsystem_name = "cpu";
@@ -838,6 +1219,9 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: false,
+            host_network: false,
+            joins_pid_ns: false,
+            idmapped_mount: None,
         };
 
         let subsystem_name = "cpu";
@@ -899,6 +1283,9 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: true,
+            host_network: false,
+            joins_pid_ns: false,
+            idmapped_mount: None,
         };
 
         let mounter = Mount::new();
@@ -954,6 +1341,126 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_mount_host_sysfs() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let sysfs_mount = SpecMountBuilder::default()
+            .destination("/sys")
+            .source("sysfs")
+            .typ("sysfs")
+            .options(vec![
+                "nosuid".to_owned(),
+                "noexec".to_owned(),
+                "nodev".to_owned(),
+                "ro".to_owned(),
+            ])
+            .build()?;
+        let mount_opts = MountOptions {
+            root: tmp.path(),
+            label: None,
+            cgroup_ns: false,
+            host_network: true,
+            joins_pid_ns: false,
+            idmapped_mount: None,
+        };
+
+        let mounter = Mount::new();
+        mounter.setup_mount(&sysfs_mount, &mount_opts)?;
+
+        let flags =
+            MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC | MsFlags::MS_NODEV | MsFlags::MS_RDONLY;
+        let got = mounter
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        let want = vec![
+            MountArgs {
+                source: Some(PathBuf::from("/sys")),
+                target: tmp.path().join("sys"),
+                fstype: Some("bind".to_owned()),
+                flags: flags | MsFlags::MS_BIND,
+                data: Some(String::new()),
+            },
+            MountArgs {
+                source: Some(tmp.path().join("sys")),
+                target: tmp.path().join("sys"),
+                fstype: None,
+                flags: flags | MsFlags::MS_BIND | MsFlags::MS_REMOUNT,
+                data: None,
+            },
+        ];
+        assert_eq!(want, got);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_proc_in_joined_pid_ns() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let proc_mount = SpecMountBuilder::default()
+            .destination("/proc")
+            .source("proc")
+            .typ("proc")
+            .options(vec!["nosuid".to_owned(), "hidepid=2".to_owned()])
+            .build()?;
+        let mount_opts = MountOptions {
+            root: tmp.path(),
+            label: None,
+            cgroup_ns: false,
+            host_network: false,
+            joins_pid_ns: true,
+            idmapped_mount: None,
+        };
+
+        let mounter = Mount::new();
+        mounter.setup_mount(&proc_mount, &mount_opts)?;
+
+        let syscall = mounter
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        let want = vec![
+            MountArgs {
+                source: Some(PathBuf::from("proc")),
+                target: tmp.path().join("proc"),
+                fstype: Some("proc".to_owned()),
+                flags: MsFlags::MS_NOSUID,
+                data: Some("hidepid=2".to_owned()),
+            },
+            MountArgs {
+                source: None,
+                target: tmp.path().join("proc"),
+                fstype: None,
+                flags: MsFlags::MS_NOSUID | MsFlags::MS_REMOUNT,
+                data: Some("hidepid=2".to_owned()),
+            },
+        ];
+        assert_eq!(want, syscall.get_mount_args());
+        assert!(syscall.get_umount_args().is_empty());
+
+        // a new pid namespace gets a proc instance of its own
+        let mounter = Mount::new();
+        mounter.setup_mount(
+            &proc_mount,
+            &MountOptions {
+                joins_pid_ns: false,
+                ..mount_opts
+            },
+        )?;
+        let got = mounter
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(got.len(), 1);
+
+        Ok(())
+    }
+
     #[test]
     #[cfg(feature = "v2")]
     fn test_mount_cgroup_v2() -> Result<()> {
@@ -972,6 +1479,9 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: true,
+            host_network: false,
+            joins_pid_ns: false,
+            idmapped_mount: None,
         };
 
         let mounter = Mount::new();
@@ -982,6 +1492,7 @@ mod tests {
             flags,
             data: String::new(),
             rec_attr: None,
+            propagation: MsFlags::empty(),
         };
         mounter
             .mount_cgroup_v2(&spec_cgroup_mount, &mount_opts, &mount_option_config)
@@ -0,0 +1,237 @@
+//! Overlay filesystems are assembled from a stack of read-only lower
+//! directories and an optional writable upper directory, which needs a work
+//! directory on the same filesystem.
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use procfs::KernelVersion;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OverlayError {
+    #[error("overlay mount requires the lowerdir option")]
+    MissingLowerDir,
+    #[error("overlay lowerdir {0:?} does not exist")]
+    LowerDirNotFound(PathBuf),
+    #[error("overlay {0} requires {1} to be set as well")]
+    MissingOption(&'static str, &'static str),
+    #[error("overlay upperdir {upper:?} and workdir {work:?} must be separate directories on the same filesystem")]
+    InvalidWorkDir { upper: PathBuf, work: PathBuf },
+    #[error("failed to create overlay directory {path:?}")]
+    CreateDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("overlay option {option} requires at least kernel {version}")]
+    UnsupportedOption {
+        option: String,
+        version: &'static str,
+    },
+    #[error("failed to determine kernel version")]
+    KernelVersion(#[from] procfs::ProcError),
+}
+
+type Result<T> = std::result::Result<T, OverlayError>;
+
+/// Overlay options that are only known to newer kernels, together with the
+/// version that introduced them. Older kernels behave as if the features were
+/// turned off, so disabling them can be dropped there.
+const VERSIONED_OPTIONS: &[(&str, (u8, u8), &str)] = &[
+    ("redirect_dir", (4, 10), "4.10"),
+    ("index", (4, 13), "4.13"),
+    ("metacopy", (4, 19), "4.19"),
+    ("volatile", (5, 10), "5.10"),
+    ("userxattr", (5, 11), "5.11"),
+];
+
+/// Validates the data of an overlay mount and creates the upper and work
+/// directories if they are missing. Returns the data to pass to mount(2),
+/// without options that the running kernel doesn't know about but which don't
+/// change its behavior.
+pub fn prepare_overlay(data: &str) -> Result<String> {
+    prepare_overlay_for_kernel(data, &KernelVersion::current()?)
+}
+
+fn prepare_overlay_for_kernel(data: &str, kernel: &KernelVersion) -> Result<String> {
+    let mut lower = None;
+    let mut upper = None;
+    let mut work = None;
+    let mut options = Vec::new();
+
+    for option in data.split(',').filter(|o| !o.is_empty()) {
+        let (key, value) = match option.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (option, None),
+        };
+        match key {
+            "lowerdir" => lower = value,
+            "upperdir" => upper = value.map(PathBuf::from),
+            "workdir" => work = value.map(PathBuf::from),
+            _ => {}
+        }
+
+        if let Some((_, (major, minor), version)) =
+            VERSIONED_OPTIONS.iter().find(|(name, _, _)| *name == key)
+        {
+            if *kernel < KernelVersion::new(*major, *minor, 0) {
+                if value == Some("off") {
+                    tracing::debug!(?option, "dropping overlay option unknown to the kernel");
+                    continue;
+                }
+                return Err(OverlayError::UnsupportedOption {
+                    option: option.to_owned(),
+                    version,
+                });
+            }
+        }
+
+        options.push(option);
+    }
+
+    let lower = lower.ok_or(OverlayError::MissingLowerDir)?;
+    // the lower directories are separated by colons, colons within a path
+    // are escaped with a backslash
+    for dir in split_lower_dirs(lower) {
+        if !Path::new(&dir).exists() {
+            return Err(OverlayError::LowerDirNotFound(PathBuf::from(dir)));
+        }
+    }
+
+    match (upper, work) {
+        (Some(upper), Some(work)) => {
+            for dir in [&upper, &work] {
+                fs::create_dir_all(dir).map_err(|err| OverlayError::CreateDir {
+                    path: dir.to_owned(),
+                    source: err,
+                })?;
+            }
+
+            let same_fs = match (fs::metadata(&upper), fs::metadata(&work)) {
+                (Ok(u), Ok(w)) => u.dev() == w.dev(),
+                _ => false,
+            };
+            if !same_fs || upper.starts_with(&work) || work.starts_with(&upper) {
+                return Err(OverlayError::InvalidWorkDir { upper, work });
+            }
+        }
+        (Some(_), None) => return Err(OverlayError::MissingOption("upperdir", "workdir")),
+        (None, Some(_)) => return Err(OverlayError::MissingOption("workdir", "upperdir")),
+        (None, None) => {}
+    }
+
+    Ok(options.join(","))
+}
+
+fn split_lower_dirs(lower: &str) -> Vec<String> {
+    let mut dirs = vec![String::new()];
+    let mut chars = lower.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    dirs.last_mut().unwrap().push(escaped);
+                }
+            }
+            ':' => dirs.push(String::new()),
+            c => dirs.last_mut().unwrap().push(c),
+        }
+    }
+
+    dirs.into_iter().filter(|d| !d.is_empty()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn kernel(major: u8, minor: u8) -> KernelVersion {
+        KernelVersion::new(major, minor, 0)
+    }
+
+    #[test]
+    fn test_prepare_overlay() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let (lower1, lower2) = (tmp.path().join("l1"), tmp.path().join("l2"));
+        fs::create_dir(&lower1)?;
+        fs::create_dir(&lower2)?;
+        let upper = tmp.path().join("upper");
+        let work = tmp.path().join("work");
+
+        let data = format!(
+            "lowerdir={}:{},upperdir={},workdir={},index=off",
+            lower1.display(),
+            lower2.display(),
+            upper.display(),
+            work.display()
+        );
+        let prepared = prepare_overlay_for_kernel(&data, &kernel(6, 1))?;
+        assert_eq!(prepared, data);
+        assert!(upper.is_dir());
+        assert!(work.is_dir());
+
+        // older kernels don't know about the index feature
+        let prepared = prepare_overlay_for_kernel(&data, &kernel(4, 9))?;
+        assert!(!prepared.contains("index"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepare_overlay_invalid() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let lower = tmp.path().join("lower");
+        fs::create_dir(&lower)?;
+        let lower = format!("lowerdir={}", lower.display());
+
+        assert!(matches!(
+            prepare_overlay_for_kernel("upperdir=/upper,workdir=/work", &kernel(6, 1)),
+            Err(OverlayError::MissingLowerDir)
+        ));
+        assert!(matches!(
+            prepare_overlay_for_kernel(
+                &format!("lowerdir={}", tmp.path().join("missing").display()),
+                &kernel(6, 1)
+            ),
+            Err(OverlayError::LowerDirNotFound(_))
+        ));
+        assert!(matches!(
+            prepare_overlay_for_kernel(
+                &format!("{lower},upperdir={}", tmp.path().join("upper").display()),
+                &kernel(6, 1)
+            ),
+            Err(OverlayError::MissingOption("upperdir", "workdir"))
+        ));
+        assert!(matches!(
+            prepare_overlay_for_kernel(
+                &format!(
+                    "{lower},upperdir={},workdir={}",
+                    tmp.path().join("upper").display(),
+                    tmp.path().join("upper/work").display()
+                ),
+                &kernel(6, 1)
+            ),
+            Err(OverlayError::InvalidWorkDir { .. })
+        ));
+        assert!(matches!(
+            prepare_overlay_for_kernel(&format!("{lower},userxattr"), &kernel(5, 4)),
+            Err(OverlayError::UnsupportedOption {
+                version: "5.11",
+                ..
+            })
+        ));
+        assert!(prepare_overlay_for_kernel(&format!("{lower},userxattr"), &kernel(5, 11)).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_lower_dirs() {
+        assert_eq!(
+            split_lower_dirs("/a:/b\\:c::/d"),
+            vec!["/a".to_owned(), "/b:c".to_owned(), "/d".to_owned()]
+        );
+    }
+}
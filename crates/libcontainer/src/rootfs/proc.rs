@@ -0,0 +1,133 @@
+//! A procfs can be hardened with the hidepid option, which hides the
+//! processes of other users, and the subset=pid option, which hides
+//! everything but the process directories.
+
+use procfs::KernelVersion;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProcMountError {
+    #[error("invalid value {value:?} of proc option {option}")]
+    InvalidValue { option: &'static str, value: String },
+    #[error("proc option {option} requires at least kernel {version}")]
+    UnsupportedOption {
+        option: String,
+        version: &'static str,
+    },
+    #[error("failed to determine kernel version")]
+    KernelVersion(#[from] procfs::ProcError),
+}
+
+type Result<T> = std::result::Result<T, ProcMountError>;
+
+/// Since 5.8, each mount of proc is an instance of its own with its own
+/// options, which also introduced the named hidepid values and subset=pid.
+/// Before, all mounts of a pid namespace share one superblock.
+const PROC_INSTANCES: (u8, u8) = (5, 8);
+
+/// Validates the hardening options within the data of a proc mount and
+/// returns them, joined like the data, for remounting.
+pub fn hardening_options(data: &str) -> Result<String> {
+    hardening_options_for_kernel(data, &KernelVersion::current()?)
+}
+
+/// Whether all mounts of proc in a pid namespace share their options
+pub fn shares_superblock() -> Result<bool> {
+    Ok(KernelVersion::current()? < instances_kernel())
+}
+
+fn instances_kernel() -> KernelVersion {
+    KernelVersion::new(PROC_INSTANCES.0, PROC_INSTANCES.1, 0)
+}
+
+fn hardening_options_for_kernel(data: &str, kernel: &KernelVersion) -> Result<String> {
+    let mut options = Vec::new();
+
+    for option in data.split(',').filter(|o| !o.is_empty()) {
+        let (key, value) = match option.split_once('=') {
+            Some((key, value)) => (key, value),
+            None => (option, ""),
+        };
+        let needs_instances = match key {
+            "hidepid" => match value {
+                "0" | "1" | "2" => false,
+                "4" | "off" | "noaccess" | "invisible" | "ptraceable" => true,
+                _ => {
+                    return Err(ProcMountError::InvalidValue {
+                        option: "hidepid",
+                        value: value.to_owned(),
+                    })
+                }
+            },
+            "subset" if value == "pid" => true,
+            "subset" => {
+                return Err(ProcMountError::InvalidValue {
+                    option: "subset",
+                    value: value.to_owned(),
+                })
+            }
+            _ => continue,
+        };
+
+        if needs_instances && *kernel < instances_kernel() {
+            return Err(ProcMountError::UnsupportedOption {
+                option: option.to_owned(),
+                version: "5.8",
+            });
+        }
+        options.push(option);
+    }
+
+    Ok(options.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn kernel(major: u8, minor: u8) -> KernelVersion {
+        KernelVersion::new(major, minor, 0)
+    }
+
+    #[test]
+    fn test_hardening_options() -> Result<()> {
+        assert_eq!(hardening_options_for_kernel("", &kernel(6, 1))?, "");
+        assert_eq!(
+            hardening_options_for_kernel("gid=10,hidepid=2", &kernel(4, 19))?,
+            "hidepid=2"
+        );
+        assert_eq!(
+            hardening_options_for_kernel("hidepid=invisible,subset=pid", &kernel(5, 8))?,
+            "hidepid=invisible,subset=pid"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hardening_options_invalid() {
+        assert!(matches!(
+            hardening_options_for_kernel("hidepid=3", &kernel(6, 1)),
+            Err(ProcMountError::InvalidValue {
+                option: "hidepid",
+                ..
+            })
+        ));
+        assert!(matches!(
+            hardening_options_for_kernel("subset=sys", &kernel(6, 1)),
+            Err(ProcMountError::InvalidValue {
+                option: "subset",
+                ..
+            })
+        ));
+        assert!(matches!(
+            hardening_options_for_kernel("subset=pid", &kernel(5, 4)),
+            Err(ProcMountError::UnsupportedOption { version: "5.8", .. })
+        ));
+        assert!(matches!(
+            hardening_options_for_kernel("hidepid=invisible", &kernel(5, 4)),
+            Err(ProcMountError::UnsupportedOption { .. })
+        ));
+    }
+}
@@ -1,13 +1,14 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::os::fd::{AsFd, OwnedFd};
 use std::path::Path;
 
 use nix::mount::MsFlags;
-use oci_spec::runtime::{Linux, Spec};
+use oci_spec::runtime::{Linux, LinuxNamespaceType, Spec};
 
-use super::device::Device;
+use super::device::{device_creation, Device};
 use super::mount::{Mount, MountOptions};
 use super::symlink::Symlink;
-use super::utils::default_devices;
+use super::utils::{default_devices, propagation_flags};
 use super::{Result, RootfsError};
 use crate::error::MissingSpecError;
 use crate::syscall::syscall::create_syscall;
@@ -35,20 +36,28 @@ impl RootFS {
         &self,
         spec: &Spec,
         rootfs: &Path,
-        bind_devices: bool,
+        in_user_ns: bool,
         cgroup_ns: bool,
+        idmapped_mounts: &HashMap<usize, OwnedFd>,
     ) -> Result<()> {
         tracing::debug!(?rootfs, "prepare rootfs");
-        let mut flags = MsFlags::MS_REC;
         let linux = spec.linux().as_ref().ok_or(MissingSpecError::Linux)?;
 
-        match linux.rootfs_propagation().as_deref() {
-            Some("shared") => flags |= MsFlags::MS_SHARED,
-            Some("private") => flags |= MsFlags::MS_PRIVATE,
-            Some("slave" | "unbindable") | None => flags |= MsFlags::MS_SLAVE,
-            Some(unknown) => {
-                return Err(RootfsError::UnknownRootfsPropagation(unknown.to_string()));
-            }
+        // The mounts of the container are set up as slaves, unless they should
+        // be shared with or hidden from the host. An unbindable root is only
+        // made so once it has become the root of the container.
+        let propagation = match linux.rootfs_propagation().as_deref() {
+            Some(propagation) => propagation_flags(propagation)
+                .ok_or_else(|| RootfsError::UnknownRootfsPropagation(propagation.to_string()))?,
+            None => MsFlags::MS_SLAVE,
+        };
+        let mut flags = MsFlags::MS_REC;
+        if propagation.contains(MsFlags::MS_SHARED) {
+            flags |= MsFlags::MS_SHARED;
+        } else if propagation.contains(MsFlags::MS_PRIVATE) {
+            flags |= MsFlags::MS_PRIVATE;
+        } else {
+            flags |= MsFlags::MS_SLAVE;
         }
 
         self.syscall
@@ -81,15 +90,32 @@ impl RootFS {
                 err
             })?;
 
+        let host_network = !linux.namespaces().as_ref().map_or(false, |namespaces| {
+            namespaces
+                .iter()
+                .any(|ns| ns.typ() == LinuxNamespaceType::Network)
+        });
+        let joins_pid_ns = !linux.namespaces().as_ref().map_or(false, |namespaces| {
+            namespaces
+                .iter()
+                .any(|ns| ns.typ() == LinuxNamespaceType::Pid && ns.path().is_none())
+        });
         let global_options = MountOptions {
             root: rootfs,
             label: linux.mount_label().as_deref(),
             cgroup_ns,
+            host_network,
+            joins_pid_ns,
+            idmapped_mount: None,
         };
 
         if let Some(mounts) = spec.mounts() {
-            for mount in mounts {
-                mounter.setup_mount(mount, &global_options)?;
+            for (index, mount) in mounts.iter().enumerate() {
+                let options = MountOptions {
+                    idmapped_mount: idmapped_mounts.get(&index).map(|fd| fd.as_fd()),
+                    ..global_options
+                };
+                mounter.setup_mount(mount, &options)?;
             }
         }
 
@@ -98,6 +124,7 @@ impl RootFS {
         symlinker.setup_default_symlinks(rootfs)?;
 
         let devicer = Device::new();
+        let creation = device_creation(spec.annotations().as_ref());
         if let Some(added_devices) = linux.devices() {
             let mut path_set = HashSet::new();
             let devices = default_devices();
@@ -105,9 +132,14 @@ impl RootFS {
                 path_set.insert(d.path());
             });
             let default = devices.iter().filter(|d| !path_set.contains(d.path()));
-            devicer.create_devices(rootfs, added_devices.iter().chain(default), bind_devices)
+            devicer.create_devices(
+                rootfs,
+                added_devices.iter().chain(default),
+                in_user_ns,
+                &creation,
+            )
         } else {
-            devicer.create_devices(rootfs, &default_devices(), bind_devices)
+            devicer.create_devices(rootfs, &default_devices(), in_user_ns, &creation)
         }?;
 
         symlinker.setup_ptmx(rootfs)?;
@@ -116,12 +148,11 @@ impl RootFS {
 
     /// Change propagation type of rootfs as specified in spec.
     pub fn adjust_root_mount_propagation(&self, linux: &Linux) -> Result<()> {
-        let rootfs_propagation = linux.rootfs_propagation().as_deref();
-        let flags = match rootfs_propagation {
-            Some("shared") => Some(MsFlags::MS_SHARED),
-            Some("unbindable") => Some(MsFlags::MS_UNBINDABLE),
-            _ => None,
-        };
+        let flags = linux
+            .rootfs_propagation()
+            .as_deref()
+            .and_then(propagation_flags)
+            .filter(|flags| flags.intersects(MsFlags::MS_SHARED | MsFlags::MS_UNBINDABLE));
 
         if let Some(flags) = flags {
             self.syscall
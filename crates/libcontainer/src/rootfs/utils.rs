@@ -1,11 +1,17 @@
-use std::path::PathBuf;
+use std::os::unix::fs::{symlink, MetadataExt};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::{fs, io};
 
+use nix::errno::Errno;
+use nix::fcntl::AtFlags;
 use nix::mount::MsFlags;
 use nix::sys::stat::SFlag;
+use nix::unistd::{fchownat, Gid, Uid};
 use oci_spec::runtime::{LinuxDevice, LinuxDeviceBuilder, LinuxDeviceType, Mount};
 
 use super::mount::MountError;
+use crate::selinux::Relabel;
 use crate::syscall::linux::{self, MountRecursive};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -18,6 +24,107 @@ pub struct MountOptionConfig {
 
     /// RecAttr represents mount properties to be applied recursively.
     pub rec_attr: Option<linux::MountAttr>,
+
+    /// Propagation type, which can't be combined with other flags and is
+    /// changed once the mount exists.
+    pub propagation: MsFlags,
+}
+
+/// How the mount is to be idmapped with the user namespace of the container
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdmapType {
+    /// Only the top-level mount is idmapped
+    Idmap,
+    /// The whole mount tree is idmapped
+    RecursiveIdmap,
+}
+
+impl IdmapType {
+    /// Flags to pass to open_tree(2) and mount_setattr(2) for this type
+    pub fn recursive_flag(self) -> u32 {
+        match self {
+            IdmapType::Idmap => 0,
+            IdmapType::RecursiveIdmap => linux::AT_RECURSIVE,
+        }
+    }
+}
+
+/// Returns the idmap type requested by the `idmap` and `ridmap` options of the mount
+pub fn idmap_type(m: &Mount) -> Option<IdmapType> {
+    m.options()
+        .as_ref()?
+        .iter()
+        .rev()
+        .find_map(|o| match o.as_str() {
+            "idmap" => Some(IdmapType::Idmap),
+            "ridmap" => Some(IdmapType::RecursiveIdmap),
+            _ => None,
+        })
+}
+
+/// Returns how the source of the mount is to be relabeled, as requested by the `z` and `Z` options
+pub fn relabel_type(m: &Mount) -> Option<Relabel> {
+    m.options()
+        .as_ref()?
+        .iter()
+        .rev()
+        .find_map(|o| match o.as_str() {
+            "z" => Some(Relabel::Shared),
+            "Z" => Some(Relabel::Private),
+            _ => None,
+        })
+}
+
+/// Returns whether the contents of the destination are to be copied into a
+/// tmpfs mounted over it, as requested by the `tmpcopyup` option like in crun.
+/// Images often ship content in directories like /run, which would be hidden
+/// by the tmpfs otherwise.
+pub fn tmpcopyup(m: &Mount) -> bool {
+    m.typ().as_deref() == Some("tmpfs")
+        && m.options()
+            .as_ref()
+            .map_or(false, |options| options.iter().any(|o| o == "tmpcopyup"))
+}
+
+/// Copies the contents of the directory `from` into `to` recursively,
+/// keeping the modes and owners of the entries. Devices, fifos and sockets
+/// are skipped.
+pub fn copy_dir_contents(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let source = entry.path();
+        let target = to.join(entry.file_name());
+        let metadata = fs::symlink_metadata(&source)?;
+        let file_type = metadata.file_type();
+        if file_type.is_dir() {
+            fs::create_dir(&target)?;
+            copy_dir_contents(&source, &target)?;
+        } else if file_type.is_symlink() {
+            symlink(fs::read_link(&source)?, &target)?;
+        } else if file_type.is_file() {
+            fs::copy(&source, &target)?;
+        } else {
+            tracing::debug!(?source, "not copying special file");
+            continue;
+        }
+
+        if !file_type.is_symlink() {
+            fs::set_permissions(&target, metadata.permissions())?;
+        }
+        match fchownat(
+            None,
+            &target,
+            Some(Uid::from_raw(metadata.uid())),
+            Some(Gid::from_raw(metadata.gid())),
+            AtFlags::AT_SYMLINK_NOFOLLOW,
+        ) {
+            // the owner is not mapped into the user namespace
+            Ok(()) | Err(Errno::EINVAL) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(())
 }
 
 pub fn default_devices() -> Vec<LinuxDevice> {
@@ -82,8 +189,83 @@ pub fn to_sflag(dev_type: LinuxDeviceType) -> SFlag {
     }
 }
 
+/// Destinations of tmpfs mounts which are usually owned by root and not
+/// writable by everyone, unlike the mode of 1777 tmpfs has by default
+const RESTRICTED_TMPFS_DESTINATIONS: &[&str] = &["/dev", "/run", "/var/run"];
+
+/// Returns the value of a filesystem option given as `key=value`
+pub fn data_option<'a>(data: &[&'a str], key: &str) -> Option<&'a str> {
+    data.iter().rev().find_map(|option| {
+        option
+            .strip_prefix(key)
+            .and_then(|value| value.strip_prefix('='))
+    })
+}
+
+/// Filesystem options which are needed for the mount to work as expected in
+/// a container, but which are left out by some specs
+fn default_data(m: &Mount, data: &[&str]) -> Vec<&'static str> {
+    let mut defaults = Vec::new();
+    match m.typ().as_deref() {
+        Some("tmpfs") => {
+            let restricted = RESTRICTED_TMPFS_DESTINATIONS
+                .iter()
+                .any(|dest| m.destination() == Path::new(dest));
+            if restricted && data_option(data, "mode").is_none() {
+                defaults.push("mode=755");
+            }
+        }
+        Some("devpts") => {
+            // /dev/ptmx is a symlink to pts/ptmx, which only belongs to the
+            // container with a new instance and is only usable with a mode
+            if !data.contains(&"newinstance") {
+                defaults.push("newinstance");
+            }
+            if data_option(data, "ptmxmode").is_none() {
+                defaults.push("ptmxmode=0666");
+            }
+        }
+        _ => {}
+    }
+
+    defaults
+}
+
+/// Removes the gid option of a devpts mount if the group is not mapped into
+/// the user namespace described by `gid_map`, as the kernel refuses to mount
+/// it otherwise. Specs generated for the host usually ask for the tty group
+/// with `gid=5`, which is not mapped for many rootless containers.
+pub fn remove_unmapped_gid(data: &str, gid_map: &str) -> String {
+    let is_mapped = |gid: u32| {
+        gid_map.lines().any(|line| {
+            let fields: Vec<u64> = line
+                .split_whitespace()
+                .filter_map(|f| f.parse().ok())
+                .collect();
+            matches!(fields.as_slice(), [inside, _, count] if (*inside..inside + count).contains(&(gid as u64)))
+        })
+    };
+
+    data.split(',')
+        .filter(
+            |option| match option.strip_prefix("gid=").map(str::parse::<u32>) {
+                Some(Ok(gid)) if !is_mapped(gid) => {
+                    tracing::warn!(
+                        gid,
+                        "group of devpts is not mapped into the user namespace, ignoring it"
+                    );
+                    false
+                }
+                _ => true,
+            },
+        )
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 pub fn parse_mount(m: &Mount) -> std::result::Result<MountOptionConfig, MountError> {
     let mut flags = MsFlags::empty();
+    let mut propagation = MsFlags::empty();
     let mut data = Vec::new();
     let mut mount_attr: Option<linux::MountAttr> = None;
 
@@ -131,6 +313,12 @@ pub fn parse_mount(m: &Mount) -> std::result::Result<MountOptionConfig, MountErr
                 continue;
             }
 
+            if let Some(flag) = propagation_flags(option) {
+                // only one propagation type can be set, the last one wins
+                propagation = flag;
+                continue;
+            }
+
             if let Some((is_clear, flag)) = match option.as_str() {
                 "defaults" => Some((false, MsFlags::empty())),
                 "ro" => Some((false, MsFlags::MS_RDONLY)),
@@ -153,24 +341,17 @@ pub fn parse_mount(m: &Mount) -> std::result::Result<MountOptionConfig, MountErr
                 "nodiratime" => Some((false, MsFlags::MS_NODIRATIME)),
                 "bind" => Some((false, MsFlags::MS_BIND)),
                 "rbind" => Some((false, MsFlags::MS_BIND | MsFlags::MS_REC)),
-                "unbindable" => Some((false, MsFlags::MS_UNBINDABLE)),
-                "runbindable" => Some((false, MsFlags::MS_UNBINDABLE | MsFlags::MS_REC)),
-                "private" => Some((true, MsFlags::MS_PRIVATE)),
-                "rprivate" => Some((true, MsFlags::MS_PRIVATE | MsFlags::MS_REC)),
-                "shared" => Some((true, MsFlags::MS_SHARED)),
-                "rshared" => Some((true, MsFlags::MS_SHARED | MsFlags::MS_REC)),
-                "slave" => Some((true, MsFlags::MS_SLAVE)),
-                "rslave" => Some((true, MsFlags::MS_SLAVE | MsFlags::MS_REC)),
-                "relatime" => Some((true, MsFlags::MS_RELATIME)),
+                "relatime" => Some((false, MsFlags::MS_RELATIME)),
                 "norelatime" => Some((true, MsFlags::MS_RELATIME)),
-                "strictatime" => Some((true, MsFlags::MS_STRICTATIME)),
+                "strictatime" => Some((false, MsFlags::MS_STRICTATIME)),
                 "nostrictatime" => Some((true, MsFlags::MS_STRICTATIME)),
-                unknown => {
-                    if unknown == "idmap" || unknown == "ridmap" {
-                        return Err(MountError::UnsupportedMountOption(unknown.to_string()));
-                    }
-                    None
-                }
+                // handled separately, see `idmap_type`
+                "idmap" | "ridmap" => continue,
+                // handled separately, see `relabel_type`
+                "z" | "Z" => continue,
+                // handled separately, see `tmpcopyup`
+                "tmpcopyup" => continue,
+                _ => None,
             } {
                 if is_clear {
                     flags &= !flag;
@@ -183,13 +364,33 @@ pub fn parse_mount(m: &Mount) -> std::result::Result<MountOptionConfig, MountErr
             data.push(option.as_str());
         }
     }
+    data.extend(default_data(m, &data));
+
     Ok(MountOptionConfig {
         flags,
         data: data.join(","),
         rec_attr: mount_attr,
+        propagation,
     })
 }
 
+/// Returns the flags to change the propagation type as requested by a mount
+/// option or the rootfsPropagation of the spec
+pub fn propagation_flags(option: &str) -> Option<MsFlags> {
+    let flags = match option {
+        "shared" => MsFlags::MS_SHARED,
+        "rshared" => MsFlags::MS_SHARED | MsFlags::MS_REC,
+        "slave" => MsFlags::MS_SLAVE,
+        "rslave" => MsFlags::MS_SLAVE | MsFlags::MS_REC,
+        "private" => MsFlags::MS_PRIVATE,
+        "rprivate" => MsFlags::MS_PRIVATE | MsFlags::MS_REC,
+        "unbindable" => MsFlags::MS_UNBINDABLE,
+        "runbindable" => MsFlags::MS_UNBINDABLE | MsFlags::MS_REC,
+        _ => return None,
+    };
+    Some(flags)
+}
+
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
@@ -224,6 +425,7 @@ mod tests {
                 flags: MsFlags::empty(),
                 data: "".to_string(),
                 rec_attr: None,
+                propagation: MsFlags::empty(),
             },
             mount_option_config
         );
@@ -243,9 +445,10 @@ mod tests {
         )?;
         assert_eq!(
             MountOptionConfig {
-                flags: MsFlags::MS_NOSUID,
+                flags: MsFlags::MS_NOSUID | MsFlags::MS_STRICTATIME,
                 data: "mode=755,size=65536k".to_string(),
                 rec_attr: None,
+                propagation: MsFlags::empty(),
             },
             mount_option_config
         );
@@ -270,7 +473,8 @@ mod tests {
             MountOptionConfig {
                 flags: MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC,
                 data: "newinstance,ptmxmode=0666,mode=0620,gid=5".to_string(),
-                rec_attr: None
+                rec_attr: None,
+                propagation: MsFlags::empty(),
             },
             mount_option_config
         );
@@ -293,7 +497,8 @@ mod tests {
             MountOptionConfig {
                 flags: MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC | MsFlags::MS_NODEV,
                 data: "mode=1777,size=65536k".to_string(),
-                rec_attr: None
+                rec_attr: None,
+                propagation: MsFlags::empty(),
             },
             mount_option_config
         );
@@ -315,7 +520,8 @@ mod tests {
             MountOptionConfig {
                 flags: MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC | MsFlags::MS_NODEV,
                 data: "".to_string(),
-                rec_attr: None
+                rec_attr: None,
+                propagation: MsFlags::empty(),
             },
             mount_option_config
         );
@@ -341,6 +547,7 @@ mod tests {
                     | MsFlags::MS_RDONLY,
                 data: "".to_string(),
                 rec_attr: None,
+                propagation: MsFlags::empty(),
             },
             mount_option_config
         );
@@ -364,9 +571,11 @@ mod tests {
                 flags: MsFlags::MS_NOSUID
                     | MsFlags::MS_NOEXEC
                     | MsFlags::MS_NODEV
+                    | MsFlags::MS_RELATIME
                     | MsFlags::MS_RDONLY,
                 data: "".to_string(),
-                rec_attr: None
+                rec_attr: None,
+                propagation: MsFlags::empty(),
             },
             mount_option_config,
         );
@@ -421,9 +630,10 @@ mod tests {
                     | MsFlags::MS_NOATIME
                     | MsFlags::MS_NODIRATIME
                     | MsFlags::MS_BIND
-                    | MsFlags::MS_UNBINDABLE,
+                    | MsFlags::MS_REC,
                 data: "".to_string(),
                 rec_attr: None,
+                propagation: MsFlags::MS_SLAVE | MsFlags::MS_REC,
             },
             mount_option_config
         );
@@ -457,11 +667,162 @@ mod tests {
             MountOptionConfig {
                 flags: MsFlags::empty(),
                 data: "".to_string(),
-                rec_attr: Some(MountAttr::all())
+                rec_attr: Some(MountAttr::all()),
+                propagation: MsFlags::empty(),
             },
             mount_option_config
         );
 
         Ok(())
     }
+
+    #[test]
+    fn test_default_data() -> Result<()> {
+        let parse = |dest: &str, typ: &str, options: &[&str]| -> Result<String> {
+            let mount = MountBuilder::default()
+                .destination(PathBuf::from(dest))
+                .typ(typ)
+                .source(PathBuf::from(typ))
+                .options(options.iter().map(|o| o.to_string()).collect::<Vec<_>>())
+                .build()?;
+            Ok(parse_mount(&mount)?.data)
+        };
+
+        assert_eq!(parse("/run", "tmpfs", &["nosuid"])?, "mode=755");
+        assert_eq!(parse("/run", "tmpfs", &["mode=700"])?, "mode=700");
+        assert_eq!(parse("/tmp", "tmpfs", &["size=64m"])?, "size=64m");
+        assert_eq!(
+            parse("/dev/pts", "devpts", &["gid=5"])?,
+            "gid=5,newinstance,ptmxmode=0666"
+        );
+        assert_eq!(
+            parse("/dev/pts", "devpts", &["newinstance", "ptmxmode=0600"])?,
+            "newinstance,ptmxmode=0600"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_unmapped_gid() {
+        let data = "newinstance,ptmxmode=0666,mode=0620,gid=5";
+        assert_eq!(remove_unmapped_gid(data, "0 0 4294967295\n"), data);
+        assert_eq!(
+            remove_unmapped_gid(data, "         0       1000          1\n"),
+            "newinstance,ptmxmode=0666,mode=0620"
+        );
+        assert_eq!(
+            remove_unmapped_gid(data, "0 1000 1\n1 100000 65536\n"),
+            data
+        );
+    }
+
+    #[test]
+    fn test_idmap_type() -> Result<()> {
+        let mount = MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(vec!["rbind".to_string(), "idmap".to_string()])
+            .build()?;
+        assert_eq!(idmap_type(&mount), Some(IdmapType::Idmap));
+        // the idmap options are applied by the idmapped mount itself
+        assert_eq!(parse_mount(&mount)?.data, "");
+
+        let mount = MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(vec!["idmap".to_string(), "ridmap".to_string()])
+            .build()?;
+        assert_eq!(idmap_type(&mount), Some(IdmapType::RecursiveIdmap));
+
+        let mount = MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(vec!["rbind".to_string()])
+            .build()?;
+        assert_eq!(idmap_type(&mount), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relabel_type() -> Result<()> {
+        let mount = MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(vec!["rbind".to_string(), "Z".to_string()])
+            .build()?;
+        assert_eq!(relabel_type(&mount), Some(Relabel::Private));
+        // the relabel options are not passed on to mount(2)
+        assert_eq!(parse_mount(&mount)?.data, "");
+
+        let mount = MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(vec!["z".to_string()])
+            .build()?;
+        assert_eq!(relabel_type(&mount), Some(Relabel::Shared));
+
+        let mount = MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(vec!["rbind".to_string()])
+            .build()?;
+        assert_eq!(relabel_type(&mount), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tmpcopyup() -> Result<()> {
+        let mount = MountBuilder::default()
+            .destination(PathBuf::from("/run"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .options(vec!["nosuid".to_string(), "tmpcopyup".to_string()])
+            .build()?;
+        assert!(tmpcopyup(&mount));
+        // the option is not passed on to mount(2)
+        assert_eq!(parse_mount(&mount)?.data, "mode=755");
+
+        let mount = MountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/srv/data"))
+            .options(vec!["rbind".to_string(), "tmpcopyup".to_string()])
+            .build()?;
+        assert!(!tmpcopyup(&mount));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_dir_contents() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let from = tempfile::tempdir()?;
+        let to = tempfile::tempdir()?;
+        fs::create_dir(from.path().join("lock"))?;
+        fs::set_permissions(from.path().join("lock"), fs::Permissions::from_mode(0o700))?;
+        fs::write(from.path().join("lock/app.pid"), "42")?;
+        symlink("lock/app.pid", from.path().join("pid"))?;
+
+        copy_dir_contents(from.path(), to.path())?;
+        assert_eq!(fs::read_to_string(to.path().join("lock/app.pid"))?, "42");
+        assert_eq!(
+            fs::metadata(to.path().join("lock"))?.permissions().mode() & 0o777,
+            0o700
+        );
+        assert_eq!(
+            fs::read_link(to.path().join("pid"))?,
+            PathBuf::from("lock/app.pid")
+        );
+
+        Ok(())
+    }
 }
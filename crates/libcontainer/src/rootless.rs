@@ -0,0 +1,391 @@
+//! Checks for the parts of a spec which can't be provided to a rootless
+//! container, i.e. one created by an unprivileged user. All of them are
+//! collected, so that they can be reported at once or be removed from the
+//! spec if the user has asked to ignore them.
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use libcgroups::common::DEFAULT_CGROUP_ROOT;
+use oci_spec::runtime::{LinuxCpu, LinuxResources, Spec};
+
+use crate::rootfs::device::{device_creation, DeviceCreation};
+use crate::utils;
+
+/// Annotation to remove the unsupported parts of the spec instead of failing
+pub const IGNORE_UNSUPPORTED_ANNOTATION: &str = "org.youki.rootless.ignore-unsupported";
+
+/// A part of the spec which is not supported for rootless containers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unsupported {
+    /// Device nodes can't be created in a user namespace, so only the nodes
+    /// existing on the host can be bind mounted
+    Device(PathBuf),
+    /// Sysctls can only be written in namespaces owned by the user namespace
+    /// of the container, which is not the case for joined namespaces
+    Sysctl(String),
+    /// Resources of a cgroup controller which is not delegated to the user
+    Resources(String),
+}
+
+impl Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Device(path) => write!(f, "device {} without a node on the host", path.display()),
+            Self::Sysctl(key) => write!(f, "sysctl {key} of a joined namespace"),
+            Self::Resources(controller) => {
+                write!(f, "resources of the undelegated {controller} controller")
+            }
+        }
+    }
+}
+
+/// Formats all unsupported parts for a single error message
+pub fn report(unsupported: &[Unsupported]) -> String {
+    unsupported
+        .iter()
+        .map(|u| u.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Checks if the unsupported parts should be removed from the spec
+pub fn ignore_unsupported_requested(annotations: Option<&HashMap<String, String>>) -> bool {
+    match annotations.and_then(|a| a.get(IGNORE_UNSUPPORTED_ANNOTATION)) {
+        Some(value) => match value.as_str() {
+            "1" | "true" => true,
+            "0" | "false" => false,
+            _ => {
+                tracing::warn!(
+                    ?value,
+                    "ignoring invalid value of {}",
+                    IGNORE_UNSUPPORTED_ANNOTATION
+                );
+                false
+            }
+        },
+        None => false,
+    }
+}
+
+/// Returns the cgroup v2 controllers available in the cgroup of the current
+/// process. Controllers of cgroup v1 are never delegated to a user.
+pub fn delegated_controllers() -> Vec<String> {
+    let cgroup = fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+    let path = match cgroup.lines().find_map(|line| line.strip_prefix("0::")) {
+        Some(path) => path.trim_start_matches('/'),
+        None => return Vec::new(),
+    };
+
+    fs::read_to_string(
+        Path::new(DEFAULT_CGROUP_ROOT)
+            .join(path)
+            .join("cgroup.controllers"),
+    )
+    .map(|controllers| controllers.split_whitespace().map(String::from).collect())
+    .unwrap_or_default()
+}
+
+/// Collects all parts of the spec which can't be provided to a rootless
+/// container with the given delegated controllers
+pub fn unsupported(spec: &Spec, controllers: &[String]) -> Vec<Unsupported> {
+    let mut unsupported = Vec::new();
+    let linux = match spec.linux() {
+        Some(linux) => linux,
+        None => return unsupported,
+    };
+
+    let creation = device_creation(spec.annotations().as_ref());
+    for device in linux.devices().iter().flatten() {
+        let mknod = creation.get(device.path()) == Some(&DeviceCreation::Mknod);
+        if mknod || !device.path().exists() {
+            unsupported.push(Unsupported::Device(device.path().clone()));
+        }
+    }
+
+    for key in linux.sysctl().iter().flat_map(|sysctl| sysctl.keys()) {
+        let joined = utils::sysctl_namespace(&key.replace('/', ".")).map_or(false, |(typ, _)| {
+            linux
+                .namespaces()
+                .iter()
+                .flatten()
+                .any(|ns| ns.typ() == typ && ns.path().is_some())
+        });
+        if joined {
+            unsupported.push(Unsupported::Sysctl(key.clone()));
+        }
+    }
+
+    if let Some(resources) = linux.resources() {
+        unsupported.extend(
+            undelegated_controllers(resources, controllers)
+                .into_iter()
+                .map(Unsupported::Resources),
+        );
+    }
+
+    unsupported
+}
+
+/// Removes the unsupported parts from the spec
+pub fn remove_unsupported(spec: &mut Spec, unsupported: &[Unsupported]) {
+    let mut linux = match spec.linux() {
+        Some(linux) => linux.clone(),
+        None => return,
+    };
+
+    for u in unsupported {
+        match u {
+            Unsupported::Device(path) => {
+                if let Some(devices) = linux.devices() {
+                    let devices = devices
+                        .iter()
+                        .filter(|device| device.path() != path)
+                        .cloned()
+                        .collect();
+                    linux.set_devices(Some(devices));
+                }
+            }
+            Unsupported::Sysctl(key) => {
+                if let Some(sysctl) = linux.sysctl() {
+                    let mut sysctl = sysctl.clone();
+                    sysctl.remove(key);
+                    linux.set_sysctl(Some(sysctl));
+                }
+            }
+            Unsupported::Resources(controller) => {
+                if let Some(resources) = linux.resources() {
+                    let mut resources = resources.clone();
+                    remove_resources(&mut resources, controller);
+                    linux.set_resources(Some(resources));
+                }
+            }
+        }
+    }
+
+    spec.set_linux(Some(linux));
+}
+
+/// Returns the controllers required by the resources which are not delegated
+pub fn undelegated_controllers(resources: &LinuxResources, controllers: &[String]) -> Vec<String> {
+    let mut requested = requested_controllers(resources);
+    requested.retain(|controller| !controllers.contains(controller));
+    requested
+}
+
+/// Returns the controllers required for the resources, where the network
+/// resources are only provided by cgroup v1
+fn requested_controllers(resources: &LinuxResources) -> Vec<String> {
+    let mut controllers: Vec<String> = Vec::new();
+    let mut request = |controller: &str| {
+        if !controllers.iter().any(|c| c == controller) {
+            controllers.push(controller.to_owned());
+        }
+    };
+
+    if resources.memory().is_some() {
+        request("memory");
+    }
+    if let Some(cpu) = resources.cpu() {
+        if cpu_without_cpuset(cpu) != LinuxCpu::default() {
+            request("cpu");
+        }
+        if cpu.cpus().is_some() || cpu.mems().is_some() {
+            request("cpuset");
+        }
+    }
+    if resources.pids().is_some() {
+        request("pids");
+    }
+    if resources.block_io().is_some() {
+        request("io");
+    }
+    if resources.hugepage_limits().is_some() {
+        request("hugetlb");
+    }
+    if resources.rdma().is_some() {
+        request("rdma");
+    }
+    if resources.network().is_some() {
+        request("network");
+    }
+    for key in resources
+        .unified()
+        .iter()
+        .flat_map(|unified| unified.keys())
+    {
+        // the files of the cgroup itself, like cgroup.max.depth, are always available
+        match key.split('.').next() {
+            Some("cgroup") | None => {}
+            Some(controller) => request(controller),
+        }
+    }
+
+    controllers
+}
+
+fn cpu_without_cpuset(cpu: &LinuxCpu) -> LinuxCpu {
+    let mut cpu = cpu.clone();
+    cpu.set_cpus(None).set_mems(None);
+    cpu
+}
+
+/// Removes the resources of the controller, including its unified ones
+pub fn remove_resources(resources: &mut LinuxResources, controller: &str) {
+    match controller {
+        "memory" => {
+            resources.set_memory(None);
+        }
+        "cpu" => {
+            let cpu = resources.cpu().as_ref().map(|cpu| {
+                let mut cpuset = LinuxCpu::default();
+                cpuset
+                    .set_cpus(cpu.cpus().clone())
+                    .set_mems(cpu.mems().clone());
+                cpuset
+            });
+            resources.set_cpu(cpu.filter(|cpu| *cpu != LinuxCpu::default()));
+        }
+        "cpuset" => {
+            let cpu = resources.cpu().as_ref().map(cpu_without_cpuset);
+            resources.set_cpu(cpu.filter(|cpu| *cpu != LinuxCpu::default()));
+        }
+        "pids" => {
+            resources.set_pids(None);
+        }
+        "io" => {
+            resources.set_block_io(None);
+        }
+        "hugetlb" => {
+            resources.set_hugepage_limits(None);
+        }
+        "rdma" => {
+            resources.set_rdma(None);
+        }
+        "network" => {
+            resources.set_network(None);
+        }
+        _ => {}
+    }
+
+    if let Some(unified) = resources.unified() {
+        let prefix = format!("{controller}.");
+        let unified: HashMap<String, String> = unified
+            .iter()
+            .filter(|(key, _)| !key.starts_with(&prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        resources.set_unified(Some(unified).filter(|unified| !unified.is_empty()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use oci_spec::runtime::{
+        LinuxBuilder, LinuxCpuBuilder, LinuxDeviceBuilder, LinuxMemoryBuilder,
+        LinuxNamespaceBuilder, LinuxNamespaceType, LinuxResourcesBuilder, SpecBuilder,
+    };
+
+    use super::*;
+
+    fn spec() -> Result<Spec> {
+        let resources = LinuxResourcesBuilder::default()
+            .memory(LinuxMemoryBuilder::default().limit(1024).build()?)
+            .cpu(
+                LinuxCpuBuilder::default()
+                    .shares(512_u64)
+                    .cpus("0-1")
+                    .build()?,
+            )
+            .unified(HashMap::from([
+                ("memory.high".to_owned(), "1024".to_owned()),
+                ("cgroup.max.depth".to_owned(), "2".to_owned()),
+            ]))
+            .build()?;
+        let linux = LinuxBuilder::default()
+            .devices(vec![LinuxDeviceBuilder::default()
+                .path("/dev/youki-missing")
+                .build()?])
+            .sysctl(HashMap::from([
+                ("net.ipv4.ip_forward".to_owned(), "1".to_owned()),
+                ("kernel.shmmax".to_owned(), "1024".to_owned()),
+            ]))
+            .namespaces(vec![
+                LinuxNamespaceBuilder::default()
+                    .typ(LinuxNamespaceType::Network)
+                    .path("/proc/1/ns/net")
+                    .build()?,
+                LinuxNamespaceBuilder::default()
+                    .typ(LinuxNamespaceType::Ipc)
+                    .build()?,
+            ])
+            .resources(resources)
+            .build()?;
+        Ok(SpecBuilder::default().linux(linux).build()?)
+    }
+
+    #[test]
+    fn test_unsupported() -> Result<()> {
+        let unsupported = unsupported(&spec()?, &["cpu".to_owned(), "pids".to_owned()]);
+        assert_eq!(
+            unsupported,
+            vec![
+                Unsupported::Device(PathBuf::from("/dev/youki-missing")),
+                Unsupported::Sysctl("net.ipv4.ip_forward".to_owned()),
+                Unsupported::Resources("memory".to_owned()),
+                Unsupported::Resources("cpuset".to_owned()),
+            ]
+        );
+        assert_eq!(
+            report(&unsupported),
+            "device /dev/youki-missing without a node on the host, sysctl net.ipv4.ip_forward of a joined namespace, \
+             resources of the undelegated memory controller, \
+             resources of the undelegated cpuset controller"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_unsupported() -> Result<()> {
+        let mut spec = spec()?;
+        let controllers = ["cpu".to_owned()];
+        let unsupported = unsupported(&spec, &controllers);
+        remove_unsupported(&mut spec, &unsupported);
+        assert!(super::unsupported(&spec, &controllers).is_empty());
+
+        let linux = spec.linux().as_ref().unwrap();
+        assert_eq!(linux.devices().as_ref().unwrap().len(), 0);
+        assert_eq!(
+            linux.sysctl().as_ref().unwrap().keys().collect::<Vec<_>>(),
+            vec!["kernel.shmmax"]
+        );
+        let resources = linux.resources().as_ref().unwrap();
+        assert!(resources.memory().is_none());
+        let cpu = resources.cpu().as_ref().unwrap();
+        assert_eq!(cpu.shares(), Some(512));
+        assert!(cpu.cpus().is_none());
+        assert_eq!(
+            resources
+                .unified()
+                .as_ref()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            vec!["cgroup.max.depth"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_unsupported_requested() {
+        let annotations = |value: &str| {
+            HashMap::from([(IGNORE_UNSUPPORTED_ANNOTATION.to_owned(), value.to_owned())])
+        };
+        assert!(ignore_unsupported_requested(Some(&annotations("true"))));
+        assert!(!ignore_unsupported_requested(Some(&annotations("0"))));
+        assert!(!ignore_unsupported_requested(Some(&annotations("yes"))));
+        assert!(!ignore_unsupported_requested(None));
+    }
+}
@@ -0,0 +1,194 @@
+//! Minimal rtnetlink client, which covers the few changes of network
+//! interfaces the runtime makes itself: bringing up the loopback interface of
+//! new network namespaces and moving interfaces into the container. Anything
+//! beyond that is left to CNI plugins.
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+
+use nix::errno::Errno;
+use nix::sys::socket::{self, AddressFamily, MsgFlags, SockFlag, SockProtocol, SockType};
+
+/// Index of the loopback interface, which is the first interface of every
+/// network namespace
+pub const LOOPBACK_INDEX: u32 = 1;
+
+/// Maximum length of interface names, including the terminating NUL
+pub const IFNAMSIZ: usize = 16;
+const NLMSG_HDRLEN: usize = 16;
+const IFINFOMSG_LEN: usize = 16;
+const RTA_HDRLEN: usize = 4;
+
+/// Returns the index of the interface in the network namespace of the calling
+/// thread
+pub fn index_of(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => None,
+        index => Some(index),
+    }
+}
+
+/// Returns the name of the interface in the network namespace of the calling
+/// thread
+pub fn name_of(index: u32) -> Option<String> {
+    let mut buf = [0u8; IFNAMSIZ];
+    let name = unsafe { libc::if_indextoname(index, buf.as_mut_ptr().cast()) };
+    if name.is_null() {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(IFNAMSIZ);
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+/// Route netlink socket of the network namespace the calling thread is in
+/// when the client is created
+#[derive(Debug)]
+pub struct Client {
+    socket: OwnedFd,
+}
+
+impl Client {
+    pub fn new() -> nix::Result<Self> {
+        let socket = socket::socket(
+            AddressFamily::Netlink,
+            SockType::Raw,
+            SockFlag::SOCK_CLOEXEC,
+            SockProtocol::NetlinkRoute,
+        )?;
+        Ok(Self { socket })
+    }
+
+    /// Brings the interface up, i.e. `ip link set <interface> up`
+    pub fn set_up(&self, index: u32) -> nix::Result<()> {
+        let flags = libc::IFF_UP as u32;
+        self.request(&new_link_request(index, flags, flags, &[]))
+    }
+
+    /// Renames the interface, and moves it into the network namespace first,
+    /// if one is given, which brings it down
+    pub fn set_name(&self, index: u32, name: &str, netns: Option<BorrowedFd>) -> nix::Result<()> {
+        let mut ifname = name.as_bytes().to_vec();
+        ifname.push(0);
+        let netns_fd = netns.map(|fd| (fd.as_raw_fd() as u32).to_ne_bytes());
+
+        let mut attrs: Vec<(u16, &[u8])> = Vec::new();
+        if let Some(fd) = &netns_fd {
+            attrs.push((libc::IFLA_NET_NS_FD, fd));
+        }
+        attrs.push((libc::IFLA_IFNAME, &ifname));
+        self.request(&new_link_request(index, 0, 0, &attrs))
+    }
+
+    fn request(&self, request: &[u8]) -> nix::Result<()> {
+        socket::send(self.socket.as_raw_fd(), request, MsgFlags::empty())?;
+
+        let mut buf = [0u8; 4096];
+        let len = socket::recv(self.socket.as_raw_fd(), &mut buf, MsgFlags::empty())?;
+        parse_ack(&buf[..len])
+    }
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Encodes an RTM_NEWLINK request which changes the flags of the interface
+/// selected by the mask to the given ones and sets the attributes
+fn new_link_request(index: u32, flags: u32, change: u32, attrs: &[(u16, &[u8])]) -> Vec<u8> {
+    let mut encoded_attrs = Vec::new();
+    for (typ, payload) in attrs {
+        encoded_attrs.extend_from_slice(&((RTA_HDRLEN + payload.len()) as u16).to_ne_bytes());
+        encoded_attrs.extend_from_slice(&typ.to_ne_bytes());
+        encoded_attrs.extend_from_slice(payload);
+        encoded_attrs.resize(align(encoded_attrs.len()), 0);
+    }
+
+    let len = NLMSG_HDRLEN + IFINFOMSG_LEN + encoded_attrs.len();
+    let mut msg = Vec::with_capacity(len);
+    // nlmsghdr
+    msg.extend_from_slice(&(len as u32).to_ne_bytes());
+    msg.extend_from_slice(&libc::RTM_NEWLINK.to_ne_bytes());
+    msg.extend_from_slice(&((libc::NLM_F_REQUEST | libc::NLM_F_ACK) as u16).to_ne_bytes());
+    msg.extend_from_slice(&1u32.to_ne_bytes());
+    msg.extend_from_slice(&0u32.to_ne_bytes());
+    // ifinfomsg
+    msg.push(libc::AF_UNSPEC as u8);
+    msg.push(0);
+    msg.extend_from_slice(&0u16.to_ne_bytes());
+    msg.extend_from_slice(&(index as i32).to_ne_bytes());
+    msg.extend_from_slice(&flags.to_ne_bytes());
+    msg.extend_from_slice(&change.to_ne_bytes());
+    msg.extend_from_slice(&encoded_attrs);
+    msg
+}
+
+/// Returns the error of the NLMSG_ERROR acknowledging a request, which is
+/// zero on success
+fn parse_ack(reply: &[u8]) -> nix::Result<()> {
+    if reply.len() < NLMSG_HDRLEN + 4 {
+        return Err(Errno::EBADMSG);
+    }
+    let typ = u16::from_ne_bytes([reply[4], reply[5]]);
+    if typ != libc::NLMSG_ERROR as u16 {
+        return Err(Errno::EBADMSG);
+    }
+    let error = i32::from_ne_bytes([reply[16], reply[17], reply[18], reply[19]]);
+    match error {
+        0 => Ok(()),
+        error => Err(Errno::from_raw(-error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_link_request() {
+        let fd = 3u32.to_ne_bytes();
+        let request = new_link_request(
+            7,
+            0,
+            0,
+            &[(libc::IFLA_NET_NS_FD, &fd), (libc::IFLA_IFNAME, b"eth1\0")],
+        );
+        // header, ifinfomsg, the fd and the padded name
+        assert_eq!(request.len(), 16 + 16 + 8 + 12);
+        assert_eq!(
+            u32::from_ne_bytes(request[..4].try_into().unwrap()) as usize,
+            request.len()
+        );
+        assert_eq!(i32::from_ne_bytes(request[20..24].try_into().unwrap()), 7);
+        assert_eq!(
+            u16::from_ne_bytes(request[34..36].try_into().unwrap()),
+            libc::IFLA_NET_NS_FD
+        );
+        assert_eq!(&request[44..49], b"eth1\0");
+
+        let up = libc::IFF_UP as u32;
+        let request = new_link_request(LOOPBACK_INDEX, up, up, &[]);
+        assert_eq!(request.len(), 16 + 16);
+        assert_eq!(u32::from_ne_bytes(request[24..28].try_into().unwrap()), up);
+        assert_eq!(u32::from_ne_bytes(request[28..32].try_into().unwrap()), up);
+    }
+
+    #[test]
+    fn test_parse_ack() {
+        let ack = |error: i32| {
+            let mut reply = vec![0u8; NLMSG_HDRLEN];
+            reply[4..6].copy_from_slice(&(libc::NLMSG_ERROR as u16).to_ne_bytes());
+            reply.extend_from_slice(&error.to_ne_bytes());
+            reply
+        };
+        assert_eq!(parse_ack(&ack(0)), Ok(()));
+        assert_eq!(parse_ack(&ack(-libc::EBUSY)), Err(Errno::EBUSY));
+        assert_eq!(parse_ack(&[0; 8]), Err(Errno::EBADMSG));
+    }
+
+    #[test]
+    fn test_index_of_loopback() {
+        assert_eq!(index_of("lo"), Some(LOOPBACK_INDEX));
+        assert_eq!(name_of(LOOPBACK_INDEX).as_deref(), Some("lo"));
+        assert_eq!(index_of("youki-missing"), None);
+    }
+}
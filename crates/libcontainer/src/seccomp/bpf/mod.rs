@@ -0,0 +1,785 @@
+//! Seccomp backend which compiles profiles to the classic BPF program the
+//! kernel runs on its own, without libseccomp. It allows building youki as a
+//! static binary, e.g. against musl, which libseccomp usually isn't available
+//! for. It is used if libcontainer is built with the `seccomp-bpf` feature
+//! but without `libseccomp`.
+//!
+//! The program behaves the same as the one libseccomp generates:
+//!
+//! - Syscalls of another architecture get the bad architecture action, which
+//!   kills the thread. Only the native architecture is filtered, the
+//!   compatible ones of [`crate::arch::SECCOMP_ARCHS`], e.g. 32-bit syscalls
+//!   on x86_64, are refused even if the profile lists them.
+//! - Each syscall with rules gets a block, in which the rules comparing
+//!   arguments are tried in the order of the profile, each argument being a
+//!   rule of its own like with libseccomp. A rule without comparisons applies
+//!   to all calls of the syscall, which makes the others redundant.
+//! - All other syscalls get the default action.
+use std::collections::BTreeMap;
+use std::os::unix::io;
+
+use libc::sock_filter;
+use oci_spec::runtime::{
+    Arch, LinuxSeccomp, LinuxSeccompAction, LinuxSeccompArg, LinuxSeccompOperator,
+};
+
+use super::{Result, SeccompError};
+use crate::arch;
+
+mod syscalls;
+
+// Instruction classes and fields of linux/filter.h
+const BPF_LD: u16 = 0x00;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_W: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_K: u16 = 0x00;
+const BPF_AND: u16 = 0x50;
+const BPF_JA: u16 = 0x00;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGT: u16 = 0x20;
+const BPF_JGE: u16 = 0x30;
+/// Maximum number of instructions of a program, BPF_MAXINSNS
+const MAX_INSTRUCTIONS: usize = 4096;
+
+// Actions of linux/seccomp.h
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+const SECCOMP_RET_KILL_THREAD: u32 = 0x0000_0000;
+const SECCOMP_RET_TRAP: u32 = 0x0003_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc0_0000;
+const SECCOMP_RET_TRACE: u32 = 0x7ff0_0000;
+const SECCOMP_RET_LOG: u32 = 0x7ffc_0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+const SECCOMP_RET_DATA: u32 = 0x0000_ffff;
+
+/// Action for syscalls of other architectures, the default of libseccomp
+const BAD_ARCH_ACTION: u32 = SECCOMP_RET_KILL_THREAD;
+
+// Offsets of the fields of struct seccomp_data
+const NR_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+const ARGS_OFFSET: u32 = 16;
+const MAX_ARGS: usize = 6;
+
+#[tracing::instrument(level = "trace", skip(seccomp))]
+pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
+    let mut program = build_program(seccomp)?;
+    let notify = super::is_notify(seccomp);
+    let mut flags = super::filter_flags(seccomp);
+    if notify {
+        flags |= super::SECCOMP_FILTER_FLAG_NEW_LISTENER;
+    }
+
+    // The same as with libseccomp, the calling thread needs either
+    // CAP_SYS_ADMIN in its user namespace or the no_new_privs bit, which is
+    // left to the caller.
+    let fd = super::load_filter(&mut program, flags)?;
+
+    Ok(notify.then_some(fd))
+}
+
+/// Compiles the filter to the BPF program the kernel runs, in the binary form
+/// libseccomp exports programs in
+pub fn compile_seccomp(seccomp: &LinuxSeccomp) -> Result<Vec<u8>> {
+    Ok(super::program_bytes(&build_program(seccomp)?))
+}
+
+/// Rules of a syscall
+#[derive(Default)]
+struct Rules<'a> {
+    unconditional: Option<u32>,
+    conditional: Vec<(&'a LinuxSeccompArg, u32)>,
+}
+
+impl Rules<'_> {
+    /// Returns the block of the syscall, which ends with a return on every
+    /// path through it
+    fn block(&self, default_action: u32) -> Vec<sock_filter> {
+        if let Some(action) = self.unconditional {
+            return vec![ret(action)];
+        }
+
+        let mut block = Vec::new();
+        for (arg, action) in &self.conditional {
+            let steps = compare(arg);
+            let len = steps.len();
+            for (i, step) in steps.into_iter().enumerate() {
+                block.push(step.instruction(len - i - 1));
+            }
+            block.push(ret(*action));
+        }
+        block.push(ret(default_action));
+
+        block
+    }
+}
+
+/// Where a jump of a comparison goes to
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    /// The next instruction of the comparison
+    Next,
+    /// The action of the rule, right after the comparison
+    Match,
+    /// The next rule, right after the action
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Load(u32),
+    And(u32),
+    Jump(u16, u32, Target, Target),
+}
+
+impl Step {
+    /// Returns the instruction of the step, which is `to_match` instructions
+    /// before the action of the rule
+    fn instruction(self, to_match: usize) -> sock_filter {
+        let to = |target| match target {
+            Target::Next => 0,
+            Target::Match => to_match as u8,
+            Target::Fail => to_match as u8 + 1,
+        };
+        match self {
+            Step::Load(offset) => load(offset),
+            Step::And(mask) => stmt(BPF_ALU | BPF_AND | BPF_K, mask),
+            Step::Jump(op, k, jt, jf) => jump(op, k, to(jt), to(jf)),
+        }
+    }
+}
+
+fn build_program(seccomp: &LinuxSeccomp) -> Result<Vec<sock_filter>> {
+    super::check_seccomp(seccomp)?;
+
+    let audit_arch = match arch::AUDIT_ARCH {
+        Some(audit_arch) if syscalls::all().next().is_some() => audit_arch,
+        _ => return Err(SeccompError::UnsupportedArch(arch::NAME)),
+    };
+    for &listed in seccomp.architectures().iter().flatten() {
+        if listed != Arch::ScmpArchNative && arch::SECCOMP_ARCHS.first() != Some(&listed) {
+            tracing::warn!(
+                arch = ?listed,
+                "the bpf seccomp backend only filters the native architecture, syscalls of this one are refused"
+            );
+        }
+    }
+
+    tracing::trace!(default_action = ?seccomp.default_action(), errno = ?seccomp.default_errno_ret(), "compiling seccomp");
+    let default_action = translate_action(seccomp.default_action(), seccomp.default_errno_ret())?;
+    let mut rules: BTreeMap<u32, Rules> = BTreeMap::new();
+    for syscall in seccomp.syscalls().iter().flatten() {
+        let action = translate_action(syscall.action(), syscall.errno_ret())?;
+        if action == default_action {
+            tracing::warn!(
+                "detect a seccomp action that is the same as the default action: {:?}",
+                syscall
+            );
+            continue;
+        }
+
+        for name in syscall.names() {
+            let nr = match syscalls::number(name) {
+                Some(nr) => nr,
+                None => {
+                    tracing::warn!(
+                        "failed to resolve syscall, likely the architecture doesn't have it. {:?}",
+                        name
+                    );
+                    continue;
+                }
+            };
+            match syscall.args() {
+                Some(args) => {
+                    for arg in args {
+                        if arg.index() >= MAX_ARGS {
+                            return Err(SeccompError::ArgIndex(arg.index()));
+                        }
+                        tracing::trace!(?name, ?action, ?arg, "add seccomp conditional rule");
+                        rules.entry(nr).or_default().conditional.push((arg, action));
+                    }
+                }
+                None => {
+                    tracing::trace!(?name, ?action, "add seccomp rule");
+                    let rules = rules.entry(nr).or_default();
+                    rules.unconditional = rules.unconditional.or(Some(action));
+                }
+            }
+        }
+    }
+
+    let mut program = vec![
+        load(ARCH_OFFSET),
+        jump(BPF_JEQ, audit_arch, 1, 0),
+        ret(BAD_ARCH_ACTION),
+        load(NR_OFFSET),
+    ];
+    if let Some(bit) = arch::X32_SYSCALL_BIT {
+        program.push(jump(BPF_JGE, bit, 0, 1));
+        program.push(ret(BAD_ARCH_ACTION));
+    }
+    for (nr, rules) in &rules {
+        // the block is skipped with an unconditional jump, as the offsets of
+        // conditional ones are limited to 255 instructions
+        let block = rules.block(default_action);
+        program.push(jump(BPF_JEQ, *nr, 1, 0));
+        program.push(stmt(BPF_JMP | BPF_JA, block.len() as u32));
+        program.extend(block);
+    }
+    program.push(ret(default_action));
+
+    if program.len() > MAX_INSTRUCTIONS {
+        return Err(SeccompError::ProgramTooLarge(program.len()));
+    }
+
+    Ok(program)
+}
+
+fn translate_action(action: LinuxSeccompAction, errno: Option<u32>) -> Result<u32> {
+    let errno = errno.map(|e| e as i32).unwrap_or(libc::EPERM);
+    let action = match action {
+        LinuxSeccompAction::ScmpActKill => SECCOMP_RET_KILL_THREAD,
+        LinuxSeccompAction::ScmpActTrap => SECCOMP_RET_TRAP,
+        LinuxSeccompAction::ScmpActErrno => SECCOMP_RET_ERRNO | (errno as u32 & SECCOMP_RET_DATA),
+        LinuxSeccompAction::ScmpActTrace => {
+            let msg: u16 = errno
+                .try_into()
+                .map_err(|err| SeccompError::TraceAction { source: err, errno })?;
+            SECCOMP_RET_TRACE | msg as u32
+        }
+        LinuxSeccompAction::ScmpActAllow => SECCOMP_RET_ALLOW,
+        LinuxSeccompAction::ScmpActKillProcess => SECCOMP_RET_KILL_PROCESS,
+        LinuxSeccompAction::ScmpActNotify => SECCOMP_RET_USER_NOTIF,
+        LinuxSeccompAction::ScmpActLog => SECCOMP_RET_LOG,
+    };
+
+    Ok(action)
+}
+
+/// Returns the steps comparing an argument, which is 64-bit and made of two
+/// words for BPF. Values are compared unsigned, the same as by libseccomp.
+fn compare(arg: &LinuxSeccompArg) -> Vec<Step> {
+    use Step::*;
+    use Target::*;
+
+    let (hi, lo) = arg_offsets(arg.index());
+    let value = arg.value();
+    let (value_hi, value_lo) = ((value >> 32) as u32, value as u32);
+    match arg.op() {
+        LinuxSeccompOperator::ScmpCmpEq => vec![
+            Load(hi),
+            Jump(BPF_JEQ, value_hi, Next, Fail),
+            Load(lo),
+            Jump(BPF_JEQ, value_lo, Match, Fail),
+        ],
+        LinuxSeccompOperator::ScmpCmpNe => vec![
+            Load(hi),
+            Jump(BPF_JEQ, value_hi, Next, Match),
+            Load(lo),
+            Jump(BPF_JEQ, value_lo, Fail, Match),
+        ],
+        // the same as the libseccomp backend, valueTwo is the mask
+        LinuxSeccompOperator::ScmpCmpMaskedEq => {
+            let mask = arg.value_two().unwrap_or(0);
+            vec![
+                Load(hi),
+                And((mask >> 32) as u32),
+                Jump(BPF_JEQ, value_hi, Next, Fail),
+                Load(lo),
+                And(mask as u32),
+                Jump(BPF_JEQ, value_lo, Match, Fail),
+            ]
+        }
+        LinuxSeccompOperator::ScmpCmpGt => vec![
+            Load(hi),
+            Jump(BPF_JGT, value_hi, Match, Next),
+            Jump(BPF_JEQ, value_hi, Next, Fail),
+            Load(lo),
+            Jump(BPF_JGT, value_lo, Match, Fail),
+        ],
+        LinuxSeccompOperator::ScmpCmpGe => vec![
+            Load(hi),
+            Jump(BPF_JGT, value_hi, Match, Next),
+            Jump(BPF_JEQ, value_hi, Next, Fail),
+            Load(lo),
+            Jump(BPF_JGE, value_lo, Match, Fail),
+        ],
+        // less is the opposite of greater or equal
+        LinuxSeccompOperator::ScmpCmpLt => vec![
+            Load(hi),
+            Jump(BPF_JGT, value_hi, Fail, Next),
+            Jump(BPF_JEQ, value_hi, Next, Match),
+            Load(lo),
+            Jump(BPF_JGE, value_lo, Fail, Match),
+        ],
+        LinuxSeccompOperator::ScmpCmpLe => vec![
+            Load(hi),
+            Jump(BPF_JGT, value_hi, Fail, Next),
+            Jump(BPF_JEQ, value_hi, Next, Match),
+            Load(lo),
+            Jump(BPF_JGT, value_lo, Fail, Match),
+        ],
+    }
+}
+
+/// Returns the offsets of the upper and the lower word of an argument
+fn arg_offsets(index: usize) -> (u32, u32) {
+    let offset = ARGS_OFFSET + 8 * index as u32;
+    if cfg!(target_endian = "little") {
+        (offset + 4, offset)
+    } else {
+        (offset, offset + 4)
+    }
+}
+
+fn stmt(code: u16, k: u32) -> sock_filter {
+    sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(op: u16, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter {
+        code: BPF_JMP | op | BPF_K,
+        jt,
+        jf,
+        k,
+    }
+}
+
+fn load(offset: u32) -> sock_filter {
+    stmt(BPF_LD | BPF_W | BPF_ABS, offset)
+}
+
+fn ret(action: u32) -> sock_filter {
+    stmt(BPF_RET | BPF_K, action)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use anyhow::{Context, Result};
+    use oci_spec::runtime::{LinuxSeccompArgBuilder, LinuxSeccompBuilder, LinuxSyscallBuilder};
+    use serial_test::serial;
+
+    use super::*;
+    use crate::test_utils::{self, TestCallbackError};
+
+    // Further instruction classes and fields of linux/filter.h, which only
+    // the interpreter needs to run the programs of libseccomp
+    const BPF_LDX: u16 = 0x01;
+    const BPF_ST: u16 = 0x02;
+    const BPF_STX: u16 = 0x03;
+    const BPF_MISC: u16 = 0x07;
+    const BPF_IMM: u16 = 0x00;
+    const BPF_MEM: u16 = 0x60;
+    const BPF_X: u16 = 0x08;
+    const BPF_A: u16 = 0x10;
+    const BPF_OR: u16 = 0x40;
+    const BPF_JSET: u16 = 0x40;
+    const BPF_TAX: u16 = 0x00;
+    const BPF_TXA: u16 = 0x80;
+
+    /// Architecture of 32-bit x86 syscalls, which aren't filtered
+    const AUDIT_ARCH_I386: u32 = 0x4000_0003;
+
+    /// Returns the raw struct seccomp_data of a syscall
+    fn seccomp_data(arch: u32, nr: u32, args: [u64; MAX_ARGS]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&nr.to_ne_bytes());
+        data.extend_from_slice(&arch.to_ne_bytes());
+        data.extend_from_slice(&0u64.to_ne_bytes());
+        for arg in args {
+            data.extend_from_slice(&arg.to_ne_bytes());
+        }
+        data
+    }
+
+    /// Runs a program the way the kernel does and returns its action
+    fn run(program: &[sock_filter], data: &[u8]) -> u32 {
+        let word = |k: u32| {
+            let k = k as usize;
+            u32::from_ne_bytes(data[k..k + 4].try_into().unwrap())
+        };
+        let (mut a, mut x) = (0u32, 0u32);
+        let mut mem = [0u32; 16];
+        let mut pc = 0;
+        loop {
+            let insn = program[pc];
+            pc += 1;
+            let src = if insn.code & BPF_X != 0 { x } else { insn.k };
+            match (insn.code & 0x07, insn.code & 0xf8) {
+                (BPF_LD, mode) if mode == BPF_W | BPF_ABS => a = word(insn.k),
+                (BPF_LD, BPF_IMM) => a = insn.k,
+                (BPF_LD, BPF_MEM) => a = mem[insn.k as usize],
+                (BPF_LDX, BPF_IMM) => x = insn.k,
+                (BPF_LDX, BPF_MEM) => x = mem[insn.k as usize],
+                (BPF_ST, _) => mem[insn.k as usize] = a,
+                (BPF_STX, _) => mem[insn.k as usize] = x,
+                (BPF_ALU, op) => match op & 0xf0 {
+                    BPF_AND => a &= src,
+                    BPF_OR => a |= src,
+                    op => panic!("unsupported alu operation {op:#x}"),
+                },
+                (BPF_JMP, op) if op & 0xf0 == BPF_JA => pc += insn.k as usize,
+                (BPF_JMP, op) => {
+                    let taken = match op & 0xf0 {
+                        BPF_JEQ => a == src,
+                        BPF_JGT => a > src,
+                        BPF_JGE => a >= src,
+                        BPF_JSET => a & src != 0,
+                        op => panic!("unsupported jump {op:#x}"),
+                    };
+                    let offset = if taken { insn.jt } else { insn.jf };
+                    pc += offset as usize;
+                }
+                (BPF_RET, BPF_A) => return a,
+                (BPF_RET, _) => return insn.k,
+                (BPF_MISC, _) if insn.code & 0xf8 == BPF_TAX => x = a,
+                (BPF_MISC, _) if insn.code & 0xf8 == BPF_TXA => a = x,
+                _ => panic!("unsupported instruction {:#x}", insn.code),
+            }
+        }
+    }
+
+    fn native() -> u32 {
+        arch::AUDIT_ARCH.expect("native architecture is not supported")
+    }
+
+    fn nr(name: &str) -> u32 {
+        syscalls::number(name).expect("unknown syscall")
+    }
+
+    fn rule(name: &str, action: LinuxSeccompAction) -> LinuxSyscallBuilder {
+        LinuxSyscallBuilder::default()
+            .names(vec![name.to_owned()])
+            .action(action)
+    }
+
+    fn arg(index: usize, op: LinuxSeccompOperator, value: u64) -> LinuxSeccompArgBuilder {
+        LinuxSeccompArgBuilder::default()
+            .index(index)
+            .op(op)
+            .value(value)
+    }
+
+    /// Profile with a rule of every operator and action, comparing values
+    /// which differ in both words of the arguments
+    fn operators_profile() -> Result<LinuxSeccomp> {
+        let syscalls = vec![
+            rule("getcwd", LinuxSeccompAction::ScmpActErrno)
+                .errno_ret(libc::EAGAIN as u32)
+                .build()?,
+            rule("kill", LinuxSeccompAction::ScmpActErrno)
+                .args(vec![arg(1, LinuxSeccompOperator::ScmpCmpEq, 9).build()?])
+                .build()?,
+            rule("read", LinuxSeccompAction::ScmpActErrno)
+                .errno_ret(libc::EBADF as u32)
+                .args(vec![
+                    arg(0, LinuxSeccompOperator::ScmpCmpNe, 0x1_0000_0003).build()?
+                ])
+                .build()?,
+            rule("write", LinuxSeccompAction::ScmpActLog)
+                .args(vec![
+                    arg(2, LinuxSeccompOperator::ScmpCmpLt, 0x2_0000_0000).build()?
+                ])
+                .build()?,
+            rule("close", LinuxSeccompAction::ScmpActTrace)
+                .errno_ret(5u32)
+                .args(vec![arg(0, LinuxSeccompOperator::ScmpCmpLe, 100).build()?])
+                .build()?,
+            rule("openat", LinuxSeccompAction::ScmpActTrap)
+                .args(vec![
+                    arg(2, LinuxSeccompOperator::ScmpCmpGt, 0xffff_ffff).build()?
+                ])
+                .build()?,
+            rule("dup", LinuxSeccompAction::ScmpActKillProcess)
+                .args(vec![
+                    arg(0, LinuxSeccompOperator::ScmpCmpGe, 0x1_0000_0010).build()?
+                ])
+                .build()?,
+            rule("dup3", LinuxSeccompAction::ScmpActKill)
+                .args(vec![arg(2, LinuxSeccompOperator::ScmpCmpMaskedEq, 0x10)
+                    .value_two(0xff_0000_00f0u64)
+                    .build()?])
+                .build()?,
+        ];
+
+        Ok(LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .syscalls(syscalls)
+            .build()?)
+    }
+
+    fn moby_profile() -> Result<LinuxSeccomp> {
+        let fixture_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/seccomp/fixture/config.json");
+        let spec = oci_spec::runtime::Spec::load(fixture_path)
+            .context("Failed to load test spec for seccomp")?;
+
+        spec.linux()
+            .as_ref()
+            .and_then(|linux| linux.seccomp().clone())
+            .context("fixture has no seccomp profile")
+    }
+
+    /// Returns the syscalls to run the programs of a profile with: every
+    /// syscall of the architecture, and for syscalls with comparisons also
+    /// argument values around the compared ones, as well as a syscall of
+    /// another architecture
+    #[cfg(feature = "libseccomp")]
+    fn inputs(seccomp: &LinuxSeccomp) -> Vec<Vec<u8>> {
+        let mut inputs = vec![seccomp_data(AUDIT_ARCH_I386, nr("getpid"), [0; MAX_ARGS])];
+        let syscalls: &[oci_spec::runtime::LinuxSyscall] =
+            seccomp.syscalls().as_deref().unwrap_or_default();
+        for (name, nr) in syscalls::all() {
+            inputs.push(seccomp_data(native(), nr, [0; MAX_ARGS]));
+            let args = syscalls
+                .iter()
+                .filter(|syscall| syscall.names().iter().any(|n| n == name))
+                .flat_map(|syscall| syscall.args().iter().flatten());
+            for arg in args {
+                let value = arg.value();
+                let mask = arg.value_two().unwrap_or(0);
+                for candidate in [
+                    value,
+                    value.wrapping_sub(1),
+                    value.wrapping_add(1),
+                    value ^ (1 << 32),
+                    value | mask,
+                    mask,
+                    u64::MAX,
+                ] {
+                    let mut args = [0; MAX_ARGS];
+                    args[arg.index()] = candidate;
+                    inputs.push(seccomp_data(native(), nr, args));
+                }
+            }
+        }
+
+        inputs
+    }
+
+    #[test]
+    fn test_translate_action() -> Result<()> {
+        for (action, errno, expected) in [
+            (LinuxSeccompAction::ScmpActErrno, None, 0x0005_0001),
+            (LinuxSeccompAction::ScmpActErrno, Some(38), 0x0005_0026),
+            (LinuxSeccompAction::ScmpActTrace, Some(1), 0x7ff0_0001),
+            (LinuxSeccompAction::ScmpActKill, None, 0),
+            (LinuxSeccompAction::ScmpActKillProcess, None, 0x8000_0000),
+            (LinuxSeccompAction::ScmpActLog, None, 0x7ffc_0000),
+            (LinuxSeccompAction::ScmpActNotify, None, 0x7fc0_0000),
+        ] {
+            assert_eq!(translate_action(action, errno)?, expected);
+        }
+        assert!(matches!(
+            translate_action(LinuxSeccompAction::ScmpActTrace, Some(u32::MAX)),
+            Err(SeccompError::TraceAction { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_program() -> Result<()> {
+        let program = build_program(&operators_profile()?)?;
+        let call = |name: &str, args: [u64; MAX_ARGS]| {
+            run(&program, &seccomp_data(native(), nr(name), args))
+        };
+
+        assert_eq!(
+            call("getcwd", [0; 6]),
+            SECCOMP_RET_ERRNO | libc::EAGAIN as u32
+        );
+        assert_eq!(call("getpid", [0; 6]), SECCOMP_RET_ALLOW);
+        assert_eq!(call("kill", [1, 9, 0, 0, 0, 0]), SECCOMP_RET_ERRNO | 1);
+        assert_eq!(call("kill", [1, 15, 0, 0, 0, 0]), SECCOMP_RET_ALLOW);
+        assert_eq!(
+            call("kill", [1, 9 | (1 << 32), 0, 0, 0, 0]),
+            SECCOMP_RET_ALLOW
+        );
+        assert_eq!(call("read", [3, 0, 0, 0, 0, 0]), SECCOMP_RET_ERRNO | 9);
+        assert_eq!(
+            call("read", [0x1_0000_0003, 0, 0, 0, 0, 0]),
+            SECCOMP_RET_ALLOW
+        );
+        assert_eq!(
+            call("write", [1, 0, 0x1_ffff_ffff, 0, 0, 0]),
+            SECCOMP_RET_LOG
+        );
+        assert_eq!(
+            call("write", [1, 0, 0x2_0000_0000, 0, 0, 0]),
+            SECCOMP_RET_ALLOW
+        );
+        assert_eq!(call("close", [100, 0, 0, 0, 0, 0]), SECCOMP_RET_TRACE | 5);
+        assert_eq!(call("close", [101, 0, 0, 0, 0, 0]), SECCOMP_RET_ALLOW);
+        assert_eq!(
+            call("openat", [0, 0, 0x1_0000_0000, 0, 0, 0]),
+            SECCOMP_RET_TRAP
+        );
+        assert_eq!(
+            call("openat", [0, 0, 0xffff_ffff, 0, 0, 0]),
+            SECCOMP_RET_ALLOW
+        );
+        assert_eq!(
+            call("dup", [0x1_0000_0010, 0, 0, 0, 0, 0]),
+            SECCOMP_RET_KILL_PROCESS
+        );
+        assert_eq!(call("dup", [0x10, 0, 0, 0, 0, 0]), SECCOMP_RET_ALLOW);
+        assert_eq!(
+            call("dup3", [0, 0, 0x100_0000_0013, 0, 0, 0]),
+            SECCOMP_RET_KILL_THREAD
+        );
+        assert_eq!(
+            call("dup3", [0, 0, 0x1_0000_0010, 0, 0, 0]),
+            SECCOMP_RET_ALLOW
+        );
+
+        let data = seccomp_data(AUDIT_ARCH_I386, nr("getpid"), [0; 6]);
+        assert_eq!(run(&program, &data), BAD_ARCH_ACTION);
+        if let Some(bit) = arch::X32_SYSCALL_BIT {
+            let data = seccomp_data(native(), nr("getpid") | bit, [0; 6]);
+            assert_eq!(run(&program, &data), BAD_ARCH_ACTION);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unconditional_rule() -> Result<()> {
+        let seccomp = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActErrno)
+            .syscalls(vec![
+                rule("kill", LinuxSeccompAction::ScmpActLog)
+                    .args(vec![arg(1, LinuxSeccompOperator::ScmpCmpEq, 9).build()?])
+                    .build()?,
+                rule("kill", LinuxSeccompAction::ScmpActAllow).build()?,
+                rule("not_a_syscall", LinuxSeccompAction::ScmpActAllow).build()?,
+            ])
+            .build()?;
+        let program = build_program(&seccomp)?;
+        for signal in [9, 15] {
+            let data = seccomp_data(native(), nr("kill"), [1, signal, 0, 0, 0, 0]);
+            assert_eq!(run(&program, &data), SECCOMP_RET_ALLOW);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_arg_index() -> Result<()> {
+        let seccomp = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .syscalls(vec![rule("kill", LinuxSeccompAction::ScmpActErrno)
+                .args(vec![arg(6, LinuxSeccompOperator::ScmpCmpEq, 9).build()?])
+                .build()?])
+            .build()?;
+        assert!(matches!(
+            build_program(&seccomp),
+            Err(SeccompError::ArgIndex(6))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_seccomp() -> Result<()> {
+        let seccomp = moby_profile()?;
+        let program = compile_seccomp(&seccomp)?;
+        let instructions = super::super::parse_program(&program)?;
+        assert_eq!(instructions.len(), build_program(&seccomp)?.len());
+        assert_eq!(super::super::program_bytes(&instructions), program);
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_initialize_seccomp() -> Result<()> {
+        let expect_error = libc::EAGAIN;
+        let seccomp_profile = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .syscalls(vec![rule("getcwd", LinuxSeccompAction::ScmpActErrno)
+                .errno_ret(expect_error as u32)
+                .build()?])
+            .build()?;
+
+        test_utils::test_in_child_process(|| {
+            let _ = prctl::set_no_new_privileges(true);
+            initialize_seccomp(&seccomp_profile).expect("failed to initialize seccomp");
+            match nix::unistd::getcwd() {
+                Err(errno) if errno == nix::errno::Errno::from_raw(expect_error) => Ok(()),
+                ret => Err(TestCallbackError::Custom(format!(
+                    "getcwd didn't fail as the seccomp profile specified: {ret:?}"
+                ))),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_seccomp_notify() -> Result<()> {
+        let seccomp_profile = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .listener_path("/run/seccomp-agent.socket")
+            .syscalls(vec![
+                rule("getcwd", LinuxSeccompAction::ScmpActNotify).build()?
+            ])
+            .build()?;
+
+        test_utils::test_in_child_process(|| {
+            let _ = prctl::set_no_new_privileges(true);
+            match initialize_seccomp(&seccomp_profile) {
+                Ok(Some(fd)) if fd > 0 => Ok(()),
+                ret => Err(TestCallbackError::Custom(format!(
+                    "failed to get a seccomp notify fd: {ret:?}"
+                ))),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    /// The numbers of the syscall table are those libseccomp knows the
+    /// syscalls by, as far as it knows them
+    #[cfg(all(feature = "libseccomp", feature = "seccomp-bpf"))]
+    #[test]
+    fn test_syscall_numbers() {
+        for (name, nr) in syscalls::all() {
+            if let Ok(resolved) = libseccomp::ScmpSyscall::from_name(name) {
+                assert_eq!(i32::from(resolved), nr as i32, "{name}");
+            }
+        }
+    }
+
+    /// Golden tests of the programs of both backends, which have to return
+    /// the same action for every syscall
+    #[cfg(all(feature = "libseccomp", feature = "seccomp-bpf"))]
+    #[test]
+    fn test_same_as_libseccomp() -> Result<()> {
+        for seccomp in [operators_profile()?, moby_profile()?] {
+            let expected = super::super::parse_program(&super::super::compile_seccomp(&seccomp)?)?;
+            let program = build_program(&seccomp)?;
+            for data in inputs(&seccomp) {
+                assert_eq!(
+                    run(&program, &data),
+                    run(&expected, &data),
+                    "different action for {:?}",
+                    &data[..8]
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
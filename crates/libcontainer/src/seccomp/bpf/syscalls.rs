@@ -0,0 +1,417 @@
+//! Numbers of the syscalls of the native architecture, which come from libc
+//! for the target. The few syscalls some of the supported libc versions don't
+//! define are numbered here per architecture from the kernel tables. Syscalls missing
+//! from both, e.g. because they are newer, can't be filtered and are skipped.
+
+/// Returns the number of the syscall on the native architecture
+pub fn number(name: &str) -> Option<u32> {
+    COMMON
+        .iter()
+        .chain(ARCH)
+        .find(|(n, _)| *n == name)
+        .map(|&(_, nr)| nr as u32)
+}
+
+/// Returns all syscalls of the native architecture with their numbers
+pub fn all() -> impl Iterator<Item = (&'static str, u32)> {
+    COMMON
+        .iter()
+        .chain(ARCH)
+        .map(|&(name, nr)| (name, nr as u32))
+}
+
+/// Syscalls of the generic table aarch64 uses, which x86_64 has as well
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const COMMON: &[(&str, libc::c_long)] = &[
+    ("io_setup", libc::SYS_io_setup),
+    ("io_destroy", libc::SYS_io_destroy),
+    ("io_submit", libc::SYS_io_submit),
+    ("io_cancel", libc::SYS_io_cancel),
+    ("io_getevents", libc::SYS_io_getevents),
+    ("setxattr", libc::SYS_setxattr),
+    ("lsetxattr", libc::SYS_lsetxattr),
+    ("fsetxattr", libc::SYS_fsetxattr),
+    ("getxattr", libc::SYS_getxattr),
+    ("lgetxattr", libc::SYS_lgetxattr),
+    ("fgetxattr", libc::SYS_fgetxattr),
+    ("listxattr", libc::SYS_listxattr),
+    ("llistxattr", libc::SYS_llistxattr),
+    ("flistxattr", libc::SYS_flistxattr),
+    ("removexattr", libc::SYS_removexattr),
+    ("lremovexattr", libc::SYS_lremovexattr),
+    ("fremovexattr", libc::SYS_fremovexattr),
+    ("getcwd", libc::SYS_getcwd),
+    ("lookup_dcookie", libc::SYS_lookup_dcookie),
+    ("eventfd2", libc::SYS_eventfd2),
+    ("epoll_create1", libc::SYS_epoll_create1),
+    ("epoll_ctl", libc::SYS_epoll_ctl),
+    ("epoll_pwait", libc::SYS_epoll_pwait),
+    ("dup", libc::SYS_dup),
+    ("dup3", libc::SYS_dup3),
+    ("fcntl", libc::SYS_fcntl),
+    ("inotify_init1", libc::SYS_inotify_init1),
+    ("inotify_add_watch", libc::SYS_inotify_add_watch),
+    ("inotify_rm_watch", libc::SYS_inotify_rm_watch),
+    ("ioctl", libc::SYS_ioctl),
+    ("ioprio_set", libc::SYS_ioprio_set),
+    ("ioprio_get", libc::SYS_ioprio_get),
+    ("flock", libc::SYS_flock),
+    ("mknodat", libc::SYS_mknodat),
+    ("mkdirat", libc::SYS_mkdirat),
+    ("unlinkat", libc::SYS_unlinkat),
+    ("symlinkat", libc::SYS_symlinkat),
+    ("linkat", libc::SYS_linkat),
+    ("renameat", libc::SYS_renameat),
+    ("umount2", libc::SYS_umount2),
+    ("mount", libc::SYS_mount),
+    ("pivot_root", libc::SYS_pivot_root),
+    ("nfsservctl", libc::SYS_nfsservctl),
+    ("statfs", libc::SYS_statfs),
+    ("fstatfs", libc::SYS_fstatfs),
+    ("truncate", libc::SYS_truncate),
+    ("ftruncate", libc::SYS_ftruncate),
+    ("fallocate", libc::SYS_fallocate),
+    ("faccessat", libc::SYS_faccessat),
+    ("chdir", libc::SYS_chdir),
+    ("fchdir", libc::SYS_fchdir),
+    ("chroot", libc::SYS_chroot),
+    ("fchmod", libc::SYS_fchmod),
+    ("fchmodat", libc::SYS_fchmodat),
+    ("fchownat", libc::SYS_fchownat),
+    ("fchown", libc::SYS_fchown),
+    ("openat", libc::SYS_openat),
+    ("close", libc::SYS_close),
+    ("vhangup", libc::SYS_vhangup),
+    ("pipe2", libc::SYS_pipe2),
+    ("quotactl", libc::SYS_quotactl),
+    ("getdents64", libc::SYS_getdents64),
+    ("lseek", libc::SYS_lseek),
+    ("read", libc::SYS_read),
+    ("write", libc::SYS_write),
+    ("readv", libc::SYS_readv),
+    ("writev", libc::SYS_writev),
+    ("pread64", libc::SYS_pread64),
+    ("pwrite64", libc::SYS_pwrite64),
+    ("preadv", libc::SYS_preadv),
+    ("pwritev", libc::SYS_pwritev),
+    ("sendfile", libc::SYS_sendfile),
+    ("pselect6", libc::SYS_pselect6),
+    ("ppoll", libc::SYS_ppoll),
+    ("signalfd4", libc::SYS_signalfd4),
+    ("vmsplice", libc::SYS_vmsplice),
+    ("splice", libc::SYS_splice),
+    ("tee", libc::SYS_tee),
+    ("readlinkat", libc::SYS_readlinkat),
+    ("newfstatat", libc::SYS_newfstatat),
+    ("fstat", libc::SYS_fstat),
+    ("sync", libc::SYS_sync),
+    ("fsync", libc::SYS_fsync),
+    ("fdatasync", libc::SYS_fdatasync),
+    ("sync_file_range", libc::SYS_sync_file_range),
+    ("timerfd_create", libc::SYS_timerfd_create),
+    ("timerfd_settime", libc::SYS_timerfd_settime),
+    ("timerfd_gettime", libc::SYS_timerfd_gettime),
+    ("utimensat", libc::SYS_utimensat),
+    ("acct", libc::SYS_acct),
+    ("capget", libc::SYS_capget),
+    ("capset", libc::SYS_capset),
+    ("personality", libc::SYS_personality),
+    ("exit", libc::SYS_exit),
+    ("exit_group", libc::SYS_exit_group),
+    ("waitid", libc::SYS_waitid),
+    ("set_tid_address", libc::SYS_set_tid_address),
+    ("unshare", libc::SYS_unshare),
+    ("futex", libc::SYS_futex),
+    ("set_robust_list", libc::SYS_set_robust_list),
+    ("get_robust_list", libc::SYS_get_robust_list),
+    ("nanosleep", libc::SYS_nanosleep),
+    ("getitimer", libc::SYS_getitimer),
+    ("setitimer", libc::SYS_setitimer),
+    ("kexec_load", libc::SYS_kexec_load),
+    ("init_module", libc::SYS_init_module),
+    ("delete_module", libc::SYS_delete_module),
+    ("timer_create", libc::SYS_timer_create),
+    ("timer_gettime", libc::SYS_timer_gettime),
+    ("timer_getoverrun", libc::SYS_timer_getoverrun),
+    ("timer_settime", libc::SYS_timer_settime),
+    ("timer_delete", libc::SYS_timer_delete),
+    ("clock_settime", libc::SYS_clock_settime),
+    ("clock_gettime", libc::SYS_clock_gettime),
+    ("clock_getres", libc::SYS_clock_getres),
+    ("clock_nanosleep", libc::SYS_clock_nanosleep),
+    ("syslog", libc::SYS_syslog),
+    ("ptrace", libc::SYS_ptrace),
+    ("sched_setparam", libc::SYS_sched_setparam),
+    ("sched_setscheduler", libc::SYS_sched_setscheduler),
+    ("sched_getscheduler", libc::SYS_sched_getscheduler),
+    ("sched_getparam", libc::SYS_sched_getparam),
+    ("sched_setaffinity", libc::SYS_sched_setaffinity),
+    ("sched_getaffinity", libc::SYS_sched_getaffinity),
+    ("sched_yield", libc::SYS_sched_yield),
+    ("sched_get_priority_max", libc::SYS_sched_get_priority_max),
+    ("sched_get_priority_min", libc::SYS_sched_get_priority_min),
+    ("sched_rr_get_interval", libc::SYS_sched_rr_get_interval),
+    ("restart_syscall", libc::SYS_restart_syscall),
+    ("kill", libc::SYS_kill),
+    ("tkill", libc::SYS_tkill),
+    ("tgkill", libc::SYS_tgkill),
+    ("sigaltstack", libc::SYS_sigaltstack),
+    ("rt_sigsuspend", libc::SYS_rt_sigsuspend),
+    ("rt_sigaction", libc::SYS_rt_sigaction),
+    ("rt_sigprocmask", libc::SYS_rt_sigprocmask),
+    ("rt_sigpending", libc::SYS_rt_sigpending),
+    ("rt_sigtimedwait", libc::SYS_rt_sigtimedwait),
+    ("rt_sigqueueinfo", libc::SYS_rt_sigqueueinfo),
+    ("rt_sigreturn", libc::SYS_rt_sigreturn),
+    ("setpriority", libc::SYS_setpriority),
+    ("getpriority", libc::SYS_getpriority),
+    ("reboot", libc::SYS_reboot),
+    ("setregid", libc::SYS_setregid),
+    ("setgid", libc::SYS_setgid),
+    ("setreuid", libc::SYS_setreuid),
+    ("setuid", libc::SYS_setuid),
+    ("setresuid", libc::SYS_setresuid),
+    ("getresuid", libc::SYS_getresuid),
+    ("setresgid", libc::SYS_setresgid),
+    ("getresgid", libc::SYS_getresgid),
+    ("setfsuid", libc::SYS_setfsuid),
+    ("setfsgid", libc::SYS_setfsgid),
+    ("times", libc::SYS_times),
+    ("setpgid", libc::SYS_setpgid),
+    ("getpgid", libc::SYS_getpgid),
+    ("getsid", libc::SYS_getsid),
+    ("setsid", libc::SYS_setsid),
+    ("getgroups", libc::SYS_getgroups),
+    ("setgroups", libc::SYS_setgroups),
+    ("uname", libc::SYS_uname),
+    ("sethostname", libc::SYS_sethostname),
+    ("setdomainname", libc::SYS_setdomainname),
+    ("getrlimit", libc::SYS_getrlimit),
+    ("setrlimit", libc::SYS_setrlimit),
+    ("getrusage", libc::SYS_getrusage),
+    ("umask", libc::SYS_umask),
+    ("prctl", libc::SYS_prctl),
+    ("getcpu", libc::SYS_getcpu),
+    ("gettimeofday", libc::SYS_gettimeofday),
+    ("settimeofday", libc::SYS_settimeofday),
+    ("adjtimex", libc::SYS_adjtimex),
+    ("getpid", libc::SYS_getpid),
+    ("getppid", libc::SYS_getppid),
+    ("getuid", libc::SYS_getuid),
+    ("geteuid", libc::SYS_geteuid),
+    ("getgid", libc::SYS_getgid),
+    ("getegid", libc::SYS_getegid),
+    ("gettid", libc::SYS_gettid),
+    ("sysinfo", libc::SYS_sysinfo),
+    ("mq_open", libc::SYS_mq_open),
+    ("mq_unlink", libc::SYS_mq_unlink),
+    ("mq_timedsend", libc::SYS_mq_timedsend),
+    ("mq_timedreceive", libc::SYS_mq_timedreceive),
+    ("mq_notify", libc::SYS_mq_notify),
+    ("mq_getsetattr", libc::SYS_mq_getsetattr),
+    ("msgget", libc::SYS_msgget),
+    ("msgctl", libc::SYS_msgctl),
+    ("msgrcv", libc::SYS_msgrcv),
+    ("msgsnd", libc::SYS_msgsnd),
+    ("semget", libc::SYS_semget),
+    ("semctl", libc::SYS_semctl),
+    ("semtimedop", libc::SYS_semtimedop),
+    ("semop", libc::SYS_semop),
+    ("shmget", libc::SYS_shmget),
+    ("shmctl", libc::SYS_shmctl),
+    ("shmat", libc::SYS_shmat),
+    ("shmdt", libc::SYS_shmdt),
+    ("socket", libc::SYS_socket),
+    ("socketpair", libc::SYS_socketpair),
+    ("bind", libc::SYS_bind),
+    ("listen", libc::SYS_listen),
+    ("accept", libc::SYS_accept),
+    ("connect", libc::SYS_connect),
+    ("getsockname", libc::SYS_getsockname),
+    ("getpeername", libc::SYS_getpeername),
+    ("sendto", libc::SYS_sendto),
+    ("recvfrom", libc::SYS_recvfrom),
+    ("setsockopt", libc::SYS_setsockopt),
+    ("getsockopt", libc::SYS_getsockopt),
+    ("shutdown", libc::SYS_shutdown),
+    ("sendmsg", libc::SYS_sendmsg),
+    ("recvmsg", libc::SYS_recvmsg),
+    ("readahead", libc::SYS_readahead),
+    ("brk", libc::SYS_brk),
+    ("munmap", libc::SYS_munmap),
+    ("mremap", libc::SYS_mremap),
+    ("add_key", libc::SYS_add_key),
+    ("request_key", libc::SYS_request_key),
+    ("keyctl", libc::SYS_keyctl),
+    ("clone", libc::SYS_clone),
+    ("execve", libc::SYS_execve),
+    ("mmap", libc::SYS_mmap),
+    ("fadvise64", libc::SYS_fadvise64),
+    ("swapon", libc::SYS_swapon),
+    ("swapoff", libc::SYS_swapoff),
+    ("mprotect", libc::SYS_mprotect),
+    ("msync", libc::SYS_msync),
+    ("mlock", libc::SYS_mlock),
+    ("munlock", libc::SYS_munlock),
+    ("mlockall", libc::SYS_mlockall),
+    ("munlockall", libc::SYS_munlockall),
+    ("mincore", libc::SYS_mincore),
+    ("madvise", libc::SYS_madvise),
+    ("remap_file_pages", libc::SYS_remap_file_pages),
+    ("mbind", libc::SYS_mbind),
+    ("get_mempolicy", libc::SYS_get_mempolicy),
+    ("set_mempolicy", libc::SYS_set_mempolicy),
+    ("migrate_pages", libc::SYS_migrate_pages),
+    ("move_pages", libc::SYS_move_pages),
+    ("rt_tgsigqueueinfo", libc::SYS_rt_tgsigqueueinfo),
+    ("perf_event_open", libc::SYS_perf_event_open),
+    ("accept4", libc::SYS_accept4),
+    ("recvmmsg", libc::SYS_recvmmsg),
+    ("wait4", libc::SYS_wait4),
+    ("prlimit64", libc::SYS_prlimit64),
+    ("fanotify_init", libc::SYS_fanotify_init),
+    ("fanotify_mark", libc::SYS_fanotify_mark),
+    ("name_to_handle_at", libc::SYS_name_to_handle_at),
+    ("open_by_handle_at", libc::SYS_open_by_handle_at),
+    ("clock_adjtime", libc::SYS_clock_adjtime),
+    ("syncfs", libc::SYS_syncfs),
+    ("setns", libc::SYS_setns),
+    ("sendmmsg", libc::SYS_sendmmsg),
+    ("process_vm_readv", libc::SYS_process_vm_readv),
+    ("process_vm_writev", libc::SYS_process_vm_writev),
+    ("kcmp", libc::SYS_kcmp),
+    ("finit_module", libc::SYS_finit_module),
+    ("sched_setattr", libc::SYS_sched_setattr),
+    ("sched_getattr", libc::SYS_sched_getattr),
+    ("renameat2", libc::SYS_renameat2),
+    ("seccomp", libc::SYS_seccomp),
+    ("getrandom", libc::SYS_getrandom),
+    ("memfd_create", libc::SYS_memfd_create),
+    ("bpf", libc::SYS_bpf),
+    ("execveat", libc::SYS_execveat),
+    ("userfaultfd", libc::SYS_userfaultfd),
+    ("membarrier", libc::SYS_membarrier),
+    ("mlock2", libc::SYS_mlock2),
+    ("copy_file_range", libc::SYS_copy_file_range),
+    ("preadv2", libc::SYS_preadv2),
+    ("pwritev2", libc::SYS_pwritev2),
+    ("pkey_mprotect", libc::SYS_pkey_mprotect),
+    ("pkey_alloc", libc::SYS_pkey_alloc),
+    ("pkey_free", libc::SYS_pkey_free),
+    ("statx", libc::SYS_statx),
+    ("io_pgetevents", SYS_IO_PGETEVENTS),
+    ("rseq", libc::SYS_rseq),
+    ("kexec_file_load", libc::SYS_kexec_file_load),
+    ("pidfd_send_signal", libc::SYS_pidfd_send_signal),
+    ("io_uring_setup", libc::SYS_io_uring_setup),
+    ("io_uring_enter", libc::SYS_io_uring_enter),
+    ("io_uring_register", libc::SYS_io_uring_register),
+    ("open_tree", libc::SYS_open_tree),
+    ("move_mount", libc::SYS_move_mount),
+    ("fsopen", libc::SYS_fsopen),
+    ("fsconfig", libc::SYS_fsconfig),
+    ("fsmount", libc::SYS_fsmount),
+    ("fspick", libc::SYS_fspick),
+    ("pidfd_open", libc::SYS_pidfd_open),
+    ("clone3", libc::SYS_clone3),
+    ("close_range", libc::SYS_close_range),
+    ("openat2", libc::SYS_openat2),
+    ("pidfd_getfd", libc::SYS_pidfd_getfd),
+    ("faccessat2", libc::SYS_faccessat2),
+    ("process_madvise", libc::SYS_process_madvise),
+    ("epoll_pwait2", libc::SYS_epoll_pwait2),
+    ("mount_setattr", libc::SYS_mount_setattr),
+    ("quotactl_fd", libc::SYS_quotactl_fd),
+    ("landlock_create_ruleset", libc::SYS_landlock_create_ruleset),
+    ("landlock_add_rule", libc::SYS_landlock_add_rule),
+    ("landlock_restrict_self", libc::SYS_landlock_restrict_self),
+    ("memfd_secret", libc::SYS_memfd_secret),
+    ("process_mrelease", libc::SYS_process_mrelease),
+    ("futex_waitv", libc::SYS_futex_waitv),
+    ("set_mempolicy_home_node", libc::SYS_set_mempolicy_home_node),
+];
+
+/// Not defined by libc for the gnu targets yet
+#[cfg(target_arch = "x86_64")]
+const SYS_IO_PGETEVENTS: libc::c_long = 333;
+#[cfg(target_arch = "aarch64")]
+const SYS_IO_PGETEVENTS: libc::c_long = 292;
+
+/// Removed from libc, since the kernel dropped these syscalls
+#[cfg(target_arch = "x86_64")]
+const SYS_CREATE_MODULE: libc::c_long = 174;
+#[cfg(target_arch = "x86_64")]
+const SYS_GET_KERNEL_SYMS: libc::c_long = 177;
+#[cfg(target_arch = "x86_64")]
+const SYS_QUERY_MODULE: libc::c_long = 178;
+
+/// Legacy syscalls x86_64 kept, which the generic table replaced with their
+/// *at and 2 variants
+#[cfg(target_arch = "x86_64")]
+const ARCH: &[(&str, libc::c_long)] = &[
+    ("open", libc::SYS_open),
+    ("stat", libc::SYS_stat),
+    ("lstat", libc::SYS_lstat),
+    ("poll", libc::SYS_poll),
+    ("access", libc::SYS_access),
+    ("pipe", libc::SYS_pipe),
+    ("select", libc::SYS_select),
+    ("dup2", libc::SYS_dup2),
+    ("pause", libc::SYS_pause),
+    ("alarm", libc::SYS_alarm),
+    ("fork", libc::SYS_fork),
+    ("vfork", libc::SYS_vfork),
+    ("getdents", libc::SYS_getdents),
+    ("rename", libc::SYS_rename),
+    ("mkdir", libc::SYS_mkdir),
+    ("rmdir", libc::SYS_rmdir),
+    ("creat", libc::SYS_creat),
+    ("link", libc::SYS_link),
+    ("unlink", libc::SYS_unlink),
+    ("symlink", libc::SYS_symlink),
+    ("readlink", libc::SYS_readlink),
+    ("chmod", libc::SYS_chmod),
+    ("chown", libc::SYS_chown),
+    ("lchown", libc::SYS_lchown),
+    ("getpgrp", libc::SYS_getpgrp),
+    ("utime", libc::SYS_utime),
+    ("utimes", libc::SYS_utimes),
+    ("mknod", libc::SYS_mknod),
+    ("uselib", libc::SYS_uselib),
+    ("ustat", libc::SYS_ustat),
+    ("sysfs", libc::SYS_sysfs),
+    ("_sysctl", libc::SYS__sysctl),
+    ("modify_ldt", libc::SYS_modify_ldt),
+    ("arch_prctl", libc::SYS_arch_prctl),
+    ("iopl", libc::SYS_iopl),
+    ("ioperm", libc::SYS_ioperm),
+    ("create_module", SYS_CREATE_MODULE),
+    ("get_kernel_syms", SYS_GET_KERNEL_SYMS),
+    ("query_module", SYS_QUERY_MODULE),
+    ("getpmsg", libc::SYS_getpmsg),
+    ("putpmsg", libc::SYS_putpmsg),
+    ("afs_syscall", libc::SYS_afs_syscall),
+    ("tuxcall", libc::SYS_tuxcall),
+    ("security", libc::SYS_security),
+    ("vserver", libc::SYS_vserver),
+    ("set_thread_area", libc::SYS_set_thread_area),
+    ("get_thread_area", libc::SYS_get_thread_area),
+    ("epoll_create", libc::SYS_epoll_create),
+    ("epoll_ctl_old", libc::SYS_epoll_ctl_old),
+    ("epoll_wait_old", libc::SYS_epoll_wait_old),
+    ("epoll_wait", libc::SYS_epoll_wait),
+    ("time", libc::SYS_time),
+    ("futimesat", libc::SYS_futimesat),
+    ("inotify_init", libc::SYS_inotify_init),
+    ("signalfd", libc::SYS_signalfd),
+    ("eventfd", libc::SYS_eventfd),
+];
+
+#[cfg(target_arch = "aarch64")]
+const ARCH: &[(&str, libc::c_long)] = &[];
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const COMMON: &[(&str, libc::c_long)] = &[];
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const ARCH: &[(&str, libc::c_long)] = &[];
@@ -0,0 +1,147 @@
+//! Cache of the BPF programs seccomp filters are compiled to, so that
+//! containers with the same profile, e.g. the default profile of Docker, don't
+//! each pay for compiling it again. Programs are keyed by the profile and the
+//! backend which compiled them, with the version of libseccomp or of
+//! libcontainer for the built-in one. Filters which notify a listener aren't
+//! cached, as the notify fd is only returned when the backend loads the
+//! filter itself.
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, DirBuilder};
+use std::hash::{Hash, Hasher};
+use std::io::ErrorKind;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use oci_spec::runtime::LinuxSeccomp;
+use serde::{Deserialize, Serialize};
+
+use super::SeccompError;
+use crate::features;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SeccompCacheError {
+    #[error("failed to serialize seccomp profile")]
+    Serialize(#[source] serde_json::Error),
+    #[error("failed to compile seccomp profile")]
+    Compile(#[source] SeccompError),
+    #[error("failed to access seccomp cache {path:?}")]
+    Io {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+}
+
+type Result<T> = std::result::Result<T, SeccompCacheError>;
+
+/// Cached program, which stores the full key so that a hash collision can't
+/// load the program of another profile
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    program: Vec<u8>,
+}
+
+/// Returns the program the profile compiles to from the cache in the
+/// directory, compiling the profile and adding the program to the cache if it
+/// isn't cached yet. Returns None for profiles which can't be cached.
+pub fn load_or_compile(dir: &Path, seccomp: &LinuxSeccomp) -> Result<Option<Vec<u8>>> {
+    if super::is_notify(seccomp) {
+        return Ok(None);
+    }
+
+    let version =
+        features::libseccomp_version().unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_owned());
+    let key = format!(
+        "{} {}\n{}",
+        features::seccomp_backend().unwrap_or_default(),
+        version,
+        serde_json::to_string(seccomp).map_err(SeccompCacheError::Serialize)?
+    );
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    let path = dir.join(format!("{:016x}.json", hasher.finish()));
+
+    match fs::read(&path) {
+        Ok(content) => match serde_json::from_slice::<CacheEntry>(&content) {
+            Ok(entry) if entry.key == key => {
+                tracing::debug!(?path, "loaded seccomp program from cache");
+                return Ok(Some(entry.program));
+            }
+            // a colliding or corrupted entry is replaced
+            _ => tracing::debug!(?path, "ignoring invalid seccomp cache entry"),
+        },
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(SeccompCacheError::Io { source: err, path }),
+    }
+
+    let program = super::compile_seccomp(seccomp).map_err(SeccompCacheError::Compile)?;
+    let entry = CacheEntry { key, program };
+    store(dir, &path, &entry).map_err(|err| SeccompCacheError::Io { source: err, path })?;
+
+    Ok(Some(entry.program))
+}
+
+// the entry is replaced atomically, as containers might be created
+// concurrently
+fn store(dir: &Path, path: &Path, entry: &CacheEntry) -> std::io::Result<()> {
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    let tmp_path = path.with_extension(format!("{}.tmp", process::id()));
+    let content = serde_json::to_vec(entry)?;
+    let result = fs::write(&tmp_path, content).and_then(|_| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use oci_spec::runtime::{Arch, LinuxSeccompAction, LinuxSeccompBuilder, LinuxSyscallBuilder};
+
+    use super::*;
+
+    fn profile(action: LinuxSeccompAction) -> Result<LinuxSeccomp> {
+        let syscall = LinuxSyscallBuilder::default()
+            .names(vec![String::from("getcwd")])
+            .action(action)
+            .build()?;
+        Ok(LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .architectures(vec![Arch::ScmpArchNative])
+            .listener_path("/run/seccomp-agent.socket")
+            .syscalls(vec![syscall])
+            .build()?)
+    }
+
+    #[test]
+    fn test_load_or_compile() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path().join("cache");
+        let seccomp = profile(LinuxSeccompAction::ScmpActErrno)?;
+
+        let program = load_or_compile(&dir, &seccomp)?.expect("program is cacheable");
+        assert_eq!(program, super::super::compile_seccomp(&seccomp)?);
+        let entries: Vec<_> = fs::read_dir(&dir)?.collect::<std::io::Result<_>>()?;
+        assert_eq!(entries.len(), 1);
+
+        // an entry of another profile under the same name isn't loaded
+        fs::write(
+            entries[0].path(),
+            serde_json::to_vec(&CacheEntry {
+                key: "other".to_owned(),
+                program: vec![0; 8],
+            })?,
+        )?;
+        assert_eq!(load_or_compile(&dir, &seccomp)?, Some(program.clone()));
+        assert_eq!(load_or_compile(&dir, &seccomp)?, Some(program));
+
+        assert_eq!(
+            load_or_compile(&dir, &profile(LinuxSeccompAction::ScmpActNotify)?)?,
+            None
+        );
+        Ok(())
+    }
+}
@@ -1,12 +1,39 @@
+//! Seccomp filters of containers. Profiles are compiled with libseccomp by
+//! default. Without the `libseccomp` feature, the [`bpf`] backend enabled by
+//! the `seccomp-bpf` feature compiles them instead.
+#[cfg(feature = "libseccomp")]
+use std::fs::File;
+#[cfg(feature = "libseccomp")]
+use std::io::{Read, Seek, SeekFrom};
 use std::num::TryFromIntError;
+#[cfg(feature = "libseccomp")]
+use std::os::fd::FromRawFd;
+#[cfg(feature = "libseccomp")]
 use std::os::unix::io;
 
+#[cfg(feature = "libseccomp")]
 use libseccomp::{
     ScmpAction, ScmpArch, ScmpArgCompare, ScmpCompareOp, ScmpFilterContext, ScmpSyscall,
 };
-use oci_spec::runtime::{
-    Arch, LinuxSeccomp, LinuxSeccompAction, LinuxSeccompFilterFlag, LinuxSeccompOperator,
-};
+use nix::errno::Errno;
+#[cfg(feature = "libseccomp")]
+use oci_spec::runtime::{Arch, LinuxSeccompOperator};
+use oci_spec::runtime::{LinuxSeccomp, LinuxSeccompAction, LinuxSeccompFilterFlag};
+
+use crate::socket_address::{SocketAddress, SocketAddressError};
+
+#[cfg(feature = "seccomp-bpf")]
+pub mod bpf;
+pub mod cache;
+
+#[cfg(not(feature = "libseccomp"))]
+pub use self::bpf::{compile_seccomp, initialize_seccomp};
+
+/// Size of a single BPF instruction, i.e. of `struct sock_filter`
+const BPF_INSTRUCTION_SIZE: usize = std::mem::size_of::<libc::sock_filter>();
+
+/// Flag of seccomp(2) to return a notify fd for the loaded filter
+const SECCOMP_FILTER_FLAG_NEW_LISTENER: libc::c_ulong = 1 << 3;
 
 #[derive(Debug, thiserror::Error)]
 pub enum SeccompError {
@@ -16,42 +43,76 @@ pub enum SeccompError {
     NotifyAsDefaultAction,
     #[error("SCMP_ACT_NOTIFY cannot be used for the write syscall")]
     NotifyWriteSyscall,
+    #[error("SCMP_ACT_NOTIFY requires listenerPath to be set")]
+    NotifyWithoutListenerPath,
+    #[error("seccomp listenerPath must be an absolute path: {0:?}")]
+    RelativeListenerPath(std::path::PathBuf),
+    #[error("invalid seccomp listenerPath")]
+    InvalidListenerPath(#[source] SocketAddressError),
+    #[cfg(feature = "libseccomp")]
     #[error("failed to add arch to seccomp")]
     AddArch {
         source: libseccomp::error::SeccompError,
         arch: Arch,
     },
+    #[cfg(feature = "libseccomp")]
     #[error("failed to load seccomp context")]
     LoadContext {
         source: libseccomp::error::SeccompError,
     },
+    #[cfg(feature = "libseccomp")]
     #[error("failed to get seccomp notify id")]
     GetNotifyId {
         source: libseccomp::error::SeccompError,
     },
+    #[cfg(feature = "libseccomp")]
     #[error("failed to add rule to seccomp")]
     AddRule {
         source: libseccomp::error::SeccompError,
     },
+    #[cfg(feature = "libseccomp")]
     #[error("failed to create new seccomp filter")]
     NewFilter {
         source: libseccomp::error::SeccompError,
         default: LinuxSeccompAction,
     },
+    #[cfg(feature = "libseccomp")]
     #[error("failed to set filter flag")]
     SetFilterFlag {
         source: libseccomp::error::SeccompError,
         flag: LinuxSeccompFilterFlag,
     },
+    #[cfg(feature = "libseccomp")]
     #[error("failed to set SCMP_FLTATR_CTL_NNP")]
     SetCtlNnp {
         source: libseccomp::error::SeccompError,
     },
+    #[cfg(feature = "libseccomp")]
+    #[error("failed to export seccomp filter as BPF program")]
+    ExportBpf {
+        source: libseccomp::error::SeccompError,
+    },
+    #[error("failed to read exported BPF program")]
+    ReadBpf(#[source] std::io::Error),
+    #[error("invalid BPF program of {0} bytes")]
+    InvalidBpf(usize),
+    #[error("failed to load BPF program")]
+    LoadBpf(#[source] nix::Error),
+    #[cfg(feature = "seccomp-bpf")]
+    #[error("the bpf seccomp backend doesn't support the architecture {0}")]
+    UnsupportedArch(&'static str),
+    #[cfg(feature = "seccomp-bpf")]
+    #[error("seccomp argument index {0} is out of range")]
+    ArgIndex(usize),
+    #[cfg(feature = "seccomp-bpf")]
+    #[error("seccomp filter compiles to {0} instructions, more than the kernel accepts")]
+    ProgramTooLarge(usize),
 }
 
 type Result<T> = std::result::Result<T, SeccompError>;
 
-fn translate_arch(arch: Arch) -> ScmpArch {
+#[cfg(feature = "libseccomp")]
+pub(crate) fn translate_arch(arch: Arch) -> ScmpArch {
     match arch {
         Arch::ScmpArchNative => ScmpArch::Native,
         Arch::ScmpArchX86 => ScmpArch::X86,
@@ -73,6 +134,27 @@ fn translate_arch(arch: Arch) -> ScmpArch {
     }
 }
 
+/// Returns the architectures processes of the architecture can switch to, e.g.
+/// by running 32-bit binaries, which need to be filtered as well. The same as
+/// runc, these are added to the filter so that a profile listing only the main
+/// architecture can't be bypassed by using the syscall ABI of another one. The
+/// native architecture is covered by [`crate::arch::SECCOMP_ARCHS`] instead.
+#[cfg(feature = "libseccomp")]
+fn compatible_archs(arch: ScmpArch) -> &'static [ScmpArch] {
+    match arch {
+        ScmpArch::X8664 => &[ScmpArch::X86, ScmpArch::X32],
+        ScmpArch::Aarch64 => &[ScmpArch::Arm],
+        ScmpArch::Mips64 => &[ScmpArch::Mips, ScmpArch::Mips64N32],
+        ScmpArch::Mips64N32 => &[ScmpArch::Mips, ScmpArch::Mips64],
+        ScmpArch::Mipsel64 => &[ScmpArch::Mipsel, ScmpArch::Mipsel64N32],
+        ScmpArch::Mipsel64N32 => &[ScmpArch::Mipsel, ScmpArch::Mipsel64],
+        ScmpArch::Ppc64 => &[ScmpArch::Ppc],
+        ScmpArch::S390X => &[ScmpArch::S390],
+        _ => &[],
+    }
+}
+
+#[cfg(feature = "libseccomp")]
 fn translate_action(action: LinuxSeccompAction, errno: Option<u32>) -> Result<ScmpAction> {
     tracing::trace!(?action, ?errno, "translating action");
     let errno = errno.map(|e| e as i32).unwrap_or(libc::EPERM);
@@ -95,6 +177,7 @@ fn translate_action(action: LinuxSeccompAction, errno: Option<u32>) -> Result<Sc
     Ok(action)
 }
 
+#[cfg(feature = "libseccomp")]
 fn translate_op(op: LinuxSeccompOperator, datum_b: Option<u64>) -> ScmpCompareOp {
     match op {
         LinuxSeccompOperator::ScmpCmpNe => ScmpCompareOp::NotEqual,
@@ -107,7 +190,7 @@ fn translate_op(op: LinuxSeccompOperator, datum_b: Option<u64>) -> ScmpCompareOp
     }
 }
 
-fn check_seccomp(seccomp: &LinuxSeccomp) -> Result<()> {
+pub fn check_seccomp(seccomp: &LinuxSeccomp) -> Result<()> {
     // We don't support notify as default action. After the seccomp filter is
     // created with notify, the container process will have to communicate the
     // returned fd to another process. Therefore, we need the write syscall or
@@ -134,11 +217,157 @@ fn check_seccomp(seccomp: &LinuxSeccomp) -> Result<()> {
         }
     }
 
+    // The notify fd is handed over to the seccomp agent listening on
+    // listenerPath. Without it, the container process would be left with
+    // syscalls that nobody is ever going to answer.
+    if is_notify(seccomp) {
+        match seccomp.listener_path() {
+            None => return Err(SeccompError::NotifyWithoutListenerPath),
+            Some(path) => match SocketAddress::parse(path) {
+                Ok(SocketAddress::Path(path)) if !path.is_absolute() => {
+                    return Err(SeccompError::RelativeListenerPath(path))
+                }
+                Ok(_) => {}
+                Err(err) => return Err(SeccompError::InvalidListenerPath(err)),
+            },
+        }
+    }
+
     Ok(())
 }
 
+#[cfg(feature = "libseccomp")]
 #[tracing::instrument(level = "trace", skip(seccomp))]
 pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
+    let ctx = build_filter(seccomp)?;
+
+    // In order to use the SECCOMP_SET_MODE_FILTER operation, either the calling
+    // thread must have the CAP_SYS_ADMIN capability in its user namespace, or
+    // the thread must already have the no_new_privs bit set.
+    // Ref: https://man7.org/linux/man-pages/man2/seccomp.2.html
+    ctx.load()
+        .map_err(|err| SeccompError::LoadContext { source: err })?;
+
+    let fd = if is_notify(seccomp) {
+        Some(
+            ctx.get_notify_fd()
+                .map_err(|err| SeccompError::GetNotifyId { source: err })?,
+        )
+    } else {
+        None
+    };
+
+    Ok(fd)
+}
+
+/// Compiles the filter to the BPF program the kernel runs, which can be
+/// loaded with [`load_seccomp_program`] later on
+#[cfg(feature = "libseccomp")]
+pub fn compile_seccomp(seccomp: &LinuxSeccomp) -> Result<Vec<u8>> {
+    let ctx = build_filter(seccomp)?;
+
+    let name = b"seccomp-bpf\0";
+    let fd = unsafe { libc::memfd_create(name.as_ptr().cast(), libc::MFD_CLOEXEC) };
+    let mut file = Errno::result(fd)
+        .map(|fd| unsafe { File::from_raw_fd(fd) })
+        .map_err(|err| SeccompError::ReadBpf(err.into()))?;
+    ctx.export_bpf(&mut file)
+        .map_err(|err| SeccompError::ExportBpf { source: err })?;
+
+    let mut program = Vec::new();
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| file.read_to_end(&mut program))
+        .map_err(SeccompError::ReadBpf)?;
+
+    Ok(program)
+}
+
+/// Loads a BPF program the filter has been compiled to with
+/// [`compile_seccomp`], which requires the same privileges as
+/// [`initialize_seccomp`]. Filters which notify a listener have to be loaded
+/// with [`initialize_seccomp`] in order to get the notify fd.
+pub fn load_seccomp_program(seccomp: &LinuxSeccomp, program: &[u8]) -> Result<()> {
+    let mut instructions = parse_program(program)?;
+    load_filter(&mut instructions, filter_flags(seccomp)).map(|_| ())
+}
+
+/// Returns the instructions of a BPF program in its binary form
+fn parse_program(program: &[u8]) -> Result<Vec<libc::sock_filter>> {
+    if program.len() % BPF_INSTRUCTION_SIZE != 0
+        || program.len() / BPF_INSTRUCTION_SIZE > u16::MAX as usize
+    {
+        return Err(SeccompError::InvalidBpf(program.len()));
+    }
+
+    Ok(program
+        .chunks_exact(BPF_INSTRUCTION_SIZE)
+        .map(|chunk| libc::sock_filter {
+            code: u16::from_ne_bytes([chunk[0], chunk[1]]),
+            jt: chunk[2],
+            jf: chunk[3],
+            k: u32::from_ne_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]),
+        })
+        .collect())
+}
+
+/// Returns the binary form of a BPF program, as exported by libseccomp
+#[cfg(feature = "seccomp-bpf")]
+fn program_bytes(instructions: &[libc::sock_filter]) -> Vec<u8> {
+    let mut program = Vec::with_capacity(instructions.len() * BPF_INSTRUCTION_SIZE);
+    for instruction in instructions {
+        program.extend_from_slice(&instruction.code.to_ne_bytes());
+        program.push(instruction.jt);
+        program.push(instruction.jf);
+        program.extend_from_slice(&instruction.k.to_ne_bytes());
+    }
+    program
+}
+
+fn filter_flags(seccomp: &LinuxSeccomp) -> libc::c_ulong {
+    seccomp
+        .flags()
+        .iter()
+        .flatten()
+        .fold(0, |flags, flag| match flag {
+            LinuxSeccompFilterFlag::SeccompFilterFlagLog => flags | libc::SECCOMP_FILTER_FLAG_LOG,
+            LinuxSeccompFilterFlag::SeccompFilterFlagTsync => {
+                flags | libc::SECCOMP_FILTER_FLAG_TSYNC
+            }
+            LinuxSeccompFilterFlag::SeccompFilterFlagSpecAllow => {
+                flags | libc::SECCOMP_FILTER_FLAG_SPEC_ALLOW
+            }
+        })
+}
+
+/// Loads the instructions as filter of the calling thread. Returns the
+/// notify fd for filters loaded with SECCOMP_FILTER_FLAG_NEW_LISTENER, and 0
+/// otherwise.
+fn load_filter(instructions: &mut [libc::sock_filter], flags: libc::c_ulong) -> Result<i32> {
+    let prog = libc::sock_fprog {
+        len: instructions.len() as u16,
+        filter: instructions.as_mut_ptr(),
+    };
+
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            flags,
+            &prog as *const libc::sock_fprog,
+        )
+    };
+    // with TSYNC, a positive result is the id of a thread which couldn't be
+    // synchronized, the kernel refuses to combine it with a new listener
+    match Errno::result(res) {
+        Ok(fd) if flags & SECCOMP_FILTER_FLAG_NEW_LISTENER != 0 => Ok(fd as i32),
+        Ok(0) => Ok(0),
+        Ok(_) => Err(SeccompError::LoadBpf(Errno::ESRCH)),
+        Err(err) => Err(SeccompError::LoadBpf(err)),
+    }
+}
+
+#[cfg(feature = "libseccomp")]
+fn build_filter(seccomp: &LinuxSeccomp) -> Result<ScmpFilterContext> {
     check_seccomp(seccomp)?;
 
     tracing::trace!(default_action = ?seccomp.default_action(), errno = ?seccomp.default_errno_ret(), "initializing seccomp");
@@ -165,9 +394,22 @@ pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
 
     if let Some(architectures) = seccomp.architectures() {
         for &arch in architectures {
-            tracing::trace!(?arch, "adding architecture");
-            ctx.add_arch(translate_arch(arch))
-                .map_err(|err| SeccompError::AddArch { source: err, arch })?;
+            let scmp_archs: Vec<ScmpArch> = match translate_arch(arch) {
+                // libseccomp knows architectures the spec types have no token
+                // for, e.g. riscv64
+                ScmpArch::Native => match crate::arch::SECCOMP_ARCHS {
+                    [] => vec![ScmpArch::native()],
+                    archs => archs.iter().map(|&a| translate_arch(a)).collect(),
+                },
+                scmp_arch => std::iter::once(scmp_arch)
+                    .chain(compatible_archs(scmp_arch).iter().copied())
+                    .collect(),
+            };
+            for scmp_arch in scmp_archs {
+                tracing::trace!(?arch, ?scmp_arch, "adding architecture");
+                ctx.add_arch(scmp_arch)
+                    .map_err(|err| SeccompError::AddArch { source: err, arch })?;
+            }
         }
     }
 
@@ -254,23 +496,7 @@ pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<io::RawFd>> {
         }
     }
 
-    // In order to use the SECCOMP_SET_MODE_FILTER operation, either the calling
-    // thread must have the CAP_SYS_ADMIN capability in its user namespace, or
-    // the thread must already have the no_new_privs bit set.
-    // Ref: https://man7.org/linux/man-pages/man2/seccomp.2.html
-    ctx.load()
-        .map_err(|err| SeccompError::LoadContext { source: err })?;
-
-    let fd = if is_notify(seccomp) {
-        Some(
-            ctx.get_notify_fd()
-                .map_err(|err| SeccompError::GetNotifyId { source: err })?,
-        )
-    } else {
-        None
-    };
-
-    Ok(fd)
+    Ok(ctx)
 }
 
 pub fn is_notify(seccomp: &LinuxSeccomp) -> bool {
@@ -343,6 +569,104 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[serial]
+    fn test_load_seccomp_program() -> Result<()> {
+        let expect_error = libc::EAGAIN;
+        let syscall = LinuxSyscallBuilder::default()
+            .names(vec![String::from("getcwd")])
+            .action(LinuxSeccompAction::ScmpActErrno)
+            .errno_ret(expect_error as u32)
+            .build()?;
+        let seccomp_profile = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .architectures(vec![Arch::ScmpArchNative])
+            .syscalls(vec![syscall])
+            .build()?;
+        let program = compile_seccomp(&seccomp_profile)?;
+        assert!(matches!(
+            load_seccomp_program(&seccomp_profile, &program[1..]),
+            Err(SeccompError::InvalidBpf(_))
+        ));
+
+        test_utils::test_in_child_process(|| {
+            let _ = prctl::set_no_new_privileges(true);
+            load_seccomp_program(&seccomp_profile, &program)
+                .expect("failed to load seccomp program");
+            match nix::unistd::getcwd() {
+                Err(errno) if errno == nix::errno::Errno::from_raw(expect_error) => Ok(()),
+                ret => Err(TestCallbackError::Custom(format!(
+                    "getcwd didn't fail as the seccomp program specified: {ret:?}"
+                ))),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "libseccomp")]
+    #[test]
+    fn test_translate_action() -> Result<()> {
+        for (action, errno, expected) in [
+            (
+                LinuxSeccompAction::ScmpActErrno,
+                None,
+                ScmpAction::Errno(libc::EPERM),
+            ),
+            (
+                LinuxSeccompAction::ScmpActErrno,
+                Some(38),
+                ScmpAction::Errno(38),
+            ),
+            (
+                LinuxSeccompAction::ScmpActTrace,
+                Some(1),
+                ScmpAction::Trace(1),
+            ),
+            (
+                LinuxSeccompAction::ScmpActKill,
+                None,
+                ScmpAction::KillThread,
+            ),
+            (
+                LinuxSeccompAction::ScmpActKillProcess,
+                None,
+                ScmpAction::KillProcess,
+            ),
+            (LinuxSeccompAction::ScmpActLog, None, ScmpAction::Log),
+        ] {
+            assert_eq!(translate_action(action, errno)?, expected);
+        }
+        assert!(matches!(
+            translate_action(LinuxSeccompAction::ScmpActTrace, Some(u32::MAX)),
+            Err(SeccompError::TraceAction { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "libseccomp")]
+    #[test]
+    fn test_compatible_archs() -> Result<()> {
+        let seccomp_profile = LinuxSeccompBuilder::default()
+            .default_action(LinuxSeccompAction::ScmpActAllow)
+            .architectures(vec![Arch::ScmpArchX86_64, Arch::ScmpArchAarch64])
+            .build()?;
+        let ctx = build_filter(&seccomp_profile)?;
+        for arch in [
+            ScmpArch::X8664,
+            ScmpArch::X86,
+            ScmpArch::X32,
+            ScmpArch::Aarch64,
+            ScmpArch::Arm,
+        ] {
+            assert!(ctx.is_arch_present(arch)?, "{arch:?} is missing");
+        }
+        assert!(!ctx.is_arch_present(ScmpArch::S390X)?);
+
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_moby() -> Result<()> {
@@ -373,6 +697,7 @@ mod tests {
         let seccomp_profile = LinuxSeccompBuilder::default()
             .default_action(LinuxSeccompAction::ScmpActAllow)
             .architectures(vec![Arch::ScmpArchNative])
+            .listener_path("/run/seccomp-agent.socket")
             .syscalls(vec![syscall])
             .build()?;
         test_utils::test_in_child_process(|| {
@@ -390,4 +715,45 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_check_seccomp_notify_listener_path() -> Result<()> {
+        let syscall = LinuxSyscallBuilder::default()
+            .names(vec![String::from("getcwd")])
+            .action(LinuxSeccompAction::ScmpActNotify)
+            .build()?;
+        let builder = || {
+            LinuxSeccompBuilder::default()
+                .default_action(LinuxSeccompAction::ScmpActAllow)
+                .syscalls(vec![syscall.clone()])
+        };
+
+        let missing = builder().build()?;
+        assert!(matches!(
+            check_seccomp(&missing),
+            Err(SeccompError::NotifyWithoutListenerPath)
+        ));
+
+        let relative = builder().listener_path("agent.sock").build()?;
+        assert!(matches!(
+            check_seccomp(&relative),
+            Err(SeccompError::RelativeListenerPath(_))
+        ));
+
+        let absolute = builder().listener_path("/run/agent.sock").build()?;
+        assert!(check_seccomp(&absolute).is_ok());
+
+        for address in ["@seccomp-agent", "vsock://2:1024"] {
+            let socket = builder().listener_path(address).build()?;
+            assert!(check_seccomp(&socket).is_ok());
+        }
+
+        let invalid = builder().listener_path("vsock://host").build()?;
+        assert!(matches!(
+            check_seccomp(&invalid),
+            Err(SeccompError::InvalidListenerPath(_))
+        ));
+
+        Ok(())
+    }
 }
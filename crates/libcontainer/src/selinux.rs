@@ -0,0 +1,218 @@
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::utils;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SELinuxError {
+    #[error("failed to set SELinux exec label")]
+    SetExecLabel {
+        path: PathBuf,
+        label: String,
+        source: std::io::Error,
+    },
+    #[error("failed to set SELinux key label {label:?}")]
+    SetKeyLabel {
+        path: PathBuf,
+        label: String,
+        source: std::io::Error,
+    },
+    #[error("failed to set SELinux label {label} on {path:?}")]
+    SetFileLabel {
+        path: PathBuf,
+        label: String,
+        source: std::io::Error,
+    },
+    #[error("relabeling {0:?} is not allowed")]
+    RelabelNotAllowed(PathBuf),
+    #[error("invalid SELinux label: {0}")]
+    InvalidLabel(String),
+    #[error(transparent)]
+    EnsureProcfs(#[from] utils::EnsureProcfsError),
+}
+
+type Result<T> = std::result::Result<T, SELinuxError>;
+
+const ENFORCE_PATH: &str = "/sys/fs/selinux/enforce";
+const XATTR_NAME: &str = "security.selinux";
+
+/// Paths which must never be relabeled, as this would break the host
+const RELABEL_EXCLUDED_PATHS: &[&str] = &[
+    "/", "/bin", "/boot", "/dev", "/etc", "/home", "/lib", "/opt", "/proc", "/root", "/run",
+    "/sbin", "/sys", "/tmp", "/usr", "/var",
+];
+
+/// How the source of a bind mount is relabeled, as requested by the `z` and `Z` mount options
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relabel {
+    /// The content is shared with other containers, so the MCS categories of the label are dropped
+    Shared,
+    /// The content is private to the container and gets the exact mount label
+    Private,
+}
+
+/// Checks if SELinux has been enabled on the system.
+pub fn is_enabled() -> bool {
+    // the enforce file only exists if selinuxfs is mounted, which is the case
+    // whenever a policy has been loaded
+    Path::new(ENFORCE_PATH).exists()
+}
+
+/// Sets the label the container process gets on its next execve.
+pub fn set_exec_label(label: &str) -> Result<()> {
+    if label.is_empty() {
+        return Ok(());
+    }
+
+    let path = Path::new("/proc/thread-self/attr/exec");
+    utils::ensure_procfs(path).map_err(SELinuxError::EnsureProcfs)?;
+    fs::write(path, label).map_err(|err| SELinuxError::SetExecLabel {
+        path: path.to_owned(),
+        label: label.to_owned(),
+        source: err,
+    })
+}
+
+/// Sets the label of the keyrings the process creates from now on, an empty
+/// label resets it to the default of the policy.
+pub fn set_key_label(label: &str) -> Result<()> {
+    if !is_enabled() {
+        return Ok(());
+    }
+
+    let path = Path::new("/proc/thread-self/attr/keycreate");
+    utils::ensure_procfs(path).map_err(SELinuxError::EnsureProcfs)?;
+    match fs::write(path, label) {
+        // kernels without keyring support don't have the file
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result.map_err(|err| SELinuxError::SetKeyLabel {
+            path: path.to_owned(),
+            label: label.to_owned(),
+            source: err,
+        }),
+    }
+}
+
+/// Recursively relabels the given path with the mount label of the container.
+pub fn relabel(path: &Path, label: &str, relabel: Relabel) -> Result<()> {
+    if label.is_empty() || !is_enabled() {
+        return Ok(());
+    }
+
+    if RELABEL_EXCLUDED_PATHS.iter().any(|p| Path::new(p) == path) {
+        return Err(SELinuxError::RelabelNotAllowed(path.to_owned()));
+    }
+
+    let label = match relabel {
+        Relabel::Private => label.to_owned(),
+        Relabel::Shared => shared_label(label)?,
+    };
+
+    relabel_recursive(path, &label)
+}
+
+fn relabel_recursive(path: &Path, label: &str) -> Result<()> {
+    set_file_label(path, label)?;
+
+    let metadata = fs::symlink_metadata(path).map_err(|err| SELinuxError::SetFileLabel {
+        path: path.to_owned(),
+        label: label.to_owned(),
+        source: err,
+    })?;
+    if !metadata.is_dir() {
+        return Ok(());
+    }
+
+    let entries = fs::read_dir(path).map_err(|err| SELinuxError::SetFileLabel {
+        path: path.to_owned(),
+        label: label.to_owned(),
+        source: err,
+    })?;
+    for entry in entries {
+        let entry = entry.map_err(|err| SELinuxError::SetFileLabel {
+            path: path.to_owned(),
+            label: label.to_owned(),
+            source: err,
+        })?;
+        relabel_recursive(&entry.path(), label)?;
+    }
+
+    Ok(())
+}
+
+/// Sets the label of a single file, without following symlinks.
+pub fn set_file_label(path: &Path, label: &str) -> Result<()> {
+    let to_error = |err| SELinuxError::SetFileLabel {
+        path: path.to_owned(),
+        label: label.to_owned(),
+        source: err,
+    };
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| to_error(std::io::Error::new(std::io::ErrorKind::InvalidInput, err)))?;
+    let c_name = CString::new(XATTR_NAME).unwrap();
+    let ret = unsafe {
+        libc::lsetxattr(
+            c_path.as_ptr(),
+            c_name.as_ptr(),
+            label.as_ptr() as *const libc::c_void,
+            label.len(),
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(to_error(std::io::Error::last_os_error()));
+    }
+
+    Ok(())
+}
+
+/// Drops the MCS categories of a label, so that the labeled content can be
+/// accessed by all containers, e.g. `system_u:object_r:container_file_t:s0:c1,c2`
+/// becomes `system_u:object_r:container_file_t:s0`
+fn shared_label(label: &str) -> Result<String> {
+    let fields: Vec<&str> = label.splitn(5, ':').collect();
+    if fields.len() < 4 {
+        return Err(SELinuxError::InvalidLabel(label.to_owned()));
+    }
+
+    Ok(fields[..4].join(":"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_label() -> Result<()> {
+        assert_eq!(
+            shared_label("system_u:object_r:container_file_t:s0:c1,c2")?,
+            "system_u:object_r:container_file_t:s0"
+        );
+        assert_eq!(
+            shared_label("system_u:object_r:container_file_t:s0")?,
+            "system_u:object_r:container_file_t:s0"
+        );
+        assert!(shared_label("container_file_t").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_relabel_excluded_path() {
+        // only checked on systems with SELinux, otherwise relabeling is a no-op
+        if !is_enabled() {
+            return;
+        }
+
+        assert!(matches!(
+            relabel(
+                Path::new("/usr"),
+                "system_u:object_r:container_file_t:s0",
+                Relabel::Private
+            ),
+            Err(SELinuxError::RelabelNotAllowed(_))
+        ));
+    }
+}
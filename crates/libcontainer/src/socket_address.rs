@@ -0,0 +1,131 @@
+//! Addresses of the sockets the runtime connects to in order to hand over file
+//! descriptors, i.e. the console socket and the seccomp listener. Besides a
+//! path these can be an abstract unix socket, written with a leading `@`, or
+//! a vsock address written as `vsock://<cid>:<port>`, for setups where the
+//! runtime runs in a VM sandbox which doesn't share a filesystem with the
+//! receiver.
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use nix::sys::socket::{self, AddressFamily, SockFlag, SockType, UnixAddr, VsockAddr};
+
+const ABSTRACT_PREFIX: &str = "@";
+const VSOCK_PREFIX: &str = "vsock://";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SocketAddressError {
+    #[error("invalid vsock address {0:?}, expected vsock://<cid>:<port>")]
+    InvalidVsock(String),
+    #[error("abstract socket name must not be empty")]
+    EmptyAbstract,
+}
+
+/// Address of a socket as given to the runtime
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SocketAddress {
+    /// Unix socket bound to a path
+    Path(PathBuf),
+    /// Unix socket in the abstract namespace, without the leading `@`
+    Abstract(Vec<u8>),
+    /// Vsock socket of the host or another VM. Vsock doesn't carry SCM_RIGHTS
+    /// messages, so the peer only receives the payload of a file descriptor
+    /// hand-over and has to be a proxy which is able to handle that.
+    Vsock { cid: u32, port: u32 },
+}
+
+impl SocketAddress {
+    pub fn parse(addr: &Path) -> Result<Self, SocketAddressError> {
+        let bytes = addr.as_os_str().as_bytes();
+        if let Some(name) = bytes.strip_prefix(ABSTRACT_PREFIX.as_bytes()) {
+            if name.is_empty() {
+                return Err(SocketAddressError::EmptyAbstract);
+            }
+            return Ok(Self::Abstract(name.to_vec()));
+        }
+
+        if let Some(vsock) = bytes.strip_prefix(VSOCK_PREFIX.as_bytes()) {
+            let invalid = || SocketAddressError::InvalidVsock(addr.to_string_lossy().into_owned());
+            let vsock = std::str::from_utf8(vsock).map_err(|_| invalid())?;
+            let (cid, port) = vsock.split_once(':').ok_or_else(invalid)?;
+            return Ok(Self::Vsock {
+                cid: cid.parse().map_err(|_| invalid())?,
+                port: port.parse().map_err(|_| invalid())?,
+            });
+        }
+
+        Ok(Self::Path(addr.to_owned()))
+    }
+
+    /// Returns the path of the socket, unless it isn't bound to one
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Path(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Opens a stream socket connected to the address
+    pub fn connect(&self) -> nix::Result<OwnedFd> {
+        match self {
+            Self::Path(path) => connect(AddressFamily::Unix, &UnixAddr::new(path.as_path())?),
+            Self::Abstract(name) => connect(AddressFamily::Unix, &UnixAddr::new_abstract(name)?),
+            Self::Vsock { cid, port } => {
+                connect(AddressFamily::Vsock, &VsockAddr::new(*cid, *port))
+            }
+        }
+    }
+}
+
+fn connect(family: AddressFamily, addr: &dyn socket::SockaddrLike) -> nix::Result<OwnedFd> {
+    let fd = socket::socket(family, SockType::Stream, SockFlag::SOCK_CLOEXEC, None)?;
+    socket::connect(fd.as_raw_fd(), addr)?;
+    Ok(fd)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nix::sys::socket::Backlog;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        assert_eq!(
+            SocketAddress::parse(Path::new("/run/console.sock"))?,
+            SocketAddress::Path(PathBuf::from("/run/console.sock"))
+        );
+        assert_eq!(
+            SocketAddress::parse(Path::new("@youki/console"))?,
+            SocketAddress::Abstract(b"youki/console".to_vec())
+        );
+        assert_eq!(
+            SocketAddress::parse(Path::new("vsock://2:1024"))?,
+            SocketAddress::Vsock { cid: 2, port: 1024 }
+        );
+        assert!(SocketAddress::parse(Path::new("@")).is_err());
+        for invalid in ["vsock://2", "vsock://host:1024", "vsock://2:-1"] {
+            assert!(SocketAddress::parse(Path::new(invalid)).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_connect_abstract() -> Result<()> {
+        let name = format!("youki-test-{}", std::process::id());
+        let listener = socket::socket(
+            AddressFamily::Unix,
+            SockType::Stream,
+            SockFlag::SOCK_CLOEXEC,
+            None,
+        )?;
+        socket::bind(
+            listener.as_raw_fd(),
+            &UnixAddr::new_abstract(name.as_bytes())?,
+        )?;
+        socket::listen(&listener, Backlog::new(1)?)?;
+        SocketAddress::parse(Path::new(&format!("@{name}")))?.connect()?;
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 //! Implements Command trait for Linux systems
 use std::any::Any;
 use std::ffi::{CStr, CString, OsStr};
-use std::os::fd::BorrowedFd;
+use std::os::fd::{BorrowedFd, FromRawFd, OwnedFd};
 use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::symlink;
 use std::os::unix::io::RawFd;
@@ -15,9 +15,9 @@ use libc::{c_char, setdomainname, uid_t};
 use nix::fcntl;
 use nix::fcntl::{open, OFlag};
 use nix::mount::{mount, umount2, MntFlags, MsFlags};
-use nix::sched::{unshare, CloneFlags};
+use nix::sched::{sched_setaffinity, unshare, CloneFlags, CpuSet};
 use nix::sys::stat::{mknod, Mode, SFlag};
-use nix::unistd::{chown, chroot, fchdir, pivot_root, sethostname, Gid, Uid};
+use nix::unistd::{chdir, chown, chroot, fchdir, pivot_root, sethostname, Gid, Pid, Uid};
 use oci_spec::runtime::PosixRlimit;
 
 use super::{Result, Syscall, SyscallError};
@@ -28,15 +28,29 @@ use crate::{capabilities, utils};
 pub const AT_RECURSIVE: u32 = 0x00008000; // Change the mount properties of the entire mount tree.
 #[allow(non_upper_case_globals)]
 pub const MOUNT_ATTR__ATIME: u64 = 0x00000070; // Setting on how atime should be updated.
-const MOUNT_ATTR_RDONLY: u64 = 0x00000001;
-const MOUNT_ATTR_NOSUID: u64 = 0x00000002;
-const MOUNT_ATTR_NODEV: u64 = 0x00000004;
-const MOUNT_ATTR_NOEXEC: u64 = 0x00000008;
-const MOUNT_ATTR_RELATIME: u64 = 0x00000000;
-const MOUNT_ATTR_NOATIME: u64 = 0x00000010;
-const MOUNT_ATTR_STRICTATIME: u64 = 0x00000020;
-const MOUNT_ATTR_NODIRATIME: u64 = 0x00000080;
+pub const MOUNT_ATTR_RDONLY: u64 = 0x00000001;
+pub const MOUNT_ATTR_NOSUID: u64 = 0x00000002;
+pub const MOUNT_ATTR_NODEV: u64 = 0x00000004;
+pub const MOUNT_ATTR_NOEXEC: u64 = 0x00000008;
+pub const MOUNT_ATTR_RELATIME: u64 = 0x00000000;
+pub const MOUNT_ATTR_NOATIME: u64 = 0x00000010;
+pub const MOUNT_ATTR_STRICTATIME: u64 = 0x00000020;
+pub const MOUNT_ATTR_NODIRATIME: u64 = 0x00000080;
 const MOUNT_ATTR_NOSYMFOLLOW: u64 = 0x00200000;
+pub const MOUNT_ATTR_IDMAP: u64 = 0x00100000; // Idmap the mount with the given user namespace.
+pub const AT_EMPTY_PATH: u32 = libc::AT_EMPTY_PATH as u32; // Operate on the dirfd itself.
+
+// Flags used in open_tree(2) and move_mount(2).
+pub const OPEN_TREE_CLONE: u32 = 1; // Create a detached clone of the mount tree.
+pub const OPEN_TREE_CLOEXEC: u32 = libc::O_CLOEXEC as u32;
+pub const MOVE_MOUNT_F_EMPTY_PATH: u32 = 0x00000004; // The source is the mount referred by the dirfd.
+
+// Flags and commands used in fsopen(2), fsconfig(2) and fsmount(2).
+pub const FSOPEN_CLOEXEC: u32 = 0x00000001;
+pub const FSCONFIG_SET_FLAG: u32 = 0; // Set a flag, the value has to be absent.
+pub const FSCONFIG_SET_STRING: u32 = 1; // Set an option to a string value.
+pub const FSCONFIG_CMD_CREATE: u32 = 6; // Create the superblock from the configuration.
+pub const FSMOUNT_CLOEXEC: u32 = 0x00000001;
 
 /// Constants used by mount_setattr(2).
 pub enum MountRecursive {
@@ -154,6 +168,13 @@ impl MountAttr {
 pub struct LinuxSyscall;
 
 impl LinuxSyscall {
+    fn path_to_cstring(path: &Path) -> Result<CString> {
+        CString::new(path.as_os_str().as_bytes()).map_err(|err| {
+            tracing::error!(?path, ?err, "failed to convert path to string");
+            nix::Error::EINVAL.into()
+        })
+    }
+
     unsafe fn from_raw_buf<'a, T>(p: *const c_char) -> T
     where
         T: From<&'a OsStr>,
@@ -282,6 +303,34 @@ impl Syscall for LinuxSyscall {
         Ok(())
     }
 
+    /// Function to set given path as root path inside process without
+    /// pivot_root, for setups where the old root can't be unmounted, e.g. when
+    /// the rootfs is on a ramdisk.
+    fn move_rootfs(&self, path: &Path) -> Result<()> {
+        chdir(path).map_err(|errno| {
+            tracing::error!(?errno, ?path, "failed to change directory to new root");
+            errno
+        })?;
+
+        // move the new root over the original root directory, the original
+        // root stays reachable underneath, which is why pivot_root is preferred
+        mount(Some("."), "/", None::<&str>, MsFlags::MS_MOVE, None::<&str>).map_err(|errno| {
+            tracing::error!(?errno, ?path, "failed to move new root");
+            errno
+        })?;
+
+        chroot(".").map_err(|errno| {
+            tracing::error!(?errno, ?path, "failed to chroot to new root");
+            errno
+        })?;
+        chdir("/").map_err(|errno| {
+            tracing::error!(?errno, "failed to change directory to new root");
+            errno
+        })?;
+
+        Ok(())
+    }
+
     /// Set namespace for process
     fn set_ns(&self, rawfd: i32, nstype: CloneFlags) -> Result<()> {
         let fd = unsafe { BorrowedFd::borrow_raw(rawfd) };
@@ -392,11 +441,9 @@ impl Syscall for LinuxSyscall {
             rlim_max: rlimit.hard(),
         };
 
-        // Change for musl libc based on seccomp needs
-        #[cfg(not(target_env = "musl"))]
-        let res = unsafe { libc::setrlimit(rlimit.typ() as u32, rlim) };
-        #[cfg(target_env = "musl")]
-        let res = unsafe { libc::setrlimit(rlimit.typ() as i32, rlim) };
+        // the type of the resource differs between glibc and musl
+        let resource = utils::rlimit_resource(rlimit.typ()) as _;
+        let res = unsafe { libc::prlimit(0, resource, rlim, ptr::null_mut()) };
 
         match res {
             0 => Ok(()),
@@ -459,6 +506,11 @@ impl Syscall for LinuxSyscall {
         Ok(())
     }
 
+    fn umount2(&self, target: &Path, flags: MntFlags) -> Result<()> {
+        umount2(target, flags)?;
+        Ok(())
+    }
+
     fn symlink(&self, original: &Path, link: &Path) -> Result<()> {
         symlink(original, link)?;
 
@@ -556,6 +608,82 @@ impl Syscall for LinuxSyscall {
         Ok(())
     }
 
+    fn open_tree(&self, dirfd: RawFd, pathname: &Path, flags: u32) -> Result<OwnedFd> {
+        let path_c_string = Self::path_to_cstring(pathname)?;
+        match unsafe { libc::syscall(libc::SYS_open_tree, dirfd, path_c_string.as_ptr(), flags) } {
+            -1 => Err(nix::Error::last().into()),
+            fd => Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) }),
+        }
+    }
+
+    fn move_mount(
+        &self,
+        from_dirfd: RawFd,
+        from_pathname: &Path,
+        to_dirfd: RawFd,
+        to_pathname: &Path,
+        flags: u32,
+    ) -> Result<()> {
+        let from_c_string = Self::path_to_cstring(from_pathname)?;
+        let to_c_string = Self::path_to_cstring(to_pathname)?;
+        match unsafe {
+            libc::syscall(
+                libc::SYS_move_mount,
+                from_dirfd,
+                from_c_string.as_ptr(),
+                to_dirfd,
+                to_c_string.as_ptr(),
+                flags,
+            )
+        } {
+            0 => Ok(()),
+            -1 => Err(nix::Error::last()),
+            _ => Err(nix::Error::UnknownErrno),
+        }?;
+        Ok(())
+    }
+
+    fn fsopen(&self, fstype: &str, flags: u32) -> Result<OwnedFd> {
+        let fstype = CString::new(fstype).map_err(|_| nix::Error::EINVAL)?;
+        match unsafe { libc::syscall(libc::SYS_fsopen, fstype.as_ptr(), flags) } {
+            -1 => Err(nix::Error::last().into()),
+            fd => Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) }),
+        }
+    }
+
+    fn fsconfig(&self, fd: RawFd, cmd: u32, key: Option<&str>, value: Option<&str>) -> Result<()> {
+        let key = key
+            .map(CString::new)
+            .transpose()
+            .map_err(|_| nix::Error::EINVAL)?;
+        let value = value
+            .map(CString::new)
+            .transpose()
+            .map_err(|_| nix::Error::EINVAL)?;
+        match unsafe {
+            libc::syscall(
+                libc::SYS_fsconfig,
+                fd,
+                cmd,
+                key.as_ref().map_or(ptr::null(), |key| key.as_ptr()),
+                value.as_ref().map_or(ptr::null(), |value| value.as_ptr()),
+                0,
+            )
+        } {
+            0 => Ok(()),
+            -1 => Err(nix::Error::last()),
+            _ => Err(nix::Error::UnknownErrno),
+        }?;
+        Ok(())
+    }
+
+    fn fsmount(&self, fd: RawFd, flags: u32, attr_flags: u64) -> Result<OwnedFd> {
+        match unsafe { libc::syscall(libc::SYS_fsmount, fd, flags, attr_flags) } {
+            -1 => Err(nix::Error::last().into()),
+            fd => Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) }),
+        }
+    }
+
     fn set_io_priority(&self, class: i64, priority: i64) -> Result<()> {
         let ioprio_who_progress: libc::c_int = 1;
         let ioprio_who_pid = 0;
@@ -574,6 +702,41 @@ impl Syscall for LinuxSyscall {
         }?;
         Ok(())
     }
+
+    /// Restricts the calling thread to the given cpus. The affinity is
+    /// inherited by children.
+    fn set_cpu_affinity(&self, cpus: &[usize]) -> Result<()> {
+        let mut cpu_set = CpuSet::new();
+        for cpu in cpus {
+            cpu_set.set(*cpu)?;
+        }
+        sched_setaffinity(Pid::from_raw(0), &cpu_set)?;
+        Ok(())
+    }
+
+    /// Creates a new session keyring with the given name and makes it the
+    /// session keyring of the calling process
+    fn join_session_keyring(&self, name: &str) -> Result<()> {
+        const KEYCTL_JOIN_SESSION_KEYRING: libc::c_int = 1;
+
+        let name = CString::new(name).map_err(|_| nix::Error::EINVAL)?;
+        match unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_JOIN_SESSION_KEYRING, name.as_ptr()) }
+        {
+            -1 => Err(nix::Error::last()),
+            _ => Ok(()),
+        }?;
+        Ok(())
+    }
+
+    /// Sets the execution domain of the calling process, which is kept
+    /// across execve and inherited by children
+    fn set_personality(&self, persona: u64) -> Result<()> {
+        match unsafe { libc::personality(persona as libc::c_ulong) } {
+            -1 => Err(nix::Error::last()),
+            _ => Ok(()),
+        }?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
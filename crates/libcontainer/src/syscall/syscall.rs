@@ -3,12 +3,14 @@
 //! implementation details
 use std::any::Any;
 use std::ffi::OsStr;
+use std::os::fd::OwnedFd;
+use std::os::unix::io::RawFd;
 use std::path::Path;
 use std::sync::Arc;
 
 use caps::{CapSet, CapsHashSet};
 use libc;
-use nix::mount::MsFlags;
+use nix::mount::{MntFlags, MsFlags};
 use nix::sched::CloneFlags;
 use nix::sys::stat::{Mode, SFlag};
 use nix::unistd::{Gid, Uid};
@@ -23,6 +25,7 @@ use crate::syscall::Result;
 pub trait Syscall {
     fn as_any(&self) -> &dyn Any;
     fn pivot_rootfs(&self, path: &Path) -> Result<()>;
+    fn move_rootfs(&self, path: &Path) -> Result<()>;
     fn chroot(&self, path: &Path) -> Result<()>;
     fn set_ns(&self, rawfd: i32, nstype: CloneFlags) -> Result<()>;
     fn set_id(&self, uid: Uid, gid: Gid) -> Result<()>;
@@ -40,6 +43,7 @@ pub trait Syscall {
         flags: MsFlags,
         data: Option<&str>,
     ) -> Result<()>;
+    fn umount2(&self, target: &Path, flags: MntFlags) -> Result<()>;
     fn symlink(&self, original: &Path, link: &Path) -> Result<()>;
     fn mknod(&self, path: &Path, kind: SFlag, perm: Mode, dev: u64) -> Result<()>;
     fn chown(&self, path: &Path, owner: Option<Uid>, group: Option<Gid>) -> Result<()>;
@@ -53,7 +57,22 @@ pub trait Syscall {
         mount_attr: &MountAttr,
         size: libc::size_t,
     ) -> Result<()>;
+    fn open_tree(&self, dirfd: RawFd, pathname: &Path, flags: u32) -> Result<OwnedFd>;
+    fn move_mount(
+        &self,
+        from_dirfd: RawFd,
+        from_pathname: &Path,
+        to_dirfd: RawFd,
+        to_pathname: &Path,
+        flags: u32,
+    ) -> Result<()>;
+    fn fsopen(&self, fstype: &str, flags: u32) -> Result<OwnedFd>;
+    fn fsconfig(&self, fd: RawFd, cmd: u32, key: Option<&str>, value: Option<&str>) -> Result<()>;
+    fn fsmount(&self, fd: RawFd, flags: u32, attr_flags: u64) -> Result<OwnedFd>;
     fn set_io_priority(&self, class: i64, priority: i64) -> Result<()>;
+    fn set_cpu_affinity(&self, cpus: &[usize]) -> Result<()>;
+    fn join_session_keyring(&self, name: &str) -> Result<()>;
+    fn set_personality(&self, persona: u64) -> Result<()>;
 }
 
 #[derive(Clone, Copy)]
@@ -1,12 +1,14 @@
 use std::any::Any;
-use std::cell::{Ref, RefCell, RefMut};
+use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
+use std::os::fd::OwnedFd;
+use std::os::unix::io::RawFd;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use caps::{CapSet, CapsHashSet};
-use nix::mount::MsFlags;
+use nix::mount::{MntFlags, MsFlags};
 use nix::sched::CloneFlags;
 use nix::sys::stat::{Mode, SFlag};
 use nix::unistd::{Gid, Uid};
@@ -38,6 +40,33 @@ pub struct ChownArgs {
     pub group: Option<Gid>,
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MoveMountArgs {
+    pub from_dirfd: RawFd,
+    pub to_pathname: PathBuf,
+    pub flags: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OpenTreeArgs {
+    pub pathname: PathBuf,
+    pub flags: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MountSetattrArgs {
+    pub dirfd: RawFd,
+    pub flags: u32,
+    pub mount_attr: linux::MountAttr,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FsconfigArgs {
+    pub cmd: u32,
+    pub key: Option<String>,
+    pub value: Option<String>,
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct IoPriorityArgs {
     pub class: i64,
@@ -56,6 +85,7 @@ pub enum ArgName {
     Namespace,
     Unshare,
     Mount,
+    Umount,
     Symlink,
     Mknod,
     Chown,
@@ -64,6 +94,15 @@ pub enum ArgName {
     Groups,
     Capability,
     IoPriority,
+    CpuAffinity,
+    MoveMount,
+    OpenTree,
+    MountSetattr,
+    Fsopen,
+    Fsconfig,
+    Fsmount,
+    SessionKeyring,
+    Personality,
 }
 
 impl ArgName {
@@ -72,6 +111,7 @@ impl ArgName {
             ArgName::Namespace,
             ArgName::Unshare,
             ArgName::Mount,
+            ArgName::Umount,
             ArgName::Symlink,
             ArgName::Mknod,
             ArgName::Chown,
@@ -80,6 +120,15 @@ impl ArgName {
             ArgName::Groups,
             ArgName::Capability,
             ArgName::IoPriority,
+            ArgName::CpuAffinity,
+            ArgName::MoveMount,
+            ArgName::OpenTree,
+            ArgName::MountSetattr,
+            ArgName::Fsopen,
+            ArgName::Fsconfig,
+            ArgName::Fsmount,
+            ArgName::SessionKeyring,
+            ArgName::Personality,
         ]
         .iter()
         .copied()
@@ -134,6 +183,8 @@ impl MockCalls {
 #[derive(Default)]
 pub struct TestHelperSyscall {
     mocks: MockCalls,
+    // fsopen fails with ENOSYS unless enabled, like on kernels before 5.2
+    fs_context: Cell<bool>,
 }
 
 impl Syscall for TestHelperSyscall {
@@ -145,6 +196,10 @@ impl Syscall for TestHelperSyscall {
         unimplemented!()
     }
 
+    fn move_rootfs(&self, _path: &Path) -> Result<()> {
+        unimplemented!()
+    }
+
     fn set_ns(&self, rawfd: i32, nstype: CloneFlags) -> Result<()> {
         self.mocks
             .act(ArgName::Namespace, Box::new((rawfd, nstype)))
@@ -205,6 +260,11 @@ impl Syscall for TestHelperSyscall {
         )
     }
 
+    fn umount2(&self, target: &Path, flags: MntFlags) -> Result<()> {
+        self.mocks
+            .act(ArgName::Umount, Box::new((target.to_owned(), flags)))
+    }
+
     fn symlink(&self, original: &Path, link: &Path) -> Result<()> {
         self.mocks.act(
             ArgName::Symlink,
@@ -244,13 +304,78 @@ impl Syscall for TestHelperSyscall {
 
     fn mount_setattr(
         &self,
-        _: i32,
+        dirfd: i32,
         _: &Path,
-        _: u32,
-        _: &linux::MountAttr,
+        flags: u32,
+        mount_attr: &linux::MountAttr,
         _: libc::size_t,
     ) -> Result<()> {
-        todo!()
+        self.mocks.act(
+            ArgName::MountSetattr,
+            Box::new(MountSetattrArgs {
+                dirfd,
+                flags,
+                mount_attr: mount_attr.clone(),
+            }),
+        )
+    }
+
+    fn open_tree(&self, _: RawFd, pathname: &Path, flags: u32) -> Result<OwnedFd> {
+        self.mocks.act(
+            ArgName::OpenTree,
+            Box::new(OpenTreeArgs {
+                pathname: pathname.to_path_buf(),
+                flags,
+            }),
+        )?;
+        // any open file works as the detached mount tree in tests
+        let file = std::fs::File::open("/dev/null")?;
+        Ok(file.into())
+    }
+
+    fn move_mount(
+        &self,
+        from_dirfd: RawFd,
+        _: &Path,
+        _: RawFd,
+        to_pathname: &Path,
+        flags: u32,
+    ) -> Result<()> {
+        self.mocks.act(
+            ArgName::MoveMount,
+            Box::new(MoveMountArgs {
+                from_dirfd,
+                to_pathname: to_pathname.to_path_buf(),
+                flags,
+            }),
+        )
+    }
+
+    fn fsopen(&self, fstype: &str, _: u32) -> Result<OwnedFd> {
+        if !self.fs_context.get() {
+            return Err(nix::Error::ENOSYS.into());
+        }
+        self.mocks
+            .act(ArgName::Fsopen, Box::new(fstype.to_owned()))?;
+        let file = std::fs::File::open("/dev/null")?;
+        Ok(file.into())
+    }
+
+    fn fsconfig(&self, _: RawFd, cmd: u32, key: Option<&str>, value: Option<&str>) -> Result<()> {
+        self.mocks.act(
+            ArgName::Fsconfig,
+            Box::new(FsconfigArgs {
+                cmd,
+                key: key.map(str::to_owned),
+                value: value.map(str::to_owned),
+            }),
+        )
+    }
+
+    fn fsmount(&self, _: RawFd, _: u32, attr_flags: u64) -> Result<OwnedFd> {
+        self.mocks.act(ArgName::Fsmount, Box::new(attr_flags))?;
+        let file = std::fs::File::open("/dev/null")?;
+        Ok(file.into())
     }
 
     fn set_io_priority(&self, class: i64, priority: i64) -> Result<()> {
@@ -259,6 +384,20 @@ impl Syscall for TestHelperSyscall {
             Box::new(IoPriorityArgs { class, priority }),
         )
     }
+
+    fn set_cpu_affinity(&self, cpus: &[usize]) -> Result<()> {
+        self.mocks
+            .act(ArgName::CpuAffinity, Box::new(cpus.to_vec()))
+    }
+
+    fn join_session_keyring(&self, name: &str) -> Result<()> {
+        self.mocks
+            .act(ArgName::SessionKeyring, Box::new(name.to_owned()))
+    }
+
+    fn set_personality(&self, persona: u64) -> Result<()> {
+        self.mocks.act(ArgName::Personality, Box::new(persona))
+    }
 }
 
 impl TestHelperSyscall {
@@ -307,6 +446,15 @@ impl TestHelperSyscall {
             .collect::<Vec<MountArgs>>()
     }
 
+    pub fn get_umount_args(&self) -> Vec<(PathBuf, MntFlags)> {
+        self.mocks
+            .fetch(ArgName::Umount)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<(PathBuf, MntFlags)>().unwrap().clone())
+            .collect::<Vec<(PathBuf, MntFlags)>>()
+    }
+
     pub fn get_symlink_args(&self) -> Vec<(PathBuf, PathBuf)> {
         self.mocks
             .fetch(ArgName::Symlink)
@@ -369,4 +517,90 @@ impl TestHelperSyscall {
             .map(|x| x.downcast_ref::<IoPriorityArgs>().unwrap().clone())
             .collect::<Vec<IoPriorityArgs>>()
     }
+
+    pub fn get_cpu_affinity_args(&self) -> Vec<Vec<usize>> {
+        self.mocks
+            .fetch(ArgName::CpuAffinity)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<Vec<usize>>().unwrap().clone())
+            .collect::<Vec<Vec<usize>>>()
+    }
+
+    pub fn get_session_keyring_args(&self) -> Vec<String> {
+        self.mocks
+            .fetch(ArgName::SessionKeyring)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<String>().unwrap().clone())
+            .collect::<Vec<String>>()
+    }
+
+    pub fn get_personality_args(&self) -> Vec<u64> {
+        self.mocks
+            .fetch(ArgName::Personality)
+            .values
+            .iter()
+            .map(|x| *x.downcast_ref::<u64>().unwrap())
+            .collect::<Vec<u64>>()
+    }
+
+    pub fn get_move_mount_args(&self) -> Vec<MoveMountArgs> {
+        self.mocks
+            .fetch(ArgName::MoveMount)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<MoveMountArgs>().unwrap().clone())
+            .collect::<Vec<MoveMountArgs>>()
+    }
+
+    pub fn get_open_tree_args(&self) -> Vec<OpenTreeArgs> {
+        self.mocks
+            .fetch(ArgName::OpenTree)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<OpenTreeArgs>().unwrap().clone())
+            .collect::<Vec<OpenTreeArgs>>()
+    }
+
+    /// Lets fsopen succeed, as on kernels supporting the new mount API
+    pub fn enable_fs_context(&self) {
+        self.fs_context.set(true);
+    }
+
+    pub fn get_fsopen_args(&self) -> Vec<String> {
+        self.mocks
+            .fetch(ArgName::Fsopen)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<String>().unwrap().clone())
+            .collect::<Vec<String>>()
+    }
+
+    pub fn get_fsconfig_args(&self) -> Vec<FsconfigArgs> {
+        self.mocks
+            .fetch(ArgName::Fsconfig)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<FsconfigArgs>().unwrap().clone())
+            .collect::<Vec<FsconfigArgs>>()
+    }
+
+    pub fn get_fsmount_args(&self) -> Vec<u64> {
+        self.mocks
+            .fetch(ArgName::Fsmount)
+            .values
+            .iter()
+            .map(|x| *x.downcast_ref::<u64>().unwrap())
+            .collect::<Vec<u64>>()
+    }
+
+    pub fn get_mount_setattr_args(&self) -> Vec<MountSetattrArgs> {
+        self.mocks
+            .fetch(ArgName::MountSetattr)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<MountSetattrArgs>().unwrap().clone())
+            .collect::<Vec<MountSetattrArgs>>()
+    }
 }
@@ -1,14 +1,20 @@
 //! tty (teletype) for user-system interaction
 
-use std::io::IoSlice;
+use std::fs::OpenOptions;
+use std::io::{IoSlice, IoSliceMut};
+use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd};
 use std::os::unix::fs::symlink;
 use std::os::unix::io::AsRawFd;
 use std::os::unix::prelude::RawFd;
 use std::path::{Path, PathBuf};
 
 use nix::errno::Errno;
+use nix::mount::{mount, MsFlags};
 use nix::sys::socket::{self, UnixAddr};
-use nix::unistd::{close, dup2};
+use nix::sys::stat::fstat;
+use nix::unistd::{close, dup2, fchown, isatty, ttyname, Uid};
+
+use crate::socket_address::{SocketAddress, SocketAddressError};
 
 #[derive(Debug)]
 pub enum StdIO {
@@ -52,6 +58,11 @@ pub enum TTYError {
         linked: Box<PathBuf>,
         console_socket_path: Box<PathBuf>,
     },
+    #[error("invalid console socket address {console_socket_path:?}")]
+    InvalidSocketAddress {
+        source: SocketAddressError,
+        console_socket_path: Box<PathBuf>,
+    },
     #[error("invalid socket name: {socket_name:?}")]
     InvalidSocketName {
         socket_name: String,
@@ -65,6 +76,27 @@ pub enum TTYError {
     SendPtyMaster { source: nix::Error },
     #[error("could not close console socket")]
     CloseConsoleSocket { source: nix::Error },
+    #[error("failed to receive pty master")]
+    ReceivePtyMaster { source: nix::Error },
+    #[error("no pty master was sent over the console socket")]
+    MissingPtyMaster,
+    #[error("failed to resize pseudo terminal")]
+    Resize { source: nix::Error },
+    #[error("failed to get the name of the pseudo terminal")]
+    PtyName { source: nix::Error },
+    #[error("failed to create {console:?}")]
+    CreateConsole {
+        source: std::io::Error,
+        console: Box<PathBuf>,
+    },
+    #[error("failed to bind mount {pty:?} to {console:?}")]
+    MountConsole {
+        source: nix::Error,
+        pty: Box<PathBuf>,
+        console: Box<PathBuf>,
+    },
+    #[error("failed to change the owner of fd {fd}")]
+    ChownStdIO { source: nix::Error, fd: RawFd },
 }
 
 type Result<T> = std::result::Result<T, TTYError>;
@@ -75,6 +107,24 @@ pub fn setup_console_socket(
     console_socket_path: &Path,
     socket_name: &str,
 ) -> Result<RawFd> {
+    let address = SocketAddress::parse(console_socket_path).map_err(|err| {
+        TTYError::InvalidSocketAddress {
+            source: err,
+            console_socket_path: console_socket_path.to_path_buf().into(),
+        }
+    })?;
+    if address.path().is_none() {
+        // abstract and vsock sockets aren't in the filesystem, so there is
+        // nothing to link into the container directory
+        let csocketfd = address
+            .connect()
+            .map_err(|err| TTYError::CreateConsoleSocket {
+                source: err,
+                socket_name: socket_name.to_string(),
+            })?;
+        return Ok(csocketfd.into_raw_fd());
+    }
+
     let linked = container_dir.join(socket_name);
     symlink(console_socket_path, &linked).map_err(|err| TTYError::Symlink {
         source: err,
@@ -108,6 +158,10 @@ pub fn setup_console_socket(
     Ok(csocketfd)
 }
 
+/// Creates a pseudo terminal for the container process and sends its master
+/// over the console socket. Like runc, the master is sent in a single message
+/// with the name of the master as payload and the fd as SCM_RIGHTS, which is
+/// what conmon and containerd expect.
 pub fn setup_console(console_fd: &RawFd) -> Result<()> {
     // You can also access pty master, but it is better to use the API.
     // ref. https://github.com/containerd/containerd/blob/261c107ffc4ff681bc73988f64e3f60c32233b37/vendor/github.com/containerd/go-runc/console.go#L139-L154
@@ -117,8 +171,7 @@ pub fn setup_console(console_fd: &RawFd) -> Result<()> {
     let iov = [IoSlice::new(pty_name)];
 
     let [master, slave] = [openpty_result.master, openpty_result.slave];
-    // Use ManuallyDrop to keep FDs open.
-    let master = std::mem::ManuallyDrop::new(master);
+    // Use ManuallyDrop to keep the slave open, it becomes the stdio of the process.
     let slave = std::mem::ManuallyDrop::new(slave);
 
     let fds = [master.as_raw_fd()];
@@ -131,9 +184,15 @@ pub fn setup_console(console_fd: &RawFd) -> Result<()> {
         None,
     )
     .map_err(|err| TTYError::SendPtyMaster { source: err })?;
+    // the receiver owns the master from now on, a copy in the container would
+    // keep the pseudo terminal open after the receiver has closed it
+    drop(master);
 
-    if unsafe { libc::ioctl(slave.as_raw_fd(), libc::TIOCSCTTY) } < 0 {
-        tracing::warn!("could not TIOCSCTTY");
+    // the process has become the leader of a new session, of which the pty
+    // becomes the controlling terminal. A non zero argument would steal the
+    // terminal from another session.
+    if unsafe { libc::ioctl(slave.as_raw_fd(), libc::TIOCSCTTY, 0) } < 0 {
+        tracing::warn!(err = ?Errno::last(), "could not TIOCSCTTY");
     };
     let slave = slave.as_raw_fd();
     connect_stdio(&slave, &slave, &slave)?;
@@ -142,6 +201,113 @@ pub fn setup_console(console_fd: &RawFd) -> Result<()> {
     Ok(())
 }
 
+/// Receives the master end of the pseudo terminal that the container
+/// process sent over the console socket in setup_console
+pub fn receive_pty_master(console_socket: RawFd) -> Result<OwnedFd> {
+    let mut buf = [0u8; 4096];
+    let mut iov = [IoSliceMut::new(&mut buf)];
+    let mut cmsg_buf = nix::cmsg_space!([RawFd; 1]);
+    let msg = socket::recvmsg::<UnixAddr>(
+        console_socket,
+        &mut iov,
+        Some(&mut cmsg_buf),
+        socket::MsgFlags::MSG_CMSG_CLOEXEC,
+    )
+    .map_err(|err| TTYError::ReceivePtyMaster { source: err })?;
+
+    for cmsg in msg.cmsgs() {
+        if let socket::ControlMessageOwned::ScmRights(fds) = cmsg {
+            if let Some(fd) = fds.first() {
+                return Ok(unsafe { OwnedFd::from_raw_fd(*fd) });
+            }
+        }
+    }
+
+    Err(TTYError::MissingPtyMaster)
+}
+
+/// Applies the window size of the terminal `terminal` to the pseudo
+/// terminal `pty`, so that the container process sees the dimensions of the
+/// terminal it is attached to
+pub fn resize_pty(terminal: RawFd, pty: RawFd) -> Result<()> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(terminal, libc::TIOCGWINSZ, &mut size) } < 0 {
+        return Err(TTYError::Resize {
+            source: Errno::last(),
+        });
+    }
+    if unsafe { libc::ioctl(pty, libc::TIOCSWINSZ, &size) } < 0 {
+        return Err(TTYError::Resize {
+            source: Errno::last(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Bind mounts the pseudo terminal the stdio of the process is connected to
+/// at /dev/console of the rootfs, like runc does, as programs like init
+/// systems write to the console. Has to be called before the root is changed,
+/// as the pseudo terminal has been opened in the devpts of the runtime.
+pub fn mount_console(rootfs: &Path) -> Result<()> {
+    let pty = ttyname(std::io::stdin()).map_err(|err| TTYError::PtyName { source: err })?;
+    let console = rootfs.join("dev/console");
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&console)
+        .map_err(|err| TTYError::CreateConsole {
+            source: err,
+            console: console.clone().into(),
+        })?;
+    mount(
+        Some(&pty),
+        &console,
+        None::<&str>,
+        MsFlags::MS_BIND,
+        None::<&str>,
+    )
+    .map_err(|err| TTYError::MountConsole {
+        source: err,
+        pty: pty.clone().into(),
+        console: console.clone().into(),
+    })?;
+
+    Ok(())
+}
+
+/// Makes the user of the container process the owner of the pseudo terminals
+/// its stdio is connected to. The pseudo terminal is created by the runtime,
+/// so a process running as another user would fail to reopen it, e.g. as
+/// /dev/tty. Has to be called before the user is set.
+pub fn chown_stdio_ptys(uid: Uid) -> Result<()> {
+    for fd in 0..3 {
+        if !isatty(fd).unwrap_or(false) {
+            continue;
+        }
+        let stat = match fstat(fd) {
+            Ok(stat) => stat,
+            Err(err) => return Err(TTYError::ChownStdIO { source: err, fd }),
+        };
+        if stat.st_uid == uid.as_raw() {
+            continue;
+        }
+        match fchown(fd, Some(uid), None) {
+            Ok(()) => {}
+            // the pseudo terminal belongs to a user which isn't mapped into
+            // the user namespace of the container, e.g. as it was passed by
+            // the caller of a rootless container
+            Err(Errno::EINVAL) | Err(Errno::EPERM) => {
+                tracing::debug!(fd, "could not change the owner of the pseudo terminal");
+            }
+            Err(err) => return Err(TTYError::ChownStdIO { source: err, fd }),
+        }
+    }
+
+    Ok(())
+}
+
 fn connect_stdio(stdin: &RawFd, stdout: &RawFd, stderr: &RawFd) -> Result<()> {
     dup2(stdin.as_raw_fd(), StdIO::Stdin.into()).map_err(|err| TTYError::ConnectStdIO {
         source: err,
@@ -208,6 +374,30 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[serial]
+    fn test_setup_console_socket_abstract() -> Result<()> {
+        let testdir = tempfile::tempdir()?;
+        let name = format!("youki-console-{}", std::process::id());
+        let lis = socket::socket(
+            socket::AddressFamily::Unix,
+            socket::SockType::Stream,
+            socket::SockFlag::empty(),
+            None,
+        )?;
+        socket::bind(lis.as_raw_fd(), &UnixAddr::new_abstract(name.as_bytes())?)?;
+        socket::listen(&lis, socket::Backlog::new(1)?)?;
+        let fd = setup_console_socket(
+            testdir.path(),
+            Path::new(&format!("@{name}")),
+            CONSOLE_SOCKET,
+        )?;
+        assert_ne!(fd, -1);
+        assert!(!testdir.path().join(CONSOLE_SOCKET).exists());
+        close(fd)?;
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_setup_console() -> Result<()> {
@@ -220,8 +410,7 @@ mod tests {
         let old_stdout: RawFd = nix::unistd::dup(StdIO::Stdout.into())?;
         let old_stderr: RawFd = nix::unistd::dup(StdIO::Stderr.into())?;
 
-        let lis = UnixListener::bind(&socket_path);
-        assert!(lis.is_ok());
+        let lis = UnixListener::bind(&socket_path)?;
         let fd = setup_console_socket(testdir.path(), &socket_path, CONSOLE_SOCKET);
         let status = setup_console(&fd.unwrap());
 
@@ -232,6 +421,133 @@ mod tests {
 
         assert!(status.is_ok());
 
+        let (stream, _) = lis.accept()?;
+        let mut buf = [0u8; 64];
+        let mut iov = [IoSliceMut::new(&mut buf)];
+        let mut cmsg_buf = nix::cmsg_space!([RawFd; 1]);
+        let msg = socket::recvmsg::<UnixAddr>(
+            stream.as_raw_fd(),
+            &mut iov,
+            Some(&mut cmsg_buf),
+            socket::MsgFlags::MSG_CMSG_CLOEXEC,
+        )?;
+        let fds: Vec<RawFd> = msg
+            .cmsgs()
+            .filter_map(|cmsg| match cmsg {
+                socket::ControlMessageOwned::ScmRights(fds) => Some(fds),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        assert_eq!(fds.len(), 1);
+        let _master = unsafe { OwnedFd::from_raw_fd(fds[0]) };
+        let len = msg.bytes;
+        assert_eq!(&buf[..len], b"/dev/ptmx");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receive_pty_master() -> Result<()> {
+        let (sender, receiver) = socket::socketpair(
+            socket::AddressFamily::Unix,
+            socket::SockType::Stream,
+            None,
+            socket::SockFlag::SOCK_CLOEXEC,
+        )?;
+        let pty = nix::pty::openpty(None, None)?;
+        let fds = [pty.master.as_raw_fd()];
+        socket::sendmsg::<UnixAddr>(
+            sender.as_raw_fd(),
+            &[IoSlice::new(b"/dev/ptmx")],
+            &[socket::ControlMessage::ScmRights(&fds)],
+            socket::MsgFlags::empty(),
+            None,
+        )?;
+
+        let master = receive_pty_master(receiver.as_raw_fd())?;
+
+        let size = libc::winsize {
+            ws_row: 42,
+            ws_col: 120,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        assert_eq!(
+            unsafe { libc::ioctl(pty.slave.as_raw_fd(), libc::TIOCSWINSZ, &size) },
+            0
+        );
+        resize_pty(pty.slave.as_raw_fd(), master.as_raw_fd())?;
+
+        let mut got: libc::winsize = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            unsafe { libc::ioctl(pty.master.as_raw_fd(), libc::TIOCGWINSZ, &mut got) },
+            0
+        );
+        assert_eq!((got.ws_row, got.ws_col), (42, 120));
+        Ok(())
+    }
+
+    #[test]
+    fn test_receive_pty_master_without_fd() -> Result<()> {
+        let (sender, receiver) = socket::socketpair(
+            socket::AddressFamily::Unix,
+            socket::SockType::Stream,
+            None,
+            socket::SockFlag::SOCK_CLOEXEC,
+        )?;
+        nix::unistd::write(&sender, b"/dev/ptmx")?;
+        assert!(matches!(
+            receive_pty_master(receiver.as_raw_fd()),
+            Err(TTYError::MissingPtyMaster)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_mount_console() -> Result<()> {
+        let rootfs = tempfile::tempdir()?;
+        std::fs::create_dir(rootfs.path().join("dev"))?;
+        let console = rootfs.path().join("dev/console");
+        crate::test_utils::test_in_child_process(|| {
+            nix::sched::unshare(nix::sched::CloneFlags::CLONE_NEWNS).expect("failed to unshare");
+            mount(
+                None::<&str>,
+                "/",
+                None::<&str>,
+                MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+                None::<&str>,
+            )
+            .expect("failed to make mounts private");
+            let pty = nix::pty::openpty(None, None).expect("failed to open pty");
+            dup2(pty.slave.as_raw_fd(), StdIO::Stdin.into()).expect("failed to dup2");
+
+            mount_console(rootfs.path()).map_err(|err| err.to_string())?;
+            let console = nix::sys::stat::stat(&console).expect("failed to stat console");
+            let slave = fstat(pty.slave.as_raw_fd()).expect("failed to stat pty");
+            assert_eq!(console.st_rdev, slave.st_rdev);
+            std::result::Result::Ok(())
+        })?;
+        // the mount is gone with the mount namespace of the child
+        assert_eq!(std::fs::metadata(&console)?.len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_chown_stdio_ptys() -> Result<()> {
+        crate::test_utils::test_in_child_process(|| {
+            // a terminal the tests are run in must not be changed
+            let pty = nix::pty::openpty(None, None).expect("failed to open pty");
+            let slave = pty.slave.as_raw_fd();
+            connect_stdio(&slave, &slave, &slave).expect("failed to connect stdio");
+
+            chown_stdio_ptys(Uid::from_raw(1000)).map_err(|err| err.to_string())?;
+            let slave = fstat(pty.slave.as_raw_fd()).expect("failed to stat pty");
+            assert_eq!(slave.st_uid, 1000);
+            std::result::Result::Ok(())
+        })?;
         Ok(())
     }
 }
@@ -0,0 +1,284 @@
+//! Resolution of the user of the container process from the /etc/passwd and
+//! /etc/group of the container, for users which are given by name, e.g. the
+//! `USER app:staff` of an image, instead of by uid and gid
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use oci_spec::runtime::User;
+
+const PASSWD: &str = "etc/passwd";
+const GROUP: &str = "etc/group";
+
+#[derive(Debug, thiserror::Error)]
+pub enum UserError {
+    #[error("failed to read {path:?}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("user {0:?} not found in /etc/passwd of the container")]
+    UnknownUser(String),
+    #[error("group {0:?} not found in /etc/group of the container")]
+    UnknownGroup(String),
+}
+
+type Result<T> = std::result::Result<T, UserError>;
+
+/// Entry of /etc/passwd
+#[derive(Debug, PartialEq, Eq)]
+struct Passwd {
+    name: String,
+    uid: u32,
+    gid: u32,
+    home: String,
+}
+
+/// Entry of /etc/group
+#[derive(Debug, PartialEq, Eq)]
+struct Group {
+    name: String,
+    gid: u32,
+    members: Vec<String>,
+}
+
+/// Returns the user of the container process, with the uid, gid and
+/// supplementary groups resolved in the filesystem of the container at
+/// `root` if the user has a username at the format `user[:group]`. Both can
+/// be given by name or by id. The additional gids of the user are kept.
+pub fn resolve(user: &User, root: &Path) -> Result<User> {
+    let username = match user.username() {
+        Some(username) => username,
+        None => return Ok(user.clone()),
+    };
+    let (name, group) = match username.split_once(':') {
+        Some((name, group)) => (name, Some(group)),
+        None => (username.as_str(), None),
+    };
+
+    let passwd = read_passwd(root)?;
+    // a numeric user doesn't have to exist, the same as for runc
+    let (uid, entry) = match name.parse::<u32>() {
+        Ok(uid) => (uid, passwd.iter().find(|p| p.uid == uid)),
+        Err(_) => {
+            let entry = passwd
+                .iter()
+                .find(|p| p.name == name)
+                .ok_or_else(|| UserError::UnknownUser(name.to_owned()))?;
+            (entry.uid, Some(entry))
+        }
+    };
+
+    let mut additional_gids = Vec::new();
+    let gid = match group {
+        Some(group) => match group.parse::<u32>() {
+            Ok(gid) => gid,
+            Err(_) => {
+                read_group(root)?
+                    .into_iter()
+                    .find(|g| g.name == group)
+                    .ok_or_else(|| UserError::UnknownGroup(group.to_owned()))?
+                    .gid
+            }
+        },
+        None => {
+            // the supplementary groups only apply to the implicit group of
+            // the user, an explicit group replaces all of them
+            if let Some(entry) = entry {
+                additional_gids = read_group(root)?
+                    .into_iter()
+                    .filter(|g| g.gid != entry.gid && g.members.iter().any(|m| m == &entry.name))
+                    .map(|g| g.gid)
+                    .collect();
+            }
+            entry.map_or(0, |entry| entry.gid)
+        }
+    };
+
+    for gid in user.additional_gids().iter().flatten() {
+        if !additional_gids.contains(gid) {
+            additional_gids.push(*gid);
+        }
+    }
+
+    let mut resolved = user.clone();
+    resolved
+        .set_uid(uid)
+        .set_gid(gid)
+        .set_additional_gids((!additional_gids.is_empty()).then_some(additional_gids));
+    tracing::debug!(?username, uid, gid, additional_gids = ?resolved.additional_gids(), "resolved user");
+    Ok(resolved)
+}
+
+/// Returns the home directory of the user with the uid in the /etc/passwd of
+/// the filesystem at `root`, which is `/` for users without one, the same as
+/// for runc
+pub fn home(uid: u32, root: &Path) -> Result<PathBuf> {
+    let home = read_passwd(root)?
+        .into_iter()
+        .find(|p| p.uid == uid)
+        .map(|p| p.home)
+        .filter(|home| !home.is_empty())
+        .unwrap_or_else(|| "/".to_owned());
+    Ok(PathBuf::from(home))
+}
+
+fn read_passwd(root: &Path) -> Result<Vec<Passwd>> {
+    Ok(read(&root.join(PASSWD))?
+        .lines()
+        .filter_map(parse_passwd)
+        .collect())
+}
+
+fn read_group(root: &Path) -> Result<Vec<Group>> {
+    Ok(read(&root.join(GROUP))?
+        .lines()
+        .filter_map(parse_group)
+        .collect())
+}
+
+// a missing file has no entries, so that containers without one can still
+// use numeric ids
+fn read(path: &Path) -> Result<String> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(source) => Err(UserError::Read {
+            path: path.to_owned(),
+            source,
+        }),
+    }
+}
+
+// name:password:uid:gid:gecos:home:shell, malformed lines are skipped
+fn parse_passwd(line: &str) -> Option<Passwd> {
+    if line.starts_with('#') {
+        return None;
+    }
+
+    let mut fields = line.split(':');
+    let name = fields.next()?;
+    let _password = fields.next()?;
+    let uid = fields.next()?.parse().ok()?;
+    let gid = fields.next()?.parse().ok()?;
+    let _gecos = fields.next();
+    Some(Passwd {
+        name: name.to_owned(),
+        uid,
+        gid,
+        home: fields.next().unwrap_or_default().to_owned(),
+    })
+}
+
+// name:password:gid:member,member, malformed lines are skipped
+fn parse_group(line: &str) -> Option<Group> {
+    if line.starts_with('#') {
+        return None;
+    }
+
+    let mut fields = line.split(':');
+    let name = fields.next()?;
+    let _password = fields.next()?;
+    let gid = fields.next()?.parse().ok()?;
+    let members = fields
+        .next()
+        .unwrap_or_default()
+        .split(',')
+        .filter(|m| !m.is_empty())
+        .map(|m| m.to_owned())
+        .collect();
+
+    Some(Group {
+        name: name.to_owned(),
+        gid,
+        members,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use oci_spec::runtime::UserBuilder;
+
+    use super::*;
+
+    fn rootfs() -> Result<tempfile::TempDir> {
+        let root = tempfile::tempdir()?;
+        fs::create_dir(root.path().join("etc"))?;
+        fs::write(
+            root.path().join(PASSWD),
+            "root:x:0:0:root:/root:/bin/sh\n\
+             # comment\n\
+             app:x:1000:1000::/home/app:/bin/sh\n\
+             broken:x:uid\n",
+        )?;
+        fs::write(
+            root.path().join(GROUP),
+            "root:x:0:\napp:x:1000:\nstaff:x:50:app,other\naudio:x:63:app\n",
+        )?;
+        Ok(root)
+    }
+
+    fn user(username: &str) -> User {
+        UserBuilder::default()
+            .username(username)
+            .additional_gids(vec![63, 7])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_resolve_by_name() -> Result<()> {
+        let root = rootfs()?;
+        let resolved = resolve(&user("app"), root.path())?;
+        assert_eq!((resolved.uid(), resolved.gid()), (1000, 1000));
+        assert_eq!(resolved.additional_gids(), &Some(vec![50, 63, 7]));
+
+        let resolved = resolve(&user("app:staff"), root.path())?;
+        assert_eq!((resolved.uid(), resolved.gid()), (1000, 50));
+        assert_eq!(resolved.additional_gids(), &Some(vec![63, 7]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_by_id() -> Result<()> {
+        let root = rootfs()?;
+        let resolved = resolve(&user("1000"), root.path())?;
+        assert_eq!((resolved.uid(), resolved.gid()), (1000, 1000));
+
+        let resolved = resolve(&user("4242:42"), root.path())?;
+        assert_eq!((resolved.uid(), resolved.gid()), (4242, 42));
+
+        let empty = tempfile::tempdir()?;
+        let resolved = resolve(&user("4242"), empty.path())?;
+        assert_eq!((resolved.uid(), resolved.gid()), (4242, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_unknown() -> Result<()> {
+        let root = rootfs()?;
+        assert!(matches!(
+            resolve(&user("nobody"), root.path()),
+            Err(UserError::UnknownUser(_))
+        ));
+        assert!(matches!(
+            resolve(&user("app:wheel"), root.path()),
+            Err(UserError::UnknownGroup(_))
+        ));
+
+        let without_username = UserBuilder::default().uid(5u32).build()?;
+        assert_eq!(resolve(&without_username, root.path())?, without_username);
+        Ok(())
+    }
+
+    #[test]
+    fn test_home() -> Result<()> {
+        let root = rootfs()?;
+        assert_eq!(home(1000, root.path())?, Path::new("/home/app"));
+        assert_eq!(home(4242, root.path())?, Path::new("/"));
+        fs::write(root.path().join(PASSWD), "nohome:x:1001:1001::\n")?;
+        assert_eq!(home(1001, root.path())?, Path::new("/"));
+        Ok(())
+    }
+}
@@ -2,13 +2,18 @@ use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::{env, fs};
 
-use nix::unistd::Pid;
+use nix::unistd::{self, Pid};
 use oci_spec::runtime::{Linux, LinuxIdMapping, LinuxNamespace, LinuxNamespaceType, Mount, Spec};
 
 use crate::error::MissingSpecError;
 use crate::namespaces::{NamespaceError, Namespaces};
 use crate::utils;
 
+/// Ranges of subordinate user ids that may be mapped by newuidmap
+const SUBUID_FILE: &str = "/etc/subuid";
+/// Ranges of subordinate group ids that may be mapped by newgidmap
+const SUBGID_FILE: &str = "/etc/subgid";
+
 // Wrap the uid/gid path function into a struct for dependency injection. This
 // allows us to mock the id mapping logic in unit tests by using a different
 // base path other than `/proc`.
@@ -95,7 +100,7 @@ pub enum ValidateSpecError {
     NoGIDMapping,
     #[error("no mount in spec")]
     NoMountSpec,
-    #[error("unprivileged user can't set supplementary groups")]
+    #[error("unprivileged user can't set supplementary groups, as setgroups has to be denied to write the gid mapping without newgidmap")]
     UnprivilegedUser,
     #[error("supplementary group needs to be mapped in the gid mappings")]
     GidNotMapped(u32),
@@ -123,6 +128,19 @@ pub enum MappingError {
     NoPathEnv,
     #[error("failed to execute newuidmap/newgidmap")]
     Execute(#[source] std::io::Error),
+    #[error("{binary:?} failed: {stderr}")]
+    MapBinaryFailed { binary: PathBuf, stderr: String },
+    #[error("failed to read subordinate ids from {path:?}")]
+    ReadSubordinateIds {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("{size} host ids starting at {host_id} are not delegated to the user in {path:?}")]
+    NotSubordinate {
+        host_id: u32,
+        size: u32,
+        path: PathBuf,
+    },
     #[error("at least one id mapping needs to be defined")]
     NoIDMapping,
     #[error("failed to write id mapping")]
@@ -159,15 +177,20 @@ impl UserNamespaceConfig {
         if user_namespace.is_some() && user_namespace.unwrap().path().is_none() {
             tracing::debug!("container with new user namespace should be created");
 
-            validate_spec_for_new_user_ns(spec).map_err(|err| {
-                tracing::error!("failed to validate spec for new user namespace: {}", err);
-                err
-            })?;
             let mut user_ns_config = UserNamespaceConfig::try_from(linux)?;
-            if let Some((uid_binary, gid_binary)) = lookup_map_binaries(linux)? {
+            if let Some((uid_binary, gid_binary)) =
+                lookup_map_binaries(linux, user_ns_config.privileged)?
+            {
+                validate_subordinate_ids(linux)?;
                 user_ns_config.newuidmap = Some(uid_binary);
                 user_ns_config.newgidmap = Some(gid_binary);
             }
+            validate_spec_for_new_user_ns(spec, user_ns_config.setgroups_deny_required()).map_err(
+                |err| {
+                    tracing::error!("failed to validate spec for new user namespace: {}", err);
+                    err
+                },
+            )?;
 
             Ok(Some(user_ns_config))
         } else {
@@ -205,6 +228,13 @@ impl UserNamespaceConfig {
     pub fn with_id_mapper(&mut self, mapper: UserNamespaceIDMapper) {
         self.id_mapper = mapper
     }
+
+    /// An unprivileged user can only write the gid mapping itself after
+    /// "deny" has been written to setgroups, see CVE-2014-8989. newgidmap
+    /// is allowed to write the mapping without it.
+    pub fn setgroups_deny_required(&self) -> bool {
+        !self.privileged && self.newgidmap.is_none()
+    }
 }
 
 impl TryFrom<&Linux> for UserNamespaceConfig {
@@ -250,7 +280,10 @@ pub fn unprivileged_user_ns_enabled() -> Result<bool> {
 
 /// Validates that the spec contains the required information for
 /// creating a new user namespace
-fn validate_spec_for_new_user_ns(spec: &Spec) -> std::result::Result<(), ValidateSpecError> {
+fn validate_spec_for_new_user_ns(
+    spec: &Spec,
+    setgroups_denied: bool,
+) -> std::result::Result<(), ValidateSpecError> {
     tracing::debug!(
         ?spec,
         "validating spec for container with new user namespace"
@@ -286,10 +319,8 @@ fn validate_spec_for_new_user_ns(spec: &Spec) -> std::result::Result<(), Validat
         .as_ref()
         .and_then(|process| process.user().additional_gids().as_ref())
     {
-        let privileged = !utils::rootless_required()?;
-
-        match (privileged, additional_gids.is_empty()) {
-            (true, false) => {
+        match (setgroups_denied, additional_gids.is_empty()) {
+            (false, false) => {
                 for gid in additional_gids {
                     if !is_id_mapped(*gid, gid_mappings) {
                         tracing::error!(?gid,"gid is specified as supplementary group, but is not mapped in the user namespace");
@@ -297,11 +328,11 @@ fn validate_spec_for_new_user_ns(spec: &Spec) -> std::result::Result<(), Validat
                     }
                 }
             }
-            (false, false) => {
+            (true, false) => {
                 tracing::error!(
                     user = ?nix::unistd::geteuid(),
                     "user is unprivileged. Supplementary groups cannot be set in \
-                        a rootless container for this user due to CVE-2014-8989",
+                        a rootless container for this user without newgidmap due to CVE-2014-8989",
                 );
                 return Err(ValidateSpecError::UnprivilegedUser);
             }
@@ -364,29 +395,41 @@ fn is_id_mapped(id: u32, mappings: &[LinuxIdMapping]) -> bool {
         .any(|m| id >= m.container_id() && id <= m.container_id() + m.size())
 }
 
-/// Looks up the location of the newuidmap and newgidmap binaries which
-/// are required to write multiple user/group mappings
+/// Looks up the location of the newuidmap and newgidmap binaries which are
+/// required by unprivileged users to write any mapping other than the one of
+/// their own user and group. Privileged users write all mappings directly.
 pub fn lookup_map_binaries(
     spec: &Linux,
+    privileged: bool,
 ) -> std::result::Result<Option<(PathBuf, PathBuf)>, MappingError> {
-    if let Some(uid_mappings) = spec.uid_mappings() {
-        if uid_mappings.len() == 1 && uid_mappings.len() == 1 {
-            return Ok(None);
-        }
+    if privileged
+        || (is_own_id_mapping(spec.uid_mappings(), unistd::geteuid().as_raw())
+            && is_own_id_mapping(spec.gid_mappings(), unistd::getegid().as_raw()))
+    {
+        return Ok(None);
+    }
 
-        let uidmap = lookup_map_binary("newuidmap")?;
-        let gidmap = lookup_map_binary("newgidmap")?;
+    let uidmap = lookup_map_binary("newuidmap")?;
+    let gidmap = lookup_map_binary("newgidmap")?;
 
-        match (uidmap, gidmap) {
-            (Some(newuidmap), Some(newgidmap)) => Ok(Some((newuidmap, newgidmap))),
-            _ => Err(MappingError::BinaryNotFound),
-        }
-    } else {
-        Ok(None)
+    match (uidmap, gidmap) {
+        (Some(newuidmap), Some(newgidmap)) => Ok(Some((newuidmap, newgidmap))),
+        _ => Err(MappingError::BinaryNotFound),
+    }
+}
+
+/// Checks if the mappings only map the given id of the host, which is the only
+/// mapping an unprivileged user is allowed to write by the kernel
+fn is_own_id_mapping(mappings: &Option<Vec<LinuxIdMapping>>, own_id: u32) -> bool {
+    match mappings.as_deref() {
+        Some([mapping]) => mapping.host_id() == own_id && mapping.size() == 1,
+        Some(_) => false,
+        None => true,
     }
 }
 
-fn lookup_map_binary(binary: &str) -> std::result::Result<Option<PathBuf>, MappingError> {
+/// Looks up a binary such as newuidmap in the directories of PATH
+pub fn lookup_map_binary(binary: &str) -> std::result::Result<Option<PathBuf>, MappingError> {
     let paths = env::var("PATH").map_err(|_| MappingError::NoPathEnv)?;
     Ok(paths
         .split_terminator(':')
@@ -394,6 +437,100 @@ fn lookup_map_binary(binary: &str) -> std::result::Result<Option<PathBuf>, Mappi
         .find(|p| p.exists()))
 }
 
+/// Checks that the host ids of the mappings are delegated to the user in
+/// /etc/subuid and /etc/subgid, so that newuidmap/newgidmap don't fail with
+/// an obscure error later on
+fn validate_subordinate_ids(linux: &Linux) -> std::result::Result<(), MappingError> {
+    let uid = unistd::geteuid();
+    let user_name = unistd::User::from_uid(uid)
+        .ok()
+        .flatten()
+        .map(|user| user.name);
+    if let Some(uid_mappings) = linux.uid_mappings() {
+        check_subordinate_ids(
+            Path::new(SUBUID_FILE),
+            uid_mappings,
+            uid.as_raw(),
+            user_name.as_deref(),
+            uid.as_raw(),
+        )?;
+    }
+    if let Some(gid_mappings) = linux.gid_mappings() {
+        check_subordinate_ids(
+            Path::new(SUBGID_FILE),
+            gid_mappings,
+            uid.as_raw(),
+            user_name.as_deref(),
+            unistd::getegid().as_raw(),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Checks that every mapping either maps the own id of the user or lies in
+/// one of the ranges delegated to the user, who is identified by name or uid
+fn check_subordinate_ids(
+    path: &Path,
+    mappings: &[LinuxIdMapping],
+    uid: u32,
+    user_name: Option<&str>,
+    own_id: u32,
+) -> std::result::Result<(), MappingError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => {
+            return Err(MappingError::ReadSubordinateIds {
+                source: err,
+                path: path.to_owned(),
+            })
+        }
+    };
+    let ranges = parse_subordinate_ids(&content, uid, user_name);
+
+    for mapping in mappings {
+        if mapping.host_id() == own_id && mapping.size() == 1 {
+            continue;
+        }
+
+        let start = u64::from(mapping.host_id());
+        let end = start + u64::from(mapping.size());
+        if !ranges
+            .iter()
+            .any(|(first, count)| start >= *first && end <= first + count)
+        {
+            tracing::error!(?mapping, ?path, "host ids are not delegated to the user");
+            return Err(MappingError::NotSubordinate {
+                host_id: mapping.host_id(),
+                size: mapping.size(),
+                path: path.to_owned(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the ranges of the user from the content of /etc/subuid or
+/// /etc/subgid, whose lines have the format "owner:first id:count"
+fn parse_subordinate_ids(content: &str, uid: u32, user_name: Option<&str>) -> Vec<(u64, u64)> {
+    let uid = uid.to_string();
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.trim().splitn(3, ':');
+            let owner = fields.next()?;
+            if owner != uid && Some(owner) != user_name {
+                return None;
+            }
+            let first = fields.next()?.parse().ok()?;
+            let count = fields.next()?.parse().ok()?;
+            Some((first, count))
+        })
+        .collect()
+}
+
 fn write_id_mapping(
     pid: Pid,
     map_file: &Path,
@@ -402,19 +539,12 @@ fn write_id_mapping(
 ) -> std::result::Result<(), MappingError> {
     tracing::debug!("Write ID mapping: {:?}", mappings);
 
-    match mappings.len() {
-        0 => return Err(MappingError::NoIDMapping),
-        1 => {
-            let mapping = mappings
-                .first()
-                .and_then(|m| format!("{} {} {}", m.container_id(), m.host_id(), m.size()).into())
-                .unwrap();
-            std::fs::write(map_file, &mapping).map_err(|err| {
-                tracing::error!(?err, ?map_file, ?mapping, "failed to write uid/gid mapping");
-                MappingError::WriteIDMapping(err)
-            })?;
-        }
-        _ => {
+    if mappings.is_empty() {
+        return Err(MappingError::NoIDMapping);
+    }
+
+    match map_binary {
+        Some(map_binary) => {
             let args: Vec<String> = mappings
                 .iter()
                 .flat_map(|m| {
@@ -426,10 +556,7 @@ fn write_id_mapping(
                 })
                 .collect();
 
-            // we can be certain here that map_binary will not be None,
-            // as in the lookup_map_binaries function, we return error
-            // if there are mappings.len() > 1 and binaries are not present
-            Command::new(map_binary.unwrap())
+            let output = Command::new(map_binary)
                 .arg(pid.to_string())
                 .args(args)
                 .output()
@@ -437,6 +564,26 @@ fn write_id_mapping(
                     tracing::error!(?err, ?map_binary, "failed to execute newuidmap/newgidmap");
                     MappingError::Execute(err)
                 })?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+                tracing::error!(?map_binary, status = ?output.status, %stderr, "failed to write uid/gid mapping");
+                return Err(MappingError::MapBinaryFailed {
+                    binary: map_binary.to_owned(),
+                    stderr,
+                });
+            }
+        }
+        None => {
+            // the kernel requires all ranges to be written at once
+            let mapping = mappings
+                .iter()
+                .map(|m| format!("{} {} {}", m.container_id(), m.host_id(), m.size()))
+                .collect::<Vec<_>>()
+                .join("\n");
+            std::fs::write(map_file, &mapping).map_err(|err| {
+                tracing::error!(?err, ?map_file, ?mapping, "failed to write uid/gid mapping");
+                MappingError::WriteIDMapping(err)
+            })?;
         }
     }
 
@@ -450,7 +597,8 @@ mod tests {
     use anyhow::Result;
     use nix::unistd::getpid;
     use oci_spec::runtime::{
-        LinuxBuilder, LinuxIdMappingBuilder, LinuxNamespaceBuilder, SpecBuilder,
+        LinuxBuilder, LinuxIdMappingBuilder, LinuxNamespaceBuilder, ProcessBuilder, SpecBuilder,
+        UserBuilder,
     };
     use rand::Rng;
     use serial_test::serial;
@@ -482,7 +630,7 @@ mod tests {
             .gid_mappings(gid_mappings)
             .build()?;
         let spec = SpecBuilder::default().linux(linux).build()?;
-        assert!(validate_spec_for_new_user_ns(&spec).is_ok());
+        assert!(validate_spec_for_new_user_ns(&spec, false).is_ok());
         Ok(())
     }
 
@@ -511,7 +659,8 @@ mod tests {
             &SpecBuilder::default()
                 .linux(linux_uid_empty)
                 .build()
-                .unwrap()
+                .unwrap(),
+            false
         )
         .is_err());
 
@@ -524,7 +673,8 @@ mod tests {
             &SpecBuilder::default()
                 .linux(linux_gid_empty)
                 .build()
-                .unwrap()
+                .unwrap(),
+            false
         )
         .is_err());
 
@@ -536,7 +686,8 @@ mod tests {
             &SpecBuilder::default()
                 .linux(linux_uid_none)
                 .build()
-                .unwrap()
+                .unwrap(),
+            false
         )
         .is_err());
 
@@ -548,7 +699,8 @@ mod tests {
             &SpecBuilder::default()
                 .linux(linux_gid_none)
                 .build()
-                .unwrap()
+                .unwrap(),
+            false
         )
         .is_err());
 
@@ -643,4 +795,131 @@ mod tests {
         );
         Ok(())
     }
+
+    fn mapping(host_id: u32, size: u32) -> LinuxIdMapping {
+        LinuxIdMappingBuilder::default()
+            .host_id(host_id)
+            .container_id(0_u32)
+            .size(size)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_validate_supplementary_gids() -> Result<()> {
+        let spec = SpecBuilder::default()
+            .linux(
+                LinuxBuilder::default()
+                    .namespaces(vec![LinuxNamespaceBuilder::default()
+                        .typ(LinuxNamespaceType::User)
+                        .build()?])
+                    .uid_mappings(vec![mapping(1000, 1)])
+                    .gid_mappings(vec![mapping(1000, 10)])
+                    .build()?,
+            )
+            .process(
+                ProcessBuilder::default()
+                    .user(UserBuilder::default().additional_gids(vec![5]).build()?)
+                    .build()?,
+            )
+            .build()?;
+
+        assert!(validate_spec_for_new_user_ns(&spec, false).is_ok());
+        assert!(matches!(
+            validate_spec_for_new_user_ns(&spec, true),
+            Err(ValidateSpecError::UnprivilegedUser)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_map_binaries() -> Result<()> {
+        let linux = LinuxBuilder::default()
+            .uid_mappings(vec![mapping(100000, 65536)])
+            .gid_mappings(vec![mapping(100000, 65536)])
+            .build()?;
+        assert_eq!(lookup_map_binaries(&linux, true)?, None);
+
+        let linux = LinuxBuilder::default()
+            .uid_mappings(vec![mapping(unistd::geteuid().as_raw(), 1)])
+            .gid_mappings(vec![mapping(unistd::getegid().as_raw(), 1)])
+            .build()?;
+        assert_eq!(lookup_map_binaries(&linux, false)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_subordinate_ids() {
+        let content = "alice:100000:65536\n1000:200000:1000\n# comment\nbob:300000:65536\n";
+        assert_eq!(
+            parse_subordinate_ids(content, 1000, Some("alice")),
+            vec![(100000, 65536), (200000, 1000)]
+        );
+        assert_eq!(parse_subordinate_ids(content, 1001, None), vec![]);
+    }
+
+    #[test]
+    fn test_check_subordinate_ids() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("subuid");
+        fs::write(&path, "alice:100000:65536\n")?;
+
+        let check = |mappings: &[LinuxIdMapping]| {
+            check_subordinate_ids(&path, mappings, 1000, Some("alice"), 1000)
+        };
+        assert!(check(&[mapping(1000, 1), mapping(100000, 65536)]).is_ok());
+        assert!(matches!(
+            check(&[mapping(100000, 65537)]),
+            Err(MappingError::NotSubordinate {
+                host_id: 100000,
+                size: 65537,
+                ..
+            })
+        ));
+        assert!(check(&[mapping(1000, 2)]).is_err());
+        // without the file only the own id can be mapped
+        assert!(check_subordinate_ids(
+            &tmp.path().join("missing"),
+            &[mapping(1000, 1)],
+            1000,
+            None,
+            1000
+        )
+        .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_multiple_mappings() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let map_file = tmp.path().join("uid_map");
+        write_id_mapping(
+            getpid(),
+            &map_file,
+            &[
+                mapping(1000, 1),
+                LinuxIdMappingBuilder::default()
+                    .host_id(100000_u32)
+                    .container_id(1_u32)
+                    .size(65536_u32)
+                    .build()?,
+            ],
+            None,
+        )?;
+        assert_eq!(fs::read_to_string(&map_file)?, "0 1000 1\n1 100000 65536");
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_binary_failed() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let result = write_id_mapping(
+            getpid(),
+            &tmp.path().join("uid_map"),
+            &[mapping(1000, 1)],
+            Some(Path::new("/bin/false")),
+        );
+        assert!(matches!(result, Err(MappingError::MapBinaryFailed { .. })));
+        Ok(())
+    }
 }
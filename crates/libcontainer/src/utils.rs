@@ -6,12 +6,15 @@ use std::os::linux::fs::MetadataExt;
 use std::os::unix::fs::DirBuilderExt;
 use std::path::{Component, Path, PathBuf};
 
+use nix::sys::resource::Resource;
 use nix::sys::stat::Mode;
 use nix::sys::statfs;
 use nix::unistd::{Uid, User};
-use oci_spec::runtime::Spec;
+use oci_spec::runtime::{
+    LinuxIOPriority, LinuxNamespaceType, LinuxSchedulerPolicy, PosixRlimitType, Scheduler, Spec,
+};
 
-use crate::error::LibcontainerError;
+use crate::error::{ErrInvalidSpec, LibcontainerError};
 use crate::user_ns::UserNamespaceConfig;
 
 #[derive(Debug, thiserror::Error)]
@@ -117,15 +120,34 @@ impl PathBufExt for Path {
     }
 }
 
+/// PATH of the container process if its environment doesn't set one, the same
+/// as the one of runc
+pub const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Returns why an entry of an environment isn't a `NAME=value` which can be
+/// passed to execve, if it isn't
+pub fn invalid_env(env: &str) -> Option<&'static str> {
+    if env.contains('\0') {
+        return Some("contains a nul byte");
+    }
+    match env.split_once('=') {
+        None => Some("missing '='"),
+        Some(("", _)) => Some("empty name"),
+        Some(_) => None,
+    }
+}
+
+/// Parses the entries of an environment, where later entries of a name
+/// override earlier ones. Entries which aren't `NAME=value` are left out.
 pub fn parse_env(envs: &[String]) -> HashMap<String, String> {
     envs.iter()
         .filter_map(|e| {
-            let mut split = e.split('=');
-
-            split.next().map(|key| {
-                let value = split.collect::<Vec<&str>>().join("=");
-                (key.into(), value)
-            })
+            if let Some(reason) = invalid_env(e) {
+                tracing::warn!(env = ?e, "ignoring invalid environment variable: {reason}");
+                return None;
+            }
+            e.split_once('=')
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
         })
         .collect()
 }
@@ -260,6 +282,48 @@ pub fn is_in_new_userns() -> Result<bool, std::io::Error> {
     Ok(!content.contains("4294967295"))
 }
 
+/// Returns the namespace a kernel parameter belongs to, along with the name of
+/// the namespace in /proc/[pid]/ns
+pub(crate) fn sysctl_namespace(key: &str) -> Option<(LinuxNamespaceType, &'static str)> {
+    match key {
+        "kernel.msgmax"
+        | "kernel.msgmnb"
+        | "kernel.msgmni"
+        | "kernel.sem"
+        | "kernel.shmall"
+        | "kernel.shmmax"
+        | "kernel.shmmni"
+        | "kernel.shm_rmid_forced" => Some((LinuxNamespaceType::Ipc, "ipc")),
+        key if key.starts_with("fs.mqueue.") => Some((LinuxNamespaceType::Ipc, "ipc")),
+        key if key.starts_with("net.") => Some((LinuxNamespaceType::Network, "net")),
+        "kernel.hostname" | "kernel.domainname" => Some((LinuxNamespaceType::Uts, "uts")),
+        _ => None,
+    }
+}
+
+/// Returns the resource of a rlimit type, whose number depends on the
+/// architecture
+pub(crate) fn rlimit_resource(typ: PosixRlimitType) -> Resource {
+    match typ {
+        PosixRlimitType::RlimitCpu => Resource::RLIMIT_CPU,
+        PosixRlimitType::RlimitFsize => Resource::RLIMIT_FSIZE,
+        PosixRlimitType::RlimitData => Resource::RLIMIT_DATA,
+        PosixRlimitType::RlimitStack => Resource::RLIMIT_STACK,
+        PosixRlimitType::RlimitCore => Resource::RLIMIT_CORE,
+        PosixRlimitType::RlimitRss => Resource::RLIMIT_RSS,
+        PosixRlimitType::RlimitNproc => Resource::RLIMIT_NPROC,
+        PosixRlimitType::RlimitNofile => Resource::RLIMIT_NOFILE,
+        PosixRlimitType::RlimitMemlock => Resource::RLIMIT_MEMLOCK,
+        PosixRlimitType::RlimitAs => Resource::RLIMIT_AS,
+        PosixRlimitType::RlimitLocks => Resource::RLIMIT_LOCKS,
+        PosixRlimitType::RlimitSigpending => Resource::RLIMIT_SIGPENDING,
+        PosixRlimitType::RlimitMsgqueue => Resource::RLIMIT_MSGQUEUE,
+        PosixRlimitType::RlimitNice => Resource::RLIMIT_NICE,
+        PosixRlimitType::RlimitRtprio => Resource::RLIMIT_RTPRIO,
+        PosixRlimitType::RlimitRttime => Resource::RLIMIT_RTTIME,
+    }
+}
+
 /// Checks if rootless mode needs to be used
 pub fn rootless_required() -> Result<bool, std::io::Error> {
     if !nix::unistd::geteuid().is_root() {
@@ -285,6 +349,124 @@ pub fn validate_spec_for_new_user_ns(spec: &Spec) -> Result<(), LibcontainerErro
     Ok(())
 }
 
+/// Returns the number of fds passed to the current process by systemd socket
+/// activation, see https://www.freedesktop.org/software/systemd/man/sd_listen_fds.html
+pub fn listen_fds() -> i32 {
+    parse_listen_fds(
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+/// The fds are only meant for the process LISTEN_PID refers to, they are not
+/// passed if the variables have been inherited from a parent.
+fn parse_listen_fds(listen_fds: Option<&str>, listen_pid: Option<&str>, pid: u32) -> i32 {
+    let (listen_fds, listen_pid) = match (listen_fds, listen_pid) {
+        (Some(listen_fds), Some(listen_pid)) => (listen_fds, listen_pid),
+        _ => return 0,
+    };
+
+    if listen_pid.parse::<u32>().ok() != Some(pid) {
+        tracing::debug!(?listen_pid, "ignoring LISTEN_FDS of another process");
+        return 0;
+    }
+
+    match listen_fds.parse::<i32>() {
+        Ok(n) if n >= 0 => n,
+        _ => {
+            tracing::warn!(
+                ?listen_fds,
+                "LISTEN_FDS is not a number of fds, ignoring it"
+            );
+            0
+        }
+    }
+}
+
+/// checks if the io priority is within the range of ioprio_set, see
+/// https://man7.org/linux/man-pages/man2/ioprio_set.2.html
+pub fn validate_io_priority(io_priority: &LinuxIOPriority) -> Result<(), ErrInvalidSpec> {
+    let priority = io_priority.priority();
+    if !(0..=7).contains(&priority) {
+        tracing::error!(
+            ?priority,
+            class = ?io_priority.class(),
+            "io priority must be within 0 to 7"
+        );
+        return Err(ErrInvalidSpec::IoPriority);
+    }
+
+    Ok(())
+}
+
+/// checks if the scheduler attributes are valid for the policy, see
+/// https://man7.org/linux/man-pages/man2/sched_setattr.2.html
+pub fn validate_scheduler(scheduler: &Scheduler) -> Result<(), ErrInvalidSpec> {
+    let policy = scheduler.policy();
+    if let Some(nice) = scheduler.nice() {
+        if (*policy == LinuxSchedulerPolicy::SchedBatch
+            || *policy == LinuxSchedulerPolicy::SchedOther)
+            && (*nice < -20 || *nice > 19)
+        {
+            tracing::error!(
+                ?nice,
+                "invalid scheduler.nice: '{}', must be within -20 to 19",
+                nice
+            );
+            return Err(ErrInvalidSpec::Scheduler);
+        }
+    }
+
+    if let Some(priority) = scheduler.priority() {
+        match policy {
+            LinuxSchedulerPolicy::SchedFifo | LinuxSchedulerPolicy::SchedRr => {
+                if !(1..=99).contains(priority) {
+                    tracing::error!(
+                        ?priority,
+                        "scheduler.priority must be within 1 to 99 for SchedFIFO and SchedRR"
+                    );
+                    return Err(ErrInvalidSpec::Scheduler);
+                }
+            }
+            _ if *priority != 0 => {
+                tracing::error!(
+                    ?policy,
+                    "scheduler.priority can only be specified for SchedFIFO or SchedRR policy"
+                );
+                return Err(ErrInvalidSpec::Scheduler);
+            }
+            _ => {}
+        }
+    }
+
+    let runtime = scheduler.runtime().unwrap_or(0);
+    let deadline = scheduler.deadline().unwrap_or(0);
+    let period = scheduler.period().unwrap_or(0);
+    if *policy == LinuxSchedulerPolicy::SchedDeadline {
+        // a period of 0 means that it is the same as the deadline
+        if runtime == 0 || runtime > deadline || (period != 0 && deadline > period) {
+            tracing::error!(
+                runtime,
+                deadline,
+                period,
+                "SchedDeadline requires 0 < runtime <= deadline <= period"
+            );
+            return Err(ErrInvalidSpec::Scheduler);
+        }
+    } else if runtime != 0 || deadline != 0 || period != 0 {
+        tracing::error!(
+            runtime,
+            deadline,
+            period,
+            "scheduler runtime, deadline and period can only be specified for SchedDeadline policy"
+        );
+        return Err(ErrInvalidSpec::Scheduler);
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use anyhow::{bail, Result};
@@ -339,6 +521,12 @@ mod tests {
         );
         assert_eq!(env_output.get_key_value(&key), Some((&key, &value)));
 
+        let env_input = ["A=1", "B==2=", "A=3", "FOO", "=bar", "C=\0"].map(String::from);
+        let env_output = parse_env(&env_input);
+        assert_eq!(env_output.len(), 2);
+        assert_eq!(env_output["A"], "3");
+        assert_eq!(env_output["B"], "=2=");
+
         Ok(())
     }
 
@@ -426,4 +614,87 @@ mod tests {
             Ok(())
         })
     }
+
+    #[test]
+    fn test_parse_listen_fds() {
+        assert_eq!(parse_listen_fds(Some("2"), Some("42"), 42), 2);
+        assert_eq!(parse_listen_fds(Some("2"), Some("43"), 42), 0);
+        assert_eq!(parse_listen_fds(Some("2"), None, 42), 0);
+        assert_eq!(parse_listen_fds(None, Some("42"), 42), 0);
+        assert_eq!(parse_listen_fds(Some("abc"), Some("42"), 42), 0);
+        assert_eq!(parse_listen_fds(Some("-1"), Some("42"), 42), 0);
+    }
+
+    #[test]
+    fn test_validate_io_priority() -> Result<()> {
+        use oci_spec::runtime::{IOPriorityClass, LinuxIOPriorityBuilder};
+
+        let io_priority = |class, priority| {
+            LinuxIOPriorityBuilder::default()
+                .class(class)
+                .priority(priority)
+                .build()
+                .unwrap()
+        };
+        assert!(validate_io_priority(&io_priority(IOPriorityClass::IoprioClassIdle, 0)).is_ok());
+        assert!(validate_io_priority(&io_priority(IOPriorityClass::IoprioClassBe, 7)).is_ok());
+        assert!(validate_io_priority(&io_priority(IOPriorityClass::IoprioClassRt, 8)).is_err());
+        assert!(validate_io_priority(&io_priority(IOPriorityClass::IoprioClassBe, -1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_scheduler() -> Result<()> {
+        use oci_spec::runtime::SchedulerBuilder;
+
+        let valid =
+            |builder: SchedulerBuilder| validate_scheduler(&builder.build().unwrap()).is_ok();
+
+        assert!(valid(
+            SchedulerBuilder::default()
+                .policy(LinuxSchedulerPolicy::SchedOther)
+                .nice(10)
+        ));
+        assert!(valid(
+            SchedulerBuilder::default()
+                .policy(LinuxSchedulerPolicy::SchedFifo)
+                .priority(50)
+        ));
+        assert!(valid(
+            SchedulerBuilder::default()
+                .policy(LinuxSchedulerPolicy::SchedDeadline)
+                .runtime(10_000_000u64)
+                .deadline(20_000_000u64)
+                .period(20_000_000u64)
+        ));
+
+        assert!(!valid(
+            SchedulerBuilder::default()
+                .policy(LinuxSchedulerPolicy::SchedBatch)
+                .nice(20)
+        ));
+        assert!(!valid(
+            SchedulerBuilder::default()
+                .policy(LinuxSchedulerPolicy::SchedRr)
+                .priority(100)
+        ));
+        assert!(!valid(
+            SchedulerBuilder::default()
+                .policy(LinuxSchedulerPolicy::SchedOther)
+                .priority(1)
+        ));
+        assert!(!valid(
+            SchedulerBuilder::default()
+                .policy(LinuxSchedulerPolicy::SchedOther)
+                .runtime(10_000_000u64)
+        ));
+        // the runtime has to fit into the deadline
+        assert!(!valid(
+            SchedulerBuilder::default()
+                .policy(LinuxSchedulerPolicy::SchedDeadline)
+                .runtime(30_000_000u64)
+                .deadline(20_000_000u64)
+        ));
+        Ok(())
+    }
 }
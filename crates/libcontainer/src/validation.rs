@@ -0,0 +1,1098 @@
+//! Validation of a spec before anything is created for the container from
+//! it. All problems are collected, so that they can be reported at once with
+//! the fields of the config.json they are about, instead of failing one by
+//! one deep inside the init process of the container.
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path};
+
+use libcgroups::common::CgroupSetup;
+use nix::sys::resource::getrlimit;
+use oci_spec::runtime::{
+    Capabilities, Capability, LinuxCapabilities, LinuxNamespaceType, LinuxPersonalityDomain,
+    LinuxResources, Mount, PosixRlimit, Spec,
+};
+
+use crate::capabilities::CapabilityExt;
+#[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
+use crate::seccomp;
+use crate::{
+    apparmor, arch, cpu_bandwidth, namespaces, net_devices, rootfs, rtnetlink, selinux, utils,
+};
+
+/// A problem with a field of the spec
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invalid {
+    /// The field as written in the config.json, e.g. `linux.namespaces[1]`
+    pub field: String,
+    /// What is wrong with the field
+    pub reason: String,
+}
+
+impl Invalid {
+    fn new(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.reason)
+    }
+}
+
+/// Formats all problems for a single error message, one per line
+pub fn report(invalid: &[Invalid]) -> String {
+    invalid
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Collects all problems of the spec which would prevent the creation of a
+/// container from it on this host
+pub fn check(spec: &Spec) -> Vec<Invalid> {
+    let mut invalid = Vec::new();
+    if !spec.version().starts_with("1.") {
+        invalid.push(Invalid::new(
+            "ociVersion",
+            format!(
+                "incompatible version {}, only 1.X.Y is supported",
+                spec.version()
+            ),
+        ));
+    }
+
+    invalid.extend(check_process(spec));
+    invalid.extend(check_namespaces(spec));
+    invalid.extend(check_mounts(spec));
+    if let Some(propagation) = spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.rootfs_propagation().as_deref())
+    {
+        if rootfs::utils::propagation_flags(propagation).is_none() {
+            invalid.push(Invalid::new(
+                "linux.rootfsPropagation",
+                format!("unknown propagation type {propagation}"),
+            ));
+        }
+    }
+    if let Some(personality) = spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.personality().as_ref())
+    {
+        if let Some(reason) = check_personality(personality.domain()) {
+            invalid.push(Invalid::new("linux.personality.domain", reason));
+        }
+    }
+    invalid.extend(check_paths(spec));
+    invalid.extend(check_time_offsets(spec));
+    invalid.extend(check_sysctl(spec));
+    invalid.extend(check_net_devices(spec));
+    invalid.extend(check_cpu_bandwidth(spec));
+    match libcgroups::common::get_cgroup_setup() {
+        Ok(setup) => invalid.extend(check_resources(spec, setup)),
+        // the cgroup manager reports this once it is created
+        Err(err) => tracing::warn!(?err, "failed to determine the cgroup setup"),
+    }
+
+    invalid.extend(check_seccomp(spec));
+
+    if let Err(err) = utils::validate_spec_for_new_user_ns(spec) {
+        invalid.push(Invalid::new("linux.namespaces", err.to_string()));
+    }
+
+    invalid
+}
+
+/// Collects the problems of the spec of a process which is executed in an
+/// existing container, which only brings its process and seccomp profile
+pub fn check_exec(spec: &Spec) -> Vec<Invalid> {
+    let mut invalid = check_process(spec);
+    invalid.extend(check_seccomp(spec));
+    invalid
+}
+
+/// Collects the entries of the environment of the process which aren't
+/// `NAME=value`. They are left out of the environment unless they are
+/// rejected with this check.
+pub fn check_env(spec: &Spec) -> Vec<Invalid> {
+    spec.process()
+        .as_ref()
+        .and_then(|process| process.env().as_ref())
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .filter_map(|(i, env)| {
+            utils::invalid_env(env).map(|reason| Invalid::new(format!("process.env[{i}]"), reason))
+        })
+        .collect()
+}
+
+#[cfg(any(feature = "libseccomp", feature = "seccomp-bpf"))]
+fn check_seccomp(spec: &Spec) -> Vec<Invalid> {
+    let mut invalid = Vec::new();
+    if let Some(seccomp) = spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.seccomp().as_ref())
+    {
+        if let Err(err) = seccomp::check_seccomp(seccomp) {
+            invalid.push(Invalid::new("linux.seccomp", err.to_string()));
+        }
+    }
+
+    invalid
+}
+
+#[cfg(not(any(feature = "libseccomp", feature = "seccomp-bpf")))]
+fn check_seccomp(_spec: &Spec) -> Vec<Invalid> {
+    Vec::new()
+}
+
+fn check_process(spec: &Spec) -> Vec<Invalid> {
+    let mut invalid = Vec::new();
+    let process = match spec.process() {
+        Some(process) => process,
+        None => return invalid,
+    };
+
+    if process.apparmor_profile().is_some() {
+        match apparmor::is_enabled() {
+            Ok(true) => {}
+            Ok(false) => invalid.push(Invalid::new(
+                "process.apparmorProfile",
+                "apparmor is not enabled on this system",
+            )),
+            Err(err) => invalid.push(Invalid::new(
+                "process.apparmorProfile",
+                format!("failed to check if apparmor is enabled: {err}"),
+            )),
+        }
+    }
+
+    if let Some(label) = process.selinux_label() {
+        if !label.is_empty() && !selinux::is_enabled() {
+            invalid.push(Invalid::new(
+                "process.selinuxLabel",
+                "selinux is not enabled on this system",
+            ));
+        }
+    }
+
+    if let Some(scheduler) = process.scheduler() {
+        if let Err(err) = utils::validate_scheduler(scheduler) {
+            invalid.push(Invalid::new("process.scheduler", err.to_string()));
+        }
+    }
+
+    if let Some(io_priority) = process.io_priority() {
+        if let Err(err) = utils::validate_io_priority(io_priority) {
+            invalid.push(Invalid::new("process.ioPriority", err.to_string()));
+        }
+    }
+
+    if let Some(capabilities) = process.capabilities() {
+        invalid.extend(check_capabilities(
+            capabilities,
+            process.user().uid(),
+            process.no_new_privileges().unwrap_or(false),
+        ));
+    }
+
+    if let Some(rlimits) = process.rlimits() {
+        invalid.extend(check_rlimits(rlimits, can_raise_hard_limits(spec)));
+    }
+
+    invalid
+}
+
+/// Hard limits can only be raised with CAP_SYS_RESOURCE in the initial user
+/// namespace, which the process doesn't have in a user namespace
+fn can_raise_hard_limits(spec: &Spec) -> bool {
+    let joins_user_ns = spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.namespaces().as_ref())
+        .map_or(false, |namespaces| {
+            namespaces
+                .iter()
+                .any(|ns| ns.typ() == LinuxNamespaceType::User)
+        });
+    let in_user_ns = joins_user_ns || utils::is_in_new_userns().unwrap_or(true);
+    !in_user_ns
+        && caps::has_cap(
+            None,
+            caps::CapSet::Effective,
+            caps::Capability::CAP_SYS_RESOURCE,
+        )
+        .unwrap_or(false)
+}
+
+fn check_rlimits(rlimits: &[PosixRlimit], can_raise_hard_limits: bool) -> Vec<Invalid> {
+    let mut invalid = Vec::new();
+    for (i, rlimit) in rlimits.iter().enumerate() {
+        let field = format!("process.rlimits[{i}]");
+        let typ = rlimit.typ();
+        if rlimits[..i].iter().any(|r| r.typ() == typ) {
+            invalid.push(Invalid::new(&field, format!("{typ} is set more than once")));
+        }
+        if rlimit.soft() > rlimit.hard() {
+            invalid.push(Invalid::new(
+                &field,
+                format!(
+                    "the soft limit {} of {typ} is greater than its hard limit {}",
+                    rlimit.soft(),
+                    rlimit.hard()
+                ),
+            ));
+        }
+        if !can_raise_hard_limits {
+            if let Ok((_, hard)) = getrlimit(utils::rlimit_resource(typ)) {
+                if rlimit.hard() > hard {
+                    invalid.push(Invalid::new(
+                        &field,
+                        format!(
+                            "the hard limit {} of {typ} is greater than the one of the runtime {hard}, which can't be raised without CAP_SYS_RESOURCE",
+                            rlimit.hard()
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    invalid
+}
+
+/// Ambient capabilities are only raised by the kernel if they are permitted
+/// and inheritable. Without them, a process of a non-root user loses all its
+/// capabilities when it executes the payload, unless the binary has file
+/// capabilities, which noNewPrivileges ignores.
+fn check_capabilities(
+    capabilities: &LinuxCapabilities,
+    uid: u32,
+    no_new_privileges: bool,
+) -> Vec<Invalid> {
+    let empty = Capabilities::new();
+    let ambient = capabilities.ambient().as_ref().unwrap_or(&empty);
+    let permitted = capabilities.permitted().as_ref().unwrap_or(&empty);
+    let inheritable = capabilities.inheritable().as_ref().unwrap_or(&empty);
+
+    let mut invalid = Vec::new();
+    let unraisable = capability_names(
+        ambient
+            .iter()
+            .filter(|cap| !permitted.contains(cap) || !inheritable.contains(cap)),
+    );
+    if !unraisable.is_empty() {
+        invalid.push(Invalid::new(
+            "process.capabilities.ambient",
+            format!(
+                "{} must be in the permitted and inheritable sets as well",
+                unraisable.join(", ")
+            ),
+        ));
+    }
+
+    if uid != 0 {
+        let effective = capabilities.effective().as_ref().unwrap_or(&empty);
+        let lost = capability_names(
+            effective
+                .union(permitted)
+                .filter(|cap| !ambient.contains(cap)),
+        );
+        if !lost.is_empty() && no_new_privileges {
+            tracing::warn!(
+                uid,
+                ?lost,
+                "capabilities which are not ambient are dropped when a non-root user executes the process, and noNewPrivileges prevents file capabilities from raising them"
+            );
+        } else if !lost.is_empty() {
+            tracing::warn!(
+                uid,
+                ?lost,
+                "capabilities which are not ambient are dropped when a non-root user executes the process, unless it has file capabilities"
+            );
+        }
+    }
+
+    invalid
+}
+
+fn capability_names<'a>(caps: impl Iterator<Item = &'a Capability>) -> Vec<String> {
+    let mut names: Vec<String> = caps.map(|cap| cap.to_cap().to_string()).collect();
+    names.sort();
+    names
+}
+
+/// Returns the name of the namespace type in /proc/[pid]/ns
+fn proc_ns_name(typ: LinuxNamespaceType) -> &'static str {
+    match typ {
+        LinuxNamespaceType::Mount => "mnt",
+        LinuxNamespaceType::Cgroup => "cgroup",
+        LinuxNamespaceType::Uts => "uts",
+        LinuxNamespaceType::Ipc => "ipc",
+        LinuxNamespaceType::User => "user",
+        LinuxNamespaceType::Pid => "pid",
+        LinuxNamespaceType::Network => "net",
+        LinuxNamespaceType::Time => "time",
+    }
+}
+
+fn creates_namespace(spec: &Spec, typ: LinuxNamespaceType) -> bool {
+    spec.linux()
+        .as_ref()
+        .and_then(|linux| linux.namespaces().as_ref())
+        .map(|namespaces| {
+            namespaces
+                .iter()
+                .any(|ns| ns.typ() == typ && ns.path().is_none())
+        })
+        .unwrap_or(false)
+}
+
+// Every namespace type may only be given once, the namespaces to create have
+// to be supported by the kernel, and the ones to join must exist and be of the
+// right type.
+fn check_namespaces(spec: &Spec) -> Vec<Invalid> {
+    let mut invalid = Vec::new();
+    let namespaces = spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.namespaces().as_ref())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for (i, ns) in namespaces.iter().enumerate() {
+        let field = format!("linux.namespaces[{i}]");
+        if namespaces[..i].iter().any(|other| other.typ() == ns.typ()) {
+            invalid.push(Invalid::new(
+                &field,
+                format!("duplicate {:?} namespace", ns.typ()),
+            ));
+            continue;
+        }
+
+        match ns.path() {
+            Some(path) => {
+                if let Err(err) = namespaces::validate_namespace_path(path, ns.typ()) {
+                    invalid.push(Invalid::new(&field, err.to_string()));
+                }
+            }
+            None => {
+                let name = proc_ns_name(ns.typ());
+                if !Path::new("/proc/self/ns").join(name).exists() {
+                    invalid.push(Invalid::new(
+                        &field,
+                        format!("{name} namespaces are not supported by the kernel"),
+                    ));
+                }
+            }
+        }
+    }
+
+    // the paths are masked with mounts, which would otherwise be visible on the host
+    let has_paths = spec.linux().as_ref().map_or(false, |linux| {
+        linux.masked_paths().iter().flatten().next().is_some()
+            || linux.readonly_paths().iter().flatten().next().is_some()
+    });
+    let has_mount_ns = namespaces
+        .iter()
+        .any(|ns| ns.typ() == LinuxNamespaceType::Mount);
+    if has_paths && !has_mount_ns {
+        invalid.push(Invalid::new(
+            "linux.namespaces",
+            "maskedPaths and readonlyPaths require a mount namespace of the container",
+        ));
+    }
+
+    invalid
+}
+
+// Mounting twice onto the same destination hides the first mount, and the
+// mappings of idmapped mounts are taken from the user namespace of the
+// container, so one has to be created along with the container.
+fn check_mounts(spec: &Spec) -> Vec<Invalid> {
+    let mut invalid = Vec::new();
+    let creates_user_ns = creates_namespace(spec, LinuxNamespaceType::User);
+    let mut destinations = HashSet::new();
+    for (i, mount) in spec.mounts().iter().flatten().enumerate() {
+        let field = format!("mounts[{i}]");
+        if !destinations.insert(mount.destination()) {
+            invalid.push(Invalid::new(
+                &field,
+                format!(
+                    "duplicate mount destination {}",
+                    mount.destination().display()
+                ),
+            ));
+        }
+
+        if rootfs::utils::idmap_type(mount).is_some() && !creates_user_ns {
+            invalid.push(Invalid::new(
+                &field,
+                "idmapped mounts require a new user namespace",
+            ));
+        }
+
+        if let Some(reason) = check_mount_data(mount) {
+            invalid.push(Invalid::new(&field, reason));
+        }
+    }
+
+    invalid
+}
+
+// The kernel rejects unknown options of these filesystems with nothing but
+// EINVAL, which doesn't tell which option is wrong.
+fn check_mount_data(mount: &Mount) -> Option<String> {
+    let typ = mount.typ().as_deref()?;
+    let allowed: Option<&[&str]> = match typ {
+        "sysfs" | "mqueue" => Some(&[]),
+        "proc" => Some(&["hidepid", "gid", "subset"]),
+        "devpts" => Some(&["newinstance", "ptmxmode", "mode", "gid", "uid", "max"]),
+        // tmpfs has too many options to list, only their values are checked
+        "tmpfs" => None,
+        _ => return None,
+    };
+
+    let config = rootfs::utils::parse_mount(mount).ok()?;
+    for option in config.data.split(',').filter(|o| !o.is_empty()) {
+        let (key, value) = option.split_once('=').unwrap_or((option, ""));
+        if allowed.map_or(false, |allowed| !allowed.contains(&key)) {
+            return Some(format!("{typ} doesn't support the option {option}"));
+        }
+
+        let valid = match key {
+            "mode" | "ptmxmode" => u32::from_str_radix(value, 8).is_ok(),
+            "gid" | "uid" | "max" => value.parse::<u32>().is_ok(),
+            _ => true,
+        };
+        if !valid {
+            return Some(format!("invalid value of the option {option}"));
+        }
+    }
+
+    None
+}
+
+// The paths are resolved inside of the rootfs, so they have to be absolute
+// and must not lead out of it.
+fn check_paths(spec: &Spec) -> Vec<Invalid> {
+    let mut invalid = Vec::new();
+    let linux = match spec.linux() {
+        Some(linux) => linux,
+        None => return invalid,
+    };
+
+    let paths = [
+        ("maskedPaths", linux.masked_paths()),
+        ("readonlyPaths", linux.readonly_paths()),
+    ];
+    for (name, paths) in paths {
+        for (i, path) in paths.iter().flatten().enumerate() {
+            let path = Path::new(path);
+            let inside_rootfs =
+                path.is_absolute() && !path.components().any(|c| c == Component::ParentDir);
+            if !inside_rootfs {
+                invalid.push(Invalid::new(
+                    format!("linux.{name}[{i}]"),
+                    format!(
+                        "{} is not an absolute path inside of the rootfs",
+                        path.display()
+                    ),
+                ));
+            }
+        }
+    }
+
+    invalid
+}
+
+fn check_personality(domain: LinuxPersonalityDomain) -> Option<String> {
+    match domain {
+        LinuxPersonalityDomain::PerLinux32 if !arch::LINUX32_PERSONALITY => Some(format!(
+            "LINUX32 is not supported on {}, which can't run 32-bit processes",
+            arch::NAME
+        )),
+        _ => None,
+    }
+}
+
+fn check_time_offsets(spec: &Spec) -> Vec<Invalid> {
+    let mut invalid = Vec::new();
+    let offsets = match spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.time_offsets().as_ref())
+    {
+        Some(offsets) => offsets,
+        None => return invalid,
+    };
+
+    // offsets can only be set for a time namespace created with the container
+    if !creates_namespace(spec, LinuxNamespaceType::Time) {
+        invalid.push(Invalid::new(
+            "linux.timeOffsets",
+            "time offsets require a new time namespace",
+        ));
+    }
+
+    if let Err(err) = namespaces::format_time_offsets(offsets) {
+        invalid.push(Invalid::new("linux.timeOffsets", err.to_string()));
+    }
+
+    invalid
+}
+
+// Like runc, only namespaced kernel parameters can be set, and only if the
+// container does not share the namespace with the host.
+fn check_sysctl(spec: &Spec) -> Vec<Invalid> {
+    let mut invalid = Vec::new();
+    let linux = match spec.linux() {
+        Some(linux) => linux,
+        None => return invalid,
+    };
+
+    for (key, value) in linux.sysctl().iter().flatten() {
+        let field = format!("linux.sysctl.{key}");
+        let key = key.replace('/', ".");
+        let (ns_type, ns_name) = match utils::sysctl_namespace(&key) {
+            Some(namespace) => namespace,
+            None => {
+                invalid.push(Invalid::new(
+                    &field,
+                    format!(
+                        "{key} is not in a separate kernel namespace and would change the host"
+                    ),
+                ));
+                continue;
+            }
+        };
+
+        if key == "kernel.hostname" {
+            if let Some(hostname) = spec.hostname() {
+                if hostname != value {
+                    invalid.push(Invalid::new(
+                        &field,
+                        format!("{value} conflicts with the hostname {hostname} of the spec"),
+                    ));
+                }
+            }
+        }
+
+        let namespace = linux
+            .namespaces()
+            .iter()
+            .flatten()
+            .find(|ns| ns.typ() == ns_type);
+        let is_host_namespace = match namespace {
+            None => Ok(true),
+            Some(ns) => match ns.path() {
+                None => Ok(false),
+                Some(path) => is_host_namespace(path, ns_name),
+            },
+        };
+        match is_host_namespace {
+            Ok(false) => {}
+            Ok(true) => invalid.push(Invalid::new(
+                &field,
+                format!(
+                    "{key} is not allowed in the host {ns_name} namespace, a separate {ns_name} namespace is required"
+                ),
+            )),
+            Err(err) => invalid.push(Invalid::new(
+                &field,
+                format!("failed to check the {ns_name} namespace: {err}"),
+            )),
+        }
+    }
+
+    invalid
+}
+
+/// Checks if the namespace at the path is the one of the runtime, i.e. of the host
+// The network devices are moved into a network namespace of the container,
+// which has to be another one than the namespace of the host they are in.
+fn check_net_devices(spec: &Spec) -> Vec<Invalid> {
+    let field = format!("annotations.{}", net_devices::NET_DEVICES_ANNOTATION);
+    let devices = match net_devices::from_annotations(spec.annotations().as_ref()) {
+        Ok(devices) => devices,
+        Err(err) => return vec![Invalid::new(field, err.to_string())],
+    };
+    if devices.is_empty() {
+        return Vec::new();
+    }
+
+    let mut invalid = Vec::new();
+    let netns = spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.namespaces().as_ref())
+        .and_then(|namespaces| {
+            namespaces
+                .iter()
+                .find(|ns| ns.typ() == LinuxNamespaceType::Network)
+        });
+    let own_netns = match netns.map(|ns| ns.path()) {
+        Some(Some(path)) => !is_host_namespace(path, "net").unwrap_or(false),
+        Some(None) => true,
+        None => false,
+    };
+    if !own_netns {
+        invalid.push(Invalid::new(
+            &field,
+            "network devices require a network namespace of the container",
+        ));
+    }
+
+    for device in devices {
+        if rtnetlink::index_of(&device.host_name).is_none() {
+            invalid.push(Invalid::new(
+                &field,
+                format!("network device {} doesn't exist", device.host_name),
+            ));
+        }
+    }
+
+    invalid
+}
+
+fn is_host_namespace(path: &Path, ns_name: &str) -> Result<bool, std::io::Error> {
+    let namespace = fs::metadata(path)?;
+    let host_namespace = fs::metadata(format!("/proc/self/ns/{ns_name}"))?;
+    Ok(namespace.dev() == host_namespace.dev() && namespace.ino() == host_namespace.ino())
+}
+
+// The kernel rejects a period or quota out of its bounds without telling
+// which one, unless they are rounded into the bounds on request.
+fn check_cpu_bandwidth(spec: &Spec) -> Vec<Invalid> {
+    cpu_bandwidth::cpu_of(spec)
+        .map(cpu_bandwidth::check)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(field, reason)| Invalid::new(format!("linux.resources.cpu.{field}"), reason))
+        .collect()
+}
+
+// Resources which only cgroup v1 provides can't be applied on a host with
+// only cgroup v2, where they would be silently ignored or fail late.
+fn check_resources(spec: &Spec, setup: CgroupSetup) -> Vec<Invalid> {
+    let mut invalid = Vec::new();
+    let resources = match spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.resources().as_ref())
+    {
+        Some(resources) => resources,
+        None => return invalid,
+    };
+    if !matches!(setup, CgroupSetup::Unified) {
+        return invalid;
+    }
+
+    for field in v1_only_resources(resources) {
+        invalid.push(Invalid::new(
+            format!("linux.resources.{field}"),
+            "only supported by cgroup v1, but this host only has cgroup v2",
+        ));
+    }
+
+    if let Some(memory) = resources.memory() {
+        if memory.kernel().is_some() || memory.kernel_tcp().is_some() {
+            tracing::warn!("kernel memory limits are not supported by cgroup v2 and are ignored");
+        }
+    }
+
+    invalid
+}
+
+fn v1_only_resources(resources: &LinuxResources) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if let Some(cpu) = resources.cpu() {
+        if cpu.realtime_runtime().is_some() {
+            fields.push("cpu.realtimeRuntime");
+        }
+        if cpu.realtime_period().is_some() {
+            fields.push("cpu.realtimePeriod");
+        }
+    }
+    if let Some(memory) = resources.memory() {
+        if memory.swappiness().is_some() {
+            fields.push("memory.swappiness");
+        }
+    }
+    if resources.network().is_some() {
+        fields.push("network");
+    }
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use nix::sys::resource::Resource;
+    use oci_spec::runtime::{
+        LinuxBuilder, LinuxCapabilitiesBuilder, LinuxMemoryBuilder, LinuxNamespaceBuilder,
+        LinuxResourcesBuilder, MountBuilder, PosixRlimitBuilder, PosixRlimitType, ProcessBuilder,
+        SpecBuilder,
+    };
+
+    use super::*;
+
+    fn namespaces(namespaces: Vec<(LinuxNamespaceType, Option<&str>)>) -> LinuxBuilder {
+        let namespaces = namespaces
+            .into_iter()
+            .map(|(typ, path)| {
+                let mut builder = LinuxNamespaceBuilder::default().typ(typ);
+                if let Some(path) = path {
+                    builder = builder.path(path);
+                }
+                builder.build().unwrap()
+            })
+            .collect::<Vec<_>>();
+        LinuxBuilder::default()
+            .namespaces(namespaces)
+            .masked_paths(vec![])
+            .readonly_paths(vec![])
+    }
+
+    fn spec_with_sysctl(key: &str, ns: Vec<(LinuxNamespaceType, Option<&str>)>) -> Spec {
+        let sysctl: HashMap<String, String> = [(key.to_owned(), "1".to_owned())].into();
+        SpecBuilder::default()
+            .hostname("youki")
+            .linux(namespaces(ns).sysctl(sysctl).build().unwrap())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_check_sysctl() {
+        let valid = |key, namespaces| check_sysctl(&spec_with_sysctl(key, namespaces)).is_empty();
+
+        assert!(valid(
+            "net.ipv4.ip_forward",
+            vec![(LinuxNamespaceType::Network, None)]
+        ));
+        assert!(valid(
+            "net/ipv4/ip_forward",
+            vec![(LinuxNamespaceType::Network, None)]
+        ));
+        assert!(valid(
+            "fs.mqueue.queues_max",
+            vec![(LinuxNamespaceType::Ipc, None)]
+        ));
+        assert!(valid(
+            "kernel.domainname",
+            vec![(LinuxNamespaceType::Uts, None)]
+        ));
+
+        // not namespaced
+        assert!(!valid("vm.swappiness", vec![]));
+        // the namespace is shared with the host
+        assert!(!valid("net.ipv4.ip_forward", vec![]));
+        assert!(!valid(
+            "net.ipv4.ip_forward",
+            vec![(LinuxNamespaceType::Network, Some("/proc/self/ns/net"))]
+        ));
+        assert!(!valid("kernel.shmmax", vec![]));
+        // conflicts with the hostname of the spec
+        assert!(!valid(
+            "kernel.hostname",
+            vec![(LinuxNamespaceType::Uts, None)]
+        ));
+    }
+
+    #[test]
+    fn test_check_personality() {
+        assert_eq!(check_personality(LinuxPersonalityDomain::PerLinux), None);
+        assert_eq!(
+            check_personality(LinuxPersonalityDomain::PerLinux32).is_none(),
+            arch::LINUX32_PERSONALITY
+        );
+    }
+
+    #[test]
+    fn test_check_namespaces() {
+        let spec = |ns: Vec<(LinuxNamespaceType, Option<&str>)>| {
+            SpecBuilder::default()
+                .linux(namespaces(ns).build().unwrap())
+                .build()
+                .unwrap()
+        };
+        assert!(check_namespaces(&spec(vec![
+            (LinuxNamespaceType::Network, Some("/proc/self/ns/net")),
+            (LinuxNamespaceType::Ipc, None),
+        ]))
+        .is_empty());
+        assert_eq!(
+            check_namespaces(&spec(vec![(
+                LinuxNamespaceType::Uts,
+                Some("/proc/self/ns/net")
+            )]))[0]
+                .field,
+            "linux.namespaces[0]"
+        );
+        assert_eq!(
+            check_namespaces(&spec(vec![
+                (LinuxNamespaceType::Network, None),
+                (LinuxNamespaceType::Network, Some("/proc/self/ns/net")),
+            ])),
+            vec![Invalid::new(
+                "linux.namespaces[1]",
+                "duplicate Network namespace"
+            )]
+        );
+
+        let masked = SpecBuilder::default()
+            .linux(
+                namespaces(vec![])
+                    .masked_paths(vec!["/proc/kcore".to_owned()])
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        assert_eq!(check_namespaces(&masked).len(), 1);
+    }
+
+    #[test]
+    fn test_check_capabilities() {
+        let set = |caps: &[Capability]| caps.iter().copied().collect::<Capabilities>();
+        let capabilities = LinuxCapabilitiesBuilder::default()
+            .effective(set(&[Capability::NetBindService]))
+            .permitted(set(&[Capability::NetBindService, Capability::Kill]))
+            .inheritable(set(&[Capability::NetBindService]))
+            .ambient(set(&[Capability::NetBindService]))
+            .build()
+            .unwrap();
+        assert!(check_capabilities(&capabilities, 1000, true).is_empty());
+
+        let mut capabilities = capabilities;
+        capabilities.set_ambient(Some(set(&[Capability::NetBindService, Capability::Kill])));
+        assert_eq!(
+            check_capabilities(&capabilities, 0, false),
+            vec![Invalid::new(
+                "process.capabilities.ambient",
+                "CAP_KILL must be in the permitted and inheritable sets as well"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_check_rlimits() {
+        let rlimit = |typ, soft: u64, hard: u64| {
+            PosixRlimitBuilder::default()
+                .typ(typ)
+                .soft(soft)
+                .hard(hard)
+                .build()
+                .unwrap()
+        };
+        let rlimits = vec![
+            rlimit(PosixRlimitType::RlimitMemlock, 1024, 1024),
+            rlimit(PosixRlimitType::RlimitNproc, 100, 10),
+            rlimit(PosixRlimitType::RlimitMemlock, 1, 1),
+        ];
+        assert_eq!(
+            check_rlimits(&rlimits, true),
+            vec![
+                Invalid::new(
+                    "process.rlimits[1]",
+                    "the soft limit 100 of RLIMIT_NPROC is greater than its hard limit 10"
+                ),
+                Invalid::new("process.rlimits[2]", "RLIMIT_MEMLOCK is set more than once"),
+            ]
+        );
+
+        let (_, hard) = getrlimit(Resource::RLIMIT_NOFILE).unwrap();
+        let raised = [rlimit(
+            PosixRlimitType::RlimitNofile,
+            1,
+            hard.saturating_add(1),
+        )];
+        if hard != libc::RLIM_INFINITY {
+            assert_eq!(check_rlimits(&raised, false).len(), 1);
+        }
+        assert!(check_rlimits(&raised, true).is_empty());
+    }
+
+    #[test]
+    fn test_check_net_devices() {
+        let spec = |value: &str, ns: Vec<(LinuxNamespaceType, Option<&str>)>| {
+            SpecBuilder::default()
+                .annotations(HashMap::from([(
+                    net_devices::NET_DEVICES_ANNOTATION.to_owned(),
+                    value.to_owned(),
+                )]))
+                .linux(namespaces(ns).build().unwrap())
+                .build()
+                .unwrap()
+        };
+        let netns = vec![(LinuxNamespaceType::Network, None)];
+        assert!(check_net_devices(&spec("lo:lo0", netns.clone())).is_empty());
+        assert!(check_net_devices(&spec("", vec![])).is_empty());
+
+        assert_eq!(check_net_devices(&spec("lo", vec![])).len(), 1);
+        assert_eq!(
+            check_net_devices(&spec(
+                "lo",
+                vec![(LinuxNamespaceType::Network, Some("/proc/self/ns/net"))]
+            ))
+            .len(),
+            1
+        );
+        assert_eq!(check_net_devices(&spec("lo:a/b", netns.clone())).len(), 1);
+        assert_eq!(
+            check_net_devices(&spec("youki-missing0", netns)),
+            vec![Invalid::new(
+                "annotations.org.youki.net.devices",
+                "network device youki-missing0 doesn't exist"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_check_mounts() {
+        let mount = |destination: &str| {
+            MountBuilder::default()
+                .destination(destination)
+                .typ("tmpfs")
+                .build()
+                .unwrap()
+        };
+        let spec = SpecBuilder::default()
+            .mounts(vec![mount("/tmp"), mount("/run"), mount("/tmp")])
+            .build()
+            .unwrap();
+        let invalid = check_mounts(&spec);
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].field, "mounts[2]");
+    }
+
+    #[test]
+    fn test_check_mount_data() {
+        let mount = |typ: &str, options: &[&str]| {
+            MountBuilder::default()
+                .destination("/mnt")
+                .typ(typ)
+                .options(options.iter().map(|o| o.to_string()).collect::<Vec<_>>())
+                .build()
+                .unwrap()
+        };
+
+        assert_eq!(check_mount_data(&mount("sysfs", &["nosuid", "ro"])), None);
+        assert!(check_mount_data(&mount("mqueue", &["size=1m"])).is_some());
+        assert_eq!(check_mount_data(&mount("proc", &["hidepid=2"])), None);
+        assert!(check_mount_data(&mount("proc", &["mode=755"])).is_some());
+        assert_eq!(
+            check_mount_data(&mount("devpts", &["ptmxmode=0666", "gid=5"])),
+            None
+        );
+        assert!(check_mount_data(&mount("devpts", &["gid=tty"])).is_some());
+        assert!(check_mount_data(&mount("tmpfs", &["mode=rwx"])).is_some());
+        assert_eq!(check_mount_data(&mount("tmpfs", &["huge=always"])), None);
+    }
+
+    #[test]
+    fn test_check_paths() {
+        let spec = SpecBuilder::default()
+            .linux(
+                LinuxBuilder::default()
+                    .masked_paths(vec!["/proc/kcore".to_owned(), "proc/keys".to_owned()])
+                    .readonly_paths(vec!["/proc/../../etc".to_owned()])
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let fields: Vec<_> = check_paths(&spec).into_iter().map(|i| i.field).collect();
+        assert_eq!(
+            fields,
+            vec!["linux.maskedPaths[1]", "linux.readonlyPaths[0]"]
+        );
+    }
+
+    #[test]
+    fn test_check_resources() {
+        let spec = SpecBuilder::default()
+            .linux(
+                LinuxBuilder::default()
+                    .resources(
+                        LinuxResourcesBuilder::default()
+                            .memory(
+                                LinuxMemoryBuilder::default()
+                                    .limit(1024 * 1024)
+                                    .swappiness(10u64)
+                                    .build()
+                                    .unwrap(),
+                            )
+                            .build()
+                            .unwrap(),
+                    )
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        assert!(check_resources(&spec, CgroupSetup::Legacy).is_empty());
+        assert_eq!(
+            check_resources(&spec, CgroupSetup::Unified),
+            vec![Invalid::new(
+                "linux.resources.memory.swappiness",
+                "only supported by cgroup v1, but this host only has cgroup v2"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let spec = SpecBuilder::default()
+            .version("2.0.0")
+            .linux(
+                LinuxBuilder::default()
+                    .masked_paths(vec!["proc/kcore".to_owned()])
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let report = report(&check(&spec));
+        assert!(report.contains("ociVersion: incompatible version 2.0.0"));
+        assert!(report.contains("\nlinux.maskedPaths[0]: proc/kcore is not an absolute path"));
+    }
+
+    #[test]
+    fn test_check_env() {
+        let spec = SpecBuilder::default()
+            .process(
+                ProcessBuilder::default()
+                    .env(
+                        ["PATH=/bin", "FOO", "=bar", "A=b=c"]
+                            .map(String::from)
+                            .to_vec(),
+                    )
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        assert_eq!(
+            check_env(&spec),
+            [
+                Invalid::new("process.env[1]", "missing '='"),
+                Invalid::new("process.env[2]", "empty name"),
+            ]
+        );
+    }
+}
@@ -5,6 +5,7 @@ use nix::unistd;
 use oci_spec::runtime::Spec;
 
 use super::{Executor, ExecutorError, ExecutorValidationError};
+use crate::utils;
 
 #[derive(Clone)]
 pub struct DefaultExecutor {}
@@ -55,15 +56,14 @@ impl Executor for DefaultExecutor {
             ))?;
 
         if let Some(args) = proc.args() {
-            let envs: Vec<String> = proc.env().as_ref().unwrap_or(&vec![]).clone();
-            let path_vars: Vec<&String> = envs.iter().filter(|&e| e.starts_with("PATH=")).collect();
-            if path_vars.is_empty() {
-                tracing::error!("PATH environment variable is not set");
-                Err(ExecutorValidationError::ArgValidationError(
-                    "PATH environment variable is not set".into(),
-                ))?;
-            }
-            let path_var = path_vars[0].trim_start_matches("PATH=");
+            // the last PATH of the environment is the one the process gets
+            let path_var = proc
+                .env()
+                .iter()
+                .flatten()
+                .rev()
+                .find_map(|e| e.strip_prefix("PATH="))
+                .unwrap_or(utils::DEFAULT_PATH);
             match get_executable_path(&args[0], path_var) {
                 None => {
                     tracing::error!(
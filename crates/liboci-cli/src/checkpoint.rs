@@ -33,7 +33,8 @@ pub struct Checkpoint {
     /// Pass a file descriptor fd to criu
     #[clap(long)]
     pub status_fd: Option<u32>, // TODO: Is u32 the right type?
-    /// Start a page server at the given URL
+    /// Send the memory pages to the page server at ADDRESS:PORT, or with lazy
+    /// pages listen there for the restoring host
     #[clap(long)]
     pub page_server: Option<String>,
     /// Allow file locks
@@ -10,14 +10,14 @@ pub struct Create {
     /// Path to the bundle directory, containing config.json and root filesystem
     #[clap(short, long, default_value = ".")]
     pub bundle: PathBuf,
-    /// Unix socket (file) path , which will receive file descriptor of the writing end of the pseudoterminal
+    /// Unix socket (file) path, @name of an abstract unix socket or vsock://<cid>:<port>, which will receive file descriptor of the writing end of the pseudoterminal
     #[clap(short, long)]
     pub console_socket: Option<PathBuf>,
     /// File to write pid of the container created
     // note that in the end, container is just another process
     #[clap(short, long)]
     pub pid_file: Option<PathBuf>,
-    /// Do not use pivot rool to jail process inside rootfs
+    /// Do not use pivot root to jail process inside rootfs
     #[clap(long)]
     pub no_pivot: bool,
     /// Do not create a new session keyring for the container.
@@ -26,6 +26,9 @@ pub struct Create {
     /// Pass N additional file descriptors to the container (stdio + $LISTEN_FDS + N in total)
     #[clap(long, default_value = "0")]
     pub preserve_fds: i32,
+    /// Remove the parts of the spec which are not supported for rootless containers instead of failing
+    #[clap(long)]
+    pub ignore_unsupported: bool,
 
     /// Name of the container instance to be started
     #[clap(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
@@ -4,7 +4,7 @@ use clap::Parser;
 #[derive(Parser, Debug)]
 pub struct Events {
     /// Sets the stats collection interval in seconds (default: 5s)
-    #[clap(long, default_value = "5")]
+    #[clap(long, default_value = "5", value_parser = clap::value_parser!(u32).range(1..))]
     pub interval: u32,
     /// Display the container stats only once
     #[clap(long)]
@@ -7,7 +7,7 @@ use clap::Parser;
 /// Reference: https://github.com/opencontainers/runc/blob/main/man/runc-exec.8.md
 #[derive(Parser, Debug)]
 pub struct Exec {
-    /// Unix socket (file) path , which will receive file descriptor of the writing end of the pseudoterminal
+    /// Unix socket (file) path, @name of an abstract unix socket or vsock://<cid>:<port>, which will receive file descriptor of the writing end of the pseudoterminal
     #[clap(long)]
     pub console_socket: Option<PathBuf>,
     #[clap(long)]
@@ -16,11 +16,14 @@ pub struct Exec {
     /// Environment variables that should be set in the container
     #[clap(short, long, value_parser = parse_env::<String, String>, number_of_values = 1)]
     pub env: Vec<(String, String)>,
+    /// Allocate a pseudo terminal for the process. Unless a console socket
+    /// is given, it is attached to the terminal of the caller
     #[clap(short, long)]
     pub tty: bool,
-    /// Run the command as a user
-    #[clap(short, long, value_parser = parse_user::<u32, u32>)]
-    pub user: Option<(u32, Option<u32>)>,
+    /// Run the command as a user, given as user[:group] with ids or names
+    /// from /etc/passwd and /etc/group of the container
+    #[clap(short, long)]
+    pub user: Option<String>,
     /// Add additional group IDs. Can be specified multiple times
     #[clap(long, short = 'g', number_of_values = 1)]
     pub additional_gids: Vec<u32>,
@@ -54,6 +57,17 @@ pub struct Exec {
     /// Execute a process in a sub-cgroup
     #[clap(long)]
     pub cgroup: Option<String>,
+    /// Record the process under an id, which `kill --exec` signals it by
+    #[clap(long)]
+    pub exec_id: Option<String>,
+    /// Accepted for compatibility with create and run, the process always
+    /// joins the root of the existing container
+    #[clap(long, hide = true)]
+    pub no_pivot: bool,
+    /// Accepted for compatibility with create and run, the process always
+    /// joins the session keyring of the existing container
+    #[clap(long, hide = true)]
+    pub no_new_keyring: bool,
 
     /// Identifier of the container
     #[clap(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
@@ -76,17 +90,3 @@ where
         .ok_or_else(|| format!("invalid VAR=value: no `=` found in `{s}`"))?;
     Ok((s[..pos].parse()?, s[pos + 1..].parse()?))
 }
-
-fn parse_user<T, U>(s: &str) -> Result<(T, Option<U>), Box<dyn Error + Send + Sync + 'static>>
-where
-    T: std::str::FromStr,
-    T::Err: Error + Send + Sync + 'static,
-    U: std::str::FromStr,
-    U::Err: Error + Send + Sync + 'static,
-{
-    if let Some(pos) = s.find(':') {
-        Ok((s[..pos].parse()?, Some(s[pos + 1..].parse()?)))
-    } else {
-        Ok((s.parse()?, None))
-    }
-}
@@ -5,7 +5,13 @@ use clap::Parser;
 pub struct Kill {
     #[clap(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
     pub container_id: String,
+    /// Name like SIGKILL or KILL, or number of the signal
+    #[clap(default_value = "SIGTERM")]
     pub signal: String,
+    /// Send the signal to all processes of the container
     #[clap(short, long)]
     pub all: bool,
+    /// Send the signal to the process executed with this exec id only
+    #[clap(long, conflicts_with = "all")]
+    pub exec: Option<String>,
 }
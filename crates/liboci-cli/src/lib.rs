@@ -25,6 +25,7 @@ mod features;
 mod list;
 mod pause;
 mod ps;
+mod restore;
 mod resume;
 mod run;
 mod spec;
@@ -37,6 +38,7 @@ pub use features::Features;
 pub use list::List;
 pub use pause::Pause;
 pub use ps::Ps;
+pub use restore::Restore;
 pub use resume::Resume;
 pub use run::Run;
 pub use spec::Spec;
@@ -61,7 +63,7 @@ pub enum StandardCmd {
 // and other runtimes.
 #[derive(Parser, Debug)]
 pub enum CommonCmd {
-    Checkpointt(Checkpoint),
+    Checkpoint(Checkpoint),
     Events(Events),
     Exec(Exec),
     Features(Features),
@@ -69,6 +71,7 @@ pub enum CommonCmd {
     Pause(Pause),
     #[clap(allow_hyphen_values = true)]
     Ps(Ps),
+    Restore(Restore),
     Resume(Resume),
     Run(Run),
     Update(Update),
@@ -85,7 +88,7 @@ pub struct GlobalOpts {
     /// change log level to debug, but the `log-level` flag takes precedence
     #[clap(long)]
     pub debug: bool,
-    /// set the log format ('text' (default), or 'json') (default: "text")
+    /// set the log format ('text' (default), 'json', or 'journald') (default: "text")
     #[clap(long)]
     pub log_format: Option<String>,
     /// root directory to store container state
@@ -94,4 +97,8 @@ pub struct GlobalOpts {
     /// Enable systemd cgroup manager, rather then use the cgroupfs directly.
     #[clap(short, long)]
     pub systemd_cgroup: bool,
+    /// cgroup manager to use, where 'auto' uses systemd if it is available;
+    /// takes precedence over --systemd-cgroup
+    #[clap(long, value_parser = ["cgroupfs", "systemd", "auto"])]
+    pub cgroup_manager: Option<String>,
 }
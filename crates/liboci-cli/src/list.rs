@@ -3,11 +3,16 @@ use clap::Parser;
 /// List created containers
 #[derive(Parser, Debug)]
 pub struct List {
-    /// Specify the format (default or table)
-    #[clap(long, default_value = "table")]
+    /// Specify the format (table or json)
+    #[clap(long, short, default_value = "table")]
     pub format: String,
 
     /// Only display container IDs
     #[clap(long, short)]
     pub quiet: bool,
+
+    /// Only display containers with one of the given statuses, e.g.
+    /// running,paused
+    #[clap(long, value_delimiter = ',')]
+    pub status: Vec<String>,
 }
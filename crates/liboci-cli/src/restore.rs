@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+/// Restore a container from a previous checkpoint
+/// Reference: https://github.com/opencontainers/runc/blob/main/man/runc-restore.8.md
+#[derive(Parser, Debug)]
+pub struct Restore {
+    /// Path to the bundle directory, containing config.json and root filesystem
+    #[clap(short, long, default_value = ".")]
+    pub bundle: PathBuf,
+    /// Unix socket (file) path, @name of an abstract unix socket or vsock://<cid>:<port>, which will receive file descriptor of the writing end of the pseudoterminal
+    #[clap(long)]
+    pub console_socket: Option<PathBuf>,
+    /// Path to criu image files for restoring
+    #[clap(long, default_value = "checkpoint")]
+    pub image_path: PathBuf,
+    /// Path for saving work files and logs
+    #[clap(long)]
+    pub work_path: Option<PathBuf>,
+    /// Allow open tcp connections
+    #[clap(long)]
+    pub tcp_established: bool,
+    /// Allow external unix sockets
+    #[clap(long)]
+    pub ext_unix_sk: bool,
+    /// Allow shell jobs
+    #[clap(long)]
+    pub shell_job: bool,
+    /// Allow file locks
+    #[clap(long)]
+    pub file_locks: bool,
+    /// Use userfaultfd to lazily restore memory pages
+    #[clap(long)]
+    pub lazy_pages: bool,
+    /// Mode in which CRIU manages the cgroups: soft, full, strict or ignore
+    #[clap(long)]
+    pub manage_cgroups_mode: Option<String>,
+    /// Detach from the container's process
+    #[clap(short, long)]
+    pub detach: bool,
+    /// File to write pid of the restored container process
+    #[clap(long)]
+    pub pid_file: Option<PathBuf>,
+    /// Disable the use of the subreaper used to reap reparented processes
+    #[clap(long)]
+    pub no_subreaper: bool,
+    /// Do not use pivot root to jail process inside rootfs
+    #[clap(long)]
+    pub no_pivot: bool,
+    /// Create a namespace, but don't restore its properties
+    #[clap(long)]
+    pub empty_ns: bool,
+    /// Enable auto-deduplication
+    #[clap(long)]
+    pub auto_dedup: bool,
+
+    #[clap(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
+    pub container_id: String,
+}
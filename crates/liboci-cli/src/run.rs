@@ -8,7 +8,7 @@ pub struct Run {
     /// Path to the bundle directory, containing config.json and root filesystem
     #[clap(short, long, default_value = ".")]
     pub bundle: PathBuf,
-    /// Unix socket (file) path , which will receive file descriptor of the writing end of the pseudoterminal
+    /// Unix socket (file) path, @name of an abstract unix socket or vsock://<cid>:<port>, which will receive file descriptor of the writing end of the pseudoterminal
     #[clap(short, long)]
     pub console_socket: Option<PathBuf>,
     /// File to write pid of the container created
@@ -27,7 +27,10 @@ pub struct Run {
     /// Pass N additional file descriptors to the container (stdio + $LISTEN_FDS + N in total)
     #[clap(long, default_value = "0")]
     pub preserve_fds: i32,
-    // Keep container's state directory and cgroup
+    /// Remove the parts of the spec which are not supported for rootless containers instead of failing
+    #[clap(long)]
+    pub ignore_unsupported: bool,
+    /// Keep the state, cgroup and mount namespace of the container after it exits, until it is deleted
     #[clap(long)]
     pub keep: bool,
     /// name of the container instance to be started
@@ -12,4 +12,19 @@ pub struct Spec {
     /// Generate a configuration for a rootless container
     #[clap(long)]
     pub rootless: bool,
+
+    /// Take the process, volumes and annotations from an OCI image
+    /// configuration, i.e. the config blob of an image
+    #[clap(long)]
+    pub from_image: Option<PathBuf>,
+
+    /// Restrict the container to this number of online cpus, the lowest ones
+    /// of the memory node if one is given
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub cpus: Option<u16>,
+
+    /// Restrict the container to the memory and the online cpus of this
+    /// NUMA node
+    #[clap(long)]
+    pub numa_node: Option<u16>,
 }
@@ -3,6 +3,13 @@ use clap::Parser;
 /// Show the container state
 #[derive(Parser, Debug)]
 pub struct State {
+    /// Remove the state of the container if it has been orphaned by a crash during creation
+    #[clap(long)]
+    pub stale_check: bool,
+    /// Specify the format (json or extended, which adds the name of the owner,
+    /// the rootfs and where the cgroup of the container is)
+    #[clap(long, short, default_value = "json")]
+    pub format: String,
     #[clap(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
     pub container_id: String,
 }
@@ -51,11 +51,16 @@ pub struct Update {
     pub memory_reservation: Option<u64>,
 
     /// Set total memory + swap usage to num bytes. Use -1 to unset the limit (i.e. use unlimited swap).
-    #[clap(long)]
+    #[clap(long, allow_negative_numbers = true)]
     pub memory_swap: Option<i64>,
 
-    /// Set the maximum number of processes allowed in the container
+    /// Make the kernel reclaim num bytes of the memory of the container, e.g. to
+    /// shrink an idle container. Requires cgroup v2 and Linux 5.19 or newer.
     #[clap(long)]
+    pub memory_reclaim: Option<u64>,
+
+    /// Set the maximum number of processes allowed in the container
+    #[clap(long, allow_negative_numbers = true)]
     pub pids_limit: Option<i64>,
 
     /// Set the value for Intel RDT/CAT L3 cache schema.
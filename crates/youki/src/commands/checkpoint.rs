@@ -9,11 +9,19 @@ use crate::commands::load_container;
 pub fn checkpoint(args: Checkpoint, root_path: PathBuf) -> Result<()> {
     tracing::debug!("start checkpointing container {}", args.container_id);
     let mut container = load_container(root_path, &args.container_id)?;
+    let page_server = args
+        .page_server
+        .as_deref()
+        .map(str::parse)
+        .transpose()
+        .context("invalid --page-server")?;
     let opts = libcontainer::container::CheckpointOptions {
         ext_unix_sk: args.ext_unix_sk,
         file_locks: args.file_locks,
         image_path: args.image_path,
+        lazy_pages: args.lazy_pages,
         leave_running: args.leave_running,
+        page_server,
         shell_job: args.shell_job,
         tcp_established: args.tcp_established,
         work_path: args.work_path,
@@ -1,8 +1,11 @@
 //! Handles the creation of a new container
+use std::env;
 use std::path::PathBuf;
 
 use anyhow::Result;
+use libcgroups::common::CgroupManagerType;
 use libcontainer::container::builder::ContainerBuilder;
+use libcontainer::notify_proxy::NOTIFY_SOCKET_ENV;
 use libcontainer::syscall::syscall::SyscallType;
 use liboci_cli::Create;
 
@@ -13,7 +16,7 @@ use crate::workload::executor::default_executor;
 // can be given impression that is is running on a complete system, but on the system which
 // it is running, it is just another process, and has attributes such as pid, file descriptors, etc.
 // associated with it like any other process.
-pub fn create(args: Create, root_path: PathBuf, systemd_cgroup: bool) -> Result<()> {
+pub fn create(args: Create, root_path: PathBuf, cgroup_manager: CgroupManagerType) -> Result<()> {
     ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
         .with_executor(default_executor())
         .with_pid_file(args.pid_file.as_ref())?
@@ -22,8 +25,12 @@ pub fn create(args: Create, root_path: PathBuf, systemd_cgroup: bool) -> Result<
         .with_preserved_fds(args.preserve_fds)
         .validate_id()?
         .as_init(&args.bundle)
-        .with_systemd(systemd_cgroup)
+        .with_cgroup_manager(cgroup_manager)
+        .with_no_pivot(args.no_pivot)
+        .with_no_new_keyring(args.no_new_keyring)
+        .with_ignore_unsupported(args.ignore_unsupported)
         .with_detach(true)
+        .with_notify_socket(env::var_os(NOTIFY_SOCKET_ENV))
         .build()?;
 
     Ok(())
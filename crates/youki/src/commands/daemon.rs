@@ -0,0 +1,382 @@
+//! Contains functionality of the daemon command, which serves operations on
+//! containers as JSON over a unix socket, so that callers running many of
+//! them don't pay for starting youki and loading its state for each one
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use libcgroups::common::CgroupManagerType;
+use libcontainer::container::builder::ContainerBuilder;
+use libcontainer::container::{Container, ExitInfo, State};
+use libcontainer::error::ErrorKind;
+use libcontainer::signal::Signal;
+use libcontainer::syscall::syscall::SyscallType;
+use nix::errno::Errno;
+use nix::sys::stat::{umask, Mode};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::commands::{container_exists, load_container};
+use crate::workload::executor::default_executor;
+
+// a client must not be able to block the daemon by keeping its connection
+// open without sending requests
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serve create, start, kill, delete, state and events requests over a unix
+/// socket until interrupted. Every line a client sends is a JSON request
+/// like `{"op": "kill", "id": "c1", "signal": "SIGKILL"}`, which is answered
+/// by a line with a JSON object whose `ok` tells whether it succeeded. A
+/// failure is described by `error` and classified by `kind`.
+///
+/// The processes of containers created without a terminal share the stdio of
+/// the daemon. The daemon is the parent of the init processes of the
+/// containers it creates, and records their exit in the state of the
+/// container once it has reaped them.
+#[derive(Parser, Debug)]
+pub struct Daemon {
+    /// Path of the unix socket to listen on, only the user running the daemon
+    /// can connect to it
+    #[clap(long)]
+    pub socket: PathBuf,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+enum Request {
+    /// Paths have to be absolute, as they would be relative to the working
+    /// directory of the daemon otherwise
+    Create {
+        id: String,
+        bundle: PathBuf,
+        pid_file: Option<PathBuf>,
+        console_socket: Option<PathBuf>,
+    },
+    Start {
+        id: String,
+    },
+    Kill {
+        id: String,
+        #[serde(default = "default_signal")]
+        signal: String,
+        #[serde(default)]
+        all: bool,
+    },
+    Delete {
+        id: String,
+        #[serde(default)]
+        force: bool,
+    },
+    /// Answered with the state of the container as `state`
+    State {
+        id: String,
+    },
+    /// Answered with the stats event of the container as `event`, the events
+    /// command has to be used to watch for further events
+    Events {
+        id: String,
+    },
+}
+
+fn default_signal() -> String {
+    "SIGTERM".to_owned()
+}
+
+pub fn daemon(args: Daemon, root_path: PathBuf, cgroup_manager: CgroupManagerType) -> Result<()> {
+    let root_path = fs::canonicalize(root_path)?;
+    let listener = bind(&args.socket)?;
+    tracing::info!(socket = ?args.socket, "serving container operations");
+
+    // the requests are handled one after another, as creating a container
+    // clones processes, which is only safe in a single threaded process
+    for stream in listener.incoming() {
+        reap_children(&root_path);
+        let result = stream.map_err(anyhow::Error::from).and_then(|stream| {
+            serve(&stream, &root_path, cgroup_manager, || {
+                reap_children(&root_path)
+            })
+        });
+        if let Err(err) = result {
+            tracing::warn!("failed to serve client: {:?}", err);
+        }
+        reap_children(&root_path);
+    }
+
+    Ok(())
+}
+
+fn bind(socket: &Path) -> Result<UnixListener> {
+    // the socket of a daemon which hasn't exited cleanly is left behind
+    match fs::symlink_metadata(socket) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if UnixStream::connect(socket).is_ok() {
+                bail!("another daemon is listening on {socket:?}");
+            }
+            fs::remove_file(socket)
+                .with_context(|| format!("failed to remove stale socket {socket:?}"))?;
+        }
+        Ok(_) => bail!("{socket:?} exists and is no socket"),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err).with_context(|| format!("failed to inspect {socket:?}")),
+    }
+
+    // anyone who can connect can use the privileges of the daemon
+    let umask_orig = umask(Mode::S_IRWXG | Mode::S_IRWXO | Mode::S_IXUSR);
+    let listener = UnixListener::bind(socket);
+    umask(umask_orig);
+    listener.with_context(|| format!("failed to listen on {socket:?}"))
+}
+
+/// Answers the requests of a client until it closes the connection or stays
+/// idle for too long, calling `answered` after each response
+fn serve(
+    stream: &UnixStream,
+    root_path: &Path,
+    cgroup_manager: CgroupManagerType,
+    mut answered: impl FnMut(),
+) -> Result<()> {
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                tracing::debug!("closing connection of idle client");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                tracing::debug!(?request, "handling request");
+                response(handle(request, root_path, cgroup_manager))
+            }
+            Err(err) => json!({
+                "ok": false,
+                "error": format!("invalid request: {err}"),
+                "kind": ErrorKind::InvalidInput.as_str(),
+            }),
+        };
+        let mut response = serde_json::to_string(&response)?;
+        response.push('\n');
+        let mut stream = stream;
+        stream.write_all(response.as_bytes())?;
+        answered();
+    }
+
+    Ok(())
+}
+
+/// Reaps the children of the daemon which have exited, so that the init
+/// processes of stopped containers don't stay zombies as long as the daemon
+/// runs. The requests are handled on this thread, so no process a request
+/// waits for is reaped here. Any child of the process is reaped, so this must
+/// not be called where other threads wait for children, e.g. in unit tests.
+fn reap_children(root_path: &Path) {
+    loop {
+        let status = match waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) | Err(Errno::ECHILD) => return,
+            Ok(status) => status,
+            Err(Errno::EINTR) => continue,
+            Err(err) => {
+                tracing::warn!("failed to reap child processes: {}", err);
+                return;
+            }
+        };
+        if let (Some(pid), Some(exit)) = (status.pid(), ExitInfo::from_wait_status(status)) {
+            tracing::debug!(?pid, status = exit.status(), "reaped child process");
+            if let Err(err) = record_exit(root_path, pid, exit) {
+                tracing::warn!(?pid, "failed to record exit of container: {:?}", err);
+            }
+        }
+    }
+}
+
+/// Records the exit of the container whose init process has the pid, if any.
+/// Other children are e.g. processes of hooks which have outlived them.
+fn record_exit(root_path: &Path, pid: Pid, mut exit: ExitInfo) -> Result<()> {
+    for container_dir in fs::read_dir(root_path)? {
+        let container_dir = container_dir?.path();
+        if !State::file_path(&container_dir).exists() {
+            continue;
+        }
+
+        let mut container = match Container::load(container_dir) {
+            Ok(container) if container.pid() == Some(pid) => container,
+            Ok(_) => continue,
+            Err(err) => {
+                tracing::debug!("skipping container: {}", err);
+                continue;
+            }
+        };
+        exit.oom_killed = container.update_oom_killed().unwrap_or_else(|err| {
+            tracing::warn!("failed to check for out of memory kills: {}", err);
+            false
+        });
+        container.record_exit(&exit)?;
+        return Ok(());
+    }
+
+    Ok(())
+}
+
+fn response(result: Result<Value>) -> Value {
+    match result {
+        Ok(mut data) => {
+            data["ok"] = Value::Bool(true);
+            data
+        }
+        Err(err) => json!({
+            "ok": false,
+            "error": format!("{err:#}"),
+            "kind": crate::error_kind(&err).as_str(),
+        }),
+    }
+}
+
+fn handle(request: Request, root_path: &Path, cgroup_manager: CgroupManagerType) -> Result<Value> {
+    match request {
+        Request::Create {
+            id,
+            bundle,
+            pid_file,
+            console_socket,
+        } => {
+            for path in [Some(&bundle), pid_file.as_ref(), console_socket.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                if !path.is_absolute() {
+                    bail!("{path:?} is not an absolute path");
+                }
+            }
+            ContainerBuilder::new(id.clone(), SyscallType::default())
+                .with_executor(default_executor())
+                .with_pid_file(pid_file.as_ref())?
+                .with_console_socket(console_socket.as_ref())
+                .with_root_path(root_path)?
+                .validate_id()?
+                .as_init(&bundle)
+                .with_cgroup_manager(cgroup_manager)
+                .with_detach(true)
+                .build()
+                .with_context(|| format!("failed to create container {id}"))?;
+        }
+        Request::Start { id } => {
+            load_container(root_path, &id)?
+                .start()
+                .with_context(|| format!("failed to start container {id}"))?;
+        }
+        Request::Kill { id, signal, all } => {
+            let signal: Signal = signal.as_str().try_into()?;
+            load_container(root_path, &id)?
+                .kill(signal, all)
+                .with_context(|| format!("failed to kill container {id}"))?;
+        }
+        Request::Delete { id, force } => {
+            if force && !container_exists(root_path, &id)? {
+                return Ok(json!({}));
+            }
+            load_container(root_path, &id)?
+                .delete(force)
+                .with_context(|| format!("failed to delete container {id}"))?;
+        }
+        Request::State { id } => {
+            let mut container = load_container(root_path, &id)?;
+            if let Err(err) = container.update_oom_killed() {
+                tracing::debug!("failed to check for out of memory kills: {}", err);
+            }
+            return Ok(json!({ "state": container.state }));
+        }
+        Request::Events { id } => {
+            let mut event = Value::Null;
+            load_container(root_path, &id)?.watch_events(0, true, |stats| {
+                event = serde_json::to_value(stats)
+                    .map_err(libcontainer::error::LibcontainerError::OtherSerialization)?;
+                Ok(())
+            })?;
+            return Ok(json!({ "event": event }));
+        }
+    }
+
+    Ok(json!({}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let request: Request = serde_json::from_str(r#"{"op": "kill", "id": "c1"}"#).unwrap();
+        assert_eq!(
+            request,
+            Request::Kill {
+                id: "c1".to_owned(),
+                signal: "SIGTERM".to_owned(),
+                all: false
+            }
+        );
+        let request: Request =
+            serde_json::from_str(r#"{"op": "create", "id": "c1", "bundle": "/bundle"}"#).unwrap();
+        assert_eq!(
+            request,
+            Request::Create {
+                id: "c1".to_owned(),
+                bundle: PathBuf::from("/bundle"),
+                pid_file: None,
+                console_socket: None,
+            }
+        );
+        assert!(serde_json::from_str::<Request>(r#"{"op": "pause", "id": "c1"}"#).is_err());
+        assert!(serde_json::from_str::<Request>(r#"{"op": "state", "idd": "c1"}"#).is_err());
+    }
+
+    #[test]
+    fn test_serve() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let (client, server) = UnixStream::pair()?;
+        (&client).write_all(
+            b"{\"op\": \"state\", \"id\": \"missing\"}\n\n\
+              {\"op\": \"create\", \"id\": \"c1\", \"bundle\": \"bundle\"}\n\
+              not json\n",
+        )?;
+        client.shutdown(std::net::Shutdown::Write)?;
+        let mut answered = 0;
+        serve(&server, root.path(), CgroupManagerType::Cgroupfs, || {
+            answered += 1
+        })?;
+        drop(server);
+
+        let responses: Vec<Value> = BufReader::new(&client)
+            .lines()
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect::<Result<_>>()?;
+        assert_eq!(responses.len(), 3);
+        assert_eq!(answered, 3);
+        assert_eq!(responses[0]["ok"], false);
+        assert_eq!(responses[0]["kind"], "container_not_found");
+        assert!(responses[1]["error"]
+            .as_str()
+            .unwrap()
+            .contains("is not an absolute path"));
+        assert_eq!(responses[2]["kind"], "invalid_input");
+        Ok(())
+    }
+}
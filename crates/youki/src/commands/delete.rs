@@ -1,18 +1,26 @@
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
+use libcontainer::container::collect_garbage;
 use liboci_cli::Delete;
 
 use crate::commands::{container_exists, load_container};
 
 pub fn delete(args: Delete, root_path: PathBuf) -> Result<()> {
     tracing::debug!("start deleting {}", args.container_id);
-    if !container_exists(&root_path, &args.container_id)? && args.force {
-        return Ok(());
-    }
+    let result = if !container_exists(&root_path, &args.container_id)? && args.force {
+        Ok(())
+    } else {
+        let mut container = load_container(&root_path, &args.container_id)?;
+        container
+            .delete(args.force)
+            .with_context(|| format!("failed to delete container {}", args.container_id))
+    };
 
-    let mut container = load_container(root_path, &args.container_id)?;
-    container
-        .delete(args.force)
-        .with_context(|| format!("failed to delete container {}", args.container_id))
+    // the cgroups of containers whose state has gone missing are removed
+    // along the way, as nothing else would remove them
+    if let Err(err) = collect_garbage(&root_path) {
+        tracing::warn!("failed to remove the cgroups of containers without state: {err}");
+    }
+    result
 }
@@ -1,26 +1,70 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::os::unix::net::UnixListener;
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use libcontainer::container::builder::ContainerBuilder;
+use libcontainer::container::ExitInfo;
+use libcontainer::pidfd::PidFd;
 use libcontainer::syscall::syscall::SyscallType;
+use libcontainer::tty;
 use liboci_cli::Exec;
-use nix::sys::wait::{waitpid, WaitStatus};
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::sys::signal::Signal;
+use nix::sys::signalfd::{SfdFlags, SigSet, SignalFd};
+use nix::sys::termios::{self, SetArg};
+use nix::sys::wait::{waitpid, WaitPidFlag};
+use nix::unistd::{self, Pid};
 
 use crate::workload::executor::default_executor;
 
 pub fn exec(args: Exec, root_path: PathBuf) -> Result<i32> {
+    // the same restrictions as runc, as the console socket is the only way
+    // to get hold of the pseudo terminal of a detached process
+    if args.detach && args.tty && args.console_socket.is_none() {
+        bail!("cannot allocate tty if youki will detach without setting console socket");
+    }
+    if !args.tty && args.console_socket.is_some() {
+        bail!("cannot use console socket if youki will not allocate tty");
+    }
+
+    // Without a console socket, a foreground process with a terminal is
+    // attached to the terminal youki is running in.
+    let attach_socket = if args.tty && args.console_socket.is_none() && !args.detach {
+        let path = root_path
+            .join(&args.container_id)
+            .join(format!("exec-console-{}", std::process::id()));
+        Some(AttachSocket::bind(path)?)
+    } else {
+        None
+    };
+    let console_socket = attach_socket
+        .as_ref()
+        .map(|socket| &socket.path)
+        .or(args.console_socket.as_ref());
+
     let pid = ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
         .with_executor(default_executor())
         .with_root_path(root_path)?
-        .with_console_socket(args.console_socket.as_ref())
+        .with_console_socket(console_socket)
         .with_pid_file(args.pid_file.as_ref())?
+        .with_preserved_fds(args.preserve_fds)
         .validate_id()?
         .as_tenant()
         .with_detach(args.detach)
+        .with_exec_id(args.exec_id.clone())
         .with_cwd(args.cwd.as_ref())
         .with_env(args.env.clone().into_iter().collect())
         .with_process(args.process.as_ref())
         .with_no_new_privs(args.no_new_privs)
+        .with_capabilities(args.cap.clone())
+        .with_apparmor_profile(args.apparmor.clone())
+        .with_process_label(args.process_label.clone())
+        .with_user(args.user.clone())
+        .with_additional_gids(args.additional_gids.clone())
         .with_container_args(args.command.clone())
         .build()?;
 
@@ -32,9 +76,182 @@ pub fn exec(args: Exec, root_path: PathBuf) -> Result<i32> {
         return Ok(0);
     }
 
-    match waitpid(pid, None)? {
-        WaitStatus::Exited(_, status) => Ok(status),
-        WaitStatus::Signaled(_, sig, _) => Ok(sig as i32),
-        _ => Ok(0),
+    if let Some(socket) = attach_socket {
+        let pty_master = socket.receive_pty_master()?;
+        return attach(pid, pty_master);
+    }
+
+    wait_for_exit(pid)
+}
+
+// Waits until the process has exited, a process killed by a signal exits with
+// 128 plus the signal like in a shell.
+fn wait_for_exit(pid: Pid) -> Result<i32> {
+    loop {
+        match waitpid(pid, None) {
+            Ok(status) => {
+                if let Some(exit) = ExitInfo::from_wait_status(status) {
+                    return Ok(exit.status());
+                }
+            }
+            Err(Errno::EINTR) => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Console socket on which the pseudo terminal of an attached process is
+/// received. The socket file is removed once it is no longer needed.
+struct AttachSocket {
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+impl AttachSocket {
+    fn bind(path: PathBuf) -> Result<Self> {
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("failed to create console socket {path:?}"))?;
+        Ok(Self { path, listener })
+    }
+
+    fn receive_pty_master(self) -> Result<OwnedFd> {
+        let (stream, _) = self
+            .listener
+            .accept()
+            .context("failed to accept console socket connection")?;
+        Ok(tty::receive_pty_master(stream.as_raw_fd())?)
+    }
+}
+
+impl Drop for AttachSocket {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            tracing::warn!(?err, path = ?self.path, "failed to remove console socket");
+        }
+    }
+}
+
+// attach forwards the io between the terminal of youki and the pseudo
+// terminal of the process until the process exits, and keeps the size of the
+// pseudo terminal in sync with the terminal of youki.
+fn attach(pid: Pid, pty_master: OwnedFd) -> Result<i32> {
+    let stdin = io::stdin();
+    // The terminal is switched to raw mode, so that input such as ctrl-c
+    // reaches the process instead of being interpreted here.
+    let orig_termios = termios::tcgetattr(&stdin).ok();
+    if let Some(orig_termios) = &orig_termios {
+        let mut raw = orig_termios.clone();
+        termios::cfmakeraw(&mut raw);
+        termios::tcsetattr(&stdin, SetArg::TCSANOW, &raw)
+            .context("failed to set terminal to raw mode")?;
+        if let Err(err) = tty::resize_pty(stdin.as_raw_fd(), pty_master.as_raw_fd()) {
+            tracing::warn!(?err, "failed to set the initial terminal size");
+        }
+    }
+
+    let result = forward(pid, pty_master, orig_termios.is_some());
+
+    if let Some(orig_termios) = orig_termios {
+        if let Err(err) = termios::tcsetattr(&stdin, SetArg::TCSANOW, &orig_termios) {
+            tracing::warn!(?err, "failed to restore terminal");
+        }
+    }
+
+    result
+}
+
+// forward copies the io and waits for the exit of the process in a single
+// poll loop, which is woken up by input, by output of the process, by the
+// resizing of the terminal and by the pidfd of the process once it exits.
+fn forward(pid: Pid, pty_master: OwnedFd, is_terminal: bool) -> Result<i32> {
+    let mut signals = SigSet::empty();
+    signals.add(Signal::SIGWINCH);
+    signals.add(Signal::SIGCHLD);
+    signals
+        .thread_block()
+        .context("failed to call pthread_sigmask")?;
+    let mut signal_fd = SignalFd::with_flags(&signals, SfdFlags::SFD_CLOEXEC)
+        .context("failed to create signalfd")?;
+    // without pidfds, which require Linux 5.3, the exit of the process is
+    // only noticed through SIGCHLD
+    let process = PidFd::open(pid).context("failed to open pidfd of the process")?;
+
+    let pty = File::from(pty_master);
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut stdin_open = true;
+    let mut pty_open = true;
+    let mut buf = [0; 8192];
+
+    let status = loop {
+        // The process may have exited before the signals were blocked, so
+        // its status is checked before waiting for the next event.
+        if let Some(exit) = ExitInfo::from_wait_status(waitpid(pid, Some(WaitPidFlag::WNOHANG))?) {
+            break exit.status();
+        }
+
+        let mut fds = vec![PollFd::new(signal_fd.as_fd(), PollFlags::POLLIN)];
+        if let Some(process) = &process {
+            fds.push(PollFd::new(process.as_fd(), PollFlags::POLLIN));
+        }
+        // the pty is still polled once stdin is closed, but not the other way
+        // round, as the pty can't be read anymore once the process closed it
+        let pty_index = fds.len();
+        if pty_open {
+            fds.push(PollFd::new(pty.as_fd(), PollFlags::POLLIN));
+        }
+        let stdin_index = fds.len();
+        if stdin_open {
+            fds.push(PollFd::new(stdin.as_fd(), PollFlags::POLLIN));
+        }
+        match poll(&mut fds, PollTimeout::NONE) {
+            Ok(_) => {}
+            Err(Errno::EINTR) => continue,
+            Err(err) => return Err(err).context("failed to poll"),
+        }
+        let ready = |index: usize| fds.get(index).and_then(|fd| fd.any()).unwrap_or_default();
+        let (signal_ready, pty_ready, stdin_ready) = (
+            ready(0),
+            pty_open && ready(pty_index),
+            stdin_open && ready(stdin_index),
+        );
+        drop(fds);
+
+        if signal_ready
+            && signal_fd.read_signal()?.map(|info| info.ssi_signo) == Some(Signal::SIGWINCH as u32)
+            && is_terminal
+        {
+            if let Err(err) = tty::resize_pty(stdin.as_raw_fd(), pty.as_raw_fd()) {
+                tracing::warn!(?err, "failed to resize terminal");
+            }
+        }
+        if pty_ready {
+            // reading fails with EIO once the process closed the pseudo terminal
+            pty_open = copy_chunk(&pty, &mut stdout, &mut buf);
+        }
+        if stdin_ready {
+            let mut input = &pty;
+            stdin_open = copy_chunk(&stdin, &mut input, &mut buf);
+        }
+    };
+
+    // the output which the process has written before exiting
+    while pty_open {
+        let mut fds = [PollFd::new(pty.as_fd(), PollFlags::POLLIN)];
+        if !matches!(poll(&mut fds, PollTimeout::ZERO), Ok(ready) if ready > 0) {
+            break;
+        }
+        pty_open = copy_chunk(&pty, &mut stdout, &mut buf);
+    }
+
+    Ok(status)
+}
+
+// Copies what can be read from the file descriptor at once, and returns
+// false once it has been closed
+fn copy_chunk(from: &impl AsFd, to: &mut impl Write, buf: &mut [u8]) -> bool {
+    match unistd::read(from.as_fd().as_raw_fd(), buf) {
+        Ok(0) | Err(_) => false,
+        Ok(n) => to.write_all(&buf[..n]).and_then(|_| to.flush()).is_ok(),
     }
 }
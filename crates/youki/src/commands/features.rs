@@ -1,8 +1,219 @@
 //! Contains Functionality of `features` container command
 use anyhow::Result;
+use libcontainer::features;
 use liboci_cli::Features;
+use serde_json::{json, Value};
 
-/// lists all existing containers
+const OCI_VERSION_MIN: &str = "1.0.0";
+const OCI_VERSION_MAX: &str = "1.2.0";
+
+const HOOKS: &[&str] = &[
+    "prestart",
+    "createRuntime",
+    "createContainer",
+    "startContainer",
+    "poststart",
+    "poststop",
+];
+
+/// Mount options known to youki, see `rootfs::utils::parse_mount`
+const MOUNT_OPTIONS: &[&str] = &[
+    "async",
+    "atime",
+    "bind",
+    "defaults",
+    "dev",
+    "diratime",
+    "dirsync",
+    "exec",
+    "idmap",
+    "mand",
+    "noatime",
+    "nodev",
+    "nodiratime",
+    "noexec",
+    "nomand",
+    "norelatime",
+    "nostrictatime",
+    "nosuid",
+    "private",
+    "ratime",
+    "rbind",
+    "rdev",
+    "rdiratime",
+    "relatime",
+    "remount",
+    "rexec",
+    "ridmap",
+    "rnoatime",
+    "rnodev",
+    "rnodiratime",
+    "rnoexec",
+    "rnorelatime",
+    "rnostrictatime",
+    "rnosuid",
+    "rnosymfollow",
+    "ro",
+    "rprivate",
+    "rrelatime",
+    "rro",
+    "rrw",
+    "rshared",
+    "rslave",
+    "rstrictatime",
+    "rsuid",
+    "rsymfollow",
+    "runbindable",
+    "rw",
+    "shared",
+    "slave",
+    "strictatime",
+    "suid",
+    "sync",
+    "tmpcopyup",
+    "unbindable",
+];
+
+const NAMESPACES: &[&str] = &[
+    "cgroup", "ipc", "mount", "network", "pid", "time", "user", "uts",
+];
+
+const SECCOMP_ACTIONS: &[&str] = &[
+    "SCMP_ACT_ALLOW",
+    "SCMP_ACT_ERRNO",
+    "SCMP_ACT_KILL",
+    "SCMP_ACT_KILL_PROCESS",
+    "SCMP_ACT_KILL_THREAD",
+    "SCMP_ACT_LOG",
+    "SCMP_ACT_NOTIFY",
+    "SCMP_ACT_TRACE",
+    "SCMP_ACT_TRAP",
+];
+
+const SECCOMP_OPERATORS: &[&str] = &[
+    "SCMP_CMP_EQ",
+    "SCMP_CMP_GE",
+    "SCMP_CMP_GT",
+    "SCMP_CMP_LE",
+    "SCMP_CMP_LT",
+    "SCMP_CMP_MASKED_EQ",
+    "SCMP_CMP_NE",
+];
+
+const SECCOMP_ARCHS: &[&str] = &[
+    "SCMP_ARCH_AARCH64",
+    "SCMP_ARCH_ARM",
+    "SCMP_ARCH_MIPS",
+    "SCMP_ARCH_MIPS64",
+    "SCMP_ARCH_MIPS64N32",
+    "SCMP_ARCH_MIPSEL",
+    "SCMP_ARCH_MIPSEL64",
+    "SCMP_ARCH_MIPSEL64N32",
+    "SCMP_ARCH_PPC",
+    "SCMP_ARCH_PPC64",
+    "SCMP_ARCH_PPC64LE",
+    "SCMP_ARCH_S390",
+    "SCMP_ARCH_S390X",
+    "SCMP_ARCH_X32",
+    "SCMP_ARCH_X86",
+    "SCMP_ARCH_X86_64",
+];
+
+const SECCOMP_FLAGS: &[&str] = &[
+    "SECCOMP_FILTER_FLAG_LOG",
+    "SECCOMP_FILTER_FLAG_SPEC_ALLOW",
+    "SECCOMP_FILTER_FLAG_TSYNC",
+];
+
+/// prints the features supported by youki as described in
+/// https://github.com/opencontainers/runtime-spec/blob/main/features.md
 pub fn features(_: Features) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&features_document())?);
     Ok(())
 }
+
+fn features_document() -> Value {
+    let mut capabilities: Vec<String> = caps::all().iter().map(|cap| cap.to_string()).collect();
+    capabilities.sort();
+
+    let seccomp = if features::seccomp_enabled() {
+        json!({
+            "enabled": true,
+            "actions": SECCOMP_ACTIONS,
+            "operators": SECCOMP_OPERATORS,
+            "archs": SECCOMP_ARCHS,
+            "knownFlags": SECCOMP_FLAGS,
+            "supportedFlags": SECCOMP_FLAGS,
+        })
+    } else {
+        json!({ "enabled": false })
+    };
+
+    let mut annotations = serde_json::Map::new();
+    if let Some(version) = features::libseccomp_version() {
+        annotations.insert(
+            "io.github.seccomp.libseccomp.version".to_owned(),
+            version.into(),
+        );
+    }
+    annotations.insert(
+        "org.opencontainers.runc.checkpoint.enabled".to_owned(),
+        "true".into(),
+    );
+    annotations.insert(
+        "org.youki.version".to_owned(),
+        env!("CARGO_PKG_VERSION").into(),
+    );
+
+    json!({
+        "ociVersionMin": OCI_VERSION_MIN,
+        "ociVersionMax": OCI_VERSION_MAX,
+        "hooks": HOOKS,
+        "mountOptions": MOUNT_OPTIONS,
+        "linux": {
+            "namespaces": NAMESPACES,
+            "capabilities": capabilities,
+            "cgroup": {
+                "v1": features::cgroup_v1_enabled(),
+                "v2": features::cgroup_v2_enabled(),
+                "systemd": features::systemd_enabled(),
+                "systemdUser": features::systemd_enabled(),
+                "rdma": true,
+            },
+            "seccomp": seccomp,
+            "apparmor": { "enabled": true },
+            "selinux": { "enabled": true },
+            "intelRdt": { "enabled": true },
+            "mountExtensions": {
+                "idmap": { "enabled": true },
+            },
+        },
+        "annotations": annotations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_features_document() {
+        let document = features_document();
+
+        assert_eq!(document["ociVersionMin"], OCI_VERSION_MIN);
+        assert_eq!(document["ociVersionMax"], OCI_VERSION_MAX);
+        assert_eq!(document["hooks"].as_array().unwrap().len(), HOOKS.len());
+        assert!(document["mountOptions"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("rbind")));
+        assert!(document["linux"]["capabilities"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("CAP_SYS_ADMIN")));
+        assert_eq!(
+            document["linux"]["seccomp"]["enabled"],
+            features::seccomp_enabled()
+        );
+    }
+}
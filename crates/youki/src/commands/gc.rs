@@ -0,0 +1,28 @@
+//! Contains functionality of the gc command, which removes what is left of
+//! containers whose state directory is gone
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use libcontainer::container::collect_garbage;
+
+/// Remove the cgroups and systemd units of containers without state
+#[derive(Parser, Debug)]
+pub struct Gc {}
+
+pub fn gc(_args: Gc, root_path: PathBuf) -> Result<()> {
+    let collected = collect_garbage(&root_path)
+        .with_context(|| format!("failed to collect the garbage of {}", root_path.display()))?;
+    for guard in collected {
+        match guard.systemd_unit() {
+            Some(unit) => println!("{}\t{}", guard.container_id(), unit),
+            None => println!(
+                "{}\t{}",
+                guard.container_id(),
+                guard.cgroup_path().display()
+            ),
+        }
+    }
+
+    Ok(())
+}
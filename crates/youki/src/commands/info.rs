@@ -2,30 +2,89 @@
 #[cfg(feature = "v2")]
 use std::collections::HashSet;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::Parser;
 #[cfg(feature = "v2")]
 use libcgroups::{common::CgroupSetup, v2::controller_type::ControllerType};
-use libcontainer::user_ns;
+use libcontainer::{apparmor, features, selinux, user_ns};
 use procfs::{CpuInfo, Current, Meminfo};
+use serde_json::{json, Value};
+
+/// Binaries unprivileged users need to map ids other than their own
+const ID_MAP_HELPERS: &[&str] = &["newuidmap", "newgidmap"];
+
 /// Show information about the system
 #[derive(Parser, Debug)]
-pub struct Info {}
+pub struct Info {
+    /// format to display the information: text or json (default: "text")
+    #[clap(short, long, default_value = "text")]
+    pub format: String,
+}
 
-pub fn info(_: Info) -> Result<()> {
-    print_youki();
-    print_kernel();
-    print_os();
-    print_hardware();
-    print_cgroups();
-    print_namespaces();
-    print_capabilities();
+pub fn info(args: Info) -> Result<()> {
+    match args.format.as_str() {
+        "text" => {
+            print_youki();
+            print_kernel();
+            print_os();
+            print_hardware();
+            print_cgroups();
+            print_namespaces();
+            print_capabilities();
+            print_security();
+            print_systemd();
+            print_id_map_helpers();
+        }
+        "json" => println!("{}", serde_json::to_string_pretty(&info_json())?),
+        format => bail!("invalid format {format:?}, must be text or json"),
+    }
 
     Ok(())
 }
 
+/// Collects the information relevant for bug reports and for deciding in CI
+/// which tests can run on the host
+fn info_json() -> Value {
+    let uname = nix::sys::utsname::uname().ok();
+    let id_map_helpers: serde_json::Map<String, Value> = ID_MAP_HELPERS
+        .iter()
+        .map(|helper| (helper.to_string(), json!(lookup_id_map_helper(helper))))
+        .collect();
+
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "commit": env!("VERGEN_GIT_SHA"),
+        "kernel": {
+            "release": uname.as_ref().map(|uname| uname.release().to_string_lossy()),
+            "version": uname.as_ref().map(|uname| uname.version().to_string_lossy()),
+            "architecture": uname.as_ref().map(|uname| uname.machine().to_string_lossy()),
+        },
+        "os": read_os(),
+        "cgroup": {
+            "setup": libcgroups::common::get_cgroup_setup().ok().map(|setup| setup.to_string()),
+            "controllers": {
+                "v1": cgroup_v1_controllers(),
+                "v2": cgroup_v2_controllers(),
+            },
+        },
+        "security": {
+            "seccomp": {
+                "enabled": features::seccomp_enabled(),
+                "backend": features::seccomp_backend(),
+                "libseccomp": features::libseccomp_version(),
+            },
+            "apparmor": apparmor_enabled(),
+            "selinux": selinux::is_enabled(),
+        },
+        "systemd": {
+            "version": systemd_version(),
+        },
+        "idMapHelpers": id_map_helpers,
+    })
+}
+
 /// print Version of Youki
 pub fn print_youki() {
     println!("{:<18}{}", "Version", env!("CARGO_PKG_VERSION"));
@@ -55,13 +114,15 @@ pub fn print_kernel() {
 /// Prints OS Distribution information
 // see https://www.freedesktop.org/software/systemd/man/os-release.html
 pub fn print_os() {
-    if let Some(os) = try_read_os_from("/etc/os-release") {
-        println!("{:<18}{}", "Operating System", os);
-    } else if let Some(os) = try_read_os_from("/usr/lib/os-release") {
+    if let Some(os) = read_os() {
         println!("{:<18}{}", "Operating System", os);
     }
 }
 
+fn read_os() -> Option<String> {
+    try_read_os_from("/etc/os-release").or_else(|| try_read_os_from("/usr/lib/os-release"))
+}
+
 /// Helper function to read the OS Distribution info
 fn try_read_os_from<P: AsRef<Path>>(path: P) -> Option<String> {
     let os_release = path.as_ref();
@@ -182,6 +243,35 @@ pub fn print_cgroup_v2_controllers() {
     }
 }
 
+/// Returns the controllers which are mounted as cgroup v1 hierarchies
+fn cgroup_v1_controllers() -> Option<Vec<String>> {
+    #[cfg(feature = "v1")]
+    {
+        let mounts = libcgroups::v1::util::list_supported_mount_points().ok()?;
+        let mut controllers: Vec<String> = mounts.keys().map(|c| c.to_string()).collect();
+        controllers.sort();
+        Some(controllers)
+    }
+    #[cfg(not(feature = "v1"))]
+    {
+        None
+    }
+}
+
+/// Returns the controllers which are available in the cgroup v2 hierarchy
+fn cgroup_v2_controllers() -> Option<Vec<String>> {
+    #[cfg(feature = "v2")]
+    {
+        let unified = libcgroups::v2::util::get_unified_mount_point().ok()?;
+        let controllers = libcgroups::v2::util::get_available_controllers(unified).ok()?;
+        Some(controllers.iter().map(|c| c.to_string()).collect())
+    }
+    #[cfg(not(feature = "v2"))]
+    {
+        None
+    }
+}
+
 fn read_kernel_config() -> Option<String> {
     let uname = nix::sys::utsname::uname();
     let kernel_config = Path::new("/boot").join(format!(
@@ -218,6 +308,7 @@ pub fn print_namespaces() {
         print_feature_status(&content, "CONFIG_USER_NS", user_display);
         print_feature_status(&content, "CONFIG_PID_NS", FeatureDisplay::new("pid"));
         print_feature_status(&content, "CONFIG_NET_NS", FeatureDisplay::new("network"));
+        print_feature_status(&content, "CONFIG_TIME_NS", FeatureDisplay::new("time"));
         // While the CONFIG_CGROUP_NS kernel feature exists, it is obsolete and should not be used. CGroup namespaces
         // are instead enabled with CONFIG_CGROUPS.
         print_feature_status(&content, "CONFIG_CGROUPS", FeatureDisplay::new("cgroup"))
@@ -256,6 +347,73 @@ pub fn print_capabilities() {
     }
 }
 
+pub fn print_security() {
+    println!("Security");
+    let seccomp = match features::libseccomp_version() {
+        Some(version) => format!("enabled (libseccomp {version})"),
+        None => match features::seccomp_backend() {
+            Some(backend) => format!("enabled ({backend})"),
+            None => "disabled".to_owned(),
+        },
+    };
+    println!("  {:<16}{}", "seccomp", seccomp);
+    println!("  {:<16}{}", "apparmor", enabled_status(apparmor_enabled()));
+    println!(
+        "  {:<16}{}",
+        "selinux",
+        enabled_status(selinux::is_enabled())
+    );
+}
+
+fn apparmor_enabled() -> bool {
+    apparmor::is_enabled().unwrap_or(false)
+}
+
+fn enabled_status(enabled: bool) -> &'static str {
+    if enabled {
+        "enabled"
+    } else {
+        "disabled"
+    }
+}
+
+pub fn print_systemd() {
+    match systemd_version() {
+        Some(version) => println!("{:<18}{}", "Systemd", version),
+        None => println!("{:<18}unavailable", "Systemd"),
+    }
+}
+
+/// Returns the version of the systemd instance which would manage the
+/// cgroups of containers, i.e. the user manager for unprivileged users
+pub(crate) fn systemd_version() -> Option<u32> {
+    #[cfg(feature = "systemd")]
+    {
+        if !libcgroups::systemd::booted() {
+            return None;
+        }
+        libcgroups::systemd::version(nix::unistd::geteuid().is_root())
+    }
+    #[cfg(not(feature = "systemd"))]
+    {
+        None
+    }
+}
+
+pub fn print_id_map_helpers() {
+    println!("ID map helpers");
+    for helper in ID_MAP_HELPERS {
+        match lookup_id_map_helper(helper) {
+            Some(path) => println!("  {:<16}{}", helper, path.display()),
+            None => println!("  {:<16}not found", helper),
+        }
+    }
+}
+
+fn lookup_id_map_helper(helper: &str) -> Option<PathBuf> {
+    user_ns::lookup_map_binary(helper).ok().flatten()
+}
+
 fn print_feature_status(config: &str, feature: &str, display: FeatureDisplay) {
     if let Some(status_flag) = find_parameter(config, feature) {
         let status = if status_flag == "y" {
@@ -1,61 +1,211 @@
 //! Contains Functionality of list container command
-use std::fmt::Write as _;
+use std::collections::HashMap;
 use std::io::Write;
-use std::path::PathBuf;
-use std::{fs, io};
+use std::path::{Path, PathBuf};
+use std::{fs, io, thread};
 
-use anyhow::Result;
-use chrono::{DateTime, Local};
-use libcontainer::container::state::State;
-use libcontainer::container::Container;
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Local, Utc};
+use libcontainer::container::{Container, ContainerStatus, State};
 use liboci_cli::List;
+use serde_json::json;
 use tabwriter::TabWriter;
 
+const STATUSES: [ContainerStatus; 5] = [
+    ContainerStatus::Creating,
+    ContainerStatus::Created,
+    ContainerStatus::Running,
+    ContainerStatus::Stopped,
+    ContainerStatus::Paused,
+];
+
+/// What is listed of a container. This is gathered by separate threads,
+/// which the container itself can't be passed between.
+struct Entry {
+    id: String,
+    pid: Option<i32>,
+    status: ContainerStatus,
+    bundle: PathBuf,
+    created: Option<DateTime<Utc>>,
+    owner: String,
+    annotations: Option<HashMap<String, String>>,
+}
+
+impl Entry {
+    fn load(container_dir: PathBuf) -> Result<Self> {
+        let container = Container::load(container_dir)?;
+        Ok(Self {
+            id: container.id().to_owned(),
+            pid: container.pid().map(|pid| pid.as_raw()),
+            status: container.status(),
+            bundle: container.bundle().to_owned(),
+            created: container.created(),
+            owner: container
+                .creator()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            annotations: container.state.annotations,
+        })
+    }
+}
+
 /// lists all existing containers
-pub fn list(_: List, root_path: PathBuf) -> Result<()> {
+pub fn list(args: List, root_path: PathBuf) -> Result<()> {
+    if !matches!(args.format.as_str(), "table" | "json") {
+        bail!("invalid format {:?}, must be table or json", args.format);
+    }
+    let statuses = parse_statuses(&args.status)?;
+
     let root_path = fs::canonicalize(root_path)?;
-    let mut content = String::new();
-    // all containers' data is stored in their respective dir in root directory
-    // so we iterate through each and print the various info
+    let entries: Vec<Entry> = load_entries(&root_path)?
+        .into_iter()
+        .filter(|entry| statuses.is_empty() || statuses.contains(&entry.status))
+        .collect();
+
+    if args.quiet {
+        for entry in &entries {
+            println!("{}", entry.id);
+        }
+        return Ok(());
+    }
+
+    match args.format.as_str() {
+        "json" => print_json(&entries),
+        _ => print_table(&entries),
+    }
+}
+
+fn parse_statuses(statuses: &[String]) -> Result<Vec<ContainerStatus>> {
+    statuses
+        .iter()
+        .map(|s| {
+            STATUSES
+                .into_iter()
+                .find(|status| status.to_string().eq_ignore_ascii_case(s))
+                .ok_or_else(|| anyhow!("invalid status {s:?}"))
+        })
+        .collect()
+}
+
+/// Loads the containers of the root directory. Each container's data is
+/// stored in its respective directory, which are read concurrently, so that
+/// listing many containers doesn't wait on each state file in turn.
+fn load_entries(root_path: &Path) -> Result<Vec<Entry>> {
+    let mut container_dirs = Vec::new();
     for container_dir in fs::read_dir(root_path)? {
         let container_dir = container_dir?.path();
-        let state_file = State::file_path(&container_dir);
-        if !state_file.exists() {
-            continue;
+        if State::file_path(&container_dir).exists() {
+            container_dirs.push(container_dir);
         }
+    }
 
-        let container = Container::load(container_dir)?;
-        let pid = if let Some(pid) = container.pid() {
-            pid.to_string()
-        } else {
-            "".to_owned()
-        };
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = container_dirs.len().div_ceil(threads).max(1);
+    let mut entries = thread::scope(|s| {
+        let handles: Vec<_> = container_dirs
+            .chunks(chunk_size)
+            .map(|dirs| {
+                s.spawn(move || {
+                    dirs.iter()
+                        .map(|dir| Entry::load(dir.to_owned()))
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("thread loading containers panicked"))
+            .collect::<Result<Vec<_>>>()
+    })?
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
 
-        let user_name = container.creator().unwrap_or_default();
+    entries.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(entries)
+}
 
-        let created = if let Some(utc) = container.created() {
+fn print_table(entries: &[Entry]) -> Result<()> {
+    let mut tab_writer = TabWriter::new(io::stdout());
+    writeln!(&mut tab_writer, "ID\tPID\tSTATUS\tBUNDLE\tCREATED\tCREATOR")?;
+    for entry in entries {
+        let pid = entry.pid.map(|pid| pid.to_string()).unwrap_or_default();
+        let created = if let Some(utc) = entry.created {
             let local: DateTime<Local> = DateTime::from(utc);
             local.to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
         } else {
             "".to_owned()
         };
 
-        let _ = writeln!(
-            content,
+        writeln!(
+            &mut tab_writer,
             "{}\t{}\t{}\t{}\t{}\t{}",
-            container.id(),
+            entry.id,
             pid,
-            container.status(),
-            container.bundle().display(),
+            entry.status,
+            entry.bundle.display(),
             created,
-            user_name.to_string_lossy()
-        );
+            entry.owner
+        )?;
     }
-
-    let mut tab_writer = TabWriter::new(io::stdout());
-    writeln!(&mut tab_writer, "ID\tPID\tSTATUS\tBUNDLE\tCREATED\tCREATOR")?;
-    write!(&mut tab_writer, "{content}")?;
     tab_writer.flush()?;
 
     Ok(())
 }
+
+// same fields as the json format of runc, so that tools parsing it work with both
+fn print_json(entries: &[Entry]) -> Result<()> {
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "id": entry.id,
+                "pid": entry.pid.unwrap_or_default(),
+                "status": entry.status,
+                "bundle": entry.bundle,
+                "created": entry.created,
+                "owner": entry.owner,
+                "annotations": entry.annotations,
+            })
+        })
+        .collect();
+    println!("{}", serde_json::to_string(&entries)?);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_statuses() -> Result<()> {
+        assert_eq!(
+            parse_statuses(&["running".to_owned(), "Paused".to_owned()])?,
+            vec![ContainerStatus::Running, ContainerStatus::Paused]
+        );
+        assert!(parse_statuses(&["unknown".to_owned()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_entries() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        for id in ["c", "a", "b"] {
+            let container_dir = tmp.path().join(id);
+            fs::create_dir(&container_dir)?;
+            State::new(id, ContainerStatus::Stopped, None, PathBuf::from("/bundle"))
+                .save(&container_dir)?;
+        }
+        fs::create_dir(tmp.path().join("not-a-container"))?;
+
+        let ids: Vec<_> = load_entries(tmp.path())?
+            .into_iter()
+            .map(|entry| entry.id)
+            .collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+        Ok(())
+    }
+}
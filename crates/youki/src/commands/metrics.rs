@@ -0,0 +1,502 @@
+//! Contains functionality of the metrics command, which serves the cgroup
+//! statistics of all containers in the Prometheus text format
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Take, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use libcgroups::common::{CgroupManager, CgroupSetup};
+use libcgroups::stats::{BlkioDeviceStat, PSIStats, Stats};
+use libcontainer::container::{Container, ContainerStatus, State};
+
+const PREFIX: &str = "youki_container";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+// a scrape must not be able to block the server by never finishing its
+// request, nor make it buffer an endless one
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REQUEST_SIZE: u64 = 8192;
+
+/// Export the cgroup statistics of the containers for Prometheus
+#[derive(Parser, Debug)]
+pub struct Metrics {
+    #[clap(subcommand)]
+    pub cmd: MetricsCmd,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MetricsCmd {
+    /// Serve the metrics of all containers over http until interrupted
+    Serve(Serve),
+}
+
+#[derive(Parser, Debug)]
+pub struct Serve {
+    /// Address to listen on, the metrics are served under /metrics
+    #[clap(long, default_value = "127.0.0.1:9176")]
+    pub listen: SocketAddr,
+}
+
+pub fn metrics(args: Metrics, root_path: PathBuf) -> Result<()> {
+    match args.cmd {
+        MetricsCmd::Serve(serve) => self::serve(serve, root_path),
+    }
+}
+
+fn serve(args: Serve, root_path: PathBuf) -> Result<()> {
+    let root_path = fs::canonicalize(root_path)?;
+    let units = CpuUnits::detect();
+    let listener = TcpListener::bind(args.listen)
+        .with_context(|| format!("failed to listen on {}", args.listen))?;
+    tracing::info!("serving metrics on http://{}/metrics", args.listen);
+
+    // scrapes are rare and cheap enough to be answered one after another
+    for stream in listener.incoming() {
+        let result = stream
+            .map_err(anyhow::Error::from)
+            .and_then(|stream| handle(stream, &root_path, units));
+        if let Err(err) = result {
+            tracing::warn!("failed to answer metrics request: {:?}", err);
+        }
+    }
+
+    Ok(())
+}
+
+fn handle(stream: TcpStream, root_path: &Path, units: CpuUnits) -> Result<()> {
+    let request_line = match read_request_line(&stream, REQUEST_TIMEOUT) {
+        Ok(request_line) => request_line,
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            tracing::debug!("closing connection of client which is too slow");
+            (&stream).write_all(
+                b"HTTP/1.1 408 Request Timeout\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            )?;
+            return Ok(());
+        }
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+            tracing::debug!("closing connection of client with invalid request: {}", err);
+            (&stream).write_all(
+                b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            )?;
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = render(&collect(root_path)?, units);
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        }
+        (Some("GET"), _) => {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+        }
+        _ => "HTTP/1.1 405 Method Not Allowed\r\nAllow: GET\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            .to_owned(),
+    };
+    (&stream).write_all(response.as_bytes())?;
+
+    Ok(())
+}
+
+/// Reads the head of a request, which has to arrive within the timeout and
+/// [`MAX_REQUEST_SIZE`], and returns the request line. The headers are not
+/// needed, but have to be read before answering.
+fn read_request_line(stream: &TcpStream, timeout: Duration) -> io::Result<String> {
+    let deadline = Instant::now() + timeout;
+    let mut reader = BufReader::new(DeadlineReader { stream, deadline }.take(MAX_REQUEST_SIZE));
+    let mut request_line = String::new();
+    read_line(&mut reader, &mut request_line)?;
+    let mut header = String::new();
+    while read_line(&mut reader, &mut header)? > 2 {
+        header.clear();
+    }
+
+    Ok(request_line)
+}
+
+// a line which is cut off by the size limit makes the request invalid
+fn read_line<R: Read>(reader: &mut BufReader<Take<R>>, line: &mut String) -> io::Result<usize> {
+    let read = reader.read_line(line)?;
+    if !line.ends_with('\n') && reader.get_ref().limit() == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "request exceeds the maximum size",
+        ));
+    }
+    Ok(read)
+}
+
+/// Reads from a stream until the deadline, which unlike a read timeout
+/// doesn't restart whenever the client sends something
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        let mut stream = self.stream;
+        stream.read(buf)
+    }
+}
+
+/// Returns the statistics of the containers of the root directory which
+/// have a cgroup. Containers whose statistics can't be read, e.g. because
+/// they have been deleted in the meantime, are left out of the scrape.
+fn collect(root_path: &Path) -> Result<Vec<(String, Stats)>> {
+    let mut containers = Vec::new();
+    for container_dir in fs::read_dir(root_path)? {
+        let container_dir = container_dir?.path();
+        if !State::file_path(&container_dir).exists() {
+            continue;
+        }
+
+        let stats = Container::load(container_dir.clone())
+            .map_err(anyhow::Error::from)
+            .and_then(|container| {
+                if !matches!(
+                    container.status(),
+                    ContainerStatus::Created | ContainerStatus::Running | ContainerStatus::Paused
+                ) {
+                    return Ok(None);
+                }
+                let stats = container.cgroup_manager()?.stats()?;
+                Ok(Some((container.id().to_owned(), stats)))
+            });
+        match stats {
+            Ok(Some(stats)) => containers.push(stats),
+            Ok(None) => {}
+            Err(err) => tracing::debug!(?container_dir, "skipping container: {:?}", err),
+        }
+    }
+
+    containers.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(containers)
+}
+
+/// Unit of the cpu times of the statistics, which are reported in
+/// nanoseconds by cgroup v1 and in microseconds by cgroup v2
+#[derive(Debug, Clone, Copy)]
+struct CpuUnits {
+    per_second: f64,
+}
+
+impl CpuUnits {
+    const NANOS: Self = Self { per_second: 1e9 };
+    const MICROS: Self = Self { per_second: 1e6 };
+
+    fn detect() -> Self {
+        match libcgroups::common::get_cgroup_setup() {
+            Ok(CgroupSetup::Unified) => Self::MICROS,
+            // the cpu controllers are bound to the v1 hierarchies on hybrid
+            // systems as well
+            _ => Self::NANOS,
+        }
+    }
+}
+
+/// Samples of a metric family, with the labels besides the container id
+type Samples = Vec<(String, f64)>;
+
+struct Family<'a> {
+    out: &'a mut String,
+    containers: &'a [(String, Stats)],
+}
+
+impl Family<'_> {
+    fn write(&mut self, name: &str, kind: &str, help: &str, samples: impl Fn(&Stats) -> Samples) {
+        let _ = writeln!(self.out, "# HELP {PREFIX}_{name} {help}");
+        let _ = writeln!(self.out, "# TYPE {PREFIX}_{name} {kind}");
+        for (id, stats) in self.containers {
+            for (labels, value) in samples(stats) {
+                let _ = writeln!(
+                    self.out,
+                    "{PREFIX}_{name}{{id=\"{}\"{labels}}} {value}",
+                    escape(id)
+                );
+            }
+        }
+    }
+
+    fn counter(&mut self, name: &str, help: &str, value: impl Fn(&Stats) -> f64) {
+        self.write(name, "counter", help, |stats| {
+            vec![(String::new(), value(stats))]
+        });
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: impl Fn(&Stats) -> f64) {
+        self.write(name, "gauge", help, |stats| {
+            vec![(String::new(), value(stats))]
+        });
+    }
+
+    fn pressure(&mut self, resource: &str, psi: impl Fn(&Stats) -> &PSIStats) {
+        self.write(
+            &format!("{resource}_pressure_waiting_seconds_total"),
+            "counter",
+            &format!("Time in which some tasks were stalled waiting for {resource}"),
+            |stats| vec![(String::new(), psi(stats).some.total as f64 / 1e6)],
+        );
+        self.write(
+            &format!("{resource}_pressure_stalled_seconds_total"),
+            "counter",
+            &format!("Time in which all tasks were stalled waiting for {resource}"),
+            |stats| vec![(String::new(), psi(stats).full.total as f64 / 1e6)],
+        );
+    }
+}
+
+fn render(containers: &[(String, Stats)], units: CpuUnits) -> String {
+    let mut out = String::new();
+    let mut family = Family {
+        out: &mut out,
+        containers,
+    };
+    let seconds = |time: u64| time as f64 / units.per_second;
+
+    family.counter(
+        "cpu_usage_seconds_total",
+        "Cpu time consumed by the tasks",
+        |s| seconds(s.cpu.usage.usage_total),
+    );
+    family.counter(
+        "cpu_user_seconds_total",
+        "Cpu time consumed by the tasks in user mode",
+        |s| seconds(s.cpu.usage.usage_user),
+    );
+    family.counter(
+        "cpu_system_seconds_total",
+        "Cpu time consumed by the tasks in kernel mode",
+        |s| seconds(s.cpu.usage.usage_kernel),
+    );
+    family.counter(
+        "cpu_cfs_periods_total",
+        "Number of elapsed enforcement periods",
+        |s| s.cpu.throttling.periods as f64,
+    );
+    family.counter(
+        "cpu_cfs_throttled_periods_total",
+        "Number of enforcement periods in which the tasks were throttled",
+        |s| s.cpu.throttling.throttled_periods as f64,
+    );
+    family.counter(
+        "cpu_cfs_throttled_seconds_total",
+        "Time for which the tasks were throttled",
+        |s| seconds(s.cpu.throttling.throttled_time),
+    );
+    family.pressure("cpu", |s| &s.cpu.psi);
+
+    family.gauge("memory_usage_bytes", "Memory used by the tasks", |s| {
+        s.memory.memory.usage as f64
+    });
+    family.gauge(
+        "memory_max_usage_bytes",
+        "Maximum recorded memory usage of the tasks",
+        |s| s.memory.memory.max_usage as f64,
+    );
+    family.gauge("memory_limit_bytes", "Memory limit of the tasks", |s| {
+        s.memory.memory.limit as f64
+    });
+    family.counter(
+        "memory_failures_total",
+        "Number of times the memory usage hit the limit",
+        |s| s.memory.memory.fail_count as f64,
+    );
+    family.gauge(
+        "memory_swap_usage_bytes",
+        "Memory and swap used by the tasks",
+        |s| s.memory.memswap.usage as f64,
+    );
+    family.gauge("swap_usage_bytes", "Swap used by the tasks", |s| {
+        s.memory.swap.usage as f64
+    });
+    family.gauge("swap_limit_bytes", "Swap limit of the tasks", |s| {
+        s.memory.swap.limit as f64
+    });
+    family.pressure("memory", |s| &s.memory.psi);
+
+    family.write(
+        "blkio_bytes_total",
+        "counter",
+        "Bytes transferred to and from a device",
+        |s| device_samples(&s.blkio.service_bytes),
+    );
+    family.write(
+        "blkio_operations_total",
+        "counter",
+        "I/O operations performed on a device",
+        |s| device_samples(&s.blkio.serviced),
+    );
+    family.pressure("io", |s| &s.blkio.psi);
+
+    family.gauge("pids_current", "Number of tasks", |s| s.pids.current as f64);
+    family.gauge(
+        "pids_limit",
+        "Maximum number of tasks, 0 if unlimited",
+        |s| s.pids.limit as f64,
+    );
+
+    out
+}
+
+fn device_samples(stats: &[BlkioDeviceStat]) -> Samples {
+    stats
+        .iter()
+        .map(|stat| {
+            let mut labels = format!(",device=\"{}:{}\"", stat.major, stat.minor);
+            if let Some(op_type) = &stat.op_type {
+                let _ = write!(labels, ",operation=\"{}\"", escape(&op_type.to_lowercase()));
+            }
+            (labels, stat.value as f64)
+        })
+        .collect()
+}
+
+// label values have to escape backslashes, quotes and line feeds
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn connect() -> Result<(TcpStream, TcpStream)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let client = TcpStream::connect(listener.local_addr()?)?;
+        let (server, _) = listener.accept()?;
+        Ok((client, server))
+    }
+
+    #[test]
+    fn test_read_request_line() -> Result<()> {
+        let (mut client, server) = connect()?;
+        client.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+        assert_eq!(
+            read_request_line(&server, REQUEST_TIMEOUT)?,
+            "GET /metrics HTTP/1.1\r\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_too_large() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let (mut client, server) = connect()?;
+        // an endless header line, of which the server reads everything sent
+        let mut request = b"GET /metrics HTTP/1.1\r\nX-Header: ".to_vec();
+        request.resize(MAX_REQUEST_SIZE as usize, b'a');
+        client.write_all(&request)?;
+
+        handle(server, root.path(), CpuUnits::MICROS)?;
+        let mut response = String::new();
+        client.read_to_string(&mut response)?;
+        assert!(
+            response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+            "{response}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_deadline() -> Result<()> {
+        let (mut client, server) = connect()?;
+        // a byte well within the read timeout doesn't extend the deadline
+        let trickle = thread::spawn(move || {
+            for _ in 0..20 {
+                if client.write_all(b"G").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+
+        let start = Instant::now();
+        let err = read_request_line(&server, Duration::from_millis(300)).unwrap_err();
+        assert!(
+            matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ),
+            "{err}"
+        );
+        assert!(start.elapsed() < Duration::from_millis(900));
+        drop(server);
+        trickle.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_render() {
+        let mut stats = Stats::default();
+        stats.cpu.usage.usage_total = 1_500_000;
+        stats.memory.memory.usage = 4096;
+        stats.memory.psi.full.total = 250_000;
+        stats.blkio.service_bytes.push(BlkioDeviceStat {
+            major: 8,
+            minor: 0,
+            op_type: Some("Read".to_owned()),
+            value: 512,
+        });
+
+        let out = render(&[("a\"b".to_owned(), stats)], CpuUnits::MICROS);
+        assert!(out.contains(
+            "# HELP youki_container_cpu_usage_seconds_total Cpu time consumed by the tasks\n\
+             # TYPE youki_container_cpu_usage_seconds_total counter\n\
+             youki_container_cpu_usage_seconds_total{id=\"a\\\"b\"} 1.5\n"
+        ));
+        assert!(out.contains("youki_container_memory_usage_bytes{id=\"a\\\"b\"} 4096\n"));
+        assert!(out.contains(
+            "youki_container_memory_pressure_stalled_seconds_total{id=\"a\\\"b\"} 0.25\n"
+        ));
+        assert!(out.contains(
+            "youki_container_blkio_bytes_total{id=\"a\\\"b\",device=\"8:0\",operation=\"read\"} 512\n"
+        ));
+    }
+
+    #[test]
+    fn test_render_groups_families() {
+        let containers = vec![
+            ("a".to_owned(), Stats::default()),
+            ("b".to_owned(), Stats::default()),
+        ];
+        let out = render(&containers, CpuUnits::NANOS);
+        // all samples of a family have to follow its header
+        let lines: Vec<_> = out.lines().collect();
+        let header = lines
+            .iter()
+            .position(|l| *l == "# TYPE youki_container_pids_current gauge")
+            .unwrap();
+        assert_eq!(
+            lines[header + 1],
+            "youki_container_pids_current{id=\"a\"} 0"
+        );
+        assert_eq!(
+            lines[header + 2],
+            "youki_container_pids_current{id=\"b\"} 0"
+        );
+    }
+}
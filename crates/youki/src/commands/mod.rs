@@ -1,27 +1,36 @@
-use std::fs;
+use std::fs::{self, File};
+use std::os::fd::AsRawFd;
 use std::path::{Path, PathBuf};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{Context, Result};
 use libcgroups::common::AnyCgroupManager;
 use libcontainer::container::Container;
+use libcontainer::error::LibcontainerError;
+use libcontainer::notify_proxy::{self, NotifyProxy, NOTIFY_SOCKET_ENV};
+use nix::unistd::{dup2, fork, ForkResult, Pid};
 
 pub mod checkpoint;
 pub mod completion;
 pub mod create;
+pub mod daemon;
 pub mod delete;
 pub mod events;
 pub mod exec;
 pub mod features;
+pub mod gc;
 pub mod info;
 pub mod kill;
 pub mod list;
+pub mod metrics;
 pub mod pause;
 pub mod ps;
+pub mod restore;
 pub mod resume;
 pub mod run;
 pub mod spec_json;
 pub mod start;
 pub mod state;
+pub mod systemd_properties;
 pub mod update;
 
 fn construct_container_root<P: AsRef<Path>>(root_path: P, container_id: &str) -> Result<PathBuf> {
@@ -40,13 +49,60 @@ fn construct_container_root<P: AsRef<Path>>(root_path: P, container_id: &str) ->
 fn load_container<P: AsRef<Path>>(root_path: P, container_id: &str) -> Result<Container> {
     let container_root = construct_container_root(root_path, container_id)?;
     if !container_root.exists() {
-        bail!("container {} does not exist.", container_id)
+        return Err(LibcontainerError::NoDirectory)
+            .with_context(|| format!("container {container_id} does not exist."));
     }
 
     Container::load(container_root)
         .with_context(|| format!("could not load state for container {container_id}"))
 }
 
+/// Binds the notify socket of the container, if the container has been
+/// created with one and youki has been given a notify socket by the caller
+fn bind_notify_proxy(container: &Container) -> Result<Option<NotifyProxy>> {
+    let host_socket = match std::env::var_os(NOTIFY_SOCKET_ENV) {
+        Some(host_socket) => PathBuf::from(host_socket),
+        None => return Ok(None),
+    };
+    if !notify_proxy::is_enabled(&container.root) {
+        return Ok(None);
+    }
+
+    let proxy = NotifyProxy::bind(&container.root, &host_socket)
+        .context("failed to bind notify socket of the container")?;
+    Ok(Some(proxy))
+}
+
+/// Forwards the notify messages of the container in a child process, which
+/// keeps running after youki has exited, until the container init exits
+fn spawn_notify_proxy(proxy: NotifyProxy, init_pid: Pid) -> Result<()> {
+    match unsafe { fork()? } {
+        ForkResult::Parent { .. } => Ok(()),
+        ForkResult::Child => {
+            detach_stdio();
+            let status = match proxy.run(init_pid) {
+                Ok(()) => 0,
+                Err(err) => {
+                    tracing::error!(?err, "failed to forward notify messages");
+                    1
+                }
+            };
+            std::process::exit(status);
+        }
+    }
+}
+
+/// Replaces the stdio of a process which keeps running after youki has
+/// exited with /dev/null. The stdio of youki is often a pipe, whose reader
+/// waits until all writers have closed it.
+fn detach_stdio() {
+    if let Ok(null) = File::options().read(true).write(true).open("/dev/null") {
+        for fd in 0..3 {
+            let _ = dup2(null.as_raw_fd(), fd);
+        }
+    }
+}
+
 fn container_exists<P: AsRef<Path>>(root_path: P, container_id: &str) -> Result<bool> {
     let container_root = construct_container_root(root_path, container_id)?;
     Ok(container_root.exists())
@@ -57,11 +113,5 @@ fn create_cgroup_manager<P: AsRef<Path>>(
     container_id: &str,
 ) -> Result<AnyCgroupManager> {
     let container = load_container(root_path, container_id)?;
-    Ok(libcgroups::common::create_cgroup_manager(
-        libcgroups::common::CgroupConfig {
-            cgroup_path: container.spec()?.cgroup_path,
-            systemd_cgroup: container.systemd(),
-            container_name: container.id().to_string(),
-        },
-    )?)
+    Ok(container.cgroup_manager()?)
 }
@@ -16,38 +16,64 @@ pub fn ps(args: Ps, root_path: PathBuf) -> Result<()> {
         .map(|pid| pid.as_raw())
         .collect();
 
-    if args.format == "json" {
-        println!("{}", serde_json::to_string(&pids)?);
-    } else if args.format == "table" {
-        let default_ps_options = vec![String::from("-ef")];
-        let ps_options = if args.ps_options.is_empty() {
-            &default_ps_options
-        } else {
-            &args.ps_options
-        };
-        let output = Command::new("ps").args(ps_options).output()?;
-        if !output.status.success() {
-            println!("{}", std::str::from_utf8(&output.stderr)?);
-        } else {
-            let lines = std::str::from_utf8(&output.stdout)?;
-            let lines: Vec<&str> = lines.split('\n').collect();
-            let pid_index = get_pid_index(lines[0])?;
-            println!("{}", &lines[0]);
-            for line in &lines[1..] {
-                if line.is_empty() {
-                    continue;
-                }
-                let fields: Vec<&str> = line.split_whitespace().collect();
-                let pid: i32 = fields[pid_index].parse()?;
-                if pids.contains(&pid) {
-                    println!("{line}");
-                }
+    match args.format.as_str() {
+        "json" => {
+            if !args.ps_options.is_empty() {
+                bail!(
+                    "ps options {:?} are not supported with json format",
+                    args.ps_options
+                );
             }
+            println!("{}", serde_json::to_string(&pids)?);
         }
+        "table" => {
+            let default_ps_options = vec![String::from("-ef")];
+            let ps_options = if args.ps_options.is_empty() {
+                &default_ps_options
+            } else {
+                &args.ps_options
+            };
+            let output = Command::new("ps").args(ps_options).output()?;
+            if !output.status.success() {
+                bail!(
+                    "ps failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            for line in filter_ps_output(std::str::from_utf8(&output.stdout)?, &pids)? {
+                println!("{line}");
+            }
+        }
+        format => bail!("invalid format {format:?}, must be table or json"),
     }
     Ok(())
 }
 
+// Keeps the title line and the lines of the processes in the container.
+fn filter_ps_output<'a>(output: &'a str, pids: &[i32]) -> Result<Vec<&'a str>> {
+    let mut lines = output.lines();
+    let title = match lines.next() {
+        Some(title) => title,
+        None => bail!("ps didn't print any output"),
+    };
+    let pid_index = get_pid_index(title)?;
+
+    let mut filtered = vec![title];
+    for line in lines {
+        if line.is_empty() {
+            continue;
+        }
+        let pid: i32 = match line.split_whitespace().nth(pid_index) {
+            Some(pid) => pid.parse()?,
+            None => bail!("missing PID field in ps output line {line:?}"),
+        };
+        if pids.contains(&pid) {
+            filtered.push(line);
+        }
+    }
+    Ok(filtered)
+}
+
 fn get_pid_index(title: &str) -> Result<usize> {
     let titles = title.split_whitespace();
 
@@ -58,3 +84,26 @@ fn get_pid_index(title: &str) -> Result<usize> {
     }
     bail!("could't find PID field in ps output");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_ps_output() -> Result<()> {
+        let output = "UID          PID    PPID  C STIME TTY          TIME CMD\n\
+                      root           1       0  0 10:00 ?        00:00:01 /sbin/init\n\
+                      root        4242       1  0 10:01 ?        00:00:00 sleep 100\n";
+
+        assert_eq!(
+            filter_ps_output(output, &[4242])?,
+            vec![
+                "UID          PID    PPID  C STIME TTY          TIME CMD",
+                "root        4242       1  0 10:01 ?        00:00:00 sleep 100",
+            ]
+        );
+        assert!(filter_ps_output("USER COMMAND\nroot init\n", &[1]).is_err());
+
+        Ok(())
+    }
+}
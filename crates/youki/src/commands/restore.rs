@@ -0,0 +1,96 @@
+//! Contains functionality of restore container command
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use libcgroups::common::CgroupManagerType;
+use libcontainer::container::builder::ContainerBuilder;
+use libcontainer::container::RestoreOptions;
+use libcontainer::syscall::syscall::SyscallType;
+use liboci_cli::Restore;
+use nix::sys::prctl;
+
+use crate::commands::run;
+
+/// Restores the container and, unless it is detached, waits for it to exit
+/// like `youki run`, returning the exit status of its init process
+pub fn restore(
+    args: Restore,
+    root_path: PathBuf,
+    cgroup_manager: CgroupManagerType,
+) -> Result<i32> {
+    tracing::debug!("start restoring container {}", args.container_id);
+    if args.console_socket.is_some() {
+        bail!("restoring a container with a terminal is not supported");
+    }
+    if args.no_pivot {
+        bail!("--no-pivot is not supported by restore");
+    }
+    if args.no_subreaper && !args.detach {
+        // the restored init process is only reparented to youki if youki
+        // is a subreaper
+        bail!("--no-subreaper is only supported with --detach");
+    }
+    let opts = RestoreOptions {
+        auto_dedup: args.auto_dedup,
+        empty_ns: args.empty_ns,
+        ext_unix_sk: args.ext_unix_sk,
+        file_locks: args.file_locks,
+        image_path: args.image_path,
+        lazy_pages: args.lazy_pages,
+        manage_cgroups: manage_cgroups(args.manage_cgroups_mode.as_deref())?,
+        shell_job: args.shell_job,
+        tcp_established: args.tcp_established,
+        work_path: args.work_path,
+    };
+
+    // CRIU exits once it has restored the container, after which the init
+    // process is reparented to youki
+    if !args.detach {
+        prctl::set_child_subreaper(true).context("failed to become a subreaper")?;
+    }
+    let mut container = ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
+        .with_pid_file(args.pid_file.as_ref())?
+        .with_root_path(root_path)?
+        .validate_id()?
+        .as_init(&args.bundle)
+        .with_cgroup_manager(cgroup_manager)
+        .with_detach(args.detach)
+        .restore(&opts)
+        .with_context(|| format!("failed to restore container {}", args.container_id))?;
+    if args.detach {
+        return Ok(0);
+    }
+
+    let exit = run::wait_foreground(&mut container);
+    container.delete(true)?;
+    Ok(exit?.status())
+}
+
+/// Returns if CRIU manages the cgroups, for the modes of `--manage-cgroups-mode`
+/// of runc. Only the soft mode, which is the default, and ignoring the cgroups
+/// can be passed to CRIU.
+fn manage_cgroups(mode: Option<&str>) -> Result<bool> {
+    match mode {
+        None | Some("soft") => Ok(true),
+        Some("ignore") => Ok(false),
+        Some(mode @ ("full" | "strict")) => {
+            bail!("--manage-cgroups-mode {mode} is not supported by restore")
+        }
+        Some(mode) => bail!("invalid --manage-cgroups-mode {mode}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manage_cgroups() {
+        assert!(manage_cgroups(None).unwrap());
+        assert!(manage_cgroups(Some("soft")).unwrap());
+        assert!(!manage_cgroups(Some("ignore")).unwrap());
+        for mode in ["full", "strict", "hard"] {
+            assert!(manage_cgroups(Some(mode)).is_err(), "{mode}");
+        }
+    }
+}
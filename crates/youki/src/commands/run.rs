@@ -1,17 +1,77 @@
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, OwnedFd};
 use std::path::PathBuf;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use libcgroups::common::CgroupManagerType;
 use libcontainer::container::builder::ContainerBuilder;
+use libcontainer::container::{Container, ExitInfo};
+use libcontainer::notify_proxy::NOTIFY_SOCKET_ENV;
+use libcontainer::pidfd::PidFd;
 use libcontainer::syscall::syscall::SyscallType;
 use liboci_cli::Run;
-use nix::sys::signal::{self, kill};
-use nix::sys::signalfd::SigSet;
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::sys::signal::{self, kill, Signal};
+use nix::sys::signalfd::{SfdFlags, SigSet, SignalFd};
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
-use nix::unistd::Pid;
+use nix::unistd::{fork, pipe2, setsid, ForkResult, Pid};
 
+use crate::commands::{bind_notify_proxy, detach_stdio, spawn_notify_proxy};
 use crate::workload::executor::default_executor;
 
-pub fn run(args: Run, root_path: PathBuf, systemd_cgroup: bool) -> Result<i32> {
+// first byte the supervisor of a detached container sends to youki, which is
+// followed by the error if the container could not be started
+const STARTED: u8 = 0;
+const FAILED: u8 = 1;
+
+pub fn run(args: Run, root_path: PathBuf, cgroup_manager: CgroupManagerType) -> Result<i32> {
+    if args.detach {
+        return run_detached(args, root_path, cgroup_manager);
+    }
+
+    let mut container = start_container(&args, root_path, cgroup_manager)?;
+
+    // Using `debug_assert` here rather than returning an error because this is
+    // a invariant. The design when the code path arrives to this point, is that
+    // the container state must have recorded the container init pid.
+    debug_assert!(
+        container.pid().is_some(),
+        "expects a container init pid in the container state"
+    );
+    let foreground_result = wait_foreground(&mut container);
+    // execute the destruction action after the container finishes running,
+    // unless the stopped container is kept for inspection
+    if !args.keep {
+        container.delete(true)?;
+    }
+    // a process killed by a signal exits with 128 plus the signal like in a shell
+    Ok(foreground_result?.status())
+}
+
+/// Waits for the init process of the container, which is a child of youki,
+/// while forwarding the signals to it, and records its exit in the state
+pub(crate) fn wait_foreground(container: &mut Container) -> Result<ExitInfo> {
+    let pid = container
+        .pid()
+        .context("no pid of the container init process in the state")?;
+    let mut exit = handle_foreground(pid)?;
+    exit.oom_killed = container.update_oom_killed().unwrap_or_else(|err| {
+        tracing::warn!("failed to check for out of memory kills: {}", err);
+        false
+    });
+    container.record_exit(&exit)?;
+    Ok(exit)
+}
+
+fn start_container(
+    args: &Run,
+    root_path: PathBuf,
+    cgroup_manager: CgroupManagerType,
+) -> Result<Container> {
     let mut container = ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
         .with_executor(default_executor())
         .with_pid_file(args.pid_file.as_ref())?
@@ -20,38 +80,126 @@ pub fn run(args: Run, root_path: PathBuf, systemd_cgroup: bool) -> Result<i32> {
         .with_preserved_fds(args.preserve_fds)
         .validate_id()?
         .as_init(&args.bundle)
-        .with_systemd(systemd_cgroup)
+        .with_cgroup_manager(cgroup_manager)
+        .with_no_pivot(args.no_pivot)
+        .with_no_new_keyring(args.no_new_keyring)
+        .with_ignore_unsupported(args.ignore_unsupported)
         .with_detach(args.detach)
+        .with_notify_socket(env::var_os(NOTIFY_SOCKET_ENV))
         .build()?;
 
+    if args.keep {
+        container.keep_mount_namespace()?;
+    }
+    let notify_proxy = bind_notify_proxy(&container)?;
     container
         .start()
         .with_context(|| format!("failed to start container {}", args.container_id))?;
+    if let (Some(proxy), Some(pid)) = (notify_proxy, container.pid()) {
+        spawn_notify_proxy(proxy, pid)?;
+    }
 
-    if args.detach {
-        return Ok(0);
+    Ok(container)
+}
+
+/// Runs the container detached from youki like `runc run -d`. The container
+/// is created and started by a daemonized supervisor in a session of its own,
+/// which stays the parent of the init process. youki waits until the
+/// container has been started, i.e. until the pseudo terminal has been sent
+/// to the console socket and the pid file has been written, and exits with
+/// the result. The logs of the supervisor still go to the log file, while
+/// logs to stderr are dropped with its stdio.
+///
+/// The supervisor reaps the init process, records its exit in the state and
+/// exits with the same status. So a subreaper of the caller, which gets the
+/// supervisor reparented instead of the init process, e.g. containerd-shim,
+/// still sees the exit status of the container. The container is kept after
+/// it has exited until it is deleted like with runc.
+fn run_detached(args: Run, root_path: PathBuf, cgroup_manager: CgroupManagerType) -> Result<i32> {
+    // the container processes must not inherit the pipe, or youki would wait
+    // for them to close it too
+    let (reader, writer) =
+        pipe2(OFlag::O_CLOEXEC).context("failed to create pipe to the supervisor")?;
+    match unsafe { fork().context("failed to fork supervisor")? } {
+        ForkResult::Parent { child } => {
+            drop(writer);
+            // the intermediate process exits as soon as it has forked the
+            // supervisor
+            waitpid(child, None)?;
+            let mut result = Vec::new();
+            File::from(reader)
+                .read_to_end(&mut result)
+                .context("failed to read result from the supervisor")?;
+            match result.split_first() {
+                Some((&STARTED, _)) => Ok(0),
+                Some((_, err)) => bail!("{}", String::from_utf8_lossy(err)),
+                None => bail!("container supervisor exited before starting the container"),
+            }
+        }
+        ForkResult::Child => {
+            drop(reader);
+            // A new session keeps the supervisor from receiving the signals
+            // of the terminal of the caller, and the second fork from ever
+            // acquiring a controlling terminal again.
+            let status = match setsid().and_then(|_| unsafe { fork() }) {
+                Ok(ForkResult::Parent { .. }) => 0,
+                Ok(ForkResult::Child) => supervise(args, root_path, cgroup_manager, writer),
+                Err(err) => {
+                    let mut writer = File::from(writer);
+                    let _ = write!(
+                        writer,
+                        "{}failed to daemonize supervisor: {err}",
+                        FAILED as char
+                    );
+                    1
+                }
+            };
+            std::process::exit(status);
+        }
     }
+}
 
-    // Using `debug_assert` here rather than returning an error because this is
-    // a invariant. The design when the code path arrives to this point, is that
-    // the container state must have recorded the container init pid.
-    debug_assert!(
-        container.pid().is_some(),
-        "expects a container init pid in the container state"
-    );
-    let foreground_result = handle_foreground(container.pid().unwrap());
-    // execute the destruction action after the container finishes running
-    container.delete(true)?;
-    // return result
-    foreground_result
+/// Starts the container and waits for it, returning the exit status of the
+/// init process as the status of the supervisor
+fn supervise(
+    args: Run,
+    root_path: PathBuf,
+    cgroup_manager: CgroupManagerType,
+    result: OwnedFd,
+) -> i32 {
+    let mut result = File::from(result);
+    let mut container = match start_container(&args, root_path, cgroup_manager) {
+        Ok(container) => container,
+        Err(err) => {
+            tracing::error!(?err, "failed to start detached container");
+            let _ = write!(result, "{}{err:#}", FAILED as char);
+            return 1;
+        }
+    };
+    let _ = result.write_all(&[STARTED]);
+    drop(result);
+    // the container init process keeps the stdio of the caller
+    detach_stdio();
+
+    match container.wait() {
+        Ok(exit) => {
+            tracing::debug!(id = ?container.id(), status = exit.status(), "detached container exited");
+            exit.status()
+        }
+        Err(err) => {
+            tracing::error!(id = ?container.id(), ?err, "failed to wait for detached container");
+            1
+        }
+    }
 }
 
 // handle_foreground will match the `runc` behavior running the foreground mode.
 // The youki main process will wait and reap the container init process. The
 // youki main process also forwards most of the signals to the container init
-// process.
+// process. The signals and the exit of the init process are waited for in a
+// single poll loop, through a signalfd and a pidfd of the init process.
 #[tracing::instrument(level = "trace")]
-fn handle_foreground(init_pid: Pid) -> Result<i32> {
+fn handle_foreground(init_pid: Pid) -> Result<ExitInfo> {
     tracing::trace!("waiting for container init process to exit");
     // We mask all signals here and forward most of the signals to the container
     // init process.
@@ -59,11 +207,41 @@ fn handle_foreground(init_pid: Pid) -> Result<i32> {
     signal_set
         .thread_block()
         .with_context(|| "failed to call pthread_sigmask")?;
+    let mut signal_fd = SignalFd::with_flags(&signal_set, SfdFlags::SFD_CLOEXEC)
+        .context("failed to create signalfd")?;
+    // without pidfds, which require Linux 5.3, the exit of the init process is
+    // only noticed through SIGCHLD
+    let init = PidFd::open(init_pid).context("failed to open pidfd of container init process")?;
+
     loop {
-        match signal_set
-            .wait()
-            .with_context(|| "failed to call sigwait")?
-        {
+        let (signal_ready, init_exited) = {
+            let mut fds = vec![PollFd::new(signal_fd.as_fd(), PollFlags::POLLIN)];
+            if let Some(init) = &init {
+                fds.push(PollFd::new(init.as_fd(), PollFlags::POLLIN));
+            }
+            match poll(&mut fds, PollTimeout::NONE) {
+                Ok(_) => {}
+                Err(Errno::EINTR) => continue,
+                Err(err) => return Err(err).context("failed to poll"),
+            }
+            let ready = |fd: &PollFd| fd.any().unwrap_or_default();
+            (ready(&fds[0]), fds.get(1).map_or(false, ready))
+        };
+
+        if init_exited {
+            if let Some(exit) = ExitInfo::from_wait_status(waitpid(init_pid, None)?) {
+                return Ok(exit);
+            }
+        }
+        if !signal_ready {
+            continue;
+        }
+
+        let signal = match signal_fd.read_signal()? {
+            Some(info) => Signal::try_from(info.ssi_signo as i32)?,
+            None => continue,
+        };
+        match signal {
             signal::SIGCHLD => {
                 // Reap all child until either container init process exits or
                 // no more child to be reaped. Once the container init process
@@ -71,24 +249,16 @@ fn handle_foreground(init_pid: Pid) -> Result<i32> {
                 tracing::trace!("reaping child processes");
                 loop {
                     match waitpid(None, Some(WaitPidFlag::WNOHANG))? {
-                        WaitStatus::Exited(pid, status) => {
-                            if pid.eq(&init_pid) {
-                                return Ok(status);
-                            }
-
-                            // Else, some random child process exited, ignoring...
-                        }
-                        WaitStatus::Signaled(pid, signal, _) => {
-                            if pid.eq(&init_pid) {
-                                return Ok(signal as i32);
-                            }
-
-                            // Else, some random child process exited, ignoring...
-                        }
                         WaitStatus::StillAlive => {
                             // No more child to reap.
                             break;
                         }
+                        status if status.pid() == Some(init_pid) => {
+                            if let Some(exit) = ExitInfo::from_wait_status(status) {
+                                return Ok(exit);
+                            }
+                        }
+                        // Else, some random child process exited, ignoring...
                         _ => {}
                     }
                 }
@@ -102,8 +272,14 @@ fn handle_foreground(init_pid: Pid) -> Result<i32> {
             }
             signal => {
                 tracing::trace!(?signal, "forwarding signal");
+                // The pidfd makes sure that the signal never reaches another
+                // process that reused the pid of an exited init process.
+                let result = match &init {
+                    Some(init) => init.send_signal(signal),
+                    None => kill(init_pid, Some(signal)),
+                };
                 // There is nothing we can do if we fail to forward the signal.
-                let _ = kill(init_pid, Some(signal)).map_err(|err| {
+                let _ = result.map_err(|err| {
                     tracing::warn!(
                         ?err,
                         ?signal,
@@ -2,14 +2,28 @@ use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use libcgroups::topology::Topology;
+use libcontainer::oci_spec::image::{ImageConfiguration, ANNOTATION_CREATED};
 use libcontainer::oci_spec::runtime::{
-    LinuxBuilder, LinuxIdMappingBuilder, LinuxNamespace, LinuxNamespaceBuilder, LinuxNamespaceType,
-    Mount, Spec,
+    LinuxIdMappingBuilder, LinuxNamespace, LinuxNamespaceBuilder, LinuxNamespaceType,
+    LinuxResources, Mount, MountBuilder, Spec,
 };
+use libcontainer::rootless;
 use nix;
 use serde_json::to_writer_pretty;
 
+// annotations which the image spec derives from the image configuration, see
+// https://github.com/opencontainers/image-spec/blob/main/conversion.md
+const ANNOTATION_OS: &str = "org.opencontainers.image.os";
+const ANNOTATION_ARCHITECTURE: &str = "org.opencontainers.image.architecture";
+const ANNOTATION_VARIANT: &str = "org.opencontainers.image.variant";
+const ANNOTATION_OS_VERSION: &str = "org.opencontainers.image.os.version";
+const ANNOTATION_OS_FEATURES: &str = "org.opencontainers.image.os.features";
+const ANNOTATION_AUTHOR: &str = "org.opencontainers.image.author";
+const ANNOTATION_STOP_SIGNAL: &str = "org.opencontainers.image.stopSignal";
+const ANNOTATION_EXPOSED_PORTS: &str = "org.opencontainers.image.exposedPorts";
+
 pub fn get_default() -> Result<Spec> {
     Ok(Spec::default())
 }
@@ -34,22 +48,25 @@ pub fn get_rootless() -> Result<Spec> {
     let uid = nix::unistd::geteuid().as_raw();
     let gid = nix::unistd::getegid().as_raw();
 
-    let linux = LinuxBuilder::default()
-        .namespaces(namespaces)
-        .uid_mappings(vec![LinuxIdMappingBuilder::default()
+    // Keep the masked and readonly paths of the default spec
+    let mut spec = get_default()?;
+    let mut linux = spec.linux().clone().unwrap_or_default();
+    let resources = linux.resources().as_ref().and_then(rootless_resources);
+    linux
+        .set_namespaces(Some(namespaces))
+        .set_uid_mappings(Some(vec![LinuxIdMappingBuilder::default()
             .host_id(uid)
             .container_id(0_u32)
             .size(1_u32)
-            .build()?])
-        .gid_mappings(vec![LinuxIdMappingBuilder::default()
+            .build()?]))
+        .set_gid_mappings(Some(vec![LinuxIdMappingBuilder::default()
             .host_id(gid)
             .container_id(0_u32)
             .size(1_u32)
-            .build()?])
-        .build()?;
+            .build()?]))
+        .set_resources(resources);
 
     // Prepare the mounts
-
     let mut mounts: Vec<Mount> = libcontainer::oci_spec::runtime::get_default_mounts();
     for mount in &mut mounts {
         if mount.destination().eq(Path::new("/sys")) {
@@ -76,18 +93,174 @@ pub fn get_rootless() -> Result<Spec> {
         }
     }
 
-    let mut spec = get_default()?;
     spec.set_linux(Some(linux)).set_mounts(Some(mounts));
     Ok(spec)
 }
 
+/// Only the resources of the cgroup v2 controllers which have been delegated
+/// to the user, e.g. by systemd, can be applied by a rootless container
+fn rootless_resources(resources: &LinuxResources) -> Option<LinuxResources> {
+    prune_resources(resources, &rootless::delegated_controllers())
+}
+
+/// Keeps the resources whose controller is delegated. Devices are always
+/// removed, as loading the device filter requires privileges.
+fn prune_resources(resources: &LinuxResources, controllers: &[String]) -> Option<LinuxResources> {
+    let mut pruned = resources.clone();
+    pruned.set_devices(None);
+    for controller in rootless::undelegated_controllers(resources, controllers) {
+        rootless::remove_resources(&mut pruned, &controller);
+    }
+
+    Some(pruned).filter(|pruned| *pruned != LinuxResources::default())
+}
+
+/// Applies an image configuration to the spec as described by the conversion
+/// of the image spec. The user is resolved by name when the container is
+/// created, the volumes of the image are backed by tmpfs mounts.
+pub fn apply_image_config(spec: &mut Spec, image: &ImageConfiguration) -> Result<()> {
+    let config = image.config().clone().unwrap_or_default();
+
+    // the annotations derived from the image take precedence over its labels
+    let mut annotations = spec.annotations().clone().unwrap_or_default();
+    annotations.extend(config.labels().clone().unwrap_or_default());
+    annotations.insert(ANNOTATION_OS.to_owned(), image.os().to_string());
+    annotations.insert(
+        ANNOTATION_ARCHITECTURE.to_owned(),
+        image.architecture().to_string(),
+    );
+    let exposed_ports = config.exposed_ports().clone().map(|mut ports| {
+        ports.sort();
+        ports.join(",")
+    });
+    for (key, value) in [
+        (ANNOTATION_VARIANT, image.variant().clone()),
+        (ANNOTATION_OS_VERSION, image.os_version().clone()),
+        (
+            ANNOTATION_OS_FEATURES,
+            image
+                .os_features()
+                .as_ref()
+                .map(|features| features.join(",")),
+        ),
+        (ANNOTATION_AUTHOR, image.author().clone()),
+        (ANNOTATION_CREATED, image.created().clone()),
+        (ANNOTATION_STOP_SIGNAL, config.stop_signal().clone()),
+        (ANNOTATION_EXPOSED_PORTS, exposed_ports),
+    ] {
+        if let Some(value) = value {
+            annotations.insert(key.to_owned(), value);
+        }
+    }
+
+    let mut process = spec.process().clone().unwrap_or_default();
+    let args: Vec<String> = config
+        .entrypoint()
+        .iter()
+        .chain(config.cmd())
+        .flatten()
+        .cloned()
+        .collect();
+    if !args.is_empty() {
+        process.set_args(Some(args));
+    }
+    process.set_env(Some(merge_env(
+        process.env().as_deref().unwrap_or_default(),
+        config.env().as_deref().unwrap_or_default(),
+    )));
+    if let Some(cwd) = config.working_dir().as_ref().filter(|cwd| !cwd.is_empty()) {
+        process.set_cwd(PathBuf::from(cwd));
+    }
+    if let Some(username) = config.user().as_ref().filter(|user| !user.is_empty()) {
+        let mut user = process.user().clone();
+        user.set_username(Some(username.clone()));
+        process.set_user(user);
+    }
+
+    let mut mounts = spec.mounts().clone().unwrap_or_default();
+    for volume in config.volumes().iter().flatten() {
+        let destination = PathBuf::from(volume);
+        if mounts.iter().any(|m| m.destination() == &destination) {
+            continue;
+        }
+        mounts.push(
+            MountBuilder::default()
+                .destination(destination)
+                .typ("tmpfs")
+                .source("tmpfs")
+                .options(vec![
+                    "rw".to_owned(),
+                    "nosuid".to_owned(),
+                    "nodev".to_owned(),
+                    "mode=755".to_owned(),
+                ])
+                .build()?,
+        );
+    }
+
+    spec.set_annotations(Some(annotations))
+        .set_process(Some(process))
+        .set_mounts(Some(mounts));
+    Ok(())
+}
+
+/// Variables of the image replace the default ones of the same name
+fn merge_env(defaults: &[String], image: &[String]) -> Vec<String> {
+    let name = |var: &str| var.split_once('=').map_or(var, |(name, _)| name).to_owned();
+    let image_names: Vec<String> = image.iter().map(|var| name(var)).collect();
+    defaults
+        .iter()
+        .filter(|var| !image_names.contains(&name(var)))
+        .chain(image)
+        .cloned()
+        .collect()
+}
+
+/// Sets the cpus and memory nodes of the cpuset to the ones selected from the
+/// topology of the host, so that they are online
+fn apply_cpuset(
+    spec: &mut Spec,
+    topology: &Topology,
+    cpus: Option<usize>,
+    node: Option<usize>,
+) -> Result<()> {
+    let selection = topology
+        .select(cpus, node)
+        .context("failed to select the cpus of the container")?;
+
+    let mut linux = spec.linux().clone().unwrap_or_default();
+    let mut resources = linux.resources().clone().unwrap_or_default();
+    let mut cpu = resources.cpu().clone().unwrap_or_default();
+    cpu.set_cpus(Some(selection.cpus.to_string()));
+    if let Some(mems) = selection.mems {
+        cpu.set_mems(Some(mems.to_string()));
+    }
+    resources.set_cpu(Some(cpu));
+    linux.set_resources(Some(resources));
+    spec.set_linux(Some(linux));
+    Ok(())
+}
+
 /// spec Cli command
 pub fn spec(args: liboci_cli::Spec) -> Result<()> {
-    let spec = if args.rootless {
+    let mut spec = if args.rootless {
         get_rootless()?
     } else {
         get_default()?
     };
+    if let Some(path) = &args.from_image {
+        let image = ImageConfiguration::from_file(path)
+            .with_context(|| format!("failed to load image configuration {path:?}"))?;
+        apply_image_config(&mut spec, &image)?;
+    }
+    if args.cpus.is_some() || args.numa_node.is_some() {
+        apply_cpuset(
+            &mut spec,
+            &Topology::new(),
+            args.cpus.map(usize::from),
+            args.numa_node.map(usize::from),
+        )?;
+    }
 
     // write data to config.json
     let file = File::create("config.json")?;
@@ -116,4 +289,184 @@ mod tests {
         writer.flush()?;
         Ok(())
     }
+
+    #[test]
+    #[serial]
+    fn test_get_rootless() -> Result<()> {
+        let spec = get_rootless()?;
+        let linux = spec.linux().as_ref().unwrap();
+        let namespaces = linux.namespaces().as_ref().unwrap();
+        assert!(namespaces
+            .iter()
+            .any(|ns| ns.typ() == LinuxNamespaceType::User));
+        assert!(!namespaces
+            .iter()
+            .any(|ns| ns.typ() == LinuxNamespaceType::Network));
+        assert_eq!(
+            linux.uid_mappings().as_ref().unwrap()[0].host_id(),
+            nix::unistd::geteuid().as_raw()
+        );
+        assert!(linux.masked_paths().is_some());
+        // the default spec only restricts devices, which rootless containers can't
+        assert!(linux
+            .resources()
+            .as_ref()
+            .map_or(true, |resources| resources.devices().is_none()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_image_config() -> Result<()> {
+        let image: ImageConfiguration = serde_json::from_str(
+            r#"{
+                "architecture": "amd64",
+                "os": "linux",
+                "author": "youki",
+                "config": {
+                    "User": "app:staff",
+                    "ExposedPorts": {"8080/tcp": {}, "443/tcp": {}},
+                    "Env": ["PATH=/app/bin:/usr/bin", "APP=1"],
+                    "Entrypoint": ["/app/bin/server"],
+                    "Cmd": ["--port", "8080"],
+                    "Volumes": {"/data": {}, "/dev/shm": {}},
+                    "WorkingDir": "/app",
+                    "Labels": {"org.opencontainers.image.os": "other", "tier": "web"},
+                    "StopSignal": "SIGINT"
+                },
+                "rootfs": {"type": "layers", "diff_ids": []},
+                "history": []
+            }"#,
+        )?;
+        let mut spec = get_default()?;
+        apply_image_config(&mut spec, &image)?;
+
+        let process = spec.process().as_ref().unwrap();
+        assert_eq!(
+            process.args().as_ref().unwrap(),
+            &["/app/bin/server", "--port", "8080"]
+        );
+        assert_eq!(
+            process.env().as_ref().unwrap(),
+            &["TERM=xterm", "PATH=/app/bin:/usr/bin", "APP=1"]
+        );
+        assert_eq!(process.cwd(), &PathBuf::from("/app"));
+        assert_eq!(process.user().username().as_deref(), Some("app:staff"));
+
+        let annotations = spec.annotations().as_ref().unwrap();
+        assert_eq!(annotations[ANNOTATION_OS], "linux");
+        assert_eq!(annotations[ANNOTATION_ARCHITECTURE], "amd64");
+        assert_eq!(annotations[ANNOTATION_AUTHOR], "youki");
+        assert_eq!(annotations[ANNOTATION_EXPOSED_PORTS], "443/tcp,8080/tcp");
+        assert_eq!(annotations[ANNOTATION_STOP_SIGNAL], "SIGINT");
+        assert_eq!(annotations["tier"], "web");
+
+        // the default mount of /dev/shm is kept
+        let mounts = spec.mounts().as_ref().unwrap();
+        let volumes: Vec<_> = mounts
+            .iter()
+            .filter(|m| {
+                m.destination().starts_with("/data") || m.destination() == Path::new("/dev/shm")
+            })
+            .map(|m| (m.destination().clone(), m.source().clone()))
+            .collect();
+        assert_eq!(
+            volumes,
+            [
+                (PathBuf::from("/dev/shm"), Some(PathBuf::from("shm"))),
+                (PathBuf::from("/data"), Some(PathBuf::from("tmpfs"))),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_cpuset() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        for (path, content) in [
+            ("cpu/online", "0-5"),
+            ("node/online", "0-1"),
+            ("node/node0/cpulist", "0-2"),
+            ("node/node1/cpulist", "3-5"),
+        ] {
+            let path = tmp.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
+        let topology = Topology::with_root(tmp.path());
+
+        let mut spec = get_default()?;
+        apply_cpuset(&mut spec, &topology, Some(2), Some(1))?;
+        let resources = spec.linux().as_ref().unwrap().resources().as_ref().unwrap();
+        let cpu = resources.cpu().as_ref().unwrap();
+        assert_eq!(cpu.cpus().as_deref(), Some("3-4"));
+        assert_eq!(cpu.mems().as_deref(), Some("1"));
+        // the other resources of the default spec are kept
+        assert!(resources.devices().is_some());
+
+        let mut spec = get_default()?;
+        apply_cpuset(&mut spec, &topology, Some(4), None)?;
+        let cpu = spec
+            .linux()
+            .as_ref()
+            .unwrap()
+            .resources()
+            .as_ref()
+            .unwrap()
+            .cpu()
+            .clone()
+            .unwrap();
+        assert_eq!(cpu.cpus().as_deref(), Some("0-3"));
+        assert!(cpu.mems().is_none());
+
+        assert!(apply_cpuset(&mut spec, &topology, Some(4), Some(0)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_resources() -> Result<()> {
+        use libcontainer::oci_spec::runtime::{
+            LinuxCpuBuilder, LinuxDeviceCgroupBuilder, LinuxMemoryBuilder, LinuxPidsBuilder,
+            LinuxResourcesBuilder,
+        };
+
+        let resources = LinuxResourcesBuilder::default()
+            .devices(vec![LinuxDeviceCgroupBuilder::default().build()?])
+            .memory(LinuxMemoryBuilder::default().limit(1024).build()?)
+            .cpu(
+                LinuxCpuBuilder::default()
+                    .shares(512_u64)
+                    .cpus("0-1")
+                    .build()?,
+            )
+            .pids(LinuxPidsBuilder::default().limit(10).build()?)
+            .unified(
+                [
+                    ("memory.high".to_owned(), "1024".to_owned()),
+                    ("io.weight".to_owned(), "100".to_owned()),
+                ]
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>(),
+            )
+            .build()?;
+
+        let pruned = prune_resources(&resources, &["memory".to_owned(), "cpu".to_owned()]).unwrap();
+        assert!(pruned.devices().is_none());
+        assert_eq!(pruned.memory(), resources.memory());
+        assert!(pruned.pids().is_none());
+        let cpu = pruned.cpu().as_ref().unwrap();
+        assert_eq!(cpu.shares(), Some(512));
+        assert!(cpu.cpus().is_none());
+        assert_eq!(
+            pruned
+                .unified()
+                .as_ref()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            vec!["memory.high"]
+        );
+
+        assert!(prune_resources(&resources, &[]).is_none());
+        Ok(())
+    }
 }
@@ -5,11 +5,19 @@ use std::path::PathBuf;
 use anyhow::{Context, Result};
 use liboci_cli::Start;
 
-use crate::commands::load_container;
+use crate::commands::{bind_notify_proxy, load_container, spawn_notify_proxy};
 
 pub fn start(args: Start, root_path: PathBuf) -> Result<()> {
     let mut container = load_container(root_path, &args.container_id)?;
+    // the socket has to be bound before the container can send messages
+    let notify_proxy = bind_notify_proxy(&container)?;
     container
         .start()
-        .with_context(|| format!("failed to start container {}", args.container_id))
+        .with_context(|| format!("failed to start container {}", args.container_id))?;
+
+    if let (Some(proxy), Some(pid)) = (notify_proxy, container.pid()) {
+        spawn_notify_proxy(proxy, pid)?;
+    }
+
+    Ok(())
 }
@@ -1,12 +1,191 @@
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use libcontainer::container::{Container, State as ContainerState};
 use liboci_cli::State;
+use serde::Serialize;
 
-use crate::commands::load_container;
+use crate::commands::{construct_container_root, load_container};
+
+/// State of a container together with what is needed to find its resources,
+/// e.g. when an orchestrator reconstructs the state of a node after a restart
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ExtendedState<'a> {
+    #[serde(flatten)]
+    state: &'a ContainerState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rootfs: Option<PathBuf>,
+    /// Path of the cgroup relative to the root of the cgroup hierarchy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cgroup_path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    systemd_unit: Option<String>,
+}
+
+impl<'a> ExtendedState<'a> {
+    fn new(container: &'a Container) -> Self {
+        let mut extended = Self {
+            state: &container.state,
+            owner: container
+                .creator()
+                .map(|owner| owner.to_string_lossy().into_owned()),
+            rootfs: None,
+            cgroup_path: None,
+            systemd_unit: None,
+        };
+        // the config is missing if the creation has failed early
+        let config = match container.spec() {
+            Ok(config) => config,
+            Err(err) => {
+                tracing::debug!("failed to load config of the container: {}", err);
+                return extended;
+            }
+        };
+
+        extended.rootfs = config.rootfs.map(|rootfs| container.bundle().join(rootfs));
+        extended.cgroup_path = Some(config.cgroup_path);
+        if container.systemd() {
+            extended.set_systemd_unit(container);
+        }
+        extended
+    }
+
+    /// The cgroup path of a container managed by systemd is given as
+    /// slice:prefix:name, which systemd turns into a unit name and a path
+    #[cfg(feature = "systemd")]
+    fn set_systemd_unit(&mut self, container: &Container) {
+        use libcgroups::common::{AnyCgroupManager, DEFAULT_CGROUP_ROOT};
+
+        match container.cgroup_manager() {
+            Ok(AnyCgroupManager::Systemd(manager)) => {
+                let path = manager.full_path();
+                self.cgroup_path = Some(match path.strip_prefix(DEFAULT_CGROUP_ROOT) {
+                    Ok(relative) => PathBuf::from("/").join(relative),
+                    Err(_) => path.to_owned(),
+                });
+                // the unit recorded in the state is part of the state already
+                if self.state.systemd_unit.is_none() {
+                    self.systemd_unit = Some(manager.unit_name().to_owned());
+                }
+            }
+            Ok(_) => {}
+            Err(err) => tracing::debug!("failed to get the systemd unit: {}", err),
+        }
+    }
+
+    #[cfg(not(feature = "systemd"))]
+    fn set_systemd_unit(&mut self, _container: &Container) {}
+}
 
 pub fn state(args: State, root_path: PathBuf) -> Result<()> {
-    let container = load_container(root_path, &args.container_id)?;
-    println!("{}", serde_json::to_string_pretty(&container.state)?);
+    let extended = match args.format.as_str() {
+        "json" => false,
+        "extended" => true,
+        _ => bail!("invalid format {:?}, must be json or extended", args.format),
+    };
+
+    if args.stale_check {
+        let container_root = construct_container_root(&root_path, &args.container_id)?;
+        if container_root.exists() && Container::remove_stale(&container_root)? {
+            bail!(
+                "container {} had orphaned state, which has been removed",
+                args.container_id
+            );
+        }
+    }
+
+    let mut container = load_container(root_path, &args.container_id)?;
+    // the cgroup is gone once the container has been deleted or if it has
+    // never been created, in which case the recorded value is reported
+    if let Err(err) = container.update_oom_killed() {
+        tracing::debug!("failed to check for out of memory kills: {}", err);
+    }
+    if extended {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&ExtendedState::new(&container))?
+        );
+    } else {
+        println!("{}", serde_json::to_string_pretty(&container.state)?);
+    }
     std::process::exit(0);
 }
+
+#[cfg(test)]
+mod tests {
+    use libcontainer::container::ContainerStatus;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    // the keys the extended state adds to the state of a container, which
+    // has no recorded systemd unit
+    const EXTENDED_KEYS: [&str; 4] = ["owner", "rootfs", "cgroupPath", "systemdUnit"];
+
+    fn container(dir: &tempfile::TempDir) -> Result<Container> {
+        let bundle = dir.path().join("bundle");
+        let root = dir.path().join("root");
+        std::fs::create_dir(&bundle)?;
+        std::fs::create_dir(&root)?;
+        Ok(Container::new(
+            "test",
+            ContainerStatus::Running,
+            Some(1234),
+            &bundle,
+            &root,
+        )?)
+    }
+
+    #[test]
+    fn test_extended_state_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let container = container(&dir)?;
+        // the container has no config, so only the state is known
+        let mut extended = ExtendedState::new(&container);
+        assert_eq!(
+            serde_json::to_value(&extended)?,
+            serde_json::to_value(&container.state)?
+        );
+
+        extended.owner = Some("1000".to_owned());
+        extended.rootfs = Some(container.bundle().join("rootfs"));
+        extended.cgroup_path = Some(PathBuf::from("/youki/test"));
+        extended.systemd_unit = Some("youki-test.scope".to_owned());
+        let value = serde_json::to_value(&extended)?;
+        assert_eq!(value["owner"], json!("1000"));
+        assert_eq!(value["rootfs"], json!(container.bundle().join("rootfs")));
+        assert_eq!(value["cgroupPath"], json!("/youki/test"));
+        assert_eq!(value["systemdUnit"], json!("youki-test.scope"));
+
+        // the extended state is the state with additional keys, which are
+        // ignored when reading it as the state
+        let state: ContainerState = serde_json::from_value(value)?;
+        let mut expected = serde_json::to_value(&container.state)?;
+        expected["systemdUnit"] = json!("youki-test.scope");
+        assert_eq!(serde_json::to_value(&state)?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plain_state_is_oci_state() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let container = container(&dir)?;
+        let value = serde_json::to_value(&container.state)?;
+
+        assert_eq!(value["ociVersion"], json!("v1.0.2"));
+        assert_eq!(value["id"], json!("test"));
+        assert_eq!(value["status"], json!("running"));
+        assert_eq!(value["pid"], json!(1234));
+        assert_eq!(value["bundle"], json!(container.bundle()));
+        assert!(value["annotations"].is_object());
+        for key in EXTENDED_KEYS {
+            assert_eq!(value.get(key), None::<&Value>, "{key}");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+//! Prints the dbus properties which the systemd unit of a container would be
+//! given, without creating the container
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use libcontainer::oci_spec::runtime::Spec;
+
+use crate::commands::info;
+
+pub fn print(bundle: &Path, systemd_version: Option<u32>) -> Result<()> {
+    let config = bundle.join("config.json");
+    let spec = Spec::load(&config).with_context(|| format!("failed to load {config:?}"))?;
+    let systemd_version = match systemd_version.or_else(info::systemd_version) {
+        Some(version) => version,
+        None => bail!(
+            "the version of systemd is unknown, pass it with --debug-systemd-properties=<version>"
+        ),
+    };
+
+    print_properties(&spec, systemd_version)
+}
+
+#[cfg(feature = "systemd")]
+fn print_properties(spec: &Spec, systemd_version: u32) -> Result<()> {
+    let default_resources = libcontainer::oci_spec::runtime::LinuxResources::default();
+    let resources = spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.resources().as_ref())
+        .unwrap_or(&default_resources);
+    let controller_opt = libcgroups::common::ControllerOpt {
+        resources,
+        disable_oom_killer: false,
+        oom_score_adj: None,
+        freezer_state: None,
+    };
+    let properties =
+        libcgroups::systemd::manager::unit_properties(&controller_opt, systemd_version)?;
+    println!("{}", serde_json::to_string_pretty(&properties)?);
+
+    Ok(())
+}
+
+#[cfg(not(feature = "systemd"))]
+fn print_properties(_spec: &Spec, _systemd_version: u32) -> Result<()> {
+    bail!("youki has been built without systemd support")
+}
@@ -1,19 +1,25 @@
 use std::path::PathBuf;
 use std::{fs, io};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use libcgroups::checkpoint::CgroupCheckpoint;
 use libcgroups::common::{CgroupManager, ControllerOpt};
 use libcgroups::{self};
-use libcontainer::oci_spec::runtime::{LinuxPidsBuilder, LinuxResources, LinuxResourcesBuilder};
+use libcontainer::cpu_bandwidth::{self, CpuBandwidth};
+use libcontainer::oci_spec::runtime::{
+    LinuxBlockIoBuilder, LinuxCpuBuilder, LinuxMemoryBuilder, LinuxPidsBuilder, LinuxResources,
+    LinuxResourcesBuilder,
+};
 use liboci_cli::Update;
 
-use crate::commands::create_cgroup_manager;
+use crate::commands::load_container;
 
 pub fn update(args: Update, root_path: PathBuf) -> Result<()> {
-    let cmanager = create_cgroup_manager(root_path, &args.container_id)?;
+    let mut container = load_container(root_path, &args.container_id)?;
+    let cmanager = container.cgroup_manager()?;
 
-    let linux_res: LinuxResources;
-    if let Some(resources_path) = args.resources {
+    let mut linux_res: LinuxResources;
+    if let Some(resources_path) = &args.resources {
         linux_res = if resources_path.to_string_lossy() == "-" {
             serde_json::from_reader(io::stdin())?
         } else {
@@ -22,18 +28,229 @@ pub fn update(args: Update, root_path: PathBuf) -> Result<()> {
             serde_json::from_reader(reader)?
         };
     } else {
-        let mut builder = LinuxResourcesBuilder::default();
-        if let Some(new_pids_limit) = args.pids_limit {
-            builder = builder.pids(LinuxPidsBuilder::default().limit(new_pids_limit).build()?);
-        }
-        linux_res = builder.build()?;
+        linux_res = resources_from_args(&args)?;
+    }
+    let round = cpu_bandwidth::round_requested(container.state.annotations.as_ref());
+    let bandwidth_update = check_cpu_bandwidth(&mut linux_res, round)?;
+
+    // the update becomes part of the cgroup configuration, which is
+    // re-applied e.g. on restore
+    let (result, update) = CgroupCheckpoint::record(|| {
+        cmanager.apply(&ControllerOpt {
+            resources: &linux_res,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        })
+    });
+    result?;
+    container.record_cgroup_update(update)?;
+    if bandwidth_update.is_some() {
+        let bandwidth = CpuBandwidth::updated(container.cpu_bandwidth(), bandwidth_update);
+        container.set_cpu_bandwidth(bandwidth).save()?;
     }
 
-    cmanager.apply(&ControllerOpt {
-        resources: &linux_res,
-        disable_oom_killer: false,
-        oom_score_adj: None,
-        freezer_state: None,
-    })?;
+    if let Some(bytes) = args.memory_reclaim {
+        let reclaimed = cmanager
+            .reclaim_memory(bytes)
+            .with_context(|| format!("failed to reclaim {bytes} bytes of memory"))?;
+        if !reclaimed {
+            bail!("memory reclaim requires cgroup v2 and Linux 5.19 or newer");
+        }
+    }
     Ok(())
 }
+
+/// Rejects a cpu period or quota out of the kernel bounds, or rounds it into
+/// them if the container has been created with the
+/// [`ROUND_ANNOTATION`](cpu_bandwidth::ROUND_ANNOTATION). Returns the updated
+/// bandwidth.
+fn check_cpu_bandwidth(
+    resources: &mut LinuxResources,
+    round: bool,
+) -> Result<Option<CpuBandwidth>> {
+    let Some(cpu) = resources.cpu_mut().as_mut() else {
+        return Ok(None);
+    };
+
+    if round {
+        cpu_bandwidth::round(cpu);
+    }
+    let invalid = cpu_bandwidth::check(cpu);
+    if !invalid.is_empty() {
+        let report: Vec<_> = invalid
+            .iter()
+            .map(|(field, reason)| format!("cpu {field}: {reason}"))
+            .collect();
+        bail!("{}", report.join(", "));
+    }
+
+    Ok(CpuBandwidth::of(cpu))
+}
+
+/// Translates the individual resource flags into the resources section of
+/// the runtime spec. Only the resources that were set are included, so that
+/// the cgroup managers leave everything else untouched.
+fn resources_from_args(args: &Update) -> Result<LinuxResources> {
+    if args.l3_cache_schema.is_some() || args.mem_bw_schema.is_some() {
+        bail!("updating Intel RDT schemas of a running container is not supported");
+    }
+
+    let mut builder = LinuxResourcesBuilder::default();
+
+    if let Some(weight) = args.blkio_weight {
+        let weight: u16 = weight
+            .try_into()
+            .with_context(|| format!("invalid blkio weight {weight}"))?;
+        builder = builder.block_io(LinuxBlockIoBuilder::default().weight(weight).build()?);
+    }
+
+    let mut cpu = LinuxCpuBuilder::default();
+    let mut has_cpu = false;
+    if let Some(period) = args.cpu_period {
+        cpu = cpu.period(period);
+        has_cpu = true;
+    }
+    if let Some(quota) = args.cpu_quota {
+        let quota: i64 = quota
+            .try_into()
+            .with_context(|| format!("invalid cpu quota {quota}"))?;
+        cpu = cpu.quota(quota);
+        has_cpu = true;
+    }
+    if let Some(rt_period) = args.cpu_rt_period {
+        cpu = cpu.realtime_period(rt_period);
+        has_cpu = true;
+    }
+    if let Some(rt_runtime) = args.cpu_rt_runtime {
+        let rt_runtime: i64 = rt_runtime
+            .try_into()
+            .with_context(|| format!("invalid cpu realtime runtime {rt_runtime}"))?;
+        cpu = cpu.realtime_runtime(rt_runtime);
+        has_cpu = true;
+    }
+    if let Some(shares) = args.cpu_share {
+        cpu = cpu.shares(shares);
+        has_cpu = true;
+    }
+    if let Some(cpus) = &args.cpuset_cpus {
+        cpu = cpu.cpus(cpus.clone());
+        has_cpu = true;
+    }
+    if let Some(mems) = &args.cpuset_mems {
+        cpu = cpu.mems(mems.clone());
+        has_cpu = true;
+    }
+    if has_cpu {
+        builder = builder.cpu(cpu.build()?);
+    }
+
+    let mut memory = LinuxMemoryBuilder::default();
+    let mut has_memory = false;
+    if let Some(limit) = args.memory {
+        let limit: i64 = limit
+            .try_into()
+            .with_context(|| format!("invalid memory limit {limit}"))?;
+        memory = memory.limit(limit);
+        has_memory = true;
+    }
+    if let Some(reservation) = args.memory_reservation {
+        let reservation: i64 = reservation
+            .try_into()
+            .with_context(|| format!("invalid memory reservation {reservation}"))?;
+        memory = memory.reservation(reservation);
+        has_memory = true;
+    }
+    if let Some(swap) = args.memory_swap {
+        memory = memory.swap(swap);
+        has_memory = true;
+    }
+    if has_memory {
+        builder = builder.memory(memory.build()?);
+    }
+
+    if let Some(new_pids_limit) = args.pids_limit {
+        builder = builder.pids(LinuxPidsBuilder::default().limit(new_pids_limit).build()?);
+    }
+
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Update {
+        Update::parse_from(["update"].iter().chain(args).chain(&["container"]))
+    }
+
+    #[test]
+    fn test_resources_from_args_empty() -> Result<()> {
+        let resources = resources_from_args(&parse(&[]))?;
+        assert!(resources.cpu().is_none());
+        assert!(resources.memory().is_none());
+        assert!(resources.pids().is_none());
+        assert!(resources.block_io().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resources_from_args() -> Result<()> {
+        let resources = resources_from_args(&parse(&[
+            "--cpu-quota",
+            "50000",
+            "--cpu-period",
+            "100000",
+            "--cpuset-cpus",
+            "0-3",
+            "--memory",
+            "1048576",
+            "--memory-swap",
+            "-1",
+            "--pids-limit",
+            "10",
+            "--blkio-weight",
+            "500",
+        ]))?;
+
+        let cpu = resources.cpu().as_ref().unwrap();
+        assert_eq!(cpu.quota(), Some(50000));
+        assert_eq!(cpu.period(), Some(100000));
+        assert_eq!(cpu.cpus(), &Some("0-3".to_owned()));
+        assert_eq!(cpu.shares(), None);
+
+        let memory = resources.memory().as_ref().unwrap();
+        assert_eq!(memory.limit(), Some(1048576));
+        assert_eq!(memory.swap(), Some(-1));
+        assert_eq!(memory.reservation(), None);
+
+        assert_eq!(resources.pids().as_ref().unwrap().limit(), 10);
+        assert_eq!(resources.block_io().as_ref().unwrap().weight(), Some(500));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resources_from_args_invalid() {
+        assert!(resources_from_args(&parse(&["--blkio-weight", "70000"])).is_err());
+        assert!(resources_from_args(&parse(&["--l3-cache-schema", "L3:0=f"])).is_err());
+    }
+
+    #[test]
+    fn test_check_cpu_bandwidth() -> Result<()> {
+        let mut resources = resources_from_args(&parse(&["--cpu-quota", "100"]))?;
+        assert!(check_cpu_bandwidth(&mut resources.clone(), false).is_err());
+        assert_eq!(
+            check_cpu_bandwidth(&mut resources, true)?,
+            Some(CpuBandwidth {
+                quota: Some(cpu_bandwidth::MIN_QUOTA),
+                period: None,
+            })
+        );
+
+        let mut resources = resources_from_args(&parse(&["--memory", "1048576"]))?;
+        assert_eq!(check_cpu_bandwidth(&mut resources, false)?, None);
+        Ok(())
+    }
+}
@@ -6,8 +6,15 @@ mod observability;
 mod rootpath;
 mod workload;
 
-use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
 use clap::{crate_version, CommandFactory, Parser};
+use libcgroups::common::CgroupManagerType;
+use libcontainer::container::State;
+use libcontainer::error::{ErrorKind, LibcontainerError};
+use libcontainer::oci_spec::runtime::Spec;
 use liboci_cli::{CommonCmd, GlobalOpts, StandardCmd};
 
 use crate::commands::info;
@@ -21,6 +28,17 @@ struct YoukiExtendOpts {
     /// set the log level (default is 'error')
     #[clap(long)]
     pub log_level: Option<String>,
+    /// Rotate the log file once it grows beyond this many bytes
+    #[clap(long)]
+    pub log_max_size: Option<u64>,
+    /// Number of rotated log files which are kept
+    #[clap(long, default_value = "1")]
+    pub log_max_files: usize,
+    /// Print the dbus properties the systemd unit of the container would be given by create or
+    /// run as JSON instead of creating the container, for the version of the running systemd
+    /// unless one is given
+    #[clap(long, value_name = "SYSTEMD_VERSION", num_args = 0..=1, require_equals = true)]
+    pub debug_systemd_properties: Option<Option<u32>>,
 }
 
 /// output Youki version in Moby compatible format
@@ -69,6 +87,102 @@ enum SubCommand {
     // Youki specific extensions
     Info(info::Info),
     Completion(commands::completion::Completion),
+    Metrics(commands::metrics::Metrics),
+    Daemon(commands::daemon::Daemon),
+    Gc(commands::gc::Gc),
+}
+
+impl SubCommand {
+    /// Name of the subcommand, as given on the commandline
+    fn operation(&self) -> &'static str {
+        match self {
+            SubCommand::Standard(cmd) => match cmd.as_ref() {
+                StandardCmd::Create(_) => "create",
+                StandardCmd::Start(_) => "start",
+                StandardCmd::State(_) => "state",
+                StandardCmd::Kill(_) => "kill",
+                StandardCmd::Delete(_) => "delete",
+            },
+            SubCommand::Common(cmd) => match cmd.as_ref() {
+                CommonCmd::Checkpoint(_) => "checkpoint",
+                CommonCmd::Events(_) => "events",
+                CommonCmd::Exec(_) => "exec",
+                CommonCmd::Features(_) => "features",
+                CommonCmd::List(_) => "list",
+                CommonCmd::Pause(_) => "pause",
+                CommonCmd::Ps(_) => "ps",
+                CommonCmd::Restore(_) => "restore",
+                CommonCmd::Resume(_) => "resume",
+                CommonCmd::Run(_) => "run",
+                CommonCmd::Update(_) => "update",
+                CommonCmd::Spec(_) => "spec",
+            },
+            SubCommand::Info(_) => "info",
+            SubCommand::Completion(_) => "completion",
+            SubCommand::Metrics(_) => "metrics",
+            SubCommand::Daemon(_) => "daemon",
+            SubCommand::Gc(_) => "gc",
+        }
+    }
+
+    /// Id of the container the subcommand operates on, if any
+    fn container_id(&self) -> Option<&str> {
+        let id = match self {
+            SubCommand::Standard(cmd) => match cmd.as_ref() {
+                StandardCmd::Create(create) => &create.container_id,
+                StandardCmd::Start(start) => &start.container_id,
+                StandardCmd::State(state) => &state.container_id,
+                StandardCmd::Kill(kill) => &kill.container_id,
+                StandardCmd::Delete(delete) => &delete.container_id,
+            },
+            SubCommand::Common(cmd) => match cmd.as_ref() {
+                CommonCmd::Checkpoint(checkpoint) => &checkpoint.container_id,
+                CommonCmd::Events(events) => &events.container_id,
+                CommonCmd::Exec(exec) => &exec.container_id,
+                CommonCmd::Pause(pause) => &pause.container_id,
+                CommonCmd::Ps(ps) => &ps.container_id,
+                CommonCmd::Restore(restore) => &restore.container_id,
+                CommonCmd::Resume(resume) => &resume.container_id,
+                CommonCmd::Run(run) => &run.container_id,
+                CommonCmd::Update(update) => &update.container_id,
+                CommonCmd::Features(_) | CommonCmd::List(_) | CommonCmd::Spec(_) => return None,
+            },
+            SubCommand::Info(_)
+            | SubCommand::Completion(_)
+            | SubCommand::Metrics(_)
+            | SubCommand::Daemon(_)
+            | SubCommand::Gc(_) => return None,
+        };
+        Some(id)
+    }
+
+    /// Annotations of the container the subcommand operates on, taken from the
+    /// bundle by create and run and from the state of the container otherwise.
+    /// They are empty if the container or its bundle can't be loaded, which is
+    /// left to the subcommand to report.
+    fn annotations(&self, root_path: &Path) -> HashMap<String, String> {
+        let bundle = match self {
+            SubCommand::Standard(cmd) => match cmd.as_ref() {
+                StandardCmd::Create(create) => Some(&create.bundle),
+                _ => None,
+            },
+            SubCommand::Common(cmd) => match cmd.as_ref() {
+                CommonCmd::Run(run) => Some(&run.bundle),
+                _ => None,
+            },
+            _ => None,
+        };
+        let annotations = match (bundle, self.container_id()) {
+            (Some(bundle), _) => Spec::load(bundle.join("config.json"))
+                .ok()
+                .and_then(|spec| spec.annotations().clone()),
+            (None, Some(id)) => State::load(&root_path.join(id))
+                .ok()
+                .and_then(|state| state.annotations),
+            (None, None) => None,
+        };
+        annotations.unwrap_or_default()
+    }
 }
 
 /// This is the entry point in the container runtime. The binary is run by a high-level container runtime,
@@ -89,7 +203,12 @@ fn main() -> Result<()> {
     let opts = Opts::parse();
     let mut app = Opts::command();
 
-    observability::init(&opts).map_err(|err| {
+    // the root path is needed to find the annotations of the container, which
+    // can override the logging
+    let root_path = rootpath::determine(opts.global.root.clone())?;
+    let mut observability_config = observability::ObservabilityConfig::from(&opts);
+    observability_config.annotations = opts.subcmd.annotations(&root_path);
+    observability::init(observability_config).map_err(|err| {
         eprintln!("failed to initialize observability: {}", err);
         err
     })?;
@@ -99,13 +218,34 @@ fn main() -> Result<()> {
         nix::unistd::geteuid(),
         std::env::args_os()
     );
-    let root_path = rootpath::determine(opts.global.root)?;
-    let systemd_cgroup = opts.global.systemd_cgroup;
+    let cgroup_manager = match opts.global.cgroup_manager.as_deref() {
+        Some(cgroup_manager) => cgroup_manager.parse()?,
+        None if opts.global.systemd_cgroup => CgroupManagerType::Systemd,
+        None => CgroupManagerType::Cgroupfs,
+    };
+
+    if let Some(systemd_version) = opts.youki_extend.debug_systemd_properties {
+        let bundle = match &opts.subcmd {
+            SubCommand::Standard(cmd) => match cmd.as_ref() {
+                StandardCmd::Create(create) => Some(&create.bundle),
+                _ => None,
+            },
+            SubCommand::Common(cmd) => match cmd.as_ref() {
+                CommonCmd::Run(run) => Some(&run.bundle),
+                _ => None,
+            },
+            _ => None,
+        };
+        return match bundle {
+            Some(bundle) => commands::systemd_properties::print(bundle, systemd_version),
+            None => bail!("--debug-systemd-properties is only supported by create and run"),
+        };
+    }
 
     let cmd_result = match opts.subcmd {
         SubCommand::Standard(cmd) => match *cmd {
             StandardCmd::Create(create) => {
-                commands::create::create(create, root_path, systemd_cgroup)
+                commands::create::create(create, root_path, cgroup_manager)
             }
             StandardCmd::Start(start) => commands::start::start(start, root_path),
             StandardCmd::Kill(kill) => commands::kill::kill(kill, root_path),
@@ -113,29 +253,45 @@ fn main() -> Result<()> {
             StandardCmd::State(state) => commands::state::state(state, root_path),
         },
         SubCommand::Common(cmd) => match *cmd {
-            CommonCmd::Checkpointt(checkpoint) => {
+            CommonCmd::Checkpoint(checkpoint) => {
                 commands::checkpoint::checkpoint(checkpoint, root_path)
             }
             CommonCmd::Events(events) => commands::events::events(events, root_path),
             CommonCmd::Exec(exec) => match commands::exec::exec(exec, root_path) {
                 Ok(exit_code) => std::process::exit(exit_code),
                 Err(e) => {
-                    tracing::error!("error in executing command: {:?}", e);
+                    let kind = error_kind(&e);
+                    tracing::error!(
+                        error_kind = kind.as_str(),
+                        "error in executing command: {:?}",
+                        e
+                    );
                     eprintln!("exec failed : {e}");
-                    std::process::exit(-1);
+                    std::process::exit(exit_code(kind));
                 }
             },
             CommonCmd::Features(features) => commands::features::features(features),
             CommonCmd::List(list) => commands::list::list(list, root_path),
             CommonCmd::Pause(pause) => commands::pause::pause(pause, root_path),
             CommonCmd::Ps(ps) => commands::ps::ps(ps, root_path),
+            CommonCmd::Restore(restore) => {
+                match commands::restore::restore(restore, root_path, cgroup_manager) {
+                    Ok(exit_code) => std::process::exit(exit_code),
+                    Err(e) => Err(e),
+                }
+            }
             CommonCmd::Resume(resume) => commands::resume::resume(resume, root_path),
-            CommonCmd::Run(run) => match commands::run::run(run, root_path, systemd_cgroup) {
+            CommonCmd::Run(run) => match commands::run::run(run, root_path, cgroup_manager) {
                 Ok(exit_code) => std::process::exit(exit_code),
                 Err(e) => {
-                    tracing::error!("error in executing command: {:?}", e);
+                    let kind = error_kind(&e);
+                    tracing::error!(
+                        error_kind = kind.as_str(),
+                        "error in executing command: {:?}",
+                        e
+                    );
                     eprintln!("run failed : {e}");
-                    std::process::exit(-1);
+                    std::process::exit(exit_code(kind));
                 }
             },
             CommonCmd::Spec(spec) => commands::spec_json::spec(spec),
@@ -146,11 +302,57 @@ fn main() -> Result<()> {
         SubCommand::Completion(completion) => {
             commands::completion::completion(completion, &mut app)
         }
+        SubCommand::Metrics(metrics) => commands::metrics::metrics(metrics, root_path),
+        SubCommand::Daemon(daemon) => commands::daemon::daemon(daemon, root_path, cgroup_manager),
+        SubCommand::Gc(gc) => commands::gc::gc(gc, root_path),
     };
 
     if let Err(ref e) = cmd_result {
-        tracing::error!("error in executing command: {:?}", e);
+        let kind = error_kind(e);
+        tracing::error!(
+            error_kind = kind.as_str(),
+            "error in executing command: {:?}",
+            e
+        );
         eprintln!("error in executing command: {:?}", e);
+        std::process::exit(exit_code(kind));
     }
     cmd_result
 }
+
+/// Returns the kind of the first error of libcontainer in the chain of the
+/// error, so that the same failure always gets the same class in the logs
+fn error_kind(err: &anyhow::Error) -> ErrorKind {
+    err.chain()
+        .find_map(|e| e.downcast_ref::<LibcontainerError>())
+        .map_or(ErrorKind::Other, LibcontainerError::kind)
+}
+
+/// Exit code of youki for the kind of error
+fn exit_code(kind: ErrorKind) -> i32 {
+    match kind {
+        ErrorKind::Other => 1,
+        ErrorKind::InvalidInput => 2,
+        ErrorKind::SpecInvalid => 3,
+        ErrorKind::ContainerExists => 4,
+        ErrorKind::ContainerNotFound => 5,
+        ErrorKind::IncorrectStatus => 6,
+        ErrorKind::CgroupControllerNotFound => 7,
+        ErrorKind::SystemdVersionTooOld => 8,
+        ErrorKind::Cgroup => 9,
+        ErrorKind::Hook => 10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kind() {
+        let err = anyhow::Error::from(LibcontainerError::NoDirectory).context("failed to load");
+        assert_eq!(error_kind(&err), ErrorKind::ContainerNotFound);
+        assert_eq!(exit_code(error_kind(&err)), 5);
+        assert_eq!(error_kind(&anyhow::anyhow!("other")), ErrorKind::Other);
+    }
+}
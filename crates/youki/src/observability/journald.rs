@@ -0,0 +1,262 @@
+//! Logging to systemd-journald with its native protocol, so that the records
+//! of youki carry structured fields, e.g. the id of the container, which can
+//! be matched with `journalctl CONTAINER_ID=<id>` next to the logs of the
+//! units of the host.
+//!
+//! Each record is a datagram of `KEY=value` lines. Values with newlines are
+//! sent as the key, the length as little endian u64, and the value. Records
+//! too large for a datagram are passed as a sealed memfd instead. See
+//! https://systemd.io/JOURNAL_NATIVE_PROTOCOL/
+use std::fmt::{self, Write as _};
+use std::fs::File;
+use std::io::{self, IoSlice, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+
+use nix::fcntl::{fcntl, FcntlArg, SealFlag};
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use nix::sys::socket::{sendmsg, ControlMessage, MsgFlags, UnixAddr};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_IDENTIFIER: &str = "youki";
+
+/// Layer which sends every event as a record to journald
+pub struct JournaldLayer {
+    socket: UnixDatagram,
+    container_id: Option<String>,
+    operation: Option<String>,
+}
+
+impl JournaldLayer {
+    /// Connects to journald, which fails if it isn't running, e.g. within a
+    /// container. The container id and the operation are added to every
+    /// record.
+    pub fn connect(container_id: Option<String>, operation: Option<String>) -> io::Result<Self> {
+        Self::connect_to(Path::new(JOURNALD_SOCKET), container_id, operation)
+    }
+
+    fn connect_to(
+        path: &Path,
+        container_id: Option<String>,
+        operation: Option<String>,
+    ) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self {
+            socket,
+            container_id,
+            operation,
+        })
+    }
+
+    fn record(&self, event: &Event<'_>) -> Vec<u8> {
+        let metadata = event.metadata();
+        let mut record = Vec::new();
+        put_field(
+            &mut record,
+            "PRIORITY",
+            priority(metadata.level()).as_bytes(),
+        );
+        put_field(
+            &mut record,
+            "SYSLOG_IDENTIFIER",
+            SYSLOG_IDENTIFIER.as_bytes(),
+        );
+        put_field(
+            &mut record,
+            "SYSLOG_PID",
+            std::process::id().to_string().as_bytes(),
+        );
+        if let Some(container_id) = &self.container_id {
+            put_field(&mut record, "CONTAINER_ID", container_id.as_bytes());
+        }
+        if let Some(operation) = &self.operation {
+            put_field(&mut record, "YOUKI_OPERATION", operation.as_bytes());
+        }
+        put_field(&mut record, "TARGET", metadata.target().as_bytes());
+        if let Some(file) = metadata.file() {
+            put_field(&mut record, "CODE_FILE", file.as_bytes());
+        }
+        if let Some(line) = metadata.line() {
+            put_field(&mut record, "CODE_LINE", line.to_string().as_bytes());
+        }
+        event.record(&mut JournaldVisitor(&mut record));
+        record
+    }
+
+    fn send(&self, record: &[u8]) -> io::Result<()> {
+        match self.socket.send(record) {
+            Ok(_) => Ok(()),
+            Err(err)
+                if matches!(
+                    err.raw_os_error(),
+                    Some(nix::libc::EMSGSIZE | nix::libc::ENOBUFS)
+                ) =>
+            {
+                self.send_memfd(record)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Passes the record as a sealed memfd, which journald reads the record
+    /// from, for records which exceed the size of a datagram
+    fn send_memfd(&self, record: &[u8]) -> io::Result<()> {
+        let fd = memfd_create(
+            c"youki-journald",
+            MemFdCreateFlag::MFD_CLOEXEC | MemFdCreateFlag::MFD_ALLOW_SEALING,
+        )?;
+        let mut file = File::from(fd);
+        file.write_all(record)?;
+        fcntl(file.as_raw_fd(), FcntlArg::F_ADD_SEALS(SealFlag::all()))?;
+
+        let fds = [file.as_raw_fd()];
+        sendmsg::<UnixAddr>(
+            self.socket.as_raw_fd(),
+            &[] as &[IoSlice],
+            &[ControlMessage::ScmRights(&fds)],
+            MsgFlags::empty(),
+            None,
+        )
+        .map_err(io::Error::from)?;
+        Ok(())
+    }
+}
+
+impl<S: Subscriber> Layer<S> for JournaldLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // there is nothing left to log the failure to
+        let _ = self.send(&self.record(event));
+    }
+}
+
+// The syslog priorities, trace and debug are both debug
+fn priority(level: &Level) -> &'static str {
+    match *level {
+        Level::ERROR => "3",
+        Level::WARN => "4",
+        Level::INFO => "6",
+        Level::DEBUG | Level::TRACE => "7",
+    }
+}
+
+/// Journald accepts uppercase letters, digits and underscores in field names,
+/// which must not start with a digit or an underscore, the latter being the
+/// prefix of the trusted fields journald adds on its own
+fn field_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' => c.to_ascii_uppercase(),
+            'A'..='Z' | '0'..='9' => c,
+            _ => '_',
+        })
+        .collect();
+    let name = name.trim_start_matches('_');
+    match name.chars().next() {
+        Some('0'..='9') | None => format!("F_{name}"),
+        _ => name.to_owned(),
+    }
+}
+
+fn put_field(record: &mut Vec<u8>, name: &str, value: &[u8]) {
+    record.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        record.push(b'\n');
+        record.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        record.push(b'=');
+    }
+    record.extend_from_slice(value);
+    record.push(b'\n');
+}
+
+struct JournaldVisitor<'a>(&'a mut Vec<u8>);
+
+impl JournaldVisitor<'_> {
+    fn put(&mut self, field: &Field, value: &str) {
+        let name = match field.name() {
+            "message" => "MESSAGE".to_owned(),
+            name => field_name(name),
+        };
+        put_field(self.0, &name, value.as_bytes());
+    }
+}
+
+impl Visit for JournaldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let mut formatted = String::new();
+        let _ = write!(formatted, "{value:?}");
+        self.put(field, &formatted);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.put(field, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_field_name() {
+        assert_eq!(field_name("error_kind"), "ERROR_KIND");
+        assert_eq!(field_name("cgroup.path"), "CGROUP_PATH");
+        assert_eq!(field_name("_PID"), "PID");
+        assert_eq!(field_name("1st"), "F_1ST");
+    }
+
+    #[test]
+    fn test_put_field() {
+        let mut record = Vec::new();
+        put_field(&mut record, "MESSAGE", b"one line");
+        assert_eq!(record, b"MESSAGE=one line\n");
+
+        let mut record = Vec::new();
+        put_field(&mut record, "MESSAGE", b"two\nlines");
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(b"two\nlines\n");
+        assert_eq!(record, expected);
+    }
+
+    #[test]
+    fn test_record() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("socket");
+        let journald = UnixDatagram::bind(&path)?;
+        let layer =
+            JournaldLayer::connect_to(&path, Some("c1".to_owned()), Some("create".to_owned()))?;
+
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(error_kind = "Cgroup", "failed to apply resources");
+        });
+
+        let mut buf = vec![0; 4096];
+        let len = journald.recv(&mut buf)?;
+        let record = String::from_utf8(buf[..len].to_vec())?;
+        let lines: Vec<_> = record.lines().collect();
+        for expected in [
+            "PRIORITY=4",
+            "SYSLOG_IDENTIFIER=youki",
+            &format!("SYSLOG_PID={}", std::process::id()),
+            "CONTAINER_ID=c1",
+            "YOUKI_OPERATION=create",
+            "MESSAGE=failed to apply resources",
+            "ERROR_KIND=Cgroup",
+        ] {
+            assert!(lines.contains(&expected), "missing {expected} in {record}");
+        }
+        Ok(())
+    }
+}
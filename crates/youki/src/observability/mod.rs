@@ -0,0 +1,853 @@
+mod journald;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use chrono::{SecondsFormat, Utc};
+use libcontainer::log_capture::{self, CaptureWriter};
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, MakeWriter};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+
+use self::journald::JournaldLayer;
+
+const LOG_FORMAT_TEXT: &str = "text";
+const LOG_FORMAT_JSON: &str = "json";
+const LOG_FORMAT_JOURNALD: &str = "journald";
+enum LogFormat {
+    Text,
+    Json,
+    /// Records are only sent to journald, see [`JournaldLayer`]
+    Journald,
+}
+
+/// If in debug mode, default level is debug to get maximum logging
+#[cfg(debug_assertions)]
+const DEFAULT_LOG_LEVEL: &str = "debug";
+
+/// If not in debug mode, default level is error to get important logs
+#[cfg(not(debug_assertions))]
+const DEFAULT_LOG_LEVEL: &str = "error";
+
+/// Number of log lines the init process relays to youki when it fails
+const CAPTURED_LOG_LINES: usize = 64;
+
+/// Annotation to raise the log level of the invocations of youki which operate
+/// on the container, e.g. `debug` for a single misbehaving container. A level
+/// below the one of the runtime is ignored.
+pub const LOG_LEVEL_ANNOTATION: &str = "org.youki.log-level";
+
+/// Annotation to redirect the logs of the invocations of youki which operate
+/// on the container to `file:<path>`, `journald` or `syslog`, instead of the
+/// log file or stderr of the runtime.
+pub const LOG_TARGET_ANNOTATION: &str = "org.youki.log-target";
+
+const SYSLOG_SOCKET: &str = "/dev/log";
+/// The daemon facility, which system services such as the runtime log as
+const SYSLOG_FACILITY_DAEMON: u8 = 3;
+
+#[derive(Debug, PartialEq, Eq)]
+enum LogTarget {
+    File(PathBuf),
+    Journald,
+    Syslog,
+}
+
+impl FromStr for LogTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(target: &str) -> Result<Self> {
+        match target {
+            "journald" => Ok(Self::Journald),
+            "syslog" => Ok(Self::Syslog),
+            _ => match target.strip_prefix("file:") {
+                Some(path) if Path::new(path).is_absolute() => Ok(Self::File(path.into())),
+                Some(path) => bail!("log file {path:?} is not an absolute path"),
+                None => bail!("unknown log target: {target}"),
+            },
+        }
+    }
+}
+
+fn detect_log_format(log_format: Option<&str>) -> Result<LogFormat> {
+    match log_format {
+        None | Some(LOG_FORMAT_TEXT) => Ok(LogFormat::Text),
+        Some(LOG_FORMAT_JSON) => Ok(LogFormat::Json),
+        Some(LOG_FORMAT_JOURNALD) => Ok(LogFormat::Journald),
+        Some(unknown) => bail!("unknown log format: {}", unknown),
+    }
+}
+
+fn detect_log_level(input: Option<String>, is_debug: bool) -> Result<Level> {
+    // We keep the `debug` flag for backward compatibility, but use `log-level`
+    // as the main way to set the log level due to the flexibility. If both are
+    // specified, `log-level` takes precedence.
+    let log_level: Cow<str> = match input {
+        None if is_debug => "debug".into(),
+        None => DEFAULT_LOG_LEVEL.into(),
+        Some(level) => level.into(),
+    };
+
+    Ok(Level::from_str(log_level.as_ref())?)
+}
+
+/// Returns the log level raised to the one of the container annotations, if
+/// that is more verbose. An invalid annotation only prints a warning, so that
+/// it can't prevent youki from operating on the container.
+fn annotated_log_level(level: Level, annotations: &HashMap<String, String>) -> Level {
+    match annotations
+        .get(LOG_LEVEL_ANNOTATION)
+        .map(|l| Level::from_str(l))
+    {
+        Some(Ok(annotated)) => level.max(annotated),
+        Some(Err(err)) => {
+            eprintln!("ignoring invalid annotation {LOG_LEVEL_ANNOTATION}: {err}");
+            level
+        }
+        None => level,
+    }
+}
+
+fn annotated_log_target(annotations: &HashMap<String, String>) -> Option<LogTarget> {
+    match annotations.get(LOG_TARGET_ANNOTATION)?.parse() {
+        Ok(target) => Some(target),
+        Err(err) => {
+            eprintln!("ignoring invalid annotation {LOG_TARGET_ANNOTATION}: {err}");
+            None
+        }
+    }
+}
+
+// The log file is shared by the invocations of youki for a container, as well
+// as by the intermediate and init processes forked from it, so records are
+// always appended instead of overwriting each other.
+fn open_log_file(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open log file {path:?}"))
+}
+
+/// Log file which is rotated once it grows beyond the maximum size, for the
+/// per container logs of long-lived containers. The rotated files are named
+/// after the log file with the suffixes `.1`, which is the newest, to `.N`.
+struct RotatingLogFile {
+    path: PathBuf,
+    file: File,
+    max_size: Option<u64>,
+    max_files: usize,
+}
+
+impl RotatingLogFile {
+    fn open(path: &Path, max_size: Option<u64>, max_files: usize) -> Result<Self> {
+        Ok(Self {
+            path: path.to_owned(),
+            file: open_log_file(path)?,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // another process which shares the log file may have rotated it
+        // already, which leaves this one writing to the rotated file
+        let current = self.file.metadata()?;
+        let rotated = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata.dev() != current.dev() || metadata.ino() != current.ino(),
+            Err(err) if err.kind() == ErrorKind::NotFound => true,
+            Err(err) => return Err(err),
+        };
+
+        if !rotated {
+            if self.max_files == 0 {
+                fs::remove_file(&self.path)?;
+            } else {
+                for index in (1..self.max_files).rev() {
+                    match fs::rename(self.rotated_path(index), self.rotated_path(index + 1)) {
+                        Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+                        _ => {}
+                    }
+                }
+                fs::rename(&self.path, self.rotated_path(1))?;
+            }
+        }
+
+        self.file = open_log_file(&self.path).map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+impl Write for RotatingLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(max_size) = self.max_size {
+            // the size of the file, as other processes append to it as well
+            let size = self.file.metadata()?.len();
+            if size > 0 && size + buf.len() as u64 > max_size {
+                self.rotate()?;
+            }
+        }
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Sends each event as a datagram to the local syslog daemon, with the
+/// severity of its level and the container id in the message, so that the
+/// records of a container can be told apart.
+struct Syslog {
+    socket: UnixDatagram,
+    container_id: Option<String>,
+}
+
+impl Syslog {
+    fn connect(container_id: Option<String>) -> Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket
+            .connect(SYSLOG_SOCKET)
+            .with_context(|| format!("failed to connect to {SYSLOG_SOCKET}"))?;
+        Ok(Self {
+            socket,
+            container_id,
+        })
+    }
+}
+
+struct SyslogRecord<'a> {
+    syslog: &'a Syslog,
+    severity: u8,
+}
+
+impl SyslogRecord<'_> {
+    fn header(&self) -> String {
+        let priority = SYSLOG_FACILITY_DAEMON * 8 + self.severity;
+        let pid = std::process::id();
+        match &self.syslog.container_id {
+            Some(container_id) => format!("<{priority}>youki[{pid}]: {container_id}: "),
+            None => format!("<{priority}>youki[{pid}]: "),
+        }
+    }
+}
+
+impl Write for SyslogRecord<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // the fmt layer writes each formatted event at once
+        let mut datagram = self.header().into_bytes();
+        datagram.extend_from_slice(buf.strip_suffix(b"\n").unwrap_or(buf));
+        self.syslog.socket.send(&datagram)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The syslog severities of RFC 5424, trace and debug are both debug
+fn syslog_severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+impl<'a> MakeWriter<'a> for Syslog {
+    type Writer = SyslogRecord<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        SyslogRecord {
+            syslog: self,
+            severity: syslog_severity(&Level::INFO),
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        SyslogRecord {
+            syslog: self,
+            severity: syslog_severity(meta.level()),
+        }
+    }
+}
+
+// The init process logs to the stdio of the container once it has been set
+// up, so the last lines are captured to be relayed when it fails.
+fn capturing_stderr() -> CaptureWriter<io::Stderr> {
+    CaptureWriter::new(io::stderr())
+}
+
+/// Formats each event as a single line JSON object, using the same keys as
+/// runc (`time`, `level` and `msg`) so that high-level runtimes such as
+/// containerd can parse the logs. The container id and the operation youki was
+/// invoked with are added to every record.
+struct JsonFormat {
+    container_id: Option<String>,
+    operation: Option<String>,
+}
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut record = Map::new();
+        event.record(&mut JsonVisitor(&mut record));
+        record.insert(
+            "time".to_owned(),
+            Utc::now()
+                .to_rfc3339_opts(SecondsFormat::Nanos, true)
+                .into(),
+        );
+        record.insert(
+            "level".to_owned(),
+            level_name(event.metadata().level()).into(),
+        );
+        if let Some(container_id) = &self.container_id {
+            record.insert("container_id".to_owned(), container_id.as_str().into());
+        }
+        if let Some(operation) = &self.operation {
+            record.insert("operation".to_owned(), operation.as_str().into());
+        }
+
+        let line = serde_json::to_string(&record).map_err(|_| fmt::Error)?;
+        writeln!(writer, "{line}")
+    }
+}
+
+// The level names are the ones used by logrus, which runc logs with.
+fn level_name(level: &Level) -> &'static str {
+    match *level {
+        Level::TRACE => "trace",
+        Level::DEBUG => "debug",
+        Level::INFO => "info",
+        Level::WARN => "warning",
+        Level::ERROR => "error",
+    }
+}
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl JsonVisitor<'_> {
+    fn insert(&mut self, field: &Field, value: Value) {
+        let key = match field.name() {
+            "message" => "msg",
+            name => name,
+        };
+        self.0.insert(key.to_owned(), value);
+    }
+}
+
+impl Visit for JsonVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{value:?}").into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ObservabilityConfig {
+    pub log_debug_flag: bool,
+    pub log_level: Option<String>,
+    pub log_file: Option<PathBuf>,
+    pub log_format: Option<String>,
+    #[allow(dead_code)]
+    pub systemd_log: bool,
+    /// Size beyond which the log file is rotated
+    pub log_max_size: Option<u64>,
+    /// Number of rotated log files which are kept
+    pub log_max_files: usize,
+    /// The container the invocation operates on, if any
+    pub container_id: Option<String>,
+    /// The subcommand youki was invoked with
+    pub operation: Option<String>,
+    /// Annotations of the container the invocation operates on, which can
+    /// override the log level and target
+    pub annotations: HashMap<String, String>,
+}
+
+impl From<&crate::Opts> for ObservabilityConfig {
+    fn from(opts: &crate::Opts) -> Self {
+        Self {
+            log_debug_flag: opts.global.debug,
+            log_level: opts.youki_extend.log_level.to_owned(),
+            log_file: opts.global.log.to_owned(),
+            log_format: opts.global.log_format.to_owned(),
+            systemd_log: opts.youki_extend.systemd_log,
+            log_max_size: opts.youki_extend.log_max_size,
+            log_max_files: opts.youki_extend.log_max_files,
+            container_id: opts.subcmd.container_id().map(str::to_owned),
+            operation: Some(opts.subcmd.operation().to_owned()),
+            annotations: HashMap::new(),
+        }
+    }
+}
+
+pub fn init<T>(config: T) -> Result<()>
+where
+    T: Into<ObservabilityConfig>,
+{
+    let mut config = config.into();
+    let level = detect_log_level(config.log_level, config.log_debug_flag)
+        .with_context(|| "failed to parse log level")?;
+    let level = annotated_log_level(level, &config.annotations);
+    let log_level_filter = tracing_subscriber::filter::LevelFilter::from(level);
+    let mut log_format = detect_log_format(config.log_format.as_deref())
+        .with_context(|| "failed to detect log format")?;
+    let log_target = annotated_log_target(&config.annotations);
+    if let Some(LogTarget::File(path)) = &log_target {
+        config.log_file = Some(path.clone());
+    }
+
+    #[cfg(debug_assertions)]
+    let journald = true;
+    #[cfg(not(debug_assertions))]
+    let journald = config.systemd_log;
+
+    let systemd_journald = if journald
+        || matches!(log_format, LogFormat::Journald)
+        || log_target == Some(LogTarget::Journald)
+    {
+        match JournaldLayer::connect(config.container_id.clone(), config.operation.clone()) {
+            Ok(layer) => {
+                // journald as the target of the container replaces the log
+                // file, the same as the journald format
+                if log_target == Some(LogTarget::Journald) {
+                    log_format = LogFormat::Journald;
+                }
+                Some(layer)
+            }
+            Err(err) if matches!(log_format, LogFormat::Journald) => {
+                return Err(err).context("failed to connect to journald");
+            }
+            Err(err) => {
+                // Do not fail if we can't open syslog, just print a warning.
+                // This is the case in, e.g., docker-in-docker.
+                eprintln!("failed to initialize journald logging: {:?}", err);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    if log_target == Some(LogTarget::Syslog) {
+        match Syslog::connect(config.container_id.clone()) {
+            Ok(syslog) => {
+                return tracing_subscriber::registry()
+                    .with(log_level_filter)
+                    .with(
+                        tracing_subscriber::fmt::layer()
+                            .without_time()
+                            .with_ansi(false)
+                            .with_writer(syslog),
+                    )
+                    .try_init()
+                    .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e));
+            }
+            Err(err) => eprintln!("failed to redirect the logs to syslog: {err:?}"),
+        }
+    }
+
+    let json_format = JsonFormat {
+        container_id: config.container_id,
+        operation: config.operation,
+    };
+    let subscriber = tracing_subscriber::registry()
+        .with(log_level_filter)
+        .with(systemd_journald);
+
+    // I really dislike how we have to specify individual branch for each
+    // combination, but I can't find any better way to do this. The tracing
+    // crate makes it hard to build a single format layer with different
+    // conditions.
+    // the init process writes the log file on its own
+    if config.log_file.is_none() {
+        log_capture::enable(CAPTURED_LOG_LINES);
+    }
+
+    match (config.log_file.as_ref(), log_format) {
+        (_, LogFormat::Journald) => {
+            // Journald only, the log file is left alone
+            subscriber
+                .try_init()
+                .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
+        }
+        (None, LogFormat::Text) => {
+            // Text to stderr
+            subscriber
+                .with(
+                    tracing_subscriber::fmt::layer()
+                        .without_time()
+                        .with_writer(capturing_stderr),
+                )
+                .try_init()
+                .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
+        }
+        (None, LogFormat::Json) => {
+            // JSON to stderr
+            subscriber
+                .with(
+                    tracing_subscriber::fmt::layer()
+                        .event_format(json_format)
+                        .with_writer(capturing_stderr),
+                )
+                .try_init()
+                .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
+        }
+        (Some(path), LogFormat::Text) => {
+            // Log file with text format
+            let file = Mutex::new(RotatingLogFile::open(
+                path,
+                config.log_max_size,
+                config.log_max_files,
+            )?);
+            subscriber
+                .with(tracing_subscriber::fmt::layer().with_writer(file))
+                .try_init()
+                .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
+        }
+        (Some(path), LogFormat::Json) => {
+            // Log file with JSON format
+            let file = Mutex::new(RotatingLogFile::open(
+                path,
+                config.log_max_size,
+                config.log_max_files,
+            )?);
+            subscriber
+                .with(
+                    tracing_subscriber::fmt::layer()
+                        .event_format(json_format)
+                        .with_writer(file),
+                )
+                .try_init()
+                .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use libcontainer::test_utils::TestCallbackError;
+
+    use super::*;
+
+    #[test]
+    fn test_detect_log_level() {
+        let test = vec![
+            ("error", tracing::Level::ERROR),
+            ("warn", tracing::Level::WARN),
+            ("info", tracing::Level::INFO),
+            ("debug", tracing::Level::DEBUG),
+            ("trace", tracing::Level::TRACE),
+        ];
+        for (input, expected) in test {
+            assert_eq!(
+                detect_log_level(Some(input.to_string()), false)
+                    .expect("failed to parse log level"),
+                expected
+            )
+        }
+        assert_eq!(
+            detect_log_level(None, true).expect("failed to parse log level"),
+            tracing::Level::DEBUG
+        );
+        // Invalid log level should fail the parse
+        assert!(detect_log_level(Some("invalid".to_string()), false).is_err());
+    }
+
+    #[test]
+    fn test_detect_log_level_default() {
+        if cfg!(debug_assertions) {
+            assert_eq!(
+                detect_log_level(None, false).unwrap(),
+                tracing::Level::DEBUG
+            )
+        } else {
+            assert_eq!(
+                detect_log_level(None, false).unwrap(),
+                tracing::Level::ERROR
+            )
+        }
+    }
+
+    #[test]
+    fn test_annotated_log_level() {
+        let annotations =
+            |level: &str| HashMap::from([(LOG_LEVEL_ANNOTATION.to_owned(), level.to_owned())]);
+        assert_eq!(
+            annotated_log_level(Level::ERROR, &annotations("debug")),
+            Level::DEBUG
+        );
+        // the annotation can't lower the level of the runtime
+        assert_eq!(
+            annotated_log_level(Level::DEBUG, &annotations("warn")),
+            Level::DEBUG
+        );
+        assert_eq!(
+            annotated_log_level(Level::WARN, &annotations("invalid")),
+            Level::WARN
+        );
+        assert_eq!(
+            annotated_log_level(Level::WARN, &HashMap::new()),
+            Level::WARN
+        );
+    }
+
+    #[test]
+    fn test_parse_log_target() {
+        assert_eq!(
+            "journald".parse::<LogTarget>().unwrap(),
+            LogTarget::Journald
+        );
+        assert_eq!("syslog".parse::<LogTarget>().unwrap(), LogTarget::Syslog);
+        assert_eq!(
+            "file:/var/log/youki/c1.log".parse::<LogTarget>().unwrap(),
+            LogTarget::File("/var/log/youki/c1.log".into())
+        );
+        assert!("file:c1.log".parse::<LogTarget>().is_err());
+        assert!("stderr".parse::<LogTarget>().is_err());
+    }
+
+    #[test]
+    fn test_syslog_record() -> Result<()> {
+        let (socket, receiver) = UnixDatagram::pair()?;
+        let syslog = Syslog {
+            socket,
+            container_id: Some("c1".to_owned()),
+        };
+        let mut record = SyslogRecord {
+            syslog: &syslog,
+            severity: syslog_severity(&Level::WARN),
+        };
+        record.write_all(b"WARN youki: testing syslog\n")?;
+
+        let mut buf = [0; 128];
+        let len = receiver.recv(&mut buf)?;
+        assert_eq!(
+            std::str::from_utf8(&buf[..len])?,
+            format!(
+                "<28>youki[{}]: c1: WARN youki: testing syslog",
+                std::process::id()
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotating_log_file() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("log.json");
+        let mut log = RotatingLogFile::open(&path, Some(8), 2)?;
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            log.write_all(line.as_bytes())?;
+        }
+        assert_eq!(fs::read_to_string(&path)?, "fourth\n");
+        assert_eq!(fs::read_to_string(log.rotated_path(1))?, "third\n");
+        assert_eq!(fs::read_to_string(log.rotated_path(2))?, "second\n");
+        assert!(!log.rotated_path(3).exists());
+
+        // a rotation by another process is followed
+        let mut other = RotatingLogFile::open(&path, Some(8), 2)?;
+        log.write_all(b"fifth\n")?;
+        other.write_all(b"sixth\n")?;
+        assert_eq!(fs::read_to_string(&path)?, "fifth\nsixth\n");
+        assert_eq!(fs::read_to_string(log.rotated_path(1))?, "fourth\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_init_many_times() -> Result<()> {
+        let cb = || {
+            let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+            let log_file = Path::join(temp_dir.path(), "test.log");
+            let config = ObservabilityConfig {
+                log_file: Some(log_file),
+                ..Default::default()
+            };
+            init(config).map_err(|err| TestCallbackError::Other(err.into()))?;
+            Ok(())
+        };
+        libcontainer::test_utils::test_in_child_process(cb)
+            .with_context(|| "failed the first init tracing")?;
+        libcontainer::test_utils::test_in_child_process(cb)
+            .with_context(|| "failed the second init tracing")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_higher_loglevel_no_log() -> Result<()> {
+        libcontainer::test_utils::test_in_child_process(|| {
+            let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+            let log_file = Path::join(temp_dir.path(), "test.log");
+            // Note, we can only init the tracing once, so we have to test in a
+            // single unit test. The orders are important here.
+            let config = ObservabilityConfig {
+                log_file: Some(log_file.clone()),
+                log_level: Some("error".to_string()),
+                ..Default::default()
+            };
+            init(config).map_err(|err| TestCallbackError::Other(err.into()))?;
+            assert!(
+                log_file
+                    .as_path()
+                    .metadata()
+                    .expect("failed to get logfile metadata")
+                    .len()
+                    == 0,
+                "a new logfile should be empty"
+            );
+            // Test that info level is not logged into the logfile because we set the log level to error.
+            tracing::info!("testing this");
+            if log_file
+                .as_path()
+                .metadata()
+                .map_err(|err| format!("failed to get logfile metadata: {err:?}"))?
+                .len()
+                != 0
+            {
+                let data = std::fs::read_to_string(&log_file)
+                    .map_err(|err| format!("failed to read the logfile: {err:?}"))?;
+                Err(TestCallbackError::Custom(format!(
+                    "info level should not be logged into the logfile, but got: {data}"
+                )))?;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_logfile() -> Result<()> {
+        libcontainer::test_utils::test_in_child_process(|| {
+            let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+            let log_file = Path::join(temp_dir.path(), "test.log");
+            // Note, we can only init the tracing once, so we have to test in a
+            // single unit test. The orders are important here.
+            let config = ObservabilityConfig {
+                log_file: Some(log_file.clone()),
+                log_format: Some(LOG_FORMAT_JSON.to_owned()),
+                ..Default::default()
+            };
+            init(config).map_err(|err| TestCallbackError::Other(err.into()))?;
+            assert!(
+                log_file
+                    .as_path()
+                    .metadata()
+                    .expect("failed to get logfile metadata")
+                    .len()
+                    == 0,
+                "a new logfile should be empty"
+            );
+            // Test that the message logged is actually JSON format.
+            tracing::error!("testing json log");
+            let data = std::fs::read_to_string(&log_file)
+                .map_err(|err| format!("failed to read the logfile: {err:?}"))?;
+            if data.is_empty() {
+                Err("logfile should not be empty")?;
+            }
+            serde_json::from_str::<serde_json::Value>(&data)
+                .map_err(|err| format!("failed to parse {data}: {err:?}"))?;
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_record() -> Result<()> {
+        libcontainer::test_utils::test_in_child_process(|| {
+            let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+            let log_file = Path::join(temp_dir.path(), "test.log");
+            // Records of earlier invocations must be kept.
+            std::fs::write(&log_file, "earlier record\n")
+                .map_err(|err| format!("failed to write the logfile: {err:?}"))?;
+            let config = ObservabilityConfig {
+                log_file: Some(log_file.clone()),
+                log_format: Some(LOG_FORMAT_JSON.to_owned()),
+                log_level: Some("warn".to_owned()),
+                container_id: Some("test_container".to_owned()),
+                operation: Some("create".to_owned()),
+                ..Default::default()
+            };
+            init(config).map_err(|err| TestCallbackError::Other(err.into()))?;
+            tracing::warn!(pid = 42, "testing json record");
+
+            let data = std::fs::read_to_string(&log_file)
+                .map_err(|err| format!("failed to read the logfile: {err:?}"))?;
+            let mut lines = data.lines();
+            if lines.next() != Some("earlier record") {
+                Err(format!("earlier record was overwritten: {data}"))?;
+            }
+            let line = lines.next().ok_or("missing json record")?;
+            let record = serde_json::from_str::<serde_json::Value>(line)
+                .map_err(|err| format!("failed to parse {line}: {err:?}"))?;
+            for (key, expected) in [
+                ("level", serde_json::json!("warning")),
+                ("msg", serde_json::json!("testing json record")),
+                ("container_id", serde_json::json!("test_container")),
+                ("operation", serde_json::json!("create")),
+                ("pid", serde_json::json!(42)),
+            ] {
+                if record[key] != expected {
+                    Err(format!("unexpected {key} in {line}"))?;
+                }
+            }
+            let time = record["time"].as_str().ok_or("missing time")?;
+            chrono::DateTime::parse_from_rfc3339(time)
+                .map_err(|err| format!("invalid time {time}: {err:?}"))?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+}
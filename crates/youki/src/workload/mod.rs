@@ -1,3 +1,10 @@
+#[cfg(any(
+    feature = "wasm-wasmer",
+    feature = "wasm-wasmedge",
+    feature = "wasm-wasmtime"
+))]
+use libcontainer::oci_spec::runtime::Spec;
+
 pub mod executor;
 #[cfg(feature = "wasm-wasmedge")]
 mod wasmedge;
@@ -5,3 +12,100 @@ mod wasmedge;
 mod wasmer;
 #[cfg(feature = "wasm-wasmtime")]
 mod wasmtime;
+
+/// Checks if the container runs a WASM module. This is either requested
+/// explicitly through the handler annotations used by crun and the wasm image
+/// spec, or detected from an entrypoint pointing to a `.wasm` module.
+#[cfg(any(
+    feature = "wasm-wasmer",
+    feature = "wasm-wasmedge",
+    feature = "wasm-wasmtime"
+))]
+fn is_wasm_workload(spec: &Spec) -> bool {
+    if let Some(annotations) = spec.annotations() {
+        if let Some(handler) = annotations.get("run.oci.handler") {
+            return handler == "wasm";
+        }
+
+        if let Some(variant) = annotations.get("module.wasm.image/variant") {
+            return variant == "compat" || variant == "compat-smart";
+        }
+    }
+
+    spec.process()
+        .as_ref()
+        .and_then(|p| p.args().as_ref())
+        .and_then(|args| args.first())
+        .map(|entrypoint| entrypoint.ends_with(".wasm"))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+#[cfg(any(
+    feature = "wasm-wasmer",
+    feature = "wasm-wasmedge",
+    feature = "wasm-wasmtime"
+))]
+mod tests {
+    use std::collections::HashMap;
+
+    use anyhow::{Context, Result};
+    use libcontainer::oci_spec::runtime::{ProcessBuilder, SpecBuilder};
+
+    use super::*;
+
+    #[test]
+    fn test_is_wasm_workload_oci_handler() -> Result<()> {
+        let mut annotations = HashMap::with_capacity(1);
+        annotations.insert("run.oci.handler".to_owned(), "wasm".to_owned());
+        let spec = SpecBuilder::default()
+            .annotations(annotations)
+            .build()
+            .context("build spec")?;
+
+        assert!(is_wasm_workload(&spec));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_wasm_workload_compat_wasm_spec() -> Result<()> {
+        for variant in ["compat", "compat-smart"] {
+            let mut annotations = HashMap::with_capacity(1);
+            annotations.insert("module.wasm.image/variant".to_owned(), variant.to_owned());
+            let spec = SpecBuilder::default()
+                .annotations(annotations)
+                .build()
+                .context("build spec")?;
+
+            assert!(is_wasm_workload(&spec));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_wasm_workload_entrypoint() -> Result<()> {
+        let spec = SpecBuilder::default()
+            .process(
+                ProcessBuilder::default()
+                    .args(vec!["/app/module.wasm".to_owned(), "--arg".to_owned()])
+                    .build()?,
+            )
+            .build()
+            .context("build spec")?;
+
+        assert!(is_wasm_workload(&spec));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_wasm_workload_no_execute() -> Result<()> {
+        let spec = SpecBuilder::default().build().context("build spec")?;
+
+        assert!(!is_wasm_workload(&spec));
+
+        Ok(())
+    }
+}
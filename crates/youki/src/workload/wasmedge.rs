@@ -12,7 +12,7 @@ pub struct WasmedgeExecutor {}
 
 impl Executor for WasmedgeExecutor {
     fn exec(&self, spec: &Spec) -> Result<(), ExecutorError> {
-        if !can_handle(spec) {
+        if !super::is_wasm_workload(spec) {
             return Err(ExecutorError::CantHandle(EXECUTOR_NAME));
         }
 
@@ -53,7 +53,7 @@ impl Executor for WasmedgeExecutor {
     }
 
     fn validate(&self, spec: &Spec) -> Result<(), ExecutorValidationError> {
-        if !can_handle(spec) {
+        if !super::is_wasm_workload(spec) {
             return Err(ExecutorValidationError::CantHandle(EXECUTOR_NAME));
         }
 
@@ -65,20 +65,6 @@ pub fn get_executor() -> WasmedgeExecutor {
     WasmedgeExecutor {}
 }
 
-fn can_handle(spec: &Spec) -> bool {
-    if let Some(annotations) = spec.annotations() {
-        if let Some(handler) = annotations.get("run.oci.handler") {
-            return handler == "wasm";
-        }
-
-        if let Some(variant) = annotations.get("module.wasm.image/variant") {
-            return variant == "compat";
-        }
-    }
-
-    false
-}
-
 fn get_args(spec: &Spec) -> &[String] {
     let p = match spec.process() {
         None => return &[],
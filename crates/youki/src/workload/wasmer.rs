@@ -10,7 +10,7 @@ pub struct WasmerExecutor {}
 
 impl Executor for WasmerExecutor {
     fn exec(&self, spec: &Spec) -> Result<(), ExecutorError> {
-        if !can_handle(spec) {
+        if !super::is_wasm_workload(spec) {
             return Err(ExecutorError::CantHandle(EXECUTOR_NAME));
         }
 
@@ -81,7 +81,7 @@ impl Executor for WasmerExecutor {
     }
 
     fn validate(&self, spec: &Spec) -> Result<(), ExecutorValidationError> {
-        if !can_handle(spec) {
+        if !super::is_wasm_workload(spec) {
             return Err(ExecutorValidationError::CantHandle(EXECUTOR_NAME));
         }
 
@@ -92,64 +92,3 @@ impl Executor for WasmerExecutor {
 pub fn get_executor() -> WasmerExecutor {
     WasmerExecutor {}
 }
-
-fn can_handle(spec: &Spec) -> bool {
-    if let Some(annotations) = spec.annotations() {
-        if let Some(handler) = annotations.get("run.oci.handler") {
-            return handler == "wasm";
-        }
-
-        if let Some(variant) = annotations.get("module.wasm.image/variant") {
-            return variant == "compat";
-        }
-    }
-
-    false
-}
-
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
-
-    use anyhow::{Context, Result};
-    use libcontainer::oci_spec::runtime::SpecBuilder;
-
-    use super::*;
-
-    #[test]
-    fn test_can_handle_oci_handler() -> Result<()> {
-        let mut annotations = HashMap::with_capacity(1);
-        annotations.insert("run.oci.handler".to_owned(), "wasm".to_owned());
-        let spec = SpecBuilder::default()
-            .annotations(annotations)
-            .build()
-            .context("build spec")?;
-
-        assert!(can_handle(&spec));
-
-        Ok(())
-    }
-
-    #[test]
-    fn test_can_handle_compat_wasm_spec() -> Result<()> {
-        let mut annotations = HashMap::with_capacity(1);
-        annotations.insert("module.wasm.image/variant".to_owned(), "compat".to_owned());
-        let spec = SpecBuilder::default()
-            .annotations(annotations)
-            .build()
-            .context("build spec")?;
-
-        assert!(can_handle(&spec));
-
-        Ok(())
-    }
-
-    #[test]
-    fn test_can_handle_no_execute() -> Result<()> {
-        let spec = SpecBuilder::default().build().context("build spec")?;
-
-        assert!(!can_handle(&spec));
-
-        Ok(())
-    }
-}
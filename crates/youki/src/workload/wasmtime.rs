@@ -10,7 +10,7 @@ pub struct WasmtimeExecutor {}
 
 impl Executor for WasmtimeExecutor {
     fn exec(&self, spec: &Spec) -> Result<(), ExecutorError> {
-        if !can_handle(spec) {
+        if !super::is_wasm_workload(spec) {
             return Err(ExecutorError::CantHandle(EXECUTOR_NAME));
         }
 
@@ -91,7 +91,7 @@ impl Executor for WasmtimeExecutor {
     }
 
     fn validate(&self, spec: &Spec) -> Result<(), ExecutorValidationError> {
-        if !can_handle(spec) {
+        if !super::is_wasm_workload(spec) {
             return Err(ExecutorValidationError::CantHandle(EXECUTOR_NAME));
         }
 
@@ -102,17 +102,3 @@ impl Executor for WasmtimeExecutor {
 pub fn get_executor() -> WasmtimeExecutor {
     WasmtimeExecutor {}
 }
-
-fn can_handle(spec: &Spec) -> bool {
-    if let Some(annotations) = spec.annotations() {
-        if let Some(handler) = annotations.get("run.oci.handler") {
-            return handler == "wasm";
-        }
-
-        if let Some(variant) = annotations.get("module.wasm.image/variant") {
-            return variant == "compat";
-        }
-    }
-
-    false
-}
@@ -9,6 +9,8 @@ use contest::logger;
 use test_framework::TestManager;
 use tests::cgroups;
 
+use crate::tests::daemon::get_daemon_test;
+use crate::tests::detach::get_detach_test;
 use crate::tests::devices::get_devices_test;
 use crate::tests::domainname::get_domainname_tests;
 use crate::tests::example::get_example_test;
@@ -94,6 +96,8 @@ fn main() -> Result<()> {
     let cc = ContainerCreate::new();
     let huge_tlb = get_tlb_test();
     let pidfile = get_pidfile_test();
+    let detach = get_detach_test();
+    let daemon = get_daemon_test();
     let ns_itype = get_ns_itype_tests();
     let hooks = get_hooks_tests();
     let cgroup_v1_pids = cgroups::pids::get_test_group();
@@ -118,6 +122,8 @@ fn main() -> Result<()> {
     tm.add_test_group(Box::new(cc));
     tm.add_test_group(Box::new(huge_tlb));
     tm.add_test_group(Box::new(pidfile));
+    tm.add_test_group(Box::new(detach));
+    tm.add_test_group(Box::new(daemon));
     tm.add_test_group(Box::new(ns_itype));
     tm.add_test_group(Box::new(hooks));
     tm.add_test_group(Box::new(cgroup_v1_pids));
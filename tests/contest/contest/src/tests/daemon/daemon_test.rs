@@ -0,0 +1,150 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use procfs::process::{all_processes, Process};
+use serde_json::{json, Value};
+use test_framework::{Test, TestGroup, TestResult};
+
+use crate::utils::{generate_uuid, get_runtime_path, prepare_bundle};
+
+fn spawn_daemon(bundle: &tempfile::TempDir, socket: &Path) -> Result<Child> {
+    let daemon = Command::new(get_runtime_path())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .arg("--root")
+        .arg(bundle.as_ref().join("runtime"))
+        .arg("daemon")
+        .arg("--socket")
+        .arg(socket)
+        .spawn()
+        .context("could not spawn daemon")?;
+    for _ in 0..50 {
+        if socket.exists() {
+            return Ok(daemon);
+        }
+        sleep(Duration::from_millis(100));
+    }
+    Err(anyhow!("the daemon did not listen on {:?}", socket))
+}
+
+/// Sends a request to the daemon and returns its response, failing if the
+/// request did not succeed
+fn request(stream: &UnixStream, request: Value) -> Result<Value> {
+    let mut line = request.to_string();
+    line.push('\n');
+    let mut writer = stream;
+    writer.write_all(line.as_bytes())?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    let response: Value = serde_json::from_str(&response)?;
+    if response["ok"] != true {
+        return Err(anyhow!("error : request {} failed: {}", request, response));
+    }
+    Ok(response)
+}
+
+fn zombie_children(pid: i32) -> Result<Vec<i32>> {
+    let mut zombies = Vec::new();
+    for process in all_processes()? {
+        // processes may exit while they are listed
+        let stat = match process.and_then(|process| process.stat()) {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+        if stat.ppid == pid && stat.state == 'Z' {
+            zombies.push(stat.pid);
+        }
+    }
+    Ok(zombies)
+}
+
+fn check_killed_container_is_reaped(
+    id: &str,
+    bundle: &tempfile::TempDir,
+    daemon: i32,
+) -> Result<()> {
+    let socket = bundle.as_ref().join("daemon.sock");
+    let stream = UnixStream::connect(&socket)?;
+    let bundle_path = bundle.as_ref().join("bundle");
+    request(
+        &stream,
+        json!({"op": "create", "id": id, "bundle": bundle_path}),
+    )?;
+    request(&stream, json!({"op": "start", "id": id}))?;
+    let state = request(&stream, json!({"op": "state", "id": id}))?;
+    let pid = state["state"]["pid"]
+        .as_i64()
+        .context("no pid in the state")? as i32;
+    if Process::new(pid)?.stat()?.ppid != daemon {
+        return Err(anyhow!(
+            "error : the init process {} is no child of the daemon",
+            pid
+        ));
+    }
+
+    request(
+        &stream,
+        json!({"op": "kill", "id": id, "signal": "SIGKILL"}),
+    )?;
+    // the daemon reaps the init process after each request
+    for _ in 0..50 {
+        sleep(Duration::from_millis(100));
+        let state = request(&stream, json!({"op": "state", "id": id}))?;
+        if state["state"]["exitStatus"] != 137 {
+            continue;
+        }
+        let zombies = zombie_children(daemon)?;
+        if !zombies.is_empty() {
+            return Err(anyhow!(
+                "error : the daemon left zombie children {:?}",
+                zombies
+            ));
+        }
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        "error : the daemon did not record the exit of the init process {}",
+        pid
+    ))
+}
+
+fn test_killed_container_is_reaped() -> TestResult {
+    let container_id = generate_uuid().to_string();
+    let bundle = prepare_bundle().unwrap();
+    let mut daemon = match spawn_daemon(&bundle, &bundle.as_ref().join("daemon.sock")) {
+        Ok(daemon) => daemon,
+        Err(err) => return TestResult::Failed(err),
+    };
+
+    let result = check_killed_container_is_reaped(&container_id, &bundle, daemon.id() as i32);
+
+    if let Ok(stream) = UnixStream::connect(bundle.as_ref().join("daemon.sock")) {
+        let _ = request(
+            &stream,
+            json!({"op": "delete", "id": container_id, "force": true}),
+        );
+    }
+    let _ = daemon.kill();
+    let _ = daemon.wait();
+    match result {
+        Ok(()) => TestResult::Passed,
+        Err(err) => TestResult::Failed(err),
+    }
+}
+
+pub fn get_daemon_test() -> TestGroup {
+    let killed_container_is_reaped = Test::new(
+        "killed_container_is_reaped",
+        Box::new(test_killed_container_is_reaped),
+    );
+    let mut tg = TestGroup::new("daemon");
+    tg.add(vec![Box::new(killed_container_is_reaped)]);
+    tg
+}
@@ -0,0 +1,2 @@
+mod daemon_test;
+pub use daemon_test::get_daemon_test;
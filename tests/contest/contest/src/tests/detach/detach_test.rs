@@ -0,0 +1,157 @@
+use std::fs::File;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use procfs::process::Process;
+use test_framework::{Test, TestGroup, TestResult};
+
+use crate::utils::{
+    delete_container, generate_uuid, get_runtime_path, get_state, kill_container, prepare_bundle,
+    State,
+};
+
+fn run_detached(id: &str, bundle: &tempfile::TempDir, pid_file: &Path) -> Result<bool> {
+    let status = Command::new(get_runtime_path())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .arg("--root")
+        .arg(bundle.as_ref().join("runtime"))
+        .arg("run")
+        .arg("--detach")
+        .arg("--bundle")
+        .arg(bundle.as_ref().join("bundle"))
+        .arg("--pid-file")
+        .arg(pid_file)
+        .arg(id)
+        .status()
+        .context("could not run container")?;
+    Ok(status.success())
+}
+
+fn check_state(id: &str, bundle: &tempfile::TempDir, status: &str) -> Result<State> {
+    let (out, err) = get_state(id, bundle)?;
+    if !err.is_empty() {
+        return Err(anyhow!("error in state : {}", err));
+    }
+    let state: State = serde_json::from_str(&out)?;
+    if state.status != status {
+        return Err(anyhow!(
+            "error in state : status not matched ,expected '{}' got {}",
+            status,
+            state.status
+        ));
+    }
+    Ok(state)
+}
+
+/// Checks the detached container and returns the pid of its supervisor
+fn check_detached(id: &str, bundle: &tempfile::TempDir, pid_file: &Path) -> Result<i32> {
+    if !run_detached(id, bundle, pid_file)? {
+        return Err(anyhow!("run --detach failed"));
+    }
+
+    let state = check_state(id, bundle, "running")?;
+    let pid: i32 = std::fs::read_to_string(pid_file)?.parse()?;
+    if state.pid != Some(pid) {
+        return Err(anyhow!(
+            "error : pid not matched ,expected {:?} as per state, but got {} from pidfile instead",
+            state.pid,
+            pid
+        ));
+    }
+
+    // youki has exited, and the init process is supervised by a daemonized
+    // runtime process in a session of its own
+    let supervisor = Process::new(pid)?.stat()?.ppid;
+    // the runtime may run from a sealed copy of its binary, so it is told by
+    // its command line
+    let cmdline = Process::new(supervisor)?.cmdline()?;
+    if !cmdline.iter().any(|arg| arg == id) {
+        return Err(anyhow!(
+            "error : the parent {} of the init process is not the supervisor",
+            supervisor
+        ));
+    }
+    let session = Process::myself()?.stat()?.session;
+    if Process::new(supervisor)?.stat()?.session == session {
+        return Err(anyhow!(
+            "error : the supervisor {} is in the session of the caller",
+            supervisor
+        ));
+    }
+
+    Ok(supervisor)
+}
+
+/// Checks that the supervisor has recorded the exit of the container and
+/// exited as well
+fn check_exited(id: &str, bundle: &tempfile::TempDir, supervisor: i32) -> Result<()> {
+    for _ in 0..50 {
+        if Process::new(supervisor).is_err() {
+            check_state(id, bundle, "stopped")?;
+            return Ok(());
+        }
+        sleep(Duration::from_millis(100));
+    }
+    Err(anyhow!(
+        "error : the supervisor {} did not exit with the container",
+        supervisor
+    ))
+}
+
+// the container is run manually, as test_inside_container creates and starts
+// the container separately
+fn test_run_detached() -> TestResult {
+    let container_id = generate_uuid().to_string();
+    let bundle = prepare_bundle().unwrap();
+    let pid_dir = tempfile::tempdir().unwrap();
+    let pid_file = pid_dir.as_ref().join("pidfile");
+    let _ = File::create(&pid_file).unwrap();
+
+    let result = check_detached(&container_id, &bundle, &pid_file);
+
+    kill_container(&container_id, &bundle)
+        .unwrap()
+        .wait()
+        .unwrap();
+    let result = result.and_then(|supervisor| check_exited(&container_id, &bundle, supervisor));
+    delete_container(&container_id, &bundle)
+        .unwrap()
+        .wait()
+        .unwrap();
+    match result {
+        Ok(()) => TestResult::Passed,
+        Err(err) => TestResult::Failed(err),
+    }
+}
+
+fn test_run_detached_fails() -> TestResult {
+    let container_id = generate_uuid().to_string();
+    let bundle = prepare_bundle().unwrap();
+    std::fs::remove_file(bundle.as_ref().join("bundle").join("config.json")).unwrap();
+    let pid_dir = tempfile::tempdir().unwrap();
+
+    match run_detached(&container_id, &bundle, &pid_dir.as_ref().join("pidfile")) {
+        Ok(false) => TestResult::Passed,
+        Ok(true) => {
+            delete_container(&container_id, &bundle)
+                .unwrap()
+                .wait()
+                .unwrap();
+            TestResult::Failed(anyhow!("run --detach succeeded without a config.json"))
+        }
+        Err(err) => TestResult::Failed(err),
+    }
+}
+
+pub fn get_detach_test() -> TestGroup {
+    let run_detached = Test::new("run_detached", Box::new(test_run_detached));
+    let run_detached_fails = Test::new("run_detached_fails", Box::new(test_run_detached_fails));
+    let mut tg = TestGroup::new("detach");
+    tg.add(vec![Box::new(run_detached), Box::new(run_detached_fails)]);
+    tg
+}
@@ -0,0 +1,2 @@
+mod detach_test;
+pub use detach_test::get_detach_test;
@@ -1,4 +1,6 @@
 pub mod cgroups;
+pub mod daemon;
+pub mod detach;
 pub mod devices;
 pub mod domainname;
 pub mod example;
@@ -38,7 +38,7 @@ fn test_seccomp_notify() -> Result<()> {
                 .seccomp(
                     LinuxSeccompBuilder::default()
                         .default_action(LinuxSeccompAction::ScmpActAllow)
-                        .architectures(vec![Arch::ScmpArchX86_64])
+                        .architectures(vec![Arch::ScmpArchNative])
                         .listener_path(&seccomp_listener_path)
                         .listener_metadata(seccomp_meta)
                         .syscalls(vec![LinuxSyscallBuilder::default()
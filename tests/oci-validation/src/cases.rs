@@ -0,0 +1,166 @@
+//! Validation cases of runtime-tools which are run against youki
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+/// Environment a case needs besides the runtime, without which it fails
+/// regardless of the runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Requirement {
+    /// Swap accounting of the cgroup v1 memory controller
+    MemorySwap,
+    /// The `cgrouptest` cgroup of the pids controller, which the case expects
+    /// to be prepared
+    PidsCgroupTest,
+}
+
+impl Requirement {
+    fn path(&self) -> &'static Path {
+        match self {
+            Self::MemorySwap => Path::new("/sys/fs/cgroup/memory/memory.memsw.limit_in_bytes"),
+            Self::PidsCgroupTest => Path::new("/sys/fs/cgroup/pids/cgrouptest/tasks"),
+        }
+    }
+
+    pub fn is_met(&self) -> bool {
+        self.path().exists()
+    }
+
+    pub fn description(&self) -> String {
+        format!("{:?} doesn't exist", self.path())
+    }
+}
+
+/// A validation executable of runtime-tools
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+    pub name: &'static str,
+    pub requirements: &'static [Requirement],
+    /// Why the case isn't run by default
+    pub disabled: Option<&'static str>,
+}
+
+impl TestCase {
+    const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            requirements: &[],
+            disabled: None,
+        }
+    }
+
+    const fn requires(mut self, requirements: &'static [Requirement]) -> Self {
+        self.requirements = requirements;
+        self
+    }
+
+    const fn disabled(mut self, reason: &'static str) -> Self {
+        self.disabled = Some(reason);
+        self
+    }
+
+    /// Path of the executable relative to the runtime-tools checkout
+    pub fn path(&self) -> PathBuf {
+        Path::new("validation")
+            .join(self.name)
+            .join(format!("{}.t", self.name))
+    }
+
+    /// Returns the requirement of the case which the environment doesn't meet
+    pub fn unmet_requirement(&self) -> Option<Requirement> {
+        self.requirements.iter().copied().find(|r| !r.is_met())
+    }
+}
+
+// excluded from linux 5.0, so even runc doesn't pass it, see
+// https://github.com/docker/cli/pull/2908
+const BLKIO_REMOVED: &str = "checks blkio features which have been removed from the kernel";
+const RUNC_FAILS: &str = "runc fails it as well";
+
+pub const CASES: &[TestCase] = &[
+    TestCase::new("create"),
+    TestCase::new("default"),
+    TestCase::new("delete"),
+    TestCase::new("delete_only_create_resources").requires(&[Requirement::PidsCgroupTest]),
+    TestCase::new("delete_resources"),
+    TestCase::new("hooks"),
+    TestCase::new("hooks_stdin"),
+    TestCase::new("hostname"),
+    TestCase::new("kill"),
+    TestCase::new("kill_no_effect"),
+    TestCase::new("killsig"),
+    TestCase::new("linux_cgroups_blkio").disabled(BLKIO_REMOVED),
+    TestCase::new("linux_cgroups_cpus"),
+    TestCase::new("linux_cgroups_devices"),
+    TestCase::new("linux_cgroups_hugetlb").requires(&[Requirement::MemorySwap]),
+    TestCase::new("linux_cgroups_memory").requires(&[Requirement::MemorySwap]),
+    TestCase::new("linux_cgroups_network"),
+    TestCase::new("linux_cgroups_pids"),
+    TestCase::new("linux_cgroups_relative_blkio").disabled(BLKIO_REMOVED),
+    TestCase::new("linux_cgroups_relative_cpus"),
+    TestCase::new("linux_cgroups_relative_devices"),
+    TestCase::new("linux_cgroups_relative_hugetlb").requires(&[Requirement::MemorySwap]),
+    TestCase::new("linux_cgroups_relative_memory").requires(&[Requirement::MemorySwap]),
+    TestCase::new("linux_cgroups_relative_network"),
+    TestCase::new("linux_cgroups_relative_pids"),
+    TestCase::new("linux_devices"),
+    TestCase::new("linux_masked_paths"),
+    TestCase::new("linux_mount_label"),
+    // https://github.com/opencontainers/runtime-tools/issues/698
+    TestCase::new("linux_ns_itype").disabled("the clean up of runtime-tools hangs in CI"),
+    TestCase::new("linux_ns_nopath"),
+    TestCase::new("linux_ns_path"),
+    TestCase::new("linux_ns_path_type"),
+    TestCase::new("linux_process_apparmor_profile")
+        .disabled("requires the acme_secure_profile apparmor profile to be installed"),
+    TestCase::new("linux_readonly_paths"),
+    TestCase::new("linux_rootfs_propagation"),
+    TestCase::new("linux_seccomp"),
+    TestCase::new("linux_sysctl"),
+    TestCase::new("linux_uid_mappings"),
+    // https://github.com/containers/youki/pull/1347#issuecomment-1315332775
+    TestCase::new("misc_props").disabled(RUNC_FAILS),
+    TestCase::new("mounts"),
+    TestCase::new("pidfile").disabled("runc fails it in CI as well"),
+    TestCase::new("poststart"),
+    TestCase::new("poststart_fail"),
+    TestCase::new("poststop"),
+    TestCase::new("poststop_fail"),
+    TestCase::new("prestart"),
+    TestCase::new("prestart_fail"),
+    TestCase::new("process"),
+    TestCase::new("process_capabilities"),
+    TestCase::new("process_capabilities_fail"),
+    TestCase::new("process_oom_score_adj"),
+    TestCase::new("process_rlimits"),
+    TestCase::new("process_rlimits_fail"),
+    TestCase::new("process_user"),
+    TestCase::new("root_readonly_true"),
+    // https://github.com/containers/youki/issues/56
+    TestCase::new("start").disabled(RUNC_FAILS),
+    TestCase::new("state"),
+];
+
+/// Returns the cases whose name matches the filter, leaving out the disabled
+/// ones unless they are included
+pub fn select(filter: Option<&Regex>, include_disabled: bool) -> Vec<&'static TestCase> {
+    CASES
+        .iter()
+        .filter(|case| include_disabled || case.disabled.is_none())
+        .filter(|case| filter.map_or(true, |f| f.is_match(case.name)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let filter = Regex::new("^linux_cgroups_(relative_)?blkio$").unwrap();
+        assert!(select(Some(&filter), false).is_empty());
+        assert_eq!(select(Some(&filter), true).len(), 2);
+        assert_eq!(select(None, true).len(), CASES.len());
+        assert_eq!(CASES[0].path(), PathBuf::from("validation/create/create.t"));
+    }
+}
@@ -0,0 +1,11 @@
+//! Harness which runs the validation suite of
+//! [runtime-tools](https://github.com/opencontainers/runtime-tools) against an
+//! OCI runtime. Every case of the suite is an executable which generates a
+//! bundle, drives the runtime through it and reports the compliance with the
+//! runtime spec as TAP, so the suite can also serve as a smoke test of what a
+//! kernel supports.
+pub mod cases;
+mod runner;
+pub mod tap;
+
+pub use runner::{Outcome, Runner};
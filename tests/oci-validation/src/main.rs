@@ -0,0 +1,111 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use oci_validation::cases::{self, TestCase};
+use oci_validation::{Outcome, Runner};
+use regex::Regex;
+
+const DEFAULT_SUITE_DIR: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../oci-runtime-tests/src/github.com/opencontainers/runtime-tools"
+);
+
+#[derive(Parser, Debug)]
+#[clap(version = "0.0.1", author = "youki team")]
+struct Opts {
+    #[clap(subcommand)]
+    command: SubCommand,
+}
+
+#[derive(Parser, Debug)]
+enum SubCommand {
+    /// run the validation cases of runtime-tools
+    Run(Run),
+    /// list the validation cases
+    List(Selection),
+}
+
+#[derive(Parser, Debug)]
+struct Selection {
+    /// Regular expression selecting the cases to run by name
+    filter: Option<Regex>,
+    /// Also select the cases which are disabled by default
+    #[clap(long)]
+    include_disabled: bool,
+}
+
+impl Selection {
+    fn cases(&self) -> Vec<&'static TestCase> {
+        cases::select(self.filter.as_ref(), self.include_disabled)
+    }
+}
+
+#[derive(Parser, Debug)]
+struct Run {
+    /// Path for the container runtime to be tested
+    #[clap(long)]
+    runtime: PathBuf,
+    /// Checkout of runtime-tools, which is built if needed
+    #[clap(long, default_value = DEFAULT_SUITE_DIR)]
+    suite_dir: PathBuf,
+    /// Directory the output of the cases is written to, defaults to the log
+    /// directory of the runtime-tools checkout
+    #[clap(long)]
+    log_dir: Option<PathBuf>,
+    #[clap(flatten)]
+    selection: Selection,
+}
+
+fn run(opts: Run) -> Result<()> {
+    if !nix::unistd::geteuid().is_root() {
+        bail!("the validation cases have to be run as root");
+    }
+
+    let log_dir = opts.log_dir.unwrap_or_else(|| opts.suite_dir.join("log"));
+    let runner = Runner::new(&opts.runtime, &opts.suite_dir)?.log_dir(&log_dir);
+    let cases = opts.selection.cases();
+    runner.build(&cases)?;
+
+    let mut failed = Vec::new();
+    for case in cases {
+        println!("Running {}", case.name);
+        match runner.run(case)? {
+            Outcome::Passed(report) => {
+                println!("ok {} ({} checks)", case.name, report.points.len())
+            }
+            Outcome::Skipped(reason) => println!("skip {}: {reason}", case.name),
+            Outcome::Failed { report, output } => {
+                println!("not ok {}", case.name);
+                for failure in report.failures() {
+                    println!("  failed: {}", failure.description);
+                }
+                eprintln!("{output}");
+                failed.push(case.name);
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        bail!("{} cases failed: {}", failed.len(), failed.join(", "));
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+
+    match opts.command {
+        SubCommand::Run(run_opts) => run(run_opts)?,
+        SubCommand::List(selection) => {
+            for case in selection.cases() {
+                match case.disabled {
+                    Some(reason) => println!("{} (disabled: {reason})", case.name),
+                    None => println!("{}", case.name),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::cases::TestCase;
+use crate::tap::Report;
+
+// runtime-tools reports this instead of skipping the cgroup cases it can't
+// validate on cgroup v2
+const CGROUP_V2_UNSUPPORTED: &str = "cgroupv2 is not supported yet";
+
+#[derive(Debug)]
+pub enum Outcome {
+    Passed(Report),
+    /// The executable failed or reported failed test points. The output is
+    /// kept, as the TAP output of a failed executable may be incomplete.
+    Failed {
+        report: Report,
+        output: String,
+    },
+    Skipped(String),
+}
+
+/// Runs the validation executables of a runtime-tools checkout against a
+/// runtime. The executables generate the bundles and drive the runtime
+/// through its command line themselves, so they have to run as root.
+#[derive(Debug)]
+pub struct Runner {
+    runtime: PathBuf,
+    suite_dir: PathBuf,
+    log_dir: Option<PathBuf>,
+}
+
+impl Runner {
+    /// The runtime is looked up in PATH unless it is an existing file
+    pub fn new(runtime: &Path, suite_dir: &Path) -> Result<Self> {
+        let runtime = if runtime.exists() {
+            runtime.canonicalize()?
+        } else {
+            which::which(runtime).with_context(|| format!("{runtime:?} not found"))?
+        };
+        if !suite_dir.join("Makefile").exists() {
+            bail!(
+                "{suite_dir:?} is no runtime-tools checkout, run `git submodule update --init --recursive`"
+            );
+        }
+
+        Ok(Self {
+            runtime,
+            suite_dir: suite_dir.to_owned(),
+            log_dir: None,
+        })
+    }
+
+    /// Writes the output of every case to `<dir>/<case>.log`
+    pub fn log_dir(mut self, dir: &Path) -> Self {
+        self.log_dir = Some(dir.to_owned());
+        self
+    }
+
+    /// Builds the validation executables of runtime-tools, unless all of the
+    /// cases have been built already
+    pub fn build(&self, cases: &[&TestCase]) -> Result<()> {
+        if cases
+            .iter()
+            .all(|case| self.suite_dir.join(case.path()).exists())
+        {
+            return Ok(());
+        }
+
+        // the checkout is at $GOPATH/src/github.com/opencontainers/runtime-tools
+        let gopath = self
+            .suite_dir
+            .ancestors()
+            .nth(4)
+            .context("runtime-tools checkout is not in a GOPATH")?;
+        let status = Command::new("make")
+            .args(["runtimetest", "validation-executables"])
+            .current_dir(&self.suite_dir)
+            .env("GO111MODULE", "auto")
+            .env("GOPATH", gopath)
+            .status()
+            .context("failed to run make")?;
+        if !status.success() {
+            bail!("failed to build the validation executables: {status}");
+        }
+
+        Ok(())
+    }
+
+    pub fn run(&self, case: &TestCase) -> Result<Outcome> {
+        if let Some(requirement) = case.unmet_requirement() {
+            return Ok(Outcome::Skipped(format!(
+                "the environment doesn't support it, {}",
+                requirement.description()
+            )));
+        }
+
+        let executable = self.suite_dir.join(case.path());
+        let output = Command::new(&executable)
+            .current_dir(&self.suite_dir)
+            .env("RUNTIME", &self.runtime)
+            .env("RUST_BACKTRACE", "1")
+            .output()
+            .with_context(|| format!("failed to run {executable:?}"))?;
+        let mut log = String::from_utf8_lossy(&output.stdout).into_owned();
+        log.push_str(&String::from_utf8_lossy(&output.stderr));
+
+        if let Some(dir) = &self.log_dir {
+            let path = dir.join(format!("{}.log", case.name));
+            fs::create_dir_all(dir)
+                .and_then(|_| fs::write(&path, &log))
+                .with_context(|| format!("failed to write {path:?}"))?;
+        }
+
+        let report = Report::parse(&String::from_utf8_lossy(&output.stdout));
+        if !output.status.success() {
+            return Ok(Outcome::Failed {
+                report,
+                output: log,
+            });
+        }
+        if report.failures().next().is_some() {
+            if report.has_diagnostic(CGROUP_V2_UNSUPPORTED) {
+                return Ok(Outcome::Skipped(
+                    "runtime-tools doesn't support cgroup v2".to_owned(),
+                ));
+            }
+            return Ok(Outcome::Failed {
+                report,
+                output: log,
+            });
+        }
+
+        Ok(Outcome::Passed(report))
+    }
+}
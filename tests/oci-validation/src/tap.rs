@@ -0,0 +1,110 @@
+//! Parser of the TAP output of the validation executables
+/// A test point, i.e. an `ok` or `not ok` line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestPoint {
+    pub ok: bool,
+    pub description: String,
+    /// `SKIP` or `TODO` directive, with which a failure doesn't count
+    pub directive: Option<String>,
+}
+
+impl TestPoint {
+    pub fn is_failure(&self) -> bool {
+        !self.ok && self.directive.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub points: Vec<TestPoint>,
+    /// Lines starting with `#` which aren't part of a test point
+    pub diagnostics: Vec<String>,
+}
+
+impl Report {
+    pub fn parse(output: &str) -> Self {
+        let mut report = Self::default();
+        for line in output.lines().map(str::trim_start) {
+            let (ok, rest) = if let Some(rest) = line.strip_prefix("not ok") {
+                (false, rest)
+            } else if let Some(rest) = line.strip_prefix("ok") {
+                (true, rest)
+            } else if let Some(diagnostic) = line.strip_prefix('#') {
+                report.diagnostics.push(diagnostic.trim().to_owned());
+                continue;
+            } else {
+                continue;
+            };
+
+            // the test number is optional, as is the dash before the description
+            let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit() || c == ' ');
+            let rest = rest.strip_prefix('-').unwrap_or(rest);
+            let (description, directive) = match rest.rsplit_once('#') {
+                Some((description, directive)) if is_directive(directive.trim()) => {
+                    (description, Some(directive.trim().to_owned()))
+                }
+                _ => (rest, None),
+            };
+            report.points.push(TestPoint {
+                ok,
+                description: description.trim().to_owned(),
+                directive,
+            });
+        }
+
+        report
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &TestPoint> {
+        self.points.iter().filter(|p| p.is_failure())
+    }
+
+    pub fn has_diagnostic(&self, diagnostic: &str) -> bool {
+        self.diagnostics.iter().any(|d| d.contains(diagnostic))
+    }
+}
+
+fn is_directive(comment: &str) -> bool {
+    ["SKIP", "TODO"].iter().any(|d| {
+        comment
+            .get(..d.len())
+            .map_or(false, |p| p.eq_ignore_ascii_case(d))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let report = Report::parse(
+            "TAP version 13\n\
+             ok 1 - create MUST create a new container\n\
+             not ok 2 - linux.resources.memory.swap is set to 1048576\n  \
+             ---\n  \
+             expected: 1048576\n  \
+             ...\n\
+             not ok 3 # SKIP cgroupv2 is not supported yet\n\
+             ok 4\n\
+             # cgroupv2 is not supported yet \n\
+             1..4\n",
+        );
+        assert_eq!(report.points.len(), 4);
+        assert_eq!(
+            report.points[0].description,
+            "create MUST create a new container"
+        );
+        let failures: Vec<_> = report.failures().collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(
+            failures[0].description,
+            "linux.resources.memory.swap is set to 1048576"
+        );
+        assert_eq!(
+            report.points[2].directive.as_deref(),
+            Some("SKIP cgroupv2 is not supported yet")
+        );
+        assert!(report.has_diagnostic("cgroupv2 is not supported yet"));
+    }
+}